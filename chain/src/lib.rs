@@ -1,4 +1,5 @@
 mod epoch;
+mod error_code;
 mod known_assets;
 mod note_source;
 mod view;
@@ -10,6 +11,7 @@ pub(crate) mod state_key;
 pub mod sync;
 
 pub use epoch::Epoch;
+pub use error_code::{ErrorCode, CODESPACE};
 pub use known_assets::KnownAssets;
 pub use note_source::NoteSource;
 pub use sync::CompactBlock;
@@ -36,7 +36,17 @@ impl From<AssetInfo> for pb::AssetInfo {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The names of each kind of action, as used in the admission lists in the [`ChainParams`].
+pub const ACTION_KINDS: &[&str] = &[
+    "spend",
+    "output",
+    "delegate",
+    "undelegate",
+    "validator_definition",
+    "ibc",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(try_from = "pb::ChainParams", into = "pb::ChainParams")]
 pub struct ChainParams {
     pub chain_id: String,
@@ -62,13 +72,50 @@ pub struct ChainParams {
     pub inbound_ics20_transfers_enabled: bool,
     /// Whether outbound ICS-20 transfers are enabled
     pub outbound_ics20_transfers_enabled: bool,
+
+    /// The kinds of action which transactions may not contain, e.g. `undelegate`.
+    pub disallowed_actions: Vec<String>,
+    /// If non-empty, the only kinds of action which transactions may contain.
+    pub allowed_actions: Vec<String>,
+
+    /// The minimum total commission of a validator's funding streams, in basis points.
+    pub min_validator_commission_bps: u64,
+    /// The maximum total commission of a validator's funding streams, in basis points, if any.
+    pub max_validator_commission_bps: Option<u64>,
+    /// The most a validator's total commission may change by in one epoch, in basis points, if
+    /// limited.
+    pub max_validator_commission_change_bps: Option<u64>,
+
+    /// The number of blocks a transaction's anchor remains valid for, if anchors expire.
+    pub anchor_window_len: Option<u64>,
+
+    /// The minimum fee a transaction must pay.
+    pub min_fee: u64,
+    /// The maximum size of an encoded transaction, in bytes, if limited.
+    pub max_transaction_bytes: Option<u64>,
+    // TODO: a minimum self-delegation can't be enforced yet: delegations are shielded, so nothing
+    // links a delegation to the validator's operator.
 }
 
 impl Protobuf<pb::ChainParams> for ChainParams {}
 
-impl From<pb::ChainParams> for ChainParams {
-    fn from(msg: pb::ChainParams) -> Self {
-        ChainParams {
+impl TryFrom<pb::ChainParams> for ChainParams {
+    type Error = anyhow::Error;
+
+    fn try_from(msg: pb::ChainParams) -> Result<Self, Self::Error> {
+        // A misspelled kind would silently disable a deny rule, or make an allowlist reject every
+        // transaction, so the lists may only name known kinds.
+        for kind in msg.disallowed_actions.iter().chain(&msg.allowed_actions) {
+            if !ACTION_KINDS.contains(&kind.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "unknown action kind {:?} in the admission lists, expected one of: {}",
+                    kind,
+                    ACTION_KINDS.join(", ")
+                ));
+            }
+        }
+
+        Ok(ChainParams {
             chain_id: msg.chain_id,
             epoch_duration: msg.epoch_duration,
             unbonding_epochs: msg.unbonding_epochs,
@@ -81,7 +128,15 @@ impl From<pb::ChainParams> for ChainParams {
             ibc_enabled: msg.ibc_enabled,
             inbound_ics20_transfers_enabled: msg.inbound_ics20_transfers_enabled,
             outbound_ics20_transfers_enabled: msg.outbound_ics20_transfers_enabled,
-        }
+            disallowed_actions: msg.disallowed_actions,
+            allowed_actions: msg.allowed_actions,
+            min_validator_commission_bps: msg.min_validator_commission_bps,
+            max_validator_commission_bps: msg.max_validator_commission_bps,
+            max_validator_commission_change_bps: msg.max_validator_commission_change_bps,
+            anchor_window_len: msg.anchor_window_len,
+            min_fee: msg.min_fee,
+            max_transaction_bytes: msg.max_transaction_bytes,
+        })
     }
 }
 
@@ -100,6 +155,14 @@ impl From<ChainParams> for pb::ChainParams {
             ibc_enabled: params.ibc_enabled,
             inbound_ics20_transfers_enabled: params.inbound_ics20_transfers_enabled,
             outbound_ics20_transfers_enabled: params.outbound_ics20_transfers_enabled,
+            disallowed_actions: params.disallowed_actions,
+            allowed_actions: params.allowed_actions,
+            min_validator_commission_bps: params.min_validator_commission_bps,
+            max_validator_commission_bps: params.max_validator_commission_bps,
+            max_validator_commission_change_bps: params.max_validator_commission_change_bps,
+            anchor_window_len: params.anchor_window_len,
+            min_fee: params.min_fee,
+            max_transaction_bytes: params.max_transaction_bytes,
         }
     }
 }
@@ -127,6 +190,45 @@ impl Default for ChainParams {
             ibc_enabled: true,
             inbound_ics20_transfers_enabled: false,
             outbound_ics20_transfers_enabled: false,
+            disallowed_actions: Vec::new(),
+            allowed_actions: Vec::new(),
+            min_validator_commission_bps: 0,
+            max_validator_commission_bps: None,
+            max_validator_commission_change_bps: None,
+            anchor_window_len: None,
+            min_fee: 0,
+            max_transaction_bytes: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_action_kinds_are_rejected() {
+        let params = ChainParams {
+            disallowed_actions: vec!["undelegate".to_string()],
+            allowed_actions: ACTION_KINDS.iter().map(|kind| kind.to_string()).collect(),
+            ..Default::default()
+        };
+        assert_eq!(
+            ChainParams::decode(params.encode_to_vec().as_slice()).unwrap(),
+            params
+        );
+
+        for misspelled in [
+            ChainParams {
+                disallowed_actions: vec!["undelegation".to_string()],
+                ..Default::default()
+            },
+            ChainParams {
+                allowed_actions: vec!["spend".to_string(), "Output".to_string()],
+                ..Default::default()
+            },
+        ] {
+            assert!(ChainParams::decode(misspelled.encode_to_vec().as_slice()).is_err());
         }
     }
 }
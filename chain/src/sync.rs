@@ -3,7 +3,10 @@ use std::convert::TryFrom;
 use anyhow::Result;
 use penumbra_crypto::{IdentityKey, NotePayload, Nullifier};
 use penumbra_proto::{chain as pb, Protobuf};
-use penumbra_tct::builder::{block, epoch};
+use penumbra_tct::{
+    self as tct,
+    builder::{block, epoch},
+};
 use serde::{Deserialize, Serialize};
 
 use crate::quarantined::Quarantined;
@@ -20,12 +23,22 @@ pub struct CompactBlock {
     pub nullifiers: Vec<Nullifier>,
     // The block root of this block.
     pub block_root: block::Root,
-    // The epoch root of this epoch, if this block ends an epoch (`None` otherwise).
+    // The epoch root of this epoch, if this block ends an epoch, or if the note commitment tree's
+    // epoch was full and was rolled over early (`None` otherwise).
     pub epoch_root: Option<epoch::Root>,
     // Newly quarantined things in this block.
     pub quarantined: Quarantined,
     // Newly slashed validators in this block.
     pub slashed: Vec<IdentityKey>,
+    // The time at which this block was committed, in RFC 3339 format, if known.
+    //
+    // This is present in every block, so it doesn't affect `CompactBlock::requires_scanning`.
+    pub block_time: Option<String>,
+    // The root of the note commitment tree after this block, if known, so that clients can check
+    // that their own tree matches consensus.
+    //
+    // This is present in every block, so it doesn't affect `CompactBlock::requires_scanning`.
+    pub nct_root: Option<tct::Root>,
     // **IMPORTANT NOTE FOR FUTURE HUMANS**: if you want to add new fields to the `CompactBlock`,
     // you must update `CompactBlock::requires_scanning` to check for the emptiness of those fields, because
     // the client will skip processing any compact block that is marked as not requiring scanning.
@@ -41,6 +54,8 @@ impl Default for CompactBlock {
             epoch_root: None,
             quarantined: Quarantined::default(),
             slashed: Vec::new(),
+            block_time: None,
+            nct_root: None,
         }
     }
 }
@@ -52,6 +67,7 @@ impl CompactBlock {
             || !self.nullifiers.is_empty() // need to collect nullifiers
             || !self.quarantined.is_empty() // need to scan quarantined notes
             || !self.slashed.is_empty() // need to process slashing
+            || self.epoch_root.is_some() // need to end the epoch, which may be rolled over early
     }
 }
 
@@ -76,6 +92,10 @@ impl From<CompactBlock> for pb::CompactBlock {
                 Some(cb.quarantined.into())
             },
             slashed: cb.slashed.into_iter().map(Into::into).collect(),
+            block_time: cb.block_time.unwrap_or_default(),
+            nct_root: cb.nct_root.map(Into::into),
+            // Resumption tokens are only attached to blocks as they're streamed.
+            resumption_token: Default::default(),
         }
     }
 }
@@ -115,6 +135,9 @@ impl TryFrom<pb::CompactBlock> for CompactBlock {
                 .into_iter()
                 .map(IdentityKey::try_from)
                 .collect::<Result<Vec<_>>>()?,
+            // An empty block time means that the block time is unknown
+            block_time: Some(value.block_time).filter(|time| !time.is_empty()),
+            nct_root: value.nct_root.map(TryInto::try_into).transpose()?,
         })
     }
 }
@@ -0,0 +1,145 @@
+//! Chain-wide restrictions on which kinds of action transactions may contain, the fee they must
+//! pay, and how large they may be.
+//!
+//! These are set in the [`ChainParams`], so every node applies the same policy, which makes it
+//! useful for staged testnet launches (e.g., disallowing undelegations during a migration window).
+//!
+//! Note that the policy can only restrict actions by kind: the assets moved by spends and outputs
+//! are shielded, so they can't be restricted by asset ID.
+
+use anyhow::Result;
+use penumbra_chain::params::ChainParams;
+pub use penumbra_chain::params::ACTION_KINDS;
+use penumbra_proto::Protobuf;
+use penumbra_transaction::{Action, Transaction};
+
+/// The name of the kind of the given action.
+pub fn action_kind(action: &Action) -> &'static str {
+    match action {
+        Action::Spend(_) => "spend",
+        Action::Output(_) => "output",
+        Action::Delegate(_) => "delegate",
+        Action::Undelegate(_) => "undelegate",
+        Action::ValidatorDefinition(_) => "validator_definition",
+        Action::IBCAction(_) => "ibc",
+    }
+}
+
+/// Check that the transaction pays at least the minimum fee, is no larger than the maximum
+/// transaction size, and that every action in it is admitted by the chain's admission lists.
+pub fn check(chain_params: &ChainParams, tx: &Transaction) -> Result<()> {
+    check_fee(chain_params, tx.transaction_body.fee.0)?;
+    if let Some(max_transaction_bytes) = chain_params.max_transaction_bytes {
+        check_size(max_transaction_bytes, tx.encode_to_vec().len() as u64)?;
+    }
+
+    for action in tx.actions() {
+        check_action_kind(chain_params, action_kind(action))?;
+    }
+
+    Ok(())
+}
+
+/// Check that an action of the given kind is admitted by the chain's admission lists.
+fn check_action_kind(chain_params: &ChainParams, kind: &str) -> Result<()> {
+    if chain_params.disallowed_actions.iter().any(|k| k == kind) {
+        return Err(anyhow::anyhow!(
+            "transaction contains a {} action, which is not allowed on this chain",
+            kind
+        ));
+    }
+    if !chain_params.allowed_actions.is_empty()
+        && !chain_params.allowed_actions.iter().any(|k| k == kind)
+    {
+        return Err(anyhow::anyhow!(
+            "transaction contains a {} action, which is not in the allowed actions for this chain",
+            kind
+        ));
+    }
+    Ok(())
+}
+
+/// Check that a fee is at least the chain's minimum fee.
+fn check_fee(chain_params: &ChainParams, fee: u64) -> Result<()> {
+    if fee < chain_params.min_fee {
+        return Err(anyhow::anyhow!(
+            "transaction fee {} is below the minimum fee {}",
+            fee,
+            chain_params.min_fee
+        ));
+    }
+    Ok(())
+}
+
+/// Check that an encoded transaction of `len` bytes is within the maximum transaction size.
+fn check_size(max_transaction_bytes: u64, len: u64) -> Result<()> {
+    if len > max_transaction_bytes {
+        return Err(anyhow::anyhow!(
+            "transaction is {} bytes, which exceeds the maximum transaction size of {} bytes",
+            len,
+            max_transaction_bytes
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fees_below_the_minimum_are_rejected() {
+        let params = ChainParams {
+            min_fee: 10,
+            ..Default::default()
+        };
+        assert!(check_fee(&params, 9).is_err());
+        assert!(check_fee(&params, 10).is_ok());
+        assert!(check_fee(&params, 11).is_ok());
+        // The default minimum admits free transactions.
+        assert!(check_fee(&ChainParams::default(), 0).is_ok());
+    }
+
+    #[test]
+    fn every_action_kind_is_admitted_by_default() {
+        for kind in ACTION_KINDS {
+            assert!(check_action_kind(&ChainParams::default(), kind).is_ok());
+        }
+    }
+
+    #[test]
+    fn denied_action_kinds_are_rejected() {
+        let params = ChainParams {
+            disallowed_actions: vec!["undelegate".to_string()],
+            ..Default::default()
+        };
+        assert!(check_action_kind(&params, "undelegate").is_err());
+        assert!(check_action_kind(&params, "delegate").is_ok());
+        assert!(check_action_kind(&params, "spend").is_ok());
+    }
+
+    #[test]
+    fn only_allowed_action_kinds_are_admitted() {
+        let params = ChainParams {
+            allowed_actions: vec!["spend".to_string(), "output".to_string()],
+            ..Default::default()
+        };
+        assert!(check_action_kind(&params, "spend").is_ok());
+        assert!(check_action_kind(&params, "output").is_ok());
+        assert!(check_action_kind(&params, "delegate").is_err());
+
+        // A kind which is both allowed and denied is denied.
+        let params = ChainParams {
+            disallowed_actions: vec!["output".to_string()],
+            ..params
+        };
+        assert!(check_action_kind(&params, "output").is_err());
+    }
+
+    #[test]
+    fn oversized_transactions_are_rejected() {
+        assert!(check_size(1000, 999).is_ok());
+        assert!(check_size(1000, 1000).is_ok());
+        assert!(check_size(1000, 1001).is_err());
+    }
+}
@@ -0,0 +1,40 @@
+//! Crate-specific metrics functionality.
+//!
+//! This module re-exports the contents of the `metrics` crate.  This is
+//! effectively a way to monkey-patch the functions in this module into the
+//! `metrics` crate, at least from the point of view of the other code in this
+//! crate.
+//!
+//! Code in this crate that wants to use metrics should `use crate::metrics;`,
+//! so that this module shadows the `metrics` crate.
+//!
+//! This trick is probably good to avoid in general, because it could be
+//! confusing, but in this limited case, it seems like a clean option.
+
+pub use metrics::*;
+
+/// Registers all metrics used by this crate.
+pub fn register_metrics() {
+    register_histogram!(COMMIT_NCT_DURATION);
+    describe_histogram!(
+        COMMIT_NCT_DURATION,
+        Unit::Seconds,
+        "The time spent serializing and writing the NCT when committing a block"
+    );
+    register_histogram!(COMMIT_STATE_DURATION);
+    describe_histogram!(
+        COMMIT_STATE_DURATION,
+        Unit::Seconds,
+        "The time spent computing the app hash and writing the JMT when committing a block"
+    );
+    register_histogram!(COMMIT_RESET_DURATION);
+    describe_histogram!(
+        COMMIT_RESET_DURATION,
+        Unit::Seconds,
+        "The time spent re-instantiating the components after committing a block"
+    );
+}
+
+pub const COMMIT_NCT_DURATION: &str = "penumbra_app_commit_nct_duration_seconds";
+pub const COMMIT_STATE_DURATION: &str = "penumbra_app_commit_state_duration_seconds";
+pub const COMMIT_RESET_DURATION: &str = "penumbra_app_commit_reset_duration_seconds";
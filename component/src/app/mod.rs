@@ -3,6 +3,9 @@ use crate::ibc::IBCComponent;
 use crate::shielded_pool::ShieldedPool;
 use crate::stake::component::Staking;
 use crate::{Component, Context};
+use std::time::Instant;
+
+use ::metrics::histogram;
 use anyhow::Result;
 use async_trait::async_trait;
 use jmt::{RootHash, Version};
@@ -13,8 +16,12 @@ use tendermint::abci::{self, types::ValidatorUpdate};
 
 use tracing::instrument;
 
+pub mod admission;
+mod metrics;
 pub mod state_key;
 
+pub use self::metrics::register_metrics;
+
 /// The Penumbra application, written as a bundle of [`Component`]s.
 ///
 /// The [`App`] is also a [`Component`], but as the top-level component,
@@ -65,20 +72,34 @@ impl App {
         // rather than the Penumbra state, because the serialization format for
         // the NCT should not be consensus-critical.  We need to grab a copy of
         // the entire NCT, so we can use it to re-instantiate the ShieldedPool.
+        let start = Instant::now();
         let nct = self.shielded_pool.note_commitment_tree();
         storage.put_nct(nct).await?;
-        // Commit the pending writes, clearing the state.
+        histogram!(metrics::COMMIT_NCT_DURATION, start.elapsed());
+
+        // Commit the pending writes, clearing the state. This computes the new app hash, and
+        // writes the changed JMT nodes, whose share of the time is measured by the storage.
+        let start = Instant::now();
         let (root_hash, version) = self.state.write().await.commit(storage.clone()).await?;
+        histogram!(metrics::COMMIT_STATE_DURATION, start.elapsed());
         tracing::debug!(?root_hash, version, "finished committing state");
 
+        // TODO: RocksDB writes aren't synced to disk when a block is committed, so there's no
+        // fsync to time here; if we start syncing the WAL on commit, measure it separately.
+        let start = Instant::now();
+
         // Get the latest version of the state, now that we've committed it.
         self.state = storage.state().await?;
 
-        // Now re-instantiate all of the components so they all have the same shared state.
+        // Now re-instantiate all of the components so they all have the same shared state,
+        // carrying over the staking component's in-memory invariant check.
+        let pool_snapshot = self.staking.pool_snapshot.take();
         self.staking = Staking::new(self.state.clone()).await;
+        self.staking.pool_snapshot = pool_snapshot;
         self.ibc = IBCComponent::new(self.state.clone()).await;
         self.dex = Dex::new(self.state.clone()).await;
         self.shielded_pool = ShieldedPool::new(self.state.clone(), nct.clone()).await;
+        histogram!(metrics::COMMIT_RESET_DURATION, start.elapsed());
 
         Ok((root_hash, version))
     }
@@ -142,6 +163,8 @@ impl Component for App {
 
     #[instrument(skip(self, ctx, tx))]
     async fn check_tx_stateful(&self, ctx: Context, tx: &Transaction) -> Result<()> {
+        admission::check(&self.state.get_chain_params().await?, tx)?;
+
         self.staking.check_tx_stateful(ctx.clone(), tx).await?;
         self.ibc.check_tx_stateful(ctx.clone(), tx).await?;
         self.dex.check_tx_stateful(ctx.clone(), tx).await?;
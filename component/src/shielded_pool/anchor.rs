@@ -0,0 +1,31 @@
+use penumbra_chain::ErrorCode;
+
+/// A transaction whose anchor is older than the chain's anchor window, so its spends can no longer
+/// be verified.
+///
+/// This is reported with its own code, so that clients can rebuild the transaction against a
+/// fresh anchor instead of failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpiredAnchor {
+    /// The height the transaction's anchor is the root of the note commitment tree at.
+    pub anchor_height: u64,
+    /// The height of the oldest anchor the chain currently accepts.
+    pub oldest_valid_height: u64,
+}
+
+impl ExpiredAnchor {
+    /// The `CheckTx` and `DeliverTx` code reported for an expired anchor.
+    pub const CODE: u32 = ErrorCode::ExpiredAnchor.code();
+}
+
+impl std::fmt::Display for ExpiredAnchor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "anchor from height {} has expired: the oldest anchor accepted is from height {}",
+            self.anchor_height, self.oldest_valid_height
+        )
+    }
+}
+
+impl std::error::Error for ExpiredAnchor {}
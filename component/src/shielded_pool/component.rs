@@ -20,12 +20,12 @@ use penumbra_crypto::{
     STAKING_TOKEN_ASSET_ID,
 };
 use penumbra_storage::{State, StateExt};
-use penumbra_tct as tct;
+use penumbra_tct::{self as tct, error::InsertError};
 use penumbra_transaction::{action::Undelegate, Action, Transaction};
 use tendermint::abci;
 use tracing::instrument;
 
-use crate::shielded_pool::{event, state_key, CommissionAmounts};
+use crate::shielded_pool::{event, state_key, CommissionAmounts, ExpiredAnchor};
 
 use super::Delible;
 
@@ -172,6 +172,20 @@ impl Component for ShieldedPool {
             self.state.check_nullifier_unspent(spent_nullifier).await?;
         }
 
+        // A validator definition can add funding streams, each of which is paid a commission
+        // note at the end of the epoch, so room is reserved for those too.
+        let defined_streams: usize = tx
+            .validator_definitions()
+            .map(|definition| {
+                definition
+                    .validator
+                    .as_ref()
+                    .map_or(0, |validator| validator.funding_streams.len())
+            })
+            .sum();
+        self.check_note_capacity(tx.note_payloads().len() + defined_streams)
+            .await?;
+
         // TODO: handle quarantine
         Ok(())
     }
@@ -199,7 +213,9 @@ impl Component for ShieldedPool {
             }
         } else {
             for compact_output in tx.note_payloads() {
-                self.add_note(compact_output, source).await;
+                self.add_note(compact_output, source)
+                    .await
+                    .expect("transactions whose notes don't fit in the block are rejected");
             }
             for spent_nullifier in tx.spent_nullifiers() {
                 self.spend_nullifier(spent_nullifier, source).await;
@@ -241,7 +257,7 @@ impl Component for ShieldedPool {
                 source,
             )
             .await
-            .unwrap();
+            .expect("room for commission notes is reserved when admitting transactions");
         }
 
         // Schedule all unquarantining that was set up in this block
@@ -302,7 +318,7 @@ impl ShieldedPool {
         let position: u64 = self
             .note_commitment_tree
             .position()
-            .expect("note commitment tree is not full")
+            .ok_or(InsertError::Full)?
             .into();
 
         let blinding_factor = Fq::from_le_bytes_mod_order(
@@ -341,19 +357,22 @@ impl ShieldedPool {
             },
             source,
         )
-        .await;
+        .await?;
 
         Ok(())
     }
 
     #[instrument(skip(self, source, note_payload), fields(note_commitment = ?note_payload.note_commitment))]
-    async fn add_note(&mut self, note_payload: NotePayload, source: NoteSource) {
+    async fn add_note(
+        &mut self,
+        note_payload: NotePayload,
+        source: NoteSource,
+    ) -> Result<(), InsertError> {
         tracing::debug!("adding note");
 
         // 1. Insert it into the NCT
         self.note_commitment_tree
-            .insert(tct::Witness::Forget, note_payload.note_commitment)
-            .expect("inserting into the note commitment tree never fails");
+            .insert(tct::Witness::Forget, note_payload.note_commitment)?;
 
         // 2. Record its source in the JMT
         self.state
@@ -362,6 +381,8 @@ impl ShieldedPool {
 
         // 3. Finally, record it in the pending compact block.
         self.compact_block.note_payloads.push(note_payload);
+
+        Ok(())
     }
 
     #[instrument(skip(self, source, note_payload), fields(note_commitment = ?note_payload.note_commitment))]
@@ -434,15 +455,27 @@ impl ShieldedPool {
     #[instrument(skip(self))]
     async fn write_compactblock_and_nct(&mut self) -> Result<()> {
         // Extract the compact block, resetting it
-        let compact_block = std::mem::take(&mut self.compact_block);
+        let mut compact_block = std::mem::take(&mut self.compact_block);
         let height = self.height().await;
 
+        // Record the block time, so clients can date their history (there's no block time
+        // at genesis, so this is left unset there)
+        compact_block.block_time = self
+            .state
+            .get_block_timestamp()
+            .await
+            .ok()
+            .map(|time| time.to_rfc3339());
+
+        // Record the NCT root, so clients can check their scanned tree against consensus (the
+        // compact block is stored in the JMT, so this is committed to by the app hash)
+        let nct_root = self.note_commitment_tree.root();
+        compact_block.nct_root = Some(nct_root);
+
         // Write the CompactBlock:
         self.state.set_compact_block(compact_block).await;
         // and the note commitment tree data and anchor:
-        self.state
-            .set_nct_anchor(height, self.note_commitment_tree.root())
-            .await;
+        self.state.set_nct_anchor(height, nct_root).await;
 
         Ok(())
     }
@@ -453,17 +486,7 @@ impl ShieldedPool {
         // Get the current block height
         let height = self.height().await;
 
-        // Close the block in the TCT
-        let block_root = self
-            .note_commitment_tree
-            .end_block()
-            .expect("ending a block in the note commitment tree can never fail");
-
-        // Put the block root in the compact block
-        self.compact_block.block_root = block_root;
-
-        // If the block ends an epoch, also close the epoch in the TCT
-        if Epoch::from_height(
+        let epoch_ends = Epoch::from_height(
             height,
             self.state
                 .get_chain_params()
@@ -471,18 +494,13 @@ impl ShieldedPool {
                 .expect("chain params request must succeed")
                 .epoch_duration,
         )
-        .is_epoch_end(height)
-        {
-            tracing::debug!(?height, "end of epoch");
+        .is_epoch_end(height);
 
-            let epoch_root = self
-                .note_commitment_tree
-                .end_epoch()
-                .expect("ending an epoch in the note commitment tree can never fail");
-
-            // Put the epoch root in the compact block
-            self.compact_block.epoch_root = Some(epoch_root);
-        }
+        // Put the block root, and the epoch root if the epoch was closed, in the compact block
+        let (block_root, epoch_root) =
+            end_nct_block(&mut self.note_commitment_tree, height, epoch_ends);
+        self.compact_block.block_root = block_root;
+        self.compact_block.epoch_root = epoch_root;
     }
 
     /// Get the current block height.
@@ -563,6 +581,39 @@ impl ShieldedPool {
         should_quarantine
     }
 
+    /// Turn away a transaction adding `notes` which don't fit in the rest of the block, leaving
+    /// room for the notes `end_block` adds, rather than failing to insert them into the NCT later.
+    async fn check_note_capacity(&self, notes: usize) -> Result<()> {
+        let notes = u32::try_from(notes)
+            .unwrap_or(u32::MAX)
+            .saturating_add(self.end_block_notes().await?);
+        self.note_commitment_tree
+            .check_capacity(notes)
+            .map_err(|e| anyhow::anyhow!("transaction's notes don't fit in the block: {}", e))
+    }
+
+    /// The number of notes `end_block` may add to the NCT in this block, which transactions must
+    /// leave room for: at the end of an epoch, the notes leaving quarantine, and a commission note
+    /// for each funding stream of each validator.
+    async fn end_block_notes(&self) -> Result<u32> {
+        let this_epoch = self.epoch().await;
+        if !this_epoch.is_epoch_end(self.height().await) {
+            return Ok(0);
+        }
+
+        let mut notes = 0;
+        for (_, per_validator) in self.state.scheduled_to_apply(this_epoch.index).await? {
+            notes += per_validator.note_payloads.len();
+        }
+        for identity_key in self.state.validator_list().await? {
+            if let Some(validator) = self.state.validator(&identity_key).await? {
+                notes += validator.funding_streams.as_ref().len();
+            }
+        }
+
+        Ok(notes.try_into().unwrap_or(u32::MAX))
+    }
+
     async fn schedule_unquarantine(&mut self) {
         // First, we group all the scheduled quarantined notes by unquarantine epoch, in the process
         // resetting the quarantine field of the component
@@ -607,7 +658,9 @@ impl ShieldedPool {
                         .expect("can try to unquarantine note")
                         .expect("note payload to unquarantine has source");
                     tracing::debug!(?note_payload, "unquarantining note");
-                    self.add_note(note_payload, note_source).await;
+                    self.add_note(note_payload, note_source).await.expect(
+                        "room for notes leaving quarantine is reserved when admitting transactions",
+                    );
                 }
                 // For all the nullifiers scheduled for unquarantine now, remove them from
                 // quarantine and add them to the proper nullifiers for this block
@@ -767,20 +820,30 @@ pub trait View: StateExt {
         .await;
     }
 
-    /// Checks whether a claimed NCT anchor is a previous valid state root.
+    /// Checks whether a claimed NCT anchor is a previous valid state root, recent enough to be
+    /// within the chain's anchor window.
     async fn check_claimed_anchor(&self, anchor: &tct::Root) -> Result<()> {
-        if let Some(anchor_height) = self
+        let anchor_height = self
             .get_proto::<u64>(state_key::anchor_lookup(anchor))
             .await?
-        {
-            tracing::debug!(?anchor, ?anchor_height, "anchor is valid");
-            Ok(())
-        } else {
-            Err(anyhow!(
-                "provided anchor {} is not a valid NCT root",
-                anchor
-            ))
+            .ok_or_else(|| anyhow!("provided anchor {} is not a valid NCT root", anchor))?;
+
+        if let Some(anchor_window_len) = self.get_chain_params().await?.anchor_window_len {
+            let oldest_valid_height = self
+                .get_block_height()
+                .await?
+                .saturating_sub(anchor_window_len);
+            if anchor_height < oldest_valid_height {
+                return Err(ExpiredAnchor {
+                    anchor_height,
+                    oldest_valid_height,
+                }
+                .into());
+            }
         }
+
+        tracing::debug!(?anchor, ?anchor_height, "anchor is valid");
+        Ok(())
     }
 
     // Returns the source if the nullifier was in quarantine already
@@ -904,3 +967,168 @@ pub trait View: StateExt {
 }
 
 impl<T: StateExt> View for T {}
+
+/// Close the block at `height` in the NCT, and its epoch too if `epoch_ends`, returning the block
+/// root and the epoch root, if the epoch was closed.
+///
+/// An epoch of the NCT only has room for 2^16 blocks, so if the chain's epochs are longer than
+/// that, the NCT is rolled over to a new epoch as soon as it's full, rather than failing to end
+/// the next block in it. Clients end the epoch whenever the compact block has an epoch root, so
+/// they follow along.
+fn end_nct_block(
+    nct: &mut tct::Tree,
+    height: u64,
+    epoch_ends: bool,
+) -> (tct::builder::block::Root, Option<tct::builder::epoch::Root>) {
+    // Close the block (the epoch is rolled over below as soon as it's full, so there's always
+    // room to end a block)
+    let block_root = nct
+        .end_block()
+        .expect("ending a block in the note commitment tree can never fail");
+
+    if !epoch_ends && !nct.current_epoch_is_full() {
+        return (block_root, None);
+    }
+
+    if epoch_ends {
+        tracing::debug!(?height, "end of epoch");
+    } else {
+        tracing::warn!(
+            ?height,
+            "epoch of the note commitment tree is full, rolling over"
+        );
+    }
+    let epoch_root = nct
+        .end_epoch()
+        .expect("the note commitment tree has room for a new epoch");
+
+    (block_root, Some(epoch_root))
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_chain::{params::ChainParams, sync::CompactBlock};
+    use penumbra_crypto::{
+        keys::{SeedPhrase, SpendKey},
+        rdsa::{SigningKey, SpendAuth},
+    };
+    use penumbra_storage::Storage;
+    use rand_core::OsRng;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        shielded_pool::CommissionAmount,
+        stake::{state_key as stake_state_key, validator::Validator, FundingStream, View as _},
+    };
+
+    #[tokio::test]
+    async fn end_block_notes_fit_after_transactions_fill_the_block() {
+        let dir = tempdir().unwrap();
+        let storage = Storage::load(dir.path().join("shielded_pool.db"))
+            .await
+            .unwrap();
+        let state = storage.state().await.unwrap();
+        // Every block ends an epoch, so every block pays commission.
+        state
+            .put_chain_params(ChainParams {
+                chain_id: "test".to_string(),
+                epoch_duration: 1,
+                ..Default::default()
+            })
+            .await;
+        state.put_block_height(1).await;
+
+        // A validator with one funding stream is paid one commission note.
+        let (address, _dtk_d) = SpendKey::from_seed_phrase(SeedPhrase::generate(OsRng), 0)
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+        let validator = Validator {
+            identity_key: IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into()),
+            consensus_key: tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(
+                OsRng,
+            ))
+            .public_key(),
+            name: "test validator".to_string(),
+            website: String::new(),
+            description: String::new(),
+            enabled: true,
+            funding_streams: vec![FundingStream {
+                address,
+                rate_bps: 100,
+            }]
+            .try_into()
+            .unwrap(),
+            sequence_number: 0,
+        };
+        state
+            .put_domain(
+                stake_state_key::validator_definition(&validator.identity_key),
+                validator.clone(),
+            )
+            .await;
+        state
+            .set_validator_list(vec![validator.identity_key.clone()])
+            .await;
+
+        // Transactions have filled all but one place in the block, which is left for the
+        // commission note...
+        let mut pool = ShieldedPool::new(state.clone(), tct::Tree::new()).await;
+        let capacity = pool.note_commitment_tree.remaining_capacity().commitments;
+        for i in 1..capacity {
+            pool.note_commitment_tree
+                .insert(tct::Witness::Forget, tct::Commitment(Fq::from(i as u64)))
+                .unwrap();
+        }
+        assert!(pool.check_note_capacity(1).await.is_err());
+        pool.check_note_capacity(0).await.unwrap();
+
+        // ...so minting it at the end of the block doesn't fail.
+        state
+            .set_commission_amounts(
+                1,
+                CommissionAmounts {
+                    notes: vec![CommissionAmount {
+                        amount: 1,
+                        destination: address,
+                    }],
+                },
+            )
+            .await;
+        pool.end_block(Context::new(), &abci::request::EndBlock { height: 1 })
+            .await;
+        let compact_block = state.compact_block(1).await.unwrap().unwrap();
+        assert_eq!(compact_block.note_payloads.len(), 1);
+    }
+
+    #[test]
+    fn full_nct_epochs_are_rolled_over_early() {
+        let mut nct = tct::Tree::new();
+        nct.insert(tct::Witness::Forget, tct::Commitment(Fq::from(1u64)))
+            .unwrap();
+
+        // Ending a block before the epoch is full only ends the block...
+        let (_, epoch_root) = end_nct_block(&mut nct, 1, false);
+        assert_eq!(epoch_root, None);
+
+        // ...but ending the block which fills it rolls the epoch over, even mid-epoch.
+        let blocks = nct.remaining_capacity().blocks;
+        for height in 2..=blocks as u64 {
+            assert_eq!(end_nct_block(&mut nct, height, false).1, None);
+        }
+        let (_, epoch_root) = end_nct_block(&mut nct, blocks as u64 + 1, false);
+        assert!(epoch_root.is_some());
+        assert!(!nct.current_epoch_is_full());
+        nct.insert(tct::Witness::Forget, tct::Commitment(Fq::from(2u64)))
+            .unwrap();
+
+        // The compact block carrying the epoch root is always scanned, so that clients roll over
+        // their own trees along with it.
+        let compact_block = CompactBlock {
+            epoch_root,
+            ..Default::default()
+        };
+        assert!(compact_block.requires_scanning());
+    }
+}
@@ -1,3 +1,4 @@
+mod anchor;
 mod commission;
 mod component;
 mod delible;
@@ -7,6 +8,7 @@ mod metrics;
 pub mod state_key;
 
 pub use self::metrics::register_metrics;
+pub use anchor::ExpiredAnchor;
 pub use commission::{CommissionAmount, CommissionAmounts};
 pub use component::{ShieldedPool, View};
 pub use delible::Delible;
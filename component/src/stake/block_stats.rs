@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use penumbra_proto::{stake as pb, Protobuf};
+use serde::{Deserialize, Serialize};
+
+/// The number of a validator's most recent proposals whose latency is kept, to report
+/// percentiles over.
+pub const LATENCY_WINDOW_LEN: usize = 100;
+
+/// Records how a validator has performed at producing blocks, over its lifetime.
+///
+/// Unlike the [`Uptime`](super::Uptime), which only covers a window of recent blocks and decides
+/// whether the validator is jailed, these are informational, for operators monitoring their
+/// validators.
+///
+/// A proposal's latency is the time between the previous block and the proposed one, by their
+/// header times. Tendermint doesn't report when each validator's vote arrived, so this is the
+/// closest measure of how quickly a validator gets its blocks signed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "pb::ValidatorBlockStats", into = "pb::ValidatorBlockStats")]
+pub struct BlockStats {
+    /// The number of blocks the validator proposed.
+    pub blocks_proposed: u64,
+    /// The number of blocks the validator signed while active.
+    pub blocks_signed: u64,
+    /// The number of blocks the validator failed to sign while active.
+    pub blocks_missed: u64,
+    /// The total number of transactions in the blocks the validator proposed.
+    pub proposed_transactions: u64,
+    /// The total size, in bytes, of the transactions in the blocks the validator proposed.
+    pub proposed_bytes: u64,
+    /// The latencies of the validator's most recent proposals, oldest first.
+    pub recent_latencies: Vec<Duration>,
+}
+
+impl BlockStats {
+    /// Record that the validator did (`true`) or did not (`false`) sign a block.
+    pub fn record_signature(&mut self, signed: bool) {
+        if signed {
+            self.blocks_signed += 1;
+        } else {
+            self.blocks_missed += 1;
+        }
+    }
+
+    /// Record that the validator proposed a block of `transactions` transactions totalling
+    /// `bytes` bytes, with the given latency, if there was a previous block to measure it from.
+    pub fn record_proposal(&mut self, transactions: u64, bytes: u64, latency: Option<Duration>) {
+        self.blocks_proposed += 1;
+        self.proposed_transactions += transactions;
+        self.proposed_bytes += bytes;
+
+        if let Some(latency) = latency {
+            if self.recent_latencies.len() == LATENCY_WINDOW_LEN {
+                self.recent_latencies.remove(0);
+            }
+            self.recent_latencies.push(latency);
+        }
+    }
+
+    /// The average number of transactions in the blocks the validator proposed, if any.
+    pub fn average_block_transactions(&self) -> Option<f64> {
+        (self.blocks_proposed > 0)
+            .then(|| self.proposed_transactions as f64 / self.blocks_proposed as f64)
+    }
+
+    /// The average size, in bytes, of the blocks the validator proposed, if any.
+    pub fn average_block_bytes(&self) -> Option<f64> {
+        (self.blocks_proposed > 0).then(|| self.proposed_bytes as f64 / self.blocks_proposed as f64)
+    }
+
+    /// The latency below which `percentile` percent of the validator's recent proposals fall, by
+    /// the nearest rank, if it has proposed any blocks with a measured latency.
+    pub fn latency_percentile(&self, percentile: u8) -> Option<Duration> {
+        let mut latencies = self.recent_latencies.clone();
+        latencies.sort();
+
+        let rank = (usize::from(percentile.min(100)) * latencies.len() + 99) / 100;
+        latencies.get(rank.saturating_sub(1)).copied()
+    }
+}
+
+impl Protobuf<pb::ValidatorBlockStats> for BlockStats {}
+
+impl From<BlockStats> for pb::ValidatorBlockStats {
+    fn from(stats: BlockStats) -> pb::ValidatorBlockStats {
+        pb::ValidatorBlockStats {
+            blocks_proposed: stats.blocks_proposed,
+            blocks_signed: stats.blocks_signed,
+            blocks_missed: stats.blocks_missed,
+            proposed_transactions: stats.proposed_transactions,
+            proposed_bytes: stats.proposed_bytes,
+            recent_latencies_ms: stats
+                .recent_latencies
+                .iter()
+                .map(|latency| latency.as_millis() as u64)
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<pb::ValidatorBlockStats> for BlockStats {
+    type Error = anyhow::Error;
+    fn try_from(msg: pb::ValidatorBlockStats) -> Result<BlockStats, Self::Error> {
+        if msg.recent_latencies_ms.len() > LATENCY_WINDOW_LEN {
+            return Err(anyhow::anyhow!(
+                "too many recent latencies: {} > {}",
+                msg.recent_latencies_ms.len(),
+                LATENCY_WINDOW_LEN
+            ));
+        }
+        Ok(BlockStats {
+            blocks_proposed: msg.blocks_proposed,
+            blocks_signed: msg.blocks_signed,
+            blocks_missed: msg.blocks_missed,
+            proposed_transactions: msg.proposed_transactions,
+            proposed_bytes: msg.proposed_bytes,
+            recent_latencies: msg
+                .recent_latencies_ms
+                .into_iter()
+                .map(Duration::from_millis)
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_recent_latencies() {
+        let mut stats = BlockStats::default();
+        for ms in 1..=(2 * LATENCY_WINDOW_LEN as u64) {
+            stats.record_proposal(2, 1000, Some(Duration::from_millis(ms)));
+        }
+        // A proposal with no previous block isn't counted towards the latencies.
+        stats.record_proposal(0, 0, None);
+
+        assert_eq!(stats.blocks_proposed, 2 * LATENCY_WINDOW_LEN as u64 + 1);
+        assert_eq!(stats.recent_latencies.len(), LATENCY_WINDOW_LEN);
+        assert_eq!(
+            stats.latency_percentile(0),
+            Some(Duration::from_millis(LATENCY_WINDOW_LEN as u64 + 1))
+        );
+        assert_eq!(
+            stats.latency_percentile(50),
+            Some(Duration::from_millis(LATENCY_WINDOW_LEN as u64 + 50))
+        );
+        assert_eq!(
+            stats.latency_percentile(100),
+            Some(Duration::from_millis(2 * LATENCY_WINDOW_LEN as u64))
+        );
+        assert_eq!(BlockStats::default().latency_percentile(50), None);
+    }
+
+    #[test]
+    fn proto_round_trip() {
+        let mut stats = BlockStats::default();
+        for h in 1..300u64 {
+            stats.record_signature(h % 13 != 0);
+            if h % 7 == 0 {
+                stats.record_proposal(h % 3, h * 100, Some(Duration::from_millis(h * 10)));
+            }
+        }
+
+        let bytes = stats.encode_to_vec();
+        let stats2 = BlockStats::decode(bytes.as_slice()).unwrap();
+        assert_eq!(stats, stats2);
+    }
+}
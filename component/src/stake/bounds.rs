@@ -0,0 +1,154 @@
+use penumbra_chain::{params::ChainParams, ErrorCode};
+
+/// A validator definition which violates the chain's bounds on validator parameters.
+///
+/// Each violation is reported with its own `DeliverTx` code, so that clients can tell them apart
+/// without parsing the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundsViolation {
+    /// The validator's total commission is below the chain's minimum.
+    CommissionTooLow { commission_bps: u64, min_bps: u64 },
+    /// The validator's total commission is above the chain's maximum.
+    CommissionTooHigh { commission_bps: u64, max_bps: u64 },
+    /// The validator's total commission changed by more than the chain allows in one epoch.
+    CommissionChangeTooLarge {
+        epoch_start_bps: u64,
+        commission_bps: u64,
+        max_change_bps: u64,
+    },
+}
+
+impl BoundsViolation {
+    /// The `DeliverTx` code reported for this violation.
+    pub fn code(&self) -> u32 {
+        match self {
+            BoundsViolation::CommissionTooLow { .. } => ErrorCode::CommissionTooLow,
+            BoundsViolation::CommissionTooHigh { .. } => ErrorCode::CommissionTooHigh,
+            BoundsViolation::CommissionChangeTooLarge { .. } => ErrorCode::CommissionChangeTooLarge,
+        }
+        .code()
+    }
+}
+
+impl std::fmt::Display for BoundsViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoundsViolation::CommissionTooLow {
+                commission_bps,
+                min_bps,
+            } => write!(
+                f,
+                "validator commission of {}bps is below the minimum of {}bps",
+                commission_bps, min_bps
+            ),
+            BoundsViolation::CommissionTooHigh {
+                commission_bps,
+                max_bps,
+            } => write!(
+                f,
+                "validator commission of {}bps is above the maximum of {}bps",
+                commission_bps, max_bps
+            ),
+            BoundsViolation::CommissionChangeTooLarge {
+                epoch_start_bps,
+                commission_bps,
+                max_change_bps,
+            } => write!(
+                f,
+                "validator commission changed from {}bps at the start of the epoch to {}bps, more than the maximum change of {}bps per epoch",
+                epoch_start_bps, commission_bps, max_change_bps
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BoundsViolation {}
+
+/// Check a validator's new total commission, `commission_bps`, against the bounds in `params`.
+///
+/// The change in commission is only bounded for a validator which already existed, whose
+/// commission at the start of the epoch is `epoch_start_bps`.
+pub fn check_commission(
+    params: &ChainParams,
+    epoch_start_bps: Option<u64>,
+    commission_bps: u64,
+) -> Result<(), BoundsViolation> {
+    if commission_bps < params.min_validator_commission_bps {
+        return Err(BoundsViolation::CommissionTooLow {
+            commission_bps,
+            min_bps: params.min_validator_commission_bps,
+        });
+    }
+    if let Some(max_bps) = params.max_validator_commission_bps {
+        if commission_bps > max_bps {
+            return Err(BoundsViolation::CommissionTooHigh {
+                commission_bps,
+                max_bps,
+            });
+        }
+    }
+    if let (Some(epoch_start_bps), Some(max_change_bps)) =
+        (epoch_start_bps, params.max_validator_commission_change_bps)
+    {
+        if commission_bps.abs_diff(epoch_start_bps) > max_change_bps {
+            return Err(BoundsViolation::CommissionChangeTooLarge {
+                epoch_start_bps,
+                commission_bps,
+                max_change_bps,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> ChainParams {
+        ChainParams {
+            min_validator_commission_bps: 100,
+            max_validator_commission_bps: Some(2000),
+            max_validator_commission_change_bps: Some(200),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn commission_within_bounds_is_allowed() {
+        assert_eq!(check_commission(&params(), None, 100), Ok(()));
+        assert_eq!(check_commission(&params(), None, 2000), Ok(()));
+        assert_eq!(check_commission(&params(), Some(500), 700), Ok(()));
+        assert_eq!(check_commission(&params(), Some(500), 300), Ok(()));
+        // Unset bounds don't limit anything.
+        assert_eq!(
+            check_commission(&ChainParams::default(), Some(0), 10_000),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn commission_out_of_bounds_is_rejected() {
+        assert_eq!(
+            check_commission(&params(), None, 99).unwrap_err().code(),
+            101
+        );
+        assert_eq!(
+            check_commission(&params(), None, 2001).unwrap_err().code(),
+            102
+        );
+        assert_eq!(
+            check_commission(&params(), Some(500), 701)
+                .unwrap_err()
+                .code(),
+            103
+        );
+        assert_eq!(
+            check_commission(&params(), Some(500), 299)
+                .unwrap_err()
+                .code(),
+            103
+        );
+    }
+}
@@ -8,7 +8,7 @@ use anyhow::{anyhow, Context as _, Result};
 use async_trait::async_trait;
 use penumbra_chain::quarantined::Slashed;
 use penumbra_chain::{genesis, Epoch, View as _};
-use penumbra_crypto::{DelegationToken, IdentityKey, STAKING_TOKEN_ASSET_ID};
+use penumbra_crypto::{Amount, DelegationToken, IdentityKey, STAKING_TOKEN_ASSET_ID};
 use penumbra_proto::Protobuf;
 use penumbra_storage::{State, StateExt};
 use penumbra_transaction::{
@@ -21,21 +21,30 @@ use tendermint::{
         self,
         types::{Evidence, LastCommitInfo, ValidatorUpdate},
     },
-    block, PublicKey,
+    account, block, PublicKey, Time,
 };
 use tracing::{instrument, Instrument};
 
 use crate::stake::{
+    invariant::{Pool, PoolSnapshot},
     metrics,
     rate::{BaseRateData, RateData},
     validator::{self, Validator},
-    DelegationChanges, Uptime,
+    BlockStats, ConsensusKeyRotations, DelegationChanges, Uptime,
 };
 
 // Max validator power is 1152921504606846975 (i64::MAX / 8)
 // https://github.com/tendermint/tendermint/blob/master/types/validator_set.go#L25
 const MAX_VOTING_POWER: i64 = 1152921504606846975;
 
+/// The address tendermint identifies a validator by in votes and block
+/// headers, the first 20 bytes of the SHA256 hash of its consensus key.
+fn consensus_address(consensus_key: &PublicKey) -> [u8; 20] {
+    Sha256::digest(&consensus_key.to_bytes()).as_slice()[0..20]
+        .try_into()
+        .unwrap()
+}
+
 // Staking component
 pub struct Staking {
     state: State,
@@ -46,6 +55,20 @@ pub struct Staking {
     /// List of changes to the tendermint validator set accumulated throughout
     /// this block, to be returned during `EndBlock`.
     tm_validator_updates: BTreeMap<IdentityKey, u64>,
+    /// The previous consensus keys of validators whose keys were rotated at
+    /// the end of this block, to be removed from the tendermint validator set.
+    /// A key is `None` if the validator wasn't in the set.
+    rotated_consensus_keys: BTreeMap<IdentityKey, Option<PublicKey>>,
+    /// The validator which proposed this block, and the number and total size
+    /// of the transactions in it, to be recorded in the proposer's block
+    /// production statistics during `EndBlock`.
+    block_proposer: Option<IdentityKey>,
+    block_transactions: u64,
+    block_bytes: u64,
+    /// The delegation pools at the end of the last epoch, to check the next
+    /// epoch's against. This is only kept in memory, so the first epoch after
+    /// a restart isn't checked.
+    pub(crate) pool_snapshot: Option<PoolSnapshot>,
 }
 
 impl Staking {
@@ -55,6 +78,11 @@ impl Staking {
             state,
             delegation_changes: Default::default(),
             tm_validator_updates: Default::default(),
+            rotated_consensus_keys: Default::default(),
+            block_proposer: None,
+            block_transactions: 0,
+            block_bytes: 0,
+            pool_snapshot: None,
         }
     }
 
@@ -92,7 +120,7 @@ impl Staking {
         cur_state: validator::State,
         new_state: validator::State,
     ) -> Result<()> {
-        let state_key = format!("staking/validators/{}/state", identity_key).into();
+        let state_key = super::state_key::validator_state(identity_key);
 
         // Update metrics
         match cur_state {
@@ -298,6 +326,11 @@ impl Staking {
 
     #[instrument(skip(self, epoch_to_end), fields(index = epoch_to_end.index))]
     async fn end_epoch(&mut self, epoch_to_end: Epoch) -> Result<()> {
+        // Apply the consensus key rotations queued during this epoch before any
+        // state transitions, so that we know which validators tendermint knows
+        // by their old keys.
+        self.rotate_consensus_keys(&epoch_to_end).await?;
+
         // calculate rate data for next rate, move previous next rate to cur rate,
         // and save the next rate data. ensure that non-Active validators maintain constant rates.
         let mut delegations_by_validator = BTreeMap::<IdentityKey, Vec<Delegate>>::new();
@@ -350,6 +383,7 @@ impl Staking {
             .await;
 
         let mut commission_amounts = Vec::new();
+        let mut pools = BTreeMap::new();
         let validator_list = self.state.validator_list().await?;
         for v in &validator_list {
             let validator = self.state.validator(v).await?.ok_or_else(|| {
@@ -371,17 +405,30 @@ impl Staking {
                 current_rate.next(&next_base_rate, funding_streams.as_ref(), &validator_state);
             assert!(next_rate.epoch_index == epoch_to_end.index + 2);
 
-            let total_delegations = delegations_by_validator
-                .get(&validator.identity_key)
-                .into_iter()
-                .flat_map(|ds| ds.iter().map(|d| d.delegation_amount))
-                .sum::<u64>();
-            let total_undelegations = undelegations_by_validator
-                .get(&validator.identity_key)
-                .into_iter()
-                .flat_map(|us| us.iter().map(|u| u.delegation_amount))
-                .sum::<u64>();
+            let total_delegations = Amount::checked_sum(
+                delegations_by_validator
+                    .get(&validator.identity_key)
+                    .into_iter()
+                    .flat_map(|ds| ds.iter().map(|d| Amount::from(d.delegation_amount))),
+            )
+            .ok_or_else(|| anyhow!("total delegations to {} overflowed", validator.identity_key))?
+            .value();
+            let total_undelegations = Amount::checked_sum(
+                undelegations_by_validator
+                    .get(&validator.identity_key)
+                    .into_iter()
+                    .flat_map(|us| us.iter().map(|u| Amount::from(u.delegation_amount))),
+            )
+            .ok_or_else(|| {
+                anyhow!(
+                    "total undelegations from {} overflowed",
+                    validator.identity_key
+                )
+            })?
+            .value();
             let delegation_delta = (total_delegations as i64) - (total_undelegations as i64);
+            let changes = delegations_by_validator.get(v).map_or(0, Vec::len)
+                + undelegations_by_validator.get(v).map_or(0, Vec::len);
 
             tracing::debug!(
                 validator = ?validator.identity_key,
@@ -415,6 +462,15 @@ impl Staking {
                 .await?
                 .expect("delegation token should be known");
 
+            pools.insert(
+                v.clone(),
+                Pool {
+                    delegation_supply: delegation_token_supply,
+                    rate: current_rate.clone(),
+                    changes,
+                },
+            );
+
             // Calculate the voting power in the newly beginning epoch
             let voting_power =
                 current_rate.voting_power(delegation_token_supply, &current_base_rate);
@@ -466,6 +522,19 @@ impl Staking {
         // The pending delegation changes should be empty at the beginning of the next epoch.
         self.delegation_changes = Default::default();
 
+        let commission =
+            Amount::checked_sum(commission_amounts.iter().map(|c| Amount::from(c.amount)))
+                .ok_or_else(|| anyhow!("total commission overflowed"))?;
+        self.check_pool_invariant(PoolSnapshot {
+            staking_supply: self
+                .state
+                .token_supply(&STAKING_TOKEN_ASSET_ID)
+                .await?
+                .unwrap_or(0),
+            commission: commission.value(),
+            pools,
+        });
+
         // Set the pending reward notes on the JMT for the current block height
         // so they can be processed by the ShieldedPool.
         self.state
@@ -480,6 +549,103 @@ impl Staking {
         Ok(())
     }
 
+    /// Replace the consensus keys of validators who queued a rotation during
+    /// `epoch`, keeping their identity keys and delegations.
+    async fn rotate_consensus_keys(&mut self, epoch: &Epoch) -> Result<()> {
+        let rotations = self.state.consensus_key_rotations(epoch.index).await?;
+        let mut previous_keys = ConsensusKeyRotations::default();
+
+        for (identity_key, consensus_key) in rotations.rotations {
+            let mut validator = self
+                .state
+                .validator(&identity_key)
+                .await?
+                .ok_or_else(|| anyhow!("queued rotation for missing validator"))?;
+            let state = self
+                .state
+                .validator_state(&identity_key)
+                .await?
+                .ok_or_else(|| anyhow!("missing state for validator {}", identity_key))?;
+
+            let old_key = std::mem::replace(&mut validator.consensus_key, consensus_key);
+            if old_key == consensus_key {
+                continue;
+            }
+            tracing::debug!(
+                ?identity_key,
+                ?old_key,
+                ?consensus_key,
+                "rotating consensus key"
+            );
+
+            // Tendermint knows the validator by its old key if it's active, or
+            // if it was removed from the active set earlier in this block.
+            let in_tm_set = state == validator::State::Active
+                || self.tm_validator_updates.get(&identity_key) == Some(&0);
+            self.rotated_consensus_keys.insert(
+                identity_key.clone(),
+                if in_tm_set { Some(old_key) } else { None },
+            );
+            if in_tm_set {
+                previous_keys.insert(identity_key.clone(), old_key);
+            }
+
+            // The mapping from the old key is kept, so that evidence of
+            // misbehavior signed with it can still be attributed.
+            self.state
+                .register_consensus_key(&consensus_key, &identity_key)
+                .await;
+            self.state
+                .put_domain(
+                    super::state_key::validator_definition(&identity_key),
+                    validator,
+                )
+                .await;
+        }
+
+        // Tendermint only applies the validator set updates from this block
+        // two blocks later, so until then, the rotated validators keep signing
+        // with their old keys.
+        if !previous_keys.rotations.is_empty() {
+            let height = self.state.get_block_height().await?;
+            self.state
+                .set_previous_consensus_keys(height, previous_keys)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    /// Compare the staking token supply and delegation pools at the end of this epoch with those
+    /// at the end of the last, recording the discrepancy, and panicking in debug builds if it's
+    /// more than rounding can explain.
+    fn check_pool_invariant(&mut self, snapshot: PoolSnapshot) {
+        gauge!(metrics::DELEGATION_POOL_VALUE, snapshot.pool_value() as f64);
+
+        if let Some(previous) = &self.pool_snapshot {
+            let discrepancy = snapshot.discrepancy_since(previous);
+            gauge!(
+                metrics::DELEGATION_POOL_DISCREPANCY,
+                discrepancy.amount as f64
+            );
+            if discrepancy.is_violation() {
+                tracing::error!(
+                    ?discrepancy,
+                    ?previous,
+                    current = ?snapshot,
+                    "delegation pool invariant violated"
+                );
+            }
+            debug_assert!(
+                !discrepancy.is_violation(),
+                "delegation pool invariant violated: {:?}",
+                discrepancy
+            );
+        }
+
+        self.pool_snapshot = Some(snapshot);
+    }
+
     /// Called during `end_epoch`. Will perform state transitions to validators based
     /// on changes to voting power that occurred in this epoch.
     pub async fn set_active_and_inactive_validators(&mut self) -> Result<()> {
@@ -552,7 +718,46 @@ impl Staking {
         // key is convenient internally, but to create the Tendermint update, we
         // now need to look up the consensus key for each validator.
         let mut updates = Vec::new();
+
+        // Validators whose consensus keys were rotated are removed from the
+        // set under their old keys, and re-added under their new ones.
+        for (identity_key, old_key) in &self.rotated_consensus_keys {
+            let old_key = match old_key {
+                Some(old_key) => old_key,
+                None => continue,
+            };
+            updates.push(ValidatorUpdate {
+                pub_key: *old_key,
+                power: 0u64.try_into().unwrap(),
+            });
+
+            // If the validator's power wasn't otherwise updated in this block,
+            // it's still active, with the same power.
+            if !self.tm_validator_updates.contains_key(identity_key) {
+                let validator = self
+                    .state
+                    .validator(identity_key)
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("rotated key of missing validator"))?;
+                let power = self
+                    .state
+                    .validator_power(identity_key)
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("active validator did not have power"))?;
+                updates.push(ValidatorUpdate {
+                    pub_key: validator.consensus_key,
+                    power: power.try_into().unwrap(),
+                });
+            }
+        }
+
         for (identity_key, power) in &self.tm_validator_updates {
+            // Tendermint never knew a rotated validator by its new key, so
+            // there's nothing to remove.
+            if *power == 0 && self.rotated_consensus_keys.contains_key(identity_key) {
+                continue;
+            }
+
             let validator = self
                 .state
                 .validator(identity_key)
@@ -567,7 +772,11 @@ impl Staking {
     }
 
     #[instrument(skip(self, last_commit_info))]
-    async fn track_uptime(&mut self, last_commit_info: &LastCommitInfo) -> Result<()> {
+    async fn track_uptime(
+        &mut self,
+        last_commit_info: &LastCommitInfo,
+        proposer_address: &account::Id,
+    ) -> Result<()> {
         // Note: this probably isn't the correct height for the LastCommitInfo,
         // which is about the *last* commit, but at least it'll be consistent,
         // which is all we need to count signatures.
@@ -581,6 +790,21 @@ impl Staking {
             .map(|vote| (vote.validator.address, vote.signed_last_block))
             .collect::<BTreeMap<[u8; 20], bool>>();
 
+        // Validators whose keys were rotated in the last two blocks are still
+        // known to tendermint by their old keys, which they may have signed
+        // this commit, or proposed this block, with.
+        let mut previous_keys = BTreeMap::new();
+        for rotation_height in height.saturating_sub(2)..height {
+            for (identity_key, old_key) in self
+                .state
+                .previous_consensus_keys(rotation_height)
+                .await?
+                .rotations
+            {
+                previous_keys.insert(identity_key, old_key);
+            }
+        }
+
         // Since we don't have a lookup from "addresses" to identity keys,
         // iterate over our app's validators, and match them up with the vote data.
         for v in self.state.validator_list().await?.iter() {
@@ -591,13 +815,29 @@ impl Staking {
                 .ok_or_else(|| anyhow::anyhow!("validator missing info"))?;
 
             if info.status.state == validator::State::Active {
-                // for some reason last_commit_info has truncated sha256 hashes
-                let ck_bytes = info.validator.consensus_key.to_bytes();
-                let addr: [u8; 20] = Sha256::digest(&ck_bytes).as_slice()[0..20]
-                    .try_into()
-                    .unwrap();
+                let addrs = std::iter::once(&info.validator.consensus_key)
+                    .chain(previous_keys.get(v))
+                    .map(consensus_address)
+                    .collect::<Vec<_>>();
+
+                if addrs
+                    .iter()
+                    .any(|addr| addr.as_slice() == proposer_address.as_bytes())
+                {
+                    self.block_proposer = Some(v.clone());
+                }
+
+                let voted = addrs
+                    .iter()
+                    .any(|addr| did_address_vote.get(addr).cloned().unwrap_or(false));
+                let mut stats = self
+                    .state
+                    .validator_block_stats(v)
+                    .await?
+                    .unwrap_or_default();
+                stats.record_signature(voted);
+                self.state.set_validator_block_stats(v, stats).await;
 
-                let voted = did_address_vote.get(&addr).cloned().unwrap_or(false);
                 let mut uptime = self
                     .state
                     .validator_uptime(v)
@@ -626,11 +866,38 @@ impl Staking {
         Ok(())
     }
 
+    /// Records this block in its proposer's block production statistics, and
+    /// remembers its time, to measure the next block's latency from.
+    async fn record_block_proposal(&mut self) -> Result<()> {
+        let timestamp = self.state.get_block_timestamp().await?;
+        let latency = self
+            .state
+            .previous_block_timestamp()
+            .await?
+            .and_then(|previous| timestamp.duration_since(previous).ok());
+        let transactions = std::mem::take(&mut self.block_transactions);
+        let bytes = std::mem::take(&mut self.block_bytes);
+
+        if let Some(proposer) = self.block_proposer.take() {
+            let mut stats = self
+                .state
+                .validator_block_stats(&proposer)
+                .await?
+                .unwrap_or_default();
+            stats.record_proposal(transactions, bytes, latency);
+            tracing::debug!(?proposer, ?latency, "recorded block proposal");
+            self.state.set_validator_block_stats(&proposer, stats).await;
+        }
+
+        self.state.put_previous_block_timestamp(timestamp).await;
+        Ok(())
+    }
+
     /// Add a validator during genesis, which will start in Active
     /// state with power assigned.
     async fn add_genesis_validator(
         &mut self,
-        genesis_allocations: &HashMap<&String, u64>,
+        genesis_allocations: &HashMap<&String, Amount>,
         genesis_base_rate: &BaseRateData,
         validator: Validator,
     ) -> Result<()> {
@@ -657,8 +924,7 @@ impl Staking {
             .to_string();
         let total_delegation_tokens = genesis_allocations
             .get(&delegation_denom)
-            .copied()
-            .unwrap_or(0);
+            .map_or(0, Amount::value);
         let power = cur_rate_data.voting_power(total_delegation_tokens, genesis_base_rate);
 
         // Update the validator to return its power to Tendermint for this block.
@@ -718,9 +984,48 @@ impl Staking {
 
     // Used for updating an existing validator's definition.
     #[tracing::instrument(skip(self, validator), fields(id = ?validator.identity_key))]
-    async fn update_validator(&mut self, validator: Validator) -> Result<()> {
+    async fn update_validator(&mut self, mut validator: Validator) -> Result<()> {
         tracing::debug!(?validator);
-        let id = &validator.identity_key;
+        let id = &validator.identity_key.clone();
+
+        // A new consensus key only takes effect at the end of the epoch, so
+        // that the tendermint validator set changes at the same time as voting
+        // power. Until then, the validator keeps signing with its current key.
+        let current = self
+            .state
+            .validator(id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("updated validator not found in JMT"))?;
+        let epoch_index = self.state.get_current_epoch().await?.index;
+        let mut rotations = self.state.consensus_key_rotations(epoch_index).await?;
+        if validator.consensus_key == current.consensus_key {
+            // This also cancels any rotation queued earlier in the epoch.
+            rotations.remove(id);
+        } else {
+            tracing::debug!(consensus_key = ?validator.consensus_key, "queuing consensus key rotation");
+            rotations.insert(id.clone(), validator.consensus_key);
+            validator.consensus_key = current.consensus_key;
+        }
+        self.state
+            .set_consensus_key_rotations(epoch_index, rotations)
+            .await;
+
+        // Remember the commission the validator started the epoch with, which bounds how much
+        // later updates in the epoch can change it.
+        if self
+            .state
+            .epoch_start_commission(epoch_index, id)
+            .await?
+            .is_none()
+        {
+            self.state
+                .set_epoch_start_commission(
+                    epoch_index,
+                    id,
+                    current.funding_streams.total_rate_bps(),
+                )
+                .await;
+        }
 
         // Get the current state, so we can determine whether this update
         // triggers a state transition.
@@ -754,12 +1059,45 @@ impl Staking {
         }
 
         self.state
-            .put_domain(format!("staking/validators/{}", id).into(), validator)
+            .put_domain(super::state_key::validator_definition(id), validator)
             .await;
 
         Ok(())
     }
 
+    /// Check that `consensus_key` isn't used, or about to be used, by a validator other than
+    /// `identity_key`.
+    ///
+    /// Keys a validator has rotated away from still count as used by it, since evidence signed
+    /// with them is still attributed to it.
+    async fn check_consensus_key_unused(
+        &self,
+        identity_key: &IdentityKey,
+        consensus_key: &PublicKey,
+    ) -> Result<()> {
+        if let Some(other) = self.state.validator_by_consensus_key(consensus_key).await? {
+            if &other.identity_key != identity_key {
+                return Err(anyhow::anyhow!(
+                    "consensus key is already used by validator {}",
+                    other.identity_key
+                ));
+            }
+        }
+        let epoch_index = self.state.get_current_epoch().await?.index;
+        let rotations = self.state.consensus_key_rotations(epoch_index).await?;
+        if let Some((other, _)) = rotations
+            .rotations
+            .iter()
+            .find(|(id, key)| key == consensus_key && id != identity_key)
+        {
+            return Err(anyhow::anyhow!(
+                "consensus key is about to be used by validator {}",
+                other
+            ));
+        }
+        Ok(())
+    }
+
     async fn process_evidence(&mut self, evidence: &Evidence) -> Result<()> {
         let ck = tendermint::PublicKey::from_raw_ed25519(&evidence.validator.address)
             .ok_or_else(|| anyhow::anyhow!("invalid ed25519 consensus pubkey from tendermint"))
@@ -808,7 +1146,12 @@ impl Component for Staking {
         // to compute the delegation tokens for each validator.
         let mut genesis_allocations = HashMap::new();
         for allocation in &app_state.allocations {
-            *genesis_allocations.entry(&allocation.denom).or_insert(0) += allocation.amount;
+            let total = genesis_allocations
+                .entry(&allocation.denom)
+                .or_insert_with(Amount::zero);
+            *total = total
+                .checked_add(allocation.amount.into())
+                .expect("total genesis allocation of a denom must not overflow");
         }
 
         // Add initial validators to the JMT
@@ -838,9 +1181,12 @@ impl Component for Staking {
             self.process_evidence(evidence).await.unwrap();
         }
 
-        self.track_uptime(&begin_block.last_commit_info)
-            .await
-            .unwrap();
+        self.track_uptime(
+            &begin_block.last_commit_info,
+            &begin_block.header.proposer_address,
+        )
+        .await
+        .unwrap();
     }
 
     #[instrument(name = "staking", skip(_ctx, tx))]
@@ -884,12 +1230,7 @@ impl Component for Staking {
 
             // TODO(hdevalence) -- is this duplicated by the check during parsing?
             // Check that the funding streams do not exceed 100% commission (10000bps)
-            let total_funding_bps = definition
-                .validator
-                .funding_streams
-                .iter()
-                .map(|fs| fs.rate_bps as u64)
-                .sum::<u64>();
+            let total_funding_bps = definition.validator.funding_streams.total_rate_bps();
 
             if total_funding_bps > 10000 {
                 return Err(anyhow::anyhow!(
@@ -1032,6 +1373,27 @@ impl Component for Staking {
                 .context("supplied proto is not a valid definition")?;
             let existing_v = self.state.validator(&v.validator.identity_key).await?;
 
+            self.check_consensus_key_unused(&v.validator.identity_key, &v.validator.consensus_key)
+                .await?;
+            let epoch_index = self.state.get_current_epoch().await?.index;
+
+            // Check that the commission is within the chain's bounds, and, for an existing
+            // validator, hasn't changed by too much since the start of the epoch.
+            let epoch_start_commission = match &existing_v {
+                Some(existing_v) => Some(
+                    self.state
+                        .epoch_start_commission(epoch_index, &v.validator.identity_key)
+                        .await?
+                        .unwrap_or_else(|| existing_v.funding_streams.total_rate_bps()),
+                ),
+                None => None,
+            };
+            super::bounds::check_commission(
+                &self.state.get_chain_params().await?,
+                epoch_start_commission,
+                v.validator.funding_streams.total_rate_bps(),
+            )?;
+
             if let Some(existing_v) = existing_v {
                 // This is an existing validator definition. Ensure that the highest
                 // existing sequence number is less than the new sequence number.
@@ -1055,6 +1417,10 @@ impl Component for Staking {
 
     #[instrument(name = "staking", skip(self, _ctx, tx))]
     async fn execute_tx(&mut self, _ctx: Context, tx: &Transaction) {
+        // Count the transaction towards the size of the block, for its proposer's statistics.
+        self.block_transactions += 1;
+        self.block_bytes += tx.encode_to_vec().len() as u64;
+
         // Queue any (un)delegations for processing at the next epoch boundary.
         for action in &tx.transaction_body.actions {
             match action {
@@ -1131,6 +1497,8 @@ impl Component for Staking {
         if cur_epoch.is_epoch_end(cur_height) {
             self.end_epoch(cur_epoch).await.unwrap();
         }
+
+        self.record_block_proposal().await.unwrap();
     }
 }
 
@@ -1160,12 +1528,12 @@ pub trait View: StateExt {
     }
 
     async fn current_validator_rate(&self, identity_key: &IdentityKey) -> Result<Option<RateData>> {
-        self.get_domain(format!("staking/validators/{}/rate/current", identity_key).into())
+        self.get_domain(super::state_key::current_rate(identity_key))
             .await
     }
 
     async fn next_validator_rate(&self, identity_key: &IdentityKey) -> Result<Option<RateData>> {
-        self.get_domain(format!("staking/validators/{}/rate/next", identity_key).into())
+        self.get_domain(super::state_key::next_rate(identity_key))
             .await
     }
 
@@ -1181,7 +1549,7 @@ pub trait View: StateExt {
         }
 
         self.put_proto(
-            format!("staking/validators/{}/power", identity_key).into(),
+            super::state_key::validator_power(identity_key),
             voting_power,
         )
         .await;
@@ -1191,7 +1559,7 @@ pub trait View: StateExt {
 
     #[instrument(skip(self))]
     async fn validator_power(&self, identity_key: &IdentityKey) -> Result<Option<u64>> {
-        self.get_proto(format!("staking/validators/{}/power", identity_key).into())
+        self.get_proto(super::state_key::validator_power(identity_key))
             .await
     }
 
@@ -1203,20 +1571,14 @@ pub trait View: StateExt {
         next_rates: RateData,
     ) {
         tracing::debug!("setting validator rates");
-        self.put_domain(
-            format!("staking/validators/{}/rate/current", identity_key).into(),
-            current_rates,
-        )
-        .await;
-        self.put_domain(
-            format!("staking/validators/{}/rate/next", identity_key).into(),
-            next_rates,
-        )
-        .await;
+        self.put_domain(super::state_key::current_rate(identity_key), current_rates)
+            .await;
+        self.put_domain(super::state_key::next_rate(identity_key), next_rates)
+            .await;
     }
 
     async fn validator(&self, identity_key: &IdentityKey) -> Result<Option<Validator>> {
-        self.get_domain(format!("staking/validators/{}", identity_key).into())
+        self.get_domain(super::state_key::validator_definition(identity_key))
             .await
     }
 
@@ -1226,7 +1588,7 @@ pub trait View: StateExt {
         // We maintain an internal mapping of consensus keys to identity keys to make this
         // lookup more efficient.
         let identity_key: Option<IdentityKey> = self
-            .get_domain(format!("staking/consensus_key/{}", ck.to_hex()).into())
+            .get_domain(super::state_key::validator_by_consensus_key(ck))
             .await?;
 
         if identity_key.is_none() {
@@ -1238,6 +1600,73 @@ pub trait View: StateExt {
         self.validator(&identity_key).await
     }
 
+    async fn register_consensus_key(&self, ck: &PublicKey, identity_key: &IdentityKey) {
+        self.put_domain(
+            super::state_key::validator_by_consensus_key(ck),
+            identity_key.clone(),
+        )
+        .await
+    }
+
+    async fn consensus_key_rotations(&self, epoch_index: u64) -> Result<ConsensusKeyRotations> {
+        Ok(self
+            .get_domain(super::state_key::consensus_key_rotations(epoch_index))
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn set_consensus_key_rotations(
+        &self,
+        epoch_index: u64,
+        rotations: ConsensusKeyRotations,
+    ) {
+        self.put_domain(
+            super::state_key::consensus_key_rotations(epoch_index),
+            rotations,
+        )
+        .await
+    }
+
+    /// The consensus keys that validators rotated away from at the end of the block at `height`.
+    async fn previous_consensus_keys(&self, height: u64) -> Result<ConsensusKeyRotations> {
+        Ok(self
+            .get_domain(super::state_key::previous_consensus_keys(height))
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn set_previous_consensus_keys(&self, height: u64, keys: ConsensusKeyRotations) {
+        self.put_domain(super::state_key::previous_consensus_keys(height), keys)
+            .await
+    }
+
+    /// The total commission of the validator with `identity_key` at the start of the epoch, if
+    /// it has since been updated.
+    async fn epoch_start_commission(
+        &self,
+        epoch_index: u64,
+        identity_key: &IdentityKey,
+    ) -> Result<Option<u64>> {
+        self.get_proto(super::state_key::epoch_start_commission(
+            epoch_index,
+            identity_key,
+        ))
+        .await
+    }
+
+    async fn set_epoch_start_commission(
+        &self,
+        epoch_index: u64,
+        identity_key: &IdentityKey,
+        commission_bps: u64,
+    ) {
+        self.put_proto(
+            super::state_key::epoch_start_commission(epoch_index, identity_key),
+            commission_bps,
+        )
+        .await
+    }
+
     async fn apply_slashing_penalty(
         &self,
         identity_key: &IdentityKey,
@@ -1293,7 +1722,9 @@ pub trait View: StateExt {
         tracing::debug!(?validator);
         let id = validator.identity_key.clone();
 
-        self.put_domain(format!("staking/validators/{}", id).into(), validator)
+        self.register_consensus_key(&validator.consensus_key, &id)
+            .await;
+        self.put_domain(super::state_key::validator_definition(&id), validator)
             .await;
         self.register_denom(&DelegationToken::from(&id).denom())
             .await?;
@@ -1303,7 +1734,7 @@ pub trait View: StateExt {
 
         // We can't call `set_validator_state` here because it requires an existing validator state,
         // so we manually initialize the state for new validators.
-        self.put_domain(format!("staking/validators/{}/state", &id).into(), state)
+        self.put_domain(super::state_key::validator_state(&id), state)
             .await;
         self.set_validator_power(&id, power).await?;
         self.set_validator_bonding_state(&id, bonding_state).await;
@@ -1346,7 +1777,7 @@ pub trait View: StateExt {
         &self,
         identity_key: &IdentityKey,
     ) -> Result<Option<validator::State>> {
-        self.get_domain(format!("staking/validators/{}/state", identity_key).into())
+        self.get_domain(super::state_key::validator_state(identity_key))
             .await
     }
 
@@ -1354,7 +1785,7 @@ pub trait View: StateExt {
         &self,
         identity_key: &IdentityKey,
     ) -> Result<Option<validator::BondingState>> {
-        self.get_domain(format!("staking/validators/{}/bonding_state", identity_key).into())
+        self.get_domain(super::state_key::validator_bonding_state(identity_key))
             .await
     }
 
@@ -1422,6 +1853,35 @@ pub trait View: StateExt {
         .await
     }
 
+    async fn validator_block_stats(
+        &self,
+        identity_key: &IdentityKey,
+    ) -> Result<Option<BlockStats>> {
+        self.get_domain(super::state_key::validator_block_stats(identity_key))
+            .await
+    }
+
+    async fn set_validator_block_stats(&self, identity_key: &IdentityKey, stats: BlockStats) {
+        self.put_domain(super::state_key::validator_block_stats(identity_key), stats)
+            .await
+    }
+
+    /// The time of the previous block, which the latency of this block is measured from.
+    async fn previous_block_timestamp(&self) -> Result<Option<Time>> {
+        let timestamp: Option<String> = self
+            .get_proto(super::state_key::previous_block_timestamp())
+            .await?;
+        Ok(timestamp.map(|timestamp| timestamp.parse()).transpose()?)
+    }
+
+    async fn put_previous_block_timestamp(&self, timestamp: Time) {
+        self.put_proto(
+            super::state_key::previous_block_timestamp(),
+            timestamp.to_rfc3339(),
+        )
+        .await
+    }
+
     async fn set_validator_bonding_state(
         &self,
         identity_key: &IdentityKey,
@@ -1429,7 +1889,7 @@ pub trait View: StateExt {
     ) {
         tracing::debug!(?state, "set bonding state");
         self.put_domain(
-            format!("staking/validators/{}/bonding_state", identity_key).into(),
+            super::state_key::validator_bonding_state(identity_key),
             state,
         )
         .await
@@ -1452,3 +1912,263 @@ pub trait View: StateExt {
 }
 
 impl<T: StateExt + Send + Sync> View for T {}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_chain::params::ChainParams;
+    use penumbra_crypto::{
+        keys::{SeedPhrase, SpendKey},
+        rdsa::{SigningKey, SpendAuth},
+    };
+    use penumbra_storage::Storage;
+    use rand_core::OsRng;
+    use tempfile::tempdir;
+    use tendermint::abci::types::VoteInfo;
+
+    use super::*;
+    use crate::stake::FundingStreams;
+
+    const EPOCH_DURATION: u64 = 10;
+
+    fn consensus_key() -> PublicKey {
+        tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(OsRng)).public_key()
+    }
+
+    fn validator(consensus_key: PublicKey) -> Validator {
+        Validator {
+            identity_key: IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into()),
+            consensus_key,
+            name: "test validator".to_string(),
+            website: String::new(),
+            description: String::new(),
+            enabled: true,
+            funding_streams: FundingStreams::default(),
+            sequence_number: 0,
+        }
+    }
+
+    /// A commit signed only by the validator with `address`.
+    fn signed_by(address: [u8; 20]) -> LastCommitInfo {
+        LastCommitInfo {
+            round: Default::default(),
+            votes: vec![VoteInfo {
+                validator: tendermint::abci::types::Validator {
+                    address,
+                    power: 1u32.into(),
+                },
+                signed_last_block: true,
+            }],
+        }
+    }
+
+    /// Start a chain whose only validator is `genesis_validator`, self-delegated so that it has
+    /// voting power.
+    async fn start_chain(storage: &Storage, genesis_validator: &Validator) -> Staking {
+        let state = storage.state().await.unwrap();
+        state
+            .put_chain_params(ChainParams {
+                chain_id: "test".to_string(),
+                epoch_duration: EPOCH_DURATION,
+                ..Default::default()
+            })
+            .await;
+        state.put_block_height(0).await;
+
+        let (address, _dtk_d) = SpendKey::from_seed_phrase(SeedPhrase::generate(OsRng), 0)
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+        let app_state = genesis::AppState {
+            allocations: vec![genesis::Allocation {
+                amount: 1_000_000,
+                denom: DelegationToken::from(&genesis_validator.identity_key)
+                    .denom()
+                    .to_string(),
+                address,
+            }],
+            chain_params: state.get_chain_params().await.unwrap(),
+            validators: vec![genesis_validator.clone().into()],
+        };
+
+        let mut staking = Staking::new(state).await;
+        staking.init_chain(&app_state).await;
+        // Start from a fresh block, without genesis's validator set updates.
+        Staking::new(staking.state).await
+    }
+
+    #[tokio::test]
+    async fn consensus_key_rotation_waits_for_the_epoch_boundary() {
+        let dir = tempdir().unwrap();
+        let storage = Storage::load(dir.path().join("rotation.db")).await.unwrap();
+        let old_key = consensus_key();
+        let genesis_validator = validator(old_key);
+        let identity_key = genesis_validator.identity_key.clone();
+        let mut staking = start_chain(&storage, &genesis_validator).await;
+
+        // The rotation is queued, but the validator keeps signing with its current key.
+        let new_key = consensus_key();
+        staking
+            .update_validator(Validator {
+                consensus_key: new_key,
+                sequence_number: 1,
+                ..genesis_validator.clone()
+            })
+            .await
+            .unwrap();
+        let validator = staking
+            .state
+            .validator(&identity_key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(validator.consensus_key, old_key);
+        assert_eq!(validator.sequence_number, 1);
+        let rotations = staking.state.consensus_key_rotations(0).await.unwrap();
+        assert_eq!(rotations.rotations, vec![(identity_key.clone(), new_key)]);
+        assert!(staking.tm_validator_updates().await.unwrap().is_empty());
+
+        // At the epoch boundary, tendermint is told to swap the old key for the new one, at the
+        // validator's current power.
+        let power = staking
+            .state
+            .validator_power(&identity_key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(power > 0);
+        staking
+            .rotate_consensus_keys(&Epoch::from_height(0, EPOCH_DURATION))
+            .await
+            .unwrap();
+        let validator = staking
+            .state
+            .validator(&identity_key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(validator.consensus_key, new_key);
+        let updates = staking
+            .tm_validator_updates()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|update| (update.pub_key, update.power.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(updates, vec![(old_key, 0), (new_key, power)]);
+    }
+
+    #[tokio::test]
+    async fn rotated_validators_sign_with_their_old_keys_until_the_update_applies() {
+        let dir = tempdir().unwrap();
+        let storage = Storage::load(dir.path().join("rotation.db")).await.unwrap();
+        let old_key = consensus_key();
+        let genesis_validator = validator(old_key);
+        let identity_key = genesis_validator.identity_key.clone();
+        let mut staking = start_chain(&storage, &genesis_validator).await;
+
+        let new_key = consensus_key();
+        staking
+            .update_validator(Validator {
+                consensus_key: new_key,
+                sequence_number: 1,
+                ..genesis_validator.clone()
+            })
+            .await
+            .unwrap();
+        let epoch = Epoch::from_height(0, EPOCH_DURATION);
+        let rotation_height = epoch.end_height().value();
+        staking.state.put_block_height(rotation_height).await;
+        staking.rotate_consensus_keys(&epoch).await.unwrap();
+
+        // Tendermint applies the rotation two blocks after it's returned from `EndBlock`: until
+        // then, the validator signs and proposes with its old key, and after, with its new one.
+        for (height, signing_key) in [
+            (rotation_height + 1, old_key),
+            (rotation_height + 2, old_key),
+            (rotation_height + 3, new_key),
+        ] {
+            let mut staking = Staking::new(staking.state.clone()).await;
+            staking.state.put_block_height(height).await;
+            let address = consensus_address(&signing_key);
+            staking
+                .track_uptime(&signed_by(address), &account::Id::new(address))
+                .await
+                .unwrap();
+            assert_eq!(staking.block_proposer, Some(identity_key.clone()));
+        }
+        let uptime = staking
+            .state
+            .validator_uptime(&identity_key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(uptime.num_missed_blocks(), 0);
+
+        // Once the rotation has been applied, votes under the old key are no longer the
+        // validator's.
+        let mut staking = Staking::new(staking.state.clone()).await;
+        staking.state.put_block_height(rotation_height + 4).await;
+        let address = consensus_address(&old_key);
+        staking
+            .track_uptime(&signed_by(address), &account::Id::new(address))
+            .await
+            .unwrap();
+        assert_eq!(staking.block_proposer, None);
+        let uptime = staking
+            .state
+            .validator_uptime(&identity_key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(uptime.num_missed_blocks(), 1);
+    }
+
+    #[tokio::test]
+    async fn consensus_keys_cannot_be_reused_by_another_validator() {
+        let dir = tempdir().unwrap();
+        let storage = Storage::load(dir.path().join("rotation.db")).await.unwrap();
+        let old_key = consensus_key();
+        let genesis_validator = validator(old_key);
+        let mut staking = start_chain(&storage, &genesis_validator).await;
+
+        let new_key = consensus_key();
+        staking
+            .update_validator(Validator {
+                consensus_key: new_key,
+                sequence_number: 1,
+                ..genesis_validator.clone()
+            })
+            .await
+            .unwrap();
+
+        // Neither the validator's current key nor the one it's about to rotate to can be taken
+        // by another validator, though the validator itself can keep using them.
+        let other = validator(consensus_key()).identity_key;
+        for key in [old_key, new_key] {
+            assert!(staking
+                .check_consensus_key_unused(&other, &key)
+                .await
+                .is_err());
+            staking
+                .check_consensus_key_unused(&genesis_validator.identity_key, &key)
+                .await
+                .unwrap();
+        }
+
+        // Once the rotation is applied, the key rotated away from still can't be reused.
+        staking
+            .rotate_consensus_keys(&Epoch::from_height(0, EPOCH_DURATION))
+            .await
+            .unwrap();
+        for key in [old_key, new_key] {
+            assert!(staking
+                .check_consensus_key_unused(&other, &key)
+                .await
+                .is_err());
+        }
+        staking
+            .check_consensus_key_unused(&other, &consensus_key())
+            .await
+            .unwrap();
+    }
+}
@@ -89,6 +89,15 @@ impl FundingStreams {
     pub fn iter(&self) -> impl Iterator<Item = &FundingStream> {
         self.funding_streams.iter()
     }
+
+    /// The validator's total commission: the sum of the rates of the funding streams, in basis
+    /// points.
+    pub fn total_rate_bps(&self) -> u64 {
+        self.funding_streams
+            .iter()
+            .map(|fs| fs.rate_bps as u64)
+            .sum()
+    }
 }
 
 impl TryFrom<Vec<FundingStream>> for FundingStreams {
@@ -0,0 +1,177 @@
+//! A consistency check on the staking token supply and delegation pools, run at epoch boundaries.
+
+use std::collections::BTreeMap;
+
+use penumbra_crypto::IdentityKey;
+
+use crate::stake::rate::RateData;
+
+/// The staking token held directly and in each validator's delegation pool, as of the end of an
+/// epoch.
+///
+/// Between two epoch boundaries, the total value (the staking token supply, plus the unbonded
+/// value of every delegation pool) should only change by:
+///
+/// - the commission minted at the end of the earlier epoch;
+/// - the change in value of the earlier delegation pools, as their exchange rates grow with
+///   rewards or shrink with slashing.
+///
+/// Delegations and undelegations only convert between staking and delegation tokens, so they
+/// don't change the total, except by rounding. Comparing consecutive snapshots catches bugs in
+/// supply accounting or rate computation before they can compound.
+#[derive(Debug, Clone, Default)]
+pub struct PoolSnapshot {
+    /// The staking token supply, after applying the epoch's delegation changes.
+    pub staking_supply: u64,
+    /// The commission minted at the end of the epoch, which isn't yet in the staking supply.
+    pub commission: u64,
+    pub pools: BTreeMap<IdentityKey, Pool>,
+}
+
+/// A validator's delegation pool at the end of an epoch.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    /// The supply of the validator's delegation token.
+    pub delegation_supply: u64,
+    /// The rate for the epoch beginning at the snapshot.
+    pub rate: RateData,
+    /// The number of delegations and undelegations applied to the pool at the end of the epoch,
+    /// each of which can be off by rounding.
+    pub changes: usize,
+}
+
+/// The result of comparing two consecutive [`PoolSnapshot`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Discrepancy {
+    /// The actual total value, less the expected total value.
+    pub amount: i128,
+    /// The largest discrepancy which can be explained by rounding.
+    pub tolerance: u128,
+}
+
+impl Discrepancy {
+    pub fn is_violation(&self) -> bool {
+        self.amount.unsigned_abs() > self.tolerance
+    }
+}
+
+impl PoolSnapshot {
+    /// The unbonded value of the delegation pools.
+    pub fn pool_value(&self) -> u128 {
+        self.pools
+            .values()
+            .map(|pool| pool.rate.unbonded_amount(pool.delegation_supply) as u128)
+            .sum()
+    }
+
+    /// The staking token supply plus the unbonded value of the delegation pools.
+    pub fn total_value(&self) -> u128 {
+        self.staking_supply as u128 + self.pool_value()
+    }
+
+    /// Compare this snapshot's total value to the value expected from the previous epoch's
+    /// snapshot.
+    pub fn discrepancy_since(&self, previous: &PoolSnapshot) -> Discrepancy {
+        // The change in value of the previous pools, from the previous epoch's rate to this one.
+        let rate_changes: i128 = self
+            .pools
+            .iter()
+            .filter_map(|(identity_key, pool)| {
+                let previous = previous.pools.get(identity_key)?;
+                let before = previous.rate.unbonded_amount(previous.delegation_supply);
+                let after = pool.rate.unbonded_amount(previous.delegation_supply);
+                Some(after as i128 - before as i128)
+            })
+            .sum();
+
+        let expected = previous.total_value() as i128 + previous.commission as i128 + rate_changes;
+
+        // Each conversion between staking and delegation tokens rounds down by less than the
+        // value of one delegation token, and so does computing the value of each pool, before and
+        // after.
+        let tolerance = self
+            .pools
+            .values()
+            .map(|pool| {
+                let token_value = pool.rate.validator_exchange_rate as u128 / 1_0000_0000 + 1;
+                (pool.changes as u128 + 3) * token_value
+            })
+            .sum();
+
+        Discrepancy {
+            amount: self.total_value() as i128 - expected,
+            tolerance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::{SigningKey, SpendAuth};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn identity_key() -> IdentityKey {
+        IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into())
+    }
+
+    fn rate(
+        identity_key: &IdentityKey,
+        epoch_index: u64,
+        validator_exchange_rate: u64,
+    ) -> RateData {
+        RateData {
+            identity_key: identity_key.clone(),
+            epoch_index,
+            validator_reward_rate: 0,
+            validator_exchange_rate,
+        }
+    }
+
+    #[test]
+    fn rewards_and_commission_are_expected() {
+        let ik = identity_key();
+        let previous = PoolSnapshot {
+            staking_supply: 1_000_000,
+            commission: 500,
+            pools: [(
+                ik.clone(),
+                Pool {
+                    delegation_supply: 2_000_000,
+                    rate: rate(&ik, 1, 1_0000_0000),
+                    changes: 0,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        };
+
+        // The pool's rate grows by 1%, and someone delegates 10_100 staking tokens at the new rate.
+        let current = PoolSnapshot {
+            staking_supply: 1_000_000 + 500 - 10_100,
+            commission: 0,
+            pools: [(
+                ik.clone(),
+                Pool {
+                    delegation_supply: 2_000_000 + 10_000,
+                    rate: rate(&ik, 2, 1_0100_0000),
+                    changes: 1,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        };
+        let discrepancy = current.discrepancy_since(&previous);
+        assert_eq!(discrepancy.amount, 0);
+        assert!(!discrepancy.is_violation());
+
+        // If the delegation tokens had been minted without debiting the staking supply, the
+        // check should fail.
+        let inflated = PoolSnapshot {
+            staking_supply: 1_000_000 + 500,
+            ..current
+        };
+        assert!(inflated.discrepancy_since(&previous).is_violation());
+    }
+}
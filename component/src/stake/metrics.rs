@@ -45,6 +45,18 @@ pub fn register_metrics() {
         Unit::Count,
         "The number of disabled validators"
     );
+    register_gauge!(DELEGATION_POOL_VALUE);
+    describe_gauge!(
+        DELEGATION_POOL_VALUE,
+        Unit::Count,
+        "The unbonded value of all delegation pools, in the staking token"
+    );
+    register_gauge!(DELEGATION_POOL_DISCREPANCY);
+    describe_gauge!(
+        DELEGATION_POOL_DISCREPANCY,
+        Unit::Count,
+        "The difference between the actual and expected total staking token value at the last epoch boundary"
+    );
     register_gauge!(TOMBSTONED_VALIDATORS);
     describe_gauge!(
         TOMBSTONED_VALIDATORS,
@@ -59,3 +71,5 @@ pub const DISABLED_VALIDATORS: &str = "penumbra_stake_validators_disabled";
 pub const INACTIVE_VALIDATORS: &str = "penumbra_stake_validators_inactive";
 pub const JAILED_VALIDATORS: &str = "penumbra_stake_validators_jailed";
 pub const TOMBSTONED_VALIDATORS: &str = "penumbra_stake_validators_tombstoned";
+pub const DELEGATION_POOL_VALUE: &str = "penumbra_stake_delegation_pool_value";
+pub const DELEGATION_POOL_DISCREPANCY: &str = "penumbra_stake_delegation_pool_discrepancy";
@@ -1,9 +1,13 @@
 #![allow(clippy::clone_on_copy)]
 use penumbra_crypto::IdentityKey;
 
+mod block_stats;
+mod bounds;
 mod changes;
 mod funding_stream;
+mod invariant;
 mod metrics;
+mod rotation;
 mod uptime;
 
 pub mod component;
@@ -12,7 +16,10 @@ pub mod state_key;
 pub mod validator;
 
 pub use self::metrics::register_metrics;
+pub use block_stats::BlockStats;
+pub use bounds::BoundsViolation;
 pub use changes::DelegationChanges;
 pub use component::View;
 pub use funding_stream::{FundingStream, FundingStreams};
+pub use rotation::ConsensusKeyRotations;
 pub use uptime::Uptime;
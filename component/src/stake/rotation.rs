@@ -0,0 +1,105 @@
+use anyhow::Result;
+use penumbra_crypto::IdentityKey;
+use penumbra_proto::{stake as pb, Protobuf};
+use serde::{Deserialize, Serialize};
+
+/// Data structure used to track validators' consensus key changes that have been committed to the
+/// chain but not yet applied at the epoch boundary.
+///
+/// Each validator has at most one pending rotation: a later rotation replaces an earlier one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(
+    try_from = "pb::ConsensusKeyRotations",
+    into = "pb::ConsensusKeyRotations"
+)]
+pub struct ConsensusKeyRotations {
+    pub rotations: Vec<(IdentityKey, tendermint::PublicKey)>,
+}
+
+impl ConsensusKeyRotations {
+    /// Queue a rotation of `identity_key`'s consensus key to `consensus_key`, replacing any
+    /// rotation already queued for it.
+    pub fn insert(&mut self, identity_key: IdentityKey, consensus_key: tendermint::PublicKey) {
+        self.remove(&identity_key);
+        self.rotations.push((identity_key, consensus_key));
+    }
+
+    /// Cancel any rotation queued for `identity_key`.
+    pub fn remove(&mut self, identity_key: &IdentityKey) {
+        self.rotations.retain(|(id, _)| id != identity_key);
+    }
+}
+
+impl Protobuf<pb::ConsensusKeyRotations> for ConsensusKeyRotations {}
+
+impl From<ConsensusKeyRotations> for pb::ConsensusKeyRotations {
+    fn from(rotations: ConsensusKeyRotations) -> pb::ConsensusKeyRotations {
+        pb::ConsensusKeyRotations {
+            rotations: rotations
+                .rotations
+                .into_iter()
+                .map(|(identity_key, consensus_key)| pb::ConsensusKeyRotation {
+                    identity_key: Some(identity_key.into()),
+                    consensus_key: consensus_key.to_bytes(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<pb::ConsensusKeyRotations> for ConsensusKeyRotations {
+    type Error = anyhow::Error;
+    fn try_from(rotations: pb::ConsensusKeyRotations) -> Result<ConsensusKeyRotations> {
+        Ok(ConsensusKeyRotations {
+            rotations: rotations
+                .rotations
+                .into_iter()
+                .map(|rotation| {
+                    Ok((
+                        rotation
+                            .identity_key
+                            .ok_or_else(|| anyhow::anyhow!("missing identity key"))?
+                            .try_into()?,
+                        tendermint::PublicKey::from_raw_ed25519(&rotation.consensus_key)
+                            .ok_or_else(|| anyhow::anyhow!("invalid ed25519 consensus pubkey"))?,
+                    ))
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::{SigningKey, SpendAuth};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn identity_key() -> IdentityKey {
+        IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into())
+    }
+
+    fn consensus_key() -> tendermint::PublicKey {
+        tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(OsRng)).public_key()
+    }
+
+    #[test]
+    fn later_rotations_replace_earlier_ones() {
+        let (a, b) = (identity_key(), identity_key());
+        let (k1, k2, k3) = (consensus_key(), consensus_key(), consensus_key());
+
+        let mut rotations = ConsensusKeyRotations::default();
+        rotations.insert(a.clone(), k1);
+        rotations.insert(b.clone(), k2);
+        rotations.insert(a.clone(), k3);
+        assert_eq!(rotations.rotations, vec![(b.clone(), k2), (a.clone(), k3)]);
+
+        rotations.remove(&b);
+        assert_eq!(rotations.rotations, vec![(a.clone(), k3)]);
+
+        let bytes = rotations.encode_to_vec();
+        let rotations2 = ConsensusKeyRotations::decode(bytes.as_slice()).unwrap();
+        assert_eq!(rotations.rotations, rotations2.rotations);
+    }
+}
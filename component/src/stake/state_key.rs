@@ -1,5 +1,58 @@
 use jmt::KeyHash;
+use penumbra_crypto::IdentityKey;
+
+pub fn validator_definition(identity_key: &IdentityKey) -> KeyHash {
+    format!("staking/validators/{}", identity_key).into()
+}
+
+pub fn validator_state(identity_key: &IdentityKey) -> KeyHash {
+    format!("staking/validators/{}/state", identity_key).into()
+}
+
+pub fn validator_bonding_state(identity_key: &IdentityKey) -> KeyHash {
+    format!("staking/validators/{}/bonding_state", identity_key).into()
+}
+
+pub fn validator_power(identity_key: &IdentityKey) -> KeyHash {
+    format!("staking/validators/{}/power", identity_key).into()
+}
+
+pub fn current_rate(identity_key: &IdentityKey) -> KeyHash {
+    format!("staking/validators/{}/rate/current", identity_key).into()
+}
+
+pub fn next_rate(identity_key: &IdentityKey) -> KeyHash {
+    format!("staking/validators/{}/rate/next", identity_key).into()
+}
 
 pub fn slashed_validators(height: u64) -> KeyHash {
     format!("staking/slashed_validators/{}", height).into()
 }
+
+pub fn consensus_key_rotations(epoch_index: u64) -> KeyHash {
+    format!("staking/consensus_key_rotations/{}", epoch_index).into()
+}
+
+pub fn previous_consensus_keys(height: u64) -> KeyHash {
+    format!("staking/previous_consensus_keys/{}", height).into()
+}
+
+pub fn epoch_start_commission(epoch_index: u64, identity_key: &IdentityKey) -> KeyHash {
+    format!(
+        "staking/epoch_start_commission/{}/{}",
+        epoch_index, identity_key
+    )
+    .into()
+}
+
+pub fn validator_by_consensus_key(consensus_key: &tendermint::PublicKey) -> KeyHash {
+    format!("staking/consensus_key/{}", consensus_key.to_hex()).into()
+}
+
+pub fn validator_block_stats(identity_key: &IdentityKey) -> KeyHash {
+    format!("staking/validator_block_stats/{}", identity_key).into()
+}
+
+pub fn previous_block_timestamp() -> KeyHash {
+    "staking/previous_block_timestamp".into()
+}
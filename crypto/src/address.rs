@@ -1,11 +1,13 @@
 use std::io::{Cursor, Read, Write};
 
+use ark_ff::PrimeField;
 use ark_serialize::CanonicalDeserialize;
+use decaf377::FieldExt;
 use f4jumble::{f4jumble, f4jumble_inv};
 use penumbra_proto::{crypto as pb, serializers::bech32str};
 use serde::{Deserialize, Serialize};
 
-use crate::{fmd, ka, keys::Diversifier, Fq};
+use crate::{fmd, ka, keys::Diversifier, Fq, Fr};
 
 // We pad addresses to 80 bytes (before jumbling and Bech32m encoding)
 // using this 5 byte padding.
@@ -72,6 +74,67 @@ impl Address {
     pub fn clue_key(&self) -> &fmd::ClueKey {
         &self.ck_d
     }
+
+    /// Check a proof, made with
+    /// [`IncomingViewingKey::prove_ownership`](crate::keys::IncomingViewingKey::prove_ownership),
+    /// that whoever made it holds the incoming viewing key of this address.
+    pub fn verify_ownership(&self, proof: &OwnershipProof) -> bool {
+        let pk_d = match decaf377::Encoding(self.pk_d.0).decompress() {
+            Ok(pk_d) => pk_d,
+            Err(_) => return false,
+        };
+        // This is the commitment to the prover's nonce iff `s = k + c * ivk`.
+        let commitment = proof.s * self.g_d - proof.c * pk_d;
+        ownership_challenge(self, &commitment) == proof.c
+    }
+}
+
+/// The length of an encoded [`OwnershipProof`].
+pub const OWNERSHIP_PROOF_LEN_BYTES: usize = 64;
+
+/// A proof that whoever made it holds the incoming viewing key of an [`Address`], so that the
+/// address can be trusted to be theirs.
+///
+/// This is a Schnorr proof of knowledge of `ivk` such that `pk_d = [ivk] g_d`, made
+/// non-interactive with a Fiat-Shamir challenge over the address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OwnershipProof {
+    pub(crate) c: Fr,
+    pub(crate) s: Fr,
+}
+
+impl OwnershipProof {
+    pub fn to_bytes(&self) -> [u8; OWNERSHIP_PROOF_LEN_BYTES] {
+        let mut bytes = [0u8; OWNERSHIP_PROOF_LEN_BYTES];
+        bytes[..32].copy_from_slice(&self.c.to_bytes());
+        bytes[32..].copy_from_slice(&self.s.to_bytes());
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for OwnershipProof {
+    type Error = anyhow::Error;
+
+    fn try_from(slice: &[u8]) -> Result<Self, Self::Error> {
+        let bytes: [u8; OWNERSHIP_PROOF_LEN_BYTES] = slice.try_into()?;
+        Ok(OwnershipProof {
+            c: Fr::from_bytes(bytes[..32].try_into().expect("slice is 32 bytes"))?,
+            s: Fr::from_bytes(bytes[32..].try_into().expect("slice is 32 bytes"))?,
+        })
+    }
+}
+
+/// The Fiat-Shamir challenge of an [`OwnershipProof`] of `address`, given the commitment to the
+/// prover's nonce.
+pub(crate) fn ownership_challenge(address: &Address, commitment: &decaf377::Element) -> Fr {
+    let hash = blake2b_simd::Params::default()
+        .personal(b"Penumbra_AddrOwn")
+        .to_state()
+        .update(&address.g_d.compress().0)
+        .update(&address.pk_d.0)
+        .update(&commitment.compress().0)
+        .finalize();
+    Fr::from_le_bytes_mod_order(hash.as_bytes())
 }
 
 impl From<Address> for pb::Address {
@@ -192,6 +255,41 @@ mod tests {
     use super::*;
     use crate::keys::{SeedPhrase, SpendKey};
 
+    #[test]
+    fn ownership_proofs_only_verify_for_the_owners_address() {
+        let mut rng = OsRng;
+        let ivk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut rng), 0)
+            .full_viewing_key()
+            .incoming()
+            .clone();
+        let (address, _dtk_d) = ivk.payment_address(7u64.into());
+        let (other_address, _dtk_d) = ivk.payment_address(8u64.into());
+
+        let proof = ivk.prove_ownership(&mut rng, &address).unwrap();
+        assert!(address.verify_ownership(&proof));
+        assert_eq!(
+            OwnershipProof::try_from(&proof.to_bytes()[..]).unwrap(),
+            proof
+        );
+
+        // The proof is bound to the address it was made for...
+        assert!(!other_address.verify_ownership(&proof));
+        // ...and can't be made for an address of another key.
+        let someone_else = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut rng), 0)
+            .full_viewing_key()
+            .incoming()
+            .payment_address(7u64.into())
+            .0;
+        assert!(ivk.prove_ownership(&mut rng, &someone_else).is_none());
+
+        // A tampered proof doesn't verify.
+        let tampered = OwnershipProof {
+            s: proof.s + Fr::from(1u64),
+            ..proof
+        };
+        assert!(!address.verify_ownership(&tampered));
+    }
+
     #[test]
     fn test_address_encoding() {
         let mut rng = OsRng;
@@ -0,0 +1,101 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::asset;
+
+/// An amount of some asset, in its base denomination.
+///
+/// Arithmetic on amounts is checked: rather than wrapping around on overflow, which would
+/// silently corrupt a balance, [`Amount::checked_add`] and [`Amount::checked_sub`] return
+/// `None`, and so does [`Amount::checked_sum`] if a total overflows. There's deliberately no
+/// [`Sum`](std::iter::Sum) implementation, since it could only panic on overflow.
+///
+/// Amounts are encoded as `uint64` fields in protobuf messages, so they convert to and from `u64`.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+impl Amount {
+    /// The zero amount.
+    pub const fn zero() -> Self {
+        Self(0)
+    }
+
+    /// Returns the amount as a number of base units.
+    pub const fn value(&self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// Sums the amounts, returning `None` if the total overflows.
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::zero(), Amount::checked_add)
+    }
+
+    /// Formats the amount in the given display unit of its denomination, e.g., `1.5` for
+    /// 1500000upenumbra in penumbra.
+    pub fn format(&self, unit: &asset::Unit) -> String {
+        unit.format_value(self.0)
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(amount: u64) -> Self {
+        Self(amount)
+    }
+}
+
+impl From<Amount> for u64 {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_is_checked() {
+        let max = Amount::from(u64::MAX);
+        assert_eq!(max.checked_add(Amount::from(1)), None);
+        assert_eq!(Amount::zero().checked_sub(Amount::from(1)), None);
+        assert_eq!(
+            Amount::from(3).checked_sub(Amount::from(1)),
+            Some(Amount::from(2))
+        );
+        assert_eq!(Amount::checked_sum([max, Amount::from(1)]), None);
+        assert_eq!(
+            Amount::checked_sum([Amount::from(1), Amount::from(2)]),
+            Some(Amount::from(3))
+        );
+    }
+
+    #[test]
+    fn format_uses_unit_exponent() {
+        let penumbra = asset::REGISTRY.parse_unit("penumbra");
+        assert_eq!(Amount::from(1_500_000).format(&penumbra), "1.5");
+        assert_eq!(
+            Amount::from(u64::MAX).format(&penumbra),
+            "18446744073709.551615"
+        );
+    }
+}
@@ -209,9 +209,11 @@ impl Unit {
     }
 
     pub fn format_value(&self, value: u64) -> String {
-        let power_of_ten = 10u64.pow(self.exponent().into());
-        let v1 = value / power_of_ten;
-        let v2 = value % power_of_ten;
+        // Work in `u128`, so that units with up to 38 decimal places can display any `u64` value
+        // (rather than overflowing when computing the power of ten).
+        let power_of_ten = 10u128.saturating_pow(self.exponent().into());
+        let v1 = value as u128 / power_of_ten;
+        let v2 = value as u128 % power_of_ten;
 
         // Pad `v2` to exponent digits.
         let v2_str = format!("{:0width$}", v2, width = self.exponent() as usize);
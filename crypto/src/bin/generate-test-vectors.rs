@@ -0,0 +1,7 @@
+//! Prints the test vectors in [`penumbra_crypto::test_vectors`] as JSON.
+
+fn main() -> anyhow::Result<()> {
+    let vectors = penumbra_crypto::test_vectors::generate();
+    println!("{}", serde_json::to_string_pretty(&vectors)?);
+    Ok(())
+}
@@ -10,6 +10,12 @@ pub use seed_phrase::SeedPhrase;
 mod spend;
 pub use spend::{SpendKey, SpendKeyBytes, SPENDKEY_LEN_BYTES};
 
+mod signing;
+pub use signing::{
+    BindingSigningKey, BindingVerificationKey, SpendAuthSigningKey, SpendAuthVerificationKey,
+    TAGGED_KEY_LEN_BYTES,
+};
+
 mod fvk;
 mod ivk;
 mod ovk;
@@ -1,4 +1,8 @@
-use std::convert::{TryFrom, TryInto};
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+    str::FromStr,
+};
 
 use aes::Aes256;
 use anyhow::anyhow;
@@ -104,7 +108,9 @@ impl DiversifierKey {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Derivative, Serialize, Deserialize)]
+#[derive(
+    Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Derivative, Serialize, Deserialize,
+)]
 #[serde(try_from = "pb::DiversifierIndex", into = "pb::DiversifierIndex")]
 #[derivative(Debug)]
 pub struct DiversifierIndex(
@@ -157,6 +163,45 @@ impl From<DiversifierIndex> for u128 {
     }
 }
 
+/// One more than the largest diversifier index, which is 11 bytes long.
+const DIVERSIFIER_INDEX_LIMIT: u128 = 1 << (8 * DIVERSIFIER_LEN_BYTES);
+
+impl TryFrom<u128> for DiversifierIndex {
+    type Error = anyhow::Error;
+
+    fn try_from(x: u128) -> Result<Self, Self::Error> {
+        if x >= DIVERSIFIER_INDEX_LIMIT {
+            return Err(anyhow!(
+                "diversifier index {} out of range, must be less than {}",
+                x,
+                DIVERSIFIER_INDEX_LIMIT
+            ));
+        }
+
+        let mut bytes = [0; 11];
+        bytes.copy_from_slice(&x.to_le_bytes()[0..11]);
+        Ok(Self(bytes))
+    }
+}
+
+/// Diversifier indices are displayed as decimal numbers, as users type them.
+impl fmt::Display for DiversifierIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        u128::from(*self).fmt(f)
+    }
+}
+
+impl FromStr for DiversifierIndex {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let x = s
+            .parse::<u128>()
+            .map_err(|e| anyhow!("invalid diversifier index {:?}: {}", s, e))?;
+        x.try_into()
+    }
+}
+
 impl TryFrom<DiversifierIndex> for u64 {
     type Error = anyhow::Error;
     fn try_from(diversifier_index: DiversifierIndex) -> Result<Self, Self::Error> {
@@ -222,7 +267,33 @@ mod tests {
         any::<[u8; 32]>().prop_map(DiversifierKey).boxed()
     }
 
+    #[test]
+    fn diversifier_index_range_is_checked() {
+        let largest = DiversifierIndex::try_from(DIVERSIFIER_INDEX_LIMIT - 1).unwrap();
+        assert_eq!(largest, DiversifierIndex([0xff; 11]));
+        assert!(DiversifierIndex::try_from(DIVERSIFIER_INDEX_LIMIT).is_err());
+        assert!(DIVERSIFIER_INDEX_LIMIT
+            .to_string()
+            .parse::<DiversifierIndex>()
+            .is_err());
+        assert!("-1".parse::<DiversifierIndex>().is_err());
+        assert!("0x01".parse::<DiversifierIndex>().is_err());
+    }
+
     proptest! {
+        #[test]
+        fn diversifier_index_string_roundtrip(index in diversifier_index_strategy()) {
+            let index2 = index.to_string().parse::<DiversifierIndex>().unwrap();
+            assert_eq!(index2, index);
+        }
+
+        #[test]
+        fn diversifier_index_matches_u64(x in any::<u64>()) {
+            let index = DiversifierIndex::from(x);
+            assert_eq!(index.to_string(), x.to_string());
+            assert_eq!(DiversifierIndex::try_from(x as u128).unwrap(), index);
+        }
+
         #[test]
         fn diversifier_encryption_roundtrip(
             key in diversifier_key_strategy(),
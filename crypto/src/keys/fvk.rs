@@ -8,9 +8,9 @@ use serde::{Deserialize, Serialize};
 
 use super::{DiversifierKey, IncomingViewingKey, NullifierKey, OutgoingViewingKey};
 use crate::{
-    ka, note, prf,
+    ka, prf,
     rdsa::{SpendAuth, VerificationKey},
-    Fq, Fr, Nullifier,
+    Fq, Fr,
 };
 
 static IVK_DOMAIN_SEP: Lazy<Fq> = Lazy::new(|| Fq::from_le_bytes_mod_order(b"penumbra.derive.ivk"));
@@ -72,16 +72,6 @@ impl FullViewingKey {
         &self.nk
     }
 
-    /// Derive the [`Nullifier`] for a positioned note given its [`merkle::Position`] and
-    /// [`note::Commitment`].
-    pub fn derive_nullifier(
-        &self,
-        pos: penumbra_tct::Position,
-        note_commitment: &note::Commitment,
-    ) -> Nullifier {
-        self.nk.derive_nullifier(pos, note_commitment)
-    }
-
     /// Returns the spend verification key contained in this full viewing key.
     pub fn spend_verification_key(&self) -> &VerificationKey<SpendAuth> {
         &self.ak
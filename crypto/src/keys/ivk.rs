@@ -1,7 +1,12 @@
-use ark_ff::PrimeField;
+use ark_ff::{PrimeField, UniformRand};
+use decaf377::FieldExt;
+use rand_core::{CryptoRng, RngCore};
 
 use super::{Diversifier, DiversifierIndex, DiversifierKey};
-use crate::{fmd, ka, prf, Address, Fr};
+use crate::{
+    address::{ownership_challenge, OwnershipProof},
+    fmd, ka, prf, Address, Fr,
+};
 
 pub const IVK_LEN_BYTES: usize = 64;
 
@@ -51,6 +56,25 @@ impl IncomingViewingKey {
     pub fn views_address(&self, address: &Address) -> bool {
         self.ivk.diversified_public(address.diversified_generator()) == *address.transmission_key()
     }
+
+    /// Prove that `address` is viewed by this key, without revealing the key, so that whoever
+    /// receives the proof can trust the address to be ours.
+    ///
+    /// Returns `None` if this key doesn't view `address`.
+    pub fn prove_ownership<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        address: &Address,
+    ) -> Option<OwnershipProof> {
+        if !self.views_address(address) {
+            return None;
+        }
+
+        let ivk = Fr::from_bytes(self.ivk.to_bytes()).expect("ivk is a valid scalar");
+        let k = Fr::rand(rng);
+        let c = ownership_challenge(address, &(k * *address.diversified_generator()));
+        Some(OwnershipProof { c, s: k + c * ivk })
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,273 @@
+//! Typed wrappers for decaf377-rdsa keys, whose encodings say which role the key plays.
+//!
+//! A bare 32-byte key could be a spend authorization key or a binding key, and a signing key or a
+//! verification key, so custody protocols and validator tooling exchange these wrappers instead.
+//! Each has its own Bech32m prefix for its string form, and a byte encoding, also used in its
+//! protobuf message, which starts with a domain tag naming its role, so a key can't be decoded as
+//! the wrong kind.
+
+use penumbra_proto::{crypto as pb, serializers::bech32str, Protobuf};
+use serde::{Deserialize, Serialize};
+
+use crate::rdsa::{Binding, SigningKey, SpendAuth, VerificationKey};
+
+/// The length of the domain-tagged byte encoding of each key: a tag byte, then the key.
+pub const TAGGED_KEY_LEN_BYTES: usize = 33;
+
+/// Implements the encodings shared by every key wrapper, for a wrapper `$name` of `$inner`,
+/// with protobuf message `$proto`, Bech32m prefix module `$bech32`, and domain tag `$tag`.
+macro_rules! key_encodings {
+    ($name:ident, $inner:ty, $proto:ident, $bech32:ident, $tag:expr) => {
+        impl $name {
+            /// The first byte of this key's tagged encoding, which no other kind of key shares.
+            pub const DOMAIN_TAG: u8 = $tag;
+
+            /// Encode this key as its domain tag followed by the key's bytes.
+            pub fn to_tagged_bytes(&self) -> [u8; TAGGED_KEY_LEN_BYTES] {
+                let mut bytes = [0u8; TAGGED_KEY_LEN_BYTES];
+                bytes[0] = Self::DOMAIN_TAG;
+                bytes[1..].copy_from_slice(&self.0.to_bytes());
+                bytes
+            }
+
+            /// Decode a key encoded with [`Self::to_tagged_bytes`], checking that it's this kind
+            /// of key.
+            pub fn from_tagged_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+                if bytes.len() != TAGGED_KEY_LEN_BYTES {
+                    return Err(anyhow::anyhow!(
+                        "tagged {} must be {} bytes, got {}",
+                        stringify!($name),
+                        TAGGED_KEY_LEN_BYTES,
+                        bytes.len()
+                    ));
+                }
+                if bytes[0] != Self::DOMAIN_TAG {
+                    return Err(anyhow::anyhow!(
+                        "expected a {} with domain tag {}, got domain tag {}",
+                        stringify!($name),
+                        Self::DOMAIN_TAG,
+                        bytes[0]
+                    ));
+                }
+                Ok(Self(<$inner>::try_from(&bytes[1..])?))
+            }
+        }
+
+        impl From<$inner> for $name {
+            fn from(key: $inner) -> Self {
+                Self(key)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(key: $name) -> Self {
+                key.0
+            }
+        }
+
+        impl Protobuf<pb::$proto> for $name {}
+
+        impl From<$name> for pb::$proto {
+            fn from(key: $name) -> Self {
+                pb::$proto {
+                    inner: key.to_tagged_bytes().to_vec(),
+                }
+            }
+        }
+
+        impl TryFrom<pb::$proto> for $name {
+            type Error = anyhow::Error;
+
+            fn try_from(msg: pb::$proto) -> Result<Self, Self::Error> {
+                Self::from_tagged_bytes(&msg.inner)
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = anyhow::Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                // The Bech32m prefix already names the kind of key, so the string form is untagged.
+                let bytes =
+                    bech32str::decode(s, bech32str::$bech32::BECH32_PREFIX, bech32str::Bech32m)?;
+                Ok(Self(<$inner>::try_from(bytes.as_slice())?))
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&bech32str::encode(
+                    &self.0.to_bytes(),
+                    bech32str::$bech32::BECH32_PREFIX,
+                    bech32str::Bech32m,
+                ))
+            }
+        }
+    };
+}
+
+/// A spend authorization verification key, which verifies the signatures authorizing spends.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(
+    try_from = "pb::SpendAuthVerificationKey",
+    into = "pb::SpendAuthVerificationKey"
+)]
+pub struct SpendAuthVerificationKey(pub VerificationKey<SpendAuth>);
+
+key_encodings!(
+    SpendAuthVerificationKey,
+    VerificationKey<SpendAuth>,
+    SpendAuthVerificationKey,
+    spend_auth_verification_key,
+    1
+);
+
+impl std::fmt::Debug for SpendAuthVerificationKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <Self as std::fmt::Display>::fmt(self, f)
+    }
+}
+
+/// A spend authorization signing key, which signs to authorize spends.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "pb::SpendAuthSigningKey", into = "pb::SpendAuthSigningKey")]
+pub struct SpendAuthSigningKey(pub SigningKey<SpendAuth>);
+
+key_encodings!(
+    SpendAuthSigningKey,
+    SigningKey<SpendAuth>,
+    SpendAuthSigningKey,
+    spend_auth_signing_key,
+    2
+);
+
+impl SpendAuthSigningKey {
+    /// The verification key for this signing key.
+    pub fn verification_key(&self) -> SpendAuthVerificationKey {
+        SpendAuthVerificationKey(VerificationKey::from(&self.0))
+    }
+}
+
+impl std::fmt::Debug for SpendAuthSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the signing key into logs.
+        f.debug_tuple("SpendAuthSigningKey")
+            .field(&self.verification_key())
+            .finish()
+    }
+}
+
+/// A binding verification key, which verifies that a transaction's value balances.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(
+    try_from = "pb::BindingVerificationKey",
+    into = "pb::BindingVerificationKey"
+)]
+pub struct BindingVerificationKey(pub VerificationKey<Binding>);
+
+key_encodings!(
+    BindingVerificationKey,
+    VerificationKey<Binding>,
+    BindingVerificationKey,
+    binding_verification_key,
+    3
+);
+
+impl std::fmt::Debug for BindingVerificationKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <Self as std::fmt::Display>::fmt(self, f)
+    }
+}
+
+/// A binding signing key, derived from the value blinding factors of a transaction's actions,
+/// which signs to show that the transaction's value balances.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "pb::BindingSigningKey", into = "pb::BindingSigningKey")]
+pub struct BindingSigningKey(pub SigningKey<Binding>);
+
+key_encodings!(
+    BindingSigningKey,
+    SigningKey<Binding>,
+    BindingSigningKey,
+    binding_signing_key,
+    4
+);
+
+impl BindingSigningKey {
+    /// The verification key for this signing key.
+    pub fn verification_key(&self) -> BindingVerificationKey {
+        BindingVerificationKey(VerificationKey::from(&self.0))
+    }
+}
+
+impl std::fmt::Debug for BindingSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the signing key into logs.
+        f.debug_tuple("BindingSigningKey")
+            .field(&self.verification_key())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn keys_round_trip_through_every_encoding() {
+        let sk = SpendAuthSigningKey(SigningKey::new(OsRng));
+        let vk = sk.verification_key();
+
+        let decoded = SpendAuthSigningKey::from_str(&sk.to_string()).unwrap();
+        assert_eq!(decoded.verification_key(), vk);
+        assert_eq!(
+            SpendAuthVerificationKey::from_str(&vk.to_string()).unwrap(),
+            vk
+        );
+        assert_eq!(
+            SpendAuthVerificationKey::decode(vk.encode_to_vec().as_slice()).unwrap(),
+            vk
+        );
+        assert_eq!(
+            SpendAuthVerificationKey::from_tagged_bytes(&vk.to_tagged_bytes()).unwrap(),
+            vk
+        );
+
+        let bsk = BindingSigningKey(SigningKey::new(OsRng));
+        let bvk = bsk.verification_key();
+        assert_eq!(
+            BindingSigningKey::from_tagged_bytes(&bsk.to_tagged_bytes())
+                .unwrap()
+                .verification_key(),
+            bvk
+        );
+        assert_eq!(
+            BindingVerificationKey::from_str(&bvk.to_string()).unwrap(),
+            bvk
+        );
+    }
+
+    #[test]
+    fn keys_are_not_decoded_as_another_kind() {
+        let sk = SpendAuthSigningKey(SigningKey::new(OsRng));
+        let vk = sk.verification_key();
+
+        // The same bytes, in the role of another kind of key, are rejected.
+        assert!(BindingVerificationKey::from_tagged_bytes(&vk.to_tagged_bytes()).is_err());
+        assert!(SpendAuthSigningKey::from_tagged_bytes(&vk.to_tagged_bytes()).is_err());
+        assert!(BindingVerificationKey::from_str(&vk.to_string()).is_err());
+        assert!(SpendAuthVerificationKey::from_str(&sk.to_string()).is_err());
+        assert!(BindingVerificationKey::decode(vk.encode_to_vec().as_slice()).is_err());
+        assert!(SpendAuthSigningKey::decode(vk.encode_to_vec().as_slice()).is_err());
+
+        // An untagged protobuf encoding is rejected, too.
+        let untagged = pb::SpendAuthVerificationKey {
+            inner: vk.0.to_bytes().to_vec(),
+        };
+        assert!(SpendAuthVerificationKey::try_from(untagged).is_err());
+    }
+}
@@ -6,6 +6,7 @@ pub use decaf377_ka as ka;
 pub use decaf377_rdsa as rdsa;
 
 mod address;
+mod amount;
 pub mod asset;
 mod delegation_token;
 pub mod eddy;
@@ -18,11 +19,16 @@ mod note_payload;
 mod nullifier;
 mod prf;
 pub mod proofs;
+#[cfg(feature = "r1cs")]
+pub mod r1cs;
 pub mod swap;
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
 pub mod transaction;
 pub mod value;
 
-pub use address::Address;
+pub use address::{Address, OwnershipProof, OWNERSHIP_PROOF_LEN_BYTES};
+pub use amount::Amount;
 pub use asset::Asset;
 pub use delegation_token::DelegationToken;
 pub use flow::MockFlowCiphertext;
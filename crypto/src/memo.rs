@@ -6,14 +6,33 @@ use chacha20poly1305::{
     ChaCha20Poly1305, Key, Nonce,
 };
 use once_cell::sync::Lazy;
+use penumbra_proto::crypto as pb;
+use rand_core::{CryptoRng, RngCore};
 
-use crate::{ka, keys::IncomingViewingKey, note::derive_symmetric_key, Address};
+use crate::{
+    ka, keys::IncomingViewingKey, note::derive_symmetric_key, Address, OwnershipProof,
+    OWNERSHIP_PROOF_LEN_BYTES,
+};
 
 pub const MEMO_CIPHERTEXT_LEN_BYTES: usize = 528;
 
 // This is the `MEMO_CIPHERTEXT_LEN_BYTES` - MAC size (16 bytes).
 pub const MEMO_LEN_BYTES: usize = 512;
 
+/// The length of an encoded [`Address`] in a memo.
+pub const MEMO_ADDRESS_LEN_BYTES: usize = 80;
+
+/// The first byte of a memo which carries a return address.
+///
+/// Such a memo is laid out as this tag, then the encoded return address, then an
+/// [`OwnershipProof`] of the return address, then the text of the memo. Plain text memos can't
+/// begin with this tag, so they can't be mistaken for one with a return address.
+pub const MEMO_RETURN_ADDRESS_TAG: u8 = 0x01;
+
+/// The offset of the text in a memo which carries a return address.
+const MEMO_RETURN_ADDRESS_TEXT_START: usize =
+    1 + MEMO_ADDRESS_LEN_BYTES + OWNERSHIP_PROOF_LEN_BYTES;
+
 /// The nonce used for memo encryption.
 pub static MEMO_ENCRYPTION_NONCE: Lazy<[u8; 12]> = Lazy::new(|| {
     let nonce_bytes = 1u128.to_le_bytes();
@@ -45,6 +64,87 @@ impl TryFrom<&[u8]> for MemoPlaintext {
 }
 
 impl MemoPlaintext {
+    /// Construct a memo with only the given text.
+    ///
+    /// Fails if the text is too long, or begins with [`MEMO_RETURN_ADDRESS_TAG`], so that it
+    /// would be read as carrying a return address.
+    pub fn from_text(text: &str) -> Result<MemoPlaintext, anyhow::Error> {
+        if text.as_bytes().first() == Some(&MEMO_RETURN_ADDRESS_TAG) {
+            return Err(anyhow!(
+                "memo text can't begin with the byte {:#04x}",
+                MEMO_RETURN_ADDRESS_TAG
+            ));
+        }
+        text.as_bytes().try_into()
+    }
+
+    /// Construct a memo carrying the sender's return address, so the recipient can refund the
+    /// payment, followed by the given text.
+    ///
+    /// The return address is accompanied by a proof that it's viewed by `ivk`, so that the
+    /// recipient knows it belongs to the sender. Fails if `ivk` doesn't view it.
+    pub fn with_return_address<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        ivk: &IncomingViewingKey,
+        return_address: &Address,
+        text: &str,
+    ) -> Result<MemoPlaintext, anyhow::Error> {
+        let text_start = MEMO_RETURN_ADDRESS_TEXT_START;
+        if text.len() > MEMO_LEN_BYTES - text_start {
+            return Err(anyhow!(
+                "provided memo exceeds maximum memo size with a return address ({} bytes)",
+                MEMO_LEN_BYTES - text_start
+            ));
+        }
+        let proof = ivk
+            .prove_ownership(rng, return_address)
+            .ok_or_else(|| anyhow!("return address is not viewed by the sender's key"))?;
+
+        let address_end = 1 + MEMO_ADDRESS_LEN_BYTES;
+        let mut mp = [0u8; MEMO_LEN_BYTES];
+        mp[0] = MEMO_RETURN_ADDRESS_TAG;
+        mp[1..address_end].copy_from_slice(&pb::Address::from(*return_address).inner);
+        mp[address_end..text_start].copy_from_slice(&proof.to_bytes());
+        mp[text_start..text_start + text.len()].copy_from_slice(text.as_bytes());
+
+        Ok(MemoPlaintext(mp))
+    }
+
+    /// The return address carried in this memo, if any.
+    ///
+    /// A return address whose proof of ownership doesn't verify is ignored, since the sender
+    /// could have put anyone's address there.
+    pub fn return_address(&self) -> Option<Address> {
+        if self.0[0] != MEMO_RETURN_ADDRESS_TAG {
+            return None;
+        }
+
+        let address_end = 1 + MEMO_ADDRESS_LEN_BYTES;
+        let address = Address::try_from(pb::Address {
+            inner: self.0[1..address_end].to_vec(),
+        })
+        .ok()?;
+        let proof =
+            OwnershipProof::try_from(&self.0[address_end..MEMO_RETURN_ADDRESS_TEXT_START]).ok()?;
+
+        address.verify_ownership(&proof).then(|| address)
+    }
+
+    /// The text of this memo, without any return address or trailing padding.
+    pub fn text(&self) -> String {
+        // The layout is given by the tag alone, so that the text of a memo whose return address
+        // doesn't verify still doesn't include it.
+        let text = if self.0[0] == MEMO_RETURN_ADDRESS_TAG {
+            &self.0[MEMO_RETURN_ADDRESS_TEXT_START..]
+        } else {
+            &self.0[..]
+        };
+
+        String::from_utf8_lossy(text)
+            .trim_end_matches('\0')
+            .to_string()
+    }
+
     /// Encrypt a memo, returning its ciphertext.
     pub fn encrypt(&self, esk: &ka::Secret, address: &Address) -> MemoCiphertext {
         let epk = esk.diversified_public(address.diversified_generator());
@@ -77,7 +177,31 @@ impl MemoPlaintext {
             .key_agreement_with(epk)
             .map_err(|_| anyhow!("could not perform key agreement"))?;
 
-        let key = derive_symmetric_key(&shared_secret, epk);
+        MemoPlaintext::decrypt_with_shared_secret(ciphertext, &shared_secret, epk)
+    }
+
+    /// Decrypt a `MemoCiphertext` sent to the address with `transmission_key`, using the
+    /// ephemeral secret key recovered with the sender's outgoing viewing key (see
+    /// [`Note::decrypt_key`](crate::Note::decrypt_key)).
+    pub fn decrypt_outgoing(
+        ciphertext: MemoCiphertext,
+        esk: &ka::Secret,
+        transmission_key: &ka::Public,
+        epk: &ka::Public,
+    ) -> Result<MemoPlaintext, anyhow::Error> {
+        let shared_secret = esk
+            .key_agreement_with(transmission_key)
+            .map_err(|_| anyhow!("could not perform key agreement"))?;
+
+        MemoPlaintext::decrypt_with_shared_secret(ciphertext, &shared_secret, epk)
+    }
+
+    fn decrypt_with_shared_secret(
+        ciphertext: MemoCiphertext,
+        shared_secret: &ka::SharedSecret,
+        epk: &ka::Public,
+    ) -> Result<MemoPlaintext, anyhow::Error> {
+        let key = derive_symmetric_key(shared_secret, epk);
         let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
         let nonce = Nonce::from_slice(&*MEMO_ENCRYPTION_NONCE);
         let plaintext = cipher
@@ -122,8 +246,90 @@ mod tests {
         let ciphertext = memo.encrypt(&esk, &dest);
 
         let epk = esk.diversified_public(dest.diversified_generator());
-        let plaintext = MemoPlaintext::decrypt(ciphertext, ivk, &epk).expect("can decrypt memo");
+        let plaintext =
+            MemoPlaintext::decrypt(ciphertext.clone(), ivk, &epk).expect("can decrypt memo");
+
+        assert_eq!(plaintext, memo);
+
+        // The sender can decrypt it too, with the ephemeral secret key.
+        let plaintext =
+            MemoPlaintext::decrypt_outgoing(ciphertext, &esk, dest.transmission_key(), &epk)
+                .expect("can decrypt outgoing memo");
 
         assert_eq!(plaintext, memo);
     }
+
+    #[test]
+    fn return_address_round_trip() {
+        let mut rng = OsRng;
+
+        let seed_phrase = SeedPhrase::generate(&mut rng);
+        let sk = SpendKey::from_seed_phrase(seed_phrase, 0);
+        let (return_address, _dtk_d) = sk
+            .full_viewing_key()
+            .incoming()
+            .payment_address(3u64.into());
+
+        let ivk = sk.full_viewing_key().incoming();
+        let memo =
+            MemoPlaintext::with_return_address(&mut rng, ivk, &return_address, "thanks").unwrap();
+        assert_eq!(memo.return_address(), Some(return_address));
+        assert_eq!(memo.text(), "thanks");
+
+        // Plain text memos have no return address, and can't be mistaken for one.
+        let memo = MemoPlaintext::from_text("thanks").unwrap();
+        assert_eq!(memo.return_address(), None);
+        assert_eq!(memo.text(), "thanks");
+        assert_eq!(MemoPlaintext::default().return_address(), None);
+        assert!(MemoPlaintext::from_text("\u{1}thanks").is_err());
+
+        // The text must leave room for the return address and its proof.
+        let long_text = "a".repeat(MEMO_LEN_BYTES - MEMO_RETURN_ADDRESS_TEXT_START + 1);
+        assert!(
+            MemoPlaintext::with_return_address(&mut rng, ivk, &return_address, &long_text).is_err()
+        );
+        let long_text = "a".repeat(MEMO_LEN_BYTES - MEMO_RETURN_ADDRESS_TEXT_START);
+        let memo =
+            MemoPlaintext::with_return_address(&mut rng, ivk, &return_address, &long_text).unwrap();
+        assert_eq!(memo.text(), long_text);
+    }
+
+    #[test]
+    fn unauthenticated_return_address_is_ignored() {
+        let mut rng = OsRng;
+
+        let sender = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut rng), 0);
+        let (sender_address, _dtk_d) = sender
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+        let victim = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut rng), 0);
+        let (victim_address, _dtk_d) = victim
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+
+        // The sender can't prove they own someone else's address...
+        assert!(MemoPlaintext::with_return_address(
+            &mut rng,
+            sender.full_viewing_key().incoming(),
+            &victim_address,
+            "refund me"
+        )
+        .is_err());
+
+        // ...nor swap it in for their own after proving ownership of that.
+        let mut memo = MemoPlaintext::with_return_address(
+            &mut rng,
+            sender.full_viewing_key().incoming(),
+            &sender_address,
+            "refund me",
+        )
+        .unwrap();
+        memo.0[1..1 + MEMO_ADDRESS_LEN_BYTES]
+            .copy_from_slice(&pb::Address::from(victim_address).inner);
+        assert_eq!(memo.return_address(), None);
+        // The text is still found after the return address.
+        assert_eq!(memo.text(), "refund me");
+    }
 }
@@ -18,7 +18,7 @@ pub use penumbra_tct::Commitment;
 use crate::{
     asset, ka,
     keys::{Diversifier, IncomingViewingKey, OutgoingViewingKey},
-    value, Fq, Value,
+    value, Amount, Fq, Value,
 };
 
 pub const NOTE_LEN_BYTES: usize = 116;
@@ -51,7 +51,7 @@ pub struct Note {
 }
 
 /// The domain separator used to generate note commitments.
-static NOTECOMMIT_DOMAIN_SEP: Lazy<Fq> = Lazy::new(|| {
+pub(crate) static NOTECOMMIT_DOMAIN_SEP: Lazy<Fq> = Lazy::new(|| {
     Fq::from_le_bytes_mod_order(blake2b_simd::blake2b(b"penumbra.notecommit").as_bytes())
 });
 
@@ -124,8 +124,8 @@ impl Note {
         self.value.asset_id
     }
 
-    pub fn amount(&self) -> u64 {
-        self.value.amount
+    pub fn amount(&self) -> Amount {
+        self.value.amount.into()
     }
 
     /// Encrypt a note, returning its ciphertext.
@@ -158,20 +158,8 @@ impl Note {
         ovk: &OutgoingViewingKey,
         cv: value::Commitment,
     ) -> [u8; OVK_WRAPPED_LEN_BYTES] {
-        let cv_bytes: [u8; 32] = cv.into();
-        let cm_bytes: [u8; 32] = self.commit().into();
         let epk = esk.diversified_public(&self.diversified_generator());
-
-        // Use Blake2b-256 to derive an encryption key `ock` from the value commitment,
-        // note commitment, the ephemeral public key, and the outgoing viewing key.
-        let mut kdf_params = blake2b_simd::Params::new();
-        kdf_params.hash_length(32);
-        let mut kdf = kdf_params.to_state();
-        kdf.update(&ovk.0);
-        kdf.update(&cv_bytes);
-        kdf.update(&cm_bytes);
-        kdf.update(&epk.0);
-        let kdf_output = kdf.finalize();
+        let kdf_output = derive_outgoing_cipher_key(ovk, cv, self.commit(), &epk);
         let ock = Key::from_slice(kdf_output.as_bytes());
 
         let mut op = Vec::new();
@@ -192,6 +180,81 @@ impl Note {
         wrapped_ovk
     }
 
+    /// Decrypt a key wrapped by [`Note::encrypt_key`] with the sender's outgoing viewing key,
+    /// returning the transmission key of the note's recipient and the ephemeral secret key the
+    /// note was encrypted with.
+    pub fn decrypt_key(
+        wrapped_key: &[u8; OVK_WRAPPED_LEN_BYTES],
+        ovk: &OutgoingViewingKey,
+        cv: value::Commitment,
+        cm: Commitment,
+        epk: &ka::Public,
+    ) -> Result<(ka::Public, ka::Secret), Error> {
+        let kdf_output = derive_outgoing_cipher_key(ovk, cv, cm, epk);
+        let ock = Key::from_slice(kdf_output.as_bytes());
+
+        let cipher = ChaCha20Poly1305::new(ock);
+        let nonce = Nonce::from_slice(&*NOTE_ENCRYPTION_NONCE);
+        let op = cipher
+            .decrypt(nonce, wrapped_key.as_ref())
+            .map_err(|_| Error::DecryptionError)?;
+        if op.len() != 64 {
+            return Err(Error::DecryptionError);
+        }
+
+        let transmission_key =
+            ka::Public::try_from(&op[0..32]).map_err(|_| Error::DecryptionError)?;
+        let esk = ka::Secret::try_from(&op[32..64]).map_err(|_| Error::DecryptionError)?;
+
+        Ok((transmission_key, esk))
+    }
+
+    /// Decrypt a note ciphertext sent by the holder of the outgoing viewing key `ovk`, using the
+    /// key wrapped for it, so that a sender can recover the notes it sent to others.
+    ///
+    /// Fails unless the decrypted note matches the note commitment `cm` and ephemeral key `epk`.
+    pub fn decrypt_outgoing(
+        ciphertext: &[u8],
+        wrapped_key: &[u8; OVK_WRAPPED_LEN_BYTES],
+        ovk: &OutgoingViewingKey,
+        cv: value::Commitment,
+        cm: Commitment,
+        epk: &ka::Public,
+    ) -> Result<Note, Error> {
+        if ciphertext.len() != NOTE_CIPHERTEXT_BYTES {
+            return Err(Error::DecryptionError);
+        }
+
+        let (transmission_key, esk) = Note::decrypt_key(wrapped_key, ovk, cv, cm, epk)?;
+        let shared_secret = esk
+            .key_agreement_with(&transmission_key)
+            .map_err(|_| Error::DecryptionError)?;
+
+        let key = derive_symmetric_key(&shared_secret, epk);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+        let nonce = Nonce::from_slice(&*NOTE_ENCRYPTION_NONCE);
+        let plaintext = cipher
+            .decrypt(nonce, ciphertext.as_ref())
+            .map_err(|_| Error::DecryptionError)?;
+
+        let plaintext_bytes: [u8; NOTE_LEN_BYTES] =
+            plaintext.try_into().map_err(|_| Error::DecryptionError)?;
+        let note: Note = plaintext_bytes
+            .try_into()
+            .map_err(|_| Error::DecryptionError)?;
+
+        // The wrapped key is only bound to the note by the key derivation, so check that the note
+        // is the one committed to, and was encrypted to the recipient it names.
+        if note.commit() != cm
+            || note.transmission_key() != transmission_key
+            || esk.diversified_public(&note.diversified_generator()) != *epk
+        {
+            return Err(Error::InvalidNoteCommitment);
+        }
+
+        Ok(note)
+    }
+
     /// Decrypt a note ciphertext to generate a plaintext `Note`.
     pub fn decrypt(
         ciphertext: &[u8],
@@ -271,6 +334,28 @@ pub(crate) fn derive_symmetric_key(
     kdf.finalize()
 }
 
+/// Use Blake2b-256 to derive the key `ock` which wraps a note's encryption key to the sender's
+/// outgoing viewing key, from the value commitment, note commitment, and ephemeral public key.
+fn derive_outgoing_cipher_key(
+    ovk: &OutgoingViewingKey,
+    cv: value::Commitment,
+    cm: Commitment,
+    epk: &ka::Public,
+) -> blake2b_simd::Hash {
+    let cv_bytes: [u8; 32] = cv.into();
+    let cm_bytes: [u8; 32] = cm.into();
+
+    let mut kdf_params = blake2b_simd::Params::new();
+    kdf_params.hash_length(32);
+    let mut kdf = kdf_params.to_state();
+    kdf.update(&ovk.0);
+    kdf.update(&cv_bytes);
+    kdf.update(&cm_bytes);
+    kdf.update(&epk.0);
+
+    kdf.finalize()
+}
+
 impl std::fmt::Debug for Note {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Note")
@@ -400,7 +485,10 @@ mod tests {
     use rand_core::OsRng;
 
     use super::*;
-    use crate::keys::{SeedPhrase, SpendKey};
+    use crate::{
+        keys::{SeedPhrase, SpendKey},
+        Fr,
+    };
 
     #[test]
     fn test_note_encryption_and_decryption() {
@@ -433,4 +521,50 @@ mod tests {
 
         assert!(Note::decrypt(&ciphertext, ivk2, &epk).is_err());
     }
+
+    #[test]
+    fn test_outgoing_note_decryption() {
+        let mut rng = OsRng;
+
+        let seed_phrase = SeedPhrase::generate(&mut rng);
+        let sk = SpendKey::from_seed_phrase(seed_phrase, 0);
+        let ovk = sk.full_viewing_key().outgoing();
+
+        let seed_phrase = SeedPhrase::generate(&mut rng);
+        let recipient = SpendKey::from_seed_phrase(seed_phrase, 0);
+        let (dest, _dtk_d) = recipient
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+
+        let value = Value {
+            amount: 10,
+            asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+        };
+        let note = Note::generate(&mut rng, &dest, value);
+        let esk = ka::Secret::new(&mut rng);
+        let cv = -value.commit(Fr::rand(&mut rng));
+
+        let ciphertext = note.encrypt(&esk);
+        let wrapped_key = note.encrypt_key(&esk, ovk, cv);
+
+        let epk = esk.diversified_public(dest.diversified_generator());
+        let plaintext =
+            Note::decrypt_outgoing(&ciphertext, &wrapped_key, ovk, cv, note.commit(), &epk)
+                .expect("can decrypt outgoing note");
+
+        assert_eq!(plaintext, note);
+
+        // Nobody else's outgoing viewing key unwraps the key.
+        let other_ovk = recipient.full_viewing_key().outgoing();
+        assert!(Note::decrypt_outgoing(
+            &ciphertext,
+            &wrapped_key,
+            other_ovk,
+            cv,
+            note.commit(),
+            &epk
+        )
+        .is_err());
+    }
 }
@@ -106,7 +106,8 @@ impl SpendProof {
 
         // Nullifier integrity.
         if nullifier
-            != self.nk.derive_nullifier(
+            != Nullifier::derive(
+                &self.nk,
                 self.note_commitment_proof.position(),
                 &self.note_commitment_proof.commitment(),
             )
@@ -456,7 +457,8 @@ impl SwapProof {
 
         // Nullifier integrity.
         if nullifier
-            != self.nk.derive_nullifier(
+            != Nullifier::derive(
+                &self.nk,
                 self.note_commitment_proof.position(),
                 &self.note_commitment_proof.commitment(),
             )
@@ -816,7 +818,7 @@ mod tests {
         };
 
         let rk: VerificationKey<SpendAuth> = rsk.into();
-        let nf = nk.derive_nullifier(0.into(), &note_commitment);
+        let nf = Nullifier::derive(&nk, 0.into(), &note_commitment);
         assert!(proof
             .verify(anchor, value_to_send.commit(v_blinding), nf, rk)
             .is_ok());
@@ -861,7 +863,7 @@ mod tests {
         };
 
         let rk: VerificationKey<SpendAuth> = rsk.into();
-        let nf = nk.derive_nullifier(0.into(), &note_commitment);
+        let nf = Nullifier::derive(&nk, 0.into(), &note_commitment);
         assert!(proof
             .verify(incorrect_anchor, value_to_send.commit(v_blinding), nf, rk)
             .is_err());
@@ -905,7 +907,7 @@ mod tests {
         };
 
         let rk: VerificationKey<SpendAuth> = rsk.into();
-        let nf = nk.derive_nullifier(0.into(), &note_commitment);
+        let nf = Nullifier::derive(&nk, 0.into(), &note_commitment);
         assert!(proof
             .verify(anchor, value_to_send.commit(Fr::rand(&mut rng)), nf, rk)
             .is_err());
@@ -949,7 +951,7 @@ mod tests {
         };
 
         let rk: VerificationKey<SpendAuth> = rsk.into();
-        let incorrect_nf = nk.derive_nullifier(5.into(), &note_commitment);
+        let incorrect_nf = Nullifier::derive(&nk, 5.into(), &note_commitment);
         assert!(proof
             .verify(anchor, value_to_send.commit(v_blinding), incorrect_nf, rk)
             .is_err());
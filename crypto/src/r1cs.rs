@@ -0,0 +1,201 @@
+//! In-circuit (R1CS) versions of the note commitment, nullifier derivation, and value commitment.
+//!
+//! Each gadget here mirrors a native function elsewhere in this crate, and the tests at the bottom
+//! of this module run both over the same test vectors, so that a change to the native
+//! implementation which isn't reflected in its circuit (or vice versa) fails the test suite.
+
+use std::ops::Deref;
+
+use ark_r1cs_std::{bits::boolean::Boolean, groups::CurveVar, prelude::*};
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+use ark_sponge::{
+    constraints::CryptographicSpongeVar, poseidon::constraints::PoseidonSpongeVar,
+    poseidon::PoseidonParameters,
+};
+use decaf377::r1cs::{ElementVar, FqVar};
+
+use crate::{note::NOTECOMMIT_DOMAIN_SEP, nullifier::NULLIFIER_DOMAIN_SEP, value, Fq};
+
+/// Hash the given field elements in-circuit, in the same way as the native `poseidon377::hash_N`
+/// for `N = inputs.len()`.
+fn hash(
+    cs: ConstraintSystemRef<Fq>,
+    parameters: &PoseidonParameters<Fq>,
+    domain_separator: &Fq,
+    inputs: &[FqVar],
+) -> Result<FqVar, SynthesisError> {
+    let mut sponge = PoseidonSpongeVar::new(cs.clone(), parameters);
+    sponge.absorb(&FqVar::new_constant(cs, *domain_separator)?)?;
+    sponge.absorb(&inputs)?;
+    Ok(sponge.squeeze_field_elements(1)?.remove(0))
+}
+
+/// The in-circuit version of [`note::commitment`](crate::note::commitment).
+pub fn note_commitment(
+    cs: ConstraintSystemRef<Fq>,
+    note_blinding: &FqVar,
+    amount: &FqVar,
+    asset_id: &FqVar,
+    diversified_generator: &ElementVar,
+    transmission_key_s: &FqVar,
+) -> Result<FqVar, SynthesisError> {
+    hash(
+        cs,
+        &poseidon377::RATE_5_PARAMS,
+        &NOTECOMMIT_DOMAIN_SEP,
+        &[
+            note_blinding.clone(),
+            amount.clone(),
+            asset_id.clone(),
+            diversified_generator.compress_to_field()?,
+            transmission_key_s.clone(),
+        ],
+    )
+}
+
+/// The in-circuit version of [`Nullifier::derive`](crate::Nullifier::derive).
+pub fn derive_nullifier(
+    cs: ConstraintSystemRef<Fq>,
+    nk: &FqVar,
+    note_commitment: &FqVar,
+    position: &FqVar,
+) -> Result<FqVar, SynthesisError> {
+    hash(
+        cs,
+        &poseidon377::RATE_3_PARAMS,
+        &NULLIFIER_DOMAIN_SEP,
+        &[nk.clone(), note_commitment.clone(), position.clone()],
+    )
+}
+
+/// The in-circuit version of [`Value::commit`](crate::Value::commit).
+///
+/// The `amount` and `blinding` scalars are given as little-endian bits, and the value generator
+/// (which depends only on the asset ID) is given as a witnessed element.
+pub fn value_commitment(
+    cs: ConstraintSystemRef<Fq>,
+    amount: &[Boolean<Fq>],
+    value_generator: &ElementVar,
+    blinding: &[Boolean<Fq>],
+) -> Result<ElementVar, SynthesisError> {
+    let blinding_generator =
+        ElementVar::new_constant(cs, *value::VALUE_BLINDING_GENERATOR.deref())?;
+
+    let v_g = value_generator.scalar_mul_le(amount.iter())?;
+    let r_h = blinding_generator.scalar_mul_le(blinding.iter())?;
+
+    Ok(v_g + r_h)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ff::{BigInteger, PrimeField, UniformRand};
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::{asset, keys::NullifierKey, note, Fr, Value};
+
+    /// The number of random test vectors to check each gadget against.
+    const TEST_VECTORS: usize = 4;
+
+    fn rng() -> ChaCha20Rng {
+        ChaCha20Rng::seed_from_u64(377)
+    }
+
+    fn scalar_bits(cs: ConstraintSystemRef<Fq>, scalar: Fr) -> Vec<Boolean<Fq>> {
+        scalar
+            .into_repr()
+            .to_bits_le()
+            .into_iter()
+            .map(|bit| Boolean::new_witness(cs.clone(), || Ok(bit)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn note_commitment_matches_native() {
+        let mut rng = rng();
+        for _ in 0..TEST_VECTORS {
+            let note_blinding = Fq::rand(&mut rng);
+            let value = Value {
+                amount: u64::rand(&mut rng),
+                asset_id: asset::Id(Fq::rand(&mut rng)),
+            };
+            let diversified_generator = decaf377::Element::rand(&mut rng);
+            let transmission_key_s = Fq::rand(&mut rng);
+
+            let native = note::commitment(
+                note_blinding,
+                value,
+                diversified_generator,
+                transmission_key_s,
+            );
+
+            let cs = ConstraintSystem::<Fq>::new_ref();
+            let in_circuit = note_commitment(
+                cs.clone(),
+                &FqVar::new_witness(cs.clone(), || Ok(note_blinding)).unwrap(),
+                &FqVar::new_witness(cs.clone(), || Ok(Fq::from(value.amount))).unwrap(),
+                &FqVar::new_witness(cs.clone(), || Ok(value.asset_id.0)).unwrap(),
+                &ElementVar::new_witness(cs.clone(), || Ok(diversified_generator)).unwrap(),
+                &FqVar::new_witness(cs.clone(), || Ok(transmission_key_s)).unwrap(),
+            )
+            .unwrap();
+
+            assert!(cs.is_satisfied().unwrap());
+            assert_eq!(native.0, in_circuit.value().unwrap());
+        }
+    }
+
+    #[test]
+    fn nullifier_matches_native() {
+        let mut rng = rng();
+        for _ in 0..TEST_VECTORS {
+            let nk = NullifierKey(Fq::rand(&mut rng));
+            let note_commitment = note::Commitment(Fq::rand(&mut rng));
+            let position = penumbra_tct::Position::from(u64::rand(&mut rng) >> 16);
+
+            let native = crate::Nullifier::derive(&nk, position, &note_commitment);
+
+            let cs = ConstraintSystem::<Fq>::new_ref();
+            let in_circuit = derive_nullifier(
+                cs.clone(),
+                &FqVar::new_witness(cs.clone(), || Ok(nk.0)).unwrap(),
+                &FqVar::new_witness(cs.clone(), || Ok(note_commitment.0)).unwrap(),
+                &FqVar::new_witness(cs.clone(), || Ok(Fq::from(u64::from(position)))).unwrap(),
+            )
+            .unwrap();
+
+            assert!(cs.is_satisfied().unwrap());
+            assert_eq!(native.0, in_circuit.value().unwrap());
+        }
+    }
+
+    #[test]
+    fn value_commitment_matches_native() {
+        let mut rng = rng();
+        for _ in 0..TEST_VECTORS {
+            let value = Value {
+                amount: u64::rand(&mut rng),
+                asset_id: asset::Id(Fq::rand(&mut rng)),
+            };
+            let blinding = Fr::rand(&mut rng);
+
+            let native = value.commit(blinding);
+
+            let cs = ConstraintSystem::<Fq>::new_ref();
+            let in_circuit = value_commitment(
+                cs.clone(),
+                &scalar_bits(cs.clone(), Fr::from(value.amount)),
+                &ElementVar::new_witness(cs.clone(), || Ok(value.asset_id.value_generator()))
+                    .unwrap(),
+                &scalar_bits(cs.clone(), blinding),
+            )
+            .unwrap();
+
+            assert!(cs.is_satisfied().unwrap());
+            assert_eq!(native.0, in_circuit.value().unwrap());
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Deterministic test vectors, for validating other implementations of Penumbra's key derivation,
+//! addresses, note commitments, nullifiers, and note commitment tree against this crate.
+//!
+//! The inputs are fixed by the constants in this module, and [`generate`] derives the vectors
+//! from them. The `generate-test-vectors` binary prints them as JSON:
+//!
+//! ```sh
+//! cargo run --bin generate-test-vectors --features test-vectors > vectors.json
+//! ```
+//!
+//! Every value is encoded the way it is displayed elsewhere in the workspace: keys and addresses
+//! in Bech32m, and field elements as the hex encoding of their canonical bytes.
+
+use decaf377::FieldExt;
+use serde::Serialize;
+
+use crate::{
+    asset,
+    keys::{SeedPhrase, SpendKey},
+    Fq, Note, Nullifier, Value,
+};
+use penumbra_tct as tct;
+
+/// The seed phrases the keys are derived from, which are the BIP39 phrases for the all-zero,
+/// all-`0x7f`, and all-one entropy.
+pub const SEED_PHRASES: [&str; 3] = [
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art",
+    "legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth title",
+    "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+];
+
+/// The index of the spend key derived from each seed phrase.
+pub const SPEND_KEY_INDEX: u64 = 0;
+
+/// The address indices derived from each key.
+pub const ADDRESS_INDICES: [u64; 4] = [0, 1, 255, u64::MAX];
+
+/// The values of the notes sent to each key's address 0, as a base denomination and an amount.
+pub const NOTE_VALUES: [(&str, u64); 3] = [("upenumbra", 0), ("upenumbra", 1_000_000), ("gm", 42)];
+
+/// A complete set of test vectors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TestVectors {
+    pub keys: Vec<KeyVector>,
+    /// The notes sent to each key, in the order they're inserted into the tree.
+    pub notes: Vec<NoteVector>,
+    /// The root of the tree after each note commitment is inserted.
+    pub tree_roots: Vec<TreeRootVector>,
+}
+
+/// The keys and addresses derived from a seed phrase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyVector {
+    pub seed_phrase: String,
+    pub spend_key_index: u64,
+    pub spend_key: String,
+    pub full_viewing_key: String,
+    pub full_viewing_key_hash: String,
+    pub addresses: Vec<AddressVector>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AddressVector {
+    pub index: u64,
+    pub address: String,
+}
+
+/// A note, its commitment, and its nullifier at the position where it's inserted into the tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NoteVector {
+    pub address: String,
+    pub denom: String,
+    pub asset_id: String,
+    pub amount: u64,
+    pub note_blinding: String,
+    pub commitment: String,
+    pub position: u64,
+    pub nullifier: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TreeRootVector {
+    /// The number of commitments inserted into the tree.
+    pub num_commitments: u64,
+    pub root: String,
+}
+
+/// Derive the test vectors from the fixed inputs.
+pub fn generate() -> TestVectors {
+    let mut keys = Vec::new();
+    let mut notes = Vec::new();
+    let mut tree_roots = Vec::new();
+    let mut tree = tct::Tree::new();
+
+    for seed_phrase in SEED_PHRASES {
+        let spend_key = SpendKey::from_seed_phrase(
+            seed_phrase
+                .parse::<SeedPhrase>()
+                .expect("seed phrase is valid"),
+            SPEND_KEY_INDEX,
+        );
+        let fvk = spend_key.full_viewing_key();
+
+        keys.push(KeyVector {
+            seed_phrase: seed_phrase.to_string(),
+            spend_key_index: SPEND_KEY_INDEX,
+            spend_key: spend_key.to_string(),
+            full_viewing_key: fvk.to_string(),
+            full_viewing_key_hash: hex::encode(fvk.hash().0),
+            addresses: ADDRESS_INDICES
+                .iter()
+                .map(|&index| AddressVector {
+                    index,
+                    address: fvk.incoming().payment_address(index.into()).0.to_string(),
+                })
+                .collect(),
+        });
+
+        let (address, _dtk) = fvk.incoming().payment_address(0u64.into());
+        for (denom, amount) in NOTE_VALUES {
+            let denom = asset::REGISTRY
+                .parse_denom(denom)
+                .expect("denomination is valid");
+            // Number the blinding factors in order, so that every note is distinct.
+            let note_blinding = Fq::from(notes.len() as u64 + 1);
+            let note = Note::from_parts(
+                *address.diversifier(),
+                *address.transmission_key(),
+                Value {
+                    amount,
+                    asset_id: denom.id(),
+                },
+                note_blinding,
+            )
+            .expect("transmission key in address is valid");
+
+            let commitment = note.commit();
+            let position = tree
+                .insert(tct::Witness::Keep, commitment)
+                .expect("tree is not full");
+            let nullifier = Nullifier::derive(fvk.nullifier_key(), position, &commitment);
+
+            notes.push(NoteVector {
+                address: address.to_string(),
+                denom: denom.to_string(),
+                asset_id: denom.id().to_string(),
+                amount,
+                note_blinding: hex::encode(note_blinding.to_bytes()),
+                commitment: commitment.to_string(),
+                position: position.into(),
+                nullifier: nullifier.to_string(),
+            });
+            tree_roots.push(TreeRootVector {
+                num_commitments: notes.len() as u64,
+                root: tree.root().to_string(),
+            });
+        }
+    }
+
+    TestVectors {
+        keys,
+        notes,
+        tree_roots,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{keys::DiversifierIndex, Address, FullViewingKey};
+
+    /// Each key is derived from its seed phrase, and each published encoding parses back to the
+    /// key it was derived from.
+    #[test]
+    fn keys_rederive_from_their_encodings() {
+        let vectors = generate();
+        assert_eq!(vectors.keys.len(), SEED_PHRASES.len());
+
+        for key in &vectors.keys {
+            let spend_key: SpendKey = key.spend_key.parse().unwrap();
+            let fvk: FullViewingKey = key.full_viewing_key.parse().unwrap();
+            assert_eq!(
+                spend_key.full_viewing_key().to_string(),
+                key.full_viewing_key
+            );
+            assert_eq!(hex::encode(fvk.hash().0), key.full_viewing_key_hash);
+
+            let from_seed = SpendKey::from_seed_phrase(
+                key.seed_phrase.parse::<SeedPhrase>().unwrap(),
+                key.spend_key_index,
+            );
+            assert_eq!(from_seed.to_string(), key.spend_key);
+
+            for vector in &key.addresses {
+                let address: Address = vector.address.parse().unwrap();
+                assert!(fvk.incoming().views_address(&address));
+                assert_eq!(
+                    fvk.incoming().index_for_diversifier(address.diversifier()),
+                    DiversifierIndex::from(vector.index)
+                );
+            }
+        }
+
+        // Different seeds give different keys.
+        let mut fvks: Vec<_> = vectors.keys.iter().map(|k| &k.full_viewing_key).collect();
+        fvks.sort();
+        fvks.dedup();
+        assert_eq!(fvks.len(), vectors.keys.len());
+    }
+
+    /// Each note's commitment and nullifier can be recomputed from its published fields alone,
+    /// and inserting the commitments in order reproduces each published tree root.
+    #[test]
+    fn notes_and_roots_recompute_from_their_fields() {
+        let vectors = generate();
+        let fvks: HashMap<String, FullViewingKey> = vectors
+            .keys
+            .iter()
+            .flat_map(|key| {
+                let fvk: FullViewingKey = key.full_viewing_key.parse().unwrap();
+                key.addresses
+                    .iter()
+                    .map(move |vector| (vector.address.clone(), fvk.clone()))
+            })
+            .collect();
+
+        let mut tree = tct::Tree::new();
+        for (i, vector) in vectors.notes.iter().enumerate() {
+            let address: Address = vector.address.parse().unwrap();
+            let denom = asset::REGISTRY.parse_denom(&vector.denom).unwrap();
+            assert_eq!(denom.id().to_string(), vector.asset_id);
+
+            let blinding: [u8; 32] = hex::decode(&vector.note_blinding)
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let note = Note::from_parts(
+                *address.diversifier(),
+                *address.transmission_key(),
+                Value {
+                    amount: vector.amount,
+                    asset_id: vector.asset_id.parse().unwrap(),
+                },
+                Fq::from_bytes(blinding).unwrap(),
+            )
+            .unwrap();
+            let commitment = note.commit();
+            assert_eq!(commitment.to_string(), vector.commitment);
+
+            let position = tree.insert(tct::Witness::Keep, commitment).unwrap();
+            assert_eq!(u64::from(position), vector.position);
+            assert_eq!(tree.root().to_string(), vectors.tree_roots[i].root);
+            assert_eq!(vectors.tree_roots[i].num_commitments, i as u64 + 1);
+
+            // The nullifier is derived with the key of the note's recipient.
+            let fvk = &fvks[&vector.address];
+            let nullifier = Nullifier::derive(fvk.nullifier_key(), position, &commitment);
+            assert_eq!(nullifier.to_string(), vector.nullifier);
+            assert_eq!(Nullifier::parse_hex(&vector.nullifier).unwrap(), nullifier);
+        }
+        assert_eq!(vectors.tree_roots.len(), vectors.notes.len());
+    }
+
+    #[test]
+    fn vectors_are_distinct() {
+        let vectors = generate();
+
+        let mut commitments: Vec<_> = vectors.notes.iter().map(|n| &n.commitment).collect();
+        commitments.sort();
+        commitments.dedup();
+        assert_eq!(commitments.len(), vectors.notes.len());
+
+        let mut nullifiers: Vec<_> = vectors.notes.iter().map(|n| &n.nullifier).collect();
+        nullifiers.sort();
+        nullifiers.dedup();
+        assert_eq!(nullifiers.len(), vectors.notes.len());
+
+        let mut roots: Vec<_> = vectors.tree_roots.iter().map(|r| &r.root).collect();
+        roots.sort();
+        roots.dedup();
+        assert_eq!(roots.len(), vectors.tree_roots.len());
+    }
+}
@@ -1,4 +1,22 @@
-//! Values (?)
+//! Values, and the Pedersen commitments to them used to check that transactions balance.
+//!
+//! A value `v` of asset `a` is committed to as `C = v * G_a + r * H`, where `G_a` is the
+//! [value generator](asset::Id::value_generator) for the asset, `H` is the
+//! [`VALUE_BLINDING_GENERATOR`], and `r` is a random blinding factor. Commitments are additively
+//! homomorphic, so the sum of a transaction's value commitments (spends positive, outputs
+//! negative, less the transparent fee) is a commitment to zero exactly when the transaction
+//! balances, with a *synthetic blinding factor* equal to the same signed sum of the blinding
+//! factors.
+//!
+//! The commitment to zero is `r * H`, which is a `decaf377-rdsa` binding verification key whose
+//! signing key is the synthetic blinding factor: the transaction's binding signature proves
+//! knowledge of it, and hence that the transaction balances. The functions here let this check be
+//! recomputed independently of transaction building:
+//!
+//! - [`Value::commit`] constructs a commitment;
+//! - [`synthetic_blinding_factor`] combines blinding factors;
+//! - [`balance_commitment`] combines commitments, accounting for the fee;
+//! - [`Commitment::is_balanced_with`] and [`Commitment::binding_verification_key`] check the result.
 
 use std::{
     convert::{TryFrom, TryInto},
@@ -13,7 +31,7 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use thiserror;
 
-use crate::{asset, Fq, Fr};
+use crate::{asset, rdsa, Fq, Fr, Zero};
 
 #[derive(Deserialize, Serialize, Copy, Clone, Debug, PartialEq, Eq)]
 #[serde(try_from = "pb::Value", into = "pb::Value")]
@@ -52,8 +70,30 @@ impl Commitment {
     pub fn to_bytes(&self) -> [u8; 32] {
         (*self).into()
     }
+
+    /// Check whether this is a commitment to zero value with the given blinding factor.
+    ///
+    /// For the [`balance_commitment`] of a transaction and its [`synthetic_blinding_factor`], this
+    /// is true exactly when the transaction balances.
+    pub fn is_balanced_with(&self, synthetic_blinding_factor: Fr) -> bool {
+        self.0 == synthetic_blinding_factor * VALUE_BLINDING_GENERATOR.deref()
+    }
+
+    /// Interpret this commitment as a binding verification key.
+    ///
+    /// When this is the [`balance_commitment`] of a transaction, its binding signature verifies
+    /// under this key exactly when it was signed with the [`synthetic_blinding_factor`] and the
+    /// transaction balances.
+    pub fn binding_verification_key(&self) -> rdsa::VerificationKey<rdsa::Binding> {
+        let bytes: rdsa::VerificationKeyBytes<rdsa::Binding> = self.to_bytes().into();
+        bytes
+            .try_into()
+            .expect("every encoded element is a valid verification key")
+    }
 }
 
+/// The generator used for the blinding factor of value commitments, and as the basepoint of
+/// binding signatures.
 pub static VALUE_BLINDING_GENERATOR: Lazy<decaf377::Element> = Lazy::new(|| {
     let s = Fq::from_le_bytes_mod_order(blake2b_simd::blake2b(b"decaf377-rdsa-binding").as_bytes());
     decaf377::Element::map_to_group_cdh(&s)
@@ -65,7 +105,39 @@ pub enum Error {
     InvalidValueCommitment,
 }
 
+/// Compute the synthetic blinding factor of a transaction from the blinding factors of the value
+/// commitments it adds to its balance (spends) and subtracts from it (outputs).
+///
+/// This is the signing key for the transaction's binding signature.
+pub fn synthetic_blinding_factor(
+    added: impl IntoIterator<Item = Fr>,
+    subtracted: impl IntoIterator<Item = Fr>,
+) -> Fr {
+    let added = added.into_iter().fold(Fr::zero(), |sum, b| sum + b);
+    subtracted.into_iter().fold(added, |sum, b| sum - b)
+}
+
+/// Compute the balance commitment of a transaction from the value commitments of its actions and
+/// its fee.
+///
+/// Action value commitments are signed already: a spend commits to its note's value, and an output
+/// to the negation of its note's value. The fee is transparent, so it's committed to with a zero
+/// blinding factor and subtracted.
+pub fn balance_commitment(
+    action_commitments: impl IntoIterator<Item = Commitment>,
+    fee: Value,
+) -> Commitment {
+    let actions = action_commitments
+        .into_iter()
+        .fold(Commitment::default(), |sum, c| sum + c);
+    actions - fee.commit(Fr::zero())
+}
+
 impl Value {
+    /// Commit to this value with the given blinding factor.
+    ///
+    /// The commitment is `amount * G + blinding * H`, where `G` is the value generator for this
+    /// value's asset and `H` is the [`VALUE_BLINDING_GENERATOR`].
     #[allow(non_snake_case)]
     pub fn commit(&self, blinding: Fr) -> Commitment {
         let G_v = self.asset_id.value_generator();
@@ -238,6 +310,75 @@ mod tests {
         assert_eq!(c0.0, b0 * VALUE_BLINDING_GENERATOR.deref());
     }
 
+    #[test]
+    fn commitment_test_vectors() {
+        let pen_id = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let blinding = Fr::from(1729u64);
+
+        // A commitment to zero is just the blinding term...
+        let zero = Value {
+            amount: 0,
+            asset_id: pen_id,
+        };
+        assert_eq!(
+            zero.commit(blinding).0,
+            blinding * VALUE_BLINDING_GENERATOR.deref()
+        );
+        assert!(zero.commit(blinding).is_balanced_with(blinding));
+
+        // ...and a commitment with a zero blinding factor is just the value term.
+        let v = Value {
+            amount: 1000,
+            asset_id: pen_id,
+        };
+        assert_eq!(
+            v.commit(Fr::zero()).0,
+            Fr::from(1000u64) * pen_id.value_generator()
+        );
+
+        // Commitments round-trip through their encoding.
+        let c = v.commit(blinding);
+        assert_eq!(Commitment::try_from(c.to_bytes()).unwrap(), c);
+    }
+
+    #[test]
+    fn balance_check() {
+        use rand_core::OsRng;
+
+        let pen_id = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let value = |amount| Value {
+            amount,
+            asset_id: pen_id,
+        };
+
+        // Spend a note of 10, output notes of 7 and 2, and pay a fee of 1.
+        let (b_spend, b_out1, b_out2) = (Fr::from(11u64), Fr::from(13u64), Fr::from(17u64));
+        let commitments = [
+            value(10).commit(b_spend),
+            -value(7).commit(b_out1),
+            -value(2).commit(b_out2),
+        ];
+        let synthetic = synthetic_blinding_factor([b_spend], [b_out1, b_out2]);
+        assert_eq!(synthetic, b_spend - b_out1 - b_out2);
+
+        let balance = balance_commitment(commitments, value(1));
+        assert!(balance.is_balanced_with(synthetic));
+
+        // A binding signature with the synthetic blinding factor verifies under the balance
+        // commitment.
+        let msg = b"transaction auth hash";
+        let sig = rdsa::SigningKey::<rdsa::Binding>::from(synthetic).sign(OsRng, msg);
+        assert!(balance.binding_verification_key().verify(msg, &sig).is_ok());
+
+        // Paying too small a fee leaves the transaction unbalanced, so neither check passes.
+        let unbalanced = balance_commitment(commitments, value(0));
+        assert!(!unbalanced.is_balanced_with(synthetic));
+        assert!(unbalanced
+            .binding_verification_key()
+            .verify(msg, &sig)
+            .is_err());
+    }
+
     #[test]
     fn value_parsing_happy() {
         let upenumbra_base_denom = asset::REGISTRY.parse_denom("upenumbra").unwrap();
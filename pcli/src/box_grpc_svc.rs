@@ -17,9 +17,24 @@ pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
 pub(crate) type RspBody = UnsyncBoxBody<Bytes, BoxError>;
 
 /// Connects to the provided tonic [`Endpoint`], returning a [`BoxGrpcService`].
-pub(crate) async fn connect(ep: Endpoint) -> anyhow::Result<BoxGrpcService> {
+///
+/// If an `auth_token` is provided, it is sent as a bearer token with every request.
+pub(crate) async fn connect(
+    ep: Endpoint,
+    auth_token: Option<&str>,
+) -> anyhow::Result<BoxGrpcService> {
+    let authorization = auth_token
+        .map(|token| grpc::HeaderValue::try_from(format!("Bearer {}", token)))
+        .transpose()?;
     let conn = ep.connect().await?;
     let svc = ServiceBuilder::new()
+        .map_request(move |mut req: grpc::Request<ReqBody>| {
+            if let Some(authorization) = &authorization {
+                req.headers_mut()
+                    .insert(grpc::header::AUTHORIZATION, authorization.clone());
+            }
+            req
+        })
         .map_response(|rsp: grpc::Response<transport::Body>| rsp.map(box_rsp_body))
         .map_err(BoxError::from)
         .service(conn);
@@ -1,28 +1,39 @@
 mod addr;
 mod balance;
 mod chain;
+mod daemon;
+mod init;
 mod query;
 mod stake;
 mod tx;
 mod validator;
+mod view;
 mod wallet;
 
 pub use addr::AddrCmd;
 pub use balance::BalanceCmd;
 pub use chain::ChainCmd;
+pub use daemon::DaemonCmd;
+pub use init::InitCmd;
 pub use query::QueryCmd;
 pub use stake::StakeCmd;
 pub use tx::TxCmd;
 pub use validator::ValidatorCmd;
+pub use view::ViewCmd;
 pub use wallet::WalletCmd;
 
 #[derive(Debug, clap::Subcommand)]
 pub enum Command {
+    /// Sets up `pcli` interactively.
+    ///
+    /// Walks through generating or importing a seed phrase, choosing a node
+    /// and checking the connection to it, and then performs the initial sync.
+    Init(InitCmd),
     /// Creates a transaction.
     #[clap(subcommand)]
     Tx(TxCmd),
     /// Manages the wallet state.
-    #[clap(subcommand)]
+    #[clap(subcommand, visible_alias = "keys")]
     Wallet(WalletCmd),
     /// Manages addresses.
     #[clap(subcommand)]
@@ -50,12 +61,21 @@ pub enum Command {
     /// View chain data.
     #[clap(subcommand)]
     Chain(ChainCmd),
+    /// Watches the wallet's view of the chain.
+    #[clap(subcommand)]
+    View(ViewCmd),
+    /// Runs in the background, serving a local JSON-RPC API for integrations.
+    ///
+    /// Keeps the view service synced, and serves `status`, `balance`, `new_address`, and `send`
+    /// methods over HTTP to clients presenting the daemon's token.
+    Daemon(DaemonCmd),
 }
 
 impl Command {
     /// Determine if this command requires a network sync before it executes.
     pub fn needs_sync(&self) -> bool {
         match self {
+            Command::Init(cmd) => cmd.needs_sync(),
             Command::Tx(cmd) => cmd.needs_sync(),
             Command::Wallet(cmd) => cmd.needs_sync(),
             Command::Addr(cmd) => cmd.needs_sync(),
@@ -64,7 +84,9 @@ impl Command {
             Command::Validator(cmd) => cmd.needs_sync(),
             Command::Stake(cmd) => cmd.needs_sync(),
             Command::Chain(cmd) => cmd.needs_sync(),
+            Command::View(cmd) => cmd.needs_sync(),
             Command::Q(_) => false,
+            Command::Daemon(cmd) => cmd.needs_sync(),
         }
     }
 }
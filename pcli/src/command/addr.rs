@@ -1,6 +1,9 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use comfy_table::{presets, Table};
-use penumbra_crypto::FullViewingKey;
+use penumbra_crypto::{keys::DiversifierIndex, Address};
+use penumbra_view::Storage;
+
+use crate::App;
 
 #[derive(Debug, clap::Subcommand)]
 pub enum AddrCmd {
@@ -9,11 +12,35 @@ pub enum AddrCmd {
         /// The index of the address to show.
         /// Default to 0
         #[clap(default_value = "0")]
-        index: u64,
+        index: DiversifierIndex,
         /// If true, emits only the address and not the (local) label for it.
         #[clap(short, long)]
         addr_only: bool,
     },
+    /// Adds someone else's address to the address book, so that it can be sent to by its label,
+    /// e.g., with `pcli tx send --to <LABEL>`.
+    Add {
+        /// The label to refer to the address by.
+        label: String,
+        /// The address.
+        address: Address,
+    },
+    /// Lists the addresses in the address book.
+    List,
+    /// Names the address with the given index, so that its balance is shown under the name.
+    ///
+    /// Several addresses can be given the same name, to treat them as one account.
+    Label {
+        /// The index of the address to name.
+        index: DiversifierIndex,
+        /// The name, or nothing to remove the address's name.
+        label: Option<String>,
+    },
+    /// Removes an address from the address book.
+    Remove {
+        /// The label of the address to remove.
+        label: String,
+    },
 }
 
 impl AddrCmd {
@@ -21,25 +48,68 @@ impl AddrCmd {
     pub fn needs_sync(&self) -> bool {
         match self {
             AddrCmd::Show { .. } => false,
+            AddrCmd::Add { .. } => false,
+            AddrCmd::List => false,
+            AddrCmd::Label { .. } => false,
+            AddrCmd::Remove { .. } => false,
         }
     }
 
-    pub fn exec(&self, fvk: &FullViewingKey) -> Result<()> {
+    pub async fn exec(&self, app: &App) -> Result<()> {
         // Set up table (this won't be used with `show --addr-only`)
         let mut table = Table::new();
         table.load_preset(presets::NOTHING);
-        table.set_header(vec!["Index", "Address"]);
 
         match self {
             AddrCmd::Show { index, addr_only } => {
-                let (address, _dtk) = fvk.incoming().payment_address((*index).into());
+                let (address, _dtk) = app.fvk.incoming().payment_address(*index);
 
                 if *addr_only {
                     println!("{}", address);
                     return Ok(()); // don't print the label
                 } else {
-                    table.add_row(vec![index.to_string(), address.to_string()]);
+                    let label = app.address_labels().await?.remove(index);
+                    table.set_header(vec!["Index", "Label", "Address"]);
+                    table.add_row(vec![
+                        index.to_string(),
+                        label.unwrap_or_default(),
+                        address.to_string(),
+                    ]);
+                }
+            }
+            AddrCmd::Add { label, address } => {
+                if app.fvk.incoming().views_address(address) {
+                    return Err(anyhow!(
+                        "{} is one of this wallet's own addresses; use `pcli tx transfer` to move funds between them",
+                        address
+                    ));
+                }
+                address_book(app)?.record_contact(label, address).await?;
+                println!("Added {:?} to the address book", label);
+                return Ok(());
+            }
+            AddrCmd::List => {
+                table.set_header(vec!["Label", "Address"]);
+                for (label, address) in address_book(app)?.contacts().await? {
+                    table.add_row(vec![label, address.to_string()]);
+                }
+            }
+            AddrCmd::Label { index, label } => {
+                app.view_storage()?
+                    .set_label(0, *index, label.as_deref())
+                    .await?;
+                match label {
+                    Some(label) => println!("Labeled address {} {:?}", index, label),
+                    None => println!("Removed the label of address {}", index),
+                }
+                return Ok(());
+            }
+            AddrCmd::Remove { label } => {
+                if !address_book(app)?.remove_contact(label).await? {
+                    return Err(anyhow!("there is no contact labeled {:?}", label));
                 }
+                println!("Removed {:?} from the address book", label);
+                return Ok(());
             }
         }
 
@@ -49,3 +119,27 @@ impl AddrCmd {
         Ok(())
     }
 }
+
+/// Parse `to` as an address, or else look it up in the address book by its label.
+pub async fn resolve_address(app: &App, to: &str) -> Result<Address> {
+    if let Ok(address) = to.parse() {
+        return Ok(address);
+    }
+
+    address_book(app)?
+        .contacts()
+        .await?
+        .remove(to)
+        .ok_or_else(|| {
+            anyhow!(
+                "{:?} is neither a valid address nor the label of a contact in the address book",
+                to
+            )
+        })
+}
+
+/// The view data the address book is kept in, which must be local.
+fn address_book(app: &App) -> Result<&Storage> {
+    app.view_storage()
+        .context("the address book is kept in the local view data")
+}
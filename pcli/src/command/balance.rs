@@ -1,9 +1,13 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::Result;
 use comfy_table::{presets, Table};
-use penumbra_crypto::{keys::DiversifierIndex, FullViewingKey, Value};
-use penumbra_view::ViewClient;
+use penumbra_chain::Epoch;
+use penumbra_crypto::{asset, keys::DiversifierIndex, Amount, FullViewingKey, Value};
+use penumbra_view::{QuarantinedNoteRecord, ViewClient};
+
+use crate::message::Message;
+
 #[derive(Debug, clap::Args)]
 pub struct BalanceCmd {
     /// If set, breaks down balances by address.
@@ -22,29 +26,39 @@ impl BalanceCmd {
         !self.offline
     }
 
-    pub async fn exec<V: ViewClient>(&self, fvk: &FullViewingKey, view: &mut V) -> Result<()> {
+    /// Print the balance, showing each address index under its label in `labels`, if it has one,
+    /// and summing the balances of labeled indices under the index `groups` groups them under.
+    pub async fn exec<V: ViewClient>(
+        &self,
+        fvk: &FullViewingKey,
+        view: &mut V,
+        labels: &BTreeMap<DiversifierIndex, String>,
+        groups: &BTreeMap<DiversifierIndex, DiversifierIndex>,
+    ) -> Result<()> {
+        let names = AddressNames { labels, groups };
         let asset_cache = view.assets().await?;
+        let epoch_duration = view.chain_params().await?.epoch_duration;
 
         // Initialize the table
         let mut table = Table::new();
         table.load_preset(presets::NOTHING);
 
         if self.by_address {
-            let notes = view.unspent_notes_by_address_and_asset(fvk.hash()).await?;
             let quarantined_notes = view
                 .quarantined_notes_by_address_and_asset(fvk.hash())
                 .await?;
 
-            // `Option<u64>` indicates the unbonding epoch, if any, for a quarantined note
-            let rows: Vec<(DiversifierIndex, Value, Option<u64>)> = if self.by_note {
-                notes
+            if self.by_note {
+                let notes = view.unspent_notes_by_address_and_asset(fvk.hash()).await?;
+                // `Option<u64>` indicates the unbonding epoch, if any, for a quarantined note
+                let rows: Vec<(DiversifierIndex, Value, Option<u64>)> = notes
                     .iter()
                     .flat_map(|(index, notes_by_asset)| {
                         // Include each note individually:
                         notes_by_asset.iter().flat_map(|(asset, notes)| {
-                            notes
-                                .iter()
-                                .map(|record| (*index, asset.value(record.note.amount()), None))
+                            notes.iter().map(|record| {
+                                (*index, asset.value(record.note.amount().into()), None)
+                            })
                         })
                     })
                     .chain(
@@ -56,78 +70,101 @@ impl BalanceCmd {
                                     notes.iter().map(|record| {
                                         (
                                             *index,
-                                            asset.value(record.note.amount()),
+                                            asset.value(record.note.amount().into()),
                                             Some(record.unbonding_epoch),
                                         )
                                     })
                                 })
                             }),
                     )
-                    .collect()
-            } else {
-                notes
-                    .iter()
-                    .flat_map(|(index, notes_by_asset)| {
-                        // Sum the notes for each asset:
-                        notes_by_asset.iter().map(|(asset, notes)| {
-                            let sum = notes.iter().map(|record| record.note.amount()).sum();
-                            (*index, asset.value(sum), None)
-                        })
-                    })
-                    .chain(
-                        quarantined_notes
-                            .iter()
-                            .flat_map(|(index, notes_by_asset)| {
-                                // Sum the notes for each asset, separating them by unbonding epoch:
-                                notes_by_asset.iter().flat_map(|(asset, records)| {
-                                    let mut sums_by_unbonding_epoch = BTreeMap::<u64, u64>::new();
-                                    for record in records {
-                                        let unbonding_epoch = record.unbonding_epoch;
-                                        *sums_by_unbonding_epoch
-                                            .entry(unbonding_epoch)
-                                            .or_default() += record.note.amount();
+                    .collect();
+
+                table.set_header(vec![Message::AddressIndex, Message::Amount]);
+                for (index, value, quarantined) in rows {
+                    table.add_row(vec![
+                        names.name(index),
+                        format!(
+                            "{}{}",
+                            value.try_format(&asset_cache).unwrap(),
+                            if let Some(unbonding_epoch) = quarantined {
+                                format!(
+                                    " ({})",
+                                    Message::UnbondingUntil {
+                                        epoch: unbonding_epoch
                                     }
-                                    sums_by_unbonding_epoch.into_iter().map(
-                                        |(unbonding_epoch, sum)| {
-                                            (*index, asset.value(sum), Some(unbonding_epoch))
-                                        },
-                                    )
-                                })
-                            }),
-                    )
-                    .collect()
-            };
-
-            table.set_header(vec!["Addr Index", "Amount"]);
-            for (index, value, quarantined) in rows {
-                table.add_row(vec![
-                    format!("{}", u128::from(index)),
-                    format!(
-                        "{}{}",
-                        value.try_format(&asset_cache).unwrap(),
-                        if let Some(unbonding_epoch) = quarantined {
-                            format!(" (unbonding until epoch {})", unbonding_epoch)
-                        } else {
-                            "".to_string()
+                                )
+                            } else {
+                                "".to_string()
+                            }
+                        ),
+                    ]);
+                }
+            } else {
+                // The view service sums the spendable notes for every address and asset at
+                // once, so only the locked notes are summed here:
+                let mut balances = BTreeMap::<(DiversifierIndex, asset::Id), Balance>::new();
+                for (index, amounts) in view.balances_by_index(fvk.hash()).await? {
+                    for (asset, amount) in amounts {
+                        balances
+                            .entry((names.group(index), asset))
+                            .or_default()
+                            .add_spendable(amount.into())?;
+                    }
+                }
+                for (index, notes_by_asset) in &quarantined_notes {
+                    for (asset, records) in notes_by_asset {
+                        let balance = balances.entry((names.group(*index), *asset)).or_default();
+                        for record in records {
+                            balance.add_quarantined(record)?;
                         }
-                    ),
+                    }
+                }
+
+                table.set_header(vec![
+                    Message::AddressIndex,
+                    Message::Amount,
+                    Message::Locked,
+                    Message::Released,
                 ]);
+                // Total each asset across addresses, if there's more than one:
+                let mut totals = BTreeMap::<asset::Id, Balance>::new();
+                for ((_index, asset), balance) in &balances {
+                    totals.entry(*asset).or_default().add(balance)?;
+                }
+                let addresses = balances
+                    .keys()
+                    .map(|(index, _asset)| *index)
+                    .collect::<BTreeSet<_>>()
+                    .len();
+
+                for ((index, asset), balance) in balances {
+                    let mut row = vec![names.name(index)];
+                    row.extend(balance.cells(asset, &asset_cache, epoch_duration));
+                    table.add_row(row);
+                }
+                if addresses > 1 {
+                    for (asset, total) in totals {
+                        let mut row = vec![Message::Total.to_string()];
+                        row.extend(total.cells(asset, &asset_cache, epoch_duration));
+                        table.add_row(row);
+                    }
+                }
             }
         } else {
-            let notes = view.unspent_notes_by_asset_and_address(fvk.hash()).await?;
             let quarantined_notes = view
                 .quarantined_notes_by_asset_and_address(fvk.hash())
                 .await?;
 
-            let rows: Vec<(Value, Option<u64>)> = if self.by_note {
-                notes
+            if self.by_note {
+                let notes = view.unspent_notes_by_asset_and_address(fvk.hash()).await?;
+                let rows: Vec<(Value, Option<u64>)> = notes
                     .iter()
                     .flat_map(|(asset, notes)| {
                         // Include each note individually:
                         notes.iter().flat_map(|(_index, notes)| {
                             notes
                                 .iter()
-                                .map(|record| (asset.value(record.note.amount()), None))
+                                .map(|record| (asset.value(record.note.amount().into()), None))
                         })
                     })
                     .chain(quarantined_notes.iter().flat_map(|(asset, notes)| {
@@ -135,51 +172,52 @@ impl BalanceCmd {
                         notes.iter().flat_map(|(_index, notes)| {
                             notes.iter().map(|record| {
                                 (
-                                    asset.value(record.note.amount()),
+                                    asset.value(record.note.amount().into()),
                                     Some(record.unbonding_epoch),
                                 )
                             })
                         })
                     }))
-                    .collect()
-            } else {
-                notes
-                    .iter()
-                    .map(|(asset, notes)| {
-                        // Sum the notes for each index:
-                        let sum = notes
-                            .values()
-                            .flat_map(|records| records.iter().map(|record| record.note.amount()))
-                            .sum();
-                        (asset.value(sum), None)
-                    })
-                    .chain(quarantined_notes.iter().flat_map(|(asset, records)| {
-                        // Sum the notes for each index, separating them by unbonding epoch:
-                        let mut sums_by_unbonding_epoch = BTreeMap::<u64, u64>::new();
-                        for records in records.values() {
-                            for record in records {
-                                let unbonding_epoch = record.unbonding_epoch;
-                                *sums_by_unbonding_epoch.entry(unbonding_epoch).or_default() +=
-                                    record.note.amount();
-                            }
+                    .collect();
+
+                table.set_header(vec![Message::Amount]);
+                for (value, quarantined) in rows {
+                    table.add_row(vec![format!(
+                        "{}{}",
+                        value.try_format(&asset_cache).unwrap(),
+                        if let Some(unbonding_epoch) = quarantined {
+                            format!(
+                                " ({})",
+                                Message::UnbondingUntil {
+                                    epoch: unbonding_epoch
+                                }
+                            )
+                        } else {
+                            "".to_string()
                         }
-                        sums_by_unbonding_epoch
-                            .into_iter()
-                            .map(|(unbonding_epoch, sum)| (asset.value(sum), Some(unbonding_epoch)))
-                    }))
-                    .collect()
-            };
-            table.set_header(vec!["Amount"]);
-            for (value, quarantined) in rows {
-                table.add_row(vec![format!(
-                    "{}{}",
-                    value.try_format(&asset_cache).unwrap(),
-                    if let Some(unbonding_epoch) = quarantined {
-                        format!(" (unbonding until epoch {})", unbonding_epoch)
-                    } else {
-                        "".to_string()
+                    )]);
+                }
+            } else {
+                // The view service sums the spendable notes for each asset, across all addresses,
+                // so only the locked notes are summed here:
+                let mut balances = BTreeMap::<asset::Id, Balance>::new();
+                for (asset, amount) in view.balances(fvk.hash(), None).await? {
+                    balances
+                        .entry(asset)
+                        .or_default()
+                        .add_spendable(amount.into())?;
+                }
+                for (asset, notes) in &quarantined_notes {
+                    let balance = balances.entry(*asset).or_default();
+                    for record in notes.values().flatten() {
+                        balance.add_quarantined(record)?;
                     }
-                )]);
+                }
+
+                table.set_header(vec![Message::Amount, Message::Locked, Message::Released]);
+                for (asset, balance) in balances {
+                    table.add_row(balance.cells(asset, &asset_cache, epoch_duration));
+                }
             }
         }
 
@@ -188,3 +226,106 @@ impl BalanceCmd {
         Ok(())
     }
 }
+
+/// The names address indices are shown under: their labels, if they have them, and otherwise
+/// their indices.
+struct AddressNames<'a> {
+    labels: &'a BTreeMap<DiversifierIndex, String>,
+    /// The index the balances of each labeled index are grouped under, from
+    /// `Storage::label_groups`, so that the indices sharing a label are shown as one account.
+    groups: &'a BTreeMap<DiversifierIndex, DiversifierIndex>,
+}
+
+impl<'a> AddressNames<'a> {
+    /// The index the balance of `index` is grouped under.
+    fn group(&self, index: DiversifierIndex) -> DiversifierIndex {
+        self.groups.get(&index).copied().unwrap_or(index)
+    }
+
+    fn name(&self, index: DiversifierIndex) -> String {
+        self.labels
+            .get(&index)
+            .cloned()
+            .unwrap_or_else(|| index.to_string())
+    }
+}
+
+/// The balance of an asset, split into spendable funds and funds locked in quarantine while the
+/// validator they were delegated to unbonds.
+#[derive(Debug, Default)]
+struct Balance {
+    spendable: Amount,
+    locked: Amount,
+    /// The earliest epoch at the end of which some of the locked funds are released.
+    earliest_release: Option<u64>,
+}
+
+impl Balance {
+    fn add_spendable(&mut self, amount: Amount) -> Result<()> {
+        self.spendable = self
+            .spendable
+            .checked_add(amount)
+            .ok_or_else(|| anyhow::anyhow!("spendable balance overflowed"))?;
+        Ok(())
+    }
+
+    fn add_quarantined(&mut self, record: &QuarantinedNoteRecord) -> Result<()> {
+        self.locked = self
+            .locked
+            .checked_add(record.note.amount())
+            .ok_or_else(|| anyhow::anyhow!("locked balance overflowed"))?;
+        self.earliest_release = Some(
+            self.earliest_release
+                .map_or(record.unbonding_epoch, |epoch| {
+                    epoch.min(record.unbonding_epoch)
+                }),
+        );
+        Ok(())
+    }
+
+    fn add(&mut self, other: &Balance) -> Result<()> {
+        self.add_spendable(other.spendable)?;
+        self.locked = self
+            .locked
+            .checked_add(other.locked)
+            .ok_or_else(|| anyhow::anyhow!("locked balance overflowed"))?;
+        self.earliest_release = match (self.earliest_release, other.earliest_release) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Ok(())
+    }
+
+    /// The table cells for this balance: the spendable amount, the locked amount, and when the
+    /// first locked funds are released.
+    fn cells(
+        &self,
+        asset: asset::Id,
+        asset_cache: &asset::Cache,
+        epoch_duration: u64,
+    ) -> Vec<String> {
+        let format = |amount: Amount| asset.value(amount.into()).try_format(asset_cache).unwrap();
+
+        match self.earliest_release {
+            Some(index) => {
+                // Quarantined notes are released at the end of their unbonding epoch
+                let release_height = Epoch {
+                    index,
+                    duration: epoch_duration,
+                }
+                .end_height();
+
+                vec![
+                    format(self.spendable),
+                    format(self.locked),
+                    Message::ReleasedAt {
+                        epoch: index,
+                        height: release_height.value(),
+                    }
+                    .to_string(),
+                ]
+            }
+            None => vec![format(self.spendable), String::new(), String::new()],
+        }
+    }
+}
@@ -89,6 +89,40 @@ impl ChainCmd {
             .add_row(vec![
                 "Outbound ICS-20 Enabled",
                 &format!("{}", params.outbound_ics20_transfers_enabled),
+            ])
+            .add_row(vec![
+                "Disallowed Actions",
+                &params.disallowed_actions.join(", "),
+            ])
+            .add_row(vec!["Allowed Actions", &params.allowed_actions.join(", ")])
+            .add_row(vec![
+                "Min Validator Commission (bps)",
+                &format!("{}", params.min_validator_commission_bps),
+            ])
+            .add_row(vec![
+                "Max Validator Commission (bps)",
+                &params
+                    .max_validator_commission_bps
+                    .map_or_else(|| "none".to_string(), |bps| bps.to_string()),
+            ])
+            .add_row(vec![
+                "Max Validator Commission Change per Epoch (bps)",
+                &params
+                    .max_validator_commission_change_bps
+                    .map_or_else(|| "none".to_string(), |bps| bps.to_string()),
+            ])
+            .add_row(vec![
+                "Anchor Window (blocks)",
+                &params
+                    .anchor_window_len
+                    .map_or_else(|| "none".to_string(), |len| len.to_string()),
+            ])
+            .add_row(vec!["Min Fee", &format!("{}", params.min_fee)])
+            .add_row(vec![
+                "Max Transaction Size (bytes)",
+                &params
+                    .max_transaction_bytes
+                    .map_or_else(|| "none".to_string(), |len| len.to_string()),
             ]);
 
         println!("{}", table);
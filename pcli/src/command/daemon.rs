@@ -0,0 +1,337 @@
+use std::{convert::Infallible, future::Future, net::SocketAddr, rc::Rc};
+
+use anyhow::Result;
+use http_body::Limited;
+use hyper::{
+    header, server::conn::Http, service::service_fn, Body, Method, Request, Response, StatusCode,
+};
+use penumbra_crypto::{Amount, Value};
+use penumbra_proto::Protobuf;
+use penumbra_view::{SelectionStrategy, ViewClient};
+use penumbra_wallet::plan;
+use rand_core::OsRng;
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use sha2::{Digest, Sha256};
+use tokio::{net::TcpListener, sync::Mutex};
+
+use crate::App;
+
+/// The largest request body the daemon will read, in bytes.
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+/// Serves a JSON-RPC API over HTTP on localhost, for integrations which can't use the Rust crates
+/// or the view protocol directly.
+///
+/// Clients `POST` JSON-RPC 2.0 requests to `/`, sending the daemon's token as a bearer token in
+/// the `Authorization` header. Amounts are passed and returned as strings, so that clients which
+/// parse JSON numbers as floats can't lose precision. The methods are:
+///
+/// - `status`: the view service's sync height, and whether it's catching up;
+/// - `balance`: the wallet's spendable balance of each asset;
+/// - `new_address`: the address with the given `index`;
+/// - `send`: sends `values` to the address `to`, returning the transaction hash.
+#[derive(Debug, clap::Args)]
+pub struct DaemonCmd {
+    /// The address to serve JSON-RPC on. Must be a loopback address.
+    #[clap(long, default_value = "127.0.0.1:8082")]
+    pub bind: SocketAddr,
+    /// The token clients must present as a bearer token.
+    #[clap(long, env = "PCLI_DAEMON_TOKEN", hide_env_values = true)]
+    pub token: String,
+}
+
+impl DaemonCmd {
+    pub fn needs_sync(&self) -> bool {
+        true
+    }
+
+    pub async fn exec(&self, app: App) -> Result<()> {
+        // The daemon can spend funds, so it's only ever served to local clients.
+        if !self.bind.ip().is_loopback() {
+            return Err(anyhow::anyhow!(
+                "refusing to serve on {}, which is not a loopback address",
+                self.bind
+            ));
+        }
+
+        let listener = TcpListener::bind(self.bind).await?;
+        println!("serving JSON-RPC on http://{}", self.bind);
+
+        let daemon = Rc::new(Daemon {
+            app: Mutex::new(app),
+            token: self.token.clone(),
+        });
+
+        // The view client's futures aren't `Send`, so connections are served on the current
+        // thread, sharing the app behind a lock.
+        tokio::task::LocalSet::new()
+            .run_until(serve(listener, daemon))
+            .await
+    }
+}
+
+async fn serve(listener: TcpListener, daemon: Rc<Daemon>) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let daemon = daemon.clone();
+        tokio::task::spawn_local(async move {
+            let service = service_fn(move |req| {
+                let daemon = daemon.clone();
+                async move { daemon.handle(req).await }
+            });
+            if let Err(e) = Http::new()
+                .http1_only(true)
+                .with_executor(LocalExec)
+                .serve_connection(stream, service)
+                .await
+            {
+                tracing::warn!(?e, %peer, "error serving daemon connection");
+            }
+        });
+    }
+}
+
+/// Spawns the tasks hyper needs onto the current [`tokio::task::LocalSet`].
+#[derive(Clone, Copy)]
+struct LocalExec;
+
+impl<F> hyper::rt::Executor<F> for LocalExec
+where
+    F: Future + 'static,
+{
+    fn execute(&self, fut: F) {
+        tokio::task::spawn_local(fut);
+    }
+}
+
+struct Daemon {
+    // Requests are handled one at a time, so that concurrent sends don't race to select notes.
+    app: Mutex<App>,
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Json,
+    method: String,
+    #[serde(default)]
+    params: Json,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn parse_error(e: impl std::fmt::Display) -> Self {
+        Self {
+            code: -32700,
+            message: format!("parse error: {}", e),
+        }
+    }
+
+    fn method_not_found(method: &str) -> Self {
+        Self {
+            code: -32601,
+            message: format!("method not found: {}", method),
+        }
+    }
+
+    fn invalid_params(e: impl std::fmt::Display) -> Self {
+        Self {
+            code: -32602,
+            message: format!("invalid params: {}", e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        Self {
+            code: -32000,
+            message: format!("{:#}", e),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NewAddressParams {
+    index: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SendParams {
+    to: String,
+    values: Vec<String>,
+    fee: Option<String>,
+    source: Option<u64>,
+    memo: Option<String>,
+    #[serde(default)]
+    single_account: bool,
+    #[serde(default = "include_return_address_default")]
+    include_return_address: bool,
+}
+
+/// As with `pcli tx send`, memos carry a return address unless asked not to.
+fn include_return_address_default() -> bool {
+    true
+}
+
+impl Daemon {
+    async fn handle(&self, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        if req.method() != Method::POST {
+            return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+        }
+
+        let authorized = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map_or(false, |token| token == self.token);
+        if !authorized {
+            return Ok(status_response(StatusCode::UNAUTHORIZED));
+        }
+
+        let body = Limited::new(req.into_body(), MAX_REQUEST_BYTES);
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(body) => body,
+            Err(e) if e.is::<http_body::LengthLimitError>() => {
+                return Ok(status_response(StatusCode::PAYLOAD_TOO_LARGE))
+            }
+            Err(_) => return Ok(status_response(StatusCode::BAD_REQUEST)),
+        };
+
+        let rsp = match serde_json::from_slice::<RpcRequest>(&body) {
+            Ok(request) => {
+                tracing::debug!(method = %request.method, "handling daemon request");
+                let result = self.call(&request.method, request.params).await;
+                rpc_response(request.id, result)
+            }
+            Err(e) => rpc_response(Json::Null, Err(RpcError::parse_error(e))),
+        };
+
+        Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(rsp.to_string()))
+            .expect("response is valid"))
+    }
+
+    async fn call(&self, method: &str, params: Json) -> Result<Json, RpcError> {
+        let mut app = self.app.lock().await;
+        let fvk_hash = app.fvk.hash();
+
+        match method {
+            "status" => {
+                let status = ViewClient::status(&mut app.view, fvk_hash).await?;
+                Ok(json!({
+                    "sync_height": status.sync_height,
+                    "catching_up": status.catching_up,
+                    "latest_known_block_height": status.latest_known_block_height,
+                    "notes_per_second": status.notes_per_second,
+                    "sync_source": status.sync_source.map(|health| json!({
+                        "url": health.url,
+                        "checked_at": health.checked_at,
+                        "error": health.error,
+                    })),
+                }))
+            }
+            "balance" => {
+                let asset_cache = ViewClient::assets(&mut app.view).await?;
+                let notes = app
+                    .view
+                    .unspent_notes_by_asset_and_address(fvk_hash)
+                    .await?;
+
+                let mut balances = Vec::new();
+                for (asset_id, notes_by_address) in notes {
+                    let amount = Amount::checked_sum(
+                        notes_by_address
+                            .values()
+                            .flatten()
+                            .map(|record| record.note.amount()),
+                    )
+                    .ok_or_else(|| anyhow::anyhow!("balance of {} overflowed", asset_id))?;
+                    balances.push(json!({
+                        "asset_id": asset_id.to_string(),
+                        "amount": amount.value().to_string(),
+                        "formatted": asset_id.value(amount.into()).try_format(&asset_cache),
+                    }));
+                }
+
+                Ok(json!({ "balances": balances }))
+            }
+            "new_address" => {
+                let params: NewAddressParams =
+                    serde_json::from_value(params).map_err(RpcError::invalid_params)?;
+                let (address, _dtk) = app.fvk.incoming().payment_address(params.index.into());
+                Ok(json!({ "address": address.to_string() }))
+            }
+            "send" => {
+                let params: SendParams =
+                    serde_json::from_value(params).map_err(RpcError::invalid_params)?;
+                let values = params
+                    .values
+                    .iter()
+                    .map(|v| v.parse())
+                    .collect::<Result<Vec<Value>, _>>()
+                    .map_err(RpcError::invalid_params)?;
+                let fee = params
+                    .fee
+                    .as_deref()
+                    .map(str::parse::<u64>)
+                    .transpose()
+                    .map_err(|e| RpcError::invalid_params(format!("fee is invalid: {}", e)))?
+                    .unwrap_or(0);
+                let to = params
+                    .to
+                    .parse()
+                    .map_err(|_| RpcError::invalid_params("address is invalid"))?;
+
+                let app = &mut *app;
+                let plan = plan::send(
+                    &app.fvk,
+                    &mut app.view,
+                    OsRng,
+                    &values,
+                    fee,
+                    to,
+                    params.source.map(Into::into),
+                    params.memo,
+                    params.single_account,
+                    params.include_return_address,
+                    SelectionStrategy::default(),
+                )
+                .await?;
+                let transaction = app.build_and_submit_transaction(plan).await?;
+
+                // This is the hash Tendermint indexes the transaction by.
+                let tx_hash = Sha256::digest(&transaction.encode_to_vec());
+                Ok(json!({ "tx_hash": hex::encode(tx_hash) }))
+            }
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+}
+
+fn rpc_response(id: Json, result: Result<Json, RpcError>) -> Json {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message },
+        }),
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .expect("response is valid")
+}
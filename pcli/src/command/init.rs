@@ -0,0 +1,171 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use penumbra_proto::client::oblivious::{
+    oblivious_query_client::ObliviousQueryClient, ChainParamsRequest,
+};
+
+use super::WalletCmd;
+use crate::profile::NetworkProfile;
+
+#[derive(Debug, clap::Args)]
+pub struct InitCmd {}
+
+impl InitCmd {
+    /// Determine if this command requires a network sync before it executes.
+    ///
+    /// The wizard kicks off the initial sync itself, once it has checked that the node is
+    /// reachable.
+    pub fn needs_sync(&self) -> bool {
+        false
+    }
+
+    /// Walk through setting up the wallet, choosing a network profile and checking the node
+    /// connection, returning the (saved) profile to sync with.
+    pub async fn exec(
+        &self,
+        data_dir: &Utf8Path,
+        default_profile: &NetworkProfile,
+    ) -> Result<NetworkProfile> {
+        std::fs::create_dir_all(data_dir).context("Failed to create data directory")?;
+
+        // Step 1: the seed phrase.
+        println!("Step 1: wallet");
+        let custody_path = data_dir.join(crate::CUSTODY_FILE_NAME);
+        if custody_path.exists() {
+            println!("Using the existing wallet at {}.", custody_path);
+        } else {
+            loop {
+                let choice = prompt(
+                    "Generate a new seed phrase, or import an existing one? [generate/import]",
+                )?;
+                match choice.as_str() {
+                    "generate" | "g" => {
+                        WalletCmd::Generate.exec(data_dir).await?;
+                        prompt("Write down your seed phrase, then press enter to continue.")?;
+                        break;
+                    }
+                    "import" | "i" => {
+                        let seed_phrase = prompt("Enter your 24 word seed phrase:")?;
+                        match (WalletCmd::ImportFromPhrase { seed_phrase })
+                            .exec(data_dir)
+                            .await
+                        {
+                            Ok(()) => break,
+                            Err(e) => println!("Could not import seed phrase: {}", e),
+                        }
+                    }
+                    _ => println!("Please enter either \"generate\" or \"import\"."),
+                }
+            }
+        }
+
+        // Step 2: custody. Local custody is currently the only backend, so there's nothing to
+        // choose yet, but say where the spend key lives.
+        println!();
+        println!("Step 2: custody");
+        println!(
+            "Your spend key is held by local custody, in {}. Keep this file safe.",
+            custody_path
+        );
+
+        // Step 3: the network.
+        println!();
+        println!("Step 3: network");
+        let names = NetworkProfile::builtin()
+            .into_iter()
+            .map(|profile| profile.name)
+            .collect::<Vec<_>>()
+            .join("/");
+        let profile = loop {
+            let name = prompt(&format!(
+                "Network profile [{}/custom] ({}):",
+                names, default_profile.name
+            ))?;
+            match name.as_str() {
+                "" => break default_profile.clone(),
+                "custom" => match custom_profile() {
+                    Ok(profile) => break profile,
+                    Err(e) => println!("{}", e),
+                },
+                name => match name.parse() {
+                    Ok(profile) => break profile,
+                    Err(e) => println!("{}", e),
+                },
+            }
+        };
+        let node = profile.host()?;
+
+        // Step 4: connectivity.
+        println!();
+        println!("Step 4: connectivity");
+        let chain_id =
+            ObliviousQueryClient::connect(format!("http://{}:{}", node, profile.pd_port))
+                .await
+                .with_context(|| {
+                    format!("Could not connect to pd at {}:{}", node, profile.pd_port)
+                })?
+                .chain_params(tonic::Request::new(ChainParamsRequest {
+                    chain_id: String::new(),
+                }))
+                .await
+                .with_context(|| format!("Could not query chain parameters from {}", node))?
+                .into_inner()
+                .chain_id;
+        println!("Connected to {}, on chain {}.", node, chain_id);
+
+        // Only save the profile once we know it works, so later commands use it by default.
+        profile.save(data_dir)?;
+        println!(
+            "Saved the {} network profile; later commands will connect to {} unless given `--node`.",
+            profile.name, node
+        );
+
+        // Step 5, the initial sync, happens once the caller has built the app.
+        println!();
+        println!("Step 5: initial sync");
+
+        Ok(node)
+    }
+}
+
+/// Ask for the endpoints of a custom network profile.
+fn custom_profile() -> Result<NetworkProfile> {
+    let node = prompt("Node hostname:")?;
+    url::Host::parse(&node).with_context(|| format!("Invalid hostname {}", node))?;
+
+    let testnet = NetworkProfile::testnet();
+    let pd_port = prompt(&format!("pd gRPC port [{}]:", testnet.pd_port))?;
+    let tendermint_port = prompt(&format!(
+        "Tendermint RPC port [{}]:",
+        testnet.tendermint_port
+    ))?;
+
+    Ok(NetworkProfile {
+        name: "custom".to_string(),
+        node,
+        pd_port: parse_port(&pd_port, testnet.pd_port)?,
+        tendermint_port: parse_port(&tendermint_port, testnet.tendermint_port)?,
+    })
+}
+
+/// Parse a port given at a prompt, or use the default if none was given.
+fn parse_port(port: &str, default: u16) -> Result<u16> {
+    if port.is_empty() {
+        Ok(default)
+    } else {
+        port.parse()
+            .with_context(|| format!("Invalid port {}", port))
+    }
+}
+
+/// Print a question and read a line of input in response, without surrounding whitespace.
+fn prompt(question: &str) -> Result<String> {
+    print!("{} ", question);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use comfy_table::{presets, Table};
 use jmt::KeyHash;
 use penumbra_chain::{quarantined::Scheduled, CompactBlock, NoteSource};
 use penumbra_component::shielded_pool::Delible;
@@ -18,6 +19,8 @@ pub enum QueryCmd {
     /// Queries shielded pool data.
     #[clap(subcommand)]
     ShieldedPool(ShieldedPool),
+    /// Lists the transactions in the node's mempool.
+    Mempool,
 }
 
 #[derive(Debug, clap::Subcommand)]
@@ -56,6 +59,11 @@ pub enum ShieldedPool {
 
 impl QueryCmd {
     pub async fn exec(&self, app: &mut App) -> Result<()> {
+        // The mempool isn't part of the chain state, so it's queried separately.
+        if let QueryCmd::Mempool = self {
+            return mempool(app).await;
+        }
+
         let mut client = app.specific_client().await?;
 
         let key_hash = self.key_hash();
@@ -83,6 +91,7 @@ impl QueryCmd {
         match self {
             QueryCmd::Key { key } => key.as_bytes().into(),
             QueryCmd::ShieldedPool(sp) => sp.key_hash(),
+            QueryCmd::Mempool => unreachable!("the mempool is not queried by key"),
         }
     }
 
@@ -92,12 +101,38 @@ impl QueryCmd {
                 println!("{}", hex::encode(bytes));
             }
             QueryCmd::ShieldedPool(sp) => sp.display_value(bytes)?,
+            QueryCmd::Mempool => unreachable!("the mempool is not queried by key"),
         }
 
         Ok(())
     }
 }
 
+async fn mempool(app: &mut App) -> Result<()> {
+    let mut client = app.oblivious_client().await?;
+    let entries = client
+        .mempool_snapshot(penumbra_proto::client::oblivious::MempoolSnapshotRequest {})
+        .await?
+        .into_inner()
+        .entries;
+
+    let mut table = Table::new();
+    table.load_preset(presets::NOTHING);
+    table.set_header(vec!["Tx Hash", "Size", "Fee", "Nullifiers", "Time in Pool"]);
+    for entry in entries {
+        table.add_row(vec![
+            hex::encode(&entry.tx_hash),
+            entry.size.to_string(),
+            entry.fee.to_string(),
+            entry.nullifier_count.to_string(),
+            format!("{}s", entry.seconds_in_pool),
+        ]);
+    }
+    println!("{}", table);
+
+    Ok(())
+}
+
 impl ShieldedPool {
     fn key_hash(&self) -> KeyHash {
         use penumbra_component::shielded_pool::state_key;
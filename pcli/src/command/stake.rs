@@ -4,13 +4,15 @@ use anyhow::{anyhow, Context, Result};
 use comfy_table::{presets, Table};
 use futures::stream::TryStreamExt;
 use penumbra_component::stake::{rate::RateData, validator};
-use penumbra_crypto::{DelegationToken, IdentityKey, Value, STAKING_TOKEN_ASSET_ID};
+use penumbra_crypto::{
+    keys::DiversifierIndex, Amount, DelegationToken, IdentityKey, Value, STAKING_TOKEN_ASSET_ID,
+};
 use penumbra_proto::client::oblivious::ValidatorInfoRequest;
-use penumbra_view::ViewClient;
-use penumbra_wallet::plan;
+use penumbra_view::{NoteRecord, ViewClient};
+use penumbra_wallet::{plan, template::Template};
 use rand_core::OsRng;
 
-use crate::App;
+use crate::{message::Message, App};
 
 #[derive(Debug, clap::Subcommand)]
 pub enum StakeCmd {
@@ -26,7 +28,7 @@ pub enum StakeCmd {
         fee: u64,
         /// Optional. Only spend funds originally received by the given address index.
         #[clap(long)]
-        source: Option<u64>,
+        source: Option<DiversifierIndex>,
     },
     /// Withdraw stake from a validator's delegation pool.
     Undelegate {
@@ -37,7 +39,11 @@ pub enum StakeCmd {
         fee: u64,
         /// Optional. Only spend funds originally received by the given address index.
         #[clap(long)]
-        source: Option<u64>,
+        source: Option<DiversifierIndex>,
+        /// If no source is given, spend all delegation tokens from a single address index,
+        /// rather than merging tokens received by several address indices.
+        #[clap(long)]
+        single_account: bool,
     },
     /// Redelegate stake from one validator's delegation pool to another.
     Redelegate {
@@ -47,14 +53,18 @@ pub enum StakeCmd {
         /// The identity key of the validator to delegate to.
         #[clap(long)]
         to: String,
-        /// The amount of stake to delegate.
+        /// The amount of delegation tokens to redelegate.
         amount: String,
         /// The transaction fee (paid in upenumbra).
         #[clap(long, default_value = "0")]
         fee: u64,
         /// Optional. Only spend funds originally received by the given address index.
         #[clap(long)]
-        source: Option<u64>,
+        source: Option<DiversifierIndex>,
+        /// If no source is given, spend all delegation tokens from a single address index,
+        /// rather than merging tokens received by several address indices.
+        #[clap(long)]
+        single_account: bool,
     },
     /// Display this wallet's delegations and their value.
     Show,
@@ -99,15 +109,13 @@ impl StakeCmd {
                     .into_inner()
                     .try_into()?;
 
-                let plan = plan::delegate(
-                    &app.fvk,
-                    &mut app.view,
-                    OsRng,
+                let plan = Template::Delegate {
                     rate_data,
                     unbonded_amount,
-                    *fee,
-                    *source,
-                )
+                    fee: *fee,
+                    source_address: *source,
+                }
+                .plan_one(&app.fvk, &mut app.view, OsRng)
                 .await?;
 
                 app.build_and_submit_transaction(plan).await?;
@@ -116,12 +124,8 @@ impl StakeCmd {
                 amount,
                 fee,
                 source,
+                single_account,
             } => {
-                let (self_address, _dtk) = app
-                    .fvk
-                    .incoming()
-                    .payment_address(source.unwrap_or(0).into());
-
                 let delegation_value @ Value {
                     amount: _,
                     asset_id,
@@ -146,72 +150,83 @@ impl StakeCmd {
                     .into_inner()
                     .try_into()?;
 
-                // first, split the input notes into exact change
-                let split_plan = plan::send(
-                    &app.fvk,
-                    &mut app.view,
-                    OsRng,
-                    &[delegation_value],
-                    *fee,
-                    self_address,
-                    *source,
-                    None,
-                )
+                let delegation_notes =
+                    split_exact_delegation(app, delegation_value, *fee, *source, *single_account)
+                        .await?;
+
+                // now we can plan and submit an exact-change undelegation
+                let undelegate_plan = Template::Undelegate {
+                    rate_data,
+                    delegation_notes,
+                    fee: *fee,
+                    source_address: *source,
+                }
+                .plan_one(&app.fvk, &mut app.view, OsRng)
                 .await?;
 
-                // find the note commitment corresponding to the delegation value within the split
-                // plan, so that we can use it to create the undelegate plan
-                let delegation_note_commitment = split_plan
-                    .output_plans()
-                    .find_map(|output| {
-                        let note = output.output_note();
-                        // grab the note commitment of whichever output in the spend plan has
-                        // exactly the right amount and asset id, and is also addressed to us
-                        if note.value() == delegation_value
-                        // this check is not necessary currently, because we never construct
-                        // undelegations to a different address than ourselves, but it's good to
-                        // leave it in here so that if we ever change that invariant, it will fail
-                        // here rather than after already executing the plan
-                            && app.fvk.incoming().views_address(&output.dest_address)
-                        {
-                            Some(note.commit())
-                        } else {
-                            None
-                        }
-                    })
-                    .expect("there must be an exact output for the amount we are expecting");
+                // Pass None as the change to await, since the change will be quarantined, so we won't detect it.
+                // But it's not spendable anyways, so we don't need to detect it.
+                let tx = app
+                    .preflight_and_build_transaction(&undelegate_plan)
+                    .await?;
+                app.submit_transaction(&tx, None).await?;
+            }
+            StakeCmd::Redelegate {
+                from,
+                to,
+                amount,
+                fee,
+                source,
+                single_account,
+            } => {
+                let from = from.parse::<IdentityKey>()?;
+                let to = to.parse::<IdentityKey>()?;
 
-                // we submit the split transaction before building the undelegate plan, because we
-                // need to await the note created by its output
-                app.build_and_submit_transaction(split_plan).await?;
+                let delegation_value = amount.parse::<Value>()?;
+                if delegation_value.asset_id != DelegationToken::new(from.clone()).id() {
+                    return Err(anyhow!(
+                        "the amount to redelegate must be in delegation tokens for {}",
+                        from
+                    ));
+                }
 
-                // await the receipt of the exact note we wish to undelegate
-                let delegation_notes = vec![
-                    app.view
-                        .await_note_by_commitment(app.fvk.hash(), delegation_note_commitment)
-                        .await?,
-                ];
+                let mut client = app.specific_client().await?;
+                let from_rate_data: RateData = client
+                    .next_validator_rate(tonic::Request::new(from.into()))
+                    .await?
+                    .into_inner()
+                    .try_into()?;
+                let to_rate_data: RateData = client
+                    .next_validator_rate(tonic::Request::new(to.into()))
+                    .await?
+                    .into_inner()
+                    .try_into()?;
 
-                // now we can plan and submit an exact-change undelegation
-                let undelegate_plan = plan::undelegate(
+                let delegation_notes =
+                    split_exact_delegation(app, delegation_value, *fee, *source, *single_account)
+                        .await?;
+
+                // now we can undelegate the exact-change note and delegate the unbonded stake in
+                // a single transaction
+                let redelegate_plan = plan::redelegate(
                     &app.fvk,
                     &mut app.view,
                     OsRng,
-                    rate_data,
+                    from_rate_data,
+                    to_rate_data,
                     delegation_notes,
                     *fee,
                     *source,
                 )
                 .await?;
 
-                // Pass None as the change to await, since the change will be quarantined, so we won't detect it.
-                // But it's not spendable anyways, so we don't need to detect it.
-                let tx = app.build_transaction(undelegate_plan).await?;
+                // As with undelegations, the new delegation tokens are quarantined, so we won't
+                // detect them until the unbonding period ends.
+                let tx = app
+                    .preflight_and_build_transaction(&redelegate_plan)
+                    .await?;
                 app.submit_transaction(&tx, None).await?;
             }
-            StakeCmd::Redelegate { .. } => {
-                todo!()
-            }
             StakeCmd::Show => {
                 let mut client = app.oblivious_client().await?;
 
@@ -235,11 +250,16 @@ impl StakeCmd {
                     .view()
                     .unspent_notes_by_asset_and_address(fvk_hash)
                     .await?;
-                let mut total = 0;
+                let mut total = Amount::zero();
 
                 let mut table = Table::new();
                 table.load_preset(presets::NOTHING);
-                table.set_header(vec!["Name", "Value", "Exch. Rate", "Tokens"]);
+                table.set_header(vec![
+                    Message::Name,
+                    Message::Value,
+                    Message::ExchangeRate,
+                    Message::Tokens,
+                ]);
                 table
                     .get_column_mut(1)
                     .unwrap()
@@ -261,10 +281,13 @@ impl StakeCmd {
                         .unwrap();
 
                     let delegation = Value {
-                        amount: notes_by_address
-                            .values()
-                            .flat_map(|notes| notes.iter().map(|n| n.note.amount()))
-                            .sum::<u64>(),
+                        amount: Amount::checked_sum(
+                            notes_by_address
+                                .values()
+                                .flat_map(|notes| notes.iter().map(|n| n.note.amount())),
+                        )
+                        .ok_or_else(|| anyhow!("delegation amount overflowed"))?
+                        .into(),
                         asset_id: dt.id(),
                     };
 
@@ -282,35 +305,42 @@ impl StakeCmd {
                         delegation.try_format(&asset_cache).unwrap(),
                     ]);
 
-                    total += unbonded.amount;
+                    total = total
+                        .checked_add(unbonded.amount.into())
+                        .ok_or_else(|| anyhow!("total stake overflowed"))?;
                 }
 
                 let unbonded = Value {
-                    amount: notes
-                        .get(&*STAKING_TOKEN_ASSET_ID)
-                        .unwrap_or(&BTreeMap::default())
-                        .values()
-                        .flat_map(|notes| notes.iter().map(|n| n.note.amount()))
-                        .sum::<u64>(),
+                    amount: Amount::checked_sum(
+                        notes
+                            .get(&*STAKING_TOKEN_ASSET_ID)
+                            .unwrap_or(&BTreeMap::default())
+                            .values()
+                            .flat_map(|notes| notes.iter().map(|n| n.note.amount())),
+                    )
+                    .ok_or_else(|| anyhow!("unbonded amount overflowed"))?
+                    .into(),
                     asset_id: *STAKING_TOKEN_ASSET_ID,
                 };
 
-                total += unbonded.amount;
+                total = total
+                    .checked_add(unbonded.amount.into())
+                    .ok_or_else(|| anyhow!("total stake overflowed"))?;
 
                 table.add_row(vec![
-                    "Unbonded Stake".to_string(),
+                    Message::UnbondedStake.to_string(),
                     unbonded.try_format(&asset_cache).unwrap(),
                     format!("{:.4}", 1.0),
                     unbonded.try_format(&asset_cache).unwrap(),
                 ]);
 
                 let total = Value {
-                    amount: total,
+                    amount: total.into(),
                     asset_id: *STAKING_TOKEN_ASSET_ID,
                 };
 
                 table.add_row(vec![
-                    "Total".to_string(),
+                    Message::Total.to_string(),
                     total.try_format(&asset_cache).unwrap(),
                     String::new(),
                     String::new(),
@@ -366,12 +396,12 @@ impl StakeCmd {
                 let mut table = Table::new();
                 table.load_preset(presets::NOTHING);
                 table.set_header(vec![
-                    "Voting Power",
-                    "Share",
-                    "Commission",
-                    "State",
-                    "Bonding State",
-                    "Validator Info",
+                    Message::VotingPower,
+                    Message::Share,
+                    Message::Commission,
+                    Message::State,
+                    Message::BondingState,
+                    Message::ValidatorInfo,
                 ]);
 
                 for v in validators {
@@ -436,3 +466,56 @@ impl StakeCmd {
         Ok(())
     }
 }
+
+/// Splits off a note holding exactly `delegation_value` from this wallet's delegation tokens,
+/// submitting the splitting transaction and waiting for the note to be detected.
+async fn split_exact_delegation(
+    app: &mut App,
+    delegation_value: Value,
+    fee: u64,
+    source: Option<DiversifierIndex>,
+    single_account: bool,
+) -> Result<Vec<NoteRecord>> {
+    // first, split the input notes into exact change
+    let split_plan = Template::Split {
+        value: delegation_value,
+        fee,
+        source_address: source,
+        single_account,
+    }
+    .plan_one(&app.fvk, &mut app.view, OsRng)
+    .await?;
+
+    // find the note commitment corresponding to the delegation value within the split
+    // plan, so that we can use it to create the undelegate plan
+    let delegation_note_commitment = split_plan
+        .output_plans()
+        .find_map(|output| {
+            let note = output.output_note();
+            // grab the note commitment of whichever output in the spend plan has
+            // exactly the right amount and asset id, and is also addressed to us
+            if note.value() == delegation_value
+            // this check is not necessary currently, because we never construct
+            // undelegations to a different address than ourselves, but it's good to
+            // leave it in here so that if we ever change that invariant, it will fail
+            // here rather than after already executing the plan
+                && app.fvk.incoming().views_address(&output.dest_address)
+            {
+                Some(note.commit())
+            } else {
+                None
+            }
+        })
+        .expect("there must be an exact output for the amount we are expecting");
+
+    // we submit the split transaction before building the undelegate plan, because we
+    // need to await the note created by its output
+    app.build_and_submit_transaction(split_plan).await?;
+
+    // await the receipt of the exact note we wish to undelegate
+    Ok(vec![
+        app.view
+            .await_note_by_commitment(app.fvk.hash(), delegation_note_commitment)
+            .await?,
+    ])
+}
@@ -1,15 +1,23 @@
-use anyhow::Result;
-use penumbra_crypto::Value;
-use penumbra_wallet::plan;
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use comfy_table::{presets, Table};
+use penumbra_crypto::{
+    asset, keys::DiversifierIndex, memo::MemoPlaintext, FullViewingKey, Note, Value,
+};
+use penumbra_proto::Protobuf;
+use penumbra_transaction::{Action, Transaction};
+use penumbra_view::{SelectionStrategy, ViewClient};
+use penumbra_wallet::{plan, template::Template};
 use rand_core::OsRng;
 
-use crate::App;
+use super::addr::resolve_address;
+use crate::{message::Message, App};
 
 #[derive(Debug, clap::Subcommand)]
 pub enum TxCmd {
     /// Send transaction to the node.
     Send {
-        /// The destination address to send funds to.
+        /// The destination address to send funds to, or the label of a contact in the address book.
         #[clap(long)]
         to: String,
         /// The amounts to send, written as typed values 1.87penumbra, 12cubes, etc.
@@ -19,10 +27,52 @@ pub enum TxCmd {
         fee: u64,
         /// Optional. Only spend funds originally received by the given address index.
         #[clap(long)]
-        source: Option<u64>,
+        source: Option<DiversifierIndex>,
         /// Optional. Set the transaction's memo field to the provided text.
         #[clap(long)]
         memo: Option<String>,
+        /// If no source is given, spend all funds from a single address index,
+        /// rather than merging funds received by several address indices.
+        #[clap(long)]
+        single_account: bool,
+        /// Don't include this wallet's address in the memo.
+        ///
+        /// By default, the memo carries the address of the source index, so
+        /// that the recipient can refund the payment.
+        #[clap(long)]
+        no_return_address: bool,
+        /// How to choose the notes to spend: in-order, largest-first, smallest-first,
+        /// minimize-change, or privacy-preferring.
+        #[clap(long, default_value = "in-order")]
+        note_selection: SelectionStrategy,
+    },
+    /// Moves funds between two address indices of this wallet.
+    ///
+    /// The transfer is marked as internal in the memo of its output, so it can
+    /// be told apart from payments to other parties.
+    Transfer {
+        /// The address index to move funds from.
+        #[clap(long)]
+        from: DiversifierIndex,
+        /// The address index to move funds to.
+        #[clap(long)]
+        to: DiversifierIndex,
+        /// The amounts to transfer, written as typed values 1.87penumbra, 12cubes, etc.
+        values: Vec<String>,
+        /// The transaction fee (paid in upenumbra).
+        #[clap(long, default_value = "0")]
+        fee: u64,
+    },
+    /// Refunds the payments received in a transaction to its return address.
+    ///
+    /// Only payments to this wallet whose memos carry a return address can be
+    /// refunded.
+    Refund {
+        /// The hash of the transaction to refund, hex-encoded.
+        tx_hash: String,
+        /// The transaction fee (paid in upenumbra).
+        #[clap(long, default_value = "0")]
+        fee: u64,
     },
     /// Sweeps small notes of the same denomination into a few larger notes.
     ///
@@ -33,6 +83,15 @@ pub enum TxCmd {
     ///
     /// Currently, only zero-fee sweep transactions are implemented.
     Sweep,
+    /// Decodes raw transaction bytes and displays their contents.
+    ///
+    /// Outputs which can be decrypted with this wallet's viewing key are
+    /// displayed along with their plaintext notes and memos.
+    Decode {
+        /// The transaction bytes, either hex-encoded or as the path to a file
+        /// containing the raw or hex-encoded bytes.
+        transaction: String,
+    },
 }
 
 impl TxCmd {
@@ -40,7 +99,10 @@ impl TxCmd {
     pub fn needs_sync(&self) -> bool {
         match self {
             TxCmd::Send { .. } => true,
+            TxCmd::Transfer { .. } => true,
+            TxCmd::Refund { .. } => true,
             TxCmd::Sweep { .. } => true,
+            TxCmd::Decode { .. } => false,
         }
     }
 
@@ -52,47 +114,268 @@ impl TxCmd {
                 fee,
                 source: from,
                 memo,
+                single_account,
+                no_return_address,
+                note_selection,
             } => {
                 // Parse all of the values provided.
                 let values = values
                     .iter()
                     .map(|v| v.parse())
                     .collect::<Result<Vec<Value>, _>>()?;
-                let to = to
-                    .parse()
-                    .map_err(|_| anyhow::anyhow!("address is invalid"))?;
-
-                let plan = plan::send(
-                    &app.fvk,
-                    &mut app.view,
-                    OsRng,
-                    &values,
-                    *fee,
-                    to,
-                    *from,
-                    memo.clone(),
-                )
+                let to = resolve_address(app, to).await?;
+
+                let plan = Template::Send {
+                    values,
+                    dest_address: to,
+                    fee: *fee,
+                    source_address: *from,
+                    memo: memo.clone(),
+                    single_account: *single_account,
+                    include_return_address: !*no_return_address,
+                    note_selection: *note_selection,
+                }
+                .plan_one(&app.fvk, &mut app.view, OsRng)
                 .await?;
                 app.build_and_submit_transaction(plan).await?;
             }
+            TxCmd::Transfer {
+                from,
+                to,
+                values,
+                fee,
+            } => {
+                let values = values
+                    .iter()
+                    .map(|v| v.parse())
+                    .collect::<Result<Vec<Value>, _>>()?;
+
+                let plan =
+                    plan::transfer(&app.fvk, &mut app.view, OsRng, &values, *fee, *from, *to)
+                        .await?;
+                app.build_and_submit_transaction(plan).await?;
+            }
+            TxCmd::Refund { tx_hash, fee } => {
+                let transaction = app.fetch_transaction(tx_hash).await?;
+
+                let plan = plan::refund(&app.fvk, &mut app.view, OsRng, &transaction, *fee).await?;
+                app.build_and_submit_transaction(plan).await?;
+            }
             TxCmd::Sweep => loop {
-                let plans = plan::sweep(&app.fvk, &mut app.view, OsRng).await?;
+                let plans = Template::Sweep.plan(&app.fvk, &mut app.view, OsRng).await?;
                 let num_plans = plans.len();
 
-                for (i, plan) in plans.into_iter().enumerate() {
-                    println!("building sweep {} of {}", i, num_plans);
-                    let tx = app.build_transaction(plan).await?;
+                for (i, plan) in plans.iter().enumerate() {
+                    println!(
+                        "{}",
+                        Message::BuildingSweep {
+                            index: i,
+                            count: num_plans,
+                        }
+                    );
+                    let tx = match app.preflight_and_build_transaction(plan).await {
+                        Ok(tx) => tx,
+                        Err(e) => {
+                            // None of the remaining sweeps will be submitted.
+                            app.release_notes(&plans[i + 1..]).await;
+                            return Err(e);
+                        }
+                    };
                     app.submit_transaction_unconfirmed(&tx).await?;
                 }
                 if num_plans == 0 {
-                    println!("finished sweeping");
+                    println!("{}", Message::FinishedSweeping);
                     break;
                 } else {
-                    println!("awaiting confirmations...");
+                    println!("{}", Message::AwaitingConfirmations);
                     tokio::time::sleep(std::time::Duration::from_secs(6)).await;
                 }
             },
+            TxCmd::Decode { transaction } => {
+                let transaction =
+                    Transaction::decode(read_transaction_bytes(transaction)?.as_ref())
+                        .context("could not decode transaction")?;
+                let asset_cache = app.view().assets().await?;
+                print_transaction(&app.fvk, &asset_cache, &transaction);
+            }
         }
         Ok(())
     }
 }
+
+/// Reads transaction bytes from either a file or a hex string.
+fn read_transaction_bytes(input: &str) -> Result<Vec<u8>> {
+    let path = Utf8PathBuf::from(input);
+    let bytes = if path.is_file() {
+        std::fs::read(&path).with_context(|| format!("could not read {}", path))?
+    } else {
+        input.as_bytes().to_vec()
+    };
+
+    // Files may contain either raw bytes or hex, so try hex first, falling back to raw bytes.
+    match std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|hex_str| hex::decode(hex_str.trim()).ok())
+    {
+        Some(decoded) => Ok(decoded),
+        None if path.is_file() => Ok(bytes),
+        None => Err(anyhow::anyhow!(
+            "transaction is neither a file nor a valid hex string"
+        )),
+    }
+}
+
+fn format_value(asset_cache: &asset::Cache, value: Value) -> String {
+    value
+        .try_format(asset_cache)
+        .unwrap_or_else(|| format!("{}{}", value.amount, value.asset_id))
+}
+
+fn print_transaction(fvk: &FullViewingKey, asset_cache: &asset::Cache, transaction: &Transaction) {
+    let body = &transaction.transaction_body;
+
+    let mut table = Table::new();
+    table.load_preset(presets::NOTHING);
+    table
+        .set_header(vec!["", ""])
+        .add_row(vec![Message::ChainId.to_string(), body.chain_id.clone()])
+        .add_row(vec![
+            Message::ExpiryHeight.to_string(),
+            body.expiry_height.to_string(),
+        ])
+        .add_row(vec![
+            Message::Fee.to_string(),
+            format_value(
+                asset_cache,
+                Value {
+                    amount: body.fee.0,
+                    asset_id: *penumbra_crypto::STAKING_TOKEN_ASSET_ID,
+                },
+            ),
+        ])
+        .add_row(vec![
+            Message::Anchor.to_string(),
+            transaction.anchor.to_string(),
+        ]);
+    println!("{}", table);
+
+    let mut table = Table::new();
+    table.load_preset(presets::NOTHING);
+    table.set_header(vec![Message::Action, Message::Details]);
+
+    for action in transaction.actions() {
+        let (kind, details) = match action {
+            Action::Spend(spend) => (
+                Message::Spend,
+                Message::SpendDetails {
+                    nullifier: spend.body.nullifier.to_string(),
+                }
+                .to_string(),
+            ),
+            Action::Output(output) => {
+                let payload = &output.body.note_payload;
+                // Only outputs addressed to us can be decrypted with our viewing key.
+                match Note::decrypt(
+                    payload.encrypted_note.as_ref(),
+                    fvk.incoming(),
+                    &payload.ephemeral_key,
+                ) {
+                    Ok(note) => {
+                        let memo = MemoPlaintext::decrypt(
+                            output.body.encrypted_memo.clone(),
+                            fvk.incoming(),
+                            &payload.ephemeral_key,
+                        )
+                        .unwrap_or_default();
+                        let index = fvk.incoming().index_for_diversifier(&note.diversifier());
+                        // Rather than show whatever index the diversifier decrypts to, make sure
+                        // it's the index of the address the note was actually sent to.
+                        let (address, _) = fvk.incoming().payment_address(index);
+                        let index = if *address.diversifier() == note.diversifier()
+                            && *address.transmission_key() == note.transmission_key()
+                        {
+                            index.to_string()
+                        } else {
+                            Message::UnknownIndex.to_string()
+                        };
+                        (
+                            Message::Output,
+                            Message::OutputDetails {
+                                value: format_value(asset_cache, note.value()),
+                                index,
+                                memo: memo.text(),
+                                return_address: memo
+                                    .return_address()
+                                    .map(|address| address.to_string()),
+                            }
+                            .to_string(),
+                        )
+                    }
+                    Err(_) => (
+                        Message::Output,
+                        Message::OutputNotOurs {
+                            note_commitment: payload.note_commitment.to_string(),
+                        }
+                        .to_string(),
+                    ),
+                }
+            }
+            Action::Delegate(delegate) => (
+                Message::Delegate,
+                Message::DelegateDetails {
+                    amount: delegate.unbonded_amount,
+                    validator: delegate.validator_identity.to_string(),
+                }
+                .to_string(),
+            ),
+            Action::Undelegate(undelegate) => (
+                Message::Undelegate,
+                Message::UndelegateDetails {
+                    amount: undelegate.unbonded_amount,
+                    validator: undelegate.validator_identity.to_string(),
+                }
+                .to_string(),
+            ),
+            Action::ValidatorDefinition(_) => (Message::ValidatorDefinition, String::new()),
+            Action::IBCAction(_) => (Message::IbcAction, String::new()),
+        };
+        table.add_row(vec![kind.to_string(), details]);
+    }
+
+    println!("{}", table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transaction_bytes_from_hex() {
+        assert_eq!(
+            read_transaction_bytes("00ff10").unwrap(),
+            vec![0x00, 0xff, 0x10]
+        );
+        assert!(read_transaction_bytes("not hex").is_err());
+    }
+
+    #[test]
+    fn transaction_bytes_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Hex files are decoded, ignoring surrounding whitespace.
+        let hex_file = dir.path().join("tx.hex");
+        std::fs::write(&hex_file, "00ff10\n").unwrap();
+        assert_eq!(
+            read_transaction_bytes(hex_file.to_str().unwrap()).unwrap(),
+            vec![0x00, 0xff, 0x10]
+        );
+
+        // Anything else is read as raw bytes.
+        let raw_file = dir.path().join("tx.bin");
+        std::fs::write(&raw_file, [0x00, 0xff, 0x10]).unwrap();
+        assert_eq!(
+            read_transaction_bytes(raw_file.to_str().unwrap()).unwrap(),
+            vec![0x00, 0xff, 0x10]
+        );
+    }
+}
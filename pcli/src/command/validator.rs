@@ -1,9 +1,12 @@
 use std::{fs::File, io::Write};
 
 use anyhow::{Context, Result};
+use comfy_table::{presets, Table};
 use futures::TryStreamExt;
-use penumbra_component::stake::{validator, validator::Validator, FundingStream, FundingStreams};
-use penumbra_crypto::IdentityKey;
+use penumbra_component::stake::{
+    validator, validator::Validator, BlockStats, FundingStream, FundingStreams,
+};
+use penumbra_crypto::{keys::DiversifierIndex, IdentityKey};
 use penumbra_proto::{stake::Validator as ProtoValidator, Message};
 use penumbra_wallet::plan;
 use rand_core::OsRng;
@@ -24,7 +27,7 @@ pub enum ValidatorCmd {
         fee: u64,
         /// Optional. Only spend funds originally received by the given address index.
         #[clap(long)]
-        source: Option<u64>,
+        source: Option<DiversifierIndex>,
     },
     /// Generates a template validator definition for editing.
     ///
@@ -43,6 +46,12 @@ pub enum ValidatorCmd {
         /// The identity key of the validator to fetch.
         identity_key: String,
     },
+    /// Shows how a validator has performed at producing blocks: how many it proposed, how many
+    /// it signed or missed, how full its blocks were, and how long they took.
+    Stats {
+        /// The identity key of the validator, or this wallet's validator if omitted.
+        identity_key: Option<String>,
+    },
 }
 
 impl ValidatorCmd {
@@ -52,6 +61,7 @@ impl ValidatorCmd {
             ValidatorCmd::UploadDefinition { .. } => true,
             ValidatorCmd::TemplateDefinition { .. } => false,
             ValidatorCmd::FetchDefinition { .. } => false,
+            ValidatorCmd::Stats { .. } => false,
         }
     }
 
@@ -179,6 +189,65 @@ impl ValidatorCmd {
                     .write_all(&serde_json::to_vec_pretty(&validator)?)
                     .context("could not write file")?;
             }
+            ValidatorCmd::Stats { identity_key } => {
+                let identity_key = match identity_key {
+                    Some(identity_key) => identity_key.parse::<IdentityKey>()?,
+                    None => IdentityKey(fvk.spend_verification_key().clone()),
+                };
+
+                use penumbra_proto::client::specific::ValidatorBlockStatsRequest;
+                let mut client = app.specific_client().await?;
+                let stats: BlockStats = client
+                    .validator_block_stats(ValidatorBlockStatsRequest {
+                        chain_id: String::new(),
+                        identity_key: Some(identity_key.into()),
+                    })
+                    .await?
+                    .into_inner()
+                    .try_into()?;
+
+                let signed_share = match stats.blocks_signed + stats.blocks_missed {
+                    0 => "-".to_string(),
+                    total => format!("{:.2}%", 100.0 * stats.blocks_signed as f64 / total as f64),
+                };
+                let average = |average: Option<f64>| {
+                    average.map_or_else(|| "-".to_string(), |a| format!("{:.1}", a))
+                };
+                let latency = |percentile: u8| {
+                    stats.latency_percentile(percentile).map_or_else(
+                        || "-".to_string(),
+                        |latency| format!("{} ms", latency.as_millis()),
+                    )
+                };
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.add_row(vec![
+                    "Blocks proposed".to_string(),
+                    stats.blocks_proposed.to_string(),
+                ]);
+                table.add_row(vec![
+                    "Blocks signed".to_string(),
+                    stats.blocks_signed.to_string(),
+                ]);
+                table.add_row(vec![
+                    "Blocks missed".to_string(),
+                    stats.blocks_missed.to_string(),
+                ]);
+                table.add_row(vec!["Signed".to_string(), signed_share]);
+                table.add_row(vec![
+                    "Average transactions per block".to_string(),
+                    average(stats.average_block_transactions()),
+                ]);
+                table.add_row(vec![
+                    "Average block size (bytes)".to_string(),
+                    average(stats.average_block_bytes()),
+                ]);
+                table.add_row(vec!["Latency p50".to_string(), latency(50)]);
+                table.add_row(vec!["Latency p90".to_string(), latency(90)]);
+                table.add_row(vec!["Latency p99".to_string(), latency(99)]);
+                println!("{}", table);
+            }
         }
 
         Ok(())
@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use comfy_table::{presets, Table};
+use futures::StreamExt;
+use penumbra_crypto::{asset, note, Amount};
+use penumbra_proto::view::NotesRequest;
+use penumbra_view::{NoteEvent, ViewClient};
+
+use crate::{message::Message, App};
+
+#[derive(Debug, clap::Subcommand)]
+pub enum ViewCmd {
+    /// Watches the balance of an asset, running a command when it crosses a threshold.
+    ///
+    /// The balance is checked as each note of the asset is detected or spent,
+    /// so that no crossing is missed, even if the balance moves back within a
+    /// single block. The command is run with `sh -c` each time the balance
+    /// moves past a threshold, with the environment variables
+    /// `PCLI_ALERT_ASSET`, `PCLI_ALERT_BALANCE`, and `PCLI_ALERT_HEIGHT` set to
+    /// the asset, its new balance, and the height of the block which moved it.
+    /// It is not run again until the balance has moved back across the
+    /// threshold.
+    Alert {
+        /// The base denomination of the asset to watch, e.g. upenumbra.
+        #[clap(long)]
+        asset: String,
+        /// Run the command when the balance drops below this amount.
+        #[clap(long, required_unless_present = "above")]
+        below: Option<u64>,
+        /// Run the command when the balance rises above this amount.
+        #[clap(long)]
+        above: Option<u64>,
+        /// The command to run when a threshold is crossed.
+        #[clap(long)]
+        exec: String,
+    },
+    /// Rescans the chain from a given height, keeping the view data before it.
+    ///
+    /// This is useful after upgrading `pcli` to fix note detection, without
+    /// deleting the view data and resyncing from genesis. Scanning resumes from
+    /// the latest snapshot of the note commitment tree at or before the height,
+    /// which are taken every 1000 blocks, or from genesis if there's none.
+    Rescan {
+        /// The height to rescan from.
+        #[clap(long)]
+        from_height: u64,
+    },
+    /// Shows the balance the wallet held as of a past block.
+    ///
+    /// The balance is reconstructed from when each note in the view data was
+    /// created and spent, so it doesn't include notes which were still
+    /// quarantined at that height, nor can it reach back past the height
+    /// spent notes were pruned up to.
+    Balance {
+        /// The height of the block to show the balance as of.
+        #[clap(long)]
+        at_height: u64,
+        /// Only show the balance of this asset, by its base denomination,
+        /// e.g. upenumbra.
+        #[clap(long)]
+        asset: Option<String>,
+    },
+    /// Deletes notes spent long ago from the view data, and compacts it.
+    ///
+    /// Notes are only deleted once they were spent before the oldest
+    /// snapshot of the note commitment tree, so that rescanning never needs
+    /// them. Deleted notes no longer show up in the transactions that created
+    /// or spent them.
+    Prune {
+        /// Keep notes spent within this many blocks of the latest one synced.
+        #[clap(long, default_value = "100000")]
+        keep_spent_for_blocks: u64,
+    },
+    /// Shows how far the view service has synced, and the health of the node
+    /// it syncs from.
+    Status,
+}
+
+impl ViewCmd {
+    /// Determine if this command requires a network sync before it executes.
+    pub fn needs_sync(&self) -> bool {
+        match self {
+            ViewCmd::Alert { .. } => true,
+            // The view data was rolled back before the view service started, so the sync rescans.
+            ViewCmd::Rescan { .. } => true,
+            ViewCmd::Balance { .. } => true,
+            ViewCmd::Prune { .. } => false,
+            ViewCmd::Status => false,
+        }
+    }
+
+    pub async fn exec(&self, app: &mut App) -> Result<()> {
+        match self {
+            ViewCmd::Alert {
+                asset,
+                below,
+                above,
+                exec,
+            } => {
+                let denom = asset::REGISTRY
+                    .parse_denom(asset)
+                    .ok_or_else(|| anyhow::anyhow!("invalid asset denomination {}", asset))?;
+                let asset_id = denom.id();
+                let fvk_hash = app.fvk.hash();
+
+                // Whether the balance is past a threshold, so that we only run the command when a
+                // threshold is crossed, not on every change.
+                let mut alerting = false;
+
+                loop {
+                    // Subscribe before taking the balance, so that no change is missed in between.
+                    let mut events = ViewClient::note_stream(&mut app.view, fvk_hash).await?;
+                    let notes = ViewClient::notes(
+                        &mut app.view,
+                        NotesRequest {
+                            fvk_hash: Some(fvk_hash.into()),
+                            asset_id: Some(asset_id.into()),
+                            include_spent: false,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                    let mut unspent = notes
+                        .into_iter()
+                        .map(|record| (record.note_commitment, record.note.amount()))
+                        .collect::<BTreeMap<_, _>>();
+                    let height = ViewClient::status(&mut app.view, fvk_hash)
+                        .await?
+                        .sync_height;
+                    check_threshold(
+                        exec,
+                        &denom,
+                        *below,
+                        *above,
+                        &unspent,
+                        height,
+                        &mut alerting,
+                    )
+                    .await?;
+
+                    while let Some(event) = events.next().await {
+                        let event = match event {
+                            Ok(event) => event,
+                            // We fell too far behind the view service: take the balance afresh.
+                            Err(e) => {
+                                tracing::warn!(?e, "note stream failed, resubscribing");
+                                break;
+                            }
+                        };
+                        // Events for notes we already counted (or never counted) don't move the
+                        // balance, so they can't cross a threshold.
+                        let height = match event {
+                            NoteEvent::Detected(record) if record.note.asset_id() == asset_id => {
+                                if unspent
+                                    .insert(record.note_commitment, record.note.amount())
+                                    .is_some()
+                                {
+                                    continue;
+                                }
+                                record.height_created
+                            }
+                            NoteEvent::Spent(record) if record.note.asset_id() == asset_id => {
+                                if unspent.remove(&record.note_commitment).is_none() {
+                                    continue;
+                                }
+                                record.height_spent.unwrap_or(record.height_created)
+                            }
+                            // Notes we counted may have been forgotten or unspent: take the
+                            // balance afresh.
+                            NoteEvent::RolledBack { height } => {
+                                tracing::info!(?height, "notes rolled back, resubscribing");
+                                break;
+                            }
+                            _ => continue,
+                        };
+                        check_threshold(
+                            exec,
+                            &denom,
+                            *below,
+                            *above,
+                            &unspent,
+                            height,
+                            &mut alerting,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            ViewCmd::Rescan { .. } => {
+                // The rescan already happened in the sync.
+                println!("Rescan complete");
+            }
+            ViewCmd::Balance { at_height, asset } => {
+                let asset_id = asset
+                    .as_deref()
+                    .map(|asset| {
+                        asset::REGISTRY
+                            .parse_denom(asset)
+                            .map(|denom| denom.id())
+                            .ok_or_else(|| anyhow::anyhow!("invalid asset denomination {}", asset))
+                    })
+                    .transpose()?;
+                let balances = app
+                    .view_storage()?
+                    .balance_at_height(asset_id, *at_height, Some(0))
+                    .await?;
+                let asset_cache = app.view().assets().await?;
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec![Message::Balance]);
+                for (asset_id, amount) in balances {
+                    table.add_row(vec![asset_id
+                        .value(amount)
+                        .try_format(&asset_cache)
+                        .unwrap()]);
+                }
+                println!("{}", table);
+            }
+            ViewCmd::Prune { .. } => unreachable!("prune command already executed"),
+            ViewCmd::Status => {
+                let status = ViewClient::status(&mut app.view, app.fvk.hash()).await?;
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table
+                    .add_row(vec![
+                        "Sync Height".to_string(),
+                        status.sync_height.to_string(),
+                    ])
+                    .add_row(vec![
+                        "Latest Known Block Height".to_string(),
+                        status.latest_known_block_height.to_string(),
+                    ])
+                    .add_row(vec![
+                        "Catching Up".to_string(),
+                        status.catching_up.to_string(),
+                    ])
+                    .add_row(vec![
+                        "Notes per Second".to_string(),
+                        format!("{:.0}", status.notes_per_second),
+                    ]);
+                match status.sync_source {
+                    Some(health) => {
+                        table
+                            .add_row(vec!["Sync Source".to_string(), health.url])
+                            .add_row(vec![
+                                "Sync Source Checked At".to_string(),
+                                health.checked_at.to_string(),
+                            ])
+                            .add_row(vec![
+                                "Sync Source Health".to_string(),
+                                health.error.unwrap_or_else(|| "healthy".to_string()),
+                            ]);
+                    }
+                    None => {
+                        table.add_row(vec![
+                            "Sync Source Health".to_string(),
+                            "not yet checked".to_string(),
+                        ]);
+                    }
+                }
+                println!("{}", table);
+            }
+        }
+    }
+}
+
+/// Check the balance held in the `unspent` notes against the thresholds, running the alert command
+/// if it has just moved past one.
+async fn check_threshold(
+    exec: &str,
+    denom: &asset::Denom,
+    below: Option<u64>,
+    above: Option<u64>,
+    unspent: &BTreeMap<note::Commitment, Amount>,
+    height: u64,
+    alerting: &mut bool,
+) -> Result<()> {
+    let balance: u64 = Amount::checked_sum(unspent.values().copied())
+        .ok_or_else(|| anyhow::anyhow!("balance of {} overflowed", denom))?
+        .into();
+    tracing::debug!(?height, ?balance, "checked balance");
+
+    let past_threshold = below.map_or(false, |below| balance < below)
+        || above.map_or(false, |above| balance > above);
+
+    if past_threshold && !*alerting {
+        println!(
+            "balance of {} is {} at height {}, running alert command",
+            denom, balance, height
+        );
+        run_alert(exec, denom, balance, height).await;
+    }
+    *alerting = past_threshold;
+    Ok(())
+}
+
+/// Run the alert command, reporting (but not propagating) any failure, so that one failed alert
+/// doesn't stop us watching for the next.
+async fn run_alert(exec: &str, denom: &asset::Denom, balance: u64, height: u64) {
+    let status = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(exec)
+        .env("PCLI_ALERT_ASSET", denom.to_string())
+        .env("PCLI_ALERT_BALANCE", balance.to_string())
+        .env("PCLI_ALERT_HEIGHT", height.to_string())
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("alert command exited with {}", status),
+        Err(e) => eprintln!("could not run alert command: {}", e),
+    }
+}
@@ -1,12 +1,14 @@
-use std::str::FromStr;
+use std::{io::Write, str::FromStr};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8PathBuf;
 use directories::ProjectDirs;
 use penumbra_crypto::keys::SeedPhrase;
+use penumbra_view::FvkMismatchError;
 use rand_core::OsRng;
 use sha2::{Digest, Sha256};
 
-use crate::Wallet;
+use crate::{legacy, secrets::Secrets, Wallet};
 
 #[derive(Debug, clap::Subcommand)]
 pub enum WalletCmd {
@@ -15,6 +17,21 @@ pub enum WalletCmd {
         /// A 24 word phrase in quotes.
         seed_phrase: String,
     },
+    /// Import a spend key from a wallet file written by an earlier version of `pcli`, or from a
+    /// raw spend seed.
+    ///
+    /// Reads the current custody file format, the older `penumbra_wallet.json` client state, a
+    /// bare wallet storing a `spend_seed`, or a file containing the raw 32-byte spend seed (as
+    /// bytes or hex). If view data already exists, the imported key must match the one it was
+    /// synced for.
+    ImportLegacy {
+        /// The wallet file to import from.
+        #[clap(required_unless_present = "spend_seed")]
+        file: Option<Utf8PathBuf>,
+        /// Import this raw spend seed, given as 64 hex characters, instead of a file.
+        #[clap(long, conflicts_with = "file")]
+        spend_seed: Option<String>,
+    },
     /// Export the full viewing key for the wallet.
     ExportFvk,
     /// Generate a new seed phrase.
@@ -23,6 +40,30 @@ pub enum WalletCmd {
     Reset,
     /// Delete the entire wallet permanently.
     Delete,
+    /// Manages the secrets presented to nodes and services, like the auth token of a remote view
+    /// service, which are saved encrypted under the secrets passphrase.
+    #[clap(subcommand)]
+    Secret(SecretCmd),
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SecretCmd {
+    /// Saves a secret, replacing any saved with the same name.
+    ///
+    /// The secret is read from standard input, rather than given as an argument, so that it isn't
+    /// kept in shell history or shown in the process list. The auth token of a remote view service
+    /// is used if it's named `view-auth-token`.
+    Set {
+        /// The name of the secret.
+        name: String,
+    },
+    /// Removes a saved secret.
+    Remove {
+        /// The name of the secret.
+        name: String,
+    },
+    /// Lists the names of the saved secrets.
+    List,
 }
 
 impl WalletCmd {
@@ -30,10 +71,12 @@ impl WalletCmd {
     pub fn needs_sync(&self) -> bool {
         match self {
             WalletCmd::ImportFromPhrase { .. } => false,
+            WalletCmd::ImportLegacy { .. } => false,
             WalletCmd::ExportFvk => false,
             WalletCmd::Generate => false,
             WalletCmd::Reset => false,
             WalletCmd::Delete => false,
+            WalletCmd::Secret(_) => false,
         }
     }
 
@@ -57,7 +100,11 @@ impl WalletCmd {
         Ok(())
     }
 
-    pub fn exec(&self, data_dir: impl AsRef<camino::Utf8Path>) -> Result<()> {
+    pub async fn exec(
+        &self,
+        data_dir: impl AsRef<camino::Utf8Path>,
+        secrets_passphrase: Option<&str>,
+    ) -> Result<()> {
         let data_dir = data_dir.as_ref();
         match self {
             WalletCmd::Generate => {
@@ -79,6 +126,53 @@ impl WalletCmd {
                 wallet.save(data_dir.join(crate::CUSTODY_FILE_NAME))?;
                 self.archive_wallet(&wallet)?;
             }
+            WalletCmd::ImportLegacy { file, spend_seed } => {
+                let spend_key = match (file, spend_seed) {
+                    (_, Some(spend_seed)) => legacy::parse_spend_seed(spend_seed)?,
+                    (Some(file), None) => legacy::read_spend_key(file)?,
+                    (None, None) => unreachable!("clap requires a file or a spend seed"),
+                };
+                let fvk = spend_key.full_viewing_key();
+
+                // Refuse to import a key which doesn't match the existing view data, since the
+                // view service would refuse to start with it anyways.
+                let view_path = data_dir.join(crate::VIEW_FILE_NAME);
+                if view_path.exists() {
+                    penumbra_view::Storage::load(&view_path)
+                        .await?
+                        .check_full_viewing_key(fvk)
+                        .await
+                        .map_err(|e| {
+                            if e.is::<FvkMismatchError>() {
+                                e.context(format!(
+                                    "the view data at {} was synced for a different wallet; run `pcli wallet reset` to delete it before importing",
+                                    view_path
+                                ))
+                            } else {
+                                e
+                            }
+                        })?;
+                }
+
+                let custody_path = data_dir.join(crate::CUSTODY_FILE_NAME);
+                if custody_path.exists() {
+                    let existing = Wallet::load(&custody_path)
+                        .with_context(|| format!("could not read wallet at {}", custody_path))?;
+                    if existing.spend_key.to_bytes().0 == spend_key.to_bytes().0 {
+                        println!("Wallet at {} already has this key", custody_path);
+                        return Ok(());
+                    }
+                    return Err(anyhow!(
+                        "A different wallet already exists at {}; run `pcli wallet delete` before importing",
+                        custody_path
+                    ));
+                }
+
+                let wallet = Wallet { spend_key };
+                wallet.save(&custody_path)?;
+                println!("Imported wallet to {}", custody_path);
+                self.archive_wallet(&wallet)?;
+            }
             WalletCmd::ExportFvk => {
                 let wallet = Wallet::load(data_dir.join(crate::CUSTODY_FILE_NAME))?;
                 println!("{}", wallet.spend_key.full_viewing_key());
@@ -100,6 +194,32 @@ impl WalletCmd {
                     ));
                 }
             }
+            WalletCmd::Secret(secret_cmd) => {
+                let passphrase = secrets_passphrase.ok_or_else(|| {
+                    anyhow!("secrets are encrypted under a passphrase; set it with --secrets-passphrase or PCLI_SECRETS_PASSPHRASE")
+                })?;
+                let secrets_path = data_dir.join(crate::SECRETS_FILE_NAME);
+                let mut secrets = Secrets::load(&secrets_path, passphrase)?;
+                match secret_cmd {
+                    SecretCmd::Set { name } => {
+                        secrets.set(name, &read_secret(name)?);
+                        secrets.save(&secrets_path, passphrase)?;
+                        println!("Saved secret {:?} to {}", name, secrets_path);
+                    }
+                    SecretCmd::Remove { name } => {
+                        if !secrets.remove(name) {
+                            return Err(anyhow!("there is no secret named {:?}", name));
+                        }
+                        secrets.save(&secrets_path, passphrase)?;
+                        println!("Removed secret {:?}", name);
+                    }
+                    SecretCmd::List => {
+                        for name in secrets.names() {
+                            println!("{}", name);
+                        }
+                    }
+                }
+            }
             WalletCmd::Reset => {
                 tracing::info!("resetting client state");
                 let view_path = data_dir.join(crate::VIEW_FILE_NAME);
@@ -122,3 +242,18 @@ impl WalletCmd {
         Ok(())
     }
 }
+
+/// Prompt for the secret named `name`, and read it from a line of standard input.
+fn read_secret(name: &str) -> Result<String> {
+    // Prompt on stderr, so that the secret can be piped in without the prompt getting in the way.
+    eprint!("Secret {:?}: ", name);
+    std::io::stderr().flush()?;
+
+    let mut value = String::new();
+    std::io::stdin().read_line(&mut value)?;
+    let value = value.trim_end_matches(&['\r', '\n'][..]);
+    if value.is_empty() {
+        return Err(anyhow!("no value given for secret {:?}", name));
+    }
+    Ok(value.to_string())
+}
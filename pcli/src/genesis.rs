@@ -0,0 +1,92 @@
+//! Pinning the chain `pcli` expects to sync, so that a mistyped or impostor node is refused before
+//! any view data is built from it.
+
+use anyhow::{anyhow, Context, Result};
+use penumbra_proto::client::oblivious::{
+    oblivious_query_client::ObliviousQueryClient, ChainParamsRequest,
+};
+
+/// The chain ID and genesis app hash a user expects, either of which may be unset.
+#[derive(Debug, Clone, Default)]
+pub struct GenesisPin {
+    pub chain_id: Option<String>,
+    /// The app hash committed by the genesis state, as recorded in the header of block 1.
+    pub app_hash: Option<[u8; 32]>,
+}
+
+impl GenesisPin {
+    /// Check the chain ID of an existing view database (or remote view service) against the pin.
+    pub fn check_chain_id(&self, chain_id: &str) -> Result<()> {
+        match &self.chain_id {
+            Some(expected) if expected != chain_id => Err(anyhow!(
+                "view data is for chain {:?}, not the pinned chain {:?}",
+                chain_id,
+                expected
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the chain served by the node whose pd gRPC and Tendermint RPC endpoints are
+    /// `pd_url` and `tendermint_url` against the pin.
+    pub async fn check_node(&self, pd_url: &str, tendermint_url: &str) -> Result<()> {
+        if let Some(expected) = &self.chain_id {
+            let chain_id = ObliviousQueryClient::connect(pd_url.to_owned())
+                .await?
+                .chain_params(tonic::Request::new(ChainParamsRequest {
+                    chain_id: String::new(),
+                }))
+                .await?
+                .into_inner()
+                .chain_id;
+            if &chain_id != expected {
+                return Err(anyhow!(
+                    "node at {} serves chain {:?}, not the pinned chain {:?}",
+                    pd_url,
+                    chain_id,
+                    expected
+                ));
+            }
+        }
+
+        if let Some(expected) = &self.app_hash {
+            let app_hash = genesis_app_hash(tendermint_url).await.with_context(|| {
+                format!("could not fetch genesis app hash from {}", tendermint_url)
+            })?;
+            if &app_hash != expected {
+                return Err(anyhow!(
+                    "node at {} has genesis app hash {}, not the pinned hash {}",
+                    tendermint_url,
+                    hex::encode(app_hash),
+                    hex::encode(expected)
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a hex-encoded app hash.
+pub fn parse_app_hash(hex: &str) -> Result<[u8; 32]> {
+    hex::decode(hex)?
+        .try_into()
+        .map_err(|_| anyhow!("app hash must be 32 bytes"))
+}
+
+/// Fetch the app hash in the header of block 1, which is the one the genesis state committed to.
+async fn genesis_app_hash(tendermint_url: &str) -> Result<[u8; 32]> {
+    let rsp: serde_json::Value = reqwest::get(format!("{}/block?height=1", tendermint_url))
+        .await?
+        .json()
+        .await?;
+
+    if let Some(error) = rsp.get("error") {
+        return Err(anyhow!("could not fetch block 1: {}", error));
+    }
+    let app_hash = rsp.get("result").unwrap_or(&rsp)["block"]["header"]["app_hash"]
+        .as_str()
+        .ok_or_else(|| anyhow!("missing app hash in block 1"))?;
+
+    parse_app_hash(app_hash)
+}
@@ -1,6 +1,7 @@
 use std::path::Path;
 
-use penumbra_crypto::keys::SpendKey;
+use anyhow::{anyhow, Context};
+use penumbra_crypto::keys::{SpendKey, SpendKeyBytes};
 use serde::{Deserialize, Serialize};
 
 /// The path to the legacy wallet file (which actually stored a client state, not a wallet...)
@@ -35,6 +36,49 @@ pub fn migrate(
     Ok(())
 }
 
+/// Read a spend key from a wallet file in any format `pcli` has used, or from a raw spend seed.
+///
+/// The formats are, from newest to oldest:
+///
+/// - the current custody file, as written by [`crate::Wallet::save`];
+/// - a bare wallet, storing the hex-encoded `spend_seed`;
+/// - the legacy client state, storing a bare wallet under `wallet`;
+/// - a raw spend seed, either as 32 bytes or as 64 hex characters.
+pub fn read_spend_key(path: impl AsRef<Path>) -> anyhow::Result<SpendKey> {
+    let path = path.as_ref();
+    let data = std::fs::read(path)
+        .with_context(|| format!("could not read wallet file {}", path.display()))?;
+
+    if let Ok(wallet) = serde_json::from_slice::<crate::Wallet>(&data) {
+        return Ok(wallet.spend_key);
+    }
+    if let Ok(wallet) = serde_json::from_slice::<LegacyWallet>(&data) {
+        return Ok(wallet.spend_key);
+    }
+    if let Ok(client_state) = serde_json::from_slice::<ClientState>(&data) {
+        return Ok(client_state.wallet.spend_key);
+    }
+    if data.len() == 32 {
+        return Ok(SpendKeyBytes::try_from(data.as_slice())?.into());
+    }
+    if let Ok(hex_seed) = std::str::from_utf8(&data) {
+        if let Ok(spend_key) = parse_spend_seed(hex_seed) {
+            return Ok(spend_key);
+        }
+    }
+
+    Err(anyhow!(
+        "{} is not a wallet file in any known format, or a raw spend seed",
+        path.display()
+    ))
+}
+
+/// Parse a raw spend seed, given as 64 hex characters.
+pub fn parse_spend_seed(hex_seed: &str) -> anyhow::Result<SpendKey> {
+    let bytes = hex::decode(hex_seed.trim()).context("spend seed is not valid hex")?;
+    Ok(SpendKeyBytes::try_from(bytes.as_slice())?.into())
+}
+
 /// A legacy client state (skeleton, just enough to deserialize the keys)
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ClientState {
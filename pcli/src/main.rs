@@ -1,22 +1,31 @@
 // Rust analyzer complains without this (but rustc is happy regardless)
 #![recursion_limit = "256"]
 #![allow(clippy::clone_on_copy)]
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
 use anyhow::Result;
 use clap::Parser;
 use futures::StreamExt;
-use penumbra_crypto::FullViewingKey;
+use penumbra_crypto::{keys::DiversifierIndex, FullViewingKey};
 use penumbra_proto::{
     custody::custody_protocol_client::CustodyProtocolClient,
     view::view_protocol_client::ViewProtocolClient,
 };
-use penumbra_view::ViewClient;
+use penumbra_view::{SpotCheck, Storage, SyncConnection, ViewClient};
 use url::Url;
 
 mod box_grpc_svc;
 mod command;
+mod genesis;
 mod legacy;
+mod message;
 mod network;
 mod opt;
+mod profile;
+mod secrets;
 mod wallet;
 mod warning;
 
@@ -28,15 +37,27 @@ use command::*;
 
 const CUSTODY_FILE_NAME: &str = "custody.json";
 const VIEW_FILE_NAME: &str = "pcli-view.sqlite";
+const SECRETS_FILE_NAME: &str = "secrets.json";
+const SYNC_BOOST_DURATION: Duration = Duration::from_secs(60);
+const SYNC_PROGRESS_TEMPLATE: &str =
+    "[{elapsed}] {bar:50.cyan/blue} {pos:>7}/{len:7} {per_sec} ETA: {eta} {msg}";
+const DESYNCED_ERROR: &str = "view data has diverged from the node's chain; roll it back with `pcli view rescan --from-height <HEIGHT>`";
 
 #[derive(Debug)]
 pub struct App {
     pub view: ViewProtocolClient<BoxGrpcService>,
+    pub spot_check: Option<SpotCheck>,
     pub custody: CustodyProtocolClient<BoxGrpcService>,
     pub fvk: FullViewingKey,
     pub wallet: Wallet,
     pub pd_url: Url,
+    /// The tendermint RPC endpoint transactions are submitted to, which may be a different node
+    /// than the one the view service syncs from.
     pub tendermint_url: Url,
+    /// The storage of the local view service, or `None` if a remote view service is used.
+    pub view_storage: Option<Storage>,
+    /// Set once the node at `pd_url` has been checked to serve the same chain as the view service.
+    pub pd_chain_id_checked: tokio::sync::OnceCell<()>,
 }
 
 impl App {
@@ -44,7 +65,37 @@ impl App {
         &mut self.view
     }
 
+    /// The local view data, which also keeps what only this wallet knows, like the address book.
+    pub fn view_storage(&self) -> Result<&Storage> {
+        self.view_storage.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("there is no local view data, since a remote view service is used")
+        })
+    }
+
+    /// The label of each labeled address index, which are only kept in the local view data, so
+    /// there are none if a remote view service is used.
+    pub async fn address_labels(&self) -> Result<BTreeMap<DiversifierIndex, String>> {
+        match &self.view_storage {
+            Some(storage) => storage.labels(0).await,
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// The index each labeled address index is grouped under, with the other indices sharing its
+    /// label, which like the labels are only kept in the local view data.
+    pub async fn address_groups(&self) -> Result<BTreeMap<DiversifierIndex, DiversifierIndex>> {
+        match &self.view_storage {
+            Some(storage) => storage.label_groups(0).await,
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
     async fn sync(&mut self) -> Result<()> {
+        // We're waiting on the sync, so ask the view service to scan as fast as it can, renewing
+        // the boost until the sync finishes.
+        ViewClient::boost_sync(&mut self.view, self.fvk.hash(), SYNC_BOOST_DURATION).await?;
+        let mut boosted_at = Instant::now();
+
         let mut status_stream = ViewClient::status_stream(&mut self.view, self.fvk.hash()).await?;
 
         // Pull out the first message from the stream, which has the current state, and use
@@ -54,6 +105,9 @@ impl App {
             .await
             .transpose()?
             .ok_or_else(|| anyhow::anyhow!("view service did not report sync status"))?;
+        if initial_status.connection == SyncConnection::Desynced {
+            return Err(anyhow::anyhow!(DESYNCED_ERROR));
+        }
 
         println!(
             "Scanning blocks from last sync height {} to latest height {}",
@@ -65,17 +119,48 @@ impl App {
             initial_status.latest_known_block_height - initial_status.sync_height,
             ProgressDrawTarget::stdout(),
         )
-        .with_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed}] {bar:50.cyan/blue} {pos:>7}/{len:7} {per_sec} ETA: {eta}"),
-        );
+        .with_style(ProgressStyle::default_bar().template(SYNC_PROGRESS_TEMPLATE));
         progress_bar.set_position(0);
 
+        let mut connection = initial_status.connection;
         while let Some(status) = status_stream.next().await.transpose()? {
             progress_bar.set_position(status.sync_height - initial_status.sync_height);
+            progress_bar.set_message(format!("({:.0} notes/sec)", status.notes_per_second));
+            match status.connection {
+                SyncConnection::Reconnecting if connection != SyncConnection::Reconnecting => {
+                    progress_bar.println("lost connection to the node, reconnecting...")
+                }
+                SyncConnection::Connected | SyncConnection::Reconnecting => {}
+                SyncConnection::Desynced => {
+                    progress_bar.abandon();
+                    return Err(anyhow::anyhow!(DESYNCED_ERROR));
+                }
+            }
+            connection = status.connection;
+            if boosted_at.elapsed() > SYNC_BOOST_DURATION / 2 {
+                ViewClient::boost_sync(&mut self.view, self.fvk.hash(), SYNC_BOOST_DURATION)
+                    .await?;
+                boosted_at = Instant::now();
+            }
         }
         progress_bar.finish();
 
+        // If we're spot checking a remote view service, check a sample of the notes it serves, and
+        // the balances it reports for them.
+        if let Some(spot_check) = &mut self.spot_check {
+            let notes = self
+                .view
+                .notes(penumbra_proto::view::NotesRequest {
+                    fvk_hash: Some(self.fvk.hash().into()),
+                    include_spent: true,
+                    ..Default::default()
+                })
+                .await?;
+            spot_check.check_notes(&notes).await?;
+            let balances = ViewClient::balances(&mut self.view, self.fvk.hash(), None).await?;
+            spot_check.check_balances(&notes, &balances).await?;
+        }
+
         Ok(())
     }
 }
@@ -92,12 +177,45 @@ async fn main() -> Result<()> {
     // Initialize tracing here, rather than when converting into an `App`, so
     // that tracing is set up even for wallet commands that don't build the `App`.
     opt.init_tracing();
+    opt.lang.set();
+    opt.load_profile()?;
 
     // The wallet command takes the data dir directly, since it may need to
     // create the client state, so handle it specially here so that we can have
     // common code for the other subcommands.
     if let Command::Wallet(wallet_cmd) = &opt.cmd {
-        wallet_cmd.exec(opt.data_path.as_path())?;
+        wallet_cmd
+            .exec(opt.data_path.as_path(), opt.secrets_passphrase.as_deref())
+            .await?;
+        return Ok(());
+    }
+
+    // The init command also needs to create the client state, and then picks
+    // the network profile to sync from, so it's handled specially too.
+    if let Command::Init(init_cmd) = &opt.cmd {
+        let profile = init_cmd
+            .exec(opt.data_path.as_path(), &opt.profile())
+            .await?;
+        opt.use_profile(profile);
+        let (mut app, _) = opt.into_app().await?;
+        app.sync().await?;
+        println!("pcli is ready to use!");
+        return Ok(());
+    }
+
+    // Rescanning rolls back the view data, which must happen before the view service is started
+    // over it, so it's handled specially too.
+    if let Command::View(ViewCmd::Rescan { from_height }) = &opt.cmd {
+        opt.roll_back_view(*from_height).await?;
+    }
+
+    // Pruning needs the lease on writing the view data, which the view service's worker would
+    // hold, so it's handled before the view service is started, like rescanning.
+    if let Command::View(ViewCmd::Prune {
+        keep_spent_for_blocks,
+    }) = &opt.cmd
+    {
+        opt.prune_view(*keep_spent_for_blocks).await?;
         return Ok(());
     }
 
@@ -113,16 +231,25 @@ async fn main() -> Result<()> {
 
     match &cmd {
         Command::Wallet(_) => unreachable!("wallet command already executed"),
+        Command::Init(_) => unreachable!("init command already executed"),
         Command::Sync => {
             // We have already synchronized the wallet above, so we can just return.
         }
         Command::Tx(tx_cmd) => tx_cmd.exec(&mut app).await?,
-        Command::Addr(addr_cmd) => addr_cmd.exec(&app.fvk)?,
-        Command::Balance(balance_cmd) => balance_cmd.exec(&app.fvk, &mut app.view).await?,
+        Command::Addr(addr_cmd) => addr_cmd.exec(&app).await?,
+        Command::Balance(balance_cmd) => {
+            let labels = app.address_labels().await?;
+            let groups = app.address_groups().await?;
+            balance_cmd
+                .exec(&app.fvk, &mut app.view, &labels, &groups)
+                .await?
+        }
         Command::Validator(cmd) => cmd.exec(&mut app).await?,
         Command::Stake(cmd) => cmd.exec(&mut app).await?,
         Command::Chain(cmd) => cmd.exec(&mut app).await?,
+        Command::View(cmd) => cmd.exec(&mut app).await?,
         Command::Q(cmd) => cmd.exec(&mut app).await?,
+        Command::Daemon(cmd) => cmd.exec(app).await?,
     }
 
     Ok(())
@@ -0,0 +1,317 @@
+//! The catalog of user-facing output of the transaction, balance, and staking commands, in each
+//! language `pcli` speaks.
+//!
+//! Commands print a [`Message`] rather than a string literal, and its [`Display`](fmt::Display)
+//! impl renders it in the language chosen with `--lang`, so adding a language means adding a
+//! method rendering every message in it. Errors and log messages aren't translated, so that they
+//! can still be searched for.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use anyhow::anyhow;
+
+/// A language `pcli`'s output can be shown in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    English,
+    Spanish,
+}
+
+/// The language every [`Message`] is rendered in, as its index among the [`Lang`] variants.
+static LANG: AtomicU8 = AtomicU8::new(0);
+
+impl Lang {
+    const ALL: [Lang; 2] = [Lang::English, Lang::Spanish];
+
+    /// Render every message in this language from now on.
+    pub fn set(self) {
+        let index = Self::ALL
+            .iter()
+            .position(|&lang| lang == self)
+            .expect("every language is listed");
+        LANG.store(index as u8, Ordering::Relaxed);
+    }
+
+    /// The language messages are rendered in.
+    pub fn current() -> Self {
+        Self::ALL[LANG.load(Ordering::Relaxed) as usize]
+    }
+}
+
+impl FromStr for Lang {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "en" => Ok(Lang::English),
+            "es" => Ok(Lang::Spanish),
+            _ => Err(anyhow!(
+                "unsupported language {:?}, expected one of en or es",
+                s
+            )),
+        }
+    }
+}
+
+/// A message shown to the user, along with the values it mentions, which are formatted by the
+/// caller.
+#[derive(Clone, Debug)]
+pub enum Message {
+    // Submitting transactions.
+    PreCheckingTransaction,
+    BroadcastingTransaction,
+    ConfirmingTransaction,
+    TransactionConfirmed,
+    TransactionSubmitted,
+    RebuildingTransaction,
+
+    // Sweeping.
+    BuildingSweep {
+        index: usize,
+        count: usize,
+    },
+    FinishedSweeping,
+    AwaitingConfirmations,
+
+    // Decoded transactions.
+    ChainId,
+    ExpiryHeight,
+    Anchor,
+    Action,
+    Details,
+    Spend,
+    SpendDetails {
+        nullifier: String,
+    },
+    Output,
+    OutputDetails {
+        value: String,
+        index: String,
+        memo: String,
+        return_address: Option<String>,
+    },
+    OutputNotOurs {
+        note_commitment: String,
+    },
+    UnknownIndex,
+    Delegate,
+    DelegateDetails {
+        amount: u64,
+        validator: String,
+    },
+    Undelegate,
+    UndelegateDetails {
+        amount: u64,
+        validator: String,
+    },
+    ValidatorDefinition,
+    IbcAction,
+
+    // Balances.
+    Asset,
+    Balance,
+    Spent,
+    Fee,
+    ResultingBalance,
+    AddressIndex,
+    Amount,
+    Locked,
+    Released,
+    Total,
+    UnbondingUntil {
+        epoch: u64,
+    },
+    ReleasedAt {
+        epoch: u64,
+        height: u64,
+    },
+
+    // Staking.
+    Name,
+    Value,
+    ExchangeRate,
+    Tokens,
+    UnbondedStake,
+    VotingPower,
+    Share,
+    Commission,
+    State,
+    BondingState,
+    ValidatorInfo,
+}
+
+impl Message {
+    fn english(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::PreCheckingTransaction => write!(f, "pre-checking transaction..."),
+            Message::BroadcastingTransaction => write!(f, "broadcasting transaction..."),
+            // Two spaces make the ellipsis line up with the messages before it.
+            Message::ConfirmingTransaction => write!(f, "confirming transaction  ..."),
+            Message::TransactionConfirmed => write!(f, "transaction confirmed and detected"),
+            Message::TransactionSubmitted => write!(f, "transaction submitted successfully"),
+            Message::RebuildingTransaction => {
+                write!(
+                    f,
+                    "anchor expired, rebuilding transaction with a fresh witness..."
+                )
+            }
+            Message::BuildingSweep { index, count } => {
+                write!(f, "building sweep {} of {}", index, count)
+            }
+            Message::FinishedSweeping => write!(f, "finished sweeping"),
+            Message::AwaitingConfirmations => write!(f, "awaiting confirmations..."),
+            Message::ChainId => write!(f, "Chain ID"),
+            Message::ExpiryHeight => write!(f, "Expiry Height"),
+            Message::Anchor => write!(f, "Anchor"),
+            Message::Action => write!(f, "Action"),
+            Message::Details => write!(f, "Details"),
+            Message::Spend => write!(f, "Spend"),
+            Message::SpendDetails { nullifier } => write!(f, "nullifier {}", nullifier),
+            Message::Output => write!(f, "Output"),
+            Message::OutputDetails {
+                value,
+                index,
+                memo,
+                return_address,
+            } => {
+                write!(f, "{} to address {}, memo: {:?}", value, index, memo)?;
+                if let Some(return_address) = return_address {
+                    write!(f, ", return address: {}", return_address)?;
+                }
+                Ok(())
+            }
+            Message::OutputNotOurs { note_commitment } => {
+                write!(f, "note commitment {} (not ours)", note_commitment)
+            }
+            Message::UnknownIndex => write!(f, "unknown index"),
+            Message::Delegate => write!(f, "Delegate"),
+            Message::DelegateDetails { amount, validator } => {
+                write!(f, "{} upenumbra to {}", amount, validator)
+            }
+            Message::Undelegate => write!(f, "Undelegate"),
+            Message::UndelegateDetails { amount, validator } => {
+                write!(f, "{} upenumbra from {}", amount, validator)
+            }
+            Message::ValidatorDefinition => write!(f, "Validator Definition"),
+            Message::IbcAction => write!(f, "IBC Action"),
+            Message::Asset => write!(f, "Asset"),
+            Message::Balance => write!(f, "Balance"),
+            Message::Spent => write!(f, "Spent"),
+            Message::Fee => write!(f, "Fee"),
+            Message::ResultingBalance => write!(f, "Resulting Balance"),
+            Message::AddressIndex => write!(f, "Addr Index"),
+            Message::Amount => write!(f, "Amount"),
+            Message::Locked => write!(f, "Locked (unbonding)"),
+            Message::Released => write!(f, "Released"),
+            Message::Total => write!(f, "Total"),
+            Message::UnbondingUntil { epoch } => write!(f, "unbonding until epoch {}", epoch),
+            Message::ReleasedAt { epoch, height } => {
+                write!(f, "epoch {} (height {})", epoch, height)
+            }
+            Message::Name => write!(f, "Name"),
+            Message::Value => write!(f, "Value"),
+            Message::ExchangeRate => write!(f, "Exch. Rate"),
+            Message::Tokens => write!(f, "Tokens"),
+            Message::UnbondedStake => write!(f, "Unbonded Stake"),
+            Message::VotingPower => write!(f, "Voting Power"),
+            Message::Share => write!(f, "Share"),
+            Message::Commission => write!(f, "Commission"),
+            Message::State => write!(f, "State"),
+            Message::BondingState => write!(f, "Bonding State"),
+            Message::ValidatorInfo => write!(f, "Validator Info"),
+        }
+    }
+
+    fn spanish(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::PreCheckingTransaction => write!(f, "verificando la transacción..."),
+            Message::BroadcastingTransaction => write!(f, "difundiendo la transacción..."),
+            Message::ConfirmingTransaction => write!(f, "confirmando la transacción..."),
+            Message::TransactionConfirmed => write!(f, "transacción confirmada y detectada"),
+            Message::TransactionSubmitted => write!(f, "transacción enviada correctamente"),
+            Message::RebuildingTransaction => write!(
+                f,
+                "el ancla ha caducado, reconstruyendo la transacción con un testigo nuevo..."
+            ),
+            Message::BuildingSweep { index, count } => {
+                write!(f, "construyendo el barrido {} de {}", index, count)
+            }
+            Message::FinishedSweeping => write!(f, "barrido terminado"),
+            Message::AwaitingConfirmations => write!(f, "esperando confirmaciones..."),
+            Message::ChainId => write!(f, "ID de cadena"),
+            Message::ExpiryHeight => write!(f, "Altura de vencimiento"),
+            Message::Anchor => write!(f, "Ancla"),
+            Message::Action => write!(f, "Acción"),
+            Message::Details => write!(f, "Detalles"),
+            Message::Spend => write!(f, "Gasto"),
+            Message::SpendDetails { nullifier } => write!(f, "anulador {}", nullifier),
+            Message::Output => write!(f, "Salida"),
+            Message::OutputDetails {
+                value,
+                index,
+                memo,
+                return_address,
+            } => {
+                write!(f, "{} a la dirección {}, memo: {:?}", value, index, memo)?;
+                if let Some(return_address) = return_address {
+                    write!(f, ", dirección de retorno: {}", return_address)?;
+                }
+                Ok(())
+            }
+            Message::OutputNotOurs { note_commitment } => {
+                write!(f, "compromiso de nota {} (ajena)", note_commitment)
+            }
+            Message::UnknownIndex => write!(f, "índice desconocido"),
+            Message::Delegate => write!(f, "Delegación"),
+            Message::DelegateDetails { amount, validator } => {
+                write!(f, "{} upenumbra a {}", amount, validator)
+            }
+            Message::Undelegate => write!(f, "Retiro de delegación"),
+            Message::UndelegateDetails { amount, validator } => {
+                write!(f, "{} upenumbra de {}", amount, validator)
+            }
+            Message::ValidatorDefinition => write!(f, "Definición de validador"),
+            Message::IbcAction => write!(f, "Acción IBC"),
+            Message::Asset => write!(f, "Activo"),
+            Message::Balance => write!(f, "Saldo"),
+            Message::Spent => write!(f, "Gastado"),
+            Message::Fee => write!(f, "Comisión"),
+            Message::ResultingBalance => write!(f, "Saldo resultante"),
+            Message::AddressIndex => write!(f, "Índice de dir."),
+            Message::Amount => write!(f, "Cantidad"),
+            Message::Locked => write!(f, "Bloqueado (desvinculando)"),
+            Message::Released => write!(f, "Liberación"),
+            Message::Total => write!(f, "Total"),
+            Message::UnbondingUntil { epoch } => {
+                write!(f, "desvinculando hasta la época {}", epoch)
+            }
+            Message::ReleasedAt { epoch, height } => {
+                write!(f, "época {} (altura {})", epoch, height)
+            }
+            Message::Name => write!(f, "Nombre"),
+            Message::Value => write!(f, "Valor"),
+            Message::ExchangeRate => write!(f, "Tipo de cambio"),
+            Message::Tokens => write!(f, "Tokens"),
+            Message::UnbondedStake => write!(f, "Participación no vinculada"),
+            Message::VotingPower => write!(f, "Poder de voto"),
+            Message::Share => write!(f, "Proporción"),
+            Message::Commission => write!(f, "Comisión"),
+            Message::State => write!(f, "Estado"),
+            Message::BondingState => write!(f, "Estado de vinculación"),
+            Message::ValidatorInfo => write!(f, "Información del validador"),
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Lang::current() {
+            Lang::English => self.english(f),
+            Lang::Spanish => self.spanish(f),
+        }
+    }
+}
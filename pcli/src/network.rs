@@ -1,49 +1,227 @@
+use std::collections::BTreeMap;
+
 use anyhow::{Context as _, Result};
+use comfy_table::{presets, Table};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use penumbra_chain::{ErrorCode, CODESPACE};
 use penumbra_component::Context;
-use penumbra_crypto::note;
+use penumbra_crypto::{asset, note, STAKING_TOKEN_ASSET_ID};
+use penumbra_custody::{AuthorizeRequest, CustodyClient};
 use penumbra_proto::{
     client::{
-        oblivious::oblivious_query_client::ObliviousQueryClient,
+        oblivious::{oblivious_query_client::ObliviousQueryClient, ChainParamsRequest},
         specific::specific_query_client::SpecificQueryClient,
     },
     Protobuf,
 };
 use penumbra_transaction::{plan::TransactionPlan, Transaction};
 use penumbra_view::ViewClient;
+use penumbra_wallet::plan;
 use rand::Rng;
 use rand_core::OsRng;
 use std::future::Future;
 use tonic::transport::Channel;
 use tracing::instrument;
 
-use crate::App;
+use crate::{message::Message, App};
 
 impl App {
+    /// Builds and submits the transaction described by `plan`, returning the submitted transaction.
     pub async fn build_and_submit_transaction(
         &mut self,
         plan: TransactionPlan,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<Transaction> {
         let self_addressed_output = plan
             .output_plans()
             .find(|output| output.is_viewed_by(self.fvk.incoming()))
             .map(|output| output.output_note().commit());
 
-        let tx = self.build_transaction(plan).await?;
+        if let Err(e) = self.preflight(&plan).await {
+            self.release_notes([&plan]).await;
+            return Err(e);
+        }
+
+        let mut rebuilt = false;
+        loop {
+            let tx = match self.build_transaction(plan.clone()).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    self.release_notes([&plan]).await;
+                    return Err(e);
+                }
+            };
+
+            match self.submit_transaction(&tx, self_addressed_output).await {
+                // If the anchor expired while the proofs were being generated, or the view
+                // service was behind, building the plan again witnesses it against the latest
+                // anchor. Only do this once, so that a view service which is stuck fails.
+                Err(e) if !rebuilt && e.is::<AnchorExpired>() => {
+                    tracing::info!(?e, "rebuilding transaction");
+                    println!("{}", Message::RebuildingTransaction);
+                    rebuilt = true;
+                }
+                result => return result.map(|()| tx),
+            }
+        }
+    }
+
+    /// Checks `plan` with [`preflight`](App::preflight) and builds it, for callers which submit
+    /// the transaction themselves, releasing the notes it reserved if either step fails.
+    pub async fn preflight_and_build_transaction(
+        &mut self,
+        plan: &TransactionPlan,
+    ) -> anyhow::Result<Transaction> {
+        let result = match self.preflight(plan).await {
+            Ok(()) => self.build_transaction(plan.clone()).await,
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            self.release_notes([plan]).await;
+        }
+        result
+    }
+
+    /// Releases the notes reserved by `plans`, which won't be submitted, so that they can be
+    /// selected into other plans right away.
+    ///
+    /// This is best-effort: the reservations lapse on their own anyway, so failures are only
+    /// logged.
+    pub async fn release_notes<'a>(
+        &mut self,
+        plans: impl IntoIterator<Item = &'a TransactionPlan>,
+    ) {
+        for plan in plans {
+            if let Err(e) = plan::release(&self.fvk, &mut self.view, plan).await {
+                tracing::warn!(?e, "could not release notes reserved by plan");
+            }
+        }
+    }
+
+    /// Prints how `plan` changes the wallet's balance of each asset it spends or receives, failing
+    /// with the shortfall of each asset whose balance doesn't cover it.
+    pub async fn preflight(&mut self, plan: &TransactionPlan) -> Result<()> {
+        let fvk_hash = self.fvk.hash();
+        let asset_cache = ViewClient::assets(&mut self.view).await?;
+        // This includes the notes the plan reserved, which are still unspent.
+        let notes = self
+            .view
+            .unspent_notes_by_asset_and_address(fvk_hash)
+            .await?;
+
+        // The net amount of each asset leaving the wallet, including the fee.
+        let mut outflows = BTreeMap::<asset::Id, i128>::new();
+        for spend in plan.spend_plans() {
+            *outflows.entry(spend.note.asset_id()).or_default() +=
+                spend.note.amount().value() as i128;
+        }
+        for output in plan
+            .output_plans()
+            .filter(|output| self.fvk.incoming().views_address(&output.dest_address))
+        {
+            *outflows.entry(output.value.asset_id).or_default() -= output.value.amount as i128;
+        }
+
+        let mut table = Table::new();
+        table.load_preset(presets::NOTHING);
+        table.set_header(vec![
+            Message::Asset,
+            Message::Balance,
+            Message::Spent,
+            Message::Fee,
+            Message::ResultingBalance,
+        ]);
+        let mut shortfalls = Vec::new();
+        for (asset_id, outflow) in outflows {
+            let balance: i128 = notes
+                .get(&asset_id)
+                .into_iter()
+                .flat_map(|notes_by_address| notes_by_address.values().flatten())
+                .map(|record| record.note.amount().value() as i128)
+                .sum();
+            let fee = if asset_id == *STAKING_TOKEN_ASSET_ID {
+                plan.fee.0 as i128
+            } else {
+                0
+            };
+            let resulting = balance - outflow;
+            let name = asset_cache
+                .get(&asset_id)
+                .map_or_else(|| asset_id.to_string(), |denom| denom.to_string());
 
-        self.submit_transaction(&tx, self_addressed_output).await
+            if resulting < 0 {
+                shortfalls.push(format!(
+                    "{} short by {}",
+                    name,
+                    format_amount(asset_id, -resulting, &asset_cache)
+                ));
+            }
+            table.add_row(vec![
+                name,
+                format_amount(asset_id, balance, &asset_cache),
+                format_amount(asset_id, outflow - fee, &asset_cache),
+                format_amount(asset_id, fee, &asset_cache),
+                format_amount(asset_id, resulting, &asset_cache),
+            ]);
+        }
+        println!("{}", table);
+
+        if !shortfalls.is_empty() {
+            return Err(anyhow::anyhow!(
+                "insufficient funds: {}",
+                shortfalls.join("; ")
+            ));
+        }
+
+        Ok(())
     }
 
+    /// Builds the transaction described by `plan`, showing a progress bar while its proofs are
+    /// generated.
     pub fn build_transaction<'a>(
         &'a mut self,
         plan: TransactionPlan,
     ) -> impl Future<Output = Result<Transaction>> + 'a {
-        penumbra_wallet::build_transaction(
-            &self.fvk,
-            &mut self.view,
-            &mut self.custody,
-            OsRng,
-            plan,
-        )
+        let proof_count = plan.spend_plans().count() + plan.output_plans().count();
+        let progress_bar =
+            ProgressBar::with_draw_target(proof_count as u64, ProgressDrawTarget::stdout())
+                .with_style(
+                    ProgressStyle::default_bar()
+                        .template("[{elapsed}] {bar:50.cyan/blue} {pos:>3}/{len:3} proofs {msg}"),
+                );
+
+        async move {
+            let auth_data = CustodyClient::authorize(
+                &mut self.custody,
+                AuthorizeRequest {
+                    fvk_hash: self.fvk.hash(),
+                    plan: plan.clone(),
+                },
+            )
+            .await?;
+            let witness_data = penumbra_wallet::witness(&self.fvk, &mut self.view, &plan).await?;
+
+            // If we're spot checking a remote view service, make sure the anchor it witnessed our
+            // spends against is real, and that the authentication paths verify against it.
+            if let Some(spot_check) = &mut self.spot_check {
+                spot_check.check_witness(&witness_data).await?;
+            }
+
+            let tx = plan.build_with_progress(
+                &mut OsRng,
+                &self.fvk,
+                auth_data,
+                witness_data,
+                |progress| {
+                    progress_bar.set_position(progress.completed as u64);
+                    progress_bar.set_message(format!(
+                        "({:?} proof took {:.2?})",
+                        progress.kind, progress.elapsed
+                    ));
+                },
+            );
+            progress_bar.finish_and_clear();
+            tx
+        }
     }
 
     /// Submits a transaction to the network.
@@ -58,13 +236,15 @@ impl App {
         transaction: &Transaction,
         await_detection_of: Option<note::Commitment>,
     ) -> Result<(), anyhow::Error> {
-        println!("pre-checking transaction...");
+        println!("{}", Message::PreCheckingTransaction);
         use penumbra_component::Component;
         let ctx = Context::new();
         pd::App::check_tx_stateless(ctx.clone(), transaction)
             .context("transaction pre-submission checks failed")?;
 
-        println!("broadcasting transaction...");
+        self.check_submit_node().await?;
+
+        println!("{}", Message::BroadcastingTransaction);
 
         let client = reqwest::Client::new();
         let req_id: u8 = rand::thread_rng().gen();
@@ -97,17 +277,37 @@ impl App {
                 .get("log")
                 .and_then(|l| l.as_str())
                 .ok_or_else(|| anyhow::anyhow!("could not parse JSON response"))?;
+            // Codes outside Penumbra's codespace are Tendermint's own, e.g., when its mempool is
+            // full; nodes which didn't report a codespace only used Penumbra's codes.
+            let error_code = match result.get("codespace").and_then(|c| c.as_str()) {
+                None | Some("") | Some(CODESPACE) => {
+                    u32::try_from(code).ok().and_then(ErrorCode::from_code)
+                }
+                Some(_) => None,
+            };
 
-            return Err(anyhow::anyhow!(
-                "Error submitting transaction: code {}, log: {}",
-                code,
-                log
-            ));
+            if error_code == Some(ErrorCode::ExpiredAnchor) {
+                return Err(AnchorExpired {
+                    error: rejection_error(log),
+                }
+                .into());
+            }
+
+            return Err(match error_code {
+                Some(error_code) => anyhow::anyhow!(
+                    "Error submitting transaction: {} (code {}): {}",
+                    error_code,
+                    code,
+                    rejection_error(log)
+                ),
+                None => {
+                    anyhow::anyhow!("Error submitting transaction: code {}, log: {}", code, log)
+                }
+            });
         }
 
         if let Some(note_commitment) = await_detection_of {
-            // putting two spaces in makes the ellipsis line up with the above
-            println!("confirming transaction  ...");
+            println!("{}", Message::ConfirmingTransaction);
             let fvk_hash = self.fvk.hash();
             tokio::time::timeout(
                 std::time::Duration::from_secs(20),
@@ -117,9 +317,9 @@ impl App {
             .await
             .context("timeout waiting to detect outputs of submitted transaction")?
             .context("error while waiting for detection of submitted transaction")?;
-            println!("transaction confirmed and detected");
+            println!("{}", Message::TransactionConfirmed);
         } else {
-            println!("transaction submitted successfully");
+            println!("{}", Message::TransactionSubmitted);
         }
 
         Ok(())
@@ -133,7 +333,9 @@ impl App {
         &self,
         transaction: &Transaction,
     ) -> Result<(), anyhow::Error> {
-        println!("broadcasting transaction...");
+        self.check_submit_node().await?;
+
+        println!("{}", Message::BroadcastingTransaction);
 
         let client = reqwest::Client::new();
         let req_id: u8 = rand::thread_rng().gen();
@@ -156,15 +358,141 @@ impl App {
         Ok(())
     }
 
+    /// Checks that the node we submit transactions to is healthy and caught up, independently of
+    /// the node the view service syncs from, so that a transaction isn't broadcast into a node
+    /// that can't relay it.
+    #[instrument(skip(self))]
+    pub async fn check_submit_node(&self) -> Result<(), anyhow::Error> {
+        let rsp: serde_json::Value = reqwest::get(self.tendermint_url.join("status")?)
+            .await
+            .and_then(|rsp| rsp.error_for_status())
+            .with_context(|| format!("submit node {} is unreachable", self.tendermint_url))?
+            .json()
+            .await?;
+
+        tracing::debug!("{}", rsp);
+
+        let catching_up = rsp
+            .get("result")
+            .unwrap_or(&rsp)
+            .get("sync_info")
+            .and_then(|sync_info| sync_info.get("catching_up"))
+            .and_then(|c| c.as_bool())
+            .ok_or_else(|| anyhow::anyhow!("could not parse catching_up in JSON response"))?;
+
+        if catching_up {
+            return Err(anyhow::anyhow!(
+                "submit node {} is still catching up with the chain",
+                self.tendermint_url
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fetches a committed transaction from the network by its hash.
+    #[instrument(skip(self))]
+    pub async fn fetch_transaction(&self, tx_hash: &str) -> Result<Transaction, anyhow::Error> {
+        #[derive(serde::Deserialize)]
+        struct TxResult {
+            #[serde(with = "penumbra_proto::serializers::base64str")]
+            tx: Vec<u8>,
+        }
+
+        let tx_hash = hex::decode(tx_hash.trim_start_matches("0x"))
+            .context("transaction hash is not valid hex")?;
+
+        let mut url = self.tendermint_url.join("tx")?;
+        url.query_pairs_mut()
+            .append_pair("hash", &format!("0x{}", hex::encode(&tx_hash)));
+        let rsp: serde_json::Value = reqwest::get(url).await?.json().await?;
+
+        tracing::debug!("{}", rsp);
+
+        if let Some(error) = rsp.get("error") {
+            return Err(anyhow::anyhow!("could not fetch transaction: {}", error));
+        }
+        let result: TxResult = serde_json::from_value(rsp.get("result").unwrap_or(&rsp).clone())
+            .context("could not parse JSON response")?;
+
+        Transaction::decode(result.tx.as_ref()).context("could not decode transaction")
+    }
+
     pub async fn specific_client(&self) -> Result<SpecificQueryClient<Channel>, anyhow::Error> {
+        self.check_chain_id().await?;
         SpecificQueryClient::connect(self.pd_url.as_ref().to_owned())
             .await
             .map_err(Into::into)
     }
 
     pub async fn oblivious_client(&self) -> Result<ObliviousQueryClient<Channel>, anyhow::Error> {
+        self.check_chain_id().await?;
         ObliviousQueryClient::connect(self.pd_url.as_ref().to_owned())
             .await
             .map_err(Into::into)
     }
+
+    /// Check that the node at `pd_url` serves the same chain as the view service.
+    ///
+    /// The node can't change chains while `pcli` runs, so this is only checked the first time a
+    /// client is made.
+    async fn check_chain_id(&self) -> Result<(), anyhow::Error> {
+        self.pd_chain_id_checked
+            .get_or_try_init(|| async {
+                let expected = self.view.clone().chain_params().await?.chain_id;
+                // The specific query service has no cheap request to check the chain ID with, so
+                // check it through the oblivious query service, which the node serves alongside.
+                let rsp = ObliviousQueryClient::connect(self.pd_url.as_ref().to_owned())
+                    .await?
+                    .chain_params(tonic::Request::new(ChainParamsRequest {
+                        chain_id: String::new(),
+                    }))
+                    .await?;
+                penumbra_view::check_chain_id(rsp.metadata(), &expected)
+                    .with_context(|| format!("node at {} is on the wrong chain", self.pd_url))
+            })
+            .await
+            .map(|_| ())
+    }
+}
+
+/// The node rejected a transaction because its anchor is older than the chain accepts, with the
+/// node's error, which says the oldest anchor height it accepts.
+#[derive(Debug)]
+struct AnchorExpired {
+    error: String,
+}
+
+impl std::fmt::Display for AnchorExpired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Error submitting transaction: {} (code {}): {}",
+            ErrorCode::ExpiredAnchor,
+            ErrorCode::ExpiredAnchor.code(),
+            self.error
+        )
+    }
+}
+
+impl std::error::Error for AnchorExpired {}
+
+/// The error a node rejected a transaction with, from the log of its response, which nodes
+/// report as JSON alongside the code, or as just the error before they did.
+fn rejection_error(log: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(log)
+        .ok()
+        .and_then(|log| log.get("error")?.as_str().map(str::to_string))
+        .unwrap_or_else(|| log.to_string())
+}
+
+/// Formats a signed amount of an asset, in its best display unit if the asset is known.
+fn format_amount(asset_id: asset::Id, amount: i128, cache: &asset::Cache) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let magnitude = amount.unsigned_abs();
+    let formatted = u64::try_from(magnitude)
+        .ok()
+        .and_then(|magnitude| asset_id.value(magnitude).try_format(cache))
+        .unwrap_or_else(|| magnitude.to_string());
+    format!("{}{}", sign, formatted)
 }
@@ -1,10 +1,14 @@
 use crate::{
     box_grpc_svc::{self, BoxGrpcService},
+    genesis::{self, GenesisPin},
     legacy,
+    message::Lang,
+    profile::NetworkProfile,
+    secrets::{Secrets, VIEW_AUTH_TOKEN},
     wallet::Wallet,
     App, Command,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use camino::Utf8PathBuf;
 use clap::Parser;
 use directories::ProjectDirs;
@@ -17,7 +21,7 @@ use penumbra_proto::{
     },
     view::{view_protocol_client::ViewProtocolClient, view_protocol_server::ViewProtocolServer},
 };
-use penumbra_view::ViewService;
+use penumbra_view::{FvkMismatchError, SpotCheck, Storage, ViewClient, ViewService};
 use std::net::SocketAddr;
 use tracing_subscriber::EnvFilter;
 use url::Url;
@@ -30,20 +34,35 @@ use url::Url;
 )]
 pub struct Opt {
     /// The hostname of the pd+tendermint node.
+    ///
+    /// By default, this is the node of the network profile chosen with `pcli init`, or
+    /// testnet.penumbra.zone if there is none.
     #[clap(
         short,
         long,
-        default_value = "testnet.penumbra.zone",
         env = "PENUMBRA_NODE_HOSTNAME",
         parse(try_from_str = url::Host::parse)
     )]
-    node: url::Host,
-    /// The port to use to speak to tendermint's RPC server.
-    #[clap(long, default_value_t = 26657, env = "PENUMBRA_TENDERMINT_PORT")]
-    tendermint_port: u16,
-    /// The port to use to speak to pd's gRPC server.
-    #[clap(long, default_value_t = 8080, env = "PENUMBRA_PD_PORT")]
-    pd_port: u16,
+    node: Option<url::Host>,
+    /// The hostname of the tendermint node to submit transactions to, if it differs from the node
+    /// the view service syncs from (e.g., to sync from a public node, but submit transactions
+    /// through your own).
+    #[clap(
+        long,
+        env = "PENUMBRA_SUBMIT_NODE_HOSTNAME",
+        parse(try_from_str = url::Host::parse)
+    )]
+    submit_node: Option<url::Host>,
+    /// The port to use to speak to tendermint's RPC server (by default, that of the network
+    /// profile).
+    #[clap(long, env = "PENUMBRA_TENDERMINT_PORT")]
+    tendermint_port: Option<u16>,
+    /// The port to use to speak to pd's gRPC server (by default, that of the network profile).
+    #[clap(long, env = "PENUMBRA_PD_PORT")]
+    pd_port: Option<u16>,
+    /// The network profile the node and ports default to, loaded from the data directory.
+    #[clap(skip)]
+    profile: Option<NetworkProfile>,
     #[clap(subcommand)]
     pub cmd: Command,
     /// The directory to store the wallet and view data in.
@@ -52,9 +71,44 @@ pub struct Opt {
     /// If set, use a remote view service instead of local synchronization.
     #[clap(short, long, env = "PENUMBRA_VIEW_ADDRESS")]
     view_address: Option<SocketAddr>,
+    /// The auth token to present to the remote view service, if it requires one.
+    ///
+    /// If this isn't set, the token saved with `pcli wallet secret set view-auth-token` is used.
+    #[clap(long, requires = "view_address", env = "PENUMBRA_VIEW_AUTH_TOKEN")]
+    view_auth_token: Option<String>,
+    /// The passphrase the secrets saved with `pcli wallet secret` are encrypted under.
+    #[clap(long, env = "PCLI_SECRETS_PASSPHRASE", hide_env_values = true)]
+    pub secrets_passphrase: Option<String>,
+    /// If set, spot check the remote view service against compact blocks fetched from this
+    /// independent pd node (which should not be the node the view service syncs from).
+    #[clap(
+        long,
+        requires = "view_address",
+        env = "PENUMBRA_SPOT_CHECK_NODE",
+        parse(try_from_str = url::Host::parse)
+    )]
+    spot_check_node: Option<url::Host>,
+    /// The maximum number of notes or authentication paths to spot check per request.
+    #[clap(long, default_value_t = 8)]
+    spot_check_samples: usize,
+    /// If set, refuse to use a node or view data for any chain but this one.
+    #[clap(long, env = "PENUMBRA_CHAIN_ID")]
+    chain_id: Option<String>,
+    /// If set, refuse to build view data from a node whose genesis app hash (the app hash in the
+    /// header of block 1, in hex) differs from this one.
+    #[clap(
+        long,
+        env = "PENUMBRA_GENESIS_APP_HASH",
+        parse(try_from_str = genesis::parse_app_hash)
+    )]
+    genesis_app_hash: Option<[u8; 32]>,
     /// The filter for `pcli`'s log messages.
     #[clap( long, default_value_t = EnvFilter::new("warn"), env = "RUST_LOG")]
     trace_filter: EnvFilter,
+    /// The language to print transaction, balance, and staking output in: `en` (English) or `es`
+    /// (Spanish).
+    #[clap(long, default_value = "en", env = "PCLI_LANG")]
+    pub lang: Lang,
 }
 
 impl Opt {
@@ -64,6 +118,44 @@ impl Opt {
             .init();
     }
 
+    /// Load the network profile saved in the data directory by `pcli init`, if there is one.
+    pub fn load_profile(&mut self) -> Result<()> {
+        self.profile = NetworkProfile::load(&self.data_path)?;
+        Ok(())
+    }
+
+    /// Use the given network profile, overriding any endpoints given as options.
+    pub fn use_profile(&mut self, profile: NetworkProfile) {
+        self.node = None;
+        self.pd_port = None;
+        self.tendermint_port = None;
+        self.profile = Some(profile);
+    }
+
+    /// The network profile the endpoints default to.
+    pub fn profile(&self) -> NetworkProfile {
+        self.profile.clone().unwrap_or_else(NetworkProfile::testnet)
+    }
+
+    /// The hostname of the pd+tendermint node.
+    pub fn node(&self) -> Result<url::Host> {
+        match &self.node {
+            Some(node) => Ok(node.clone()),
+            None => self.profile().host(),
+        }
+    }
+
+    /// The port of pd's gRPC server.
+    pub fn pd_port(&self) -> u16 {
+        self.pd_port.unwrap_or_else(|| self.profile().pd_port)
+    }
+
+    /// The port of tendermint's RPC server.
+    pub fn tendermint_port(&self) -> u16 {
+        self.tendermint_port
+            .unwrap_or_else(|| self.profile().tendermint_port)
+    }
+
     pub async fn into_app(self) -> Result<(App, Command)> {
         // Create the data directory if it is missing.
         std::fs::create_dir_all(&self.data_path).context("Failed to create data directory")?;
@@ -87,61 +179,195 @@ impl Opt {
         let fvk = wallet.spend_key.full_viewing_key().clone();
 
         // ...and the view service...
-        let view = self.view_client(&fvk).await?;
+        let (mut view, view_storage) = self.view_client(&fvk).await?;
+        // Whether the view data was just built or already existed, it must be for the pinned chain.
+        if self.chain_id.is_some() {
+            self.genesis_pin()
+                .check_chain_id(&view.chain_params().await?.chain_id)?;
+        }
+
+        // ...and, if requested, the spot checker for the remote view service.
+        let spot_check = if let Some(node) = &self.spot_check_node {
+            tracing::info!(%node, "spot checking remote view service");
+            let spot_check = SpotCheck::connect(
+                fvk.clone(),
+                node.to_string(),
+                self.pd_port(),
+                self.spot_check_samples,
+            )
+            .await?;
+            spot_check.check_chain_id(&view.chain_params().await?.chain_id)?;
+            Some(spot_check)
+        } else {
+            None
+        };
 
-        let mut tendermint_url = format!("http://{}", self.node)
+        let node = self.node()?;
+        let mut pd_url = format!("http://{}", node)
             .parse::<Url>()
-            .with_context(|| format!("Invalid node URL: {}", self.node))?;
-        let mut pd_url = tendermint_url.clone();
+            .with_context(|| format!("Invalid node URL: {}", node))?;
         pd_url
-            .set_port(Some(self.pd_port))
+            .set_port(Some(self.pd_port()))
             .expect("pd URL will not be `file://`");
+        let submit_node = self.submit_node.as_ref().unwrap_or(&node);
+        let mut tendermint_url = format!("http://{}", submit_node)
+            .parse::<Url>()
+            .with_context(|| format!("Invalid submit node URL: {}", submit_node))?;
         tendermint_url
-            .set_port(Some(self.tendermint_port))
+            .set_port(Some(self.tendermint_port()))
             .expect("tendermint URL will not be `file://`");
 
         let app = App {
             view,
+            spot_check,
             custody,
             fvk,
             wallet,
             pd_url,
             tendermint_url,
+            view_storage,
+            pd_chain_id_checked: Default::default(),
         };
         Ok((app, self.cmd))
     }
 
-    /// Constructs a [`ViewProtocolClient`] based on the command-line options.
+    /// Constructs a [`ViewProtocolClient`] based on the command-line options, along with the
+    /// storage of the view service, if it's local.
     async fn view_client(
         &self,
         fvk: &FullViewingKey,
-    ) -> Result<ViewProtocolClient<BoxGrpcService>> {
-        let svc = if let Some(address) = self.view_address {
+    ) -> Result<(ViewProtocolClient<BoxGrpcService>, Option<Storage>)> {
+        let (svc, storage) = if let Some(address) = self.view_address {
             // Use a remote view service.
             tracing::info!(%address, "using remote view service");
 
             let ep = tonic::transport::Endpoint::new(format!("http://{}", address))?;
-            box_grpc_svc::connect(ep).await?
+            (
+                box_grpc_svc::connect(ep, self.view_auth_token()?.as_deref()).await?,
+                None,
+            )
         } else {
             // Use an in-memory view service.
             let path = self.data_path.join(crate::VIEW_FILE_NAME);
             tracing::info!(%path, "using local view service");
 
+            // Before building view data from scratch, make sure the node serves the pinned chain.
+            let node = self.node()?;
+            if !path.exists() {
+                self.genesis_pin()
+                    .check_node(
+                        &format!("http://{}:{}", node, self.pd_port()),
+                        &format!("http://{}:{}", node, self.tendermint_port()),
+                    )
+                    .await?;
+            }
+
             let svc = ViewService::load_or_initialize(
-                path,
+                &path,
                 &fvk,
-                self.node.to_string(),
-                self.pd_port,
-                self.tendermint_port,
+                node.to_string(),
+                self.pd_port(),
+                self.tendermint_port(),
             )
-            .await?;
+            .await
+            .map_err(|e| {
+                if e.is::<FvkMismatchError>() {
+                    e.context(format!(
+                        "the view data at {} was synced for a different wallet; run `pcli wallet reset` to delete it and rescan",
+                        path
+                    ))
+                } else {
+                    e
+                }
+            })?;
 
             // Now build the view and custody clients, doing gRPC with ourselves
+            let storage = svc.storage().clone();
             let svc = ViewProtocolServer::new(svc);
-            box_grpc_svc::local(svc)
+            (box_grpc_svc::local(svc), Some(storage))
         };
 
-        Ok(ViewProtocolClient::new(svc))
+        Ok((ViewProtocolClient::new(svc), storage))
+    }
+
+    /// The auth token to present to the remote view service: the one given as an option, or
+    /// else the saved one, if the secrets passphrase is given.
+    fn view_auth_token(&self) -> Result<Option<String>> {
+        if let Some(token) = &self.view_auth_token {
+            return Ok(Some(token.clone()));
+        }
+        let passphrase = match &self.secrets_passphrase {
+            Some(passphrase) => passphrase,
+            None => return Ok(None),
+        };
+        let secrets = Secrets::load(self.data_path.join(crate::SECRETS_FILE_NAME), passphrase)?;
+        Ok(secrets.get(VIEW_AUTH_TOKEN).map(str::to_string))
+    }
+
+    /// Roll back the local view data to the latest snapshot at or before `height`, so that the
+    /// next sync rescans the chain from there.
+    pub async fn roll_back_view(&self, height: u64) -> Result<()> {
+        if let Some(address) = self.view_address {
+            return Err(anyhow!(
+                "can't roll back the remote view service at {}; roll it back with `pviewd rescan`, or its `ResetToHeight` RPC, instead",
+                address
+            ));
+        }
+
+        let path = self.data_path.join(crate::VIEW_FILE_NAME);
+        if !path.exists() {
+            return Err(anyhow!("no view data at {} to roll back", path));
+        }
+
+        match Storage::load(&path).await?.reset_to_height(height).await? {
+            Some(resume_height) => println!(
+                "Rolled back view data to height {}, rescanning from there",
+                resume_height
+            ),
+            None => println!("Rolled back view data to genesis, rescanning the whole chain"),
+        }
+
+        Ok(())
+    }
+
+    /// Delete notes spent more than `keep_spent_for_blocks` blocks ago from the local view data,
+    /// compacting it if that freed enough space.
+    pub async fn prune_view(&self, keep_spent_for_blocks: u64) -> Result<()> {
+        if let Some(address) = self.view_address {
+            return Err(anyhow!(
+                "can't prune the remote view service at {}; prune its view data where it runs instead",
+                address
+            ));
+        }
+
+        let path = self.data_path.join(crate::VIEW_FILE_NAME);
+        if !path.exists() {
+            return Err(anyhow!("no view data at {} to prune", path));
+        }
+
+        let summary = Storage::load(&path)
+            .await?
+            .prune(keep_spent_for_blocks)
+            .await?;
+        println!(
+            "Pruned {} spent notes and {} quarantined nullifiers from the view data{}",
+            summary.notes,
+            summary.quarantined_nullifiers,
+            if summary.vacuumed {
+                ", and compacted it"
+            } else {
+                ""
+            }
+        );
+
+        Ok(())
+    }
+
+    fn genesis_pin(&self) -> GenesisPin {
+        GenesisPin {
+            chain_id: self.chain_id.clone(),
+            app_hash: self.genesis_app_hash,
+        }
     }
 }
 
@@ -0,0 +1,131 @@
+//! Network profiles, which bundle the endpoints `pcli` connects to on a network, so that the
+//! network chosen with `pcli init` is used without passing `--node` to every command.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+
+/// The name of the file in the data directory holding the network profile chosen with `pcli init`.
+pub const PROFILE_FILE_NAME: &str = "network_profile.json";
+
+/// The endpoints of a network's pd+tendermint node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkProfile {
+    /// The name of the profile, e.g. `testnet`.
+    pub name: String,
+    /// The hostname of the pd+tendermint node.
+    pub node: String,
+    /// The port of pd's gRPC server.
+    pub pd_port: u16,
+    /// The port of tendermint's RPC server.
+    pub tendermint_port: u16,
+}
+
+impl NetworkProfile {
+    /// The public testnet, which `pcli` connects to unless told otherwise.
+    pub fn testnet() -> Self {
+        Self {
+            name: "testnet".to_string(),
+            node: "testnet.penumbra.zone".to_string(),
+            pd_port: 8080,
+            tendermint_port: 26657,
+        }
+    }
+
+    /// A node running on this machine, e.g. a devnet started with `pd testnet`.
+    pub fn local() -> Self {
+        Self {
+            name: "local".to_string(),
+            node: "127.0.0.1".to_string(),
+            pd_port: 8080,
+            tendermint_port: 26657,
+        }
+    }
+
+    /// The built-in profiles, by name.
+    pub fn builtin() -> Vec<Self> {
+        vec![Self::testnet(), Self::local()]
+    }
+
+    /// The hostname of the node, parsed.
+    pub fn host(&self) -> Result<url::Host> {
+        url::Host::parse(&self.node)
+            .with_context(|| format!("invalid node hostname {:?} in profile", self.node))
+    }
+
+    /// Load the profile saved in `data_dir`, if there is one.
+    pub fn load(data_dir: &Utf8Path) -> Result<Option<Self>> {
+        let path = data_dir.join(PROFILE_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let profile: Self = serde_json::from_slice(&std::fs::read(&path)?)
+            .with_context(|| format!("could not parse network profile at {}", path))?;
+        // Check the hostname now, rather than when it's first used.
+        profile.host()?;
+        Ok(Some(profile))
+    }
+
+    /// Save the profile in `data_dir`, to be used by later commands.
+    pub fn save(&self, data_dir: &Utf8Path) -> Result<()> {
+        let path = data_dir.join(PROFILE_FILE_NAME);
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("could not write network profile to {}", path))
+    }
+}
+
+impl FromStr for NetworkProfile {
+    type Err = anyhow::Error;
+
+    /// Look up a built-in profile by name.
+    fn from_str(name: &str) -> Result<Self> {
+        Self::builtin()
+            .into_iter()
+            .find(|profile| profile.name == name)
+            .ok_or_else(|| anyhow!("unknown network profile {:?}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = Utf8Path::from_path(dir.path()).unwrap();
+        assert_eq!(NetworkProfile::load(data_dir).unwrap(), None);
+
+        let profile = NetworkProfile {
+            name: "custom".to_string(),
+            node: "node.example.com".to_string(),
+            pd_port: 9090,
+            tendermint_port: 36657,
+        };
+        profile.save(data_dir).unwrap();
+        assert_eq!(NetworkProfile::load(data_dir).unwrap(), Some(profile));
+    }
+
+    #[test]
+    fn builtin_profiles_by_name() {
+        for profile in NetworkProfile::builtin() {
+            assert_eq!(profile.name.parse::<NetworkProfile>().unwrap(), profile);
+            profile.host().unwrap();
+        }
+        assert!("mainnet".parse::<NetworkProfile>().is_err());
+    }
+
+    #[test]
+    fn invalid_hostname_rejected_on_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = Utf8Path::from_path(dir.path()).unwrap();
+        std::fs::write(
+            data_dir.join(PROFILE_FILE_NAME),
+            r#"{"name":"bad","node":"not a host","pd_port":8080,"tendermint_port":26657}"#,
+        )
+        .unwrap();
+        assert!(NetworkProfile::load(data_dir).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Secrets `pcli` presents to the services it uses, like the auth token of a remote view service,
+//! kept encrypted in the data directory under a passphrase rather than in plaintext options or
+//! environment variables.
+//!
+//! Each data directory has its own secrets, so a data directory set up for one node and view
+//! service carries the credentials for them.
+//!
+//! The secrets are a JSON map from name to value, encrypted with ChaCha20-Poly1305 under a key
+//! stretched from the passphrase with PBKDF2-HMAC-SHA512, using a random salt. The salt, the
+//! nonce, and the ciphertext are stored hex-encoded in a JSON file, and the salt and nonce are
+//! chosen again each time the secrets are saved.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8Path;
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hmac::Hmac;
+use pbkdf2::pbkdf2;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// The name of the secret holding the auth token to present to the remote view service.
+pub const VIEW_AUTH_TOKEN: &str = "view-auth-token";
+
+/// The number of PBKDF2 rounds the passphrase is stretched with, which makes guessing it slow.
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// The secrets file, as it's stored.
+#[derive(Serialize, Deserialize)]
+struct SecretsFile {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The decrypted secrets of a data directory, by name.
+#[derive(Default)]
+pub struct Secrets {
+    secrets: BTreeMap<String, String>,
+}
+
+impl Secrets {
+    /// Read and decrypt the secrets at `path`, or none if there is no file there yet.
+    pub fn load(path: impl AsRef<Utf8Path>, passphrase: &str) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file: SecretsFile = serde_json::from_slice(&std::fs::read(path)?)
+            .with_context(|| format!("could not parse secrets file at {}", path))?;
+        let salt = hex::decode(&file.salt)?;
+        let nonce = hex::decode(&file.nonce)?;
+        if nonce.len() != 12 {
+            return Err(anyhow!("secrets file at {} has an invalid nonce", path));
+        }
+
+        let plaintext = cipher(passphrase, &salt)
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                hex::decode(&file.ciphertext)?.as_ref(),
+            )
+            .map_err(|_| {
+                anyhow!(
+                    "could not decrypt the secrets at {}: the passphrase is wrong",
+                    path
+                )
+            })?;
+
+        Ok(Self {
+            secrets: serde_json::from_slice(&plaintext)?,
+        })
+    }
+
+    /// Encrypt the secrets under `passphrase`, and write them to `path`, replacing any secrets
+    /// already there.
+    pub fn save(&self, path: impl AsRef<Utf8Path>, passphrase: &str) -> Result<()> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = cipher(passphrase, &salt)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                serde_json::to_vec(&self.secrets)?.as_ref(),
+            )
+            .map_err(|_| anyhow!("could not encrypt secrets"))?;
+
+        let file = SecretsFile {
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        };
+        std::fs::write(path.as_ref(), serde_json::to_vec_pretty(&file)?)?;
+
+        Ok(())
+    }
+
+    /// The secret named `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.secrets.get(name).map(String::as_str)
+    }
+
+    /// Set the secret named `name` to `value`.
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.secrets.insert(name.to_string(), value.to_string());
+    }
+
+    /// Remove the secret named `name`, returning whether there was one.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.secrets.remove(name).is_some()
+    }
+
+    /// The names of the secrets, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.secrets.keys().map(String::as_str)
+    }
+}
+
+impl std::fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the secrets into logs.
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+/// The cipher the secrets are encrypted with, keyed by stretching `passphrase` with `salt`.
+fn cipher(passphrase: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    pbkdf2::<Hmac<sha2::Sha512>>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = Utf8Path::from_path(dir.path()).unwrap();
+        let path = data_dir.join("secrets.json");
+        // There are no secrets until some are saved.
+        let secrets = Secrets::load(&path, "passphrase").unwrap();
+        assert_eq!(secrets.names().count(), 0);
+
+        let mut secrets = Secrets::default();
+        secrets.set(VIEW_AUTH_TOKEN, "token");
+        secrets.set("other", "value");
+        secrets.save(&path, "passphrase").unwrap();
+        // The secrets aren't stored in plaintext.
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("token"));
+
+        let mut loaded = Secrets::load(&path, "passphrase").unwrap();
+        assert_eq!(loaded.get(VIEW_AUTH_TOKEN), Some("token"));
+        assert_eq!(loaded.get("other"), Some("value"));
+        assert!(loaded.remove("other"));
+        assert!(!loaded.remove("other"));
+        assert_eq!(loaded.names().collect::<Vec<_>>(), vec![VIEW_AUTH_TOKEN]);
+    }
+
+    #[test]
+    fn wrong_passphrase_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = Utf8Path::from_path(dir.path()).unwrap();
+        let path = data_dir.join("secrets.json");
+
+        let mut secrets = Secrets::default();
+        secrets.set(VIEW_AUTH_TOKEN, "token");
+        secrets.save(&path, "passphrase").unwrap();
+
+        let error = Secrets::load(&path, "wrong").unwrap_err();
+        assert!(error.to_string().contains("the passphrase is wrong"));
+    }
+}
@@ -5,3 +5,4 @@ mod worker;
 use message::Message;
 pub use service::Consensus;
 use worker::Worker;
+pub(crate) use worker::{check_tx_stateless, deliver_tx};
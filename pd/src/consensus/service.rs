@@ -2,6 +2,7 @@ use std::{
     future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use futures::FutureExt;
@@ -13,10 +14,10 @@ use tendermint::{
 use tokio::sync::{mpsc, oneshot, watch};
 use tokio_util::sync::PollSender;
 use tower_abci::BoxError;
-use tracing::error_span;
+use tracing::{error_span, field};
 
-use super::{Message, Worker};
-use crate::RequestExt;
+use super::{worker::MAX_DELIVER_TX_BATCH, Message, Worker};
+use crate::{telemetry::BlockTimes, NodeLoad, RequestExt};
 
 #[derive(Clone)]
 pub struct Consensus {
@@ -24,17 +25,37 @@ pub struct Consensus {
 }
 
 impl Consensus {
-    pub async fn new(storage: Storage) -> anyhow::Result<(Self, watch::Receiver<block::Height>)> {
-        let (queue_tx, queue_rx) = mpsc::channel(10);
+    /// Spawn the consensus worker over `storage`.
+    ///
+    /// The worker warns whenever a `Commit` takes longer than `slow_commit_threshold`, and records
+    /// the time it spends processing each block in `block_times`. Each `Commit` is reported to
+    /// `load`, so that low-priority queries can be held back until it finishes.
+    pub async fn new(
+        storage: Storage,
+        slow_commit_threshold: Duration,
+        block_times: BlockTimes,
+        load: NodeLoad,
+    ) -> anyhow::Result<(Self, watch::Receiver<block::Height>)> {
+        // The queue is deep enough to hold a full batch of `DeliverTx` requests
+        let (queue_tx, queue_rx) = mpsc::channel(MAX_DELIVER_TX_BATCH);
         let initial_height = match storage.latest_version().await? {
             Some(version) => version.try_into().unwrap(),
             _ => 0u32.into(),
         };
         let (height_tx, height_rx) = watch::channel(initial_height);
 
-        tokio::task::Builder::new()
-            .name("consensus::Worker")
-            .spawn(Worker::new(storage, queue_rx, height_tx).await?.run());
+        tokio::task::Builder::new().name("consensus::Worker").spawn(
+            Worker::new(
+                storage,
+                queue_rx,
+                height_tx,
+                slow_commit_threshold,
+                block_times,
+                load,
+            )
+            .await?
+            .run(),
+        );
 
         Ok((
             Self {
@@ -67,7 +88,9 @@ impl tower::Service<ConsensusRequest> for Consensus {
         }
 
         let span = req.create_span();
-        let span = error_span!(parent: &span, "app", role = "consensus");
+        // The worker records the height of the block being executed, which `DeliverTx` and
+        // `Commit` requests don't carry.
+        let span = error_span!(parent: &span, "app", role = "consensus", height = field::Empty);
         let (tx, rx) = oneshot::channel();
 
         self.queue
@@ -1,52 +1,128 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
 use anyhow::{anyhow, Result};
 
+use futures::future;
 use penumbra_proto::Protobuf;
 
-use penumbra_chain::genesis;
+use penumbra_chain::{genesis, ErrorCode};
 use penumbra_component::{Component, Context};
 use penumbra_storage::Storage;
 use penumbra_transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tendermint::{
     abci::{self, ConsensusRequest as Request, ConsensusResponse as Response},
     block,
 };
 use tokio::sync::{mpsc, watch};
-use tracing::{instrument, Instrument};
+use tracing::{instrument, Instrument, Span};
 
 use super::Message;
-use crate::App;
+use crate::{metrics, rejection::rejection_code, telemetry::BlockTimes, App, NodeLoad};
+
+/// The maximum number of queued `DeliverTx` requests to verify together.
+pub const MAX_DELIVER_TX_BATCH: usize = 64;
 
 pub struct Worker {
     queue: mpsc::Receiver<Message>,
+    // A message taken from the queue while collecting a batch of `DeliverTx` requests, which
+    // must be handled next.
+    pending: Option<Message>,
     height_tx: watch::Sender<block::Height>,
     storage: Storage,
     app: App,
+    /// The height of the block being executed.
+    height: u64,
+    /// The index of the next `DeliverTx` in the block being executed.
+    tx_index: u32,
+    /// The results recorded for the block being executed, if it is being replayed, by index.
+    recorded: BTreeMap<u32, DeliverTxRecord>,
+    /// Commits which take longer than this are logged as warnings.
+    slow_commit_threshold: Duration,
+    /// When the block being executed began, for recording its processing time.
+    block_start: Option<Instant>,
+    block_times: BlockTimes,
+    /// Where block commits are reported, so low-priority queries can be held back meanwhile.
+    load: NodeLoad,
 }
 
+/// The result of a `DeliverTx` request, recorded until its block is committed.
+///
+/// Tendermint only includes the code of each `DeliverTx` response in the block's results hash,
+/// so a failure is replayed with its original log but none of its events.
+#[derive(Debug, Serialize, Deserialize)]
+struct DeliverTxRecord {
+    height: u64,
+    tx_hash: [u8; 32],
+    /// The error, if the transaction failed.
+    error: Option<String>,
+    /// The code of the `DeliverTx` response, which is nonzero if the transaction failed.
+    code: u32,
+}
+
+/// A failure recorded before a crash, replayed with its original code and log.
+#[derive(Debug)]
+struct RecordedFailure {
+    code: u32,
+    log: String,
+}
+
+impl std::fmt::Display for RecordedFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.log)
+    }
+}
+
+impl std::error::Error for RecordedFailure {}
+
 impl Worker {
-    #[instrument(skip(storage, queue, height_tx), name = "consensus::Worker::new")]
+    #[instrument(skip(storage, queue, height_tx, load), name = "consensus::Worker::new")]
     pub async fn new(
         storage: Storage,
         queue: mpsc::Receiver<Message>,
         height_tx: watch::Sender<block::Height>,
+        slow_commit_threshold: Duration,
+        block_times: BlockTimes,
+        load: NodeLoad,
     ) -> Result<Self> {
         let app = App::new(storage.clone()).await;
 
         Ok(Self {
             queue,
+            pending: None,
             height_tx,
             storage,
             app,
+            height: 0,
+            tx_index: 0,
+            recorded: BTreeMap::new(),
+            slow_commit_threshold,
+            block_start: None,
+            block_times,
+            load,
         })
     }
 
     pub async fn run(mut self) -> Result<()> {
-        while let Some(Message {
-            req,
-            rsp_sender,
-            span,
-        }) = self.queue.recv().await
-        {
+        while let Some(message) = self.next_message().await {
+            // Tendermint sends a block's `DeliverTx` requests without waiting for each response,
+            // so handle whichever of them are already queued together
+            if let Request::DeliverTx(_) = message.req {
+                let batch = self.collect_deliver_tx_batch(message);
+                self.deliver_tx_batch(batch).await;
+                continue;
+            }
+
+            let Message {
+                req,
+                rsp_sender,
+                span,
+            } = message;
+
             // The send only fails if the receiver was dropped, which happens
             // if the caller didn't propagate the message back to tendermint
             // for some reason -- but that's not our problem.
@@ -63,50 +139,179 @@ impl Worker {
                         .await
                         .expect("begin_block must succeed"),
                 ),
-                Request::DeliverTx(deliver_tx) => {
-                    let ctx = Context::new();
-                    let rsp = self
-                        .deliver_tx(ctx.clone(), deliver_tx)
-                        .instrument(span.clone())
-                        .await;
-                    span.in_scope(|| {
-                        Response::DeliverTx(match rsp {
-                            Ok(()) => {
-                                tracing::info!("deliver_tx succeeded");
-                                abci::response::DeliverTx {
-                                    events: ctx.into_events(),
-                                    ..Default::default()
-                                }
-                            }
-                            Err(e) => {
-                                tracing::info!(?e, "deliver_tx failed");
-                                abci::response::DeliverTx {
-                                    code: 1,
-                                    log: e.to_string(),
-                                    events: ctx.into_events(),
-                                    ..Default::default()
-                                }
-                            }
-                        })
-                    })
-                }
+                Request::DeliverTx(_) => unreachable!("DeliverTx requests are handled in batches"),
                 Request::EndBlock(end_block) => Response::EndBlock(
                     self.end_block(end_block)
                         .instrument(span)
                         .await
                         .expect("end_block must succeed"),
                 ),
-                Request::Commit => Response::Commit(
+                Request::Commit => Response::Commit({
+                    span.record("height", &self.height);
                     self.commit()
                         .instrument(span)
                         .await
-                        .expect("commit must succeed"),
-                ),
+                        .expect("commit must succeed")
+                }),
             });
         }
         Ok(())
     }
 
+    /// Get the next message to handle, either one set aside while batching or the next in the
+    /// queue.
+    async fn next_message(&mut self) -> Option<Message> {
+        match self.pending.take() {
+            Some(message) => Some(message),
+            None => self.queue.recv().await,
+        }
+    }
+
+    /// Collect the `DeliverTx` requests which are already queued behind `first`, stopping at the
+    /// first other request (which is set aside to be handled next).
+    fn collect_deliver_tx_batch(&mut self, first: Message) -> Vec<Message> {
+        let mut batch = vec![first];
+        while batch.len() < MAX_DELIVER_TX_BATCH {
+            match self.queue.try_recv() {
+                Ok(message) if matches!(message.req, Request::DeliverTx(_)) => batch.push(message),
+                Ok(message) => {
+                    self.pending = Some(message);
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        batch
+    }
+
+    /// Perform full transaction validation via `DeliverTx`, for a batch of transactions.
+    ///
+    /// State changes are only applied for valid transactions. Invalid transaction are ignored.
+    ///
+    /// We must perform all checks again here even though they are performed in `CheckTx`, as a
+    /// Byzantine node may propose a block containing double spends or other disallowed behavior,
+    /// so it is not safe to assume all checks performed in `CheckTx` were done.
+    ///
+    /// Parsing and stateless checks (which include proof and signature verification, and so are
+    /// most of the cost) don't depend on any other transaction, so they run in parallel across
+    /// the whole batch. Stateful checks and execution then run in block order, one transaction
+    /// at a time, because each transaction's stateful validity depends on the effects of the
+    /// transactions before it (e.g., a nullifier spent earlier in the same block).
+    ///
+    /// If the block is being replayed after a crash, transactions with a recorded result aren't
+    /// checked again: failed transactions get their recorded failure, and successful ones are
+    /// executed again (to rebuild the uncommitted state) without repeating their stateless
+    /// checks.
+    async fn deliver_tx_batch(&mut self, batch: Vec<Message>) {
+        let mut checks = Vec::with_capacity(batch.len());
+        for Message {
+            req,
+            rsp_sender,
+            span,
+        } in batch
+        {
+            let deliver_tx = match req {
+                Request::DeliverTx(deliver_tx) => deliver_tx,
+                _ => unreachable!("batches contain only DeliverTx requests"),
+            };
+
+            span.record("height", &self.height);
+            let index = self.tx_index;
+            self.tx_index += 1;
+            let tx_hash: [u8; 32] = Sha256::digest(&deliver_tx.tx).into();
+            let recorded = self
+                .recorded
+                .remove(&index)
+                .filter(|record| record.tx_hash == tx_hash);
+
+            let ctx = Context::new();
+            let check = match &recorded {
+                // Don't check a transaction which already failed: it will only fail again
+                Some(DeliverTxRecord { error: Some(_), .. }) => None,
+                // Don't repeat the stateless checks of a transaction which already passed them
+                Some(DeliverTxRecord { error: None, .. }) => {
+                    let span = span.clone();
+                    Some(tokio::task::spawn_blocking(move || {
+                        span.in_scope(|| Transaction::decode(deliver_tx.tx))
+                    }))
+                }
+                None => {
+                    let ctx = ctx.clone();
+                    let span = span.clone();
+                    Some(tokio::task::spawn_blocking(move || {
+                        span.in_scope(|| check_tx_stateless(ctx, deliver_tx.tx))
+                    }))
+                }
+            };
+
+            checks.push(async move {
+                let transaction = match check {
+                    // Propagate panics, as if the check had run on this task
+                    Some(check) => Some(
+                        check
+                            .await
+                            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic())),
+                    ),
+                    None => None,
+                };
+                (index, tx_hash, recorded, ctx, transaction, rsp_sender, span)
+            });
+        }
+        let checked = future::join_all(checks).await;
+
+        for (index, tx_hash, recorded, ctx, transaction, rsp_sender, span) in checked {
+            let rsp = match (recorded, transaction) {
+                (
+                    Some(DeliverTxRecord {
+                        error: Some(log),
+                        code,
+                        ..
+                    }),
+                    _,
+                ) => Err(RecordedFailure { code, log }.into()),
+                (Some(_), Some(Ok(transaction))) => {
+                    // It's important to panic here rather than return a different result, since
+                    // that would make this node's results diverge from the rest of the network
+                    deliver_tx(&mut self.app, ctx.clone(), &transaction)
+                        .instrument(span.clone())
+                        .await
+                        .expect("a transaction which succeeded before must succeed on replay");
+                    Ok(())
+                }
+                (Some(_), _) => {
+                    panic!("a transaction which succeeded before must decode on replay")
+                }
+                (None, Some(Ok(transaction))) => {
+                    deliver_tx(&mut self.app, ctx.clone(), &transaction)
+                        .instrument(span.clone())
+                        .await
+                }
+                (None, Some(Err(e))) => Err(e),
+                (None, None) => unreachable!("unrecorded transactions are always checked"),
+            };
+
+            // Record the result before responding, so that it's available if we crash before
+            // the block is committed.
+            let record = DeliverTxRecord {
+                height: self.height,
+                tx_hash,
+                error: rsp.as_ref().err().map(ToString::to_string),
+                code: rsp.as_ref().map_or_else(deliver_tx_code, |_| 0),
+            };
+            self.storage
+                .put_deliver_tx_result(
+                    index,
+                    bincode::serialize(&record).expect("can serialize deliver_tx record"),
+                )
+                .await
+                .expect("can record deliver_tx result");
+
+            // The send only fails if the receiver was dropped, which is not our problem (see
+            // above).
+            let _ = rsp_sender.send(deliver_tx_response(&span, ctx, rsp));
+        }
+    }
+
     /// Initializes the chain based on the genesis data.
     ///
     /// The genesis data is provided by tendermint, and is used to initialize
@@ -157,6 +362,26 @@ impl Worker {
         &mut self,
         begin_block: abci::request::BeginBlock,
     ) -> Result<abci::response::BeginBlock> {
+        // If we crashed after delivering some of this block's transactions but before committing
+        // it, Tendermint replays the whole block: pick up the results we recorded for it.
+        self.block_start = Some(Instant::now());
+        self.height = begin_block.header.height.value();
+        self.tx_index = 0;
+        self.recorded = BTreeMap::new();
+        for (index, bytes) in self.storage.deliver_tx_results().await? {
+            let record: DeliverTxRecord = bincode::deserialize(&bytes)?;
+            if record.height == self.height {
+                self.recorded.insert(index, record);
+            }
+        }
+        if !self.recorded.is_empty() {
+            tracing::info!(
+                height = self.height,
+                recorded = self.recorded.len(),
+                "replaying block with recorded deliver_tx results"
+            );
+        }
+
         let ctx = Context::new();
         self.app.begin_block(ctx.clone(), &begin_block).await;
         Ok(abci::response::BeginBlock {
@@ -164,33 +389,6 @@ impl Worker {
         })
     }
 
-    /// Perform full transaction validation via `DeliverTx`.
-    ///
-    /// State changes are only applied for valid transactions. Invalid transaction are ignored.
-    ///
-    /// We must perform all checks again here even though they are performed in `CheckTx`, as a
-    /// Byzantine node may propose a block containing double spends or other disallowed behavior,
-    /// so it is not safe to assume all checks performed in `CheckTx` were done.
-    async fn deliver_tx(
-        &mut self,
-        ctx: Context,
-        deliver_tx: abci::request::DeliverTx,
-    ) -> Result<()> {
-        // Verify the transaction is well-formed...
-        let transaction = Transaction::decode(deliver_tx.tx)?;
-        // ... and statelessly valid...
-        App::check_tx_stateless(ctx.clone(), &transaction)?;
-        // ... and statefully valid.
-        self.app
-            .check_tx_stateful(ctx.clone(), &transaction)
-            .await?;
-        // Now execute the transaction. It's important to panic on error here, since if
-        // we fail to execute the transaction here, it's because of an internal
-        // error and we may have left the chain in an inconsistent state.
-        self.app.execute_tx(ctx.clone(), &transaction).await;
-        Ok(())
-    }
-
     async fn end_block(
         &mut self,
         end_block: abci::request::EndBlock,
@@ -217,11 +415,16 @@ impl Worker {
     }
 
     async fn commit(&mut self) -> Result<abci::response::Commit> {
+        let start = Instant::now();
+        let _committing = self.load.begin_commit();
+
         // Begin sidecar code
 
         // Note: App::commit resets internal components, so we don't need to do that ourselves.
         let (jmt_root, _) = self.app.commit(self.storage.clone()).await?;
         let app_hash = jmt_root.0.to_vec();
+        // Now that the block is committed, it won't be replayed.
+        self.storage.clear_deliver_tx_results().await?;
         let _ = self.height_tx.send(
             self.storage
                 .latest_version()
@@ -231,7 +434,21 @@ impl Worker {
                 .unwrap(),
         );
 
-        tracing::info!(app_hash = ?hex::encode(&app_hash), "finished block commit");
+        let elapsed = start.elapsed();
+        metrics::histogram!(metrics::CONSENSUS_COMMIT_DURATION, elapsed);
+        if let Some(block_start) = self.block_start.take() {
+            self.block_times.record(block_start.elapsed());
+        }
+        tracing::info!(app_hash = ?hex::encode(&app_hash), ?elapsed, "finished block commit");
+        if elapsed > self.slow_commit_threshold {
+            // The per-phase breakdown is in the `penumbra_app_commit_*` and
+            // `penumbra_storage_*_duration_seconds` histograms.
+            tracing::warn!(
+                ?elapsed,
+                threshold = ?self.slow_commit_threshold,
+                "block commit was slow"
+            );
+        }
 
         Ok(abci::response::Commit {
             data: app_hash.into(),
@@ -239,3 +456,65 @@ impl Worker {
         })
     }
 }
+
+/// The first phase of `DeliverTx`, which doesn't touch the state: check that the transaction is
+/// well-formed and statelessly valid.
+pub(crate) fn check_tx_stateless(ctx: Context, tx: impl bytes::Buf) -> Result<Transaction> {
+    // Verify the transaction is well-formed...
+    let transaction = Transaction::decode(tx)?;
+    // ... and statelessly valid.
+    App::check_tx_stateless(ctx, &transaction)?;
+    Ok(transaction)
+}
+
+/// The second phase of `DeliverTx`: check that a statelessly valid transaction is statefully
+/// valid, and execute it.
+pub(crate) async fn deliver_tx(
+    app: &mut App,
+    ctx: Context,
+    transaction: &Transaction,
+) -> Result<()> {
+    // Verify the transaction is statefully valid.
+    app.check_tx_stateful(ctx.clone(), transaction).await?;
+    // Now execute the transaction. It's important to panic on error here, since if
+    // we fail to execute the transaction here, it's because of an internal
+    // error and we may have left the chain in an inconsistent state.
+    app.execute_tx(ctx, transaction).await;
+    Ok(())
+}
+
+/// The code of the `DeliverTx` response for a transaction which failed with `e`.
+///
+/// Violations of the chain's validator parameter bounds and expired anchors have their own codes;
+/// any other failure is reported as [`ErrorCode::Unspecified`].
+fn deliver_tx_code(e: &anyhow::Error) -> u32 {
+    if let Some(failure) = e.downcast_ref::<RecordedFailure>() {
+        failure.code
+    } else {
+        rejection_code(e, ErrorCode::Unspecified)
+    }
+}
+
+/// Build the response to a `DeliverTx` request from its result.
+fn deliver_tx_response(span: &Span, ctx: Context, rsp: Result<()>) -> Response {
+    span.in_scope(|| {
+        Response::DeliverTx(match rsp {
+            Ok(()) => {
+                tracing::info!("deliver_tx succeeded");
+                abci::response::DeliverTx {
+                    events: ctx.into_events(),
+                    ..Default::default()
+                }
+            }
+            Err(e) => {
+                tracing::info!(?e, "deliver_tx failed");
+                abci::response::DeliverTx {
+                    code: deliver_tx_code(&e),
+                    log: e.to_string(),
+                    events: ctx.into_events(),
+                    ..Default::default()
+                }
+            }
+        })
+    })
+}
@@ -0,0 +1,206 @@
+//! A single-node development chain, for iterating on applications without running a testnet.
+//!
+//! A devnet has one validator, whose node is configured to produce a block as soon as there's a
+//! transaction to include, and a handful of test accounts funded at genesis, whose seed phrases
+//! are kept in the devnet's directory so that they can be imported into `pcli`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use penumbra_chain::{genesis, params::ChainParams};
+use penumbra_component::stake::{validator::Validator, FundingStreams};
+use penumbra_crypto::{
+    keys::{SeedPhrase, SpendKey},
+    DelegationToken, IdentityKey,
+};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+
+use crate::testnet::{generate_tm_config, tendermint_genesis, write_configs, ValidatorKeys};
+
+/// The chain ID of every devnet.
+pub const CHAIN_ID: &str = "penumbra-devnet";
+
+/// How long Tendermint waits after committing a block before starting the next one.
+///
+/// `pd devnet` passes this to `pd` as its target block time.
+pub const TIMEOUT_COMMIT_MS: u64 = 100;
+
+/// How often Tendermint makes a block when there are no transactions, so that epochs still end.
+pub const EMPTY_BLOCK_INTERVAL_SECS: u64 = 5;
+
+/// The name of the file in a devnet's directory listing its test accounts.
+const ACCOUNTS_FILE_NAME: &str = "accounts.json";
+
+/// The amounts of each denomination every test account is funded with.
+const TEST_ACCOUNT_ALLOCATIONS: &[(&str, u64)] = &[
+    ("upenumbra", 1_000_000 * 1_000_000),
+    ("gm", 1_000),
+    ("gn", 1_000),
+];
+
+/// A test account funded at genesis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestAccount {
+    /// The account's seed phrase, which `pcli wallet import-from-phrase` accepts.
+    pub seed_phrase: String,
+    /// The account's default address.
+    pub address: String,
+}
+
+/// The directory the devnet's single node keeps its `pd` and `tendermint` state in.
+pub fn node_dir(dir: &Path) -> PathBuf {
+    dir.join("node0")
+}
+
+/// Generate a devnet in `dir`, which must not exist, with `num_accounts` test accounts.
+pub fn generate(dir: &Path, num_accounts: usize, epoch_duration: u64) -> anyhow::Result<()> {
+    if dir.exists() {
+        return Err(anyhow::anyhow!(
+            "devnet directory {:?} already exists, refusing to overwrite it",
+            dir
+        ));
+    }
+
+    let mut allocations = Vec::new();
+    let mut accounts = Vec::new();
+    for _ in 0..num_accounts {
+        let seed_phrase = SeedPhrase::generate(OsRng);
+        let spend_key = SpendKey::from_seed_phrase(seed_phrase.clone(), 0);
+        let (address, _dtk_d) = spend_key
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+
+        for (denom, amount) in TEST_ACCOUNT_ALLOCATIONS {
+            allocations.push(genesis::Allocation {
+                amount: *amount,
+                denom: denom.to_string(),
+                address,
+            });
+        }
+        accounts.push(TestAccount {
+            seed_phrase: seed_phrase.to_string(),
+            address: address.to_string(),
+        });
+    }
+
+    // The validator delegates to itself at genesis, so that it has voting power.
+    let vk = ValidatorKeys::generate();
+    let spend_key = SpendKey::from(vk.validator_spend_key.clone());
+    let (validator_address, _dtk_d) = spend_key
+        .full_viewing_key()
+        .incoming()
+        .payment_address(0u64.into());
+    let identity_key = IdentityKey(vk.validator_id_vk);
+    allocations.push(genesis::Allocation {
+        amount: 50_000 * 10u64.pow(6),
+        denom: DelegationToken::from(&identity_key).denom().to_string(),
+        address: validator_address,
+    });
+
+    let validator = Validator {
+        identity_key,
+        consensus_key: vk.validator_cons_pk,
+        name: "devnet validator".to_string(),
+        website: String::new(),
+        description: "The only validator of a local devnet.".to_string(),
+        enabled: true,
+        funding_streams: FundingStreams::default(),
+        sequence_number: 0,
+    };
+
+    let app_state = genesis::AppState {
+        allocations,
+        chain_params: ChainParams {
+            chain_id: CHAIN_ID.to_string(),
+            epoch_duration,
+            ..Default::default()
+        },
+        validators: vec![validator.into()],
+    };
+    let genesis = tendermint_genesis(CHAIN_ID, app_state);
+
+    let tm_config = devnet_tm_config(generate_tm_config("devnet", &[]))?;
+    write_configs(node_dir(dir), &vk, &genesis, tm_config)?;
+
+    fs::write(
+        dir.join(ACCOUNTS_FILE_NAME),
+        serde_json::to_string_pretty(&accounts)?,
+    )?;
+
+    Ok(())
+}
+
+/// The test accounts of the devnet in `dir`.
+pub fn accounts(dir: &Path) -> anyhow::Result<Vec<TestAccount>> {
+    let path = dir.join(ACCOUNTS_FILE_NAME);
+    let accounts = fs::read(&path).with_context(|| format!("cannot read {:?}", path))?;
+    Ok(serde_json::from_slice(&accounts)?)
+}
+
+/// Adjust the testnet Tendermint config for a devnet: commit as soon as a block is ready, only
+/// make a block when there's a transaction or the empty block interval has passed, and only
+/// serve the RPC on localhost.
+fn devnet_tm_config(mut config: String) -> anyhow::Result<String> {
+    for (setting, value) in [
+        (
+            "timeout-commit = \"5s\"",
+            format!("timeout-commit = \"{}ms\"", TIMEOUT_COMMIT_MS),
+        ),
+        (
+            "create-empty-blocks = true",
+            "create-empty-blocks = false".to_string(),
+        ),
+        (
+            "create-empty-blocks-interval = \"0s\"",
+            format!(
+                "create-empty-blocks-interval = \"{}s\"",
+                EMPTY_BLOCK_INTERVAL_SECS
+            ),
+        ),
+        (
+            "laddr = \"tcp://0.0.0.0:26657\"",
+            "laddr = \"tcp://127.0.0.1:26657\"".to_string(),
+        ),
+    ] {
+        // Fail loudly if the template changes, rather than silently running a slow devnet.
+        if !config.contains(setting) {
+            return Err(anyhow::anyhow!(
+                "Tendermint config template has no `{}` setting",
+                setting
+            ));
+        }
+        config = config.replacen(setting, &value, 1);
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devnet_tm_config_speeds_up_blocks() {
+        let config = devnet_tm_config(generate_tm_config("node0", &[])).unwrap();
+
+        assert!(config.contains(&format!("timeout-commit = \"{}ms\"", TIMEOUT_COMMIT_MS)));
+        assert!(config.contains("create-empty-blocks = false"));
+        assert!(config.contains(&format!(
+            "create-empty-blocks-interval = \"{}s\"",
+            EMPTY_BLOCK_INTERVAL_SECS
+        )));
+        // Only the RPC is limited to localhost, not the p2p listener.
+        assert!(config.contains("laddr = \"tcp://127.0.0.1:26657\""));
+        assert!(config.contains("laddr = \"tcp://0.0.0.0:26656\""));
+    }
+
+    #[test]
+    fn devnet_tm_config_rejects_a_changed_template() {
+        let template = generate_tm_config("node0", &[]).replace("timeout-commit", "timeout_commit");
+        assert!(devnet_tm_config(template).is_err());
+    }
+}
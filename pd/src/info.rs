@@ -15,22 +15,40 @@ use tokio::sync::watch;
 use tower_abci::BoxError;
 use tracing::Instrument;
 
-use crate::RequestExt;
+use crate::{mempool::MempoolEntry, RequestExt};
 
 mod oblivious;
+mod query_path;
+mod resumption_token;
 mod specific;
 
+use query_path::QueryPath;
+pub use resumption_token::ResumptionKey;
+use resumption_token::ResumptionToken;
+
 const ABCI_INFO_VERSION: &str = env!("VERGEN_GIT_SEMVER");
 
 #[derive(Clone, Debug)]
 pub struct Info {
     storage: Storage,
     height_rx: watch::Receiver<block::Height>,
+    mempool_rx: watch::Receiver<Vec<MempoolEntry>>,
+    resumption_key: ResumptionKey,
 }
 
 impl Info {
-    pub fn new(storage: Storage, height_rx: watch::Receiver<block::Height>) -> Self {
-        Self { storage, height_rx }
+    pub fn new(
+        storage: Storage,
+        height_rx: watch::Receiver<block::Height>,
+        mempool_rx: watch::Receiver<Vec<MempoolEntry>>,
+        resumption_key: ResumptionKey,
+    ) -> Self {
+        Self {
+            storage,
+            height_rx,
+            mempool_rx,
+            resumption_key,
+        }
     }
 
     async fn state_tonic(&self) -> Result<State, tonic::Status> {
@@ -64,45 +82,50 @@ impl Info {
     ) -> Result<abci::response::Query, anyhow::Error> {
         tracing::info!(?query);
 
-        match query.path.as_str() {
-            "state/key" => {
-                let height: u64 = query.height.into();
-                let key = query.data.to_vec();
-
-                let jmt_proof = jmt::JellyfishMerkleTree::new(&self.storage)
-                    .get_with_ics23_proof(key.clone(), height)
-                    .await?;
-                let value = jmt_proof.value.clone();
-
-                let commitment_proof = ics23::CommitmentProof {
-                    proof: Some(ics23::commitment_proof::Proof::Exist(jmt_proof)),
-                };
-
-                let op = tendermint::merkle::proof::ProofOp {
-                    field_type: "jmt:v".to_string(),
-                    key,
-                    data: commitment_proof.encode_to_vec(),
-                };
-                let proof = Some(tendermint::merkle::proof::Proof { ops: vec![op] });
-
-                Ok(abci::response::Query {
-                    code: 0,
-                    key: query.data,
-                    log: "".to_string(),
-                    value: value.into(),
-                    proof,
-                    height: height.try_into().unwrap(),
-                    codespace: "".to_string(),
-                    info: "".to_string(),
-                    index: 0,
-                })
-            }
-            _ => {
-                // TODO: handle unrecognized path
-                Ok(Default::default())
-            }
-        }
-        // TODO: implement (#22)
+        let path: QueryPath = query.path.parse()?;
+        let key = path.key(&query.data);
+
+        // A height of 0 asks for the latest state, as in Tendermint's own queries.
+        let height = match u64::from(query.height) {
+            0 => self
+                .storage
+                .latest_version()
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("no state has been committed yet"))?,
+            height => height,
+        };
+
+        let jmt_proof = jmt::JellyfishMerkleTree::new(&self.storage)
+            .get_with_ics23_proof(key.clone(), height)
+            .await?;
+        let value = jmt_proof.value.clone();
+
+        let proof = if query.prove {
+            let commitment_proof = ics23::CommitmentProof {
+                proof: Some(ics23::commitment_proof::Proof::Exist(jmt_proof)),
+            };
+
+            let op = tendermint::merkle::proof::ProofOp {
+                field_type: "jmt:v".to_string(),
+                key: key.clone(),
+                data: commitment_proof.encode_to_vec(),
+            };
+            Some(tendermint::merkle::proof::Proof { ops: vec![op] })
+        } else {
+            None
+        };
+
+        Ok(abci::response::Query {
+            code: 0,
+            key: key.into(),
+            log: "".to_string(),
+            value: value.into(),
+            proof,
+            height: height.try_into().unwrap(),
+            codespace: "".to_string(),
+            info: "".to_string(),
+            index: 0,
+        })
     }
 }
 
@@ -12,11 +12,13 @@ use penumbra_proto::{
     chain::{ChainParams, CompactBlock, KnownAssets},
     client::oblivious::{
         oblivious_query_server::ObliviousQuery, AssetListRequest, ChainParamsRequest,
-        CompactBlockRangeRequest, ValidatorInfoRequest,
+        ChainParamsResponse, CompactBlockRangeRequest, MempoolSnapshotRequest,
+        MempoolSnapshotResponse, ValidatorInfoRequest,
     },
     stake::ValidatorInfo,
     Protobuf,
 };
+use penumbra_storage::State;
 use tokio::sync::mpsc;
 use tonic::Status;
 use tracing::{instrument, Instrument};
@@ -47,7 +49,33 @@ impl Drop for CompactBlockConnectionCounter {
     }
 }
 
-use super::Info;
+use super::{Info, ResumptionKey, ResumptionToken};
+
+/// Sends the compact block at `height` to the client, along with a token to resume the stream
+/// immediately after it.
+async fn send_compact_block(
+    state: &State,
+    tx: &mpsc::Sender<Result<CompactBlock, tonic::Status>>,
+    key: &ResumptionKey,
+    chain_id: &str,
+    height: u64,
+    end_height: u64,
+    keep_alive: bool,
+) -> anyhow::Result<()> {
+    let block = state
+        .compact_block(height)
+        .await?
+        .expect("compact block for in-range height must be present");
+    let resumption_token =
+        ResumptionToken::new(chain_id.to_string(), height + 1, end_height, keep_alive);
+    tx.send(Ok(CompactBlock {
+        resumption_token: resumption_token.encode(key).into(),
+        ..block.to_proto()
+    }))
+    .await?;
+    metrics::increment_counter!(metrics::CLIENT_OBLIVIOUS_COMPACT_BLOCK_SERVED_TOTAL);
+    Ok(())
+}
 
 #[tonic::async_trait]
 impl ObliviousQuery for Info {
@@ -72,6 +100,27 @@ impl ObliviousQuery for Info {
         Ok(tonic::Response::new(chain_params.into()))
     }
 
+    #[instrument(skip(self, request))]
+    async fn chain_params_with_height(
+        &self,
+        request: tonic::Request<ChainParamsRequest>,
+    ) -> Result<tonic::Response<ChainParamsResponse>, Status> {
+        let state = self.state_tonic().await?;
+        state.check_chain_id(&request.get_ref().chain_id).await?;
+
+        let chain_params = state.get_chain_params().await.map_err(|e| {
+            tonic::Status::unavailable(format!("error getting chain parameters: {}", e))
+        })?;
+        let height = state.get_block_height().await.map_err(|e| {
+            tonic::Status::unavailable(format!("error getting block height: {}", e))
+        })?;
+
+        Ok(tonic::Response::new(ChainParamsResponse {
+            chain_params: Some(chain_params.into()),
+            height,
+        }))
+    }
+
     #[instrument(skip(self, request))]
     async fn asset_list(
         &self,
@@ -129,6 +178,7 @@ impl ObliviousQuery for Info {
             start_height = request.get_ref().start_height,
             end_height = request.get_ref().end_height,
             keep_alive = request.get_ref().keep_alive,
+            resuming = !request.get_ref().resumption_token.is_empty(),
         ),
     )]
     async fn compact_block_range(
@@ -138,13 +188,32 @@ impl ObliviousQuery for Info {
         let state = self.state_tonic().await?;
         state.check_chain_id(&request.get_ref().chain_id).await?;
 
+        let chain_id = state
+            .get_chain_id()
+            .await
+            .map_err(|e| tonic::Status::unavailable(format!("error getting chain id: {}", e)))?;
+
         let CompactBlockRangeRequest {
             start_height,
             end_height,
             keep_alive,
+            resumption_token,
             ..
         } = request.into_inner();
 
+        // If the client is resuming a previous stream, the token determines the
+        // parameters of the stream, rather than the rest of the request.
+        let (start_height, end_height, keep_alive) = if resumption_token.is_empty() {
+            (start_height, end_height, keep_alive)
+        } else {
+            let token = ResumptionToken::decode(&resumption_token, &chain_id, &self.resumption_key)
+                .map_err(|e| {
+                    tonic::Status::invalid_argument(format!("invalid resumption token: {}", e))
+                })?;
+            tracing::debug!(?token, "resuming compact block stream");
+            (token.next_height, token.end_height, token.keep_alive)
+        };
+
         let current_height = state.get_block_height().await.map_err(|e| {
             tonic::Status::unavailable(format!("error getting block height: {}", e))
         })?;
@@ -152,16 +221,21 @@ impl ObliviousQuery for Info {
         // Treat end_height = 0 as end_height = current_height so that if the
         // end_height is unspecified in the proto, it will be treated as a
         // request to sync up to the current height.
-        let end_height = if end_height == 0 {
+        //
+        // This is only done for fresh requests: a resumed stream keeps the
+        // end height of the original request, so that its tokens stay stable.
+        let requested_end_height = if end_height == 0 && resumption_token.is_empty() {
             current_height
         } else {
-            std::cmp::min(end_height, current_height)
+            end_height
         };
+        let end_height = std::cmp::min(requested_end_height, current_height);
 
         // Clone these, so we can keep copies in the worker task we spawn
         // to handle this request.
         let storage = self.storage.clone();
         let mut height_rx = self.height_rx.clone();
+        let resumption_key = self.resumption_key.clone();
 
         let (tx, rx) = mpsc::channel(10);
         let txerr = tx.clone();
@@ -175,14 +249,16 @@ impl ObliviousQuery for Info {
                     "catching up from start height to current end height"
                 );
                 for height in start_height..=end_height {
-                    let block = state
-                        .compact_block(height)
-                        .await?
-                        .expect("compact block for in-range height must be present");
-                    tx.send(Ok(block.to_proto())).await?;
-                    metrics::increment_counter!(
-                        metrics::CLIENT_OBLIVIOUS_COMPACT_BLOCK_SERVED_TOTAL
-                    );
+                    send_compact_block(
+                        &state,
+                        &tx,
+                        &resumption_key,
+                        &chain_id,
+                        height,
+                        requested_end_height,
+                        keep_alive,
+                    )
+                    .await?;
                 }
 
                 // If the client didn't request a keep-alive, we're done.
@@ -200,20 +276,25 @@ impl ObliviousQuery for Info {
                     "finished request, client requested keep-alive, continuing to stream blocks"
                 );
 
-                // We want to send all blocks *after* end_height (which we already sent)
+                // We want to send all blocks *after* the ones we already sent
                 // up to and including cur_height (which we won't send in the loop below).
-                // This range could be empty.
-                for height in (end_height + 1)..=cur_height {
+                // This range could be empty. A resumed stream may start past end_height,
+                // in which case we must not resend blocks the client already has.
+                let mut next_height = std::cmp::max(start_height, end_height + 1);
+                for height in next_height..=cur_height {
                     tracing::debug!(?height, "sending block in phase 2 catch-up");
-                    let block = state
-                        .compact_block(height)
-                        .await?
-                        .expect("compact block for in-range height must be present");
-                    tx.send(Ok(block.to_proto())).await?;
-                    metrics::increment_counter!(
-                        metrics::CLIENT_OBLIVIOUS_COMPACT_BLOCK_SERVED_TOTAL
-                    );
+                    send_compact_block(
+                        &state,
+                        &tx,
+                        &resumption_key,
+                        &chain_id,
+                        height,
+                        requested_end_height,
+                        keep_alive,
+                    )
+                    .await?;
                 }
+                next_height = std::cmp::max(next_height, cur_height + 1);
 
                 // Phase 2: wait on the height notifier and stream blocks as
                 // they're created.
@@ -223,16 +304,22 @@ impl ObliviousQuery for Info {
                 loop {
                     height_rx.changed().await?;
                     let height = height_rx.borrow().value();
+                    if height < next_height {
+                        continue;
+                    }
                     tracing::debug!(?height, "notifying client of new block");
                     let state = storage.state().await?;
-                    let block = state
-                        .compact_block(height)
-                        .await?
-                        .expect("compact block for in-range height must be present");
-                    tx.send(Ok(block.to_proto())).await?;
-                    metrics::increment_counter!(
-                        metrics::CLIENT_OBLIVIOUS_COMPACT_BLOCK_SERVED_TOTAL
-                    );
+                    send_compact_block(
+                        &state,
+                        &tx,
+                        &resumption_key,
+                        &chain_id,
+                        height,
+                        requested_end_height,
+                        keep_alive,
+                    )
+                    .await?;
+                    next_height = height + 1;
                 }
             }
             .map_err(|e| async move {
@@ -256,4 +343,14 @@ impl ObliviousQuery for Info {
             tokio_stream::wrappers::ReceiverStream::new(rx).boxed(),
         ))
     }
+
+    #[instrument(skip(self, _request))]
+    async fn mempool_snapshot(
+        &self,
+        _request: tonic::Request<MempoolSnapshotRequest>,
+    ) -> Result<tonic::Response<MempoolSnapshotResponse>, Status> {
+        let entries = self.mempool_rx.borrow().iter().map(Into::into).collect();
+
+        Ok(tonic::Response::new(MempoolSnapshotResponse { entries }))
+    }
 }
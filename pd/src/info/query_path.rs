@@ -0,0 +1,167 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use penumbra_crypto::{IdentityKey, Nullifier};
+
+/// The parts of a validator's state which can be queried under `validators/<identity key>/`.
+const VALIDATOR_FIELDS: [&str; 5] = [
+    "state",
+    "power",
+    "rate/current",
+    "rate/next",
+    "bonding_state",
+];
+
+/// The path of an ABCI `Query`, which names the state key whose value to return, along with a
+/// proof of it against the app hash.
+///
+/// The paths are:
+///
+/// - `state/key`, for the raw key given as the query's data;
+/// - `state/nullifier/<hex nullifier>`, for the height at which a nullifier was spent;
+/// - `state/anchor/<height>`, for the note commitment tree root at a height;
+/// - `validators/<identity key>`, for a validator's definition, and
+///   `validators/<identity key>/<field>`, for its `state`, `power`, `rate/current`,
+///   `rate/next`, or `bonding_state`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryPath {
+    Key,
+    Nullifier(Nullifier),
+    Anchor(u64),
+    Validator {
+        identity_key: IdentityKey,
+        field: Option<&'static str>,
+    },
+}
+
+impl QueryPath {
+    /// The state key this path names, given the query's data.
+    pub fn key(&self, data: &[u8]) -> Vec<u8> {
+        // These mirror the keys the components write to, in their `state_key` modules.
+        match self {
+            QueryPath::Key => data.to_vec(),
+            QueryPath::Nullifier(nullifier) => {
+                format!("shielded_pool/spent_nullifiers/{}", nullifier).into_bytes()
+            }
+            QueryPath::Anchor(height) => format!("shielded_pool/anchor/{}", height).into_bytes(),
+            QueryPath::Validator {
+                identity_key,
+                field: None,
+            } => format!("staking/validators/{}", identity_key).into_bytes(),
+            QueryPath::Validator {
+                identity_key,
+                field: Some(field),
+            } => format!("staking/validators/{}/{}", identity_key, field).into_bytes(),
+        }
+    }
+}
+
+impl FromStr for QueryPath {
+    type Err = anyhow::Error;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        // Tendermint passes the path through as the client gave it, which may have a leading
+        // slash.
+        let path = path.strip_prefix('/').unwrap_or(path);
+
+        if path == "state/key" {
+            Ok(QueryPath::Key)
+        } else if let Some(nullifier) = path.strip_prefix("state/nullifier/") {
+            let bytes = hex::decode(nullifier).context("nullifier must be hex-encoded")?;
+            Ok(QueryPath::Nullifier(Nullifier::try_from(bytes.as_slice())?))
+        } else if let Some(height) = path.strip_prefix("state/anchor/") {
+            Ok(QueryPath::Anchor(
+                height.parse().context("anchor height must be a number")?,
+            ))
+        } else if let Some(validator) = path.strip_prefix("validators/") {
+            let (identity_key, field) = match validator.split_once('/') {
+                Some((identity_key, field)) => {
+                    let field = VALIDATOR_FIELDS
+                        .iter()
+                        .find(|known| **known == field)
+                        .ok_or_else(|| anyhow!("unknown validator field {:?}", field))?;
+                    (identity_key, Some(*field))
+                }
+                None => (validator, None),
+            };
+            Ok(QueryPath::Validator {
+                identity_key: identity_key.parse()?,
+                field,
+            })
+        } else {
+            Err(anyhow!("unknown query path {:?}", path))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_component::{shielded_pool, stake};
+    use penumbra_crypto::{
+        rdsa::{SigningKey, SpendAuth},
+        Fq,
+    };
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn key(path: &str) -> jmt::KeyHash {
+        path.parse::<QueryPath>().unwrap().key(&[]).into()
+    }
+
+    #[test]
+    fn paths_name_the_keys_the_components_write() {
+        let nullifier = Nullifier(Fq::from(1u64));
+        assert_eq!(
+            key(&format!(
+                "state/nullifier/{}",
+                hex::encode(nullifier.to_bytes())
+            )),
+            shielded_pool::state_key::spent_nullifier_lookup(&nullifier)
+        );
+        assert_eq!(
+            key("/state/anchor/7"),
+            shielded_pool::state_key::anchor_by_height(&7)
+        );
+
+        let identity_key = IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into());
+        let validator_key = |field: &str| match field {
+            "" => key(&format!("validators/{}", identity_key)),
+            field => key(&format!("validators/{}/{}", identity_key, field)),
+        };
+        assert_eq!(
+            validator_key(""),
+            stake::state_key::validator_definition(&identity_key)
+        );
+        assert_eq!(
+            validator_key("state"),
+            stake::state_key::validator_state(&identity_key)
+        );
+        assert_eq!(
+            validator_key("power"),
+            stake::state_key::validator_power(&identity_key)
+        );
+        assert_eq!(
+            validator_key("rate/current"),
+            stake::state_key::current_rate(&identity_key)
+        );
+        assert_eq!(
+            validator_key("rate/next"),
+            stake::state_key::next_rate(&identity_key)
+        );
+        assert_eq!(
+            validator_key("bonding_state"),
+            stake::state_key::validator_bonding_state(&identity_key)
+        );
+    }
+
+    #[test]
+    fn unknown_paths_are_rejected() {
+        let identity_key = IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into());
+        assert!(format!("validators/{}/uptime", identity_key)
+            .parse::<QueryPath>()
+            .is_err());
+        assert!("state/anchor/latest".parse::<QueryPath>().is_err());
+        assert!("storage/key".parse::<QueryPath>().is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// How long a [`ResumptionToken`] remains valid after it was issued.
+pub const RESUMPTION_TOKEN_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The name of the file in the `pd` home directory storing the key resumption tokens are
+/// authenticated with.
+pub const RESUMPTION_KEY_FILE_NAME: &str = "resumption_token_key";
+
+/// The version byte prefixed to every encoded token, so we can change the format later.
+const VERSION: u8 = 0;
+
+/// The length of the MAC appended to every encoded token.
+const MAC_LEN: usize = 16;
+
+/// The secret key with which a node authenticates the resumption tokens it issues, so that
+/// clients can't forge tokens for streams they never requested.
+#[derive(Clone)]
+pub struct ResumptionKey([u8; 32]);
+
+impl ResumptionKey {
+    /// Read the node's resumption key from `home`, generating and saving a new one if there is
+    /// none.
+    pub fn load_or_generate(home: &Path) -> Result<Self> {
+        let path = home.join(RESUMPTION_KEY_FILE_NAME);
+        if path.exists() {
+            let key = std::fs::read_to_string(&path).with_context(|| {
+                format!("could not read resumption key from {}", path.display())
+            })?;
+            let key = hex::decode(key.trim())?
+                .try_into()
+                .map_err(|_| anyhow!("resumption key in {} is malformed", path.display()))?;
+            return Ok(Self(key));
+        }
+
+        // The key is only readable by the node's user, since anyone who can read it can forge
+        // tokens.
+        let key = rand::thread_rng().gen::<[u8; 32]>();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&path)
+            .and_then(|mut file| file.write_all(hex::encode(key).as_bytes()))
+            .with_context(|| format!("could not write resumption key to {}", path.display()))?;
+        Ok(Self(key))
+    }
+}
+
+impl std::fmt::Debug for ResumptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ResumptionKey(..)")
+    }
+}
+
+/// A token which lets a client resume a compact block stream immediately after the last block it
+/// received, with the same parameters as the original request.
+///
+/// Tokens are opaque to clients, and are self-contained and authenticated with the node's
+/// [`ResumptionKey`], so that they remain valid across `pd` restarts, and on any node sharing
+/// the key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumptionToken {
+    /// The chain for which this token was issued.
+    pub chain_id: String,
+    /// The height of the next block to send.
+    pub next_height: u64,
+    /// The end height of the original request (after defaulting).
+    pub end_height: u64,
+    /// Whether the original request asked to keep the stream alive.
+    pub keep_alive: bool,
+    /// The time at which this token was issued, in seconds since the Unix epoch.
+    pub issued_at: u64,
+}
+
+impl ResumptionToken {
+    /// Create a new token, issued now.
+    pub fn new(chain_id: String, next_height: u64, end_height: u64, keep_alive: bool) -> Self {
+        Self {
+            chain_id,
+            next_height,
+            end_height,
+            keep_alive,
+            issued_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("system time is after the Unix epoch")
+                .as_secs(),
+        }
+    }
+
+    /// Encode this token to opaque bytes, authenticated with `key`.
+    pub fn encode(&self, key: &ResumptionKey) -> Vec<u8> {
+        let mut bytes = vec![VERSION];
+        bytes.extend(bincode::serialize(self).expect("can serialize resumption token"));
+        let mac = mac(key, &bytes);
+        bytes.extend_from_slice(&mac);
+        bytes
+    }
+
+    /// Decode a token from bytes, checking that it was issued with `key`, for the given chain,
+    /// and has not expired.
+    pub fn decode(bytes: &[u8], chain_id: &str, key: &ResumptionKey) -> Result<Self> {
+        if bytes.len() < 1 + MAC_LEN {
+            return Err(anyhow!("resumption token is too short"));
+        }
+        let (body, expected_mac) = bytes.split_at(bytes.len() - MAC_LEN);
+        if !constant_time_eq(&mac(key, body), expected_mac) {
+            return Err(anyhow!("resumption token was not issued by this node"));
+        }
+        if body[0] != VERSION {
+            return Err(anyhow!("unknown resumption token version {}", body[0]));
+        }
+
+        let token: Self = bincode::deserialize(&body[1..])?;
+
+        if token.chain_id != chain_id {
+            return Err(anyhow!(
+                "resumption token was issued for chain {}, not {}",
+                token.chain_id,
+                chain_id
+            ));
+        }
+
+        let issued_at = UNIX_EPOCH + Duration::from_secs(token.issued_at);
+        if issued_at + RESUMPTION_TOKEN_LIFETIME < SystemTime::now() {
+            return Err(anyhow!("resumption token has expired"));
+        }
+
+        Ok(token)
+    }
+}
+
+fn mac(key: &ResumptionKey, bytes: &[u8]) -> [u8; MAC_LEN] {
+    let hash = blake2b_simd::Params::new()
+        .hash_length(MAC_LEN)
+        .key(&key.0)
+        .personal(b"pd_resume_token")
+        .hash(bytes);
+    hash.as_bytes()
+        .try_into()
+        .expect("MAC has the configured length")
+}
+
+/// Compare two MACs without leaking, through timing, how long a prefix of a forgery is right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> ResumptionKey {
+        ResumptionKey([7; 32])
+    }
+
+    #[test]
+    fn generated_key_is_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = ResumptionKey::load_or_generate(dir.path()).unwrap();
+        assert_eq!(
+            ResumptionKey::load_or_generate(dir.path()).unwrap().0,
+            key.0
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(dir.path().join(RESUMPTION_KEY_FILE_NAME)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let token = ResumptionToken::new("penumbra-test".to_string(), 17, 100, true);
+        let decoded =
+            ResumptionToken::decode(&token.encode(&key()), "penumbra-test", &key()).unwrap();
+        assert_eq!(token, decoded);
+    }
+
+    #[test]
+    fn wrong_chain_rejected() {
+        let token = ResumptionToken::new("penumbra-test".to_string(), 17, 100, true);
+        assert!(ResumptionToken::decode(&token.encode(&key()), "penumbra-other", &key()).is_err());
+    }
+
+    #[test]
+    fn wrong_key_rejected() {
+        let token = ResumptionToken::new("penumbra-test".to_string(), 17, 100, true);
+        let other_key = ResumptionKey([8; 32]);
+        assert!(
+            ResumptionToken::decode(&token.encode(&other_key), "penumbra-test", &key()).is_err()
+        );
+    }
+
+    #[test]
+    fn corrupted_rejected() {
+        let token = ResumptionToken::new("penumbra-test".to_string(), 17, 100, true);
+        let mut bytes = token.encode(&key());
+        bytes[3] ^= 1;
+        assert!(ResumptionToken::decode(&bytes, "penumbra-test", &key()).is_err());
+    }
+
+    #[test]
+    fn expired_rejected() {
+        let mut token = ResumptionToken::new("penumbra-test".to_string(), 17, 100, true);
+        token.issued_at -= RESUMPTION_TOKEN_LIFETIME.as_secs() + 1;
+        assert!(ResumptionToken::decode(&token.encode(&key()), "penumbra-test", &key()).is_err());
+    }
+
+    #[test]
+    fn key_persists_across_restarts() {
+        let home = tempfile::tempdir().unwrap();
+        let key = ResumptionKey::load_or_generate(home.path()).unwrap();
+        let reloaded = ResumptionKey::load_or_generate(home.path()).unwrap();
+        assert_eq!(key.0, reloaded.0);
+    }
+}
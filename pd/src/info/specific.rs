@@ -6,7 +6,7 @@ use penumbra_proto::{
     chain::NoteSource,
     client::specific::{
         specific_query_server::SpecificQuery, KeyValueRequest, KeyValueResponse,
-        ValidatorStatusRequest,
+        ValidatorBlockStatsRequest, ValidatorStatusRequest,
     },
     crypto::NoteCommitment,
 };
@@ -68,6 +68,42 @@ impl SpecificQuery for Info {
         Ok(tonic::Response::new(status.into()))
     }
 
+    #[instrument(skip(self, request))]
+    async fn validator_block_stats(
+        &self,
+        request: tonic::Request<ValidatorBlockStatsRequest>,
+    ) -> Result<tonic::Response<proto::stake::ValidatorBlockStats>, Status> {
+        let state = self.state_tonic().await?;
+        state.check_chain_id(&request.get_ref().chain_id).await?;
+
+        let id = request
+            .into_inner()
+            .identity_key
+            .ok_or_else(|| Status::invalid_argument("missing identity key"))?
+            .try_into()
+            .map_err(|_| Status::invalid_argument("invalid identity key"))?;
+
+        if state
+            .validator(&id)
+            .await
+            .map_err(|e| Status::unavailable(format!("error getting validator: {}", e)))?
+            .is_none()
+        {
+            return Err(Status::not_found("validator not found"));
+        }
+
+        // A validator which has never been active has no statistics yet.
+        let stats = state
+            .validator_block_stats(&id)
+            .await
+            .map_err(|e| {
+                Status::unavailable(format!("error getting validator block stats: {}", e))
+            })?
+            .unwrap_or_default();
+
+        Ok(tonic::Response::new(stats.into()))
+    }
+
     #[instrument(skip(self, request))]
     async fn next_validator_rate(
         &self,
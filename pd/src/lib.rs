@@ -5,19 +5,30 @@
 #![allow(clippy::clone_on_copy)]
 
 mod consensus;
+pub mod devnet;
 mod info;
+mod load_shed;
 mod mempool;
 mod metrics;
+mod rejection;
 mod request_ext;
+mod response_metadata;
 mod snapshot;
+pub mod telemetry;
 
+#[cfg(feature = "simulate")]
+pub mod simulate;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod testnet;
 
 use request_ext::RequestExt;
 
 pub use crate::metrics::register_metrics;
 pub use consensus::Consensus;
-pub use info::Info;
-pub use mempool::Mempool;
+pub use info::{Info, ResumptionKey};
+pub use load_shed::{LoadShedLayer, NodeLoad};
+pub use mempool::{Mempool, MempoolEntry};
 pub use penumbra_component::app::App;
+pub use response_metadata::ResponseMetadataLayer;
 pub use snapshot::Snapshot;
@@ -0,0 +1,328 @@
+//! A layer shedding low-priority gRPC traffic while the node is busy, so that wallets syncing
+//! against `pd` can never starve consensus.
+//!
+//! Bulk queries, like streaming compact blocks, are held back while a block is being committed,
+//! and rejected while the node is catching up with the chain. Rejected requests get an
+//! `Unavailable` status with a `retry-after` hint, in seconds, in their metadata.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::FutureExt;
+use penumbra_proto::client::RETRY_AFTER_METADATA_KEY;
+use tokio::sync::watch;
+use tonic::{body::BoxBody, metadata::MetadataMap, Code, Status};
+use tower::{Layer, Service};
+
+use crate::metrics;
+
+/// The gRPC methods whose requests may be delayed or rejected while the node is busy.
+///
+/// These are the queries wallets make in bulk while syncing. Cheap point queries, like the chain
+/// parameters, are always served.
+const LOW_PRIORITY_METHODS: &[&str] = &[
+    "/penumbra.client.oblivious.ObliviousQuery/CompactBlockRange",
+    "/penumbra.client.oblivious.ObliviousQuery/ValidatorInfo",
+    "/penumbra.client.oblivious.ObliviousQuery/AssetList",
+    "/penumbra.client.specific.SpecificQuery/TransactionByNote",
+    "/penumbra.client.specific.SpecificQuery/KeyValue",
+];
+
+/// How long to suggest clients wait before retrying while a block is being committed.
+const COMMIT_RETRY_AFTER: Duration = Duration::from_secs(1);
+/// How long to suggest clients wait before retrying while the node is catching up.
+const CATCH_UP_RETRY_AFTER: Duration = Duration::from_secs(30);
+/// How often to ask Tendermint whether the node is catching up.
+const CATCH_UP_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether the node is busy, shared between the consensus worker, which reports block commits,
+/// and the [`LoadShedLayer`].
+#[derive(Clone, Debug)]
+pub struct NodeLoad {
+    committing: Arc<watch::Sender<bool>>,
+    catching_up: Arc<AtomicBool>,
+}
+
+impl Default for NodeLoad {
+    fn default() -> Self {
+        Self {
+            committing: Arc::new(watch::channel(false).0),
+            catching_up: Default::default(),
+        }
+    }
+}
+
+impl NodeLoad {
+    /// Mark a block commit as in flight, until the returned guard is dropped.
+    pub fn begin_commit(&self) -> CommitGuard {
+        self.committing.send_replace(true);
+        CommitGuard(self.committing.clone())
+    }
+
+    /// Whether the node is catching up with the chain, as last reported by Tendermint.
+    pub fn catching_up(&self) -> bool {
+        self.catching_up.load(Ordering::Relaxed)
+    }
+
+    /// Wait for any block commit in flight to finish, for at most `timeout`, returning whether it
+    /// finished in time.
+    async fn wait_for_commit(&self, timeout: Duration) -> bool {
+        let mut committing = self.committing.subscribe();
+        let finished = async move {
+            while *committing.borrow_and_update() {
+                if committing.changed().await.is_err() {
+                    break;
+                }
+            }
+        };
+        tokio::time::timeout(timeout, finished).await.is_ok()
+    }
+
+    /// Poll the Tendermint RPC at `tendermint_rpc` for whether the node is catching up, until the
+    /// process exits.
+    ///
+    /// If Tendermint can't be reached, the last known state is kept.
+    pub async fn watch_catch_up(self, tendermint_rpc: String) -> anyhow::Result<()> {
+        let client = reqwest::Client::new();
+        let mut interval = tokio::time::interval(CATCH_UP_POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            match catching_up(&client, &tendermint_rpc).await {
+                Ok(catching_up) => {
+                    if self.catching_up.swap(catching_up, Ordering::Relaxed) != catching_up {
+                        tracing::info!(catching_up, "node catch-up state changed");
+                    }
+                }
+                Err(e) => tracing::debug!(?e, "could not check whether the node is catching up"),
+            }
+        }
+    }
+}
+
+/// Marks a block commit as in flight while it's alive. See [`NodeLoad::begin_commit`].
+#[derive(Debug)]
+pub struct CommitGuard(Arc<watch::Sender<bool>>);
+
+impl Drop for CommitGuard {
+    fn drop(&mut self) {
+        self.0.send_replace(false);
+    }
+}
+
+/// Ask the Tendermint RPC at `tendermint_rpc` whether the node is catching up.
+async fn catching_up(client: &reqwest::Client, tendermint_rpc: &str) -> anyhow::Result<bool> {
+    let rsp: serde_json::Value = client
+        .get(format!("{}/status", tendermint_rpc))
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    rsp["result"]["sync_info"]["catching_up"]
+        .as_bool()
+        .ok_or_else(|| anyhow::anyhow!("missing catch-up state in response"))
+}
+
+/// Delays low-priority requests while a block is being committed, for at most `max_delay`, and
+/// rejects them if the commit takes longer, or while the node is catching up.
+#[derive(Clone, Debug)]
+pub struct LoadShedLayer {
+    load: NodeLoad,
+    max_delay: Duration,
+}
+
+impl LoadShedLayer {
+    pub fn new(load: NodeLoad, max_delay: Duration) -> Self {
+        Self { load, max_delay }
+    }
+}
+
+impl<S> Layer<S> for LoadShedLayer {
+    type Service = LoadShed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoadShed {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service produced by [`LoadShedLayer`].
+#[derive(Clone, Debug)]
+pub struct LoadShed<S> {
+    inner: S,
+    layer: LoadShedLayer,
+}
+
+impl<S, ReqBody> Service<http::Request<ReqBody>> for LoadShed<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        if !LOW_PRIORITY_METHODS.contains(&req.uri().path()) {
+            return self.inner.call(req).boxed();
+        }
+
+        // The inner service was driven to readiness, so keep it for this request, leaving a
+        // clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        async move {
+            if layer.load.catching_up() {
+                return Ok(shed(
+                    req.uri().path(),
+                    "node is catching up with the chain",
+                    CATCH_UP_RETRY_AFTER,
+                ));
+            }
+            if !layer.load.wait_for_commit(layer.max_delay).await {
+                return Ok(shed(
+                    req.uri().path(),
+                    "node is committing a block",
+                    COMMIT_RETRY_AFTER,
+                ));
+            }
+
+            inner.call(req).await
+        }
+        .boxed()
+    }
+}
+
+/// The response rejecting a request to `method` for `reason`, suggesting it be retried after
+/// `retry_after`.
+fn shed(method: &str, reason: &str, retry_after: Duration) -> http::Response<BoxBody> {
+    tracing::debug!(method, reason, "shedding low-priority request");
+    metrics::increment_counter!(metrics::GRPC_REQUESTS_SHED_TOTAL);
+
+    let mut metadata = MetadataMap::new();
+    metadata.insert(
+        RETRY_AFTER_METADATA_KEY,
+        retry_after
+            .as_secs()
+            .to_string()
+            .parse()
+            .expect("integers are valid metadata values"),
+    );
+
+    Status::with_metadata(
+        Code::Unavailable,
+        format!("{}, retry in {}s", reason, retry_after.as_secs()),
+        metadata,
+    )
+    .to_http()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const COMPACT_BLOCK_RANGE: &str = "/penumbra.client.oblivious.ObliviousQuery/CompactBlockRange";
+    const CHAIN_PARAMS: &str = "/penumbra.client.oblivious.ObliviousQuery/ChainParams";
+
+    /// Make a request to `method` through a [`LoadShed`] service in front of one which always
+    /// succeeds, returning the status it was rejected with, if any.
+    async fn request(load: &NodeLoad, max_delay: Duration, method: &str) -> Result<(), Status> {
+        let service = LoadShedLayer::new(load.clone(), max_delay).layer(tower::service_fn(
+            |_req: http::Request<()>| async {
+                Ok::<_, Infallible>(http::Response::new(tonic::body::empty_body()))
+            },
+        ));
+        let req = http::Request::builder().uri(method).body(()).unwrap();
+        let rsp = service.oneshot(req).await.unwrap();
+
+        match Status::from_header_map(rsp.headers()) {
+            Some(status) => Err(status),
+            None => Ok(()),
+        }
+    }
+
+    fn retry_after(status: &Status) -> &str {
+        status
+            .metadata()
+            .get(RETRY_AFTER_METADATA_KEY)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn low_priority_requests_wait_for_commits() {
+        let load = NodeLoad::default();
+        let commit = load.begin_commit();
+
+        let rsp = request(&load, Duration::from_secs(10), COMPACT_BLOCK_RANGE);
+        tokio::pin!(rsp);
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut rsp)
+            .await
+            .is_err());
+
+        drop(commit);
+        rsp.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn low_priority_requests_are_shed_after_max_delay() {
+        let load = NodeLoad::default();
+        let _commit = load.begin_commit();
+
+        let status = request(&load, Duration::from_millis(50), COMPACT_BLOCK_RANGE)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(retry_after(&status), "1");
+    }
+
+    #[tokio::test]
+    async fn low_priority_requests_are_shed_while_catching_up() {
+        let load = NodeLoad::default();
+        load.catching_up.store(true, Ordering::Relaxed);
+
+        let status = request(&load, Duration::from_secs(10), COMPACT_BLOCK_RANGE)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(retry_after(&status), "30");
+    }
+
+    #[tokio::test]
+    async fn other_requests_are_never_held_back() {
+        let load = NodeLoad::default();
+        let _commit = load.begin_commit();
+        load.catching_up.store(true, Ordering::Relaxed);
+
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            request(&load, Duration::from_secs(10), CHAIN_PARAMS),
+        )
+        .await
+        .expect("request was held back")
+        .unwrap();
+    }
+}
@@ -1,8 +1,10 @@
 #![allow(clippy::clone_on_copy)]
 #![recursion_limit = "256"]
 use std::{
+    ffi::OsString,
     net::{Ipv4Addr, SocketAddr},
     path::PathBuf,
+    time::Duration,
 };
 
 use console_subscriber::ConsoleLayer;
@@ -12,9 +14,20 @@ use metrics_util::layers::Stack;
 use anyhow::Context;
 use clap::{Parser, Subcommand};
 use metrics_exporter_prometheus::PrometheusBuilder;
-use pd::testnet::{canonicalize_path, generate_tm_config, write_configs, ValidatorKeys};
+use opentelemetry::{
+    sdk::{trace as sdktrace, Resource},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use pd::{
+    telemetry::{BlockTimes, Reporter},
+    testnet::{canonicalize_path, generate_tm_config, write_configs, ValidatorKeys},
+};
 use penumbra_chain::{genesis::Allocation, params::ChainParams};
-use penumbra_component::stake::{validator::Validator, FundingStream, FundingStreams};
+use penumbra_component::{
+    app::admission::ACTION_KINDS,
+    stake::{validator::Validator, FundingStream, FundingStreams},
+};
 use penumbra_crypto::{keys::SpendKey, DelegationToken};
 use penumbra_proto::client::{
     oblivious::oblivious_query_server::ObliviousQueryServer,
@@ -23,9 +36,16 @@ use penumbra_proto::client::{
 use penumbra_storage::Storage;
 use rand::Rng;
 use rand_core::OsRng;
-use tokio::runtime;
+use tokio::{
+    runtime,
+    signal::unix::{signal, SignalKind},
+};
 use tonic::transport::Server;
-use tracing_subscriber::{prelude::*, EnvFilter};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::{prelude::*, registry::LookupSpan, reload, EnvFilter, Registry};
+
+/// The name of the file in `pd`'s home directory from which the log filter is reloaded on `SIGHUP`.
+const LOG_FILTER_FILE_NAME: &str = "log_filter";
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -42,22 +62,29 @@ struct Opt {
 #[derive(Debug, Subcommand)]
 enum RootCommand {
     /// Start running the ABCI and wallet services.
-    Start {
-        /// The path used to store pd-releated data, including the Rocks database.
+    Start(StartOptions),
+
+    /// Run a single-node development chain, with pre-funded test accounts.
+    ///
+    /// The first run generates the devnet in `--home`; later runs resume it. Tendermint must be
+    /// installed, as `pd` runs it alongside itself, configured to make a block as soon as there's
+    /// a transaction to include.
+    Devnet {
+        /// The directory to keep the devnet in [default: ~/.penumbra/devnet].
         #[clap(long)]
-        home: PathBuf,
-        /// Bind the services to this host.
-        #[clap(long, default_value = "127.0.0.1")]
-        host: String,
-        /// Bind the ABCI server to this port.
-        #[clap(short, long, default_value = "26658")]
-        abci_port: u16,
-        /// Bind the gRPC server to this port.
-        #[clap(short, long, default_value = "8080")]
-        grpc_port: u16,
-        /// Bind the metrics endpoint to this port.
-        #[clap(short, long, default_value = "9000")]
-        metrics_port: u16,
+        home: Option<PathBuf>,
+        /// Delete the devnet in `--home`, if there is one, and generate a new one.
+        #[clap(long)]
+        reset: bool,
+        /// The number of test accounts to fund at genesis.
+        #[clap(long, default_value = "2")]
+        accounts: usize,
+        /// Number of blocks per epoch.
+        #[clap(long, default_value = "10")]
+        epoch_duration: u64,
+        /// The `tendermint` binary to run.
+        #[clap(long, default_value = "tendermint")]
+        tendermint: PathBuf,
     },
 
     /// Generate, join, or reset a testnet.
@@ -70,6 +97,69 @@ enum RootCommand {
         #[clap(subcommand)]
         tn_cmd: TestnetCommand,
     },
+
+    /// Deterministically replay a scripted sequence of blocks, printing the app hash after each.
+    ///
+    /// The script is a JSON file giving the genesis app state and the transactions in each
+    /// block; block times, hashes, and votes are synthesized from a virtual clock and a seeded
+    /// RNG, so replaying the same script always produces the same state.
+    #[cfg(feature = "simulate")]
+    Simulate {
+        /// The script to replay.
+        script: PathBuf,
+        /// The directory to store the simulated chain's Rocks database in. Must not exist.
+        #[clap(long)]
+        home: PathBuf,
+        /// Stop after committing this height.
+        #[clap(long)]
+        stop_at: Option<u64>,
+    },
+}
+
+#[derive(Debug, Parser)]
+struct StartOptions {
+    /// The path used to store pd-releated data, including the Rocks database.
+    #[clap(long)]
+    home: PathBuf,
+    /// Bind the services to this host.
+    #[clap(long, default_value = "127.0.0.1")]
+    host: String,
+    /// Bind the ABCI server to this port.
+    #[clap(short, long, default_value = "26658")]
+    abci_port: u16,
+    /// Bind the gRPC server to this port.
+    #[clap(short, long, default_value = "8080")]
+    grpc_port: u16,
+    /// Bind the metrics endpoint to this port.
+    #[clap(short, long, default_value = "9000")]
+    metrics_port: u16,
+    /// The target block time, in milliseconds, which should match Tendermint's
+    /// `timeout_commit`.
+    #[clap(long, default_value = "5000")]
+    target_block_time_ms: u64,
+    /// Warn whenever committing a block takes longer than this fraction of the target block
+    /// time.
+    #[clap(long, default_value = "0.25")]
+    slow_commit_fraction: f64,
+    /// Opt in to periodically publishing anonymized node statistics (height, version, peer
+    /// count, and block processing times) to this URL. Disabled by default.
+    #[clap(long)]
+    telemetry_endpoint: Option<String>,
+    /// The interval, in seconds, between telemetry reports.
+    #[clap(long, default_value = "60")]
+    telemetry_interval_secs: u64,
+    /// The URL of the Tendermint RPC, from which telemetry reads the peer count, and from
+    /// which `pd` learns whether the node is catching up.
+    #[clap(long, default_value = "http://127.0.0.1:26657")]
+    tendermint_rpc: String,
+    /// How long, in milliseconds, to hold back low-priority queries, like compact block
+    /// streams, while a block is being committed, before rejecting them as unavailable.
+    #[clap(long, default_value = "1000")]
+    max_query_delay_ms: u64,
+    /// If set, export tracing spans, like those of each `CheckTx`, `DeliverTx`, and `Commit`,
+    /// to this OpenTelemetry collector, e.g., Jaeger or Tempo, over OTLP/gRPC.
+    #[clap(long)]
+    otlp_endpoint: Option<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -86,6 +176,31 @@ enum TestnetCommand {
         /// Maximum number of validators in the consensus set.
         #[clap(long, default_value = "32")]
         active_validator_limit: u64,
+        /// Kinds of action which transactions may not contain, e.g. `undelegate`.
+        #[clap(long, possible_values = ACTION_KINDS)]
+        disallowed_actions: Vec<String>,
+        /// If set, the only kinds of action which transactions may contain.
+        #[clap(long, possible_values = ACTION_KINDS)]
+        allowed_actions: Vec<String>,
+        /// The minimum total commission of a validator's funding streams, in basis points.
+        #[clap(long, default_value = "0")]
+        min_validator_commission_bps: u64,
+        /// If set, the maximum total commission of a validator's funding streams, in basis points.
+        #[clap(long)]
+        max_validator_commission_bps: Option<u64>,
+        /// If set, the most a validator's total commission may change by in one epoch, in basis
+        /// points.
+        #[clap(long)]
+        max_validator_commission_change_bps: Option<u64>,
+        /// If set, the number of blocks a transaction's anchor remains valid for.
+        #[clap(long)]
+        anchor_window_len: Option<u64>,
+        /// The minimum fee a transaction must pay.
+        #[clap(long, default_value = "0")]
+        min_fee: u64,
+        /// If set, the maximum size of an encoded transaction, in bytes.
+        #[clap(long)]
+        max_transaction_bytes: Option<u64>,
         /// Whether to preserve the chain ID (useful for public testnets) or append a random suffix (useful for dev/testing).
         #[clap(long)]
         preserve_chain_id: bool,
@@ -126,6 +241,202 @@ fn remote_addr(req: &http::Request<()>) -> Option<SocketAddr> {
         .and_then(|i| i.remote_addr())
 }
 
+/// A tracing layer exporting spans to the OpenTelemetry collector at `endpoint`, in batches, so
+/// that the handling of each ABCI request, e.g., a transaction's `DeliverTx`, can be followed in
+/// a tracing backend.
+fn otlp_exporter<S>(endpoint: &str) -> anyhow::Result<OpenTelemetryLayer<S, sdktrace::Tracer>>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            sdktrace::config()
+                .with_resource(Resource::new(vec![KeyValue::new("service.name", "pd")])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+        .context("Unable to install the OTLP exporter")?;
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Reload the tracing filter whenever `pd` receives a `SIGHUP`, so that operators can change log
+/// levels during an incident without restarting the node.
+///
+/// The new filter directives are read from the file at `path` if it exists, and otherwise from
+/// `RUST_LOG`, as at startup.
+async fn reload_log_filter_on_sighup(
+    path: PathBuf,
+    handle: reload::Handle<EnvFilter, Registry>,
+) -> anyhow::Result<()> {
+    let mut hangups = signal(SignalKind::hangup())?;
+
+    while hangups.recv().await.is_some() {
+        let directives = match std::fs::read_to_string(&path) {
+            Ok(directives) => directives.trim().to_string(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string())
+            }
+            Err(e) => {
+                tracing::warn!(?path, error = %e, "could not read log filter file");
+                continue;
+            }
+        };
+
+        match EnvFilter::try_new(&directives) {
+            Ok(filter) => {
+                handle.reload(filter)?;
+                tracing::info!(%directives, "reloaded log filter");
+            }
+            Err(e) => {
+                tracing::warn!(%directives, error = %e, "invalid log filter, keeping the current one")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run `pd`'s ABCI, gRPC, and metrics servers until one of them fails.
+async fn start(
+    opts: StartOptions,
+    filter_handle: reload::Handle<EnvFilter, Registry>,
+) -> anyhow::Result<()> {
+    let StartOptions {
+        home,
+        host,
+        abci_port,
+        grpc_port,
+        metrics_port,
+        target_block_time_ms,
+        slow_commit_fraction,
+        telemetry_endpoint,
+        telemetry_interval_secs,
+        tendermint_rpc,
+        max_query_delay_ms,
+        otlp_endpoint: _,
+    } = opts;
+
+    tracing::info!(?host, ?abci_port, ?grpc_port, "starting pd");
+
+    tokio::task::Builder::new()
+        .name("log_filter_reload")
+        .spawn(reload_log_filter_on_sighup(
+            home.join(LOG_FILTER_FILE_NAME),
+            filter_handle,
+        ));
+
+    let mut rocks_path = home.clone();
+    rocks_path.push("rocksdb");
+
+    let storage = Storage::load(rocks_path)
+        .await
+        .context("Unable to initialize RocksDB storage")?;
+
+    let slow_commit_threshold =
+        Duration::from_millis(target_block_time_ms).mul_f64(slow_commit_fraction);
+    let block_times = BlockTimes::default();
+    let load = pd::NodeLoad::default();
+    tokio::task::Builder::new()
+        .name("catch_up_watcher")
+        .spawn(load.clone().watch_catch_up(tendermint_rpc.clone()));
+    let (consensus, height_rx) = pd::Consensus::new(
+        storage.clone(),
+        slow_commit_threshold,
+        block_times.clone(),
+        load.clone(),
+    )
+    .await?;
+    if let Some(endpoint) = telemetry_endpoint {
+        let reporter = Reporter::new(
+            &home,
+            endpoint,
+            Duration::from_secs(telemetry_interval_secs),
+            tendermint_rpc,
+            storage.clone(),
+            height_rx.clone(),
+            block_times,
+        )?;
+        tokio::task::Builder::new()
+            .name("telemetry_reporter")
+            .spawn(reporter.run());
+    }
+    let (mempool, mempool_rx) = pd::Mempool::new(storage.clone(), height_rx.clone()).await?;
+    let resumption_key = pd::ResumptionKey::load_or_generate(&home)?;
+    let info = pd::Info::new(storage.clone(), height_rx, mempool_rx, resumption_key);
+    let snapshot = pd::Snapshot {};
+
+    let abci_server = tokio::task::Builder::new().name("abci_server").spawn(
+        tower_abci::Server::builder()
+            .consensus(consensus)
+            .snapshot(snapshot)
+            .mempool(mempool)
+            .info(info.clone())
+            .finish()
+            .unwrap()
+            .listen(format!("{}:{}", host, abci_port)),
+    );
+
+    let grpc_server = tokio::task::Builder::new().name("grpc_server").spawn(
+        Server::builder()
+            .trace_fn(|req| match remote_addr(req) {
+                Some(remote_addr) => {
+                    tracing::error_span!("grpc", ?remote_addr)
+                }
+                None => tracing::error_span!("grpc"),
+            })
+            .layer(pd::ResponseMetadataLayer::new(storage.clone()))
+            .layer(pd::LoadShedLayer::new(
+                load,
+                Duration::from_millis(max_query_delay_ms),
+            ))
+            .add_service(ObliviousQueryServer::new(info.clone()))
+            .add_service(SpecificQueryServer::new(info.clone()))
+            .serve(
+                format!("{}:{}", host, grpc_port)
+                    .parse()
+                    .expect("this is a valid address"),
+            ),
+    );
+
+    // Configure a Prometheus recorder and exporter.
+    let (recorder, exporter) = PrometheusBuilder::new()
+        .with_http_listener(
+            format!("{}:{}", host, metrics_port)
+                .parse::<SocketAddr>()
+                .expect("this is a valid address"),
+        )
+        .build()
+        .expect("failed to build prometheus recorder");
+
+    Stack::new(recorder)
+        // Adding the `TracingContextLayer` will add labels from the tracing span to metrics.
+        // The only labels to be included are "chain_id" and "role".
+        .push(TracingContextLayer::only_allow(&["chain_id", "role"]))
+        .install()
+        .expect("global recorder already installed");
+
+    // This spawns the HTTP service that lets Prometheus pull metrics from `pd`
+    let handle = runtime::Handle::try_current().expect("unable to get runtime handle");
+    handle.spawn(exporter);
+
+    pd::register_metrics();
+
+    // TODO: better error reporting
+    // We error out if either service errors, rather than keep running
+    tokio::select! {
+        x = abci_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+        x = grpc_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+    };
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Instantiate tracing layers.
@@ -139,97 +450,117 @@ async fn main() -> anyhow::Result<()> {
     let filter_layer = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new("info"))
         .unwrap();
+    // It's wrapped in a reload layer, so the filter can be changed while `pd` is running.
+    let (filter_layer, filter_handle) = reload::Layer::new(filter_layer);
+
+    let opt = Opt::parse();
+
+    // The `OpenTelemetryLayer` exports spans to a collector, if `pd start` was given one.
+    let otlp_layer = match &opt.cmd {
+        RootCommand::Start(StartOptions {
+            otlp_endpoint: Some(endpoint),
+            ..
+        }) => Some(otlp_exporter(endpoint)?),
+        _ => None,
+    };
 
     tracing_subscriber::registry()
         .with(filter_layer)
         .with(fmt_layer)
         .with(metrics_layer)
         .with(console_layer)
+        .with(otlp_layer)
         .init();
 
-    let opt = Opt::parse();
-
     match opt.cmd {
-        RootCommand::Start {
+        RootCommand::Start(opts) => start(opts, filter_handle).await?,
+
+        RootCommand::Devnet {
             home,
-            host,
-            abci_port,
-            grpc_port,
-            metrics_port,
+            reset,
+            accounts,
+            epoch_duration,
+            tendermint,
         } => {
-            tracing::info!(?host, ?abci_port, ?grpc_port, "starting pd");
-
-            let mut rocks_path = home.clone();
-            rocks_path.push("rocksdb");
+            let home = home.unwrap_or_else(|| canonicalize_path("~/.penumbra/devnet"));
+            if reset && home.exists() {
+                std::fs::remove_dir_all(&home)?;
+            }
+            if !home.exists() {
+                pd::devnet::generate(&home, accounts, epoch_duration)?;
+            }
 
-            let storage = Storage::load(rocks_path)
-                .await
-                .context("Unable to initialize RocksDB storage")?;
+            for (i, account) in pd::devnet::accounts(&home)?.iter().enumerate() {
+                println!("test account {}: {}", i, account.address);
+                println!(
+                    "  pcli wallet import-from-phrase \"{}\"",
+                    account.seed_phrase
+                );
+            }
 
-            let (consensus, height_rx) = pd::Consensus::new(storage.clone()).await?;
-            let mempool = pd::Mempool::new(storage.clone(), height_rx.clone()).await?;
-            let info = pd::Info::new(storage.clone(), height_rx);
-            let snapshot = pd::Snapshot {};
-
-            let abci_server = tokio::task::Builder::new().name("abci_server").spawn(
-                tower_abci::Server::builder()
-                    .consensus(consensus)
-                    .snapshot(snapshot)
-                    .mempool(mempool)
-                    .info(info.clone())
-                    .finish()
-                    .unwrap()
-                    .listen(format!("{}:{}", host, abci_port)),
-            );
+            let node_dir = pd::devnet::node_dir(&home);
+            let mut tendermint = tokio::process::Command::new(&tendermint)
+                .arg("start")
+                .arg("--home")
+                .arg(node_dir.join("tendermint"))
+                .kill_on_drop(true)
+                .spawn()
+                .with_context(|| {
+                    format!(
+                        "Unable to run {:?}; is Tendermint v0.35 installed?",
+                        tendermint
+                    )
+                })?;
 
-            let grpc_server = tokio::task::Builder::new().name("grpc_server").spawn(
-                Server::builder()
-                    .trace_fn(|req| match remote_addr(req) {
-                        Some(remote_addr) => {
-                            tracing::error_span!("grpc", ?remote_addr)
-                        }
-                        None => tracing::error_span!("grpc"),
-                    })
-                    .add_service(ObliviousQueryServer::new(info.clone()))
-                    .add_service(SpecificQueryServer::new(info.clone()))
-                    .serve(
-                        format!("{}:{}", host, grpc_port)
-                            .parse()
-                            .expect("this is a valid address"),
-                    ),
-            );
+            // Start `pd` with its defaults, but keeping pace with the devnet's faster blocks.
+            let opts = StartOptions::parse_from([
+                OsString::from("start"),
+                "--home".into(),
+                node_dir.join("pd").into_os_string(),
+                "--target-block-time-ms".into(),
+                pd::devnet::TIMEOUT_COMMIT_MS.to_string().into(),
+            ]);
 
-            // Configure a Prometheus recorder and exporter.
-            let (recorder, exporter) = PrometheusBuilder::new()
-                .with_http_listener(
-                    format!("{}:{}", host, metrics_port)
-                        .parse::<SocketAddr>()
-                        .expect("this is a valid address"),
-                )
-                .build()
-                .expect("failed to build prometheus recorder");
-
-            Stack::new(recorder)
-                // Adding the `TracingContextLayer` will add labels from the tracing span to metrics.
-                // The only labels to be included are "chain_id" and "role".
-                .push(TracingContextLayer::only_allow(&["chain_id", "role"]))
-                .install()
-                .expect("global recorder already installed");
-
-            // This spawns the HTTP service that lets Prometheus pull metrics from `pd`
-            let handle = runtime::Handle::try_current().expect("unable to get runtime handle");
-            handle.spawn(exporter);
-
-            pd::register_metrics();
-
-            // TODO: better error reporting
-            // We error out if either service errors, rather than keep running
             tokio::select! {
-                x = abci_server => x?.map_err(|e| anyhow::anyhow!(e))?,
-                x = grpc_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+                x = start(opts, filter_handle) => x?,
+                status = tendermint.wait() => {
+                    return Err(anyhow::anyhow!("tendermint exited: {}", status?));
+                }
             };
         }
 
+        #[cfg(feature = "simulate")]
+        RootCommand::Simulate {
+            script,
+            home,
+            stop_at,
+        } => {
+            if home.exists() {
+                return Err(anyhow::anyhow!(
+                    "simulation directory {:?} already exists, refusing to overwrite it",
+                    home
+                ));
+            }
+            std::fs::create_dir_all(&home)?;
+            let script: pd::simulate::Script = serde_json::from_slice(&std::fs::read(&script)?)
+                .context("Unable to parse simulation script")?;
+            let storage = Storage::load(home.join("rocksdb"))
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+
+            // Print each block as it's committed, so that the history leading up to a
+            // divergence is kept.
+            pd::simulate::run(script, storage, stop_at, |committed| {
+                println!(
+                    "{}\t{}\t{}",
+                    committed.height,
+                    hex::encode(&committed.app_hash),
+                    committed.failed_txs
+                );
+            })
+            .await?;
+        }
+
         RootCommand::Testnet {
             tn_cmd: TestnetCommand::UnsafeResetAll {},
             testnet_dir,
@@ -313,6 +644,14 @@ async fn main() -> anyhow::Result<()> {
                     epoch_duration,
                     unbonding_epochs,
                     active_validator_limit,
+                    disallowed_actions,
+                    allowed_actions,
+                    min_validator_commission_bps,
+                    max_validator_commission_bps,
+                    max_validator_commission_change_bps,
+                    anchor_window_len,
+                    min_fee,
+                    max_transaction_bytes,
                     allocations_input_file,
                     validators_input_file,
                     chain_id,
@@ -320,11 +659,7 @@ async fn main() -> anyhow::Result<()> {
                 },
             testnet_dir,
         } => {
-            use std::{
-                fs::File,
-                str::FromStr,
-                time::{Duration, SystemTime, UNIX_EPOCH},
-            };
+            use std::{fs::File, str::FromStr};
 
             use rand::Rng;
 
@@ -343,16 +678,7 @@ async fn main() -> anyhow::Result<()> {
             use pd::testnet::*;
             use penumbra_chain::genesis;
             use penumbra_crypto::{Address, IdentityKey};
-            use tendermint::{node, public_key::Algorithm, Genesis, Time};
-
-            let genesis_time = Time::from_unix_timestamp(
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("time travels linearly in a forward direction")
-                    .as_secs() as i64,
-                0,
-            )
-            .expect("able to convert current time into Time");
+            use tendermint::node;
 
             // By default output directory will be in `~/.penumbra/testnet_data/`
             let output_dir = match testnet_dir {
@@ -497,48 +823,21 @@ async fn main() -> anyhow::Result<()> {
                     epoch_duration,
                     unbonding_epochs,
                     active_validator_limit,
+                    disallowed_actions,
+                    allowed_actions,
+                    min_validator_commission_bps,
+                    max_validator_commission_bps,
+                    max_validator_commission_change_bps,
+                    anchor_window_len,
+                    min_fee,
+                    max_transaction_bytes,
                     ..Default::default()
                 },
                 validators: validators.clone().into_iter().map(Into::into).collect(),
             };
 
             // Create the genesis data shared by all nodes
-            let validator_genesis = Genesis {
-                genesis_time,
-                chain_id: chain_id
-                    .parse::<tendermint::chain::Id>()
-                    .expect("able to create chain ID"),
-                initial_height: 0,
-                consensus_params: tendermint::consensus::Params {
-                    block: tendermint::block::Size {
-                        max_bytes: 22020096,
-                        max_gas: -1,
-                        // minimum time increment between consecutive blocks
-                        time_iota_ms: 500,
-                    },
-                    // TODO Should these correspond with values used within `pd` for penumbra epochs?
-                    evidence: tendermint::evidence::Params {
-                        max_age_num_blocks: 100000,
-                        // 1 day
-                        max_age_duration: tendermint::evidence::Duration(Duration::new(86400, 0)),
-                        max_bytes: 1048576,
-                    },
-                    validator: tendermint::consensus::params::ValidatorParams {
-                        pub_key_types: vec![Algorithm::Ed25519],
-                    },
-                    version: Some(tendermint::consensus::params::VersionParams { app_version: 0 }),
-                },
-                // always empty in genesis json
-                app_hash: vec![],
-                app_state,
-                // List of initial validators. Note this may be overridden entirely by
-                // the application, and may be left empty to make explicit that the
-                // application will initialize the validator set with ResponseInitChain.
-                // - https://docs.tendermint.com/v0.32/tendermint-core/using-tendermint.html
-                // For penumbra, we can leave this empty since the app_state also contains Validator
-                // configs.
-                validators: vec![],
-            };
+            let validator_genesis = tendermint_genesis(&chain_id, app_state);
 
             for (n, vk) in validator_keys.iter().enumerate() {
                 let node_name = format!("node{}", n);
@@ -1,7 +1,9 @@
+mod entry;
 mod message;
 mod service;
 mod worker;
 
-use message::Message;
+pub use entry::MempoolEntry;
+use message::{Message, Rejection};
 pub use service::Mempool;
 use worker::Worker;
@@ -0,0 +1,48 @@
+use std::time::Instant;
+
+use bytes::Bytes;
+use penumbra_proto::client::oblivious as pb;
+use penumbra_transaction::Transaction;
+use sha2::{Digest, Sha256};
+
+/// A transaction accepted into the mempool, as recorded for inspection.
+#[derive(Clone, Debug)]
+pub struct MempoolEntry {
+    /// The SHA-256 hash of the transaction, which is also its Tendermint hash.
+    pub tx_hash: [u8; 32],
+    /// The size of the encoded transaction, in bytes.
+    pub size: usize,
+    /// The fee paid by the transaction.
+    pub fee: u64,
+    /// The number of nullifiers the transaction spends.
+    pub nullifier_count: usize,
+    /// When the transaction was first accepted into the mempool.
+    pub first_seen: Instant,
+}
+
+impl MempoolEntry {
+    pub fn new(tx_bytes: &Bytes, tx: &Transaction, first_seen: Instant) -> Self {
+        let mut tx_hash = [0; 32];
+        tx_hash.copy_from_slice(Sha256::digest(tx_bytes).as_slice());
+
+        Self {
+            tx_hash,
+            size: tx_bytes.len(),
+            fee: tx.transaction_body.fee.0,
+            nullifier_count: tx.spent_nullifiers().len(),
+            first_seen,
+        }
+    }
+}
+
+impl From<&MempoolEntry> for pb::MempoolEntry {
+    fn from(entry: &MempoolEntry) -> Self {
+        pb::MempoolEntry {
+            tx_hash: entry.tx_hash.to_vec(),
+            size: entry.size as u64,
+            fee: entry.fee,
+            nullifier_count: entry.nullifier_count as u64,
+            seconds_in_pool: entry.first_seen.elapsed().as_secs(),
+        }
+    }
+}
@@ -1,11 +1,28 @@
-use anyhow::Result;
 use bytes::Bytes;
+use penumbra_chain::ErrorCode;
 use tokio::sync::oneshot;
 use tracing::Span;
 
 #[derive(Debug)]
 pub struct Message {
     pub tx_bytes: Bytes,
-    pub rsp_sender: oneshot::Sender<Result<()>>,
+    /// Sent the fee paid by the transaction if it's accepted.
+    pub rsp_sender: oneshot::Sender<Result<u64, Rejection>>,
     pub span: Span,
 }
+
+/// A transaction the mempool rejected, with the check it failed.
+#[derive(Debug)]
+pub struct Rejection {
+    pub check: ErrorCode,
+    pub error: anyhow::Error,
+}
+
+impl Rejection {
+    pub fn new(check: ErrorCode, error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            check,
+            error: error.into(),
+        }
+    }
+}
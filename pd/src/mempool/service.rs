@@ -5,6 +5,7 @@ use std::{
 };
 
 use futures::FutureExt;
+use penumbra_chain::{ErrorCode, CODESPACE};
 use penumbra_storage::Storage;
 use tendermint::{
     abci::{
@@ -18,8 +19,9 @@ use tokio_util::sync::PollSender;
 use tower_abci::BoxError;
 use tracing::{error_span, Instrument};
 
-use super::{Message, Worker};
+use super::{MempoolEntry, Message, Rejection, Worker};
 use crate::metrics;
+use crate::rejection::rejection_code;
 use crate::RequestExt;
 
 #[derive(Clone)]
@@ -28,19 +30,27 @@ pub struct Mempool {
 }
 
 impl Mempool {
+    /// Creates a new mempool service, returning it along with a channel that
+    /// tracks the transactions currently in the mempool.
     pub async fn new(
         storage: Storage,
         height_rx: watch::Receiver<block::Height>,
-    ) -> anyhow::Result<Self> {
+    ) -> anyhow::Result<(Self, watch::Receiver<Vec<MempoolEntry>>)> {
         let (queue_tx, queue_rx) = mpsc::channel(10);
+        let (entries_tx, entries_rx) = watch::channel(Vec::new());
 
-        tokio::task::Builder::new()
-            .name("mempool::Worker")
-            .spawn(Worker::new(storage, queue_rx, height_rx).await?.run());
+        tokio::task::Builder::new().name("mempool::Worker").spawn(
+            Worker::new(storage, queue_rx, height_rx, entries_tx)
+                .await?
+                .run(),
+        );
 
-        Ok(Self {
-            queue: PollSender::new(queue_tx),
-        })
+        Ok((
+            Self {
+                queue: PollSender::new(queue_tx),
+            },
+            entries_rx,
+        ))
     }
 }
 
@@ -70,6 +80,7 @@ impl tower::Service<MempoolRequest> for Mempool {
         let MempoolRequest::CheckTx(CheckTxReq {
             tx: tx_bytes, kind, ..
         }) = req;
+        let size = tx_bytes.len();
 
         self.queue
             .send_item(Message {
@@ -89,25 +100,34 @@ impl tower::Service<MempoolRequest> for Mempool {
                 .await
                 .map_err(|_| anyhow::anyhow!("mempool worker terminated or panicked"))?
             {
-                Ok(()) => {
+                Ok(fee) => {
                     tracing::info!("tx accepted");
                     metrics::increment_counter!(
                         metrics::MEMPOOL_CHECKTX_TOTAL,
                         "kind" => kind_str,
                         "code" => "0"
                     );
-                    Ok(MempoolResponse::CheckTx(CheckTxRsp::default()))
+                    Ok(MempoolResponse::CheckTx(CheckTxRsp {
+                        gas_wanted: gas(size),
+                        gas_used: gas(size),
+                        // Transactions paying higher fees are included first.
+                        priority: i64::try_from(fee).unwrap_or(i64::MAX),
+                        ..Default::default()
+                    }))
                 }
-                Err(e) => {
-                    tracing::info!(?e, "tx rejected");
+                Err(Rejection { check, error }) => {
+                    tracing::info!(?error, ?check, "tx rejected");
+                    let code = rejection_code(&error, check);
                     metrics::increment_counter!(
                         metrics::MEMPOOL_CHECKTX_TOTAL,
                         "kind" => kind_str,
-                        "code" => "1"
+                        "code" => code.to_string()
                     );
                     Ok(MempoolResponse::CheckTx(CheckTxRsp {
-                        code: 1,
-                        log: e.to_string(),
+                        code,
+                        log: rejection_log(code, &error),
+                        codespace: CODESPACE.to_string(),
+                        gas_wanted: gas(size),
                         ..Default::default()
                     }))
                 }
@@ -117,3 +137,24 @@ impl tower::Service<MempoolRequest> for Mempool {
         .boxed()
     }
 }
+
+/// The gas a transaction of `size` bytes uses.
+///
+/// Penumbra doesn't meter execution, so a transaction's size, which is what limits how many fit in
+/// a block, stands in for its gas.
+fn gas(size: usize) -> i64 {
+    i64::try_from(size).unwrap_or(i64::MAX)
+}
+
+/// The log of a transaction rejected with `code` for `error`, as a JSON object with the code, its
+/// codespace, the description of the code, and the error, so that clients can show the
+/// description and fall back to the error for codes they don't know.
+fn rejection_log(code: u32, error: &anyhow::Error) -> String {
+    serde_json::json!({
+        "code": code,
+        "codespace": CODESPACE,
+        "description": ErrorCode::from_code(code).map(ErrorCode::description),
+        "error": format!("{:#}", error),
+    })
+    .to_string()
+}
@@ -1,6 +1,9 @@
+use std::{collections::BTreeMap, time::Instant};
+
 use anyhow::Result;
 use bytes::Bytes;
 
+use penumbra_chain::ErrorCode;
 use penumbra_component::{Component, Context};
 use penumbra_proto::Protobuf;
 use penumbra_storage::Storage;
@@ -9,7 +12,7 @@ use tendermint::block;
 use tokio::sync::{mpsc, watch};
 use tracing::{instrument, Instrument};
 
-use super::Message;
+use super::{MempoolEntry, Message, Rejection};
 use crate::App;
 
 pub struct Worker {
@@ -17,14 +20,25 @@ pub struct Worker {
     storage: Storage,
     app: App,
     height_rx: watch::Receiver<block::Height>,
+    /// The transactions accepted since the last block, by hash.
+    entries: BTreeMap<[u8; 32], MempoolEntry>,
+    /// The transactions accepted before the last block, which are carried over
+    /// into `entries` (keeping their original arrival time) as they're
+    /// rechecked.
+    previous_entries: BTreeMap<[u8; 32], MempoolEntry>,
+    entries_tx: watch::Sender<Vec<MempoolEntry>>,
 }
 
 impl Worker {
-    #[instrument(skip(storage, queue, height_rx), name = "mempool::Worker::new")]
+    #[instrument(
+        skip(storage, queue, height_rx, entries_tx),
+        name = "mempool::Worker::new"
+    )]
     pub async fn new(
         storage: Storage,
         queue: mpsc::Receiver<Message>,
         height_rx: watch::Receiver<block::Height>,
+        entries_tx: watch::Sender<Vec<MempoolEntry>>,
     ) -> Result<Self> {
         let app = App::new(storage.clone()).await;
 
@@ -33,6 +47,9 @@ impl Worker {
             storage,
             app,
             height_rx,
+            entries: BTreeMap::new(),
+            previous_entries: BTreeMap::new(),
+            entries_tx,
         })
     }
 
@@ -41,12 +58,43 @@ impl Worker {
     /// perform the stateful checks in the worker, and have a frontend service
     /// that performs the stateless checks.  However, this probably isn't
     /// important to do until we know that it's a bottleneck.
-    async fn check_and_execute_tx(&mut self, ctx: Context, tx_bytes: Bytes) -> Result<()> {
-        let tx = Transaction::decode(tx_bytes.as_ref())?;
-        App::check_tx_stateless(ctx.clone(), &tx)?;
-        self.app.check_tx_stateful(ctx.clone(), &tx).await?;
+    ///
+    /// Returns the fee the transaction pays, or which check it failed.
+    async fn check_and_execute_tx(
+        &mut self,
+        ctx: Context,
+        tx_bytes: Bytes,
+    ) -> Result<u64, Rejection> {
+        let tx = Transaction::decode(tx_bytes.as_ref())
+            .map_err(|e| Rejection::new(ErrorCode::InvalidEncoding, e))?;
+        App::check_tx_stateless(ctx.clone(), &tx)
+            .map_err(|e| Rejection::new(ErrorCode::StatelessCheckFailed, e))?;
+        self.app
+            .check_tx_stateful(ctx.clone(), &tx)
+            .await
+            .map_err(|e| Rejection::new(ErrorCode::StatefulCheckFailed, e))?;
         self.app.execute_tx(ctx.clone(), &tx).await;
-        Ok(())
+        self.record_entry(&tx_bytes, &tx);
+        Ok(tx.transaction_body.fee.0)
+    }
+
+    /// Record an accepted transaction, so that it shows up in mempool snapshots.
+    fn record_entry(&mut self, tx_bytes: &Bytes, tx: &Transaction) {
+        let mut entry = MempoolEntry::new(tx_bytes, tx, Instant::now());
+        // If this is a recheck of a transaction from before the last block, it
+        // has been in the mempool since it was first seen.
+        if let Some(previous) = self.previous_entries.remove(&entry.tx_hash) {
+            entry.first_seen = previous.first_seen;
+        }
+        self.entries.insert(entry.tx_hash, entry);
+        self.publish_entries();
+    }
+
+    fn publish_entries(&self) {
+        // Ignore errors, which just mean that nobody is inspecting the mempool.
+        let _ = self
+            .entries_tx
+            .send(self.entries.values().cloned().collect());
     }
 
     pub async fn run(mut self) -> Result<()> {
@@ -61,6 +109,11 @@ impl Worker {
                         let height = self.height_rx.borrow().value();
                         tracing::info!(?height, "resetting ephemeral mempool state");
                         self.app = App::new(self.storage.clone()).await;
+                        // Transactions left in the mempool will be rechecked
+                        // against the new state; any that aren't were either
+                        // included in the block or evicted.
+                        self.previous_entries = std::mem::take(&mut self.entries);
+                        self.publish_entries();
                     } else {
                         tracing::info!("consensus worker shut down, shutting down mempool worker");
                         // The consensus worker shut down, we should too.
@@ -22,6 +22,7 @@ pub fn register_metrics() {
     penumbra_component::stake::register_metrics();
     penumbra_component::ibc::register_metrics();
     penumbra_component::shielded_pool::register_metrics();
+    penumbra_component::app::register_metrics();
 
     register_counter!(MEMPOOL_CHECKTX_TOTAL);
     describe_counter!(
@@ -30,6 +31,13 @@ pub fn register_metrics() {
         "The total number of checktx requests made to the mempool"
     );
 
+    register_histogram!(CONSENSUS_COMMIT_DURATION);
+    describe_histogram!(
+        CONSENSUS_COMMIT_DURATION,
+        Unit::Seconds,
+        "The total time spent handling Commit requests"
+    );
+
     register_gauge!(CLIENT_OBLIVIOUS_COMPACT_BLOCK_ACTIVE_CONNECTIONS);
     describe_gauge!(
         CLIENT_OBLIVIOUS_COMPACT_BLOCK_ACTIVE_CONNECTIONS,
@@ -43,12 +51,23 @@ pub fn register_metrics() {
         Unit::Count,
         "The total number of compact blocks served to clients"
     );
+
+    register_counter!(GRPC_REQUESTS_SHED_TOTAL);
+    describe_counter!(
+        GRPC_REQUESTS_SHED_TOTAL,
+        Unit::Count,
+        "The total number of low-priority gRPC requests rejected while the node was busy"
+    );
 }
 
 pub const MEMPOOL_CHECKTX_TOTAL: &str = "penumbra_pd_mempool_checktx_total";
 
+pub const CONSENSUS_COMMIT_DURATION: &str = "penumbra_pd_consensus_commit_duration_seconds";
+
 pub const CLIENT_OBLIVIOUS_COMPACT_BLOCK_ACTIVE_CONNECTIONS: &str =
     "penumbra_pd_oblivious_client_compact_active_connections";
 
 pub const CLIENT_OBLIVIOUS_COMPACT_BLOCK_SERVED_TOTAL: &str =
     "penumbra_pd_oblivious_client_compact_block_served_total";
+
+pub const GRPC_REQUESTS_SHED_TOTAL: &str = "penumbra_pd_grpc_requests_shed_total";
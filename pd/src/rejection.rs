@@ -0,0 +1,14 @@
+use penumbra_chain::ErrorCode;
+use penumbra_component::{shielded_pool::ExpiredAnchor, stake::BoundsViolation};
+
+/// The code a transaction which failed with `e` is rejected with: the code of its error, if it has
+/// its own, or else `fallback`, e.g., the code of the check it failed.
+pub(crate) fn rejection_code(e: &anyhow::Error, fallback: ErrorCode) -> u32 {
+    if let Some(violation) = e.downcast_ref::<BoundsViolation>() {
+        violation.code()
+    } else if e.is::<ExpiredAnchor>() {
+        ExpiredAnchor::CODE
+    } else {
+        fallback.code()
+    }
+}
@@ -0,0 +1,103 @@
+//! A layer stamping every gRPC response with the ID of the chain `pd` serves and its software
+//! version, so that clients can tell when they've been pointed at a node on the wrong network.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+};
+
+use futures::FutureExt;
+use http::HeaderValue;
+use penumbra_chain::View as _;
+use penumbra_proto::client::{CHAIN_ID_METADATA_KEY, PD_VERSION_METADATA_KEY};
+use penumbra_storage::Storage;
+use tower::{Layer, Service};
+
+const PD_VERSION: &str = env!("VERGEN_GIT_SEMVER");
+
+/// Adds the chain ID and `pd` version to the metadata of every response.
+///
+/// The chain ID is only known once the genesis state has been committed, so until then responses
+/// carry only the version.
+#[derive(Clone, Debug)]
+pub struct ResponseMetadataLayer {
+    storage: Storage,
+    chain_id: Arc<RwLock<Option<HeaderValue>>>,
+}
+
+impl ResponseMetadataLayer {
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            chain_id: Default::default(),
+        }
+    }
+
+    async fn chain_id(&self) -> Option<HeaderValue> {
+        if let Some(chain_id) = self.chain_id.read().expect("lock is not poisoned").clone() {
+            return Some(chain_id);
+        }
+
+        // The chain ID never changes once it's set, so it only needs to be read once.
+        let chain_id = self.storage.state().await.ok()?.get_chain_id().await.ok()?;
+        let chain_id = HeaderValue::from_str(&chain_id).ok()?;
+        *self.chain_id.write().expect("lock is not poisoned") = Some(chain_id.clone());
+        Some(chain_id)
+    }
+}
+
+impl<S> Layer<S> for ResponseMetadataLayer {
+    type Service = ResponseMetadata<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseMetadata {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service produced by [`ResponseMetadataLayer`].
+#[derive(Clone, Debug)]
+pub struct ResponseMetadata<S> {
+    inner: S,
+    layer: ResponseMetadataLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for ResponseMetadata<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        let rsp = self.inner.call(req);
+        let layer = self.layer.clone();
+
+        async move {
+            let mut rsp = rsp.await?;
+            let chain_id = layer.chain_id().await;
+
+            let headers = rsp.headers_mut();
+            headers.insert(
+                PD_VERSION_METADATA_KEY,
+                HeaderValue::from_static(PD_VERSION),
+            );
+            if let Some(chain_id) = chain_id {
+                headers.insert(CHAIN_ID_METADATA_KEY, chain_id);
+            }
+
+            Ok(rsp)
+        }
+        .boxed()
+    }
+}
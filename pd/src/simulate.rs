@@ -0,0 +1,237 @@
+//! Deterministic replay of a scripted sequence of blocks, for reproducing consensus failures.
+//!
+//! Rather than being driven by Tendermint, the [`App`] is driven directly from a [`Script`]
+//! listing the genesis state and the transactions in each block. Everything Tendermint would
+//! normally supply is synthesized deterministically:
+//!
+//! - block times come from a virtual clock, starting at the script's genesis time and advancing by
+//!   a fixed interval per block;
+//! - block hashes and proposer addresses are drawn from an RNG seeded by the script;
+//! - the last commit info records a vote from every validator in the current Tendermint validator
+//!   set (as tracked from the app's validator updates), except those the script marks absent.
+//!
+//! Replaying the same script therefore produces bit-identical state, so the app hash reported at
+//! each height can be compared against a testnet's, or across commits when bisecting.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use penumbra_chain::genesis;
+use penumbra_component::{Component, Context};
+use penumbra_storage::Storage;
+use rand_chacha::ChaChaRng;
+use rand_core::{RngCore, SeedableRng};
+use serde::Deserialize;
+use serde_with::{hex::Hex, serde_as};
+use sha2::{Digest, Sha256};
+use tendermint::{
+    abci::{
+        self,
+        types::{LastCommitInfo, Validator, ValidatorUpdate, VoteInfo},
+    },
+    account, block, chain, AppHash, Hash, Time,
+};
+
+use crate::{consensus, App};
+
+/// A scripted sequence of blocks to replay.
+#[derive(Debug, Deserialize)]
+pub struct Script {
+    /// The chain ID to put in block headers.
+    pub chain_id: String,
+    /// The seed for the RNG used to synthesize block hashes and proposer addresses.
+    #[serde(default)]
+    pub seed: u64,
+    /// The genesis time, in seconds since the Unix epoch.
+    pub genesis_time: i64,
+    /// The number of seconds the virtual clock advances per block.
+    #[serde(default = "default_block_interval")]
+    pub block_interval: u64,
+    /// The genesis state of the app.
+    pub app_state: genesis::AppState,
+    /// The blocks to replay, starting at height 1.
+    pub blocks: Vec<ScriptedBlock>,
+}
+
+fn default_block_interval() -> u64 {
+    5
+}
+
+/// A block in a [`Script`].
+#[serde_as]
+#[derive(Debug, Default, Deserialize)]
+pub struct ScriptedBlock {
+    /// The hex-encoded transactions in the block.
+    #[serde_as(as = "Vec<Hex>")]
+    #[serde(default)]
+    pub txs: Vec<Vec<u8>>,
+    /// The hex-encoded Tendermint addresses of validators which did not sign the previous block.
+    #[serde_as(as = "Vec<Hex>")]
+    #[serde(default)]
+    pub absent_validators: Vec<[u8; 20]>,
+    /// If set, the hex-encoded app hash expected after committing this block; the replay stops at
+    /// the first block whose app hash differs.
+    #[serde_as(as = "Option<Hex>")]
+    #[serde(default)]
+    pub app_hash: Option<[u8; 32]>,
+}
+
+/// The app hash after committing a block.
+#[derive(Debug, Clone)]
+pub struct Committed {
+    pub height: u64,
+    pub app_hash: [u8; 32],
+    /// The number of transactions in the block which failed to execute.
+    pub failed_txs: usize,
+}
+
+/// Replay the script against fresh `storage`, up to `stop_at` (or the end of the script), passing
+/// the app hash after genesis and after each block to `on_commit` as soon as it's committed.
+///
+/// Since each block is reported before its app hash is checked against the script, the blocks
+/// leading up to a divergence are reported even though the replay then fails.
+pub async fn run(
+    script: Script,
+    storage: Storage,
+    stop_at: Option<u64>,
+    mut on_commit: impl FnMut(&Committed),
+) -> Result<()> {
+    if storage.latest_version().await?.is_some() {
+        return Err(anyhow!("simulation storage must be empty"));
+    }
+
+    let mut rng = ChaChaRng::seed_from_u64(script.seed);
+    let chain_id = chain::Id::try_from(script.chain_id.clone())?;
+    let mut app = App::new(storage.clone()).await;
+
+    // Genesis.
+    app.init_chain(&script.app_state).await;
+    let mut validators = BTreeMap::new();
+    apply_validator_updates(&mut validators, app.tm_validator_updates().await?);
+    let (jmt_root, _) = app.commit(storage.clone()).await?;
+    let mut last_app_hash = jmt_root.0;
+    on_commit(&Committed {
+        height: 0,
+        app_hash: last_app_hash,
+        failed_txs: 0,
+    });
+    tracing::info!(app_hash = %hex::encode(&last_app_hash), "committed genesis");
+
+    for (index, scripted) in script.blocks.iter().enumerate() {
+        let height = index as u64 + 1;
+        if stop_at.map_or(false, |stop_at| height > stop_at) {
+            break;
+        }
+
+        let time = Time::from_unix_timestamp(
+            script.genesis_time + (height * script.block_interval) as i64,
+            0,
+        )?;
+
+        let mut block_hash = [0u8; 32];
+        rng.fill_bytes(&mut block_hash);
+        let mut proposer_address = [0u8; 20];
+        rng.fill_bytes(&mut proposer_address);
+
+        let votes = validators
+            .iter()
+            .map(|(address, power)| VoteInfo {
+                validator: Validator {
+                    address: *address,
+                    power: *power,
+                },
+                signed_last_block: !scripted.absent_validators.contains(address),
+            })
+            .collect();
+
+        let begin_block = abci::request::BeginBlock {
+            hash: Hash::Sha256(block_hash),
+            header: block::Header {
+                version: block::header::Version { block: 11, app: 1 },
+                chain_id: chain_id.clone(),
+                height: block::Height::try_from(height)?,
+                time,
+                last_block_id: None,
+                last_commit_hash: None,
+                data_hash: None,
+                validators_hash: Hash::None,
+                next_validators_hash: Hash::None,
+                consensus_hash: Hash::None,
+                app_hash: AppHash::try_from(last_app_hash.to_vec())?,
+                last_results_hash: None,
+                evidence_hash: None,
+                proposer_address: account::Id::new(proposer_address),
+            },
+            last_commit_info: LastCommitInfo {
+                round: Default::default(),
+                votes,
+            },
+            byzantine_validators: Vec::new(),
+        };
+        app.begin_block(Context::new(), &begin_block).await;
+
+        let mut failed_txs = 0;
+        for (tx_index, tx_bytes) in scripted.txs.iter().enumerate() {
+            if let Err(e) = deliver_tx(&mut app, tx_bytes).await {
+                tracing::info!(?height, ?tx_index, ?e, "deliver_tx failed");
+                failed_txs += 1;
+            }
+        }
+
+        app.end_block(
+            Context::new(),
+            &abci::request::EndBlock {
+                height: height as i64,
+            },
+        )
+        .await;
+        apply_validator_updates(&mut validators, app.tm_validator_updates().await?);
+
+        let (jmt_root, _) = app.commit(storage.clone()).await?;
+        last_app_hash = jmt_root.0;
+        tracing::info!(?height, app_hash = %hex::encode(&last_app_hash), "committed block");
+        on_commit(&Committed {
+            height,
+            app_hash: last_app_hash,
+            failed_txs,
+        });
+
+        if let Some(expected) = scripted.app_hash {
+            if expected != last_app_hash {
+                return Err(anyhow!(
+                    "app hash diverged at height {}: expected {}, got {}",
+                    height,
+                    hex::encode(&expected),
+                    hex::encode(&last_app_hash)
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check and execute a transaction, as the consensus worker does in `DeliverTx`.
+async fn deliver_tx(app: &mut App, tx_bytes: &[u8]) -> Result<()> {
+    let ctx = Context::new();
+    let transaction = consensus::check_tx_stateless(ctx.clone(), tx_bytes)?;
+    consensus::deliver_tx(app, ctx, &transaction).await
+}
+
+/// Track the Tendermint validator set the way Tendermint would, keyed by address (the truncated
+/// SHA256 hash of the consensus key).
+fn apply_validator_updates(
+    validators: &mut BTreeMap<[u8; 20], tendermint::vote::Power>,
+    updates: Vec<ValidatorUpdate>,
+) {
+    for update in updates {
+        let address: [u8; 20] = Sha256::digest(&update.pub_key.to_bytes()).as_slice()[0..20]
+            .try_into()
+            .expect("hash is at least 20 bytes");
+        if update.power.value() == 0 {
+            validators.remove(&address);
+        } else {
+            validators.insert(address, update.power);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Opt-in reporting of anonymized node statistics, to help network operators follow the health of
+//! a testnet.
+//!
+//! Telemetry is off unless `pd start` is given a `--telemetry-endpoint`. When it is on, a
+//! [`Reporter`] periodically `POST`s a JSON [`Report`] to the endpoint. Reports identify the node
+//! only by a random ID, generated on first use and stored in the `pd` home directory, which is
+//! unrelated to any of the node's keys: they carry no addresses, keys, or monikers.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use penumbra_chain::View as _;
+use penumbra_storage::Storage;
+use rand::Rng;
+use serde::Serialize;
+use tendermint::block;
+use tokio::sync::watch;
+
+/// The name of the file in the `pd` home directory storing the node's telemetry ID.
+pub const NODE_ID_FILE_NAME: &str = "telemetry_id";
+
+/// The statistics reported about a node.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// The node's random telemetry ID.
+    pub node_id: String,
+    /// The version of `pd` the node is running.
+    pub version: String,
+    /// The chain ID, if the chain has been initialized.
+    pub chain_id: Option<String>,
+    /// The height of the latest committed block.
+    pub height: u64,
+    /// The number of peers Tendermint is connected to, if its RPC is reachable.
+    pub peers: Option<u64>,
+    /// The time spent processing blocks since the previous report.
+    pub blocks: BlockSummary,
+}
+
+/// A summary of the time spent processing blocks, from `BeginBlock` until the end of `Commit`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct BlockSummary {
+    /// The number of blocks processed.
+    pub count: u64,
+    /// The mean processing time, in milliseconds.
+    pub mean_ms: f64,
+    /// The longest processing time, in milliseconds.
+    pub max_ms: f64,
+}
+
+/// Block processing times, recorded by the consensus worker and taken by the [`Reporter`].
+///
+/// Only a running total is kept, so recording is cheap whether or not telemetry is enabled.
+#[derive(Debug, Clone, Default)]
+pub struct BlockTimes(Arc<Mutex<Totals>>);
+
+#[derive(Debug, Default)]
+struct Totals {
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl BlockTimes {
+    /// Record the time spent processing one block.
+    pub fn record(&self, elapsed: Duration) {
+        let mut totals = self.0.lock().expect("lock is not poisoned");
+        totals.count += 1;
+        totals.total += elapsed;
+        totals.max = totals.max.max(elapsed);
+    }
+
+    /// Summarize the times recorded since the last call, and reset them.
+    pub fn take(&self) -> BlockSummary {
+        let totals = std::mem::take(&mut *self.0.lock().expect("lock is not poisoned"));
+        let mean = if totals.count == 0 {
+            Duration::ZERO
+        } else {
+            totals.total / totals.count as u32
+        };
+
+        BlockSummary {
+            count: totals.count,
+            mean_ms: mean.as_secs_f64() * 1000.0,
+            max_ms: totals.max.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Periodically publishes a [`Report`] to a telemetry endpoint.
+pub struct Reporter {
+    client: reqwest::Client,
+    endpoint: String,
+    interval: Duration,
+    tendermint_rpc: String,
+    node_id: String,
+    storage: Storage,
+    height_rx: watch::Receiver<block::Height>,
+    block_times: BlockTimes,
+}
+
+impl Reporter {
+    /// Create a reporter publishing to `endpoint` every `interval`, with the node ID stored in
+    /// `home`, and reading the peer count from the Tendermint RPC at `tendermint_rpc`.
+    pub fn new(
+        home: &Path,
+        endpoint: String,
+        interval: Duration,
+        tendermint_rpc: String,
+        storage: Storage,
+        height_rx: watch::Receiver<block::Height>,
+        block_times: BlockTimes,
+    ) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            endpoint,
+            interval,
+            tendermint_rpc,
+            node_id: load_or_generate_node_id(home)?,
+            storage,
+            height_rx,
+            block_times,
+        })
+    }
+
+    /// Publish reports until the process exits, logging (but otherwise ignoring) failures, so
+    /// that an unreachable endpoint never affects the node.
+    pub async fn run(self) -> Result<()> {
+        tracing::info!(endpoint = %self.endpoint, node_id = %self.node_id, "reporting telemetry");
+        let mut interval = tokio::time::interval(self.interval);
+        // The first tick completes immediately, and there's nothing to report yet.
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            let report = self.report().await;
+            tracing::debug!(?report, "publishing telemetry");
+            if let Err(e) = self.publish(&report).await {
+                tracing::warn!(?e, endpoint = %self.endpoint, "could not publish telemetry");
+            }
+        }
+    }
+
+    async fn report(&self) -> Report {
+        let chain_id = match self.storage.state().await {
+            Ok(state) => state.get_chain_id().await.ok(),
+            Err(_) => None,
+        };
+
+        Report {
+            node_id: self.node_id.clone(),
+            version: env!("VERGEN_GIT_SEMVER").to_string(),
+            chain_id,
+            height: self.height_rx.borrow().value(),
+            peers: self.peers().await.ok(),
+            blocks: self.block_times.take(),
+        }
+    }
+
+    async fn peers(&self) -> Result<u64> {
+        let rsp: serde_json::Value = self
+            .client
+            .get(format!("{}/net_info", self.tendermint_rpc))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        // Tendermint encodes integers in RPC responses as strings.
+        rsp["result"]["n_peers"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("missing peer count in response"))?
+            .parse()
+            .map_err(Into::into)
+    }
+
+    async fn publish(&self, report: &Report) -> Result<()> {
+        self.client
+            .post(&self.endpoint)
+            .json(report)
+            .timeout(self.interval)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Read the node's telemetry ID from `home`, generating and saving a new one if there is none.
+fn load_or_generate_node_id(home: &Path) -> Result<String> {
+    let path = home.join(NODE_ID_FILE_NAME);
+    if path.exists() {
+        let node_id = std::fs::read_to_string(&path)
+            .with_context(|| format!("could not read telemetry ID from {}", path.display()))?;
+        return Ok(node_id.trim().to_string());
+    }
+
+    let node_id = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+    std::fs::write(&path, &node_id)
+        .with_context(|| format!("could not write telemetry ID to {}", path.display()))?;
+    Ok(node_id)
+}
@@ -0,0 +1,353 @@
+//! Fixtures for integration tests which drive the [`App`] directly, rather than through
+//! Tendermint.
+//!
+//! Setting up the state a test needs, by replaying genesis and every block after it, can take
+//! much longer than the test itself. Instead, a [`StateFixture`] can be built up once and
+//! [saved](StateFixture::save) as a compressed snapshot, which each test then
+//! [loads](StateFixture::load) before driving synthetic blocks with
+//! [`execute_block`](StateFixture::execute_block).
+//!
+//! Synthetic blocks are [`BLOCK_INTERVAL_SECS`] apart, and carry a vote from every validator in
+//! the Tendermint validator set, as tracked from the app's validator updates.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use penumbra_chain::{genesis, View as _};
+use penumbra_component::{Component, Context};
+use penumbra_proto::Protobuf;
+use penumbra_storage::Storage;
+use penumbra_transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+use tendermint::{
+    abci::{
+        self,
+        types::{LastCommitInfo, Validator, ValidatorUpdate, VoteInfo},
+    },
+    account, block, vote, AppHash, Hash, Time,
+};
+
+use crate::{
+    consensus::{check_tx_stateless, deliver_tx},
+    App,
+};
+
+/// The number of seconds between synthetic blocks.
+pub const BLOCK_INTERVAL_SECS: i64 = 5;
+
+/// The contents of a snapshot file, which is gzip-compressed `bincode`.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    /// The Tendermint validator set, as addresses and voting power.
+    validators: Vec<([u8; 20], u64)>,
+    /// The files of a RocksDB checkpoint of the storage, by name.
+    files: Vec<(String, Vec<u8>)>,
+}
+
+/// An [`App`] over storage in a temporary directory, which is deleted when the fixture is
+/// dropped.
+pub struct StateFixture {
+    app: App,
+    storage: Storage,
+    /// The Tendermint validator set, keyed by address (the truncated SHA256 hash of the consensus
+    /// key).
+    validators: BTreeMap<[u8; 20], vote::Power>,
+    _dir: TempDir,
+}
+
+/// The result of executing a synthetic block.
+#[derive(Debug)]
+pub struct ExecutedBlock {
+    pub height: u64,
+    pub app_hash: [u8; 32],
+    /// The result of each of the block's transactions, in order.
+    pub tx_results: Vec<Result<()>>,
+}
+
+impl StateFixture {
+    /// Initialize fresh state from `app_state`, and commit it as the genesis block.
+    pub async fn from_genesis(app_state: &genesis::AppState) -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let storage = Storage::load(dir.path().join("state")).await?;
+        let mut app = App::new(storage.clone()).await;
+
+        app.init_chain(app_state).await;
+        let mut validators = BTreeMap::new();
+        apply_validator_updates(&mut validators, app.tm_validator_updates().await?);
+        app.commit(storage.clone()).await?;
+
+        Ok(Self {
+            app,
+            storage,
+            validators,
+            _dir: dir,
+        })
+    }
+
+    /// Load the state saved in the snapshot at `path` by [`StateFixture::save`].
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let dir = tempfile::tempdir()?;
+        let state_path = dir.path().join("state");
+
+        let validators = {
+            let state_path = state_path.clone();
+            tokio::task::spawn_blocking(move || -> Result<_> {
+                let file = File::open(&path)
+                    .with_context(|| format!("could not open snapshot {}", path.display()))?;
+                let snapshot: Snapshot =
+                    bincode::deserialize_from(GzDecoder::new(BufReader::new(file)))
+                        .with_context(|| format!("could not decode snapshot {}", path.display()))?;
+
+                std::fs::create_dir(&state_path)?;
+                for (name, bytes) in snapshot.files {
+                    // Checkpoints are flat, so anything but a plain file name is corrupt.
+                    if Path::new(&name).file_name() != Some(name.as_ref()) {
+                        return Err(anyhow!("invalid file name {:?} in snapshot", name));
+                    }
+                    std::fs::write(state_path.join(name), bytes)?;
+                }
+
+                Ok(snapshot.validators)
+            })
+            .await??
+        };
+
+        let storage = Storage::load(state_path).await?;
+        let app = App::new(storage.clone()).await;
+
+        Ok(Self {
+            app,
+            storage,
+            validators: validators
+                .into_iter()
+                .map(|(address, power)| Ok((address, vote::Power::try_from(power)?)))
+                .collect::<Result<_>>()?,
+            _dir: dir,
+        })
+    }
+
+    /// Save the committed state as a compressed snapshot at `path`, for [`StateFixture::load`].
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref().to_owned();
+        let checkpoint_dir = tempfile::tempdir()?;
+        let checkpoint_path = checkpoint_dir.path().join("state");
+        self.storage.checkpoint(checkpoint_path.clone()).await?;
+
+        let validators = self
+            .validators
+            .iter()
+            .map(|(address, power)| (*address, power.value()))
+            .collect();
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut files = Vec::new();
+            for entry in std::fs::read_dir(&checkpoint_path)? {
+                let entry = entry?;
+                let name = entry
+                    .file_name()
+                    .into_string()
+                    .map_err(|name| anyhow!("invalid file name {:?} in checkpoint", name))?;
+                files.push((name, std::fs::read(entry.path())?));
+            }
+            drop(checkpoint_dir);
+
+            let file = File::create(&path)
+                .with_context(|| format!("could not create snapshot {}", path.display()))?;
+            let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+            bincode::serialize_into(&mut encoder, &Snapshot { validators, files })?;
+            encoder.finish()?.flush()?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// The app, for driving it directly or inspecting its state.
+    pub fn app(&mut self) -> &mut App {
+        &mut self.app
+    }
+
+    /// The storage backing the app.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// The height of the latest committed block.
+    pub async fn height(&self) -> Result<u64> {
+        self.storage
+            .latest_version()
+            .await?
+            .ok_or_else(|| anyhow!("state has not been initialized"))
+    }
+
+    /// Execute and commit a block containing `txs`.
+    ///
+    /// Transactions which fail their checks aren't executed, as in `DeliverTx`, and their errors
+    /// are returned in the block's `tx_results`.
+    pub async fn execute_block(&mut self, txs: &[Transaction]) -> Result<ExecutedBlock> {
+        let last_height = self.height().await?;
+        let height = last_height + 1;
+        let state = self.storage.state().await?;
+        let chain_id = state.get_chain_id().await?;
+        // The genesis block has no timestamp, so the first synthetic block is at the Unix epoch.
+        let time = match state.get_block_timestamp().await {
+            Ok(last) => Time::from_unix_timestamp(last.unix_timestamp() + BLOCK_INTERVAL_SECS, 0)?,
+            Err(_) => Time::from_unix_timestamp(0, 0)?,
+        };
+        let last_app_hash = jmt::JellyfishMerkleTree::new(&self.storage)
+            .get_root_hash(last_height)
+            .await?;
+
+        let votes = self
+            .validators
+            .iter()
+            .map(|(address, power)| VoteInfo {
+                validator: Validator {
+                    address: *address,
+                    power: *power,
+                },
+                signed_last_block: true,
+            })
+            .collect();
+
+        let begin_block = abci::request::BeginBlock {
+            hash: Hash::Sha256(Sha256::digest(&height.to_be_bytes()).into()),
+            header: block::Header {
+                version: block::header::Version { block: 11, app: 1 },
+                chain_id: chain_id.try_into()?,
+                height: block::Height::try_from(height)?,
+                time,
+                last_block_id: None,
+                last_commit_hash: None,
+                data_hash: None,
+                validators_hash: Hash::None,
+                next_validators_hash: Hash::None,
+                consensus_hash: Hash::None,
+                app_hash: AppHash::try_from(last_app_hash.0.to_vec())?,
+                last_results_hash: None,
+                evidence_hash: None,
+                proposer_address: account::Id::new([0; 20]),
+            },
+            last_commit_info: LastCommitInfo {
+                round: Default::default(),
+                votes,
+            },
+            byzantine_validators: Vec::new(),
+        };
+        self.app.begin_block(Context::new(), &begin_block).await;
+
+        let mut tx_results = Vec::with_capacity(txs.len());
+        for transaction in txs {
+            tx_results.push(self.deliver_tx(transaction).await);
+        }
+
+        self.app
+            .end_block(
+                Context::new(),
+                &abci::request::EndBlock {
+                    height: height as i64,
+                },
+            )
+            .await;
+        apply_validator_updates(&mut self.validators, self.app.tm_validator_updates().await?);
+
+        let (app_hash, _) = self.app.commit(self.storage.clone()).await?;
+
+        Ok(ExecutedBlock {
+            height,
+            app_hash: app_hash.0,
+            tx_results,
+        })
+    }
+
+    /// Check and execute a transaction, as the consensus worker does in `DeliverTx`, starting
+    /// from its encoding so that decoding is checked too.
+    async fn deliver_tx(&mut self, transaction: &Transaction) -> Result<()> {
+        let ctx = Context::new();
+        let transaction = check_tx_stateless(ctx.clone(), transaction.encode_to_vec().as_slice())?;
+        deliver_tx(&mut self.app, ctx, &transaction).await
+    }
+}
+
+/// Track the Tendermint validator set the way Tendermint would.
+fn apply_validator_updates(
+    validators: &mut BTreeMap<[u8; 20], vote::Power>,
+    updates: Vec<ValidatorUpdate>,
+) {
+    for update in updates {
+        let address: [u8; 20] = Sha256::digest(&update.pub_key.to_bytes()).as_slice()[0..20]
+            .try_into()
+            .expect("hash is at least 20 bytes");
+        if update.power.value() == 0 {
+            validators.remove(&address);
+        } else {
+            validators.insert(address, update.power);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_chain::params::ChainParams;
+
+    use super::*;
+
+    fn app_state() -> genesis::AppState {
+        genesis::AppState {
+            chain_params: ChainParams {
+                chain_id: "penumbra-testing".to_string(),
+                epoch_duration: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn blocks_advance_the_state() {
+        let mut fixture = StateFixture::from_genesis(&app_state()).await.unwrap();
+        assert_eq!(fixture.height().await.unwrap(), 0);
+
+        let first = fixture.execute_block(&[]).await.unwrap();
+        let second = fixture.execute_block(&[]).await.unwrap();
+        assert_eq!((first.height, second.height), (1, 2));
+        assert_eq!(fixture.height().await.unwrap(), 2);
+        assert!(first.tx_results.is_empty());
+        assert_ne!(first.app_hash, second.app_hash);
+
+        // Blocks are synthesized deterministically, so the same genesis gives the same state.
+        let mut replayed = StateFixture::from_genesis(&app_state()).await.unwrap();
+        replayed.execute_block(&[]).await.unwrap();
+        assert_eq!(
+            replayed.execute_block(&[]).await.unwrap().app_hash,
+            second.app_hash
+        );
+    }
+
+    #[tokio::test]
+    async fn loaded_snapshots_continue_where_they_were_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fixture.bin.gz");
+
+        let mut fixture = StateFixture::from_genesis(&app_state()).await.unwrap();
+        fixture.execute_block(&[]).await.unwrap();
+        fixture.save(&path).await.unwrap();
+
+        let mut loaded = StateFixture::load(&path).await.unwrap();
+        assert_eq!(loaded.height().await.unwrap(), 1);
+        assert_eq!(loaded.validators, fixture.validators);
+        assert_eq!(
+            loaded.execute_block(&[]).await.unwrap().app_hash,
+            fixture.execute_block(&[]).await.unwrap().app_hash
+        );
+    }
+}
@@ -5,6 +5,7 @@ use std::{
     io::{Read, Write},
     path::PathBuf,
     str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{Context, Result};
@@ -19,7 +20,7 @@ use rand::Rng;
 use rand_core::OsRng;
 use regex::{Captures, Regex};
 use serde::{de, Deserialize};
-use tendermint::{node::Id, Genesis, PrivateKey};
+use tendermint::{node::Id, public_key::Algorithm, Genesis, PrivateKey, Time};
 use tendermint_config::{NodeKey, PrivValidatorKey};
 
 /// Methods and types used for generating testnet configurations.
@@ -96,6 +97,56 @@ pub fn generate_tm_config(node_name: &str, persistent_peers: &[(Id, String)]) ->
         node_name, peers_string,
     )
 }
+
+/// The Tendermint genesis for a new chain called `chain_id`, starting now with `app_state`.
+pub fn tendermint_genesis(chain_id: &str, app_state: AppState) -> Genesis<AppState> {
+    let genesis_time = Time::from_unix_timestamp(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time travels linearly in a forward direction")
+            .as_secs() as i64,
+        0,
+    )
+    .expect("able to convert current time into Time");
+
+    Genesis {
+        genesis_time,
+        chain_id: chain_id
+            .parse::<tendermint::chain::Id>()
+            .expect("able to create chain ID"),
+        initial_height: 0,
+        consensus_params: tendermint::consensus::Params {
+            block: tendermint::block::Size {
+                max_bytes: 22020096,
+                max_gas: -1,
+                // minimum time increment between consecutive blocks
+                time_iota_ms: 500,
+            },
+            // TODO Should these correspond with values used within `pd` for penumbra epochs?
+            evidence: tendermint::evidence::Params {
+                max_age_num_blocks: 100000,
+                // 1 day
+                max_age_duration: tendermint::evidence::Duration(Duration::new(86400, 0)),
+                max_bytes: 1048576,
+            },
+            validator: tendermint::consensus::params::ValidatorParams {
+                pub_key_types: vec![Algorithm::Ed25519],
+            },
+            version: Some(tendermint::consensus::params::VersionParams { app_version: 0 }),
+        },
+        // always empty in genesis json
+        app_hash: vec![],
+        app_state,
+        // List of initial validators. Note this may be overridden entirely by
+        // the application, and may be left empty to make explicit that the
+        // application will initialize the validator set with ResponseInitChain.
+        // - https://docs.tendermint.com/v0.32/tendermint-core/using-tendermint.html
+        // For penumbra, we can leave this empty since the app_state also contains Validator
+        // configs.
+        validators: vec![],
+    }
+}
+
 pub struct ValidatorKeys {
     // Penumbra spending key and viewing key for this node.
     pub validator_id_sk: SigningKey<SpendAuth>,
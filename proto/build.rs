@@ -76,6 +76,8 @@ fn main() -> Result<()> {
 static SERIALIZE: &str = r#"#[derive(::serde::Deserialize, ::serde::Serialize)]"#;
 /// Serializes newtype structs as if the inner field were serialized on its own.
 static SERDE_TRANSPARENT: &str = r#"#[serde(transparent)]"#;
+/// Fills in a missing field with its default value when deserializing.
+static SERDE_DEFAULT: &str = r#"#[serde(default)]"#;
 
 static AS_HEX: &str = r#"#[serde(with = "crate::serializers::hexstr")]"#;
 static AS_HEX_FOR_BYTES: &str = r#"#[serde(with = "crate::serializers::hexstr_bytes")]"#;
@@ -87,6 +89,14 @@ static AS_BECH32_ASSET_ID: &str = r#"#[serde(with = "crate::serializers::bech32s
 static AS_BECH32_SPEND_KEY: &str = r#"#[serde(with = "crate::serializers::bech32str::spend_key")]"#;
 static AS_BECH32_FULL_VIEWING_KEY: &str =
     r#"#[serde(with = "crate::serializers::bech32str::full_viewing_key")]"#;
+static AS_BECH32_SPEND_AUTH_VERIFICATION_KEY: &str =
+    r#"#[serde(with = "crate::serializers::bech32str::spend_auth_verification_key")]"#;
+static AS_BECH32_SPEND_AUTH_SIGNING_KEY: &str =
+    r#"#[serde(with = "crate::serializers::bech32str::spend_auth_signing_key")]"#;
+static AS_BECH32_BINDING_VERIFICATION_KEY: &str =
+    r#"#[serde(with = "crate::serializers::bech32str::binding_verification_key")]"#;
+static AS_BECH32_BINDING_SIGNING_KEY: &str =
+    r#"#[serde(with = "crate::serializers::bech32str::binding_signing_key")]"#;
 
 static TYPE_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.stake.Validator", SERIALIZE),
@@ -103,9 +113,12 @@ static TYPE_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.stake.Delegate", SERIALIZE),
     (".penumbra.stake.Undelegate", SERIALIZE),
     (".penumbra.stake.DelegationChanges", SERIALIZE),
+    (".penumbra.stake.ConsensusKeyRotation", SERIALIZE),
+    (".penumbra.stake.ConsensusKeyRotations", SERIALIZE),
     (".penumbra.stake.CommissionAmount", SERIALIZE),
     (".penumbra.stake.CommissionAmounts", SERIALIZE),
     (".penumbra.stake.Uptime", SERIALIZE),
+    (".penumbra.stake.ValidatorBlockStats", SERIALIZE),
     (".penumbra.crypto.IdentityKey", SERIALIZE),
     (".penumbra.crypto.IdentityKey", SERDE_TRANSPARENT),
     (".penumbra.crypto.Address", SERIALIZE),
@@ -135,6 +148,17 @@ static TYPE_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.crypto.Nullifier", SERIALIZE),
     (".penumbra.crypto.Nullifier", SERDE_TRANSPARENT),
     (".penumbra.crypto.AuthPath", SERIALIZE),
+    (".penumbra.crypto.SpendAuthVerificationKey", SERIALIZE),
+    (
+        ".penumbra.crypto.SpendAuthVerificationKey",
+        SERDE_TRANSPARENT,
+    ),
+    (".penumbra.crypto.SpendAuthSigningKey", SERIALIZE),
+    (".penumbra.crypto.SpendAuthSigningKey", SERDE_TRANSPARENT),
+    (".penumbra.crypto.BindingVerificationKey", SERIALIZE),
+    (".penumbra.crypto.BindingVerificationKey", SERDE_TRANSPARENT),
+    (".penumbra.crypto.BindingSigningKey", SERIALIZE),
+    (".penumbra.crypto.BindingSigningKey", SERDE_TRANSPARENT),
     (".penumbra.chain.ChainParams", SERIALIZE),
     (".penumbra.chain.CompactBlock", SERIALIZE),
     (".penumbra.chain.KnownAssets", SERIALIZE),
@@ -147,6 +171,8 @@ static TYPE_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.chain.QuarantinedPerValidator", SERIALIZE),
     (".penumbra.view.NoteRecord", SERIALIZE),
     (".penumbra.view.QuarantinedNoteRecord", SERIALIZE),
+    (".penumbra.view.TransactionInfo", SERIALIZE),
+    (".penumbra.view.SentOutput", SERIALIZE),
     (".penumbra.transaction.TransactionPlan", SERIALIZE),
     (".penumbra.transaction.Fee", SERIALIZE),
     (".penumbra.transaction.ActionPlan", SERIALIZE),
@@ -162,6 +188,10 @@ static FIELD_ATTRIBUTES: &[(&str, &str)] = &[
     // Using base64 for the validator's consensus key means that
     // the format is the same as the Tendermint json config files.
     (".penumbra.stake.Validator.consensus_key", AS_BASE64),
+    (
+        ".penumbra.stake.ConsensusKeyRotation.consensus_key",
+        AS_BASE64,
+    ),
     (".penumbra.stake.ValidatorDefinition.auth_sig", AS_HEX),
     (".penumbra.stake.Uptime.bitvec", AS_BASE64),
     (".penumbra.crypto.Address.inner", AS_BECH32_ADDRESS),
@@ -188,7 +218,46 @@ static FIELD_ATTRIBUTES: &[(&str, &str)] = &[
         AS_HEX_FOR_BYTES,
     ),
     (".penumbra.crypto.Nullifier.inner", AS_HEX),
+    (
+        ".penumbra.crypto.SpendAuthVerificationKey.inner",
+        AS_BECH32_SPEND_AUTH_VERIFICATION_KEY,
+    ),
+    (
+        ".penumbra.crypto.SpendAuthSigningKey.inner",
+        AS_BECH32_SPEND_AUTH_SIGNING_KEY,
+    ),
+    (
+        ".penumbra.crypto.BindingVerificationKey.inner",
+        AS_BECH32_BINDING_VERIFICATION_KEY,
+    ),
+    (
+        ".penumbra.crypto.BindingSigningKey.inner",
+        AS_BECH32_BINDING_SIGNING_KEY,
+    ),
     (".penumbra.chain.NoteSource.inner", AS_HEX),
+    (
+        ".penumbra.chain.CompactBlock.resumption_token",
+        AS_HEX_FOR_BYTES,
+    ),
+    // Resumption tokens were added after launch, so older compact blocks omit them.
+    (
+        ".penumbra.chain.CompactBlock.resumption_token",
+        SERDE_DEFAULT,
+    ),
+    (".penumbra.view.TransactionInfo.tx_hash", AS_HEX),
+    // Admission lists were added after launch, so older genesis files omit them.
+    (
+        ".penumbra.chain.ChainParams.disallowed_actions",
+        SERDE_DEFAULT,
+    ),
+    (".penumbra.chain.ChainParams.allowed_actions", SERDE_DEFAULT),
+    // The minimum commission was added after launch, so older genesis files omit it.
+    (
+        ".penumbra.chain.ChainParams.min_validator_commission_bps",
+        SERDE_DEFAULT,
+    ),
+    // Fee and size limits were added after launch, so older genesis files omit them.
+    (".penumbra.chain.ChainParams.min_fee", SERDE_DEFAULT),
     (
         ".penumbra.transaction.SpendPlan.randomizer",
         AS_HEX_FOR_BYTES,
@@ -50,6 +50,14 @@ pub mod chain {
 
 /// Client protocol structures.
 pub mod client {
+    /// The response metadata key under which `pd` reports the ID of the chain it serves.
+    pub const CHAIN_ID_METADATA_KEY: &str = "x-penumbra-chain-id";
+    /// The response metadata key under which `pd` reports its software version.
+    pub const PD_VERSION_METADATA_KEY: &str = "x-penumbra-pd-version";
+    /// The response metadata key under which `pd` suggests how many seconds to wait before
+    /// retrying a request it rejected because it was busy.
+    pub const RETRY_AFTER_METADATA_KEY: &str = "retry-after";
+
     pub mod oblivious {
         tonic::include_proto!("penumbra.client.oblivious");
     }
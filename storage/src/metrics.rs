@@ -21,6 +21,31 @@ pub fn register_metrics() {
         Unit::Bytes,
         "The size of the serialized TCT in bytes"
     );
+
+    register_histogram!(TCT_SERIALIZE_DURATION);
+    describe_histogram!(
+        TCT_SERIALIZE_DURATION,
+        Unit::Seconds,
+        "The time spent serializing the TCT when committing a block"
+    );
+
+    register_histogram!(TCT_WRITE_DURATION);
+    describe_histogram!(
+        TCT_WRITE_DURATION,
+        Unit::Seconds,
+        "The time spent writing the serialized TCT to RocksDB when committing a block"
+    );
+
+    register_histogram!(JMT_WRITE_NODE_BATCH_DURATION);
+    describe_histogram!(
+        JMT_WRITE_NODE_BATCH_DURATION,
+        Unit::Seconds,
+        "The time spent writing a batch of JMT nodes to RocksDB when committing a block"
+    );
 }
 
 pub const TCT_SIZE_BYTES: &str = "penumbra_storage_tct_size_bytes";
+pub const TCT_SERIALIZE_DURATION: &str = "penumbra_storage_tct_serialize_duration_seconds";
+pub const TCT_WRITE_DURATION: &str = "penumbra_storage_tct_write_duration_seconds";
+pub const JMT_WRITE_NODE_BATCH_DURATION: &str =
+    "penumbra_storage_jmt_write_node_batch_duration_seconds";
@@ -1,13 +1,13 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Instant};
 
-use ::metrics::gauge;
+use ::metrics::{gauge, histogram};
 use anyhow::Result;
 use futures::future::BoxFuture;
 use jmt::{
     storage::{Node, NodeBatch, NodeKey, TreeReader, TreeWriter},
     WriteOverlay,
 };
-use rocksdb::{Options, DB};
+use rocksdb::{IteratorMode, Options, DB};
 use tokio::sync::RwLock;
 use tracing::{instrument, Span};
 
@@ -30,7 +30,11 @@ impl Storage {
                     opts.create_if_missing(true);
                     opts.create_missing_column_families(true);
 
-                    Ok(Self(Arc::new(DB::open_cf(&opts, path, ["jmt", "nct"])?)))
+                    Ok(Self(Arc::new(DB::open_cf(
+                        &opts,
+                        path,
+                        ["jmt", "nct", "deliver_tx"],
+                    )?)))
                 })
             })
             .await
@@ -78,7 +82,9 @@ impl Storage {
         let db = self.0.clone();
 
         tracing::debug!("serializing TCT");
+        let start = Instant::now();
         let tct_data = bincode::serialize(tct)?;
+        histogram!(metrics::TCT_SERIALIZE_DURATION, start.elapsed());
         tracing::debug!(tct_bytes = tct_data.len(), "serialized TCT");
         gauge!(metrics::TCT_SIZE_BYTES, tct_data.len() as f64);
 
@@ -87,8 +93,10 @@ impl Storage {
             .name("put_nct")
             .spawn_blocking(move || {
                 span.in_scope(|| {
+                    let start = Instant::now();
                     let nct_cf = db.cf_handle("nct").expect("nct column family not found");
                     db.put_cf(nct_cf, "tct", &tct_data)?;
+                    histogram!(metrics::TCT_WRITE_DURATION, start.elapsed());
                     Ok::<_, anyhow::Error>(())
                 })
             })
@@ -112,6 +120,96 @@ impl Storage {
             })
             .await?
     }
+
+    /// Writes a consistent copy of the database to `path`, which must not exist yet, without
+    /// blocking writers for longer than it takes to flush the memtables.
+    ///
+    /// The copy can be opened with [`Storage::load`]. Where possible, its files are hard links
+    /// to the live database's, so `path` should be on the same filesystem.
+    pub async fn checkpoint(&self, path: PathBuf) -> Result<()> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::Builder::new()
+            .name("checkpoint")
+            .spawn_blocking(move || {
+                span.in_scope(|| {
+                    rocksdb::checkpoint::Checkpoint::new(&db)?.create_checkpoint(&path)?;
+                    Ok(())
+                })
+            })
+            .await?
+    }
+
+    /// Records the (encoded) result of the `index`th `DeliverTx` of the block being executed.
+    ///
+    /// These results are kept outside the JMT, so they don't affect the app hash, and are only
+    /// needed until the block is committed (see [`Self::clear_deliver_tx_results`]).
+    pub async fn put_deliver_tx_result(&self, index: u32, result: Vec<u8>) -> Result<()> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::Builder::new()
+            .name("put_deliver_tx_result")
+            .spawn_blocking(move || {
+                span.in_scope(|| {
+                    let cf = db
+                        .cf_handle("deliver_tx")
+                        .expect("deliver_tx column family not found");
+                    db.put_cf(cf, index.to_be_bytes(), &result)?;
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .await?
+    }
+
+    /// Returns the recorded `DeliverTx` results, by their index in the block.
+    pub async fn deliver_tx_results(&self) -> Result<BTreeMap<u32, Vec<u8>>> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::Builder::new()
+            .name("deliver_tx_results")
+            .spawn_blocking(move || {
+                span.in_scope(|| {
+                    let cf = db
+                        .cf_handle("deliver_tx")
+                        .expect("deliver_tx column family not found");
+                    let mut results = BTreeMap::new();
+                    for (key, value) in db.iterator_cf(cf, IteratorMode::Start) {
+                        let index = u32::from_be_bytes(
+                            key.as_ref()
+                                .try_into()
+                                .map_err(|_| anyhow::anyhow!("invalid deliver_tx key"))?,
+                        );
+                        results.insert(index, value.into_vec());
+                    }
+                    Ok(results)
+                })
+            })
+            .await?
+    }
+
+    /// Deletes all the recorded `DeliverTx` results, once their block has been committed.
+    pub async fn clear_deliver_tx_results(&self) -> Result<()> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::Builder::new()
+            .name("clear_deliver_tx_results")
+            .spawn_blocking(move || {
+                span.in_scope(|| {
+                    let cf = db
+                        .cf_handle("deliver_tx")
+                        .expect("deliver_tx column family not found");
+                    let keys = db
+                        .iterator_cf(cf, IteratorMode::Start)
+                        .map(|(key, _)| key)
+                        .collect::<Vec<_>>();
+                    for key in keys {
+                        db.delete_cf(cf, key)?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .await?
+    }
 }
 
 impl TreeWriter for Storage {
@@ -135,6 +233,7 @@ impl TreeWriter for Storage {
                 .name("Storage::write_node_batch")
                 .spawn_blocking(move || {
                     span.in_scope(|| {
+                        let start = Instant::now();
                         for (node_key, node) in node_batch.clone() {
                             let key_bytes = &node_key.encode()?;
                             let value_bytes = &node.encode()?;
@@ -143,6 +242,7 @@ impl TreeWriter for Storage {
                             let jmt_cf = db.cf_handle("jmt").expect("jmt column family not found");
                             db.put_cf(jmt_cf, key_bytes, &value_bytes)?;
                         }
+                        histogram!(metrics::JMT_WRITE_NODE_BATCH_DURATION, start.elapsed());
 
                         Ok(())
                     })
@@ -5,7 +5,7 @@ use std::collections::HashSet;
 
 use proptest::{arbitrary::*, prelude::*};
 
-use penumbra_tct::{proptest::CommitmentStrategy, validate, Commitment, Tree, Witness};
+use penumbra_tct::{proptest::CommitmentStrategy, validate, Commitment, Root, Tree, Witness};
 
 const MAX_USED_COMMITMENTS: usize = 3;
 const MAX_TIER_ACTIONS: usize = 10;
@@ -210,4 +210,70 @@ proptest! {
         }
         validate::forgotten(&tree).unwrap();
     }
+
+    #[test]
+    fn forget_batch_matches_forget(
+        (actions, forget) in
+            prop::collection::vec(any::<Commitment>(), 1..MAX_USED_COMMITMENTS)
+                .prop_flat_map(|commitments| {
+                    (
+                        prop::collection::vec(any_with::<Action>(commitments.clone()), 1..MAX_TIER_ACTIONS),
+                        prop::collection::vec(CommitmentStrategy::one_of(commitments), 0..MAX_USED_COMMITMENTS * 2),
+                    )
+                })
+    ) {
+        let mut tree = Tree::new();
+        for action in actions {
+            action.apply(&mut tree).unwrap();
+        }
+
+        // Forget the commitments one at a time in one copy of the tree, and all at once in another
+        let mut one_at_a_time = tree.clone();
+        let expected = forget.iter().filter(|commitment| one_at_a_time.forget(**commitment)).count();
+        let actual = tree.forget_batch(&forget);
+
+        assert_eq!(expected, actual);
+        assert_eq!(one_at_a_time.root(), tree.root());
+        assert_eq!(one_at_a_time.witnessed_count(), tree.witnessed_count());
+
+        validate::index(&tree).unwrap();
+        validate::all_proofs(&tree).unwrap();
+        validate::forgotten(&tree).unwrap();
+    }
+
+    #[test]
+    fn old_proofs_verify_against_root_history(
+        (before, after) in
+            prop::collection::vec(any::<Commitment>(), 1..MAX_USED_COMMITMENTS)
+                .prop_flat_map(|commitments| {
+                    (
+                        prop::collection::vec(any_with::<Action>(commitments.clone()), 1..MAX_TIER_ACTIONS),
+                        prop::collection::vec(any_with::<Action>(commitments), 1..MAX_TIER_ACTIONS),
+                    )
+                })
+    ) {
+        // Retain enough history that the root at the end of `before` is never discarded
+        let mut tree = Tree::with_root_history(MAX_TIER_ACTIONS + 1);
+        for action in before {
+            action.apply(&mut tree).unwrap();
+        }
+        tree.end_block().unwrap();
+        let anchor = tree.root();
+        assert!(tree.is_recent_root(anchor));
+
+        // Take proofs against the root at the end of the block...
+        let proofs: Vec<_> = tree
+            .commitments()
+            .map(|(commitment, _)| tree.witness(commitment).unwrap())
+            .collect();
+
+        // ... and check that they still verify against the history once the tree has moved on
+        for action in after {
+            action.apply(&mut tree).unwrap();
+        }
+        let history: Vec<Root> = tree.root_history().collect();
+        for proof in proofs {
+            assert_eq!(proof.verify_against_any(&history), Ok(anchor));
+        }
+    }
 }
@@ -3,9 +3,9 @@
 
 use thiserror::Error;
 
-use crate::builder;
 #[cfg(doc)]
 use crate::prelude::*;
+use crate::{builder, Position};
 
 #[doc(inline)]
 pub use crate::tree::RootDecodeError;
@@ -15,7 +15,7 @@ pub mod proof {
     #[doc(inline)]
     pub use crate::internal::{
         path::PathDecodeError,
-        proof::{ProofDecodeError as DecodeError, VerifyError},
+        proof::{ProofDecodeError as DecodeError, VerifyAnyError, VerifyError},
     };
 }
 
@@ -77,6 +77,11 @@ pub enum InsertError {
     /// The most recent block of the most recent epoch of the [`Tree`] was full.
     #[error("most recent block in most recent epoch of tree is full")]
     BlockFull,
+    /// The [`Commitment`] is already witnessed in the [`Tree`], at the given [`Position`].
+    ///
+    /// This is only returned by [`Tree::insert_unique`].
+    #[error("commitment is already witnessed at position {0:?}")]
+    Duplicate(Position),
 }
 
 /// An error occurred when trying to insert a block into the [`Tree`].
@@ -86,7 +86,7 @@ impl<Item: Focus> Tier<Item> {
             // The tier is full or is a single hash, so return the item without inserting it
             inner @ (Inner::Complete(_) | Inner::Hash(_)) => Err((item, Self { inner })),
             // The tier is a frontier, so try inserting into it
-            Inner::Frontier(frontier) => {
+            Inner::Frontier(mut frontier) => {
                 if frontier.is_full() {
                     // Don't even try inserting when we know it will fail: this means that there is *no
                     // implicit finalization* of the frontier, even when it is full
@@ -97,12 +97,16 @@ impl<Item: Focus> Tier<Item> {
                         },
                     ))
                 } else {
-                    // If it's not full, then insert the item into it (which we know will succeed)
-                    let inner =
-                        Inner::Frontier(Box::new(frontier.insert_owned(item).unwrap_or_else(
-                            |_| panic!("frontier is not full, so insert must succeed"),
-                        )));
-                    Ok(Self { inner })
+                    // If it's not full, then insert the item into it (which we know will succeed),
+                    // moving the result back into the same box: this is the hot path for every
+                    // commitment inserted, so we avoid freeing and re-allocating the whole nested
+                    // frontier each time
+                    *frontier = (*frontier)
+                        .insert_owned(item)
+                        .unwrap_or_else(|_| panic!("frontier is not full, so insert must succeed"));
+                    Ok(Self {
+                        inner: Inner::Frontier(frontier),
+                    })
                 }
             }
         }
@@ -335,4 +339,21 @@ mod test {
         }
         assert_eq!(tier.position(), None);
     }
+
+    #[test]
+    fn insert_reuses_frontier_allocation() {
+        fn frontier_ptr(tier: &Tier<Item>) -> *const Nested<Item> {
+            match &tier.inner {
+                Inner::Frontier(frontier) => &**frontier,
+                _ => panic!("tier should be a frontier"),
+            }
+        }
+
+        let mut tier: Tier<Item> = Tier::new(Hash::zero().into());
+        let ptr = frontier_ptr(&tier);
+        for _ in 0..1000 {
+            tier.insert(Hash::zero().into()).unwrap();
+            assert_eq!(frontier_ptr(&tier), ptr);
+        }
+    }
 }
@@ -99,6 +99,31 @@ impl<Item: Focus> Top<Item> {
         }
     }
 
+    /// Forget the commitments at all of the given indices, returning how many were forgotten.
+    ///
+    /// Unlike calling [`forget`](Self::forget) repeatedly, the forgotten version is calculated only
+    /// once for the whole batch, so every path touched by the batch is marked with the same
+    /// forgotten version, and the tree's forgotten version advances by one for the batch as a whole.
+    ///
+    /// Each index is still forgotten by its own traversal, in the order given.
+    #[inline]
+    pub fn forget_batch(&mut self, indices: impl IntoIterator<Item = u64>) -> usize
+    where
+        Item: Forget,
+        Item::Complete: ForgetOwned,
+    {
+        let forgotten = self.forgotten();
+
+        if let Some(ref mut inner) = self.inner {
+            indices
+                .into_iter()
+                .filter(|&index| inner.forget(forgotten, index))
+                .count()
+        } else {
+            0
+        }
+    }
+
     /// Count the number of times something has been forgotten from this tree.
     #[inline]
     pub fn forgotten(&self) -> Option<Forgotten> {
@@ -35,6 +35,30 @@ impl<Tree: Height> Proof<Tree> {
         }
     }
 
+    /// Verify a [`Proof`] of inclusion against any of several root [`struct@Hash`]es of a tree,
+    /// returning the first root against which it verifies.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VerifyAnyError`] if the proof is invalid for every one of the roots.
+    pub fn verify_any(
+        &self,
+        roots: impl IntoIterator<Item = Hash>,
+    ) -> Result<Hash, VerifyAnyError> {
+        // Compute the root implied by the proof once, rather than once per candidate root
+        let proof_root = Tree::Height::root(&self.auth_path, self.position, Hash::of(self.leaf));
+
+        let mut count = 0;
+        for root in roots {
+            if root == proof_root {
+                return Ok(root);
+            }
+            count += 1;
+        }
+
+        Err(VerifyAnyError { count })
+    }
+
     /// Get the index of the item this proof claims to witness.
     pub fn index(&self) -> u64 {
         self.position
@@ -61,6 +85,20 @@ impl VerifyError {
     }
 }
 
+/// A proof of inclusion did not verify against any of the provided root hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("invalid inclusion proof for all of {count} root hashes")]
+pub struct VerifyAnyError {
+    count: usize,
+}
+
+impl VerifyAnyError {
+    /// Get the number of root hashes against which the proof failed to verify.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
 /// When deserializing a proof, it was malformed.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Error)]
 #[error("could not decode proof")]
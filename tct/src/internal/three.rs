@@ -16,16 +16,12 @@ pub struct Three<T> {
 }
 
 impl<T> Three<T> {
-    /// Create a new `Three` with no elements, but the capacity for them pre-allocated.
+    /// Create a new `Three` with no elements.
     ///
-    /// This technically allocates space for 4 elements, so that when [`Self::push`] overfills, it
-    /// does not re-allocate.
+    /// This does not allocate until the first element is pushed: most frontier nodes are created
+    /// along a fresh path in which no node has any siblings yet, and many of them never will.
     pub fn new() -> Self {
-        Self {
-            // This has capacity 4 to prevent re-allocating memory when we push to a filled `Three`
-            // and thereby generate a [T; 4].
-            elems: Vec::with_capacity(4),
-        }
+        Self { elems: Vec::new() }
     }
 
     /// Push a new item into this [`Three`], or return exactly four items (including the pushed
@@ -35,6 +31,9 @@ impl<T> Three<T> {
     /// successful.
     #[inline]
     pub fn push(mut self, item: T) -> Result<Self, [T; 4]> {
+        // The first push reserves room for 4 elements, so that pushing to a filled `Three` (and
+        // thereby generating a [T; 4]) never re-allocates
+        self.elems.reserve_exact(4 - self.elems.len());
         // Push an element into the internal vec
         self.elems.push(item);
         // In debug mode, check that the size is never above 4
@@ -236,3 +235,23 @@ impl<'de, T: Deserialize<'de>> Deserialize<'de> for Three<T> {
         deserializer.deserialize_seq(ThreeVisitor(PhantomData))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn allocates_once_on_first_push() {
+        let three = Three::<u8>::new();
+        assert_eq!(three.elems.capacity(), 0);
+
+        let three = three.push(0).unwrap();
+        assert!(three.elems.capacity() >= 4);
+        let ptr = three.elems.as_ptr();
+
+        // Filling the `Three` and overfilling it into a [T; 4] never re-allocates
+        let three = three.push(1).unwrap().push(2).unwrap();
+        assert_eq!(three.elems.as_ptr(), ptr);
+        assert_eq!(three.push(3), Err([0, 1, 2, 3]));
+    }
+}
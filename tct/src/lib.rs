@@ -57,7 +57,7 @@ pub mod structure;
 pub mod validate;
 pub use commitment::Commitment;
 pub use proof::Proof;
-pub use tree::{Position, Root, Tree};
+pub use tree::{Capacity, Position, Root, Tree};
 
 #[cfg(any(doc, feature = "internal"))]
 pub mod internal;
@@ -83,7 +83,7 @@ pub mod builder {
 // A crate-internal prelude to make things easier to import
 mod prelude {
     pub(crate) use super::{
-        error::proof::VerifyError,
+        error::proof::{VerifyAnyError, VerifyError},
         index,
         internal::{
             complete::{self, Complete, ForgetOwned},
@@ -132,7 +132,7 @@ mod test {
 
     #[test]
     fn check_eternity_size() {
-        static_assertions::assert_eq_size!(Tree, [u8; 896]);
+        static_assertions::assert_eq_size!(Tree, [u8; 936]);
     }
 
     #[test]
@@ -58,6 +58,16 @@ impl Proof {
         self.0.verify(root.0)
     }
 
+    /// Verify a [`Proof`] of inclusion against any of several [`Root`]s of a [`Tree`], such as its
+    /// [`root_history`](Tree::root_history), returning the first root against which it verifies.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VerifyAnyError`] if the proof is invalid for every one of the [`Root`]s.
+    pub fn verify_against_any(&self, roots: &[Root]) -> Result<Root, VerifyAnyError> {
+        self.0.verify_any(roots.iter().map(|root| root.0)).map(Root)
+    }
+
     /// Get the commitment whose inclusion is witnessed by the proof.
     pub fn commitment(&self) -> Commitment {
         self.0.leaf
@@ -1,6 +1,7 @@
 //! A dynamic representation of nodes within the internal tree structure.
 
 use std::{
+    collections::BTreeMap,
     fmt::{Debug, Display},
     ops::Range,
 };
@@ -128,6 +129,20 @@ impl Display for Place {
     }
 }
 
+/// The metadata of the witnessed commitments in a [`Tree`], with its type erased so that [`Node`]
+/// needn't be generic over it.
+pub(crate) trait Metadata: Send + Sync {
+    /// The metadata of the commitment at this position, if any.
+    fn at(&self, position: Position) -> Option<&(dyn std::any::Any + Send + Sync)>;
+}
+
+impl<M: Send + Sync + 'static> Metadata for BTreeMap<index::within::Tree, M> {
+    fn at(&self, position: Position) -> Option<&(dyn std::any::Any + Send + Sync)> {
+        self.get(&u64::from(position).into())
+            .map(|metadata| metadata as &(dyn std::any::Any + Send + Sync))
+    }
+}
+
 /// An arbitrary node somewhere within a tree.
 #[derive(Copy, Clone)]
 pub struct Node<'a> {
@@ -135,6 +150,7 @@ pub struct Node<'a> {
     forgotten: Forgotten,
     parent: Option<&'a Node<'a>>,
     this: Insert<&'a (dyn Any + 'a)>,
+    metadata: Option<&'a dyn Metadata>,
 }
 
 impl Debug for Node<'_> {
@@ -182,6 +198,7 @@ impl<'a> Node<'a> {
             forgotten: this.forgotten(),
             parent: None,
             this: Insert::Keep(this),
+            metadata: None,
         }
     }
 
@@ -192,6 +209,15 @@ impl<'a> Node<'a> {
             forgotten,
             parent: None,
             this: child,
+            metadata: None,
+        }
+    }
+
+    /// Look up the metadata of the leaves of this node, and every node beneath it, in `metadata`.
+    pub(crate) fn with_metadata(self, metadata: &'a dyn Metadata) -> Self {
+        Self {
+            metadata: Some(metadata),
+            ..self
         }
     }
 
@@ -226,6 +252,22 @@ impl<'a> Node<'a> {
         (self as &dyn Any).forgotten()
     }
 
+    /// The metadata the commitment at this leaf was inserted with, using
+    /// [`Tree::insert_with_metadata`].
+    ///
+    /// Returns `None` if this is not the leaf of a witnessed commitment, if the commitment was
+    /// inserted without metadata, or if the metadata isn't of type `M`.
+    pub fn metadata<M: 'static>(&self) -> Option<&'a M> {
+        if let Kind::Leaf {
+            commitment: Some(_),
+        } = self.kind()
+        {
+            self.metadata?.at(self.position())?.downcast_ref()
+        } else {
+            None
+        }
+    }
+
     /// The index of this node from the left of the tree.
     ///
     /// For items at the base, this is the position of the item.
@@ -344,6 +386,7 @@ impl Any for Node<'_> {
                         this: child.this,
                         parent: Some(self),
                         offset: self.offset * 4 + nth as u64,
+                        metadata: self.metadata,
                     }
                 })
                 .collect()
@@ -1,4 +1,7 @@
-use std::fmt::{Debug, Display};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::{Debug, Display},
+};
 
 use decaf377::{FieldExt, Fq};
 use hash_hasher::HashedMap;
@@ -16,17 +19,44 @@ pub(crate) use epoch::block;
 
 /// A sparse merkle tree witnessing up to 65,536 epochs of up to 65,536 blocks of up to 65,536
 /// [`Commitment`]s.
+///
+/// Each witnessed commitment can carry application metadata of type `M` (by default, none), set
+/// when it is inserted with [`insert_with_metadata`](Tree::insert_with_metadata). The metadata
+/// lives exactly as long as the witness: it's dropped when the commitment is forgotten, so it
+/// can't drift from the tree the way a separate map from positions would. It isn't serialized
+/// with the tree, though: see [`metadata_entries`](Tree::metadata_entries).
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Tree {
+pub struct Tree<M = ()> {
     index: HashedMap<Commitment, index::within::Tree>,
     inner: frontier::Top<frontier::Tier<frontier::Tier<frontier::Item>>>,
+    /// The roots of the tree at the ends of recent blocks, most recent first.
+    ///
+    /// This isn't serialized, so that trees serialize the same as they did before root history
+    /// existed. It's saved and restored separately, with `root_history` and
+    /// `restore_root_history`, or else restarted from the current root by `set_root_history_len`
+    /// after loading.
+    #[serde(skip)]
+    root_history: VecDeque<Root>,
+    /// The maximum number of roots to retain in `root_history`.
+    #[serde(skip)]
+    root_history_len: usize,
+    /// The metadata of witnessed commitments, by position, which are always also in `index`.
+    ///
+    /// Like the root history, this isn't serialized, so that trees serialize the same as they did
+    /// before metadata existed. It's saved and restored separately, with `metadata_entries` and
+    /// `restore_metadata`.
+    #[serde(skip)]
+    metadata: BTreeMap<index::within::Tree, M>,
 }
 
-impl Default for Tree {
+impl<M> Default for Tree<M> {
     fn default() -> Self {
         Self {
             index: HashedMap::default(),
             inner: frontier::Top::new(frontier::TrackForgotten::Yes),
+            root_history: VecDeque::new(),
+            root_history_len: 0,
+            metadata: BTreeMap::new(),
         }
     }
 }
@@ -114,12 +144,111 @@ impl From<u64> for Position {
     }
 }
 
+/// The remaining capacity of a [`Tree`], as returned by [`Tree::remaining_capacity`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Capacity {
+    /// The number of [`Commitment`]s which can still be inserted into the current block.
+    ///
+    /// If the current block has been finalized, this is the capacity of the next block, unless
+    /// the current epoch is full.
+    pub commitments: u32,
+    /// The number of new blocks which can still be started in the current epoch.
+    pub blocks: u32,
+    /// The number of new epochs which can still be started in the tree.
+    pub epochs: u32,
+}
+
 impl Tree {
     /// Create a new empty [`Tree`] for storing all commitments to the end of time.
+    ///
+    /// To create a [`Tree`] whose commitments carry metadata, use [`Tree::default`].
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Create a new empty [`Tree`] which retains its [`Root`]s at the ends of its `len` most
+    /// recent blocks, so that [`Proof`]s can be verified against any of them.
+    ///
+    /// See [`root_history`](Tree::root_history) for details.
+    pub fn with_root_history(len: usize) -> Self {
+        let mut tree = Self::default();
+        tree.set_root_history_len(len);
+        tree
+    }
+}
+
+impl<M> Tree<M> {
+    /// Set the number of historical [`Root`]s retained by this [`Tree`], discarding the oldest
+    /// retained roots if there are now too many.
+    ///
+    /// A length of zero (the default) retains no history at all.
+    ///
+    /// The root history isn't serialized, so a deserialized [`Tree`] retains no history until this
+    /// is called, and then starts with only its current root, unless the history saved before it
+    /// was serialized is passed to [`restore_root_history`](Tree::restore_root_history).
+    #[instrument(skip(self))]
+    pub fn set_root_history_len(&mut self, len: usize) {
+        self.root_history_len = len;
+        self.root_history.truncate(len);
+        if self.root_history.is_empty() {
+            self.record_root();
+        }
+    }
+
+    /// Get the [`Root`]s of this [`Tree`] at the ends of its most recent blocks, most recent
+    /// first.
+    ///
+    /// A root is recorded every time a block or epoch is ended or inserted, up to the number of
+    /// roots set by [`set_root_history_len`](Tree::set_root_history_len). This is the window of
+    /// recent roots (anchors) against which a [`Proof`] can be checked using
+    /// [`Proof::verify_against_any`].
+    pub fn root_history(&self) -> impl Iterator<Item = Root> + '_ {
+        self.root_history.iter().copied()
+    }
+
+    /// Restore a root history saved from [`root_history`](Tree::root_history), after loading this
+    /// [`Tree`] and setting its [`set_root_history_len`](Tree::set_root_history_len).
+    ///
+    /// The saved roots are kept only if the most recent of them is this tree's current root, so
+    /// that a history saved from another state of the tree is never mistaken for this one's. At
+    /// most as many roots as the history length are kept, and the number kept is returned.
+    #[instrument(skip(self, roots))]
+    pub fn restore_root_history(&mut self, roots: impl IntoIterator<Item = Root>) -> usize {
+        let roots: VecDeque<Root> = roots.into_iter().take(self.root_history_len).collect();
+        let restored = if roots.front() == Some(&self.root()) {
+            self.root_history = roots;
+            self.root_history.len()
+        } else {
+            0
+        };
+        trace!(restored);
+        restored
+    }
+
+    /// Check whether the given [`Root`] is among the roots retained in the
+    /// [`root_history`](Tree::root_history) of this [`Tree`].
+    #[instrument(skip(self))]
+    pub fn is_recent_root(&self, root: Root) -> bool {
+        let is_recent = self.root_history.contains(&root);
+        trace!(?is_recent);
+        is_recent
+    }
+
+    /// Record the current root in the root history, if we are retaining any.
+    fn record_root(&mut self) {
+        if self.root_history_len == 0 {
+            return;
+        }
+
+        // Ending an epoch directly after ending a block doesn't change the root, so don't record
+        // the same root twice in a row
+        let root = self.root();
+        if self.root_history.front() != Some(&root) {
+            self.root_history.push_front(root);
+            self.root_history.truncate(self.root_history_len);
+        }
+    }
+
     /// Get the root hash of this [`Tree`].
     ///
     /// Internal hashing is performed lazily to prevent unnecessary intermediary hashes from being
@@ -146,6 +275,13 @@ impl Tree {
     /// - the [`Tree`] is full,
     /// - the current epoch is full, or
     /// - the current block is full.
+    ///
+    /// # Repeated commitments
+    ///
+    /// The tree indexes each witnessed commitment by a single position, so if a commitment which
+    /// is already witnessed is inserted again with [`Witness::Keep`], its previous position is
+    /// forgotten and only the new one can be witnessed. Use [`insert_unique`](Tree::insert_unique)
+    /// to reject such insertions instead.
     #[instrument(skip(self))]
     pub fn insert(
         &mut self,
@@ -211,6 +347,7 @@ impl Tree {
                 // practice because commitments should be unique
                 let forgotten = self.inner.forget(replaced);
                 debug_assert!(forgotten);
+                self.metadata.remove(&replaced);
             }
         }
 
@@ -219,6 +356,99 @@ impl Tree {
         Ok(position)
     }
 
+    /// Add a new [`Commitment`] to the most recent block of the most recent epoch of this [`Tree`],
+    /// witnessing it along with its `metadata`.
+    ///
+    /// This is like [`insert`](Tree::insert) with [`Witness::Keep`], except that the `metadata` is
+    /// kept until the commitment is forgotten, and can be retrieved with
+    /// [`metadata`](Tree::metadata), or from the leaf of the commitment when traversing the
+    /// [`structure`](Tree::structure) of the tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error [`insert`](Tree::insert) would, in which case the `metadata` is dropped.
+    #[instrument(skip(self, metadata))]
+    pub fn insert_with_metadata(
+        &mut self,
+        commitment: Commitment,
+        metadata: M,
+    ) -> Result<Position, InsertError> {
+        let position = self.insert(Witness::Keep, commitment)?;
+        self.metadata.insert(position.0, metadata);
+        Ok(position)
+    }
+
+    /// Get the metadata the given [`Commitment`] was inserted with, if it is currently witnessed
+    /// and was inserted with [`insert_with_metadata`](Tree::insert_with_metadata).
+    #[instrument(skip(self))]
+    pub fn metadata(&self, commitment: Commitment) -> Option<&M> {
+        let metadata = self
+            .index
+            .get(&commitment)
+            .and_then(|index| self.metadata.get(index));
+        trace!(found = metadata.is_some());
+        metadata
+    }
+
+    /// Get the metadata of every witnessed commitment inserted with
+    /// [`insert_with_metadata`](Tree::insert_with_metadata), by position.
+    ///
+    /// The metadata isn't serialized along with the [`Tree`], so that serialized trees keep their
+    /// format. To persist it, save these entries alongside the tree, and pass them to
+    /// [`restore_metadata`](Tree::restore_metadata) after loading it.
+    pub fn metadata_entries(&self) -> impl Iterator<Item = (Position, &M)> + '_ {
+        self.metadata
+            .iter()
+            .map(|(position, metadata)| (Position(*position), metadata))
+    }
+
+    /// Restore metadata saved from [`metadata_entries`](Tree::metadata_entries), after loading
+    /// this [`Tree`].
+    ///
+    /// Entries for positions which aren't witnessed in this tree are dropped, so that the metadata
+    /// never outlives a witness, and the number of entries restored is returned.
+    #[instrument(skip(self, entries))]
+    pub fn restore_metadata(&mut self, entries: impl IntoIterator<Item = (Position, M)>) -> usize {
+        let witnessed: BTreeSet<index::within::Tree> = self.index.values().copied().collect();
+        let mut restored = 0;
+        for (position, metadata) in entries {
+            if witnessed.contains(&position.0) {
+                self.metadata.insert(position.0, metadata);
+                restored += 1;
+            }
+        }
+        trace!(restored);
+        restored
+    }
+
+    /// Add a new [`Commitment`] to the most recent block of the most recent epoch of this [`Tree`],
+    /// unless it is already witnessed in the tree.
+    ///
+    /// This is like [`insert`](Tree::insert), except that inserting a commitment which is currently
+    /// witnessed fails, rather than forgetting its previous position. Commitments which were
+    /// inserted with [`Witness::Forget`], or have since been [`forget`](Tree::forget)ten, are no
+    /// longer known to the tree, so repeating them is not detected.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InsertError::Duplicate`] with the existing position of the commitment, without
+    /// modifying the tree, if the commitment is already witnessed, and otherwise returns any error
+    /// [`insert`](Tree::insert) would.
+    #[instrument(skip(self))]
+    pub fn insert_unique(
+        &mut self,
+        witness: Witness,
+        commitment: Commitment,
+    ) -> Result<Position, InsertError> {
+        if let Some(position) = self.position_of(commitment) {
+            let error = InsertError::Duplicate(position);
+            error!(%error);
+            return Err(error);
+        }
+
+        self.insert(witness, commitment)
+    }
+
     /// Get a [`Proof`] of inclusion for the commitment at this index in the tree.
     ///
     /// If the index is not witnessed in this tree, return `None`.
@@ -265,14 +495,77 @@ impl Tree {
             // Forget the index for this element in the tree
             let forgotten = self.inner.forget(within_epoch);
             debug_assert!(forgotten);
-            // Remove this entry from the index
+            // Remove this entry from the index, along with its metadata
             self.index.remove(&commitment);
+            self.metadata.remove(&within_epoch);
         }
 
         trace!(?forgotten);
         forgotten
     }
 
+    /// Forget about the witnesses for all the given [`Commitment`]s at once.
+    ///
+    /// Returns the number of commitments which were previously witnessed (and now are forgotten).
+    /// Commitments which were not witnessed, or which are repeated in the input, are ignored.
+    ///
+    /// Unlike calling [`forget`](Tree::forget) for each commitment, the
+    /// [`forgotten`](Tree::forgotten) version of the tree is advanced only once for the whole
+    /// batch, rather than once per commitment. Each commitment is still forgotten by its own
+    /// traversal of the tree, though, so this is no faster than forgetting them one at a time.
+    #[instrument(skip(self, commitments))]
+    pub fn forget_batch(&mut self, commitments: &[Commitment]) -> usize {
+        // Remove every witnessed commitment from the index, collecting its position (repeated
+        // commitments are only found in the index the first time they are removed)
+        let mut positions: Vec<u64> = commitments
+            .iter()
+            .filter_map(|commitment| self.index.remove(commitment))
+            .inspect(|position| {
+                self.metadata.remove(position);
+            })
+            .map(Into::into)
+            .collect();
+
+        // Forget in order of position, whatever order the commitments were given in
+        positions.sort_unstable();
+
+        // Every indexed position must have been witnessed in the tree
+        let expected = positions.len();
+        let count = self.inner.forget_batch(positions);
+        debug_assert_eq!(count, expected);
+
+        trace!(forgotten = ?count);
+        count
+    }
+
+    /// Get [`Proof`]s of inclusion for all the given [`Commitment`]s at once, in order of position.
+    ///
+    /// Commitments which are not witnessed, or which are repeated in the input, are skipped.
+    ///
+    /// Each proof is found by its own traversal of the tree, but the hashes which their paths share
+    /// are computed only once, and then reused from the tree's cache.
+    #[instrument(skip(self, commitments))]
+    pub fn witness_batch(&self, commitments: &[Commitment]) -> Vec<Proof> {
+        let mut witnessed: Vec<(u64, Commitment)> = commitments
+            .iter()
+            .filter_map(|commitment| Some(((*self.index.get(commitment)?).into(), *commitment)))
+            .collect();
+
+        witnessed.sort_unstable_by_key(|&(position, _)| position);
+        witnessed.dedup_by_key(|&mut (position, _)| position);
+
+        let proofs: Vec<Proof> = witnessed
+            .into_iter()
+            .map(|(_, commitment)| {
+                self.witness(commitment)
+                    .expect("commitment must be witnessed because it is indexed")
+            })
+            .collect();
+
+        trace!(witnessed = ?proofs.len());
+        proofs
+    }
+
     /// Get the position in this [`Tree`] of the given [`Commitment`], if it is currently witnessed.
     #[instrument(skip(self))]
     pub fn position_of(&self, commitment: Commitment) -> Option<Position> {
@@ -281,6 +574,21 @@ impl Tree {
         position
     }
 
+    /// Get every position in this [`Tree`] at which the given [`Commitment`] is currently
+    /// witnessed, in order.
+    ///
+    /// A commitment can be inserted more than once, but the tree only witnesses its most recent
+    /// [`Witness::Keep`] insertion (see [`insert`](Tree::insert)), so this returns at most one
+    /// position: the one [`position_of`](Tree::position_of) returns. Positions at which the
+    /// commitment was inserted with [`Witness::Forget`], or which were replaced by a later
+    /// insertion, are not known to the tree and so are not returned.
+    #[instrument(skip(self))]
+    pub fn positions_of(&self, commitment: Commitment) -> Vec<Position> {
+        let positions: Vec<Position> = self.position_of(commitment).into_iter().collect();
+        trace!(?positions);
+        positions
+    }
+
     /// Add a new block all at once to the most recently inserted epoch of this [`Tree`], returning
     /// the block root of the finalized block.
     ///
@@ -317,6 +625,7 @@ impl Tree {
             error!(%error);
             error
         })?;
+        self.record_root();
         trace!(?block_root);
         Ok(block_root)
     }
@@ -420,6 +729,7 @@ impl Tree {
                 // commitments should be unique
                 let forgotten = self.inner.forget(replaced);
                 debug_assert!(forgotten);
+                self.metadata.remove(&replaced);
             }
         }
 
@@ -455,10 +765,25 @@ impl Tree {
                 })?;
         };
 
+        self.record_root();
         trace!(finalized_block_root = ?finalized_root);
         Ok(finalized_root)
     }
 
+    /// [End the current block](Tree::end_block), and get fresh [`Proof`]s for the given
+    /// [`Commitment`]s against the tree's new root, as by [`witness_batch`](Tree::witness_batch).
+    ///
+    /// A client holding proofs for its own commitments can use this to keep them up to date as each
+    /// block is finalized, rather than witnessing each commitment again when it's needed.
+    #[instrument(skip(self, commitments))]
+    pub fn end_block_and_witness(
+        &mut self,
+        commitments: &[Commitment],
+    ) -> Result<(block::Root, Vec<Proof>), InsertBlockError> {
+        let block_root = self.end_block()?;
+        Ok((block_root, self.witness_batch(commitments)))
+    }
+
     /// Get the root hash of the most recent block in the most recent epoch of this [`Tree`].
     #[instrument(skip(self))]
     pub fn current_block_root(&self) -> block::Root {
@@ -515,6 +840,7 @@ impl Tree {
             error!(%error);
             error
         })?;
+        self.record_root();
         trace!(?epoch_root);
         Ok(epoch_root)
     }
@@ -572,6 +898,7 @@ impl Tree {
                 // commitments should be unique
                 let forgotten = self.inner.forget(replaced);
                 debug_assert!(forgotten);
+                self.metadata.remove(&replaced);
             }
         }
 
@@ -603,10 +930,22 @@ impl Tree {
                 })?;
         };
 
+        self.record_root();
         trace!(finalized_epoch_root = ?finalized_root);
         Ok(finalized_root)
     }
 
+    /// [End the current epoch](Tree::end_epoch), and get fresh [`Proof`]s for the given
+    /// [`Commitment`]s against the tree's new root, as by [`witness_batch`](Tree::witness_batch).
+    #[instrument(skip(self, commitments))]
+    pub fn end_epoch_and_witness(
+        &mut self,
+        commitments: &[Commitment],
+    ) -> Result<(epoch::Root, Vec<Proof>), InsertEpochError> {
+        let epoch_root = self.end_epoch()?;
+        Ok((epoch_root, self.witness_batch(commitments)))
+    }
+
     /// Get the root hash of the most recent epoch in this [`Tree`].
     #[instrument(skip(self))]
     pub fn current_epoch_root(&self) -> epoch::Root {
@@ -627,6 +966,101 @@ impl Tree {
         root
     }
 
+    /// Check whether the most recent block in the most recent epoch of this [`Tree`] is full.
+    ///
+    /// If it is, inserting a [`Commitment`] will fail with [`InsertError::BlockFull`] until the
+    /// block is ended with [`end_block`](Tree::end_block). If the most recent block has already
+    /// been finalized, the next insertion starts a new block, so this returns `false`.
+    #[instrument(skip(self))]
+    pub fn current_block_is_full(&self) -> bool {
+        let is_full = self
+            .inner
+            .focus()
+            .and_then(|epoch| epoch.focus())
+            .map_or(false, |block| !block.is_finalized() && block.is_full());
+        trace!(?is_full);
+        is_full
+    }
+
+    /// Check whether the most recent epoch of this [`Tree`] is full.
+    ///
+    /// If it is, inserting a [`Commitment`] or a block will fail until the epoch is ended with
+    /// [`end_epoch`](Tree::end_epoch). If the most recent epoch has already been finalized, the
+    /// next insertion starts a new epoch, so this returns `false`.
+    #[instrument(skip(self))]
+    pub fn current_epoch_is_full(&self) -> bool {
+        let is_full = self
+            .inner
+            .focus()
+            .map_or(false, |epoch| !epoch.is_finalized() && epoch.is_full());
+        trace!(?is_full);
+        is_full
+    }
+
+    /// Check whether `commitments` more [`Commitment`]s can be inserted into the current block,
+    /// returning the error that the first insertion which wouldn't fit would fail with if not.
+    ///
+    /// This lets a caller turn away a batch of insertions up front, rather than failing part way
+    /// through inserting it.
+    #[instrument(skip(self))]
+    pub fn check_capacity(&self, commitments: u32) -> Result<(), InsertError> {
+        let result = if commitments == 0 {
+            Ok(())
+        } else if self.position().is_none() {
+            Err(InsertError::Full)
+        } else if self.current_epoch_is_full() {
+            Err(InsertError::EpochFull)
+        } else if self.remaining_capacity().commitments < commitments {
+            Err(InsertError::BlockFull)
+        } else {
+            Ok(())
+        };
+        trace!(?result);
+        result
+    }
+
+    /// The number of [`Commitment`]s, blocks, and epochs which can still be inserted into this
+    /// [`Tree`], before the current block, the current epoch, or the whole tree is full.
+    #[instrument(skip(self))]
+    pub fn remaining_capacity(&self) -> Capacity {
+        const TIER_CAPACITY: u32 = 1 << 16;
+
+        let capacity = match self.position() {
+            // Nothing more can be inserted into a full tree
+            None => Capacity::default(),
+            Some(position) => {
+                let (epoch, block, commitment) = (
+                    u32::from(position.epoch()),
+                    u32::from(position.block()),
+                    u32::from(position.commitment()),
+                );
+                let block_is_full = self.current_block_is_full();
+                let epoch_is_full = self.current_epoch_is_full();
+
+                // The position of the next commitment skips past a full block or epoch, even
+                // though it hasn't been finalized, so we can't use it to count what remains in a
+                // full block or epoch. Otherwise, the current block or epoch has started if the
+                // position is past its beginning, since unfinalized blocks and epochs are never
+                // empty.
+                Capacity {
+                    commitments: if block_is_full || epoch_is_full {
+                        0
+                    } else {
+                        TIER_CAPACITY - commitment
+                    },
+                    blocks: if epoch_is_full {
+                        0
+                    } else {
+                        TIER_CAPACITY - block - u32::from(commitment > 0)
+                    },
+                    epochs: TIER_CAPACITY - epoch - u32::from(block > 0 || commitment > 0),
+                }
+            }
+        };
+        trace!(?capacity);
+        capacity
+    }
+
     /// The position in this [`Tree`] at which the next [`Commitment`] would be inserted.
     ///
     /// If the [`Tree`] is full, returns `None`.
@@ -688,9 +1122,342 @@ impl Tree {
 
     /// Get a dynamic representation of the internal structure of the tree, which can be traversed
     /// and inspected arbitrarily.
-    pub fn structure(&self) -> structure::Node {
+    ///
+    /// The metadata of each witnessed commitment is available from its leaf, with
+    /// [`Node::metadata`](structure::Node::metadata).
+    pub fn structure(&self) -> structure::Node
+    where
+        M: Send + Sync + 'static,
+    {
         let _structure_span = trace_span!("structure");
         // TODO: use the structure span for instrumenting methods of the structure, as it is traversed
-        Node::root(&self.inner)
+        Node::root(&self.inner).with_metadata(&self.metadata)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// A sequence of insertions drawn from a small pool of commitments, so that repeats are common.
+    fn insertions() -> impl Strategy<Value = Vec<(Witness, Commitment)>> {
+        prop::collection::vec(any::<Commitment>(), 1..4).prop_flat_map(|pool| {
+            prop::collection::vec((any::<Witness>(), any_with::<Commitment>(pool)), 0..32)
+        })
+    }
+
+    #[test]
+    fn remaining_capacity_tracks_blocks_and_epochs() {
+        const TIER_CAPACITY: u32 = 1 << 16;
+        let capacity = |commitments, blocks, epochs| Capacity {
+            commitments,
+            blocks,
+            epochs,
+        };
+
+        let mut tree = Tree::new();
+        assert_eq!(
+            tree.remaining_capacity(),
+            capacity(TIER_CAPACITY, TIER_CAPACITY, TIER_CAPACITY)
+        );
+
+        tree.insert(Witness::Forget, Commitment(0u64.into()))
+            .unwrap();
+        tree.insert(Witness::Forget, Commitment(1u64.into()))
+            .unwrap();
+        assert_eq!(
+            tree.remaining_capacity(),
+            capacity(TIER_CAPACITY - 2, TIER_CAPACITY - 1, TIER_CAPACITY - 1)
+        );
+
+        tree.end_block().unwrap();
+        assert_eq!(
+            tree.remaining_capacity(),
+            capacity(TIER_CAPACITY, TIER_CAPACITY - 1, TIER_CAPACITY - 1)
+        );
+
+        tree.end_epoch().unwrap();
+        assert_eq!(
+            tree.remaining_capacity(),
+            capacity(TIER_CAPACITY, TIER_CAPACITY, TIER_CAPACITY - 1)
+        );
+
+        assert!(!tree.current_block_is_full());
+        assert!(!tree.current_epoch_is_full());
+    }
+
+    #[test]
+    fn check_capacity_reports_which_tier_is_full() {
+        const TIER_CAPACITY: u32 = 1 << 16;
+
+        let mut tree = Tree::new();
+        tree.insert(Witness::Forget, Commitment(0u64.into()))
+            .unwrap();
+
+        assert_eq!(tree.check_capacity(0), Ok(()));
+        assert_eq!(tree.check_capacity(TIER_CAPACITY - 1), Ok(()));
+        assert_eq!(
+            tree.check_capacity(TIER_CAPACITY),
+            Err(InsertError::BlockFull)
+        );
+
+        // Once the block is ended, the next block has room for a whole block's worth
+        tree.end_block().unwrap();
+        assert_eq!(tree.check_capacity(TIER_CAPACITY), Ok(()));
+    }
+
+    #[test]
+    fn root_history_is_not_serialized() {
+        let mut tree = Tree::with_root_history(4);
+        let mut plain = Tree::new();
+        for i in 0..3u64 {
+            let commitment = Commitment(i.into());
+            tree.insert(Witness::Keep, commitment).unwrap();
+            plain.insert(Witness::Keep, commitment).unwrap();
+            tree.end_block().unwrap();
+            plain.end_block().unwrap();
+        }
+
+        // A tree with history serializes exactly like one without, so existing stored trees
+        // still load
+        let bytes = bincode::serialize(&tree).unwrap();
+        assert_eq!(bytes, bincode::serialize(&plain).unwrap());
+
+        // After loading, the history restarts from the current root...
+        let mut loaded: Tree = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.root_history().count(), 0);
+        loaded.set_root_history_len(4);
+        assert_eq!(loaded.root_history().collect::<Vec<_>>(), vec![tree.root()]);
+
+        // ...unless the history saved alongside it is restored.
+        let saved = tree.root_history().collect::<Vec<_>>();
+        assert_eq!(saved.len(), 4);
+        assert_eq!(loaded.restore_root_history(saved.clone()), 4);
+        assert_eq!(loaded.root_history().collect::<Vec<_>>(), saved);
+        for root in &saved {
+            assert!(loaded.is_recent_root(*root));
+        }
+    }
+
+    #[test]
+    fn stale_root_histories_are_not_restored() {
+        let mut tree = Tree::with_root_history(4);
+        tree.insert(Witness::Keep, Commitment(0u64.into())).unwrap();
+        tree.end_block().unwrap();
+        let saved = tree.root_history().collect::<Vec<_>>();
+
+        // The tree moves on after the history was saved...
+        tree.insert(Witness::Keep, Commitment(1u64.into())).unwrap();
+        tree.end_block().unwrap();
+        let bytes = bincode::serialize(&tree).unwrap();
+        let mut loaded: Tree = bincode::deserialize(&bytes).unwrap();
+        loaded.set_root_history_len(4);
+
+        // ...so the saved history doesn't lead to its current root, and isn't restored.
+        assert_eq!(loaded.restore_root_history(saved), 0);
+        assert_eq!(loaded.root_history().collect::<Vec<_>>(), vec![tree.root()]);
+
+        // A longer history than the loaded tree retains is truncated.
+        let mut short: Tree = bincode::deserialize(&bytes).unwrap();
+        short.set_root_history_len(2);
+        assert_eq!(short.restore_root_history(tree.root_history()), 2);
+        assert_eq!(
+            short.root_history().collect::<Vec<_>>(),
+            tree.root_history().take(2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn metadata_is_not_serialized() {
+        let mut tree = Tree::<u64>::default();
+        let mut plain = Tree::new();
+        for i in 0..3u64 {
+            let commitment = Commitment(i.into());
+            tree.insert_with_metadata(commitment, i).unwrap();
+            plain.insert(Witness::Keep, commitment).unwrap();
+        }
+        tree.forget(Commitment(1u64.into()));
+        plain.forget(Commitment(1u64.into()));
+
+        // A tree with metadata serializes exactly like one without, so existing stored trees
+        // still load
+        let bytes = bincode::serialize(&tree).unwrap();
+        assert_eq!(bytes, bincode::serialize(&plain).unwrap());
+
+        // The metadata is restored separately, except for positions that aren't witnessed
+        let entries: Vec<(Position, u64)> = tree
+            .metadata_entries()
+            .map(|(position, metadata)| (position, *metadata))
+            .collect();
+        assert_eq!(entries.len(), 2);
+        let mut loaded: Tree<u64> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.metadata(Commitment(0u64.into())), None);
+        let stale = (Position::from(1u64), 1);
+        assert_eq!(
+            loaded.restore_metadata(entries.into_iter().chain([stale])),
+            2
+        );
+        for i in [0, 2u64] {
+            assert_eq!(loaded.metadata(Commitment(i.into())), Some(&i));
+        }
+        assert_eq!(loaded.metadata(Commitment(1u64.into())), None);
+    }
+
+    #[test]
+    fn end_block_and_witness_refreshes_proofs() {
+        let mut tree = Tree::new();
+        let ours = [Commitment(2u64.into()), Commitment(0u64.into())];
+        let theirs = Commitment(1u64.into());
+
+        tree.insert(Witness::Keep, ours[1]).unwrap();
+        tree.insert(Witness::Forget, theirs).unwrap();
+        tree.insert(Witness::Keep, ours[0]).unwrap();
+
+        // Proofs are returned in order of position, skipping repeated and unwitnessed commitments
+        let (_, proofs) = tree
+            .end_block_and_witness(&[ours[0], theirs, ours[1], ours[0]])
+            .unwrap();
+        assert_eq!(
+            proofs.iter().map(Proof::commitment).collect::<Vec<_>>(),
+            vec![ours[1], ours[0]]
+        );
+        for proof in &proofs {
+            proof.verify(tree.root()).unwrap();
+            assert_eq!(Some(proof), tree.witness(proof.commitment()).as_ref());
+        }
+
+        // After more commitments are added, the old proofs are stale, but fresh ones verify
+        tree.insert(Witness::Forget, Commitment(3u64.into()))
+            .unwrap();
+        let (_, fresh) = tree.end_epoch_and_witness(&ours).unwrap();
+        assert!(proofs
+            .iter()
+            .all(|proof| proof.verify(tree.root()).is_err()));
+        for proof in &fresh {
+            proof.verify(tree.root()).unwrap();
+        }
+        assert_eq!(fresh.len(), 2);
+    }
+
+    proptest! {
+        #[test]
+        fn insert_unique_rejects_witnessed_duplicates(insertions in insertions()) {
+            let mut tree = Tree::new();
+            let mut witnessed = HashMap::new();
+
+            for (witness, commitment) in insertions {
+                let (root, position) = (tree.root(), tree.position());
+                match tree.insert_unique(witness, commitment) {
+                    Ok(inserted) => {
+                        prop_assert!(!witnessed.contains_key(&commitment));
+                        if witness == Witness::Keep {
+                            witnessed.insert(commitment, inserted);
+                        }
+                    }
+                    Err(InsertError::Duplicate(existing)) => {
+                        prop_assert_eq!(witnessed.get(&commitment), Some(&existing));
+                        // A rejected insertion leaves the tree untouched
+                        prop_assert_eq!(tree.root(), root);
+                        prop_assert_eq!(tree.position(), position);
+                    }
+                    Err(error) => panic!("unexpected error: {}", error),
+                }
+            }
+
+            prop_assert_eq!(tree.witnessed_count(), witnessed.len());
+            for (commitment, position) in witnessed {
+                prop_assert_eq!(tree.position_of(commitment), Some(position));
+                prop_assert!(tree.witness(commitment).is_some());
+            }
+        }
+
+        #[test]
+        fn insert_witnesses_latest_duplicate(insertions in insertions()) {
+            let mut tree = Tree::new();
+            let mut witnessed = HashMap::new();
+
+            for (witness, commitment) in insertions {
+                let position = tree.insert(witness, commitment).unwrap();
+                if witness == Witness::Keep {
+                    witnessed.insert(commitment, position);
+                }
+            }
+
+            prop_assert_eq!(tree.witnessed_count(), witnessed.len());
+            for (commitment, position) in witnessed {
+                prop_assert_eq!(tree.position_of(commitment), Some(position));
+            }
+        }
+
+        #[test]
+        fn positions_of_is_the_latest_kept_insertion(insertions in insertions()) {
+            let mut tree = Tree::new();
+            let mut latest = HashMap::new();
+            let mut forgotten = Vec::new();
+
+            for (witness, commitment) in insertions {
+                let position = tree.insert(witness, commitment).unwrap();
+                match witness {
+                    // Keeping a commitment again replaces its earlier position
+                    Witness::Keep => {
+                        latest.insert(commitment, position);
+                    }
+                    // Forgetting a repeat doesn't disturb the position it's already kept at
+                    Witness::Forget => forgotten.push(commitment),
+                }
+            }
+
+            for commitment in forgotten {
+                if !latest.contains_key(&commitment) {
+                    prop_assert!(tree.positions_of(commitment).is_empty());
+                }
+            }
+            for (commitment, position) in latest {
+                prop_assert_eq!(tree.positions_of(commitment), vec![position]);
+                // Forgetting the commitment forgets every position it was witnessed at
+                tree.forget(commitment);
+                prop_assert!(tree.positions_of(commitment).is_empty());
+            }
+        }
+
+        #[test]
+        fn metadata_lives_as_long_as_the_witness(insertions in insertions()) {
+            let mut tree = Tree::<usize>::default();
+            let mut expected = HashMap::new();
+
+            // Keep each commitment with its insertion's index as metadata, or forget it instead
+            for (i, (witness, commitment)) in insertions.into_iter().enumerate() {
+                match witness {
+                    Witness::Keep => {
+                        tree.insert_with_metadata(commitment, i).unwrap();
+                        expected.insert(commitment, i);
+                    }
+                    Witness::Forget => {
+                        tree.forget(commitment);
+                        expected.remove(&commitment);
+                    }
+                }
+            }
+
+            for (commitment, i) in &expected {
+                prop_assert_eq!(tree.metadata(*commitment), Some(i));
+            }
+
+            // The same metadata is found at the leaves of the tree
+            let mut leaves = HashMap::new();
+            structure::traverse(tree.structure(), &mut |node| {
+                if let Kind::Leaf {
+                    commitment: Some(commitment),
+                } = node.kind()
+                {
+                    leaves.insert(commitment, *node.metadata::<usize>().unwrap());
+                }
+            });
+            prop_assert_eq!(leaves, expected);
+        }
     }
 }
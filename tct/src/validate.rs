@@ -13,7 +13,7 @@ use crate::prelude::*;
 ///
 /// If this ever returns `Err`, it indicates either a bug in this crate, or a tree that was
 /// deserialized from an untrustworthy source.
-pub fn index(tree: &Tree) -> Result<(), IndexMalformed> {
+pub fn index<M: Send + Sync + 'static>(tree: &Tree<M>) -> Result<(), IndexMalformed> {
     // A reverse index from positions back to the commitments that are supposed to map to their hashes
     let reverse_index: HashMap<Position, Commitment> = tree
         .commitments()
@@ -116,7 +116,7 @@ pub enum IndexError {
 ///
 /// If this ever returns `Err`, it indicates either a bug in this crate, or a tree that was
 /// deserialized from an untrustworthy source.
-pub fn all_proofs(tree: &Tree) -> Result<(), InvalidWitnesses> {
+pub fn all_proofs<M: Send + Sync + 'static>(tree: &Tree<M>) -> Result<(), InvalidWitnesses> {
     let root = tree.root();
 
     let mut errors = vec![];
@@ -181,7 +181,7 @@ pub enum WitnessError {
 /// a lot of hashing.
 ///
 /// If this ever returns `Err`, it indicates a bug in this crate.
-pub fn cached_hashes(tree: &Tree) -> Result<(), InvalidCachedHashes> {
+pub fn cached_hashes<M: Send + Sync + 'static>(tree: &Tree<M>) -> Result<(), InvalidCachedHashes> {
     use structure::*;
 
     fn check_hashes(errors: &mut Vec<InvalidCachedHash>, node: Node) {
@@ -255,7 +255,7 @@ pub struct InvalidCachedHash {
 /// This is a relatively expensive operation which requires traversing the entire tree structure.
 ///
 /// If this ever returns `Err`, it indicates a bug in this crate.
-pub fn forgotten(tree: &Tree) -> Result<(), InvalidForgotten> {
+pub fn forgotten<M: Send + Sync + 'static>(tree: &Tree<M>) -> Result<(), InvalidForgotten> {
     use structure::*;
 
     fn check_forgotten(
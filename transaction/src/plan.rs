@@ -13,6 +13,7 @@ mod auth;
 mod build;
 
 pub use action::{ActionPlan, OutputPlan, SpendPlan};
+pub use build::{BuildProgress, ProofKind};
 
 /// A declaration of a planned [`Transaction`](crate::Transaction),
 /// for use in transaction authorization and creation.
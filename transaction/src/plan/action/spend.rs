@@ -1,6 +1,8 @@
 use ark_ff::UniformRand;
 use decaf377_rdsa::{Signature, SpendAuth};
-use penumbra_crypto::{proofs::transparent::SpendProof, FieldExt, Fr, FullViewingKey, Note};
+use penumbra_crypto::{
+    proofs::transparent::SpendProof, FieldExt, Fr, FullViewingKey, Note, Nullifier,
+};
 use penumbra_proto::{transaction as pb, Protobuf};
 use penumbra_tct as tct;
 use rand_core::{CryptoRng, RngCore};
@@ -51,7 +53,7 @@ impl SpendPlan {
     pub fn spend_body(&self, fvk: &FullViewingKey) -> spend::Body {
         spend::Body {
             value_commitment: self.note.value().commit(self.value_blinding),
-            nullifier: fvk.derive_nullifier(self.position, &self.note.commit()),
+            nullifier: Nullifier::derive(fvk.nullifier_key(), self.position, &self.note.commit()),
             rk: fvk.spend_verification_key().randomize(&self.randomizer),
         }
     }
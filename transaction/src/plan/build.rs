@@ -1,10 +1,33 @@
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
-use penumbra_crypto::{rdsa, Fr, FullViewingKey, Zero};
+use penumbra_crypto::{rdsa, value, FullViewingKey};
 use rand_core::{CryptoRng, RngCore};
 
 use super::TransactionPlan;
 use crate::{action::Action, AuthorizationData, Transaction, TransactionBody, WitnessData};
 
+/// The kind of proof generated while building a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofKind {
+    Spend,
+    Output,
+}
+
+/// Progress made building a transaction, reported by
+/// [`TransactionPlan::build_with_progress`] after each proof is generated.
+#[derive(Debug, Clone, Copy)]
+pub struct BuildProgress {
+    /// The kind of proof which was just generated.
+    pub kind: ProofKind,
+    /// The number of proofs generated so far, including this one.
+    pub completed: usize,
+    /// The total number of proofs the transaction needs.
+    pub total: usize,
+    /// How long generating this proof took.
+    pub elapsed: Duration,
+}
+
 impl TransactionPlan {
     /// Build the transaction this plan describes.
     ///
@@ -20,6 +43,22 @@ impl TransactionPlan {
         fvk: &FullViewingKey,
         auth_data: AuthorizationData,
         witness_data: WitnessData,
+    ) -> Result<Transaction> {
+        self.build_with_progress(rng, fvk, auth_data, witness_data, |_| {})
+    }
+
+    /// Build the transaction this plan describes, like [`TransactionPlan::build`], calling
+    /// `progress` after each spend or output proof is generated.
+    ///
+    /// Proving takes most of the time spent building a transaction, so this lets a client show
+    /// progress while building a transaction with many actions.
+    pub fn build_with_progress<R: CryptoRng + RngCore>(
+        self,
+        rng: &mut R,
+        fvk: &FullViewingKey,
+        auth_data: AuthorizationData,
+        witness_data: WitnessData,
+        mut progress: impl FnMut(BuildProgress),
     ) -> Result<Transaction> {
         // Do some basic input sanity-checking.
         let spend_count = self.spend_plans().count();
@@ -39,7 +78,19 @@ impl TransactionPlan {
         }
 
         let mut actions = Vec::new();
-        let mut synthetic_blinding_factor = Fr::zero();
+        // Only spends and outputs need proofs, and they're built first, so the number of actions
+        // built so far is the number of proofs generated.
+        let total = spend_count + self.output_plans().count();
+
+        // Spends add to the transaction's value balance, and outputs subtract from it. All other
+        // actions have "transparent" value balance with no blinding factor, so they don't
+        // contribute to the synthetic blinding factor used for the binding signature.
+        let synthetic_blinding_factor = value::synthetic_blinding_factor(
+            self.spend_plans()
+                .map(|spend_plan| spend_plan.value_blinding),
+            self.output_plans()
+                .map(|output_plan| output_plan.value_blinding),
+        );
 
         // We build the actions sorted by type, with all spends first, then all
         // outputs, etc.  This order has to align with the ordering in
@@ -52,26 +103,31 @@ impl TransactionPlan {
             .zip(auth_data.spend_auths.into_iter())
             .zip(witness_data.note_commitment_proofs.into_iter())
         {
-            // Spends add to the transaction's value balance.
-            synthetic_blinding_factor += spend_plan.value_blinding;
+            let started = Instant::now();
             actions.push(Action::Spend(spend_plan.spend(fvk, auth_sig, auth_path)));
+            progress(BuildProgress {
+                kind: ProofKind::Spend,
+                completed: actions.len(),
+                total,
+                elapsed: started.elapsed(),
+            });
         }
 
         // Build the transaction's outputs.
         for output_plan in self.output_plans() {
-            // Outputs subtract from the transaction's value balance.
-            synthetic_blinding_factor -= output_plan.value_blinding;
+            let started = Instant::now();
             actions.push(Action::Output(output_plan.output(fvk.outgoing())));
+            progress(BuildProgress {
+                kind: ProofKind::Output,
+                completed: actions.len(),
+                total,
+                elapsed: started.elapsed(),
+            });
         }
 
         // We don't have anything more to build, but iterate through the rest of
         // the action plans by type so that the transaction will have them in a
         // defined order.
-
-        // All of these actions have "transparent" value balance with no
-        // blinding factor, so they don't contribute to the
-        // synthetic_blinding_factor used for the binding signature.
-
         for delegation in self.delegations().cloned() {
             actions.push(Action::Delegate(delegation))
         }
@@ -1,12 +1,11 @@
 use std::convert::{TryFrom, TryInto};
 
 use anyhow::Error;
-use ark_ff::Zero;
 use bytes::Bytes;
 use penumbra_crypto::{
-    rdsa::{Binding, Signature, VerificationKey, VerificationKeyBytes},
+    rdsa::{Binding, Signature, VerificationKey},
     transaction::Fee,
-    Fr, NotePayload, Nullifier, Value, STAKING_TOKEN_ASSET_ID,
+    value, NotePayload, Nullifier, Value, STAKING_TOKEN_ASSET_ID,
 };
 use penumbra_proto::{ibc as pb_ibc, stake as pbs, transaction as pbt, Message, Protobuf};
 use penumbra_tct as tct;
@@ -126,28 +125,28 @@ impl Transaction {
         id_bytes
     }
 
-    /// Compute the binding verification key from the transaction data.
-    pub fn binding_verification_key(&self) -> VerificationKey<Binding> {
-        let mut value_commitments = decaf377::Element::default();
-        for action in &self.transaction_body.actions {
-            value_commitments += action.value_commitment().0;
-        }
-
-        // Add fee into binding verification key computation.
+    /// Compute the balance commitment of the transaction: the sum of its actions' value
+    /// commitments, less the fee.
+    ///
+    /// See [`value::balance_commitment`] for details.
+    pub fn balance_commitment(&self) -> value::Commitment {
         let fee_value = Value {
             amount: self.transaction_body.fee.0,
             asset_id: *STAKING_TOKEN_ASSET_ID,
         };
-        let fee_v_blinding = Fr::zero();
-        let fee_value_commitment = fee_value.commit(fee_v_blinding);
-        value_commitments -= fee_value_commitment.0;
 
-        let binding_verification_key_bytes: VerificationKeyBytes<Binding> =
-            value_commitments.compress().0.into();
+        value::balance_commitment(
+            self.transaction_body
+                .actions
+                .iter()
+                .map(|action| action.value_commitment()),
+            fee_value,
+        )
+    }
 
-        binding_verification_key_bytes
-            .try_into()
-            .expect("verification key is valid")
+    /// Compute the binding verification key from the transaction data.
+    pub fn binding_verification_key(&self) -> VerificationKey<Binding> {
+        self.balance_commitment().binding_verification_key()
     }
 }
 
@@ -0,0 +1,7 @@
+-- The timestamps of blocks in which our notes were created or spent, so that
+-- history can be displayed and filtered by date rather than by height
+CREATE TABLE block_times (
+    height      BIGINT PRIMARY KEY NOT NULL,
+    -- RFC 3339 timestamp of the block
+    block_time  TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Soft reservations of notes selected into transaction plans, so that
+-- concurrent clients don't select the same notes to spend
+CREATE TABLE note_reservations (
+    note_commitment BLOB PRIMARY KEY NOT NULL,
+    -- the plan the note was selected into
+    plan_id         BLOB NOT NULL,
+    -- when the reservation lapses, in seconds since the Unix epoch
+    expires_at      BIGINT NOT NULL
+);
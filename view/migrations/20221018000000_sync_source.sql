@@ -0,0 +1,12 @@
+-- The node the view service syncs compact blocks from, and the result of the
+-- last check of its health. Transactions may be submitted to a different node,
+-- which the view service doesn't track.
+CREATE TABLE sync_source (
+    -- there is only ever one row
+    id         INTEGER PRIMARY KEY CHECK (id = 0),
+    url        TEXT NOT NULL,
+    -- when the node was last checked, in seconds since the Unix epoch
+    checked_at BIGINT NOT NULL,
+    -- the error from the last check, or NULL if the node was healthy
+    error      TEXT
+);
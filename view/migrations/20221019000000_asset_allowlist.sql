@@ -0,0 +1,5 @@
+-- If nonempty, the only assets whose notes are recorded; notes of any other
+-- asset are dropped while scanning
+CREATE TABLE asset_allowlist (
+    asset_id BLOB PRIMARY KEY NOT NULL
+);
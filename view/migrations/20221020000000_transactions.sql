@@ -0,0 +1,27 @@
+-- The transactions which created or spent our notes, so that history can be shown per transaction
+-- rather than per note
+CREATE TABLE transactions (
+    tx_hash     BLOB PRIMARY KEY NOT NULL,
+    height      BIGINT NOT NULL,
+    fee         BIGINT NOT NULL,
+    -- the text of the memo of the first of our outputs, if any
+    memo        TEXT
+);
+
+CREATE INDEX transactions_height_idx ON transactions (height);
+
+-- The notes each transaction created or spent
+CREATE TABLE transaction_notes (
+    tx_hash         BLOB NOT NULL,
+    note_commitment BLOB NOT NULL,
+    -- whether the transaction spent the note, rather than creating it
+    spent           BOOLEAN NOT NULL
+);
+
+CREATE INDEX transaction_notes_idx ON transaction_notes (tx_hash);
+
+-- The heights of blocks whose transactions have been recorded, so that blocks containing our
+-- notes but scanned before the transactions were fetched can be found
+CREATE TABLE transaction_heights (
+    height      BIGINT PRIMARY KEY NOT NULL
+);
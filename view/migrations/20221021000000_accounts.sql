@@ -0,0 +1,18 @@
+-- The accounts scanned into this database, each a full viewing key with a label to refer to it by.
+-- Account 0 is the one the database was initialized with, whose key is also kept in
+-- `full_viewing_key`.
+CREATE TABLE full_viewing_keys (
+    account     BIGINT PRIMARY KEY NOT NULL,
+    label       TEXT NOT NULL UNIQUE,
+    bytes       BLOB NOT NULL
+);
+
+INSERT INTO full_viewing_keys (account, label, bytes)
+    SELECT 0, 'default', bytes FROM full_viewing_key;
+
+-- The account whose key decrypted each note: notes scanned before accounts were added belong to
+-- the first one
+ALTER TABLE notes ADD COLUMN account BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE quarantined_notes ADD COLUMN account BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX notes_account_idx ON notes (account);
@@ -0,0 +1,5 @@
+-- Compact blocks retained after scanning, for offline analysis.
+CREATE TABLE compact_block_cache (
+    height BIGINT PRIMARY KEY NOT NULL,
+    bytes BLOB NOT NULL
+);
@@ -0,0 +1,12 @@
+-- The outputs of our transactions sent to others, recovered with our outgoing viewing key
+CREATE TABLE transaction_sent_outputs (
+    tx_hash     BLOB NOT NULL,
+    -- the note sent, encoded as in note plaintexts
+    note        BLOB NOT NULL,
+    memo        TEXT
+);
+
+CREATE INDEX transaction_sent_outputs_idx ON transaction_sent_outputs (tx_hash);
+
+-- Fetch the transactions recorded so far again, to recover the outputs they sent.
+DELETE FROM transaction_heights;
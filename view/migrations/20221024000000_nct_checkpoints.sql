@@ -0,0 +1,9 @@
+-- Snapshots of the note commitment tree, from which scanning can resume after a rollback.
+CREATE TABLE note_commitment_tree_checkpoints (
+    height BIGINT PRIMARY KEY NOT NULL,
+    bytes BLOB NOT NULL
+);
+
+-- The height at which each quarantined nullifier was spent, so that the spend can be rolled back;
+-- null for nullifiers recorded before this was tracked, all of which precede any checkpoint.
+ALTER TABLE quarantined_nullifiers ADD COLUMN height BIGINT;
@@ -0,0 +1,7 @@
+-- The changes made to the note commitment tree by each block since its last snapshot, which are
+-- replayed onto the snapshot when the tree is loaded, so that each block only writes what it
+-- changed.
+CREATE TABLE note_commitment_tree_updates (
+    height BIGINT PRIMARY KEY NOT NULL,
+    updates BLOB NOT NULL
+);
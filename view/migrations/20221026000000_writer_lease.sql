@@ -0,0 +1,7 @@
+-- The view worker allowed to record scanned blocks, until its lease expires, so that two workers
+-- sharing a database don't interleave their blocks. There is at most one row.
+CREATE TABLE writer_lease (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+    holder TEXT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
@@ -0,0 +1,15 @@
+-- Where each note came from, as a code matching the `NoteOrigin` proto enum: 1 for a genesis
+-- allocation, 2 for a payment received, 3 for the output of one of our own transactions, e.g.,
+-- change, and 4 for a staking reward; null until it's known.
+ALTER TABLE notes ADD COLUMN source INTEGER;
+
+-- Classify the notes already scanned as far as the transactions already fetched allow, the same
+-- way as scanning does.
+UPDATE notes SET source = 1 WHERE height_created = 0;
+UPDATE notes SET source = 3 WHERE source IS NULL AND note_commitment IN
+    (SELECT note_commitment FROM transaction_notes WHERE NOT spent AND tx_hash IN
+        (SELECT tx_hash FROM transaction_notes WHERE spent));
+UPDATE notes SET source = 2 WHERE source IS NULL AND note_commitment IN
+    (SELECT note_commitment FROM transaction_notes WHERE NOT spent);
+UPDATE notes SET source = 4 WHERE source IS NULL AND height_created IN
+    (SELECT height FROM transaction_heights);
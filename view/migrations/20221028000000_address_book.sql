@@ -0,0 +1,6 @@
+-- Payment addresses of other parties, each with a label to refer to it by, e.g., when sending.
+CREATE TABLE address_book (
+    label       TEXT PRIMARY KEY NOT NULL,
+    -- the address as its bech32 string
+    address     TEXT NOT NULL
+);
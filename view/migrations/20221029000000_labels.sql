@@ -0,0 +1,8 @@
+-- Names chosen for diversifier indices of each account, so that they can be treated as named
+-- accounts; several indices may share a name.
+CREATE TABLE labels (
+    account             BIGINT NOT NULL,
+    diversifier_index   BLOB NOT NULL,
+    label               TEXT NOT NULL,
+    PRIMARY KEY (account, diversifier_index)
+);
@@ -0,0 +1,2 @@
+-- The height up to which spent notes were pruned, before which balances can't be reconstructed
+CREATE TABLE pruned_height (height BIGINT NOT NULL);
@@ -0,0 +1,24 @@
+use penumbra_crypto::{keys::FullViewingKeyHash, FullViewingKey};
+
+/// The label of the account a view database is initialized with.
+pub const DEFAULT_ACCOUNT_LABEL: &str = "default";
+
+/// One of the accounts scanned by a view service.
+///
+/// A view service can scan for the notes of several full viewing keys at once, sharing one
+/// database and one pass over the chain. Clients select an account by the hash of its full viewing
+/// key, which they can look up by label.
+#[derive(Debug, Clone)]
+pub struct Account {
+    /// The account's index in the database, in the order accounts were added.
+    pub index: u32,
+    /// A name for the account, unique within the database.
+    pub label: String,
+    pub fvk: FullViewingKey,
+}
+
+impl Account {
+    pub fn fvk_hash(&self) -> FullViewingKeyHash {
+        self.fvk.hash()
+    }
+}
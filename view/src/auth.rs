@@ -0,0 +1,71 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Context;
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+
+/// A capability which can be granted to a view service auth token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    /// Read unspent notes, and so the wallet's balances.
+    ReadBalances,
+    /// Read spent notes, and query notes by when they were created.
+    ReadHistory,
+    /// Reserve notes and request witnesses for them, in order to plan and build transactions.
+    PlanTransactions,
+    /// Manage the view service's storage, e.g., export its compact block cache, or reset it to a
+    /// height to rescan from.
+    ManageStorage,
+}
+
+/// The auth tokens accepted by a view service, along with the scopes granted to each.
+///
+/// Clients present a token as a bearer token in the `authorization` metadata of each request.
+/// Methods which reveal nothing about the wallet (the chain parameters, known assets, and sync
+/// status) only require a valid token; all others require specific scopes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Authorization {
+    tokens: BTreeMap<String, BTreeSet<Scope>>,
+}
+
+impl Authorization {
+    /// Loads the tokens from a JSON file, mapping each token to the list of scopes it grants.
+    pub fn load(path: impl AsRef<Utf8Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read auth tokens from {}", path))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("could not parse auth tokens in {}", path))
+    }
+
+    /// Accept `token`, granting it the given scopes in addition to any it already had.
+    pub fn grant(&mut self, token: String, scopes: impl IntoIterator<Item = Scope>) {
+        self.tokens.entry(token).or_default().extend(scopes);
+    }
+
+    /// Check that the request carries a known token which was granted all of the `required`
+    /// scopes.
+    pub fn check<T>(
+        &self,
+        request: &tonic::Request<T>,
+        required: &[Scope],
+    ) -> Result<(), tonic::Status> {
+        let granted = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| self.tokens.get(token))
+            .ok_or_else(|| tonic::Status::unauthenticated("missing or invalid auth token"))?;
+
+        if let Some(missing) = required.iter().find(|scope| !granted.contains(scope)) {
+            return Err(tonic::Status::permission_denied(format!(
+                "auth token is not granted the {:?} scope",
+                missing
+            )));
+        }
+
+        Ok(())
+    }
+}
@@ -1,14 +1,15 @@
 #![recursion_limit = "256"]
 #![allow(clippy::clone_on_copy)]
 use anyhow::{Context, Result};
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::{Parser, Subcommand};
-use penumbra_crypto::FullViewingKey;
+use penumbra_crypto::{asset, FullViewingKey};
 use penumbra_proto::client::oblivious::oblivious_query_client::ObliviousQueryClient;
 use penumbra_proto::client::oblivious::ChainParamsRequest;
 use penumbra_proto::view::view_protocol_server::ViewProtocolServer;
-use penumbra_view::ViewService;
+use penumbra_view::{Authorization, Storage, StorageKey, ViewService};
 use std::env;
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
 use std::str::FromStr;
 use tonic::transport::Server;
 
@@ -34,6 +35,18 @@ struct Opt {
     /// The port to use to speak to pd's gRPC server.
     #[clap(long, default_value = "8080")]
     pd_port: u16,
+    /// If set, the state database is encrypted with this passphrase, which is then needed to
+    /// open it. Requires `pviewd` to be built with the `sqlcipher` feature.
+    #[clap(long, env = "PVIEWD_DB_PASSPHRASE", hide_env_values = true)]
+    db_passphrase: Option<String>,
+}
+
+/// Load the state database at `path`, decrypting it with `key`, if set.
+async fn load_storage(path: &Utf8Path, key: Option<&StorageKey>) -> Result<Storage> {
+    match key {
+        Some(key) => Storage::load_encrypted(path, key).await,
+        None => Storage::load(path).await,
+    }
 }
 
 #[derive(Debug, Subcommand)]
@@ -43,6 +56,32 @@ enum Command {
         /// The full viewing key to initialize the view service with.
         full_viewing_key: String,
     },
+    /// Add another account to an initialized view service, and rescan the chain for its notes.
+    AddAccount {
+        /// A label for the account, unique within the view service.
+        #[clap(long)]
+        label: String,
+        /// The full viewing key of the account.
+        full_viewing_key: String,
+    },
+    /// Roll back the view service's data to a given height, so that it rescans the chain from
+    /// there when next started. Scanning resumes from the latest snapshot of the note commitment
+    /// tree at or before the height, or from genesis if there's none.
+    Rescan {
+        /// The height to rescan from.
+        #[clap(long)]
+        from_height: u64,
+    },
+    /// Print the compact blocks retained by `pviewd start --retain-compact-blocks`, one JSON
+    /// object per line, so that they can be re-scanned offline.
+    ExportCompactBlocks {
+        /// Only export blocks at or after this height.
+        #[clap(long)]
+        start_height: Option<u64>,
+        /// Only export blocks at or before this height.
+        #[clap(long)]
+        end_height: Option<u64>,
+    },
     /// Start the view service.
     Start {
         /// Bind the view service to this host.
@@ -51,12 +90,41 @@ enum Command {
         /// Bind the view gRPC server to this port.
         #[clap(long, default_value = "8081")]
         view_port: u16,
+        /// If set, require clients to authenticate with one of the tokens in this JSON file,
+        /// which maps each token to the scopes it is granted.
+        #[clap(long)]
+        auth_tokens: Option<Utf8PathBuf>,
+        /// If set, scan at most this many blocks per second, so that syncing in the background
+        /// doesn't monopolize a core. Clients can lift the limit temporarily while they wait.
+        #[clap(long)]
+        max_blocks_per_second: Option<NonZeroU32>,
+        /// If set, trial-decrypt notes on at most this many threads while scanning. By default,
+        /// one thread per core is used.
+        #[clap(long)]
+        scan_threads: Option<NonZeroUsize>,
+        /// If set, only record notes of this asset, which may be repeated, and ignore notes of any
+        /// other asset. Changing the list to include a new asset rescans the chain from genesis.
+        #[clap(long = "allow-asset", value_name = "ASSET_ID")]
+        allowed_assets: Vec<asset::Id>,
+        /// If set, keep this many of the most recently scanned compact blocks, so that they can be
+        /// exported to investigate notes the view service missed. By default, none are kept.
+        #[clap(long, value_name = "BLOCKS")]
+        retain_compact_blocks: Option<NonZeroU64>,
+        /// If set, record the transactions which created or spent the wallet's notes, for the
+        /// `TransactionInfo` RPC.
+        ///
+        /// This fetches the full block at each height where the wallet received or spent a note,
+        /// which reveals those heights to the node, so only set it if the node is trusted with
+        /// them.
+        #[clap(long)]
+        transaction_history: bool,
     },
 }
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
     let opt = Opt::parse();
+    let storage_key = opt.db_passphrase.clone().map(StorageKey::Passphrase);
 
     match opt.cmd {
         Command::Init { full_viewing_key } => {
@@ -72,22 +140,95 @@ async fn main() -> Result<()> {
                 .into_inner()
                 .try_into()?;
 
-            penumbra_view::Storage::initialize(
-                opt.sqlite_path.as_path(),
-                FullViewingKey::from_str(full_viewing_key.as_ref())
-                    .context("The provided string is not a valid FullViewingKey")?,
-                params,
-            )
-            .await?;
+            let fvk = FullViewingKey::from_str(full_viewing_key.as_ref())
+                .context("The provided string is not a valid FullViewingKey")?;
+            match storage_key {
+                Some(key) => {
+                    Storage::initialize_encrypted(opt.sqlite_path.as_path(), fvk, params, &key)
+                        .await?
+                }
+                None => Storage::initialize(opt.sqlite_path.as_path(), fvk, params).await?,
+            };
+            Ok(())
+        }
+        Command::AddAccount {
+            label,
+            full_viewing_key,
+        } => {
+            let account = load_storage(&opt.sqlite_path, storage_key.as_ref())
+                .await?
+                .add_account(
+                    &label,
+                    &FullViewingKey::from_str(full_viewing_key.as_ref())
+                        .context("The provided string is not a valid FullViewingKey")?,
+                )
+                .await?;
+            println!("added account {} ({})", account.index, account.label);
+            Ok(())
+        }
+        Command::Rescan { from_height } => {
+            let resume_height = load_storage(&opt.sqlite_path, storage_key.as_ref())
+                .await?
+                .reset_to_height(from_height)
+                .await?;
+            match resume_height {
+                Some(height) => println!("rolled back to height {}", height),
+                None => println!("rolled back to genesis"),
+            }
+            Ok(())
+        }
+        Command::ExportCompactBlocks {
+            start_height,
+            end_height,
+        } => {
+            let blocks = load_storage(&opt.sqlite_path, storage_key.as_ref())
+                .await?
+                .cached_compact_blocks(start_height.unwrap_or(0)..=end_height.unwrap_or(u64::MAX))
+                .await?;
+            if blocks.is_empty() {
+                tracing::warn!("no compact blocks cached in that range");
+            }
+            for block in blocks {
+                println!("{}", serde_json::to_string(&block)?);
+            }
             Ok(())
         }
-        Command::Start { host, view_port } => {
+        Command::Start {
+            host,
+            view_port,
+            auth_tokens,
+            max_blocks_per_second,
+            scan_threads,
+            allowed_assets,
+            retain_compact_blocks,
+            transaction_history,
+        } => {
             tracing::info!(?opt.sqlite_path, ?host, ?view_port, ?opt.node, ?opt.tendermint_port, ?opt.pd_port, "starting pviewd");
 
-            let storage = penumbra_view::Storage::load(opt.sqlite_path).await?;
+            let storage = load_storage(&opt.sqlite_path, storage_key.as_ref()).await?;
+            storage
+                .set_asset_allowlist(if allowed_assets.is_empty() {
+                    None
+                } else {
+                    Some(allowed_assets.into_iter().collect())
+                })
+                .await?;
+            storage
+                .set_compact_block_retention(retain_compact_blocks)
+                .await?;
+            storage.set_transaction_history(transaction_history);
 
-            let service =
+            let mut service =
                 ViewService::new(storage, opt.node, opt.pd_port, opt.tendermint_port).await?;
+            if let Some(path) = auth_tokens {
+                service = service.with_authorization(Authorization::load(path)?);
+            }
+            if let Some(max_blocks_per_second) = max_blocks_per_second {
+                service = service.with_sync_limit(max_blocks_per_second);
+            }
+            if let Some(scan_threads) = scan_threads {
+                service = service.with_scan_threads(scan_threads);
+            }
 
             tokio::spawn(
                 Server::builder()
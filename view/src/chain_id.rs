@@ -0,0 +1,109 @@
+use penumbra_proto::client::{CHAIN_ID_METADATA_KEY, PD_VERSION_METADATA_KEY};
+use tonic::metadata::MetadataMap;
+
+/// An error returned when a node reports that it serves a different chain than expected, or
+/// doesn't report which chain it serves.
+///
+/// This usually means the node's address was mistyped, or belongs to a different testnet, and
+/// using it would mix data from two chains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainIdMismatchError {
+    /// The chain ID the node reported, if any.
+    pub reported: Option<String>,
+    /// The chain ID that was expected.
+    pub expected: String,
+    /// The version of `pd` the node reported, if any.
+    pub pd_version: Option<String>,
+}
+
+impl std::fmt::Display for ChainIdMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reported {
+            Some(reported) => write!(
+                f,
+                "node serves chain {:?}, not the expected chain {:?}",
+                reported, self.expected
+            )?,
+            None => write!(
+                f,
+                "node did not report which chain it serves, expected chain {:?}",
+                self.expected
+            )?,
+        }
+        if let Some(pd_version) = &self.pd_version {
+            write!(f, " (pd version {})", pd_version)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ChainIdMismatchError {}
+
+/// Check the chain ID a node reported in a response's `metadata` against the `expected` one.
+///
+/// Nodes which don't report a chain ID, because they predate it or haven't committed their
+/// genesis state, are rejected too, since there's no telling which chain they serve.
+pub fn check_chain_id(metadata: &MetadataMap, expected: &str) -> Result<(), ChainIdMismatchError> {
+    let reported = metadata
+        .get(CHAIN_ID_METADATA_KEY)
+        .and_then(|value| value.to_str().ok());
+
+    if reported == Some(expected) {
+        Ok(())
+    } else {
+        Err(ChainIdMismatchError {
+            reported: reported.map(ToString::to_string),
+            expected: expected.to_string(),
+            pd_version: metadata
+                .get(PD_VERSION_METADATA_KEY)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(chain_id: Option<&'static str>, pd_version: Option<&'static str>) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        if let Some(chain_id) = chain_id {
+            metadata.insert(CHAIN_ID_METADATA_KEY, chain_id.parse().unwrap());
+        }
+        if let Some(pd_version) = pd_version {
+            metadata.insert(PD_VERSION_METADATA_KEY, pd_version.parse().unwrap());
+        }
+        metadata
+    }
+
+    #[test]
+    fn matching_chain_id_is_accepted() {
+        let metadata = metadata(Some("penumbra-testnet"), Some("0.1.0"));
+        assert_eq!(check_chain_id(&metadata, "penumbra-testnet"), Ok(()));
+    }
+
+    #[test]
+    fn other_chain_id_is_rejected() {
+        let metadata = metadata(Some("penumbra-devnet"), Some("0.1.0"));
+        let error = check_chain_id(&metadata, "penumbra-testnet").unwrap_err();
+        assert_eq!(
+            error,
+            ChainIdMismatchError {
+                reported: Some("penumbra-devnet".to_string()),
+                expected: "penumbra-testnet".to_string(),
+                pd_version: Some("0.1.0".to_string()),
+            }
+        );
+        assert!(error.to_string().contains("pd version 0.1.0"));
+    }
+
+    #[test]
+    fn missing_chain_id_is_rejected() {
+        for metadata in [metadata(None, Some("0.1.0")), metadata(None, None)] {
+            let error = check_chain_id(&metadata, "penumbra-testnet").unwrap_err();
+            assert_eq!(error.reported, None);
+            assert_eq!(error.expected, "penumbra-testnet");
+        }
+    }
+}
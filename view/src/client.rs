@@ -1,17 +1,17 @@
-use std::{collections::BTreeMap, pin::Pin};
+use std::{collections::BTreeMap, pin::Pin, time::Duration};
 
 use anyhow::Result;
 use futures::{Stream, StreamExt, TryStreamExt};
-use penumbra_chain::params::ChainParams;
+use penumbra_chain::{params::ChainParams, sync::CompactBlock};
 use penumbra_crypto::keys::FullViewingKeyHash;
-use penumbra_crypto::{asset, keys::DiversifierIndex, note, Asset};
+use penumbra_crypto::{asset, keys::DiversifierIndex, note, Asset, IdentityKey, Nullifier};
 use penumbra_proto::view as pb;
 use penumbra_proto::view::view_protocol_client::ViewProtocolClient;
 use penumbra_transaction::WitnessData;
 use tonic::async_trait;
 use tracing::instrument;
 
-use crate::{NoteRecord, QuarantinedNoteRecord, StatusStreamResponse};
+use crate::{NoteEvent, NoteRecord, QuarantinedNoteRecord, StatusStreamResponse, TransactionInfo};
 
 /// The view protocol is used by a view client, who wants to do some
 /// transaction-related actions, to request data from a view service, which is
@@ -48,6 +48,44 @@ pub trait ViewClient {
         request: pb::QuarantinedNotesRequest,
     ) -> Result<Vec<QuarantinedNoteRecord>>;
 
+    /// Streams changes to our notes as the view service records them, until the view service
+    /// shuts down.
+    ///
+    /// Fails if the client falls too far behind, after which it should query for notes to catch
+    /// up before streaming again.
+    async fn note_stream(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<NoteEvent>> + Send + 'static>>>;
+
+    /// Queries for the total amount of each asset in unspent notes, optionally only counting notes
+    /// sent to the address with `diversifier_index`.
+    async fn balances(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        diversifier_index: Option<DiversifierIndex>,
+    ) -> Result<BTreeMap<asset::Id, u64>>;
+
+    /// Queries for the total amount of each asset in unspent notes, for each diversifier index
+    /// with any, in a single request.
+    async fn balances_by_index(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+    ) -> Result<BTreeMap<DiversifierIndex, BTreeMap<asset::Id, u64>>>;
+
+    /// Queries for the total amount of each asset in quarantined notes, by the validator they're
+    /// quarantined for, and then by the epoch they exit quarantine.
+    async fn quarantined_balances(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+    ) -> Result<BTreeMap<IdentityKey, BTreeMap<u64, BTreeMap<asset::Id, u64>>>>;
+
+    /// Queries for the transactions which created or spent our notes.
+    async fn transaction_info(
+        &mut self,
+        request: pb::TransactionInfoRequest,
+    ) -> Result<Vec<TransactionInfo>>;
+
     /// Queries for a specific note by commitment, returning immediately if it is not found.
     async fn note_by_commitment(
         &mut self,
@@ -64,6 +102,23 @@ pub trait ViewClient {
         note_commitment: note::Commitment,
     ) -> Result<NoteRecord>;
 
+    /// Queries for the note with the given nullifier, returning immediately if it is not found.
+    ///
+    /// This maps a spend observed on chain back to the note it spent, if the note is ours.
+    async fn note_by_nullifier(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        nullifier: Nullifier,
+    ) -> Result<NoteRecord>;
+
+    /// Queries for the note with the given nullifier, waiting until the note is detected if it is
+    /// not found.
+    async fn await_note_by_nullifier(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        nullifier: Nullifier,
+    ) -> Result<NoteRecord>;
+
     /// Returns authentication paths for the given note commitments.
     ///
     /// This method takes a batch of input commitments, rather than just one, so
@@ -75,6 +130,52 @@ pub trait ViewClient {
     /// Queries for all known assets.
     async fn assets(&mut self) -> Result<asset::Cache>;
 
+    /// Lists the accounts the view service scans for, mapping each label to the full viewing key
+    /// hash which selects the account in other queries.
+    async fn accounts(&mut self) -> Result<BTreeMap<String, FullViewingKeyHash>>;
+
+    /// Returns the full viewing key hash of the account with the given label.
+    async fn account_by_label(&mut self, label: &str) -> Result<FullViewingKeyHash> {
+        self.accounts()
+            .await?
+            .remove(label)
+            .ok_or_else(|| anyhow::anyhow!("no account is labeled {:?}", label))
+    }
+
+    /// Exports the compact blocks with heights in `start_height..=end_height` that the view
+    /// service retained while scanning, which is empty unless it was configured to retain them.
+    async fn compact_block_cache(
+        &mut self,
+        start_height: Option<u64>,
+        end_height: Option<u64>,
+    ) -> Result<Vec<CompactBlock>>;
+
+    /// Rolls the view service's storage back to the latest snapshot of the note commitment tree
+    /// at or before `height`, and rescans the chain from there, returning the height scanning
+    /// resumes after, or `None` if it restarts from genesis.
+    async fn reset_to_height(&mut self, height: u64) -> Result<Option<u64>>;
+
+    /// Soft-reserves the given notes for the transaction plan `plan_id`, so that concurrent
+    /// clients don't select them into other plans.
+    ///
+    /// Fails without reserving anything if any of the notes is reserved by a different plan.
+    async fn reserve_notes(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        plan_id: Vec<u8>,
+        note_commitments: Vec<note::Commitment>,
+    ) -> Result<()>;
+
+    /// Releases the reservations of the given notes, so that other plans can select them again.
+    async fn release_notes(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        note_commitments: Vec<note::Commitment>,
+    ) -> Result<()>;
+
+    /// Lifts the view service's limit on how quickly it scans blocks for the next `duration`.
+    async fn boost_sync(&mut self, fvk_hash: FullViewingKeyHash, duration: Duration) -> Result<()>;
+
     /// Return unspent notes, grouped by diversifier index and then by asset id.
     #[instrument(skip(self, fvk_hash))]
     async fn unspent_notes_by_address_and_asset(
@@ -144,6 +245,7 @@ pub trait ViewClient {
         let notes = self
             .quarantined_notes(pb::QuarantinedNotesRequest {
                 fvk_hash: Some(fvk_hash.into()),
+                ..Default::default()
             })
             .await?;
         tracing::trace!(?notes);
@@ -172,6 +274,7 @@ pub trait ViewClient {
         let notes = self
             .quarantined_notes(pb::QuarantinedNotesRequest {
                 fvk_hash: Some(fvk_hash.into()),
+                ..Default::default()
             })
             .await?;
         tracing::trace!(?notes);
@@ -271,6 +374,142 @@ where
         pb_notes.into_iter().map(TryInto::try_into).collect()
     }
 
+    async fn note_stream(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<NoteEvent>> + Send + 'static>>> {
+        let stream = ViewProtocolClient::note_stream(
+            self,
+            tonic::Request::new(pb::NoteStreamRequest {
+                fvk_hash: Some(fvk_hash.into()),
+            }),
+        )
+        .await?
+        .into_inner();
+
+        Ok(stream
+            .map_err(|e| anyhow::anyhow!("view service error: {}", e))
+            .and_then(|msg| async move { NoteEvent::try_from(msg) })
+            .boxed())
+    }
+
+    async fn balances(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        diversifier_index: Option<DiversifierIndex>,
+    ) -> Result<BTreeMap<asset::Id, u64>> {
+        let balances: Vec<_> = ViewProtocolClient::balances(
+            self,
+            tonic::Request::new(pb::BalancesRequest {
+                fvk_hash: Some(fvk_hash.into()),
+                diversifier_index: diversifier_index.map(Into::into),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_inner()
+        .try_collect()
+        .await?;
+
+        balances
+            .into_iter()
+            .map(|balance| {
+                let asset_id = balance
+                    .asset_id
+                    .ok_or_else(|| anyhow::anyhow!("missing asset id in response"))?
+                    .try_into()?;
+                Ok((asset_id, balance.amount))
+            })
+            .collect()
+    }
+
+    async fn balances_by_index(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+    ) -> Result<BTreeMap<DiversifierIndex, BTreeMap<asset::Id, u64>>> {
+        let balances: Vec<_> = ViewProtocolClient::balances(
+            self,
+            tonic::Request::new(pb::BalancesRequest {
+                fvk_hash: Some(fvk_hash.into()),
+                by_diversifier_index: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_inner()
+        .try_collect()
+        .await?;
+
+        let mut balances_by_index = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for balance in balances {
+            let diversifier_index: DiversifierIndex = balance
+                .diversifier_index
+                .ok_or_else(|| anyhow::anyhow!("missing diversifier index in response"))?
+                .try_into()?;
+            let asset_id: asset::Id = balance
+                .asset_id
+                .ok_or_else(|| anyhow::anyhow!("missing asset id in response"))?
+                .try_into()?;
+            balances_by_index
+                .entry(diversifier_index)
+                .or_default()
+                .insert(asset_id, balance.amount);
+        }
+
+        Ok(balances_by_index)
+    }
+
+    async fn quarantined_balances(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+    ) -> Result<BTreeMap<IdentityKey, BTreeMap<u64, BTreeMap<asset::Id, u64>>>> {
+        let balances: Vec<_> = ViewProtocolClient::quarantined_balances(
+            self,
+            tonic::Request::new(pb::QuarantinedBalancesRequest {
+                fvk_hash: Some(fvk_hash.into()),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_inner()
+        .try_collect()
+        .await?;
+
+        let mut quarantined_balances = BTreeMap::<_, BTreeMap<_, BTreeMap<_, _>>>::new();
+        for balance in balances {
+            let identity_key: IdentityKey = balance
+                .identity_key
+                .ok_or_else(|| anyhow::anyhow!("missing identity key in response"))?
+                .try_into()?;
+            let asset_id: asset::Id = balance
+                .asset_id
+                .ok_or_else(|| anyhow::anyhow!("missing asset id in response"))?
+                .try_into()?;
+            quarantined_balances
+                .entry(identity_key)
+                .or_default()
+                .entry(balance.unbonding_epoch)
+                .or_default()
+                .insert(asset_id, balance.amount);
+        }
+
+        Ok(quarantined_balances)
+    }
+
+    async fn transaction_info(
+        &mut self,
+        request: pb::TransactionInfoRequest,
+    ) -> Result<Vec<TransactionInfo>> {
+        let pb_transactions: Vec<_> = self
+            .transaction_info(tonic::Request::new(request))
+            .await?
+            .into_inner()
+            .try_collect()
+            .await?;
+
+        pb_transactions.into_iter().map(TryInto::try_into).collect()
+    }
+
     async fn note_by_commitment(
         &mut self,
         fvk_hash: FullViewingKeyHash,
@@ -310,6 +549,91 @@ where
         .try_into()
     }
 
+    async fn note_by_nullifier(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        nullifier: Nullifier,
+    ) -> Result<NoteRecord> {
+        ViewProtocolClient::note_by_nullifier(
+            self,
+            tonic::Request::new(pb::NoteByNullifierRequest {
+                fvk_hash: Some(fvk_hash.into()),
+                nullifier: Some(nullifier.into()),
+                await_detection: false,
+            }),
+        )
+        .await?
+        .into_inner()
+        .try_into()
+    }
+
+    async fn await_note_by_nullifier(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        nullifier: Nullifier,
+    ) -> Result<NoteRecord> {
+        ViewProtocolClient::note_by_nullifier(
+            self,
+            tonic::Request::new(pb::NoteByNullifierRequest {
+                fvk_hash: Some(fvk_hash.into()),
+                nullifier: Some(nullifier.into()),
+                await_detection: true,
+            }),
+        )
+        .await?
+        .into_inner()
+        .try_into()
+    }
+
+    async fn reserve_notes(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        plan_id: Vec<u8>,
+        note_commitments: Vec<note::Commitment>,
+    ) -> Result<()> {
+        ViewProtocolClient::reserve_notes(
+            self,
+            tonic::Request::new(pb::ReserveNotesRequest {
+                fvk_hash: Some(fvk_hash.into()),
+                plan_id,
+                note_commitments: note_commitments.into_iter().map(Into::into).collect(),
+            }),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn release_notes(
+        &mut self,
+        fvk_hash: FullViewingKeyHash,
+        note_commitments: Vec<note::Commitment>,
+    ) -> Result<()> {
+        ViewProtocolClient::release_notes(
+            self,
+            tonic::Request::new(pb::ReleaseNotesRequest {
+                fvk_hash: Some(fvk_hash.into()),
+                note_commitments: note_commitments.into_iter().map(Into::into).collect(),
+            }),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn boost_sync(&mut self, fvk_hash: FullViewingKeyHash, duration: Duration) -> Result<()> {
+        ViewProtocolClient::boost_sync(
+            self,
+            tonic::Request::new(pb::BoostSyncRequest {
+                fvk_hash: Some(fvk_hash.into()),
+                duration_secs: duration.as_secs(),
+            }),
+        )
+        .await?;
+
+        Ok(())
+    }
+
     async fn witness(&mut self, request: pb::WitnessRequest) -> Result<WitnessData> {
         let witness_data: WitnessData = self
             .witness(tonic::Request::new(request))
@@ -337,4 +661,50 @@ where
 
         Ok(assets.into_iter().map(|asset| asset.denom).collect())
     }
+
+    async fn accounts(&mut self) -> Result<BTreeMap<String, FullViewingKeyHash>> {
+        ViewProtocolClient::accounts(self, tonic::Request::new(pb::AccountsRequest {}))
+            .await?
+            .into_inner()
+            .accounts
+            .into_iter()
+            .map(|account| {
+                let fvk_hash = account
+                    .fvk_hash
+                    .ok_or_else(|| anyhow::anyhow!("missing FVK hash in response"))?
+                    .try_into()?;
+                Ok((account.label, fvk_hash))
+            })
+            .collect()
+    }
+    async fn compact_block_cache(
+        &mut self,
+        start_height: Option<u64>,
+        end_height: Option<u64>,
+    ) -> Result<Vec<CompactBlock>> {
+        let pb_blocks: Vec<_> = ViewProtocolClient::compact_block_cache(
+            self,
+            tonic::Request::new(pb::CompactBlockCacheRequest {
+                start_height,
+                end_height,
+            }),
+        )
+        .await?
+        .into_inner()
+        .try_collect()
+        .await?;
+
+        pb_blocks.into_iter().map(TryInto::try_into).collect()
+    }
+
+    async fn reset_to_height(&mut self, height: u64) -> Result<Option<u64>> {
+        let response = ViewProtocolClient::reset_to_height(
+            self,
+            tonic::Request::new(pb::ResetToHeightRequest { height }),
+        )
+        .await?
+        .into_inner();
+
+        Ok(response.resume_height)
+    }
 }
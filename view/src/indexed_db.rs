@@ -0,0 +1,811 @@
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    num::NonZeroU64,
+    rc::Rc,
+};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use penumbra_chain::{params::ChainParams, sync::CompactBlock};
+use penumbra_crypto::{Asset, FullViewingKey, Nullifier};
+use penumbra_proto::Protobuf;
+use penumbra_tct as tct;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{
+    Event, IdbDatabase, IdbFactory, IdbObjectStore, IdbObjectStoreParameters, IdbOpenDbRequest,
+    IdbRequest, IdbTransaction, IdbTransactionMode,
+};
+
+use crate::{
+    sync::ScanResult, Account, NoteOrigin, NoteRecord, QuarantinedNoteRecord, StorageBackend,
+    TransactionInfo, DEFAULT_ACCOUNT_LABEL,
+};
+
+/// The version of the database's object stores and indices, which is bumped, with a migration in
+/// [`create_stores`], whenever they change.
+const SCHEMA_VERSION: u32 = 1;
+
+// Values which there's only one of, like the note commitment tree, keyed by name.
+const META: &str = "meta";
+// Accounts, keyed by their index.
+const ACCOUNTS: &str = "accounts";
+// Assets, keyed by their hex-encoded ID.
+const ASSETS: &str = "assets";
+// Our notes, keyed by their hex-encoded commitment, and indexed by their hex-encoded nullifier.
+const NOTES: &str = "notes";
+// Our quarantined notes, keyed by their hex-encoded commitment, and indexed by the identity key
+// of the validator they're quarantined for.
+const QUARANTINED_NOTES: &str = "quarantined_notes";
+// The nullifiers of our notes spent in undelegations, keyed by the hex-encoded nullifier, and
+// indexed like the quarantined notes.
+const QUARANTINED_NULLIFIERS: &str = "quarantined_nullifiers";
+// The timestamps of the blocks our notes were created or spent in, keyed by height.
+const BLOCK_TIMES: &str = "block_times";
+// The transactions which created or spent our notes, keyed by the height they're recorded for.
+const TRANSACTIONS: &str = "transactions";
+
+/// View storage in a browser's IndexedDB, for running the view logic in a web page or browser
+/// extension, where SQLite isn't available.
+///
+/// Like [`Storage`](crate::Storage), this keeps the height of the last empty block in memory
+/// rather than writing it, so after a restart, scanning resumes from the last block with anything
+/// in it for us. Unlike `Storage`, it keeps no snapshots of the note commitment tree, so
+/// [`StorageBackend::reset_to_height`] always rescans from genesis, and it doesn't cache compact
+/// blocks.
+#[derive(Clone)]
+pub struct IndexedDbStorage {
+    db: IdbDatabase,
+    uncommitted_height: Rc<Cell<Option<u64>>>,
+    transaction_history: Rc<Cell<bool>>,
+}
+
+impl IndexedDbStorage {
+    /// Create the IndexedDB database `name`, scanning for the notes of `fvk` on the chain with
+    /// `params`, failing if it already exists.
+    pub async fn initialize(
+        name: &str,
+        fvk: &FullViewingKey,
+        params: ChainParams,
+    ) -> anyhow::Result<Self> {
+        let storage = Self::open(name).await?;
+        if !storage.accounts().await?.is_empty() {
+            return Err(anyhow!("IndexedDB view database {:?} already exists", name));
+        }
+
+        let transaction = storage.transaction(&[META, ACCOUNTS], IdbTransactionMode::Readwrite)?;
+        let accounts = store(&transaction, ACCOUNTS)?;
+        wait(accounts.put(&object(&[
+            ("index", 0.into()),
+            ("label", DEFAULT_ACCOUNT_LABEL.into()),
+            ("fvk", bytes_value(&fvk.encode_to_vec())),
+        ])?))
+        .await?;
+        let meta = store(&transaction, META)?;
+        put_meta(&meta, "chain_params", bytes_value(&params.encode_to_vec())).await?;
+        put_meta(
+            &meta,
+            "nct",
+            bytes_value(&bincode::serialize(&tct::Tree::new())?),
+        )
+        .await?;
+        commit(&transaction).await?;
+
+        Ok(storage)
+    }
+
+    /// Load the IndexedDB database `name`, which must have been initialized.
+    pub async fn load(name: &str) -> anyhow::Result<Self> {
+        let storage = Self::open(name).await?;
+        if storage.accounts().await?.is_empty() {
+            return Err(anyhow!("IndexedDB view database {:?} does not exist", name));
+        }
+        Ok(storage)
+    }
+
+    /// Whether to fetch and record the transactions which created or spent our notes, which
+    /// reveals their heights to the node. This is off until turned on.
+    pub fn set_transaction_history(&self, enabled: bool) {
+        self.transaction_history.set(enabled);
+    }
+
+    /// Our notes, of `account`, or of every account if it's `None`, including spent notes if
+    /// `include_spent` is set.
+    pub async fn notes(
+        &self,
+        account: Option<u32>,
+        include_spent: bool,
+    ) -> anyhow::Result<Vec<NoteRecord>> {
+        let transaction = self.transaction(&[NOTES, BLOCK_TIMES], IdbTransactionMode::Readonly)?;
+        let block_times = store(&transaction, BLOCK_TIMES)?;
+
+        let mut notes = Vec::new();
+        for (note_account, mut record) in all_notes(&store(&transaction, NOTES)?).await? {
+            if account.map_or(false, |account| account != note_account)
+                || (!include_spent && record.height_spent.is_some())
+            {
+                continue;
+            }
+            record.time_created = block_time(&block_times, record.height_created).await?;
+            notes.push(record);
+        }
+
+        Ok(notes)
+    }
+
+    /// Our quarantined notes, of `account`, or of every account if it's `None`.
+    pub async fn quarantined_notes(
+        &self,
+        account: Option<u32>,
+    ) -> anyhow::Result<Vec<QuarantinedNoteRecord>> {
+        let transaction = self.transaction(&[QUARANTINED_NOTES], IdbTransactionMode::Readonly)?;
+        let values = wait(store(&transaction, QUARANTINED_NOTES)?.get_all()).await?;
+
+        let mut notes = Vec::new();
+        for value in Array::from(&values).iter() {
+            if account.map_or(true, |account| account == account_field(&value)) {
+                notes.push(QuarantinedNoteRecord::decode(
+                    bytes_field(&value, "record")?.as_slice(),
+                )?);
+            }
+        }
+
+        Ok(notes)
+    }
+
+    async fn open(name: &str) -> anyhow::Result<Self> {
+        // Look the factory up on the global object, rather than the window, so that this works in
+        // the service workers of browser extensions too.
+        let factory: IdbFactory = Reflect::get(&js_sys::global(), &"indexedDB".into())
+            .map_err(js_error)?
+            .dyn_into()
+            .map_err(|_| anyhow!("IndexedDB is not available"))?;
+        let request = factory
+            .open_with_u32(name, SCHEMA_VERSION)
+            .map_err(js_error)?;
+
+        let upgrade_request = request.clone();
+        let on_upgrade = Closure::wrap(Box::new(move |_: Event| {
+            if let Err(e) = create_stores(&upgrade_request) {
+                tracing::error!(?e, "could not create IndexedDB object stores");
+                // Aborting the upgrade fails the open request.
+                if let Some(transaction) = upgrade_request.transaction() {
+                    let _ = transaction.abort();
+                }
+            }
+        }) as Box<dyn FnMut(Event)>);
+        request.set_onupgradeneeded(Some(on_upgrade.as_ref().unchecked_ref()));
+        let db = wait(Ok(request.clone().into())).await;
+        request.set_onupgradeneeded(None);
+
+        Ok(Self {
+            db: db
+                .with_context(|| format!("could not open IndexedDB view database {:?}", name))?
+                .dyn_into()
+                .map_err(|_| anyhow!("opening IndexedDB did not return a database"))?,
+            uncommitted_height: Default::default(),
+            transaction_history: Default::default(),
+        })
+    }
+
+    fn transaction(
+        &self,
+        stores: &[&str],
+        mode: IdbTransactionMode,
+    ) -> anyhow::Result<IdbTransaction> {
+        let stores = stores
+            .iter()
+            .map(|store| JsValue::from_str(store))
+            .collect::<Array>();
+        self.db
+            .transaction_with_str_sequence_and_mode(&stores, mode)
+            .map_err(js_error)
+    }
+
+    async fn get_meta(&self, key: &str) -> anyhow::Result<Option<JsValue>> {
+        let transaction = self.transaction(&[META], IdbTransactionMode::Readonly)?;
+        get(&store(&transaction, META)?, &key.into()).await
+    }
+
+    async fn put_meta(&self, key: &str, value: JsValue) -> anyhow::Result<()> {
+        let transaction = self.transaction(&[META], IdbTransactionMode::Readwrite)?;
+        put_meta(&store(&transaction, META)?, key, value).await?;
+        commit(&transaction).await
+    }
+
+    /// The sync height which has been written, ignoring empty blocks recorded since.
+    async fn committed_sync_height(&self) -> anyhow::Result<Option<u64>> {
+        Ok(self
+            .get_meta("sync_height")
+            .await?
+            .and_then(|height| height.as_f64())
+            .map(|height| height as u64))
+    }
+}
+
+#[async_trait(?Send)]
+impl StorageBackend for IndexedDbStorage {
+    async fn accounts(&self) -> anyhow::Result<Vec<Account>> {
+        let transaction = self.transaction(&[ACCOUNTS], IdbTransactionMode::Readonly)?;
+        let values = wait(store(&transaction, ACCOUNTS)?.get_all()).await?;
+
+        Array::from(&values)
+            .iter()
+            .map(|value| {
+                Ok(Account {
+                    index: account_field(&Reflect::get(&value, &"index".into()).map_err(js_error)?),
+                    label: Reflect::get(&value, &"label".into())
+                        .map_err(js_error)?
+                        .as_string()
+                        .ok_or_else(|| anyhow!("account label is not a string"))?,
+                    fvk: FullViewingKey::decode(bytes_field(&value, "fvk")?.as_slice())?,
+                })
+            })
+            .collect()
+    }
+
+    async fn last_sync_height(&self) -> anyhow::Result<Option<u64>> {
+        match self.uncommitted_height.get() {
+            Some(height) => Ok(Some(height)),
+            None => self.committed_sync_height().await,
+        }
+    }
+
+    async fn note_commitment_tree(&self) -> anyhow::Result<tct::Tree> {
+        let bytes = self
+            .get_meta("nct")
+            .await?
+            .ok_or_else(|| anyhow!("missing note commitment tree"))?;
+        Ok(bincode::deserialize(&Uint8Array::new(&bytes).to_vec())?)
+    }
+
+    async fn chain_params(&self) -> anyhow::Result<ChainParams> {
+        let bytes = self
+            .get_meta("chain_params")
+            .await?
+            .ok_or_else(|| anyhow!("missing chain parameters"))?;
+        ChainParams::decode(Uint8Array::new(&bytes).to_vec().as_slice())
+    }
+
+    async fn update_chain_params(&self, params: &ChainParams) -> anyhow::Result<()> {
+        self.put_meta("chain_params", bytes_value(&params.encode_to_vec()))
+            .await
+    }
+
+    async fn assets(&self) -> anyhow::Result<Vec<Asset>> {
+        let transaction = self.transaction(&[ASSETS], IdbTransactionMode::Readonly)?;
+        let values = wait(store(&transaction, ASSETS)?.get_all()).await?;
+
+        Array::from(&values)
+            .iter()
+            .map(|value| Asset::decode(Uint8Array::new(&value).to_vec().as_slice()))
+            .collect()
+    }
+
+    async fn record_asset(&self, asset: Asset) -> anyhow::Result<()> {
+        let transaction = self.transaction(&[ASSETS], IdbTransactionMode::Readwrite)?;
+        wait(store(&transaction, ASSETS)?.put_with_key(
+            &bytes_value(&asset.encode_to_vec()),
+            &hex_key(&asset.id.to_bytes()),
+        ))
+        .await?;
+        commit(&transaction).await
+    }
+
+    async fn record_empty_block(&self, height: u64) -> anyhow::Result<()> {
+        let last_sync_height = self
+            .last_sync_height()
+            .await?
+            .ok_or_else(|| anyhow!("invalid: tried to record empty block as genesis block"))?;
+        if height != last_sync_height + 1 {
+            return Err(anyhow!(
+                "Wrong block height {} for latest sync height {}",
+                height,
+                last_sync_height
+            ));
+        }
+
+        self.uncommitted_height.set(Some(height));
+        Ok(())
+    }
+
+    async fn record_blocks(
+        &self,
+        scan_results: Vec<ScanResult>,
+        nct: &mut tct::Tree,
+    ) -> anyhow::Result<()> {
+        let (first_height, last_height) = match (scan_results.first(), scan_results.last()) {
+            (Some(first), Some(last)) => (first.height, last.height),
+            _ => return Ok(()),
+        };
+
+        let last_sync_height = self.last_sync_height().await?;
+        let correct_height = match last_sync_height {
+            Some(height) => first_height == height + 1,
+            None => first_height == 0,
+        };
+        if !correct_height {
+            return Err(anyhow!(
+                "Wrong block height {} for latest sync height {:?}",
+                first_height,
+                last_sync_height
+            ));
+        }
+
+        let transaction = self.transaction(
+            &[META, NOTES, QUARANTINED_NOTES, QUARANTINED_NULLIFIERS],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let notes = store(&transaction, NOTES)?;
+        let quarantined_notes = store(&transaction, QUARANTINED_NOTES)?;
+        let quarantined_nullifiers = store(&transaction, QUARANTINED_NULLIFIERS)?;
+
+        for scan_result in &scan_results {
+            let account = |commitment: &tct::Commitment| {
+                scan_result.accounts.get(commitment).copied().unwrap_or(0)
+            };
+
+            for record in &scan_result.new_quarantined_notes {
+                wait(quarantined_notes.put(&object(&[
+                    ("commitment", hex_key(&record.note_commitment.0.to_bytes())),
+                    (
+                        "identity_key",
+                        JsValue::from_str(&record.identity_key.to_string()),
+                    ),
+                    ("account", account(&record.note_commitment).into()),
+                    ("record", bytes_value(&record.encode_to_vec())),
+                ])?))
+                .await?;
+            }
+
+            for record in &scan_result.new_notes {
+                let commitment = hex_key(&record.note_commitment.0.to_bytes());
+                let mut record = record.clone();
+                // A note which was quarantined is the output of our own undelegation, now
+                // unbonded, and created by no transaction in this block.
+                if get(&quarantined_notes, &commitment).await?.is_some() {
+                    record.source = Some(NoteOrigin::Change);
+                    wait(quarantined_notes.delete(&commitment)).await?;
+                }
+                put_note(&notes, account(&record.note_commitment), &record).await?;
+            }
+
+            // Spends in undelegations are only quarantined, so the spent notes aren't forgotten
+            // from the tree, in case they're rolled back.
+            for (identity_key, nullifiers) in &scan_result.spent_quarantined_nullifiers {
+                for nullifier in nullifiers {
+                    wait(quarantined_nullifiers.put(&object(&[
+                        ("nullifier", hex_key(&nullifier.to_bytes())),
+                        ("identity_key", JsValue::from_str(&identity_key.to_string())),
+                    ])?))
+                    .await?;
+                    set_height_spent(&notes, nullifier, Some(scan_result.height)).await?;
+                }
+            }
+
+            for nullifier in &scan_result.spent_nullifiers {
+                if let Some(commitment) =
+                    set_height_spent(&notes, nullifier, Some(scan_result.height)).await?
+                {
+                    nct.forget(commitment);
+                }
+                wait(quarantined_nullifiers.delete(&hex_key(&nullifier.to_bytes()))).await?;
+            }
+
+            // A slashed validator's quarantined notes are lost, and the spends quarantined for it
+            // are rolled back.
+            for identity_key in &scan_result.slashed_validators {
+                let identity_key = JsValue::from_str(&identity_key.to_string());
+                for commitment in index_keys(&quarantined_notes, &identity_key).await? {
+                    wait(quarantined_notes.delete(&commitment)).await?;
+                }
+                for nullifier in index_keys(&quarantined_nullifiers, &identity_key).await? {
+                    let bytes = hex::decode(
+                        nullifier
+                            .as_string()
+                            .ok_or_else(|| anyhow!("nullifier key is not a string"))?,
+                    )?;
+                    set_height_spent(&notes, &Nullifier::try_from(bytes.as_slice())?, None).await?;
+                    wait(quarantined_nullifiers.delete(&nullifier)).await?;
+                }
+            }
+        }
+
+        let meta = store(&transaction, META)?;
+        put_meta(&meta, "nct", bytes_value(&bincode::serialize(nct)?)).await?;
+        put_meta(&meta, "sync_height", JsValue::from_f64(last_height as f64)).await?;
+        commit(&transaction).await?;
+        self.uncommitted_height.set(None);
+
+        Ok(())
+    }
+
+    async fn reset_to_height(&self, height: u64) -> anyhow::Result<Option<u64>> {
+        tracing::info!(
+            height,
+            "IndexedDB view storage keeps no snapshots, rescanning from genesis"
+        );
+
+        let stores = [
+            META,
+            NOTES,
+            QUARANTINED_NOTES,
+            QUARANTINED_NULLIFIERS,
+            TRANSACTIONS,
+        ];
+        let transaction = self.transaction(&stores, IdbTransactionMode::Readwrite)?;
+        for name in &stores[1..] {
+            wait(store(&transaction, name)?.clear()).await?;
+        }
+        let meta = store(&transaction, META)?;
+        wait(meta.delete(&"sync_height".into())).await?;
+        put_meta(
+            &meta,
+            "nct",
+            bytes_value(&bincode::serialize(&tct::Tree::new())?),
+        )
+        .await?;
+        commit(&transaction).await?;
+        self.uncommitted_height.set(None);
+
+        Ok(None)
+    }
+
+    async fn notes_at_height(&self, height: u64) -> anyhow::Result<Vec<NoteRecord>> {
+        Ok(self
+            .notes(None, true)
+            .await?
+            .into_iter()
+            .filter(|record| record.height_created == height || record.height_spent == Some(height))
+            .collect())
+    }
+
+    async fn heights_missing_block_times(&self) -> anyhow::Result<Vec<u64>> {
+        let transaction = self.transaction(
+            &[NOTES, QUARANTINED_NOTES, BLOCK_TIMES],
+            IdbTransactionMode::Readonly,
+        )?;
+
+        let mut heights = note_heights(&store(&transaction, NOTES)?).await?;
+        let quarantined = wait(store(&transaction, QUARANTINED_NOTES)?.get_all()).await?;
+        for value in Array::from(&quarantined).iter() {
+            heights.insert(
+                QuarantinedNoteRecord::decode(bytes_field(&value, "record")?.as_slice())?
+                    .height_created,
+            );
+        }
+        for height in height_keys(&store(&transaction, BLOCK_TIMES)?).await? {
+            heights.remove(&height);
+        }
+
+        Ok(heights.into_iter().collect())
+    }
+
+    async fn record_block_time(&self, height: u64, block_time: String) -> anyhow::Result<()> {
+        let transaction = self.transaction(&[BLOCK_TIMES], IdbTransactionMode::Readwrite)?;
+        wait(
+            store(&transaction, BLOCK_TIMES)?
+                .put_with_key(&block_time.into(), &JsValue::from_f64(height as f64)),
+        )
+        .await?;
+        commit(&transaction).await
+    }
+
+    async fn heights_missing_transactions(&self) -> anyhow::Result<Vec<u64>> {
+        let transaction = self.transaction(&[NOTES, TRANSACTIONS], IdbTransactionMode::Readonly)?;
+
+        let mut heights = note_heights(&store(&transaction, NOTES)?).await?;
+        for height in height_keys(&store(&transaction, TRANSACTIONS)?).await? {
+            heights.remove(&height);
+        }
+
+        Ok(heights.into_iter().collect())
+    }
+
+    async fn record_transactions(
+        &self,
+        height: u64,
+        transactions: &[TransactionInfo],
+    ) -> anyhow::Result<()> {
+        let transaction =
+            self.transaction(&[NOTES, TRANSACTIONS], IdbTransactionMode::Readwrite)?;
+        let notes = store(&transaction, NOTES)?;
+
+        // Classify our notes by the transactions which created them, as `Storage` does.
+        let mut sources = BTreeMap::new();
+        for info in transactions {
+            // A transaction spending our notes is our own, so its outputs to us are change.
+            let source = if info.spends.is_empty() {
+                NoteOrigin::Received
+            } else {
+                NoteOrigin::Change
+            };
+            for record in &info.outputs {
+                sources.insert(record.note_commitment.0.to_bytes(), source);
+            }
+        }
+        for (account, mut record) in all_notes(&notes).await? {
+            let source = match sources.get(&record.note_commitment.0.to_bytes()) {
+                Some(source) => *source,
+                // The notes created at this height by none of its transactions were minted at
+                // the end of the block, as staking rewards.
+                None if record.height_created == height && record.source.is_none() => {
+                    NoteOrigin::StakingReward
+                }
+                None => continue,
+            };
+            record.source = Some(source);
+            put_note(&notes, account, &record).await?;
+        }
+
+        let infos = transactions
+            .iter()
+            .map(|info| bytes_value(&info.encode_to_vec()))
+            .collect::<Array>();
+        wait(
+            store(&transaction, TRANSACTIONS)?
+                .put_with_key(&infos, &JsValue::from_f64(height as f64)),
+        )
+        .await?;
+        commit(&transaction).await
+    }
+
+    fn compact_block_retention(&self) -> Option<NonZeroU64> {
+        None
+    }
+
+    fn transaction_history(&self) -> bool {
+        self.transaction_history.get()
+    }
+
+    async fn cache_compact_blocks(&self, _blocks: &[CompactBlock]) -> anyhow::Result<()> {
+        // Compact blocks aren't cached, as `compact_block_retention` says.
+        Ok(())
+    }
+
+    async fn record_sync_source_health(
+        &self,
+        url: &str,
+        error: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.put_meta(
+            "sync_source",
+            object(&[
+                ("url", url.into()),
+                ("checked_at", (js_sys::Date::now() / 1000.0).floor().into()),
+                ("error", error.map_or(JsValue::NULL, JsValue::from)),
+            ])?,
+        )
+        .await
+    }
+}
+
+/// Create the object stores and indices of the database being opened by `request`.
+fn create_stores(request: &IdbOpenDbRequest) -> Result<(), JsValue> {
+    let db: IdbDatabase = request.result()?.dyn_into()?;
+    let existing = db.object_store_names();
+    let keyed_by = |key_path: &str| {
+        let mut parameters = IdbObjectStoreParameters::new();
+        parameters.key_path(Some(&key_path.into()));
+        parameters
+    };
+
+    for name in [META, ASSETS, BLOCK_TIMES, TRANSACTIONS] {
+        if !existing.contains(name) {
+            db.create_object_store(name)?;
+        }
+    }
+    if !existing.contains(ACCOUNTS) {
+        db.create_object_store_with_optional_parameters(ACCOUNTS, &keyed_by("index"))?;
+    }
+    if !existing.contains(NOTES) {
+        db.create_object_store_with_optional_parameters(NOTES, &keyed_by("commitment"))?
+            .create_index_with_str("nullifier", "nullifier")?;
+    }
+    if !existing.contains(QUARANTINED_NOTES) {
+        db.create_object_store_with_optional_parameters(
+            QUARANTINED_NOTES,
+            &keyed_by("commitment"),
+        )?
+        .create_index_with_str("identity_key", "identity_key")?;
+    }
+    if !existing.contains(QUARANTINED_NULLIFIERS) {
+        db.create_object_store_with_optional_parameters(
+            QUARANTINED_NULLIFIERS,
+            &keyed_by("nullifier"),
+        )?
+        .create_index_with_str("identity_key", "identity_key")?;
+    }
+
+    Ok(())
+}
+
+/// Wait for the IndexedDB `request` to finish, returning its result.
+async fn wait(request: Result<IdbRequest, JsValue>) -> anyhow::Result<JsValue> {
+    let request = request.map_err(js_error)?;
+
+    let (tx, rx) = oneshot::channel();
+    let mut tx = Some(tx);
+    let on_done = Closure::wrap(Box::new(move |_: Event| {
+        if let Some(tx) = tx.take() {
+            let _ = tx.send(());
+        }
+    }) as Box<dyn FnMut(Event)>);
+    request.set_onsuccess(Some(on_done.as_ref().unchecked_ref()));
+    request.set_onerror(Some(on_done.as_ref().unchecked_ref()));
+    let done = rx.await;
+    request.set_onsuccess(None);
+    request.set_onerror(None);
+    done.context("IndexedDB request was dropped")?;
+
+    if let Some(e) = request.error().map_err(js_error)? {
+        return Err(anyhow!("IndexedDB request failed: {}", e.message()));
+    }
+    request.result().map_err(js_error)
+}
+
+/// Wait for the IndexedDB `transaction` to commit, after its last request.
+async fn commit(transaction: &IdbTransaction) -> anyhow::Result<()> {
+    let (tx, rx) = oneshot::channel();
+    let mut tx = Some(tx);
+    let on_done = Closure::wrap(Box::new(move |event: Event| {
+        if let Some(tx) = tx.take() {
+            let _ = tx.send(event.type_());
+        }
+    }) as Box<dyn FnMut(Event)>);
+    transaction.set_oncomplete(Some(on_done.as_ref().unchecked_ref()));
+    transaction.set_onerror(Some(on_done.as_ref().unchecked_ref()));
+    transaction.set_onabort(Some(on_done.as_ref().unchecked_ref()));
+    let outcome = rx.await;
+    transaction.set_oncomplete(None);
+    transaction.set_onerror(None);
+    transaction.set_onabort(None);
+
+    match outcome
+        .context("IndexedDB transaction was dropped")?
+        .as_str()
+    {
+        "complete" => Ok(()),
+        _ => Err(anyhow!(
+            "IndexedDB transaction failed: {}",
+            transaction
+                .error()
+                .map_or_else(|| "aborted".to_string(), |e| e.message())
+        )),
+    }
+}
+
+fn store(transaction: &IdbTransaction, name: &str) -> anyhow::Result<IdbObjectStore> {
+    transaction.object_store(name).map_err(js_error)
+}
+
+async fn get(store: &IdbObjectStore, key: &JsValue) -> anyhow::Result<Option<JsValue>> {
+    let value = wait(store.get(key)).await?;
+    Ok((!value.is_undefined()).then(|| value))
+}
+
+async fn put_meta(meta: &IdbObjectStore, key: &str, value: JsValue) -> anyhow::Result<()> {
+    wait(meta.put_with_key(&value, &key.into())).await?;
+    Ok(())
+}
+
+/// The primary keys of the values in `store` whose `identity_key` is `identity_key`.
+async fn index_keys(store: &IdbObjectStore, identity_key: &JsValue) -> anyhow::Result<Array> {
+    let index = store.index("identity_key").map_err(js_error)?;
+    Ok(Array::from(
+        &wait(index.get_all_keys_with_key(identity_key)).await?,
+    ))
+}
+
+/// The heights keying the values in `store`.
+async fn height_keys(store: &IdbObjectStore) -> anyhow::Result<Vec<u64>> {
+    let keys = wait(store.get_all_keys()).await?;
+    Ok(Array::from(&keys)
+        .iter()
+        .filter_map(|key| key.as_f64())
+        .map(|height| height as u64)
+        .collect())
+}
+
+/// Every note in `notes`, along with the index of the account it belongs to.
+async fn all_notes(notes: &IdbObjectStore) -> anyhow::Result<Vec<(u32, NoteRecord)>> {
+    let values = wait(notes.get_all()).await?;
+    Array::from(&values)
+        .iter()
+        .map(|value| {
+            Ok((
+                account_field(&value),
+                NoteRecord::decode(bytes_field(&value, "record")?.as_slice())?,
+            ))
+        })
+        .collect()
+}
+
+/// The heights at which the notes in `notes` were created or spent.
+async fn note_heights(notes: &IdbObjectStore) -> anyhow::Result<BTreeSet<u64>> {
+    let mut heights = BTreeSet::new();
+    for (_, record) in all_notes(notes).await? {
+        heights.insert(record.height_created);
+        heights.extend(record.height_spent);
+    }
+    Ok(heights)
+}
+
+async fn put_note(notes: &IdbObjectStore, account: u32, record: &NoteRecord) -> anyhow::Result<()> {
+    wait(notes.put(&object(&[
+        ("commitment", hex_key(&record.note_commitment.0.to_bytes())),
+        ("nullifier", hex_key(&record.nullifier.to_bytes())),
+        ("account", account.into()),
+        ("record", bytes_value(&record.encode_to_vec())),
+    ])?))
+    .await?;
+    Ok(())
+}
+
+/// Set the height at which our note with `nullifier` was spent, if we have it, returning its
+/// commitment.
+async fn set_height_spent(
+    notes: &IdbObjectStore,
+    nullifier: &Nullifier,
+    height_spent: Option<u64>,
+) -> anyhow::Result<Option<tct::Commitment>> {
+    let index = notes.index("nullifier").map_err(js_error)?;
+    let value = wait(index.get(&hex_key(&nullifier.to_bytes()))).await?;
+    if value.is_undefined() {
+        return Ok(None);
+    }
+
+    let mut record = NoteRecord::decode(bytes_field(&value, "record")?.as_slice())?;
+    record.height_spent = height_spent;
+    put_note(notes, account_field(&value), &record).await?;
+    Ok(Some(record.note_commitment))
+}
+
+async fn block_time(block_times: &IdbObjectStore, height: u64) -> anyhow::Result<Option<String>> {
+    Ok(get(block_times, &JsValue::from_f64(height as f64))
+        .await?
+        .and_then(|block_time| block_time.as_string()))
+}
+
+/// A JavaScript object with the given fields, as stored in IndexedDB.
+fn object(fields: &[(&str, JsValue)]) -> anyhow::Result<JsValue> {
+    let object = Object::new();
+    for (name, value) in fields {
+        Reflect::set(&object, &(*name).into(), value).map_err(js_error)?;
+    }
+    Ok(object.into())
+}
+
+fn bytes_value(bytes: &[u8]) -> JsValue {
+    Uint8Array::from(bytes).into()
+}
+
+fn bytes_field(value: &JsValue, name: &str) -> anyhow::Result<Vec<u8>> {
+    let field = Reflect::get(value, &name.into()).map_err(js_error)?;
+    if !field.is_instance_of::<Uint8Array>() {
+        return Err(anyhow!("stored {} is not a byte array", name));
+    }
+    Ok(Uint8Array::new(&field).to_vec())
+}
+
+/// The account index stored in `value`'s `account` field, or `value` itself if it's a number.
+fn account_field(value: &JsValue) -> u32 {
+    value
+        .as_f64()
+        .or_else(|| {
+            Reflect::get(value, &"account".into())
+                .ok()
+                .and_then(|account| account.as_f64())
+        })
+        .unwrap_or(0.0) as u32
+}
+
+fn hex_key(bytes: &[u8]) -> JsValue {
+    JsValue::from_str(&hex::encode(bytes))
+}
+
+fn js_error(e: JsValue) -> anyhow::Error {
+    anyhow!("IndexedDB error: {:?}", e)
+}
@@ -1,22 +1,83 @@
 // Required because of NCT type size
 #![recursion_limit = "256"]
 
+mod account;
+#[cfg(not(target_arch = "wasm32"))]
+mod auth;
+#[cfg(not(target_arch = "wasm32"))]
+mod chain_id;
+#[cfg(not(target_arch = "wasm32"))]
 mod client;
+#[cfg(target_arch = "wasm32")]
+mod indexed_db;
+#[cfg(not(target_arch = "wasm32"))]
 mod metrics;
+mod note_event;
+mod note_origin;
 mod note_record;
+mod note_selection;
 mod quarantined_note_record;
+#[cfg(not(target_arch = "wasm32"))]
+mod scan_rate;
+#[cfg(not(target_arch = "wasm32"))]
 mod service;
+#[cfg(not(target_arch = "wasm32"))]
+mod snapshot;
+#[cfg(not(target_arch = "wasm32"))]
+mod spot_check;
+#[cfg(not(target_arch = "wasm32"))]
 mod status;
+#[cfg(not(target_arch = "wasm32"))]
 mod storage;
+mod storage_backend;
+#[cfg(not(target_arch = "wasm32"))]
+mod storage_config;
+#[cfg(not(target_arch = "wasm32"))]
+mod storage_key;
 mod sync;
+#[cfg(not(target_arch = "wasm32"))]
+mod throttle;
+mod transaction_info;
+#[cfg(not(target_arch = "wasm32"))]
+mod witness_cache;
+#[cfg(not(target_arch = "wasm32"))]
 mod worker;
 
+#[cfg(not(target_arch = "wasm32"))]
 use worker::Worker;
 
-pub use crate::metrics::register_metrics;
-pub use client::ViewClient;
+pub use account::{Account, DEFAULT_ACCOUNT_LABEL};
+pub use note_event::NoteEvent;
+pub use note_origin::NoteOrigin;
 pub use note_record::NoteRecord;
+pub use note_selection::SelectionStrategy;
 pub use quarantined_note_record::QuarantinedNoteRecord;
+pub use storage_backend::{MaybeSendSync, StorageBackend};
+pub use sync::{NctUpdate, ScanResult};
+pub use transaction_info::{SentOutput, TransactionInfo};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::metrics::register_metrics;
+#[cfg(not(target_arch = "wasm32"))]
+pub use auth::{Authorization, Scope};
+#[cfg(not(target_arch = "wasm32"))]
+pub use chain_id::{check_chain_id, ChainIdMismatchError};
+#[cfg(not(target_arch = "wasm32"))]
+pub use client::ViewClient;
+#[cfg(not(target_arch = "wasm32"))]
 pub use service::ViewService;
-pub use status::StatusStreamResponse;
-pub use storage::Storage;
+#[cfg(not(target_arch = "wasm32"))]
+pub use spot_check::SpotCheck;
+#[cfg(not(target_arch = "wasm32"))]
+pub use status::{StatusStreamResponse, SyncConnection};
+#[cfg(not(target_arch = "wasm32"))]
+pub use storage::{
+    FvkMismatchError, PruneSummary, Storage, SyncSourceHealth, WriterLeaseHeldError,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use storage_config::{JournalMode, StorageConfig, Synchronous};
+#[cfg(not(target_arch = "wasm32"))]
+pub use storage_key::StorageKey;
+
+#[cfg(target_arch = "wasm32")]
+pub use indexed_db::IndexedDbStorage;
@@ -0,0 +1,75 @@
+use penumbra_proto::{view as pb, Protobuf};
+
+use crate::{NoteRecord, QuarantinedNoteRecord};
+
+/// A change to a wallet's notes, recorded while scanning.
+///
+/// Corresponds to the NoteStreamResponse proto
+#[derive(Debug, Clone)]
+pub enum NoteEvent {
+    /// A note was detected.
+    Detected(NoteRecord),
+    /// A note was spent.
+    Spent(NoteRecord),
+    /// A note was quarantined until the validator it was undelegated from finishes unbonding.
+    Quarantined(QuarantinedNoteRecord),
+    /// Everything scanned after `height`, or everything if it's `None`, was rolled back to be
+    /// rescanned: notes detected after it were forgotten, and notes spent after it are unspent
+    /// again, so subscribers must query for notes to catch up.
+    RolledBack { height: Option<u64> },
+}
+
+impl Protobuf<pb::NoteStreamResponse> for NoteEvent {}
+impl From<NoteEvent> for pb::NoteStreamResponse {
+    fn from(v: NoteEvent) -> Self {
+        use pb::note_stream_response::Event;
+
+        pb::NoteStreamResponse {
+            event: Some(match v {
+                NoteEvent::Detected(record) => Event::Detected(record.into()),
+                NoteEvent::Spent(record) => Event::Spent(record.into()),
+                NoteEvent::Quarantined(record) => Event::Quarantined(record.into()),
+                NoteEvent::RolledBack { height } => {
+                    Event::RolledBack(pb::note_stream_response::RolledBack { height })
+                }
+            }),
+        }
+    }
+}
+
+impl TryFrom<pb::NoteStreamResponse> for NoteEvent {
+    type Error = anyhow::Error;
+    fn try_from(v: pb::NoteStreamResponse) -> Result<Self, Self::Error> {
+        use pb::note_stream_response::Event;
+
+        Ok(
+            match v
+                .event
+                .ok_or_else(|| anyhow::anyhow!("missing note event"))?
+            {
+                Event::Detected(record) => NoteEvent::Detected(record.try_into()?),
+                Event::Spent(record) => NoteEvent::Spent(record.try_into()?),
+                Event::Quarantined(record) => NoteEvent::Quarantined(record.try_into()?),
+                Event::RolledBack(rolled_back) => NoteEvent::RolledBack {
+                    height: rolled_back.height,
+                },
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollbacks_round_trip() {
+        for height in [None, Some(0), Some(1000)] {
+            let bytes = NoteEvent::RolledBack { height }.encode_to_vec();
+            assert!(matches!(
+                NoteEvent::decode(bytes.as_slice()).unwrap(),
+                NoteEvent::RolledBack { height: decoded } if decoded == height
+            ));
+        }
+    }
+}
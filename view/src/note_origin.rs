@@ -0,0 +1,62 @@
+use anyhow::anyhow;
+use penumbra_proto::view as pb;
+
+/// Where one of our notes came from, so that wallets can tell payments apart from their own
+/// change.
+///
+/// Compact blocks don't say which transaction created a note, so only genesis allocations and
+/// unbonded notes are classified when they're scanned; the rest are classified once the
+/// transactions of the block they were created in are fetched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NoteOrigin {
+    /// Allocated at genesis.
+    Genesis,
+    /// Sent by a transaction which spent none of our notes.
+    Received,
+    /// Created by one of our own transactions, e.g., change, or the proceeds of a delegation or
+    /// undelegation.
+    Change,
+    /// Minted outside of any transaction, as a validator's funding stream reward.
+    StakingReward,
+}
+
+impl NoteOrigin {
+    /// The code stored in the `source` column of the notes table, which is the value of the
+    /// corresponding proto enum variant.
+    pub(crate) fn code(self) -> i64 {
+        pb::NoteOrigin::from(self) as i64
+    }
+
+    pub(crate) fn from_code(code: i64) -> anyhow::Result<Self> {
+        i32::try_from(code)
+            .ok()
+            .and_then(pb::NoteOrigin::from_i32)
+            .ok_or_else(|| anyhow!("unknown note origin code {}", code))?
+            .try_into()
+    }
+}
+
+impl From<NoteOrigin> for pb::NoteOrigin {
+    fn from(origin: NoteOrigin) -> Self {
+        match origin {
+            NoteOrigin::Genesis => pb::NoteOrigin::Genesis,
+            NoteOrigin::Received => pb::NoteOrigin::Received,
+            NoteOrigin::Change => pb::NoteOrigin::Change,
+            NoteOrigin::StakingReward => pb::NoteOrigin::StakingReward,
+        }
+    }
+}
+
+impl TryFrom<pb::NoteOrigin> for NoteOrigin {
+    type Error = anyhow::Error;
+
+    fn try_from(origin: pb::NoteOrigin) -> anyhow::Result<Self> {
+        match origin {
+            pb::NoteOrigin::Unclassified => Err(anyhow!("note origin is unclassified")),
+            pb::NoteOrigin::Genesis => Ok(NoteOrigin::Genesis),
+            pb::NoteOrigin::Received => Ok(NoteOrigin::Received),
+            pb::NoteOrigin::Change => Ok(NoteOrigin::Change),
+            pb::NoteOrigin::StakingReward => Ok(NoteOrigin::StakingReward),
+        }
+    }
+}
@@ -8,8 +8,11 @@ use penumbra_proto::{view as pb, Protobuf};
 use penumbra_tct as tct;
 
 use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
 use sqlx::Row;
 
+use crate::NoteOrigin;
+
 /// Corresponds to the NoteRecord proto
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(try_from = "pb::NoteRecord", into = "pb::NoteRecord")]
@@ -21,6 +24,10 @@ pub struct NoteRecord {
     pub height_created: u64,
     pub height_spent: Option<u64>,
     pub position: tct::Position,
+    /// The time at which the note was created, in RFC 3339 format, if known.
+    pub time_created: Option<String>,
+    /// Where the note came from, if it's been classified yet.
+    pub source: Option<NoteOrigin>,
 }
 
 impl Protobuf<pb::NoteRecord> for NoteRecord {}
@@ -34,6 +41,8 @@ impl From<NoteRecord> for pb::NoteRecord {
             height_created: v.height_created,
             height_spent: v.height_spent,
             position: v.position.into(),
+            time_created: v.time_created.unwrap_or_default(),
+            source: v.source.map_or(pb::NoteOrigin::Unclassified, Into::into) as i32,
         }
     }
 }
@@ -41,6 +50,7 @@ impl From<NoteRecord> for pb::NoteRecord {
 impl TryFrom<pb::NoteRecord> for NoteRecord {
     type Error = anyhow::Error;
     fn try_from(v: pb::NoteRecord) -> Result<Self, Self::Error> {
+        let source = v.source().try_into().ok();
         Ok(NoteRecord {
             note_commitment: v
                 .note_commitment
@@ -61,10 +71,13 @@ impl TryFrom<pb::NoteRecord> for NoteRecord {
             height_created: v.height_created,
             height_spent: v.height_spent,
             position: v.position.into(),
+            time_created: Some(v.time_created).filter(|time| !time.is_empty()),
+            source,
         })
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for NoteRecord {
     fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
         // This is not a fun time.
@@ -145,6 +158,22 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for NoteRecord {
             .get::<'r, Option<i64>, _>("height_spent")
             .map(|v| v as u64);
         let position = (row.get::<'r, i64, _>("position") as u64).into();
+        // The creation time is only present when the query joins in the block times.
+        let time_created = row
+            .try_get::<'r, Option<String>, _>("time_created")
+            .ok()
+            .flatten();
+        // Notes are only classified once their transactions are fetched.
+        let source = row
+            .try_get::<'r, Option<i64>, _>("source")
+            .ok()
+            .flatten()
+            .map(NoteOrigin::from_code)
+            .transpose()
+            .map_err(|e| sqlx::Error::ColumnDecode {
+                index: "source".to_string(),
+                source: e.into(),
+            })?;
 
         let value = Value { amount, asset_id };
         let note =
@@ -163,6 +192,8 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for NoteRecord {
             position,
             height_created,
             height_spent,
+            time_created,
+            source,
         })
     }
 }
@@ -0,0 +1,231 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::anyhow;
+use penumbra_crypto::{keys::DiversifierIndex, Amount};
+use penumbra_proto::view as pb;
+
+use crate::NoteRecord;
+
+/// How to choose which notes to spend, among the candidates, to cover an amount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Take notes in the order they were recorded until they cover the amount.
+    InOrder,
+    /// Take the largest notes first, spending as few notes as possible.
+    LargestFirst,
+    /// Take the smallest notes first, consolidating dust.
+    SmallestFirst,
+    /// Take the largest notes smaller than what's left to cover, finishing with whichever single
+    /// note covers the rest with the least change, so that as little change as possible is left
+    /// over.
+    MinimizeChange,
+    /// Take notes sent to a single address index if possible, so that a transaction doesn't link
+    /// the wallet's addresses, and otherwise from as few address indices as possible.
+    PrivacyPreferring,
+}
+
+impl Default for SelectionStrategy {
+    fn default() -> Self {
+        SelectionStrategy::InOrder
+    }
+}
+
+impl SelectionStrategy {
+    /// Choose notes from `candidates`, which must all be of the same asset, whose total is at
+    /// least `amount`.
+    pub fn select(
+        self,
+        mut candidates: Vec<NoteRecord>,
+        amount: Amount,
+    ) -> anyhow::Result<Vec<NoteRecord>> {
+        if amount == Amount::zero() {
+            return Ok(Vec::new());
+        }
+
+        match self {
+            SelectionStrategy::InOrder => {}
+            SelectionStrategy::LargestFirst => {
+                candidates.sort_by_key(|record| std::cmp::Reverse(record.note.amount()))
+            }
+            SelectionStrategy::SmallestFirst => {
+                candidates.sort_by_key(|record| record.note.amount())
+            }
+            SelectionStrategy::MinimizeChange => return minimize_change(candidates, amount),
+            SelectionStrategy::PrivacyPreferring => {
+                candidates = by_fewest_indices(candidates, amount)?
+            }
+        }
+
+        take_until(candidates, amount)
+    }
+
+    /// For the strategies which just take notes in some order, the sign by which that order
+    /// sorts notes' amounts: `0` to take them in the order they were recorded, `-1` to take the
+    /// largest first, and `1` to take the smallest first.
+    ///
+    /// Storage uses this to sort the candidates in SQL and stop loading them once they cover the
+    /// amount, rather than loading every one. The other strategies need to see every candidate.
+    pub(crate) fn amount_order(self) -> Option<i64> {
+        match self {
+            SelectionStrategy::InOrder => Some(0),
+            SelectionStrategy::LargestFirst => Some(-1),
+            SelectionStrategy::SmallestFirst => Some(1),
+            SelectionStrategy::MinimizeChange | SelectionStrategy::PrivacyPreferring => None,
+        }
+    }
+}
+
+/// Take notes in order until their total is at least `amount`.
+fn take_until(candidates: Vec<NoteRecord>, amount: Amount) -> anyhow::Result<Vec<NoteRecord>> {
+    let mut total = Amount::zero();
+    let mut selected = Vec::new();
+    for record in candidates {
+        if total >= amount {
+            break;
+        }
+        // We know all the notes are of the same type, so adding raw quantities makes sense.
+        total = total
+            .checked_add(record.note.amount())
+            .ok_or_else(|| anyhow!("total amount of notes overflowed"))?;
+        selected.push(record);
+    }
+
+    if total < amount {
+        return Err(anyhow!(
+            "requested amount of {} exceeds total of {}",
+            amount,
+            total
+        ));
+    }
+
+    Ok(selected)
+}
+
+fn minimize_change(
+    mut candidates: Vec<NoteRecord>,
+    amount: Amount,
+) -> anyhow::Result<Vec<NoteRecord>> {
+    candidates.sort_by_key(|record| record.note.amount());
+
+    let mut remaining = amount;
+    let mut selected = Vec::new();
+    // The complete selection leaving the least change so far, along with that change.
+    let mut best: Option<(Amount, Vec<NoteRecord>)> = None;
+    loop {
+        // The smallest note covering the rest completes the selection with the least change...
+        let covering = candidates
+            .iter()
+            .position(|record| record.note.amount() >= remaining);
+        if let Some(index) = covering {
+            let change = candidates[index]
+                .note
+                .amount()
+                .checked_sub(remaining)
+                .expect("the note covers the rest");
+            if best.as_ref().map_or(true, |(least, _)| change < *least) {
+                let mut selection = selected.clone();
+                selection.push(candidates[index].clone());
+                best = Some((change, selection));
+            }
+            if change == Amount::zero() {
+                break;
+            }
+        }
+
+        // ...but taking the largest note smaller than the rest may lead to a closer fit.
+        let below = covering.unwrap_or(candidates.len());
+        if below == 0 {
+            break;
+        }
+        let record = candidates.remove(below - 1);
+        remaining = remaining
+            .checked_sub(record.note.amount())
+            .expect("the note is smaller than the rest");
+        selected.push(record);
+    }
+
+    best.map(|(_, selection)| selection).ok_or_else(|| {
+        // Every note was taken without covering the amount.
+        anyhow!(
+            "requested amount of {} exceeds total of {}",
+            amount,
+            amount
+                .checked_sub(remaining)
+                .expect("the notes taken are less than the amount")
+        )
+    })
+}
+
+/// Order the candidates so that taking them in order draws on as few address indices as
+/// possible: the notes of the smallest index total covering `amount`, if there is one, and
+/// otherwise the notes of the indices with the largest totals first. Within an index, larger
+/// notes come first.
+fn by_fewest_indices(
+    candidates: Vec<NoteRecord>,
+    amount: Amount,
+) -> anyhow::Result<Vec<NoteRecord>> {
+    let mut by_index = BTreeMap::<DiversifierIndex, (Amount, Vec<NoteRecord>)>::new();
+    for record in candidates {
+        let (total, records) = by_index.entry(record.diversifier_index).or_default();
+        *total = total
+            .checked_add(record.note.amount())
+            .ok_or_else(|| anyhow!("total amount of notes overflowed"))?;
+        records.push(record);
+    }
+
+    let mut groups = by_index.into_values().collect::<Vec<_>>();
+    groups.sort_by_key(|(total, _)| std::cmp::Reverse(*total));
+    if let Some(covering) = groups.iter().rposition(|(total, _)| *total >= amount) {
+        groups.swap(0, covering);
+    }
+
+    Ok(groups
+        .into_iter()
+        .flat_map(|(_, mut records)| {
+            records.sort_by_key(|record| std::cmp::Reverse(record.note.amount()));
+            records
+        })
+        .collect())
+}
+
+impl From<pb::NoteSelectionStrategy> for SelectionStrategy {
+    fn from(strategy: pb::NoteSelectionStrategy) -> Self {
+        match strategy {
+            pb::NoteSelectionStrategy::InOrder => SelectionStrategy::InOrder,
+            pb::NoteSelectionStrategy::LargestFirst => SelectionStrategy::LargestFirst,
+            pb::NoteSelectionStrategy::SmallestFirst => SelectionStrategy::SmallestFirst,
+            pb::NoteSelectionStrategy::MinimizeChange => SelectionStrategy::MinimizeChange,
+            pb::NoteSelectionStrategy::PrivacyPreferring => SelectionStrategy::PrivacyPreferring,
+        }
+    }
+}
+
+impl From<SelectionStrategy> for pb::NoteSelectionStrategy {
+    fn from(strategy: SelectionStrategy) -> Self {
+        match strategy {
+            SelectionStrategy::InOrder => pb::NoteSelectionStrategy::InOrder,
+            SelectionStrategy::LargestFirst => pb::NoteSelectionStrategy::LargestFirst,
+            SelectionStrategy::SmallestFirst => pb::NoteSelectionStrategy::SmallestFirst,
+            SelectionStrategy::MinimizeChange => pb::NoteSelectionStrategy::MinimizeChange,
+            SelectionStrategy::PrivacyPreferring => pb::NoteSelectionStrategy::PrivacyPreferring,
+        }
+    }
+}
+
+impl FromStr for SelectionStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "in-order" => Ok(SelectionStrategy::InOrder),
+            "largest-first" => Ok(SelectionStrategy::LargestFirst),
+            "smallest-first" => Ok(SelectionStrategy::SmallestFirst),
+            "minimize-change" => Ok(SelectionStrategy::MinimizeChange),
+            "privacy-preferring" => Ok(SelectionStrategy::PrivacyPreferring),
+            _ => Err(anyhow!(
+                "unknown note selection strategy {:?}, expected one of in-order, largest-first, smallest-first, minimize-change, or privacy-preferring",
+                s
+            )),
+        }
+    }
+}
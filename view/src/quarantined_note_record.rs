@@ -7,6 +7,7 @@ use penumbra_crypto::{
 use penumbra_proto::{view as pb, Protobuf};
 
 use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
 use sqlx::Row;
 
 /// Corresponds to the QuarantinedNoteRecord proto
@@ -64,6 +65,7 @@ impl TryFrom<pb::QuarantinedNoteRecord> for QuarantinedNoteRecord {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for QuarantinedNoteRecord {
     fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
         // This is not a fun time.
@@ -0,0 +1,70 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long the worker scans between updates of the estimate.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// The weight of the latest window in the estimate, so that it follows changes in the scanning
+/// rate within a few seconds without jumping around from one window to the next.
+const SMOOTHING: f64 = 0.3;
+
+/// An estimate of how many notes the worker trial-decrypts per second, so that clients can show
+/// how quickly a sync is progressing, and how long it will take.
+///
+/// The estimate is a moving average of the rate over each [`WINDOW`] of scanning.
+#[derive(Debug, Default)]
+pub(crate) struct ScanRate {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// When the current window started, or `None` if nothing was scanned yet.
+    window_start: Option<Instant>,
+    /// The notes scanned so far in the current window.
+    window_notes: usize,
+    /// The estimate as of the end of the last window, or `None` if no window has ended yet.
+    notes_per_second: Option<f64>,
+}
+
+impl ScanRate {
+    /// Record that a block with `notes` notes was scanned.
+    pub fn record(&self, notes: usize) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.window_notes += notes;
+        match state.window_start {
+            None => state.window_start = Some(now),
+            Some(start) => {
+                let elapsed = now.duration_since(start);
+                if elapsed >= WINDOW {
+                    let rate = state.window_notes as f64 / elapsed.as_secs_f64();
+                    state.notes_per_second = Some(match state.notes_per_second {
+                        Some(estimate) => SMOOTHING * rate + (1.0 - SMOOTHING) * estimate,
+                        None => rate,
+                    });
+                    state.window_start = Some(now);
+                    state.window_notes = 0;
+                }
+            }
+        }
+    }
+
+    /// The estimated number of notes scanned per second, which is zero until the worker has been
+    /// scanning for a whole window, and decays to zero once it stops scanning.
+    pub fn notes_per_second(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        let estimate = state.notes_per_second.unwrap_or(0.0);
+        // If the worker is waiting for new blocks, the current window may have lasted much longer
+        // than usual, in which case the rate over it is more accurate than the stale estimate.
+        match state.window_start {
+            Some(start) if start.elapsed() >= WINDOW * 2 => {
+                let rate = state.window_notes as f64 / start.elapsed().as_secs_f64();
+                rate.min(estimate)
+            }
+            _ => estimate,
+        }
+    }
+}
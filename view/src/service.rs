@@ -1,6 +1,9 @@
 use std::{
+    collections::BTreeMap,
+    num::{NonZeroU32, NonZeroUsize},
     pin::Pin,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use anyhow::anyhow;
@@ -10,6 +13,7 @@ use futures::stream::{StreamExt, TryStreamExt};
 use penumbra_crypto::{
     asset,
     keys::{DiversifierIndex, FullViewingKey, FullViewingKeyHash},
+    IdentityKey,
 };
 use penumbra_proto::{
     chain as pbp,
@@ -19,12 +23,18 @@ use penumbra_proto::{
 };
 use penumbra_tct::{Commitment, Proof};
 use penumbra_transaction::WitnessData;
-use tokio::sync::{watch, RwLock};
-use tokio_stream::wrappers::WatchStream;
+use tokio::sync::{broadcast, mpsc, oneshot, watch, RwLock};
 use tonic::async_trait;
 use tracing::instrument;
 
-use crate::{Storage, Worker};
+use crate::{
+    scan_rate::ScanRate, sync::ScanPool, throttle::SyncThrottle, witness_cache::WitnessCache,
+    worker::ResetRequest, Account, Authorization, NoteOrigin, Scope, Storage, SyncConnection,
+    Worker, WriterLeaseHeldError,
+};
+
+/// How long a query with a `min_height` waits for the view service to sync to it.
+const MIN_HEIGHT_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// A service that synchronizes private chain state and responds to queries
 /// about it.
@@ -40,7 +50,6 @@ pub struct ViewService {
     // A shared error slot for errors bubbled up by the worker. This is a regular Mutex
     // rather than a Tokio Mutex because it should be uncontended.
     error_slot: Arc<Mutex<Option<anyhow::Error>>>,
-    fvk_hash: FullViewingKeyHash,
     // A copy of the NCT used by the worker task.
     note_commitment_tree: Arc<RwLock<penumbra_tct::Tree>>,
     // The address of the pd+tendermint node.
@@ -49,6 +58,20 @@ pub struct ViewService {
     tendermint_port: u16,
     /// Used to watch for changes to the sync height.
     sync_height_rx: watch::Receiver<u64>,
+    /// Used to watch for changes to the state of the worker's connection to the node.
+    connection_rx: watch::Receiver<SyncConnection>,
+    /// If set, the auth tokens required to call the service.
+    authorization: Option<Arc<Authorization>>,
+    /// Limits the worker's scanning rate, shared with the worker task.
+    sync_throttle: Arc<SyncThrottle>,
+    /// The thread pool the worker trial-decrypts notes on, shared with the worker task.
+    scan_pool: Arc<ScanPool>,
+    /// The worker's estimate of how many notes it scans per second, shared with the worker task.
+    scan_rate: Arc<ScanRate>,
+    /// Auth paths for the notes most likely to be spent, refreshed by a task after each block.
+    witness_cache: Arc<WitnessCache>,
+    /// Used to ask the worker to reset storage, so that it stops scanning while it does.
+    reset_tx: mpsc::Sender<ResetRequest>,
 }
 
 impl ViewService {
@@ -65,6 +88,12 @@ impl ViewService {
         Self::new(storage, node, pd_port, tendermint_port).await
     }
 
+    /// The storage this service scans into, which also keeps data only the local wallet knows,
+    /// like its address book.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
     /// Constructs a new [`ViewService`], spawning a sync task internally.
     ///
     /// The sync task uses the provided `client` to sync with the chain.
@@ -78,56 +107,122 @@ impl ViewService {
         pd_port: u16,
         tendermint_port: u16,
     ) -> Result<Self, anyhow::Error> {
-        let (worker, nct, error_slot, sync_height_rx) =
-            Worker::new(storage.clone(), node.clone(), pd_port).await?;
+        let sync_throttle = Arc::new(SyncThrottle::default());
+        let scan_pool = Arc::new(ScanPool::default());
+        let scan_rate = Arc::new(ScanRate::default());
+        let (worker, nct, error_slot, sync_height_rx, connection_rx, reset_tx) = Worker::new(
+            storage.clone(),
+            node.clone(),
+            pd_port,
+            tendermint_port,
+            sync_throttle.clone(),
+            scan_pool.clone(),
+            scan_rate.clone(),
+        )
+        .await?;
 
         tokio::spawn(worker.run());
 
-        let fvk = storage.full_viewing_key().await?;
-        let fvk_hash = fvk.hash();
+        let witness_cache = Arc::new(WitnessCache::default());
+        tokio::spawn(witness_cache.clone().run(
+            storage.clone(),
+            nct.clone(),
+            sync_height_rx.clone(),
+        ));
 
         Ok(Self {
             storage,
-            fvk_hash,
             error_slot,
             sync_height_rx,
+            connection_rx,
             note_commitment_tree: nct,
             node,
             tendermint_port,
+            authorization: None,
+            sync_throttle,
+            scan_pool,
+            scan_rate,
+            witness_cache,
+            reset_tx,
         })
     }
 
-    async fn check_fvk(&self, fvk: Option<&pbc::FullViewingKeyHash>) -> Result<(), tonic::Status> {
-        // Takes an Option to avoid making the caller handle missing fields,
-        // should error on None or wrong FVK hash
-        match fvk {
-            Some(fvk) => {
-                if fvk != &self.fvk_hash.into() {
-                    return Err(tonic::Status::new(
-                        tonic::Code::InvalidArgument,
-                        "Invalid FVK hash",
-                    ));
-                }
+    /// Limit the worker to scanning at most `max_blocks_per_second` blocks per second, unless a
+    /// client boosts the sync (see [`ViewProtocol::boost_sync`]).
+    ///
+    /// By default, the worker scans as fast as it can. Blocks with nothing to scan aren't limited.
+    pub fn with_sync_limit(self, max_blocks_per_second: NonZeroU32) -> Self {
+        self.sync_throttle
+            .set_max_blocks_per_second(Some(max_blocks_per_second));
+        self
+    }
 
-                Ok(())
-            }
-            None => Err(tonic::Status::new(
-                tonic::Code::InvalidArgument,
-                "Missing FVK",
-            )),
+    /// Trial-decrypt each block's notes on at most `threads` threads.
+    ///
+    /// By default, the worker uses one thread per core.
+    pub fn with_scan_threads(self, threads: NonZeroUsize) -> Self {
+        self.scan_pool.set_threads(Some(threads));
+        self
+    }
+
+    /// Require every request to carry one of the auth tokens in `authorization`.
+    ///
+    /// By default, requests are not authenticated, which is only appropriate when the service is
+    /// not shared, e.g. when it runs in-process.
+    pub fn with_authorization(mut self, authorization: Authorization) -> Self {
+        self.authorization = Some(Arc::new(authorization));
+        self
+    }
+
+    fn check_scopes<T>(
+        &self,
+        request: &tonic::Request<T>,
+        required: &[Scope],
+    ) -> Result<(), tonic::Status> {
+        match &self.authorization {
+            Some(authorization) => authorization.check(request, required),
+            None => Ok(()),
         }
     }
 
+    /// Find the account a request is for, by the hash of its full viewing key.
+    async fn check_fvk(
+        &self,
+        fvk: Option<&pbc::FullViewingKeyHash>,
+    ) -> Result<Account, tonic::Status> {
+        // Takes an Option to avoid making the caller handle missing fields,
+        // should error on None or unknown FVK hash
+        let fvk_hash = match fvk {
+            Some(fvk) => FullViewingKeyHash::try_from(fvk.clone()).map_err(|_| {
+                tonic::Status::new(tonic::Code::InvalidArgument, "Invalid FVK hash")
+            })?,
+            None => {
+                return Err(tonic::Status::new(
+                    tonic::Code::InvalidArgument,
+                    "Missing FVK",
+                ))
+            }
+        };
+
+        self.storage
+            .account(&fvk_hash)
+            .await
+            .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?
+            .ok_or_else(|| tonic::Status::new(tonic::Code::InvalidArgument, "Invalid FVK hash"))
+    }
+
     async fn check_worker(&self) -> Result<(), tonic::Status> {
         // If the shared error slot is set, then an error has occurred in the worker
         // that we should bubble up.
-        if self.error_slot.lock().unwrap().is_some() {
+        if let Some(e) = self.error_slot.lock().unwrap().as_ref() {
+            // Unless another worker is scanning into the same storage, which is still readable,
+            // just not written by this service's worker.
+            if e.is::<WriterLeaseHeldError>() {
+                return Ok(());
+            }
             return Err(tonic::Status::new(
                 tonic::Code::Internal,
-                format!(
-                    "Worker failed: {}",
-                    self.error_slot.lock().unwrap().as_ref().unwrap()
-                ),
+                format!("Worker failed: {}", e),
             ));
         }
 
@@ -137,6 +232,19 @@ impl ViewService {
         Ok(())
     }
 
+    /// Wait until the storage has synced to `min_height`, if it's set, so that the query sees
+    /// every block up to it.
+    async fn wait_for_min_height(&self, min_height: u64) -> Result<(), tonic::Status> {
+        if min_height == 0 {
+            return Ok(());
+        }
+
+        self.storage
+            .wait_for_height(min_height, MIN_HEIGHT_TIMEOUT)
+            .await
+            .map_err(|e| tonic::Status::deadline_exceeded(e.to_string()))
+    }
+
     /// Return the latest block height known by the fullnode or its peers, as
     /// well as whether the fullnode is caught up with that height.
     #[instrument(skip(self))]
@@ -216,6 +324,10 @@ impl ViewService {
         Ok(StatusResponse {
             sync_height,
             catching_up,
+            connection: pb::SyncConnection::from(*self.connection_rx.borrow()) as i32,
+            latest_known_block_height,
+            notes_per_second: self.scan_rate.notes_per_second(),
+            sync_source: self.storage.sync_source_health().await?.map(Into::into),
         })
     }
 }
@@ -224,9 +336,23 @@ impl ViewService {
 impl ViewProtocol for ViewService {
     type NotesStream =
         Pin<Box<dyn futures::Stream<Item = Result<pb::NoteRecord, tonic::Status>> + Send>>;
+    type NoteStreamStream =
+        Pin<Box<dyn futures::Stream<Item = Result<pb::NoteStreamResponse, tonic::Status>> + Send>>;
+    type BalancesStream =
+        Pin<Box<dyn futures::Stream<Item = Result<pb::BalancesResponse, tonic::Status>> + Send>>;
     type QuarantinedNotesStream = Pin<
         Box<dyn futures::Stream<Item = Result<pb::QuarantinedNoteRecord, tonic::Status>> + Send>,
     >;
+    type QuarantinedBalancesStream = Pin<
+        Box<
+            dyn futures::Stream<Item = Result<pb::QuarantinedBalancesResponse, tonic::Status>>
+                + Send,
+        >,
+    >;
+    type TransactionInfoStream =
+        Pin<Box<dyn futures::Stream<Item = Result<pb::TransactionInfo, tonic::Status>> + Send>>;
+    type CompactBlockCacheStream =
+        Pin<Box<dyn futures::Stream<Item = Result<pbp::CompactBlock, tonic::Status>> + Send>>;
     type AssetsStream =
         Pin<Box<dyn futures::Stream<Item = Result<pbc::Asset, tonic::Status>> + Send>>;
     type StatusStreamStream = Pin<
@@ -238,6 +364,7 @@ impl ViewProtocol for ViewService {
         request: tonic::Request<pb::NoteByCommitmentRequest>,
     ) -> Result<tonic::Response<pb::NoteRecord>, tonic::Status> {
         self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::ReadBalances])?;
         self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
 
         let request = request.into_inner();
@@ -260,11 +387,89 @@ impl ViewProtocol for ViewService {
         )))
     }
 
+    async fn note_by_nullifier(
+        &self,
+        request: tonic::Request<pb::NoteByNullifierRequest>,
+    ) -> Result<tonic::Response<pb::NoteRecord>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::ReadBalances])?;
+        self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+
+        let request = request.into_inner();
+
+        let nullifier = request
+            .nullifier
+            .ok_or_else(|| tonic::Status::failed_precondition("Missing nullifier in request"))?
+            .try_into()
+            .map_err(|_| tonic::Status::failed_precondition("Invalid nullifier in request"))?;
+
+        Ok(tonic::Response::new(pb::NoteRecord::from(
+            self.storage
+                .note_by_nullifier(nullifier, request.await_detection)
+                .await
+                .map_err(|e| tonic::Status::internal(format!("error: {}", e)))?,
+        )))
+    }
+
+    async fn reserve_notes(
+        &self,
+        request: tonic::Request<pb::ReserveNotesRequest>,
+    ) -> Result<tonic::Response<pb::ReserveNotesResponse>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::PlanTransactions])?;
+        self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+
+        let request = request.into_inner();
+
+        let note_commitments = request
+            .note_commitments
+            .into_iter()
+            .map(Commitment::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                tonic::Status::failed_precondition("Invalid note commitment in request")
+            })?;
+
+        self.storage
+            .reserve_notes(&request.plan_id, &note_commitments)
+            .await
+            .map_err(|e| tonic::Status::aborted(format!("error reserving notes: {}", e)))?;
+
+        Ok(tonic::Response::new(pb::ReserveNotesResponse {}))
+    }
+
+    async fn release_notes(
+        &self,
+        request: tonic::Request<pb::ReleaseNotesRequest>,
+    ) -> Result<tonic::Response<pb::ReleaseNotesResponse>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::PlanTransactions])?;
+        self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+
+        let note_commitments = request
+            .into_inner()
+            .note_commitments
+            .into_iter()
+            .map(Commitment::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                tonic::Status::failed_precondition("Invalid note commitment in request")
+            })?;
+
+        self.storage
+            .release_notes(&note_commitments)
+            .await
+            .map_err(|e| tonic::Status::internal(format!("error releasing notes: {}", e)))?;
+
+        Ok(tonic::Response::new(pb::ReleaseNotesResponse {}))
+    }
+
     async fn status(
         &self,
         request: tonic::Request<pb::StatusRequest>,
     ) -> Result<tonic::Response<pb::StatusResponse>, tonic::Status> {
         self.check_worker().await?;
+        self.check_scopes(&request, &[])?;
         self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
 
         Ok(tonic::Response::new(self.status().await.map_err(|e| {
@@ -277,6 +482,7 @@ impl ViewProtocol for ViewService {
         request: tonic::Request<pb::StatusStreamRequest>,
     ) -> Result<tonic::Response<Self::StatusStreamStream>, tonic::Status> {
         self.check_worker().await?;
+        self.check_scopes(&request, &[])?;
         self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
 
         let (latest_known_block_height, _) =
@@ -287,16 +493,33 @@ impl ViewProtocol for ViewService {
                 ))
             })?;
 
-        // Create a stream of sync height updates from our worker, and send them to the client
-        // until we've reached the latest known block height at the time the request was made.
-        let mut sync_height_stream = WatchStream::new(self.sync_height_rx.clone());
+        // Send the client the sync height and connection state each time our worker updates
+        // either, until it's connected and has reached the latest known block height at the time
+        // the request was made.
+        let mut sync_height_rx = self.sync_height_rx.clone();
+        let mut connection_rx = self.connection_rx.clone();
+        let scan_rate = self.scan_rate.clone();
         let stream = try_stream! {
-            while let Some(sync_height) = sync_height_stream.next().await {
+            loop {
+                let sync_height = *sync_height_rx.borrow_and_update();
+                let connection = *connection_rx.borrow_and_update();
                 yield pb::StatusStreamResponse {
                     latest_known_block_height,
                     sync_height,
+                    connection: pb::SyncConnection::from(connection) as i32,
+                    notes_per_second: scan_rate.notes_per_second(),
+                };
+                if sync_height >= latest_known_block_height
+                    && connection == SyncConnection::Connected
+                {
+                    break;
+                }
+                let changed = tokio::select! {
+                    changed = sync_height_rx.changed() => changed,
+                    changed = connection_rx.changed() => changed,
                 };
-                if sync_height >= latest_known_block_height {
+                // The worker has stopped.
+                if changed.is_err() {
                     break;
                 }
             }
@@ -310,7 +533,18 @@ impl ViewProtocol for ViewService {
         request: tonic::Request<pb::NotesRequest>,
     ) -> Result<tonic::Response<Self::NotesStream>, tonic::Status> {
         self.check_worker().await?;
-        self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+        // Spent notes and creation heights reveal the wallet's history, not just its balance.
+        if request.get_ref().include_spent
+            || !request.get_ref().created_after.is_empty()
+            || !request.get_ref().created_before.is_empty()
+        {
+            self.check_scopes(&request, &[Scope::ReadBalances, Scope::ReadHistory])?;
+        } else {
+            self.check_scopes(&request, &[Scope::ReadBalances])?;
+        }
+        let account = self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+        self.wait_for_min_height(request.get_ref().min_height)
+            .await?;
 
         let include_spent = request.get_ref().include_spent;
         let asset_id = request
@@ -327,11 +561,36 @@ impl ViewProtocol for ViewService {
             .map(DiversifierIndex::try_from)
             .map_or(Ok(None), |v| v.map(Some))
             .map_err(|_| tonic::Status::invalid_argument("invalid diversifier index"))?;
-        let amount_to_spend = request.get_ref().amount_to_spend;
+        let amount_to_spend = request.get_ref().amount_to_spend.into();
+        let created_after = Some(request.get_ref().created_after.clone()).filter(|t| !t.is_empty());
+        let created_before =
+            Some(request.get_ref().created_before.clone()).filter(|t| !t.is_empty());
+        let sources = request
+            .get_ref()
+            .sources
+            .iter()
+            .map(|&source| {
+                pb::NoteOrigin::from_i32(source)
+                    // Unclassified notes are matched by `None`.
+                    .map(|source| NoteOrigin::try_from(source).ok())
+                    .ok_or_else(|| tonic::Status::invalid_argument("invalid note source"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         let notes = self
             .storage
-            .notes(include_spent, asset_id, diversifier_index, amount_to_spend)
+            .notes(
+                include_spent,
+                asset_id,
+                diversifier_index,
+                amount_to_spend,
+                request.get_ref().selection_strategy().into(),
+                created_after,
+                created_before,
+                request.get_ref().exclude_reserved,
+                Some(account.index),
+                &sources,
+            )
             .await
             .map_err(|e| tonic::Status::unavailable(format!("error fetching notes: {}", e)))?;
 
@@ -350,16 +609,139 @@ impl ViewProtocol for ViewService {
         ))
     }
 
+    async fn note_stream(
+        &self,
+        request: tonic::Request<pb::NoteStreamRequest>,
+    ) -> Result<tonic::Response<Self::NoteStreamStream>, tonic::Status> {
+        self.check_worker().await?;
+        // Spends reveal the wallet's history, not just its balance.
+        self.check_scopes(&request, &[Scope::ReadBalances, Scope::ReadHistory])?;
+        let account = self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+
+        let mut rx = self.storage.subscribe_note_events();
+        let stream = try_stream! {
+            loop {
+                match rx.recv().await {
+                    Ok((index, event)) => {
+                        if index.map_or(true, |index| index == account.index) {
+                            yield event.into()
+                        }
+                    }
+                    // The client missed some events, so it must query for notes to catch up.
+                    Err(broadcast::error::RecvError::Lagged(missed)) => Err(anyhow!(
+                        "fell behind by {} note events, query for notes to catch up",
+                        missed
+                    ))?,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+
+        Ok(tonic::Response::new(
+            stream
+                .map_err(|e: anyhow::Error| {
+                    tonic::Status::data_loss(format!("error streaming notes: {}", e))
+                })
+                .boxed(),
+        ))
+    }
+
+    async fn balances(
+        &self,
+        request: tonic::Request<pb::BalancesRequest>,
+    ) -> Result<tonic::Response<Self::BalancesStream>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::ReadBalances])?;
+        let account = self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+        self.wait_for_min_height(request.get_ref().min_height)
+            .await?;
+
+        let diversifier_index = request
+            .get_ref()
+            .diversifier_index
+            .to_owned()
+            .map(DiversifierIndex::try_from)
+            .map_or(Ok(None), |v| v.map(Some))
+            .map_err(|_| tonic::Status::invalid_argument("invalid diversifier index"))?;
+
+        let balances: Vec<(Option<DiversifierIndex>, BTreeMap<asset::Id, u64>)> =
+            if request.get_ref().by_diversifier_index {
+                if diversifier_index.is_some() {
+                    return Err(tonic::Status::invalid_argument(
+                        "cannot both filter and group by diversifier index",
+                    ));
+                }
+                self.storage
+                    .balances_by_index(Some(account.index))
+                    .await
+                    .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?
+                    .into_iter()
+                    .map(|(index, balances)| (Some(index), balances))
+                    .collect()
+            } else {
+                let balances = self
+                    .storage
+                    .balances(diversifier_index, Some(account.index))
+                    .await
+                    .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?;
+                vec![(None, balances)]
+            };
+
+        let stream = try_stream! {
+            for (diversifier_index, balances) in balances {
+                for (asset_id, amount) in balances {
+                    yield pb::BalancesResponse {
+                        asset_id: Some(asset_id.into()),
+                        amount,
+                        diversifier_index: diversifier_index.map(Into::into),
+                    }
+                }
+            }
+        };
+
+        Ok(tonic::Response::new(
+            stream
+                .map_err(|e: anyhow::Error| {
+                    tonic::Status::unavailable(format!("database error: {}", e))
+                })
+                .boxed(),
+        ))
+    }
+
     async fn quarantined_notes(
         &self,
         request: tonic::Request<pb::QuarantinedNotesRequest>,
     ) -> Result<tonic::Response<Self::QuarantinedNotesStream>, tonic::Status> {
         self.check_worker().await?;
-        self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+        self.check_scopes(&request, &[Scope::ReadBalances])?;
+        let account = self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+        self.wait_for_min_height(request.get_ref().min_height)
+            .await?;
+
+        let identity_key = request
+            .get_ref()
+            .identity_key
+            .to_owned()
+            .map(IdentityKey::try_from)
+            .map_or(Ok(None), |v| v.map(Some))
+            .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+        let max_unbonding_epoch = request.get_ref().max_unbonding_epoch;
+        let asset_id = request
+            .get_ref()
+            .asset_id
+            .to_owned()
+            .map(asset::Id::try_from)
+            .map_or(Ok(None), |v| v.map(Some))
+            .map_err(|_| tonic::Status::invalid_argument("invalid asset id"))?;
 
         let notes = self
             .storage
-            .quarantined_notes()
+            .quarantined_notes(
+                Some(account.index),
+                identity_key.as_ref(),
+                max_unbonding_epoch,
+                asset_id,
+            )
             .await
             .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?;
 
@@ -378,11 +760,166 @@ impl ViewProtocol for ViewService {
         ))
     }
 
+    async fn quarantined_balances(
+        &self,
+        request: tonic::Request<pb::QuarantinedBalancesRequest>,
+    ) -> Result<tonic::Response<Self::QuarantinedBalancesStream>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::ReadBalances])?;
+        let account = self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+        self.wait_for_min_height(request.get_ref().min_height)
+            .await?;
+
+        let balances = self
+            .storage
+            .quarantined_balance_by_validator(Some(account.index))
+            .await
+            .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?;
+
+        let stream = try_stream! {
+            for (identity_key, by_epoch) in balances {
+                for (unbonding_epoch, by_asset) in by_epoch {
+                    for (asset_id, amount) in by_asset {
+                        yield pb::QuarantinedBalancesResponse {
+                            identity_key: Some(identity_key.into()),
+                            unbonding_epoch,
+                            asset_id: Some(asset_id.into()),
+                            amount,
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(tonic::Response::new(
+            stream
+                .map_err(|e: anyhow::Error| {
+                    tonic::Status::unavailable(format!("database error: {}", e))
+                })
+                .boxed(),
+        ))
+    }
+
+    async fn transaction_info(
+        &self,
+        request: tonic::Request<pb::TransactionInfoRequest>,
+    ) -> Result<tonic::Response<Self::TransactionInfoStream>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::ReadHistory])?;
+        let account = self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+
+        let start_height = request.get_ref().start_height.unwrap_or(0);
+        let end_height = request.get_ref().end_height.unwrap_or(u64::MAX);
+        let transactions = self
+            .storage
+            .transactions(start_height..=end_height, Some(account.index))
+            .await
+            .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?;
+
+        let stream = try_stream! {
+            for transaction in transactions {
+                yield transaction.into()
+            }
+        };
+
+        Ok(tonic::Response::new(
+            stream
+                .map_err(|e: anyhow::Error| {
+                    tonic::Status::unavailable(format!("database error: {}", e))
+                })
+                .boxed(),
+        ))
+    }
+
+    async fn compact_block_cache(
+        &self,
+        request: tonic::Request<pb::CompactBlockCacheRequest>,
+    ) -> Result<tonic::Response<Self::CompactBlockCacheStream>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::ManageStorage])?;
+
+        let start_height = request.get_ref().start_height.unwrap_or(0);
+        let end_height = request.get_ref().end_height.unwrap_or(u64::MAX);
+        let blocks = self
+            .storage
+            .cached_compact_blocks(start_height..=end_height)
+            .await
+            .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?;
+
+        let stream = try_stream! {
+            for block in blocks {
+                yield block.into()
+            }
+        };
+
+        Ok(tonic::Response::new(
+            stream
+                .map_err(|e: anyhow::Error| {
+                    tonic::Status::unavailable(format!("database error: {}", e))
+                })
+                .boxed(),
+        ))
+    }
+
+    async fn reset_to_height(
+        &self,
+        request: tonic::Request<pb::ResetToHeightRequest>,
+    ) -> Result<tonic::Response<pb::ResetToHeightResponse>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::ManageStorage])?;
+        // Only the worker holding the writer lease can reset the storage.
+        if let Some(e) = self.error_slot.lock().unwrap().as_ref() {
+            return Err(tonic::Status::failed_precondition(e.to_string()));
+        }
+
+        let (reply, reply_rx) = oneshot::channel();
+        self.reset_tx
+            .send(ResetRequest {
+                height: request.get_ref().height,
+                reply,
+            })
+            .await
+            .map_err(|_| tonic::Status::unavailable("view worker is not running"))?;
+        let resume_height = reply_rx
+            .await
+            .map_err(|_| tonic::Status::unavailable("view worker stopped before resetting"))?
+            .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?;
+
+        Ok(tonic::Response::new(pb::ResetToHeightResponse {
+            resume_height,
+        }))
+    }
+
+    async fn accounts(
+        &self,
+        request: tonic::Request<pb::AccountsRequest>,
+    ) -> Result<tonic::Response<pb::AccountsResponse>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[])?;
+
+        let accounts = self
+            .storage
+            .accounts()
+            .await
+            .map_err(|e| tonic::Status::unavailable(format!("database error: {}", e)))?;
+
+        Ok(tonic::Response::new(pb::AccountsResponse {
+            accounts: accounts
+                .into_iter()
+                .map(|account| pb::AccountInfo {
+                    fvk_hash: Some(account.fvk_hash().into()),
+                    label: account.label,
+                })
+                .collect(),
+        }))
+    }
+
     async fn assets(
         &self,
-        _request: tonic::Request<pb::AssetRequest>,
+        request: tonic::Request<pb::AssetRequest>,
     ) -> Result<tonic::Response<Self::AssetsStream>, tonic::Status> {
         self.check_worker().await?;
+        self.check_scopes(&request, &[])?;
 
         // Fetch assets from storage.
         let assets = self
@@ -411,6 +948,7 @@ impl ViewProtocol for ViewService {
         request: tonic::Request<pb::WitnessRequest>,
     ) -> Result<tonic::Response<pbt::WitnessData>, tonic::Status> {
         self.check_worker().await?;
+        self.check_scopes(&request, &[Scope::PlanTransactions])?;
         self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
 
         // Acquire a read lock for the NCT that will live for the entire request,
@@ -420,7 +958,8 @@ impl ViewProtocol for ViewService {
         // Read the NCT root
         let anchor = nct.root();
 
-        // Obtain an auth path for each requested note commitment
+        // Obtain an auth path for each requested note commitment, from the cache if it was
+        // refreshed since the last block
         let requested_note_commitments = request
             .get_ref()
             .note_commitments
@@ -436,7 +975,7 @@ impl ViewProtocol for ViewService {
         let auth_paths: Vec<Proof> = requested_note_commitments
             .iter()
             .map(|nc| {
-                nct.witness(*nc).ok_or_else(|| {
+                self.witness_cache.witness(&nct, *nc).ok_or_else(|| {
                     tonic::Status::new(tonic::Code::InvalidArgument, "Note commitment missing")
                 })
             })
@@ -452,11 +991,27 @@ impl ViewProtocol for ViewService {
         Ok(tonic::Response::new(witness_data.into()))
     }
 
+    async fn boost_sync(
+        &self,
+        request: tonic::Request<pb::BoostSyncRequest>,
+    ) -> Result<tonic::Response<pb::BoostSyncResponse>, tonic::Status> {
+        self.check_worker().await?;
+        self.check_scopes(&request, &[])?;
+        self.check_fvk(request.get_ref().fvk_hash.as_ref()).await?;
+
+        let duration = Duration::from_secs(request.get_ref().duration_secs);
+        tracing::debug!(?duration, "boosting sync");
+        self.sync_throttle.boost(duration);
+
+        Ok(tonic::Response::new(pb::BoostSyncResponse {}))
+    }
+
     async fn chain_params(
         &self,
-        _request: tonic::Request<pb::ChainParamsRequest>,
+        request: tonic::Request<pb::ChainParamsRequest>,
     ) -> Result<tonic::Response<pbp::ChainParams>, tonic::Status> {
         self.check_worker().await?;
+        self.check_scopes(&request, &[])?;
 
         let params = self.storage.chain_params().await.map_err(|e| {
             tonic::Status::unavailable(format!("error getting chain params: {}", e))
@@ -0,0 +1,108 @@
+//! Snapshots of view storage, which restore a synced wallet on another machine, or from a backup,
+//! without rescanning the chain.
+//!
+//! A snapshot is the [`SNAPSHOT_MAGIC`] bytes, then the [`SNAPSHOT_VERSION`] as a little-endian
+//! `u16`, then the bincode encoding of a [`Snapshot`]. Only what can't be fetched again after a
+//! restore is kept: the transactions and block times of our notes are refetched by the worker.
+
+use anyhow::anyhow;
+use penumbra_crypto::keys::FullViewingKeyHash;
+use serde::{Deserialize, Serialize};
+
+/// The bytes every snapshot starts with.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"PVSNAPSH";
+
+/// The version of the snapshot format, bumped whenever [`Snapshot`] changes.
+const SNAPSHOT_VERSION: u16 = 2;
+
+/// Everything scanned into view storage as of one block, with each row as it is stored in the
+/// database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    /// The hash of each account's full viewing key, in the order of the accounts.
+    pub fvk_hashes: Vec<FullViewingKeyHash>,
+    /// The protobuf encoding of the chain parameters.
+    pub chain_params: Vec<u8>,
+    /// The height scanned to, or -1 if nothing was scanned.
+    pub sync_height: i64,
+    /// The bincode encoding of the note commitment tree as of `sync_height`.
+    pub nct: Vec<u8>,
+    pub notes: Vec<NoteRow>,
+    pub quarantined_notes: Vec<QuarantinedNoteRow>,
+    pub quarantined_nullifiers: Vec<QuarantinedNullifierRow>,
+    pub assets: Vec<AssetRow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub(crate) struct NoteRow {
+    pub note_commitment: Vec<u8>,
+    pub height_spent: Option<i64>,
+    pub height_created: i64,
+    pub diversifier: Vec<u8>,
+    pub amount: i64,
+    pub asset_id: Vec<u8>,
+    pub transmission_key: Vec<u8>,
+    pub blinding_factor: Vec<u8>,
+    pub diversifier_index: Vec<u8>,
+    pub nullifier: Vec<u8>,
+    pub position: i64,
+    pub account: i64,
+    pub source: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub(crate) struct QuarantinedNoteRow {
+    pub note_commitment: Vec<u8>,
+    pub height_created: i64,
+    pub diversifier: Vec<u8>,
+    pub amount: i64,
+    pub asset_id: Vec<u8>,
+    pub transmission_key: Vec<u8>,
+    pub blinding_factor: Vec<u8>,
+    pub diversifier_index: Vec<u8>,
+    pub unbonding_epoch: i64,
+    pub identity_key: Vec<u8>,
+    pub account: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub(crate) struct QuarantinedNullifierRow {
+    pub nullifier: Vec<u8>,
+    pub identity_key: Vec<u8>,
+    pub height: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub(crate) struct AssetRow {
+    pub asset_id: Vec<u8>,
+    pub denom: String,
+}
+
+impl Snapshot {
+    pub fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let bytes = bytes
+            .strip_prefix(SNAPSHOT_MAGIC)
+            .ok_or_else(|| anyhow!("not a view storage snapshot"))?;
+        if bytes.len() < 2 {
+            return Err(anyhow!("view storage snapshot is truncated"));
+        }
+        let (version, bytes) = bytes.split_at(2);
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        if version != SNAPSHOT_VERSION {
+            return Err(anyhow!(
+                "view storage snapshot has version {}, but only version {} is supported",
+                version,
+                SNAPSHOT_VERSION
+            ));
+        }
+
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
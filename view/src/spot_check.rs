@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, Result};
+use penumbra_chain::CompactBlock;
+use penumbra_crypto::{asset, FullViewingKey, Note, Nullifier};
+use penumbra_proto::client::{
+    oblivious::{
+        oblivious_query_client::ObliviousQueryClient, ChainParamsRequest, CompactBlockRangeRequest,
+    },
+    specific::{specific_query_client::SpecificQueryClient, KeyValueRequest},
+};
+use penumbra_tct as tct;
+use penumbra_transaction::WitnessData;
+use rand::seq::SliceRandom;
+use tonic::transport::Channel;
+use tracing::instrument;
+
+use crate::NoteRecord;
+
+/// Spot checks the honesty of a (possibly remote) view service, by re-deriving a random sample of
+/// the data it serves from compact blocks fetched from an independent `pd` node.
+///
+/// This is intended for clients which delegate scanning to a shared `pviewd` they do not fully
+/// trust: it cannot prove that the view service is honest, but a view service which serves
+/// fabricated notes, hides spends, or witnesses against a fake anchor will eventually be caught.
+///
+/// Everything is checked against data fetched from the independent node, including the chain ID,
+/// so that a view service can't point the checks at a chain of its own making.
+#[derive(Debug)]
+pub struct SpotCheck {
+    fvk: FullViewingKey,
+    chain_id: String,
+    oblivious_client: ObliviousQueryClient<Channel>,
+    specific_client: SpecificQueryClient<Channel>,
+    sample_size: usize,
+}
+
+impl SpotCheck {
+    /// Connects to the independent `pd` node at `node:pd_port`, which should not be the node the
+    /// view service being checked is syncing from.
+    ///
+    /// Each check examines at most `sample_size` randomly chosen items.
+    pub async fn connect(
+        fvk: FullViewingKey,
+        node: String,
+        pd_port: u16,
+        sample_size: usize,
+    ) -> Result<Self> {
+        let mut oblivious_client =
+            ObliviousQueryClient::connect(format!("http://{}:{}", node, pd_port)).await?;
+        let specific_client =
+            SpecificQueryClient::connect(format!("http://{}:{}", node, pd_port)).await?;
+
+        let chain_id = oblivious_client
+            .chain_params(tonic::Request::new(ChainParamsRequest {
+                chain_id: String::new(),
+            }))
+            .await?
+            .into_inner()
+            .chain_id;
+
+        Ok(Self {
+            fvk,
+            chain_id,
+            oblivious_client,
+            specific_client,
+            sample_size,
+        })
+    }
+
+    /// Checks that the view service is serving data for the same chain as the independent node.
+    pub fn check_chain_id(&self, chain_id: &str) -> Result<()> {
+        if chain_id != self.chain_id {
+            return Err(discrepancy(format!(
+                "view service is on chain {:?}, but the independent node is on chain {:?}",
+                chain_id, self.chain_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks a random sample of the given note records against the chain, returning an error
+    /// describing the first discrepancy found.
+    ///
+    /// For each sampled note, this checks that the note was created in the block at which the view
+    /// service claims it was created, that it decrypts under our full viewing key, and that its
+    /// nullifier is correctly derived; if the note is claimed to be spent, this also checks that its
+    /// nullifier was revealed in the block at which it was claimed to be spent.
+    #[instrument(skip(self, notes))]
+    pub async fn check_notes(&mut self, notes: &[NoteRecord]) -> Result<()> {
+        let sample = notes.choose_multiple(&mut rand::thread_rng(), self.sample_size);
+
+        for record in sample {
+            let block = self.compact_block(record.height_created).await?;
+
+            let payload = block
+                .note_payloads
+                .iter()
+                .find(|payload| payload.note_commitment == record.note_commitment)
+                .ok_or_else(|| {
+                    discrepancy(format!(
+                        "note commitment {} is not in the block at height {}",
+                        record.note_commitment, record.height_created
+                    ))
+                })?;
+
+            let note = Note::decrypt(
+                payload.encrypted_note.as_ref(),
+                self.fvk.incoming(),
+                &payload.ephemeral_key,
+            )
+            .map_err(|_| {
+                discrepancy(format!(
+                    "note commitment {} does not decrypt under our viewing key",
+                    record.note_commitment
+                ))
+            })?;
+
+            if note != record.note {
+                return Err(discrepancy(format!(
+                    "note with commitment {} does not match the note on chain",
+                    record.note_commitment
+                )));
+            }
+
+            if Nullifier::derive(
+                self.fvk.nullifier_key(),
+                record.position,
+                &record.note_commitment,
+            ) != record.nullifier
+            {
+                return Err(discrepancy(format!(
+                    "nullifier for note commitment {} is incorrectly derived",
+                    record.note_commitment
+                )));
+            }
+
+            if let Some(height_spent) = record.height_spent {
+                let block = self.compact_block(height_spent).await?;
+
+                let quarantined_nullifiers = block
+                    .quarantined
+                    .into_iter()
+                    .flat_map(|(_, scheduled)| scheduled)
+                    .flat_map(|(_, unbonding)| unbonding.nullifiers);
+
+                if !block
+                    .nullifiers
+                    .into_iter()
+                    .chain(quarantined_nullifiers)
+                    .any(|nullifier| nullifier == record.nullifier)
+                {
+                    return Err(discrepancy(format!(
+                        "nullifier for note commitment {} is not in the block at height {}",
+                        record.note_commitment, height_spent
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the balances the view service reports against the notes it serves, returning an
+    /// error describing the first discrepancy found.
+    ///
+    /// Each reported balance must be the total of the unspent notes of its asset, and a random
+    /// sample of the notes claimed to be unspent must not have had their nullifiers revealed,
+    /// according to the independent node.
+    #[instrument(skip(self, notes, balances))]
+    pub async fn check_balances(
+        &mut self,
+        notes: &[NoteRecord],
+        balances: &BTreeMap<asset::Id, u64>,
+    ) -> Result<()> {
+        let totals = unspent_totals(notes)?;
+        if let Some(message) = balance_discrepancy(&totals, balances) {
+            return Err(discrepancy(message));
+        }
+
+        let unspent = notes
+            .iter()
+            .filter(|record| record.height_spent.is_none())
+            .collect::<Vec<_>>();
+        for record in unspent.choose_multiple(&mut rand::thread_rng(), self.sample_size) {
+            if self.nullifier_spent(record.nullifier).await? {
+                return Err(discrepancy(format!(
+                    "note commitment {} is reported unspent, but its nullifier has been revealed",
+                    record.note_commitment
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks a random sample of the authentication paths in the given witness data, returning an
+    /// error describing the first discrepancy found.
+    ///
+    /// This checks that the anchor is a valid note commitment tree root according to the
+    /// independent node, and that each sampled authentication path verifies against that anchor.
+    #[instrument(skip(self, witness_data))]
+    pub async fn check_witness(&mut self, witness_data: &WitnessData) -> Result<()> {
+        self.check_anchor(witness_data.anchor).await?;
+
+        let sample = witness_data
+            .note_commitment_proofs
+            .choose_multiple(&mut rand::thread_rng(), self.sample_size);
+
+        verify_proofs(witness_data.anchor, sample)
+    }
+
+    /// Checks that the given anchor is a valid note commitment tree root according to the
+    /// independent node.
+    #[instrument(skip(self))]
+    pub async fn check_anchor(&mut self, anchor: tct::Root) -> Result<()> {
+        let result = self
+            .specific_client
+            .key_value(KeyValueRequest {
+                chain_id: self.chain_id.clone(),
+                key: format!("shielded_pool/valid_anchors/{}", anchor).into_bytes(),
+                ..Default::default()
+            })
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(status) if status.code() == tonic::Code::NotFound => Err(discrepancy(format!(
+                "anchor {} is not a valid note commitment tree root",
+                anchor
+            ))),
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    /// Whether the independent node has seen `nullifier` revealed, by a spend or an undelegation.
+    async fn nullifier_spent(&mut self, nullifier: Nullifier) -> Result<bool> {
+        for key in [
+            format!("shielded_pool/spent_nullifiers/{}", nullifier),
+            format!("shielded_pool/quarantined_spent_nullifiers/{}", nullifier),
+        ] {
+            let result = self
+                .specific_client
+                .key_value(KeyValueRequest {
+                    chain_id: self.chain_id.clone(),
+                    key: key.into_bytes(),
+                    ..Default::default()
+                })
+                .await;
+
+            match result {
+                Ok(_) => return Ok(true),
+                Err(status) if status.code() == tonic::Code::NotFound => {}
+                Err(status) => return Err(status.into()),
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn compact_block(&mut self, height: u64) -> Result<CompactBlock> {
+        let mut stream = self
+            .oblivious_client
+            .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
+                chain_id: self.chain_id.clone(),
+                start_height: height,
+                end_height: height,
+                keep_alive: false,
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+
+        let block = stream
+            .message()
+            .await?
+            .ok_or_else(|| anyhow!("independent node did not return block {}", height))?;
+
+        CompactBlock::try_from(block)
+    }
+}
+
+/// The total amount of each asset in the unspent notes among `notes`.
+fn unspent_totals(notes: &[NoteRecord]) -> Result<BTreeMap<asset::Id, u64>> {
+    let mut totals = BTreeMap::<asset::Id, u64>::new();
+    for record in notes.iter().filter(|record| record.height_spent.is_none()) {
+        let asset_id = record.note.asset_id();
+        let total = totals.entry(asset_id).or_default();
+        *total = total
+            .checked_add(record.note.amount().value())
+            .ok_or_else(|| discrepancy(format!("unspent notes of asset {} overflow", asset_id)))?;
+    }
+
+    Ok(totals)
+}
+
+/// Describes the first asset whose reported balance isn't the total of its unspent notes, if any.
+fn balance_discrepancy(
+    totals: &BTreeMap<asset::Id, u64>,
+    balances: &BTreeMap<asset::Id, u64>,
+) -> Option<String> {
+    let asset_ids = totals
+        .keys()
+        .chain(balances.keys())
+        .collect::<BTreeSet<_>>();
+    for asset_id in asset_ids {
+        let total = totals.get(asset_id).copied().unwrap_or(0);
+        let balance = balances.get(asset_id).copied().unwrap_or(0);
+        if total != balance {
+            return Some(format!(
+                "balance of asset {} is reported as {}, but its unspent notes total {}",
+                asset_id, balance, total
+            ));
+        }
+    }
+
+    None
+}
+
+/// Checks that each of the `proofs` verifies against `anchor`.
+fn verify_proofs<'a>(
+    anchor: tct::Root,
+    proofs: impl IntoIterator<Item = &'a tct::Proof>,
+) -> Result<()> {
+    for proof in proofs {
+        proof.verify(anchor).map_err(|_| {
+            discrepancy(format!(
+                "authentication path for note commitment {} does not verify against anchor {}",
+                proof.commitment(),
+                anchor
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+// Log a discrepancy immediately, so that it's visible even if the caller swallows the error.
+fn discrepancy(message: String) -> anyhow::Error {
+    let e = anyhow!("view service spot check failed: {}", message);
+    tracing::error!(?e);
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        keys::{SeedPhrase, SpendKey},
+        Fq, Value,
+    };
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn balances_must_be_the_total_of_unspent_notes() {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key();
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let gm = asset::REGISTRY.parse_denom("gm").unwrap().id();
+
+        let mut nct = tct::Tree::new();
+        let mut record = |amount, asset_id, height_spent| {
+            let note = Note::generate(&mut OsRng, &address, Value { amount, asset_id });
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment).unwrap();
+            NoteRecord {
+                note_commitment,
+                diversifier_index: 0u64.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: 0,
+                height_spent,
+                position,
+                time_created: None,
+                source: None,
+            }
+        };
+        let notes = vec![
+            record(3, upenumbra, None),
+            record(4, upenumbra, None),
+            record(5, upenumbra, Some(1)),
+            record(7, gm, None),
+        ];
+
+        let totals = unspent_totals(&notes).unwrap();
+        let honest = [(upenumbra, 7), (gm, 7)].into_iter().collect();
+        assert_eq!(totals, honest);
+        assert_eq!(balance_discrepancy(&totals, &honest), None);
+
+        // Counting a spent note, leaving out an asset, or reporting an asset with no notes are all
+        // caught.
+        let inflated = [(upenumbra, 12), (gm, 7)].into_iter().collect();
+        assert!(balance_discrepancy(&totals, &inflated).is_some());
+        let hidden = [(upenumbra, 7)].into_iter().collect();
+        assert!(balance_discrepancy(&totals, &hidden).is_some());
+        let fabricated = [(upenumbra, 7), (gm, 7), (asset::Id(Fq::from(1u64)), 1)]
+            .into_iter()
+            .collect();
+        assert!(balance_discrepancy(&totals, &fabricated).is_some());
+    }
+
+    #[test]
+    fn proofs_must_verify_against_the_anchor() {
+        let mut nct = tct::Tree::new();
+        let commitments = (0..4u64)
+            .map(|i| tct::Commitment(Fq::from(i)))
+            .collect::<Vec<_>>();
+        for &commitment in &commitments {
+            nct.insert(tct::Witness::Keep, commitment).unwrap();
+        }
+        let anchor = nct.root();
+        let proofs = commitments
+            .iter()
+            .map(|&commitment| nct.witness(commitment).unwrap())
+            .collect::<Vec<_>>();
+        assert!(verify_proofs(anchor, &proofs).is_ok());
+
+        // Once the tree moves on, its new root is a different anchor, which the old paths don't
+        // verify against.
+        nct.insert(tct::Witness::Forget, tct::Commitment(Fq::from(4u64)))
+            .unwrap();
+        assert!(verify_proofs(nct.root(), &proofs).is_err());
+        assert!(verify_proofs(anchor, &proofs[..1]).is_ok());
+    }
+}
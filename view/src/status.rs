@@ -1,9 +1,15 @@
+use anyhow::anyhow;
 use penumbra_proto::{view as pb, Protobuf};
 
+use crate::SyncSourceHealth;
+
 #[derive(Clone, Copy, Debug)]
 pub struct StatusStreamResponse {
     pub latest_known_block_height: u64,
     pub sync_height: u64,
+    pub connection: SyncConnection,
+    /// The view service's estimate of how many notes it scans per second.
+    pub notes_per_second: f64,
 }
 
 impl Protobuf<pb::StatusStreamResponse> for StatusStreamResponse {}
@@ -15,6 +21,10 @@ impl TryFrom<pb::StatusStreamResponse> for StatusStreamResponse {
         Ok(StatusStreamResponse {
             latest_known_block_height: proto.latest_known_block_height,
             sync_height: proto.sync_height,
+            connection: pb::SyncConnection::from_i32(proto.connection)
+                .ok_or_else(|| anyhow!("unknown sync connection state {}", proto.connection))?
+                .into(),
+            notes_per_second: proto.notes_per_second,
         })
     }
 }
@@ -24,6 +34,61 @@ impl From<StatusStreamResponse> for pb::StatusStreamResponse {
         pb::StatusStreamResponse {
             latest_known_block_height: msg.latest_known_block_height,
             sync_height: msg.sync_height,
+            connection: pb::SyncConnection::from(msg.connection) as i32,
+            notes_per_second: msg.notes_per_second,
+        }
+    }
+}
+
+/// The state of the view worker's connection to the node it syncs from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncConnection {
+    /// Streaming blocks from the node.
+    Connected,
+    /// The connection dropped, e.g., because the node restarted, and the worker is waiting to
+    /// reconnect and resume from its sync height.
+    Reconnecting,
+    /// The node's blocks don't match storage, so the worker stopped syncing until storage is
+    /// reset to a height before they diverged.
+    Desynced,
+}
+
+impl From<SyncConnection> for pb::SyncConnection {
+    fn from(connection: SyncConnection) -> Self {
+        match connection {
+            SyncConnection::Connected => pb::SyncConnection::Connected,
+            SyncConnection::Reconnecting => pb::SyncConnection::Reconnecting,
+            SyncConnection::Desynced => pb::SyncConnection::Desynced,
+        }
+    }
+}
+
+impl From<pb::SyncConnection> for SyncConnection {
+    fn from(connection: pb::SyncConnection) -> Self {
+        match connection {
+            pb::SyncConnection::Connected => SyncConnection::Connected,
+            pb::SyncConnection::Reconnecting => SyncConnection::Reconnecting,
+            pb::SyncConnection::Desynced => SyncConnection::Desynced,
+        }
+    }
+}
+
+impl From<SyncSourceHealth> for pb::SyncSourceHealth {
+    fn from(health: SyncSourceHealth) -> Self {
+        pb::SyncSourceHealth {
+            url: health.url,
+            checked_at: health.checked_at,
+            error: health.error,
+        }
+    }
+}
+
+impl From<pb::SyncSourceHealth> for SyncSourceHealth {
+    fn from(health: pb::SyncSourceHealth) -> Self {
+        SyncSourceHealth {
+            url: health.url,
+            checked_at: health.checked_at,
+            error: health.error,
         }
     }
 }
@@ -1,27 +1,159 @@
 use anyhow::{anyhow, Context};
 use camino::Utf8Path;
-use futures::Future;
+use futures::{Future, TryStreamExt};
 use parking_lot::Mutex;
-use penumbra_chain::params::ChainParams;
+use penumbra_chain::{params::ChainParams, sync::CompactBlock};
 use penumbra_crypto::{
     asset::{self, Id},
-    Asset, FieldExt, FullViewingKey,
+    keys::{DiversifierIndex, FullViewingKeyHash},
+    Address, Amount, Asset, FieldExt, FullViewingKey, IdentityKey, Note, Nullifier,
 };
 use penumbra_proto::{
     client::oblivious::{oblivious_query_client::ObliviousQueryClient, ChainParamsRequest},
     Protobuf,
 };
 use penumbra_tct as tct;
-use sqlx::{migrate::MigrateDatabase, query, Pool, Sqlite};
-use std::{num::NonZeroU64, sync::Arc};
+use rand::Rng;
+use rand_core::OsRng;
+use sqlx::{
+    migrate::MigrateDatabase,
+    query,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    FromRow, Pool, Row, Sqlite,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    num::NonZeroU64,
+    ops::{Bound, RangeBounds},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tct::Commitment;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, watch};
+
+use crate::{
+    account::DEFAULT_ACCOUNT_LABEL,
+    snapshot::Snapshot,
+    sync::{empty_block_nct_updates, NctUpdate, ScanResult},
+    Account, NoteEvent, NoteOrigin, NoteRecord, QuarantinedNoteRecord, SelectionStrategy,
+    SentOutput, StorageConfig, StorageKey, TransactionInfo,
+};
+
+/// How long a reservation made by [`Storage::reserve_notes`] lasts.
+///
+/// This only needs to cover building, signing, and submitting the transaction: once it's
+/// confirmed, its notes are spent anyway.
+pub const NOTE_RESERVATION_TTL: Duration = Duration::from_secs(120);
+
+/// How many note events a subscriber can fall behind by before it misses some.
+const NOTE_EVENTS_CAPACITY: usize = 1024;
+
+/// How long a view worker's lease on recording blocks lasts, after it's last renewed.
+///
+/// If the worker holding it dies, another can only take over once it expires.
+pub const WRITER_LEASE_TTL: Duration = Duration::from_secs(30);
+
+/// How many blocks apart snapshots of the note commitment tree are taken, for
+/// [`Storage::reset_to_height`].
+///
+/// Only the latest snapshot is loaded: the changes made by each block since are recorded
+/// separately, and replayed onto it.
+const NCT_CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// How many of the most recent snapshots of the note commitment tree are kept.
+const NCT_CHECKPOINTS_RETAINED: i64 = 64;
+
+/// The database is vacuumed by [`Storage::prune`] once at least one in this many of its pages
+/// are free.
+const VACUUM_FREE_FRACTION: i64 = 4;
+
+/// The error returned when loading view storage with a different full viewing key than the one it
+/// was initialized with.
+///
+/// Scanning with the wrong key would silently miss all of the stored wallet's notes, and mix two
+/// wallets' data in one database, so this is checked whenever existing storage is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FvkMismatchError {
+    /// The hash of the full viewing key in the view storage.
+    pub stored: FullViewingKeyHash,
+    /// The hash of the full viewing key that was expected.
+    pub expected: FullViewingKeyHash,
+}
+
+impl std::fmt::Display for FvkMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "view storage belongs to full viewing key with hash {}, not the wallet's key with hash {}",
+            hex::encode(&self.stored.0),
+            hex::encode(&self.expected.0)
+        )
+    }
+}
 
-use crate::{sync::ScanResult, NoteRecord, QuarantinedNoteRecord};
+impl std::error::Error for FvkMismatchError {}
 
+/// The error returned when another view worker holds the lease on recording blocks into the same
+/// database.
+///
+/// Two workers scanning into one database would interleave their blocks, so whichever takes the
+/// lease first records blocks, and the other's storage is read-only until the lease expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriterLeaseHeldError {
+    /// The worker holding the lease.
+    pub holder: String,
+    /// When the lease expires, unless it's renewed, in seconds since the Unix epoch.
+    pub expires_at: i64,
+}
+
+impl std::fmt::Display for WriterLeaseHeldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "view storage is being written by another view worker ({}), whose lease expires at {} unless renewed, so it is read-only here",
+            self.holder, self.expires_at
+        )
+    }
+}
+
+impl std::error::Error for WriterLeaseHeldError {}
+
+/// The result of the last health check of the node the view service syncs from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncSourceHealth {
+    /// The URL of the node's gRPC endpoint.
+    pub url: String,
+    /// When the node was last checked, in seconds since the Unix epoch.
+    pub checked_at: u64,
+    /// The error from the last check, or `None` if the node was healthy.
+    pub error: Option<String>,
+}
+
+/// What [`Storage::prune`] deleted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneSummary {
+    /// The number of spent notes deleted.
+    pub notes: u64,
+    /// The number of quarantined nullifiers deleted, whose notes were already gone.
+    pub quarantined_nullifiers: u64,
+    /// Whether the database was vacuumed afterwards, to return the space freed to the filesystem.
+    pub vacuumed: bool,
+}
+
+/// The view service's SQLite database.
+///
+/// Writes, by the sync worker or on behalf of clients, go through a single writer connection,
+/// while queries use a pool of read-only connections, so that they aren't blocked by the worker
+/// recording a block. With SQLite's write-ahead log, readers see the state as of the last
+/// committed write, so a query made after [`Storage::wait_for_height`] returns reflects every
+/// block up to that height.
 #[derive(Clone)]
 pub struct Storage {
+    /// The writer connection.
     pool: Pool<Sqlite>,
+    /// The read-only connections, or the writer connection again, for an in-memory database.
+    read_pool: Pool<Sqlite>,
 
     /// This allows an optimization where we only commit to the database after
     /// scanning a nonempty block.
@@ -32,7 +164,35 @@ pub struct Storage {
     /// Using a `NonZeroU64` ensures that `Option<NonZeroU64>` fits in 8 bytes.
     uncommitted_height: Arc<Mutex<Option<NonZeroU64>>>,
 
+    /// If set, the only assets whose notes are recorded, cached from the database.
+    asset_allowlist: Arc<Mutex<Option<BTreeSet<asset::Id>>>>,
+
+    /// If set, how many of the most recently scanned compact blocks are kept in the cache.
+    compact_block_retention: Arc<Mutex<Option<NonZeroU64>>>,
+
+    /// Whether the transactions which created or spent our notes are fetched and recorded.
+    transaction_history: Arc<Mutex<bool>>,
+
     scanned_notes_tx: tokio::sync::broadcast::Sender<NoteRecord>,
+
+    /// Changes to each account's notes, broadcast once they're committed.
+    note_events_tx: broadcast::Sender<(Option<u32>, NoteEvent)>,
+
+    /// The height readers are guaranteed to see, updated after each block is recorded.
+    sync_height_tx: Arc<watch::Sender<Option<u64>>>,
+
+    /// Identifies this storage as the holder of the writer lease.
+    writer_id: Arc<String>,
+    /// When this storage's writer lease expires, if it holds one.
+    writer_lease_expires_at: Arc<Mutex<Option<i64>>>,
+}
+
+impl std::fmt::Debug for Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Storage")
+            .field("writer_id", &self.writer_id)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Storage {
@@ -45,7 +205,9 @@ impl Storage {
     ) -> anyhow::Result<Self> {
         let storage_path = storage_path.as_ref();
         if storage_path.exists() {
-            Self::load(storage_path.as_str()).await
+            let storage = Self::load(storage_path.as_str()).await?;
+            storage.check_full_viewing_key(fvk).await?;
+            Ok(storage)
         } else {
             let mut client =
                 ObliviousQueryClient::connect(format!("http://{}:{}", node, pd_port)).await?;
@@ -61,20 +223,170 @@ impl Storage {
     }
 
     pub async fn load(path: impl AsRef<Utf8Path>) -> anyhow::Result<Self> {
-        Ok(Self {
-            pool: Pool::<Sqlite>::connect(path.as_ref().as_str()).await?,
+        Self::open(path.as_ref(), None, &StorageConfig::default()).await
+    }
+
+    /// Load the database at `path`, which was created with [`Self::initialize_encrypted`],
+    /// decrypting it with `key`.
+    pub async fn load_encrypted(
+        path: impl AsRef<Utf8Path>,
+        key: &StorageKey,
+    ) -> anyhow::Result<Self> {
+        Self::open(path.as_ref(), Some(key), &StorageConfig::default()).await
+    }
+
+    /// Load the database at `path`, decrypting it with `key`, if it's set, and connecting to it as
+    /// `config` says.
+    pub async fn load_with_config(
+        path: impl AsRef<Utf8Path>,
+        key: Option<&StorageKey>,
+        config: &StorageConfig,
+    ) -> anyhow::Result<Self> {
+        Self::open(path.as_ref(), key, config).await
+    }
+
+    async fn open(
+        path: &Utf8Path,
+        key: Option<&StorageKey>,
+        config: &StorageConfig,
+    ) -> anyhow::Result<Self> {
+        let pool = connect_writer(path, key, config).await?;
+
+        // Run any migrations added since the database was created
+        sqlx::migrate!().run(&pool).await?;
+
+        let asset_allowlist = load_asset_allowlist(&pool).await?;
+        let read_pool = connect_readers(path, key, config).await?;
+
+        Self::new(pool, read_pool, asset_allowlist).await
+    }
+
+    /// Wrap the writer connection `pool` and the read-only connections `read_pool`.
+    async fn new(
+        pool: Pool<Sqlite>,
+        read_pool: Pool<Sqlite>,
+        asset_allowlist: Option<BTreeSet<asset::Id>>,
+    ) -> anyhow::Result<Self> {
+        let storage = Self {
+            pool,
+            read_pool,
             uncommitted_height: Arc::new(Mutex::new(None)),
+            asset_allowlist: Arc::new(Mutex::new(asset_allowlist)),
+            compact_block_retention: Arc::new(Mutex::new(None)),
+            transaction_history: Arc::new(Mutex::new(false)),
             scanned_notes_tx: broadcast::channel(10).0,
+            note_events_tx: broadcast::channel(NOTE_EVENTS_CAPACITY).0,
+            sync_height_tx: Arc::new(watch::channel(None).0),
+            writer_id: Arc::new(format!(
+                "process {}, instance {}",
+                std::process::id(),
+                hex::encode(OsRng.gen::<[u8; 4]>())
+            )),
+            writer_lease_expires_at: Arc::new(Mutex::new(None)),
+        };
+        storage
+            .sync_height_tx
+            .send_replace(storage.last_sync_height().await?);
+
+        Ok(storage)
+    }
+
+    /// Wait until this storage has recorded every block up to `height`, so that queries made
+    /// afterwards reflect them, failing if that takes longer than `timeout`.
+    ///
+    /// This lets a client that knows a block has been produced, e.g., because it just submitted a
+    /// transaction included in it, avoid reading a stale balance.
+    pub async fn wait_for_height(&self, height: u64, timeout: Duration) -> anyhow::Result<()> {
+        let mut sync_height_rx = self.sync_height_tx.subscribe();
+        let wait = async {
+            loop {
+                if sync_height_rx
+                    .borrow_and_update()
+                    .map_or(false, |h| h >= height)
+                {
+                    return;
+                }
+                // The sender lives as long as `self`, so this can't fail.
+                let _ = sync_height_rx.changed().await;
+            }
+        };
+
+        tokio::time::timeout(timeout, wait).await.map_err(|_| {
+            anyhow!(
+                "view storage did not sync to height {} within {:?}",
+                height,
+                timeout
+            )
         })
     }
 
+    /// Check that the given full viewing key is one of this storage's accounts, returning an
+    /// [`FvkMismatchError`] if not.
+    pub async fn check_full_viewing_key(&self, fvk: &FullViewingKey) -> anyhow::Result<()> {
+        let expected = fvk.hash();
+        if self.account(&expected).await?.is_some() {
+            return Ok(());
+        }
+
+        let stored = self.full_viewing_key().await?.hash();
+        Err(FvkMismatchError { stored, expected }.into())
+    }
+
     pub async fn initialize(
         storage_path: impl AsRef<Utf8Path>,
         fvk: FullViewingKey,
         params: ChainParams,
     ) -> anyhow::Result<Self> {
-        let storage_path = storage_path.as_ref();
-        tracing::debug!(%storage_path, ?fvk, ?params);
+        Self::create(
+            storage_path.as_ref(),
+            None,
+            fvk,
+            params,
+            &StorageConfig::default(),
+        )
+        .await
+    }
+
+    /// Like [`Self::initialize`], but encrypts the whole database with `key`, which is then
+    /// needed to [`Self::load_encrypted`] it.
+    ///
+    /// This fails unless the view service was built with the `sqlcipher` feature.
+    pub async fn initialize_encrypted(
+        storage_path: impl AsRef<Utf8Path>,
+        fvk: FullViewingKey,
+        params: ChainParams,
+        key: &StorageKey,
+    ) -> anyhow::Result<Self> {
+        Self::create(
+            storage_path.as_ref(),
+            Some(key),
+            fvk,
+            params,
+            &StorageConfig::default(),
+        )
+        .await
+    }
+
+    /// Like [`Self::initialize`], but encrypts the database with `key`, if it's set, and connects
+    /// to it as `config` says.
+    pub async fn initialize_with_config(
+        storage_path: impl AsRef<Utf8Path>,
+        fvk: FullViewingKey,
+        params: ChainParams,
+        key: Option<&StorageKey>,
+        config: &StorageConfig,
+    ) -> anyhow::Result<Self> {
+        Self::create(storage_path.as_ref(), key, fvk, params, config).await
+    }
+
+    async fn create(
+        storage_path: &Utf8Path,
+        key: Option<&StorageKey>,
+        fvk: FullViewingKey,
+        params: ChainParams,
+        config: &StorageConfig,
+    ) -> anyhow::Result<Self> {
+        tracing::debug!(%storage_path, ?fvk, ?params, encrypted = key.is_some());
         // We don't want to overwrite existing data,
         // but also, SQLX will complain if the file doesn't already exist
         if storage_path.exists() {
@@ -85,50 +397,51 @@ impl Storage {
         // Create the SQLite database
         sqlx::Sqlite::create_database(storage_path.as_str());
 
-        let pool = Pool::<Sqlite>::connect(storage_path.as_str()).await?;
-
-        // Run migrations
-        sqlx::migrate!().run(&pool).await?;
-
-        // Initialize the database state with: empty NCT, chain params, FVK
-        let mut tx = pool.begin().await?;
-
-        let nct_bytes = bincode::serialize(&tct::Tree::new())?;
-        let chain_params_bytes = &ChainParams::encode_to_vec(&params)[..];
-        let fvk_bytes = &FullViewingKey::encode_to_vec(&fvk)[..];
+        let pool = connect_writer(storage_path, key, config).await?;
+        populate(&pool, fvk, params).await?;
+        let read_pool = connect_readers(storage_path, key, config).await?;
 
-        sqlx::query!(
-            "INSERT INTO note_commitment_tree (bytes) VALUES (?)",
-            nct_bytes
-        )
-        .execute(&mut tx)
-        .await?;
+        Self::new(pool, read_pool, None).await
+    }
 
-        sqlx::query!(
-            "INSERT INTO chain_params (bytes) VALUES (?)",
-            chain_params_bytes
-        )
-        .execute(&mut tx)
-        .await?;
+    /// Like [`Self::initialize`], but keeps the database in memory rather than in a file, so that
+    /// nothing touches the filesystem, e.g., in tests or for an ephemeral wallet. Everything is
+    /// lost once the storage is dropped.
+    pub async fn initialize_in_memory(
+        fvk: FullViewingKey,
+        params: ChainParams,
+    ) -> anyhow::Result<Self> {
+        tracing::debug!(?fvk, ?params, "initializing in-memory storage");
 
-        sqlx::query!("INSERT INTO full_viewing_key (bytes) VALUES (?)", fvk_bytes)
-            .execute(&mut tx)
+        // An in-memory database only lives as long as its connection, and isn't shared with
+        // other connections, so reads and writes go through a single connection that's never
+        // closed.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(SqliteConnectOptions::from_str("sqlite::memory:")?)
             .await?;
+        populate(&pool, fvk, params).await?;
 
-        // Insert -1 as a signaling value for pre-genesis.
-        // We just have to be careful to treat negative values as None
-        // in last_sync_height.
-        sqlx::query!("INSERT INTO sync_height (height) VALUES (?)", -1i64)
-            .execute(&mut tx)
-            .await?;
+        Self::new(pool.clone(), pool, None).await
+    }
 
-        tx.commit().await?;
+    /// Subscribe to changes to notes, along with the index of the account each change is for, or
+    /// `None` if it's for every account, as they are recorded.
+    ///
+    /// A subscriber that falls too far behind misses events, and is told so by the receiver.
+    pub fn subscribe_note_events(&self) -> broadcast::Receiver<(Option<u32>, NoteEvent)> {
+        self.note_events_tx.subscribe()
+    }
 
-        Ok(Storage {
-            pool,
-            uncommitted_height: Arc::new(Mutex::new(None)),
-            scanned_notes_tx: broadcast::channel(10).0,
-        })
+    /// Tell subscribers to note events that everything scanned after `height`, or everything if
+    /// it's `None`, was rolled back, once the rollback is committed.
+    fn send_rolled_back(&self, height: Option<u64>) {
+        // As when recording blocks, there may be no subscribers.
+        let _ = self
+            .note_events_tx
+            .send((None, NoteEvent::RolledBack { height }));
     }
 
     /// Query for a note by its note commitment, optionally waiting until the note is detected.
@@ -142,18 +455,16 @@ impl Storage {
         let mut rx = self.scanned_notes_tx.subscribe();
 
         // Clone the pool handle so that the returned future is 'static
-        let pool = self.pool.clone();
+        let pool = self.read_pool.clone();
         async move {
             // Check if we already have the note
             if let Some(record) = sqlx::query_as::<_, NoteRecord>(
-                format!(
-                    "SELECT *
-                    FROM notes
-                    WHERE note_commitment = x'{}'",
-                    hex::encode(note_commitment.0.to_bytes())
-                )
-                .as_str(),
+                "SELECT notes.*, block_times.block_time AS time_created
+                FROM notes
+                LEFT JOIN block_times ON notes.height_created = block_times.height
+                WHERE note_commitment = ?",
             )
+            .bind(note_commitment.0.to_bytes().to_vec())
             .fetch_optional(&pool)
             .await?
             {
@@ -176,6 +487,51 @@ impl Storage {
         }
     }
 
+    /// Query for a note by its nullifier, optionally waiting until the note is detected.
+    ///
+    /// This maps a spend observed on chain back to the note it spent, if the note is ours.
+    pub fn note_by_nullifier(
+        &self,
+        nullifier: Nullifier,
+        await_detection: bool,
+    ) -> impl Future<Output = anyhow::Result<NoteRecord>> {
+        // Start subscribing now, before querying for whether we already
+        // have the record, so that we can't miss it if we race a write.
+        let mut rx = self.scanned_notes_tx.subscribe();
+
+        // Clone the pool handle so that the returned future is 'static
+        let pool = self.read_pool.clone();
+        async move {
+            // Check if we already have the note
+            if let Some(record) = sqlx::query_as::<_, NoteRecord>(
+                "SELECT notes.*, block_times.block_time AS time_created
+                FROM notes
+                LEFT JOIN block_times ON notes.height_created = block_times.height
+                WHERE nullifier = ?",
+            )
+            .bind(nullifier.to_bytes().to_vec())
+            .fetch_optional(&pool)
+            .await?
+            {
+                return Ok(record);
+            }
+
+            if !await_detection {
+                return Err(anyhow!("Note with nullifier {} not found", nullifier));
+            }
+
+            // Otherwise, wait for newly detected notes and check whether they're
+            // the requested one.
+            loop {
+                let record = rx.recv().await.context("Change subscriber failed")?;
+
+                if record.nullifier == nullifier {
+                    return Ok(record);
+                }
+            }
+        }
+    }
+
     /// The last block height we've scanned to, if any.
     pub async fn last_sync_height(&self) -> anyhow::Result<Option<u64>> {
         // Check if we have uncommitted blocks beyond the database height.
@@ -191,7 +547,7 @@ impl Storage {
             LIMIT 1
         "#
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&self.read_pool)
         .await?;
 
         // Special-case negative values to None
@@ -206,467 +562,3607 @@ impl Storage {
             LIMIT 1
         "#
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&self.read_pool)
         .await?;
 
         ChainParams::decode(result.bytes.as_slice())
     }
 
-    pub async fn full_viewing_key(&self) -> anyhow::Result<FullViewingKey> {
-        let result = query!(
-            r#"
-            SELECT bytes
-            FROM full_viewing_key
-            LIMIT 1
-            "#
-        )
-        .fetch_one(&self.pool)
-        .await?;
+    /// Replace the cached chain parameters, e.g. after they've changed on chain.
+    pub async fn update_chain_params(&self, params: &ChainParams) -> anyhow::Result<()> {
+        let chain_params_bytes = ChainParams::encode_to_vec(params);
+        sqlx::query("UPDATE chain_params SET bytes = ?")
+            .bind(chain_params_bytes)
+            .execute(&self.pool)
+            .await?;
 
-        FullViewingKey::decode(result.bytes.as_slice())
+        Ok(())
     }
 
-    pub async fn note_commitment_tree(&self) -> anyhow::Result<tct::Tree> {
-        let result = query!(
-            r#"
-            SELECT bytes
-            FROM note_commitment_tree
-            LIMIT 1
-            "#
-        )
-        .fetch_one(&self.pool)
-        .await?;
-
-        Ok(bincode::deserialize(result.bytes.as_slice())?)
+    /// The assets whose notes are recorded, or `None` if notes of every asset are recorded.
+    pub fn asset_allowlist(&self) -> Option<BTreeSet<asset::Id>> {
+        self.asset_allowlist.lock().clone()
     }
 
-    pub async fn assets(&self) -> anyhow::Result<Vec<Asset>> {
-        let result = sqlx::query!(
-            "SELECT *
-            FROM assets"
-        )
-        .fetch_all(&self.pool)
-        .await?;
-
-        let mut output: Vec<Asset> = Vec::new();
-
-        for record in result {
-            let asset = Asset {
-                id: Id::try_from(record.asset_id.as_slice())?,
-                denom: asset::REGISTRY
-                    .parse_denom(&record.denom)
-                    .ok_or_else(|| anyhow::anyhow!("invalid denomination {}", record.denom))?,
-            };
-            output.push(asset);
+    /// Only record notes of the assets in `allowlist`, or of every asset if it's `None`, e.g., so
+    /// that an exchange's deposit wallet isn't filled with notes of spam assets.
+    ///
+    /// Notes of assets no longer allowed are deleted. If the allowlist grows, notes of the newly
+    /// allowed assets were dropped while scanning, so the scan state is reset, and the chain is
+    /// rescanned from genesis. This must be called before the storage is used to construct a
+    /// [`ViewService`](crate::ViewService), since its worker loads the scan state when it starts.
+    pub async fn set_asset_allowlist(
+        &self,
+        allowlist: Option<BTreeSet<asset::Id>>,
+    ) -> anyhow::Result<()> {
+        if allowlist.as_ref().map_or(false, BTreeSet::is_empty) {
+            return Err(anyhow!("the asset allowlist must not be empty"));
         }
 
-        Ok(output)
-    }
+        let previous = self.asset_allowlist();
+        if previous == allowlist {
+            return Ok(());
+        }
 
-    pub async fn notes(
-        &self,
-        include_spent: bool,
-        asset_id: Option<asset::Id>,
-        diversifier_index: Option<penumbra_crypto::keys::DiversifierIndex>,
-        amount_to_spend: u64,
-    ) -> anyhow::Result<Vec<NoteRecord>> {
-        // If set, return spent notes as well as unspent notes.
-        // bool include_spent = 2;
-        let spent_clause = match include_spent {
-            false => "NULL",
-            true => "height_spent",
+        let grew = match (&previous, &allowlist) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(previous), Some(allowlist)) => !allowlist.is_subset(previous),
         };
 
-        // If set, only return notes with the specified asset id.
-        // crypto.AssetId asset_id = 3;
+        let mut tx = self.pool.begin().await?;
 
-        let asset_clause = asset_id
-            .map(|id| format!("x'{}'", hex::encode(&id.to_bytes())))
-            .unwrap_or_else(|| "asset_id".to_string());
+        sqlx::query("DELETE FROM asset_allowlist")
+            .execute(&mut tx)
+            .await?;
+        for asset_id in allowlist.iter().flatten() {
+            sqlx::query("INSERT INTO asset_allowlist (asset_id) VALUES (?)")
+                .bind(asset_id.to_bytes().to_vec())
+                .execute(&mut tx)
+                .await?;
+        }
 
-        // If set, only return notes with the specified diversifier index.
-        // crypto.DiversifierIndex diversifier_index = 4;
-        let diversifier_clause = diversifier_index
-            .map(|d| format!("x'{}'", hex::encode(&d.0)))
-            .unwrap_or_else(|| "diversifier_index".to_string());
+        if grew {
+            tracing::info!(?allowlist, "asset allowlist grew, rescanning from genesis");
+            reset_to_genesis(&mut tx).await?;
+            self.uncommitted_height.lock().take();
+        } else if let Some(allowlist) = &allowlist {
+            // The tree is rewritten below, so it's loaded inside the transaction, where no block
+            // can be recorded after it's read.
+            let mut nct = load_nct(&mut tx).await?;
 
-        let result = sqlx::query_as::<_, NoteRecord>(
-            format!(
-                "SELECT *
-            FROM notes
-            WHERE height_spent IS {}
-            AND asset_id IS {}
-            AND diversifier_index IS {}",
-                spent_clause, asset_clause, diversifier_clause
-            )
-            .as_str(),
-        )
-        .fetch_all(&self.pool)
-        .await?;
+            // Delete the notes of assets which are no longer allowed, and forget their
+            // commitments, since we'll never need to witness them.
+            let notes: Vec<(Vec<u8>, Vec<u8>)> =
+                sqlx::query_as("SELECT note_commitment, asset_id FROM notes")
+                    .fetch_all(&mut tx)
+                    .await?;
+            for (commitment_bytes, asset_id_bytes) in notes {
+                if allowlist.contains(&asset::Id::try_from(asset_id_bytes.as_slice())?) {
+                    continue;
+                }
+                nct.forget(Commitment::try_from(commitment_bytes.as_slice())?);
+                sqlx::query("DELETE FROM notes WHERE note_commitment = ?")
+                    .bind(commitment_bytes)
+                    .execute(&mut tx)
+                    .await?;
+            }
 
-        // If set, stop returning notes once the total exceeds this amount.
-        //
-        // Ignored if `asset_id` is unset or if `include_spent` is set.
-        // uint64 amount_to_spend = 5;
-        //TODO: figure out a clever way to only return notes up to the sum using SQL
-        let amount_cutoff = (amount_to_spend != 0) && !(include_spent || asset_id.is_none());
-        let mut amount_total = 0;
-
-        let mut output: Vec<NoteRecord> = Vec::new();
-
-        for record in result.into_iter() {
-            let amount = record.note.amount();
-            output.push(record);
-            // If we're tracking amounts, accumulate the value of the note
-            // and check if we should break out of the loop.
-            if amount_cutoff {
-                // We know all the notes are of the same type, so adding raw quantities makes sense.
-                amount_total += amount;
-                if amount_total >= amount_to_spend {
-                    break;
+            let quarantined: Vec<(Vec<u8>, Vec<u8>)> =
+                sqlx::query_as("SELECT note_commitment, asset_id FROM quarantined_notes")
+                    .fetch_all(&mut tx)
+                    .await?;
+            for (commitment_bytes, asset_id_bytes) in quarantined {
+                if !allowlist.contains(&asset::Id::try_from(asset_id_bytes.as_slice())?) {
+                    sqlx::query("DELETE FROM quarantined_notes WHERE note_commitment = ?")
+                        .bind(commitment_bytes)
+                        .execute(&mut tx)
+                        .await?;
                 }
             }
+
+            sqlx::query("UPDATE note_commitment_tree SET bytes = ?")
+                .bind(bincode::serialize(&nct)?)
+                .execute(&mut tx)
+                .await?;
+            sqlx::query("DELETE FROM note_commitment_tree_updates")
+                .execute(&mut tx)
+                .await?;
         }
 
-        if amount_total < amount_to_spend {
-            return Err(anyhow!(
-                "requested amount of {} exceeds total of {}",
-                amount_to_spend,
-                amount_total
-            ));
+        tx.commit().await?;
+        *self.asset_allowlist.lock() = allowlist;
+        if grew {
+            self.sync_height_tx.send_replace(None);
+            self.send_rolled_back(None);
         }
 
-        Ok(output)
+        Ok(())
     }
 
-    pub async fn quarantined_notes(&self) -> anyhow::Result<Vec<QuarantinedNoteRecord>> {
-        let result = sqlx::query_as::<_, QuarantinedNoteRecord>("SELECT * FROM quarantined_notes")
-            .fetch_all(&self.pool)
-            .await?;
+    /// How many of the most recently scanned compact blocks are kept, or `None` if they aren't.
+    pub fn compact_block_retention(&self) -> Option<NonZeroU64> {
+        *self.compact_block_retention.lock()
+    }
 
-        Ok(result)
+    /// Whether the transactions which created or spent our notes are fetched and recorded.
+    pub fn transaction_history(&self) -> bool {
+        *self.transaction_history.lock()
     }
 
-    pub async fn record_asset(&self, asset: Asset) -> anyhow::Result<()> {
-        let mut tx = self.pool.begin().await?;
+    /// Set whether the transactions which created or spent our notes are fetched and recorded,
+    /// which is off by default.
+    ///
+    /// Compact blocks don't delimit transactions, so recording them means fetching the full block
+    /// at each height where we received or spent a note. This reveals those heights to the node,
+    /// which can link them to this client, so history should only be turned on when the node is
+    /// trusted with them, e.g., when it's the user's own.
+    ///
+    /// Heights scanned while it was off are fetched once it's turned on.
+    pub fn set_transaction_history(&self, enabled: bool) {
+        *self.transaction_history.lock() = enabled;
+    }
 
-        let asset_id = asset.id.to_bytes().to_vec();
-        let denom = asset.denom.to_string();
-        sqlx::query!(
-            "INSERT INTO assets
-                    (
-                        asset_id,
-                        denom
-                    )
-                    VALUES
-                    (
-                        ?,
-                        ?
-                    )",
-            asset_id,
-            denom,
-        )
+    /// Keep the last `retention` compact blocks scanned, or none if it's `None`, so that they can
+    /// be exported with [`Self::cached_compact_blocks`], e.g., to investigate a note the wallet
+    /// missed by re-scanning exactly the blocks the worker saw.
+    ///
+    /// Blocks already cached beyond the new window are dropped as soon as the next block is cached.
+    /// If caching is turned off, the whole cache is dropped.
+    pub async fn set_compact_block_retention(
+        &self,
+        retention: Option<NonZeroU64>,
+    ) -> anyhow::Result<()> {
+        if retention.is_none() {
+            sqlx::query("DELETE FROM compact_block_cache")
+                .execute(&self.pool)
+                .await?;
+        }
+        *self.compact_block_retention.lock() = retention;
+        Ok(())
+    }
+
+    /// Add `blocks` to the compact block cache, dropping blocks which fall out of the retention
+    /// window. Does nothing if caching is turned off.
+    pub async fn cache_compact_blocks(&self, blocks: &[CompactBlock]) -> anyhow::Result<()> {
+        let (retention, last_height) = match (self.compact_block_retention(), blocks.last()) {
+            (Some(retention), Some(last)) => (retention.get(), last.height),
+            _ => return Ok(()),
+        };
+
+        let mut tx = self.pool.begin().await?;
+        for block in blocks {
+            sqlx::query("INSERT OR REPLACE INTO compact_block_cache (height, bytes) VALUES (?, ?)")
+                .bind(block.height as i64)
+                .bind(block.encode_to_vec())
+                .execute(&mut tx)
+                .await?;
+        }
+        if let Some(oldest_retained) = (last_height + 1).checked_sub(retention) {
+            sqlx::query("DELETE FROM compact_block_cache WHERE height < ?")
+                .bind(oldest_retained as i64)
+                .execute(&mut tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The cached compact blocks with heights in `range`, in order.
+    pub async fn cached_compact_blocks(
+        &self,
+        range: impl RangeBounds<u64>,
+    ) -> anyhow::Result<Vec<CompactBlock>> {
+        let (start_height, end_height) = height_bounds(range);
+
+        let rows: Vec<(Vec<u8>,)> = sqlx::query_as(
+            "SELECT bytes FROM compact_block_cache WHERE height >= ? AND height <= ? ORDER BY height",
+        )
+        .bind(start_height)
+        .bind(end_height)
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(bytes,)| CompactBlock::decode(bytes.as_slice()))
+            .collect()
+    }
+
+    /// Record the result of checking the health of the node we sync from.
+    pub async fn record_sync_source_health(
+        &self,
+        url: &str,
+        error: Option<String>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO sync_source (id, url, checked_at, error) VALUES (0, ?, ?, ?)",
+        )
+        .bind(url)
+        .bind(unix_now())
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The result of the last health check of the node we sync from, if it's been checked.
+    pub async fn sync_source_health(&self) -> anyhow::Result<Option<SyncSourceHealth>> {
+        let row: Option<(String, i64, Option<String>)> =
+            sqlx::query_as("SELECT url, checked_at, error FROM sync_source WHERE id = 0")
+                .fetch_optional(&self.read_pool)
+                .await?;
+
+        Ok(row.map(|(url, checked_at, error)| SyncSourceHealth {
+            url,
+            checked_at: checked_at as u64,
+            error,
+        }))
+    }
+
+    pub async fn full_viewing_key(&self) -> anyhow::Result<FullViewingKey> {
+        let result = query!(
+            r#"
+            SELECT bytes
+            FROM full_viewing_key
+            LIMIT 1
+            "#
+        )
+        .fetch_one(&self.read_pool)
+        .await?;
+
+        FullViewingKey::decode(result.bytes.as_slice())
+    }
+
+    /// The accounts scanned into this storage, in the order they were added.
+    pub async fn accounts(&self) -> anyhow::Result<Vec<Account>> {
+        let rows: Vec<(i64, String, Vec<u8>)> =
+            sqlx::query_as("SELECT account, label, bytes FROM full_viewing_keys ORDER BY account")
+                .fetch_all(&self.read_pool)
+                .await?;
+
+        rows.into_iter()
+            .map(|(index, label, bytes)| {
+                Ok(Account {
+                    index: index as u32,
+                    label,
+                    fvk: FullViewingKey::decode(bytes.as_slice())?,
+                })
+            })
+            .collect()
+    }
+
+    /// The account with the full viewing key whose hash is `fvk_hash`, if any.
+    pub async fn account(&self, fvk_hash: &FullViewingKeyHash) -> anyhow::Result<Option<Account>> {
+        Ok(self
+            .accounts()
+            .await?
+            .into_iter()
+            .find(|account| &account.fvk_hash() == fvk_hash))
+    }
+
+    /// Add an account scanning for the notes of `fvk`, referred to by `label`.
+    ///
+    /// The notes of the new account could be anywhere in the chain, so everything scanned so far
+    /// is forgotten, and the chain is rescanned from genesis for all accounts. A worker syncing
+    /// this storage must be restarted to pick up the new account.
+    pub async fn add_account(&self, label: &str, fvk: &FullViewingKey) -> anyhow::Result<Account> {
+        let accounts = self.accounts().await?;
+        if accounts.iter().any(|account| account.label == label) {
+            return Err(anyhow!("there is already an account labeled {:?}", label));
+        }
+        if accounts
+            .iter()
+            .any(|account| account.fvk_hash() == fvk.hash())
+        {
+            return Err(anyhow!(
+                "there is already an account with this full viewing key"
+            ));
+        }
+        let account = Account {
+            index: accounts.last().map_or(0, |account| account.index + 1),
+            label: label.to_string(),
+            fvk: fvk.clone(),
+        };
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO full_viewing_keys (account, label, bytes) VALUES (?, ?, ?)")
+            .bind(account.index as i64)
+            .bind(&account.label)
+            .bind(FullViewingKey::encode_to_vec(fvk))
+            .execute(&mut tx)
+            .await?;
+        tracing::info!(label, "added account, rescanning from genesis");
+        reset_to_genesis(&mut tx).await?;
+        tx.commit().await?;
+
+        self.uncommitted_height.lock().take();
+        self.sync_height_tx.send_replace(None);
+        self.send_rolled_back(None);
+
+        Ok(account)
+    }
+
+    /// Record `address` in the address book, so that it can be referred to by `label`.
+    pub async fn record_contact(&self, label: &str, address: &Address) -> anyhow::Result<()> {
+        let result =
+            sqlx::query("INSERT OR IGNORE INTO address_book (label, address) VALUES (?, ?)")
+                .bind(label)
+                .bind(address.to_string())
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("there is already a contact labeled {:?}", label));
+        }
+
+        Ok(())
+    }
+
+    /// The addresses in the address book, by their labels.
+    pub async fn contacts(&self) -> anyhow::Result<BTreeMap<String, Address>> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT label, address FROM address_book")
+            .fetch_all(&self.read_pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|(label, address)| {
+                let address = address
+                    .parse()
+                    .with_context(|| format!("contact {:?} has an invalid address", label))?;
+                Ok((label, address))
+            })
+            .collect()
+    }
+
+    /// Remove the address labeled `label` from the address book, returning whether there was one.
+    pub async fn remove_contact(&self, label: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM address_book WHERE label = ?")
+            .bind(label)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Name the diversifier index `index` of `account` `label`, or remove its name if `label` is
+    /// `None`.
+    pub async fn set_label(
+        &self,
+        account: u32,
+        index: DiversifierIndex,
+        label: Option<&str>,
+    ) -> anyhow::Result<()> {
+        match label {
+            Some(label) => {
+                sqlx::query(
+                    "INSERT OR REPLACE INTO labels (account, diversifier_index, label) VALUES (?, ?, ?)",
+                )
+                .bind(i64::from(account))
+                .bind(index.0.to_vec())
+                .bind(label)
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM labels WHERE account = ? AND diversifier_index = ?")
+                    .bind(i64::from(account))
+                    .bind(index.0.to_vec())
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The name of the diversifier index `index` of `account`, if it has one.
+    pub async fn label(
+        &self,
+        account: u32,
+        index: DiversifierIndex,
+    ) -> anyhow::Result<Option<String>> {
+        Ok(self.labels(account).await?.remove(&index))
+    }
+
+    /// The name of each named diversifier index of `account`.
+    pub async fn labels(&self, account: u32) -> anyhow::Result<BTreeMap<DiversifierIndex, String>> {
+        let rows: Vec<(Vec<u8>, String)> =
+            sqlx::query_as("SELECT diversifier_index, label FROM labels WHERE account = ?")
+                .bind(i64::from(account))
+                .fetch_all(&self.read_pool)
+                .await?;
+
+        rows.into_iter()
+            .map(|(index, label)| Ok((DiversifierIndex::try_from(index.as_slice())?, label)))
+            .collect()
+    }
+
+    /// The diversifier index each labeled index of `account` is grouped under: the numerically
+    /// lowest index with the same label, so that indices sharing a label are treated as one
+    /// account.
+    pub async fn label_groups(
+        &self,
+        account: u32,
+    ) -> anyhow::Result<BTreeMap<DiversifierIndex, DiversifierIndex>> {
+        let labels = self.labels(account).await?;
+
+        // Indices are ordered by their bytes, which are little-endian, so find the lowest of
+        // each label by their numeric values.
+        let mut lowest = BTreeMap::<&str, DiversifierIndex>::new();
+        for (index, label) in &labels {
+            let lowest = lowest.entry(label.as_str()).or_insert(*index);
+            if u128::from(*index) < u128::from(*lowest) {
+                *lowest = *index;
+            }
+        }
+
+        Ok(labels
+            .iter()
+            .map(|(index, label)| (*index, lowest[label.as_str()]))
+            .collect())
+    }
+
+    /// The total amount of each asset in the unspent notes of `account`, like
+    /// [`Self::balances_by_index`], but with the indices sharing a label summed under the index
+    /// [`Self::label_groups`] groups them under.
+    pub async fn balances_by_label(
+        &self,
+        account: u32,
+    ) -> anyhow::Result<BTreeMap<DiversifierIndex, BTreeMap<asset::Id, u64>>> {
+        let groups = self.label_groups(account).await?;
+
+        let mut balances = BTreeMap::<_, BTreeMap<_, u64>>::new();
+        for (index, amounts) in self.balances_by_index(Some(account)).await? {
+            let group = balances
+                .entry(groups.get(&index).copied().unwrap_or(index))
+                .or_default();
+            for (asset_id, amount) in amounts {
+                let total = group.entry(asset_id).or_default();
+                *total = total
+                    .checked_add(amount)
+                    .ok_or_else(|| anyhow!("balance overflows a u64"))?;
+            }
+        }
+
+        Ok(balances)
+    }
+
+    /// The note commitment tree as of the last recorded block: the latest snapshot of the tree,
+    /// with the changes made by each block since replayed onto it.
+    pub async fn note_commitment_tree(&self) -> anyhow::Result<tct::Tree> {
+        // Read the snapshot and the changes since in one transaction, so that a snapshot taken in
+        // between can't be replayed onto.
+        let mut tx = self.read_pool.begin().await?;
+        let nct = load_nct(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(nct)
+    }
+
+    pub async fn assets(&self) -> anyhow::Result<Vec<Asset>> {
+        let result = sqlx::query!(
+            "SELECT *
+            FROM assets"
+        )
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        let mut output: Vec<Asset> = Vec::new();
+
+        for record in result {
+            let asset = Asset {
+                id: Id::try_from(record.asset_id.as_slice())?,
+                denom: asset::REGISTRY
+                    .parse_denom(&record.denom)
+                    .ok_or_else(|| anyhow::anyhow!("invalid denomination {}", record.denom))?,
+            };
+            output.push(asset);
+        }
+
+        Ok(output)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn notes(
+        &self,
+        include_spent: bool,
+        asset_id: Option<asset::Id>,
+        diversifier_index: Option<penumbra_crypto::keys::DiversifierIndex>,
+        amount_to_spend: Amount,
+        strategy: SelectionStrategy,
+        created_after: Option<String>,
+        created_before: Option<String>,
+        exclude_reserved: bool,
+        account: Option<u32>,
+        sources: &[Option<NoteOrigin>],
+    ) -> anyhow::Result<Vec<NoteRecord>> {
+        // If set, only return enough notes to cover this amount, chosen by `strategy`.
+        //
+        // Ignored if `asset_id` is unset or if `include_spent` is set.
+        // uint64 amount_to_spend = 5;
+        // NoteSelectionStrategy selection_strategy = 10;
+        let cutoff = amount_to_spend != Amount::zero() && !include_spent && asset_id.is_some();
+        // Strategies which take notes in some order stop loading them as soon as they cover the
+        // amount, so that spending from a wallet with many notes doesn't load all of them. The
+        // others choose among every note.
+        let amount_order = strategy.amount_order().filter(|_| cutoff);
+
+        // Each filter is a bound parameter which, when unset, matches every note, so that the
+        // statement is the same whatever the filters, and can be cached.
+        //
+        // Amounts are stored as signed integers, so those above `i64::MAX` are negative, and
+        // sorting on whether an amount is negative first orders them as unsigned.
+        let query = sqlx::query_as::<_, NoteRecord>(
+            "SELECT notes.*, block_times.block_time AS time_created
+            FROM notes
+            LEFT JOIN block_times ON notes.height_created = block_times.height
+            WHERE (?1 OR height_spent IS NULL)
+            AND asset_id IS COALESCE(?2, asset_id)
+            AND diversifier_index IS COALESCE(?3, diversifier_index)
+            AND account IS COALESCE(?4, account)
+            AND (?5 IS NULL OR julianday(block_times.block_time) >= julianday(?5))
+            AND (?6 IS NULL OR julianday(block_times.block_time) < julianday(?6))
+            AND (NOT ?7 OR notes.note_commitment NOT IN
+                (SELECT note_commitment FROM note_reservations WHERE expires_at > ?8))
+            AND (?10 = 0 OR (?10 >> COALESCE(notes.source, 0)) & 1)
+            ORDER BY ?9 * (notes.amount < 0), ?9 * notes.amount, notes.rowid",
+        )
+        // If set, return spent notes as well as unspent notes.
+        // bool include_spent = 2;
+        .bind(include_spent)
+        // If set, only return notes with the specified asset id.
+        // crypto.AssetId asset_id = 3;
+        .bind(asset_id.map(|id| id.to_bytes().to_vec()))
+        // If set, only return notes with the specified diversifier index.
+        // crypto.DiversifierIndex diversifier_index = 4;
+        .bind(diversifier_index.map(|d| d.0.to_vec()))
+        // If set, only return notes of this account.
+        .bind(account.map(i64::from))
+        // If set, only return notes created at or after / strictly before this time.
+        // Notes whose creation time is unknown are excluded by either filter.
+        // string created_after = 6;
+        // string created_before = 7;
+        .bind(created_after)
+        .bind(created_before)
+        // If set, don't return notes reserved by a transaction plan.
+        // bool exclude_reserved = 8;
+        .bind(exclude_reserved)
+        .bind(unix_now())
+        .bind(amount_order.unwrap_or(0))
+        // If nonempty, only return notes from one of these sources, as a bitmask of their codes,
+        // where `None`, i.e., an unclassified note, has code 0.
+        // repeated NoteOrigin sources = 11;
+        .bind(sources.iter().fold(0i64, |mask, source| {
+            mask | 1 << source.map_or(0, NoteOrigin::code)
+        }));
+
+        let result = if amount_order.is_some() {
+            let mut notes = query.fetch(&self.read_pool);
+            let mut total = Amount::zero();
+            let mut result = Vec::new();
+            while total < amount_to_spend {
+                let record = match notes.try_next().await? {
+                    Some(record) => record,
+                    None => break,
+                };
+                total = total
+                    .checked_add(record.note.amount())
+                    .ok_or_else(|| anyhow!("total amount of notes overflowed"))?;
+                result.push(record);
+            }
+            result
+        } else {
+            query.fetch_all(&self.read_pool).await?
+        };
+
+        if !cutoff {
+            return Ok(result);
+        }
+
+        // For the strategies cut off while loading, this only checks that the notes cover the
+        // amount, returning them unchanged.
+        strategy.select(result, amount_to_spend)
+    }
+
+    /// The total amount of each asset in the unspent notes of `account` (or of every account, if
+    /// it's `None`), only counting notes with `diversifier_index`, if set.
+    ///
+    /// This only loads the amounts and asset IDs of the notes, rather than every note.
+    pub async fn balances(
+        &self,
+        diversifier_index: Option<DiversifierIndex>,
+        account: Option<u32>,
+    ) -> anyhow::Result<BTreeMap<asset::Id, u64>> {
+        let rows: Vec<(Vec<u8>, i64)> = sqlx::query_as(
+            "SELECT asset_id, amount FROM notes
+            WHERE height_spent IS NULL
+            AND diversifier_index IS COALESCE(?, diversifier_index)
+            AND account IS COALESCE(?, account)",
+        )
+        .bind(diversifier_index.map(|index| index.0.to_vec()))
+        .bind(account.map(i64::from))
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        let mut balances = BTreeMap::new();
+        for (asset_id, amount) in rows {
+            add_amount(
+                balances
+                    .entry(asset::Id::try_from(asset_id.as_slice())?)
+                    .or_default(),
+                amount,
+            )?;
+        }
+
+        Ok(balances)
+    }
+
+    /// The total amount of each asset held in notes of `account` (or of every account, if it's
+    /// `None`) as of the block at `height`, only including `asset_id`, if it's set.
+    ///
+    /// This counts the notes created at or before `height` which weren't spent by then, so it
+    /// doesn't include notes still quarantined at `height`. Once spent notes are deleted by
+    /// [`Self::prune`], balances before the height they were pruned up to can't be reconstructed.
+    pub async fn balance_at_height(
+        &self,
+        asset_id: Option<asset::Id>,
+        height: u64,
+        account: Option<u32>,
+    ) -> anyhow::Result<BTreeMap<asset::Id, u64>> {
+        let sync_height = self.last_sync_height().await?;
+        if sync_height.map_or(true, |sync_height| height > sync_height) {
+            return Err(anyhow!(
+                "can't reconstruct the balance at height {}, since the view data is only synced to height {:?}",
+                height,
+                sync_height
+            ));
+        }
+
+        let (pruned_height,): (Option<i64>,) =
+            sqlx::query_as("SELECT MAX(height) FROM pruned_height")
+                .fetch_one(&self.read_pool)
+                .await?;
+        let height = height_bounds(..=height).1;
+        if let Some(pruned_height) = pruned_height.filter(|&pruned| height < pruned) {
+            return Err(anyhow!(
+                "can't reconstruct the balance at height {}, since notes spent up to height {} were pruned",
+                height,
+                pruned_height
+            ));
+        }
+
+        let rows: Vec<(Vec<u8>, i64)> = sqlx::query_as(
+            "SELECT asset_id, amount FROM notes
+            WHERE height_created <= ?1
+            AND (height_spent IS NULL OR height_spent > ?1)
+            AND asset_id IS COALESCE(?2, asset_id)
+            AND account IS COALESCE(?3, account)",
+        )
+        .bind(height)
+        .bind(asset_id.map(|id| id.to_bytes().to_vec()))
+        .bind(account.map(i64::from))
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        let mut balances = BTreeMap::new();
+        for (asset_id, amount) in rows {
+            add_amount(
+                balances
+                    .entry(asset::Id::try_from(asset_id.as_slice())?)
+                    .or_default(),
+                amount,
+            )?;
+        }
+
+        Ok(balances)
+    }
+
+    /// The total amount of each asset in the unspent notes of `account` (or of every account, if
+    /// it's `None`), for each diversifier index with any, summed in a single query.
+    pub async fn balances_by_index(
+        &self,
+        account: Option<u32>,
+    ) -> anyhow::Result<BTreeMap<DiversifierIndex, BTreeMap<asset::Id, u64>>> {
+        let rows: Vec<(Vec<u8>, Vec<u8>, i64)> = sqlx::query_as(
+            "SELECT diversifier_index, asset_id, amount FROM notes
+            WHERE height_spent IS NULL
+            AND account IS COALESCE(?, account)",
+        )
+        .bind(account.map(i64::from))
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        let mut balances = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for (diversifier_index, asset_id, amount) in rows {
+            add_amount(
+                balances
+                    .entry(DiversifierIndex::try_from(diversifier_index.as_slice())?)
+                    .or_default()
+                    .entry(asset::Id::try_from(asset_id.as_slice())?)
+                    .or_default(),
+                amount,
+            )?;
+        }
+
+        Ok(balances)
+    }
+
+    /// The quarantined notes of `account` (or of every account, if it's `None`), only including
+    /// notes quarantined for `identity_key`, which exit quarantine at or before
+    /// `max_unbonding_epoch`, and of `asset_id`, for each filter that's set.
+    pub async fn quarantined_notes(
+        &self,
+        account: Option<u32>,
+        identity_key: Option<&IdentityKey>,
+        max_unbonding_epoch: Option<u64>,
+        asset_id: Option<asset::Id>,
+    ) -> anyhow::Result<Vec<QuarantinedNoteRecord>> {
+        let result = sqlx::query_as::<_, QuarantinedNoteRecord>(
+            "SELECT * FROM quarantined_notes
+            WHERE account IS COALESCE(?, account)
+            AND identity_key IS COALESCE(?, identity_key)
+            AND unbonding_epoch <= COALESCE(?, unbonding_epoch)
+            AND asset_id IS COALESCE(?, asset_id)",
+        )
+        .bind(account.map(i64::from))
+        .bind(identity_key.map(|identity_key| identity_key.encode_to_vec()))
+        .bind(max_unbonding_epoch.map(|epoch| epoch.min(i64::MAX as u64) as i64))
+        .bind(asset_id.map(|id| id.to_bytes().to_vec()))
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// The total amount of each asset in the quarantined notes of `account` (or of every
+    /// account, if it's `None`), by the validator they're quarantined for, and then by the epoch
+    /// they exit quarantine.
+    pub async fn quarantined_balance_by_validator(
+        &self,
+        account: Option<u32>,
+    ) -> anyhow::Result<BTreeMap<IdentityKey, BTreeMap<u64, BTreeMap<asset::Id, u64>>>> {
+        let rows: Vec<(Vec<u8>, i64, Vec<u8>, i64)> = sqlx::query_as(
+            "SELECT identity_key, unbonding_epoch, asset_id, amount FROM quarantined_notes
+            WHERE account IS COALESCE(?, account)",
+        )
+        .bind(account.map(i64::from))
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        let mut balances = BTreeMap::<_, BTreeMap<_, BTreeMap<_, _>>>::new();
+        for (identity_key, unbonding_epoch, asset_id, amount) in rows {
+            add_amount(
+                balances
+                    .entry(IdentityKey::decode(identity_key.as_slice())?)
+                    .or_default()
+                    .entry(unbonding_epoch as u64)
+                    .or_default()
+                    .entry(asset::Id::try_from(asset_id.as_slice())?)
+                    .or_default(),
+                amount,
+            )?;
+        }
+
+        Ok(balances)
+    }
+
+    /// Soft-reserve the notes with the given commitments for the transaction plan `plan_id`, for
+    /// [`NOTE_RESERVATION_TTL`].
+    ///
+    /// This lets concurrent clients planning against the same storage avoid selecting the same
+    /// notes, rather than having one of their transactions fail at the node. Reserving notes
+    /// already reserved by the same plan renews the reservation; if any note is reserved by a
+    /// different plan, nothing is reserved and an error is returned.
+    pub async fn reserve_notes(
+        &self,
+        plan_id: &[u8],
+        commitments: &[tct::Commitment],
+    ) -> anyhow::Result<()> {
+        let now = unix_now();
+        let expires_at = now + NOTE_RESERVATION_TTL.as_secs() as i64;
+
+        let mut tx = self.pool.begin().await?;
+
+        // Forget lapsed reservations.
+        sqlx::query("DELETE FROM note_reservations WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&mut tx)
+            .await?;
+
+        for commitment in commitments {
+            let note_commitment = commitment.0.to_bytes().to_vec();
+
+            let reserved_by: Option<(Vec<u8>,)> =
+                sqlx::query_as("SELECT plan_id FROM note_reservations WHERE note_commitment = ?")
+                    .bind(&note_commitment)
+                    .fetch_optional(&mut tx)
+                    .await?;
+            if let Some((reserved_by,)) = reserved_by {
+                if reserved_by != plan_id {
+                    // Dropping the transaction rolls back any reservations made so far.
+                    return Err(anyhow!(
+                        "note {} is already reserved by another transaction plan",
+                        commitment
+                    ));
+                }
+            }
+
+            sqlx::query(
+                "INSERT OR REPLACE INTO note_reservations (note_commitment, plan_id, expires_at)
+                VALUES (?, ?, ?)",
+            )
+            .bind(note_commitment)
+            .bind(plan_id)
+            .bind(expires_at)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Release the reservations of the notes with the given commitments, e.g., because the plan
+    /// they were reserved for couldn't be completed, so that other plans can select them again.
+    ///
+    /// Notes which aren't reserved are ignored.
+    pub async fn release_notes(&self, commitments: &[tct::Commitment]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for commitment in commitments {
+            sqlx::query("DELETE FROM note_reservations WHERE note_commitment = ?")
+                .bind(commitment.0.to_bytes().to_vec())
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn record_asset(&self, asset: Asset) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let asset_id = asset.id.to_bytes().to_vec();
+        let denom = asset.denom.to_string();
+        sqlx::query!(
+            "INSERT INTO assets
+                    (
+                        asset_id,
+                        denom
+                    )
+                    VALUES
+                    (
+                        ?,
+                        ?
+                    )",
+            asset_id,
+            denom,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The heights of blocks in which our notes were created or spent, but whose timestamps we
+    /// haven't recorded, e.g., because they were scanned before we recorded block times.
+    pub async fn heights_missing_block_times(&self) -> anyhow::Result<Vec<u64>> {
+        let heights: Vec<(i64,)> = sqlx::query_as(
+            "SELECT height FROM (
+                SELECT height_created AS height FROM notes
+                UNION SELECT height_spent AS height FROM notes WHERE height_spent IS NOT NULL
+                UNION SELECT height_created AS height FROM quarantined_notes
+            )
+            WHERE height NOT IN (SELECT height FROM block_times)
+            ORDER BY height",
+        )
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        Ok(heights.into_iter().map(|(height,)| height as u64).collect())
+    }
+
+    pub async fn record_block_time(&self, height: u64, block_time: String) -> anyhow::Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO block_times (height, block_time) VALUES (?, ?)")
+            .bind(height as i64)
+            .bind(block_time)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// The heights of blocks in which our notes were created or spent, but whose transactions we
+    /// haven't recorded.
+    pub async fn heights_missing_transactions(&self) -> anyhow::Result<Vec<u64>> {
+        let heights: Vec<(i64,)> = sqlx::query_as(
+            "SELECT height FROM (
+                SELECT height_created AS height FROM notes
+                UNION SELECT height_spent AS height FROM notes WHERE height_spent IS NOT NULL
+            )
+            WHERE height NOT IN (SELECT height FROM transaction_heights)
+            ORDER BY height",
+        )
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        Ok(heights.into_iter().map(|(height,)| height as u64).collect())
+    }
+
+    /// Our notes which were created or spent at `height`.
+    pub async fn notes_at_height(&self, height: u64) -> anyhow::Result<Vec<NoteRecord>> {
+        let notes = sqlx::query_as::<_, NoteRecord>(
+            "SELECT notes.*, block_times.block_time AS time_created
+            FROM notes
+            LEFT JOIN block_times ON notes.height_created = block_times.height
+            WHERE height_created = ? OR height_spent = ?",
+        )
+        .bind(height as i64)
+        .bind(height as i64)
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        Ok(notes)
+    }
+
+    /// Record the `transactions` at `height` which created or spent our notes, marking the
+    /// transactions at that height as recorded.
+    pub async fn record_transactions(
+        &self,
+        height: u64,
+        transactions: &[TransactionInfo],
+    ) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for transaction in transactions {
+            let tx_hash = transaction.tx_hash.to_vec();
+            sqlx::query(
+                "INSERT OR REPLACE INTO transactions (tx_hash, height, fee, memo)
+                VALUES (?, ?, ?, ?)",
+            )
+            .bind(&tx_hash)
+            .bind(transaction.height as i64)
+            .bind(transaction.fee as i64)
+            .bind(&transaction.memo)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query("DELETE FROM transaction_notes WHERE tx_hash = ?")
+                .bind(&tx_hash)
+                .execute(&mut tx)
+                .await?;
+            let notes = transaction
+                .spends
+                .iter()
+                .map(|record| (record, true))
+                .chain(transaction.outputs.iter().map(|record| (record, false)));
+            for (record, spent) in notes {
+                sqlx::query(
+                    "INSERT INTO transaction_notes (tx_hash, note_commitment, spent)
+                    VALUES (?, ?, ?)",
+                )
+                .bind(&tx_hash)
+                .bind(record.note_commitment.0.to_bytes().to_vec())
+                .bind(spent)
+                .execute(&mut tx)
+                .await?;
+            }
+
+            // A transaction spending our notes is our own, so its outputs to us are change.
+            let source = if transaction.spends.is_empty() {
+                NoteOrigin::Received
+            } else {
+                NoteOrigin::Change
+            };
+            for record in &transaction.outputs {
+                sqlx::query("UPDATE notes SET source = ? WHERE note_commitment = ?")
+                    .bind(source.code())
+                    .bind(record.note_commitment.0.to_bytes().to_vec())
+                    .execute(&mut tx)
+                    .await?;
+            }
+
+            sqlx::query("DELETE FROM transaction_sent_outputs WHERE tx_hash = ?")
+                .bind(&tx_hash)
+                .execute(&mut tx)
+                .await?;
+            for sent in &transaction.sent {
+                sqlx::query(
+                    "INSERT INTO transaction_sent_outputs (tx_hash, note, memo) VALUES (?, ?, ?)",
+                )
+                .bind(&tx_hash)
+                .bind(sent.note.to_bytes().to_vec())
+                .bind(&sent.memo)
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+
+        // The notes created at this height by none of its transactions were minted at the end of
+        // the block, as staking rewards.
+        sqlx::query("UPDATE notes SET source = ? WHERE height_created = ? AND source IS NULL")
+            .bind(NoteOrigin::StakingReward.code())
+            .bind(height as i64)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("INSERT OR REPLACE INTO transaction_heights (height) VALUES (?)")
+            .bind(height as i64)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The transactions which created or spent the notes of `account`, or of every account if
+    /// it's `None`, in the given range of heights, ordered by height.
+    pub async fn transactions(
+        &self,
+        range: impl RangeBounds<u64>,
+        account: Option<u32>,
+    ) -> anyhow::Result<Vec<TransactionInfo>> {
+        let (start_height, end_height) = height_bounds(range);
+
+        let rows: Vec<(Vec<u8>, i64, i64, Option<String>)> = sqlx::query_as(
+            "SELECT tx_hash, height, fee, memo FROM transactions
+            WHERE height >= ? AND height <= ?
+            ORDER BY height, tx_hash",
+        )
+        .bind(start_height)
+        .bind(end_height)
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        let mut transactions = Vec::with_capacity(rows.len());
+        for (tx_hash, height, fee, memo) in rows {
+            let spends = self.transaction_notes(&tx_hash, true, account).await?;
+            let outputs = self.transaction_notes(&tx_hash, false, account).await?;
+            if spends.is_empty() && outputs.is_empty() {
+                // The transaction only involved other accounts.
+                continue;
+            }
+            // Only the account which spent notes in the transaction sent its outputs.
+            let sent = if spends.is_empty() {
+                Vec::new()
+            } else {
+                self.transaction_sent_outputs(&tx_hash).await?
+            };
+
+            transactions.push(TransactionInfo {
+                tx_hash: tx_hash
+                    .try_into()
+                    .map_err(|_| anyhow!("transaction hash must be 32 bytes"))?,
+                height: height as u64,
+                spends,
+                outputs,
+                fee: fee as u64,
+                memo,
+                sent,
+            });
+        }
+
+        Ok(transactions)
+    }
+
+    /// Our notes spent, or created, by the transaction with hash `tx_hash`.
+    async fn transaction_sent_outputs(&self, tx_hash: &[u8]) -> anyhow::Result<Vec<SentOutput>> {
+        let rows: Vec<(Vec<u8>, Option<String>)> =
+            sqlx::query_as("SELECT note, memo FROM transaction_sent_outputs WHERE tx_hash = ?")
+                .bind(tx_hash)
+                .fetch_all(&self.read_pool)
+                .await?;
+
+        rows.into_iter()
+            .map(|(note, memo)| {
+                Ok(SentOutput {
+                    note: Note::try_from(note.as_slice())?,
+                    memo,
+                })
+            })
+            .collect()
+    }
+
+    async fn transaction_notes(
+        &self,
+        tx_hash: &[u8],
+        spent: bool,
+        account: Option<u32>,
+    ) -> anyhow::Result<Vec<NoteRecord>> {
+        let notes = sqlx::query_as::<_, NoteRecord>(
+            "SELECT notes.*, block_times.block_time AS time_created
+            FROM transaction_notes
+            JOIN notes ON transaction_notes.note_commitment = notes.note_commitment
+            LEFT JOIN block_times ON notes.height_created = block_times.height
+            WHERE transaction_notes.tx_hash = ? AND transaction_notes.spent = ?
+            AND notes.account IS COALESCE(?, notes.account)",
+        )
+        .bind(tx_hash)
+        .bind(spent)
+        .bind(account.map(i64::from))
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        Ok(notes)
+    }
+
+    pub async fn record_empty_block(&self, height: u64) -> anyhow::Result<()> {
+        //Check that the incoming block height follows the latest recorded height
+        let last_sync_height = self.last_sync_height().await?.ok_or_else(|| {
+            anyhow::anyhow!("invalid: tried to record empty block as genesis block")
+        })?;
+
+        if height != last_sync_height + 1 {
+            return Err(anyhow::anyhow!(
+                "Wrong block height {} for latest sync height {}",
+                height,
+                last_sync_height
+            ));
+        }
+
+        // Empty blocks aren't written, so renew the lease while waiting for one that is.
+        let renew_after = unix_now() + (WRITER_LEASE_TTL.as_secs() / 2) as i64;
+        if self
+            .writer_lease_expires_at
+            .lock()
+            .map_or(true, |expires_at| expires_at <= renew_after)
+        {
+            self.acquire_writer_lease().await?;
+        }
+
+        *self.uncommitted_height.lock() = Some(height.try_into().unwrap());
+        self.sync_height_tx.send_replace(Some(height));
+        Ok(())
+    }
+
+    /// Take or renew the lease on recording blocks into this database, failing with a
+    /// [`WriterLeaseHeldError`] if another view worker holds it.
+    ///
+    /// Recording blocks, or resetting to a height, takes or renews the lease too, so a worker
+    /// only needs to call this on startup, to find out early whether it can write.
+    pub async fn acquire_writer_lease(&self) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let expires_at = claim_writer_lease(&mut tx, &self.writer_id).await?;
+        tx.commit().await?;
+        *self.writer_lease_expires_at.lock() = Some(expires_at);
+
+        Ok(())
+    }
+
+    pub async fn record_block(
+        &self,
+        scan_result: ScanResult,
+        nct: &mut tct::Tree,
+    ) -> anyhow::Result<()> {
+        self.record_blocks(vec![scan_result], nct).await
+    }
+
+    /// Record a run of consecutive blocks in a single database transaction, which is much faster
+    /// than recording them one at a time during a long sync.
+    ///
+    /// The `nct` must already include every block in the run, and it is persisted as of the last
+    /// one. Blocks with nothing in them are represented by an empty [`ScanResult`] at their height.
+    pub async fn record_blocks(
+        &self,
+        mut scan_results: Vec<ScanResult>,
+        nct: &mut tct::Tree,
+    ) -> anyhow::Result<()> {
+        let (first_height, last_height) = match (scan_results.first(), scan_results.last()) {
+            (Some(first), Some(last)) => (first.height, last.height),
+            _ => return Ok(()),
+        };
+
+        //Check that the incoming block height follows the latest recorded height
+        let last_sync_height = self.last_sync_height().await?;
+
+        let correct_height = match last_sync_height {
+            // Require that the new block follows the last one we scanned.
+            Some(cur_height) => first_height == cur_height + 1,
+            // Require that the new block represents the initial chain state.
+            None => first_height == 0,
+        };
+
+        if !correct_height {
+            return Err(anyhow::anyhow!(
+                "Wrong block height {} for latest sync height {:?}",
+                first_height,
+                last_sync_height
+            ));
+        }
+        // ...and that the blocks follow one another.
+        for pair in scan_results.windows(2) {
+            if pair[1].height != pair[0].height + 1 {
+                return Err(anyhow::anyhow!(
+                    "Wrong block height {} following block {}",
+                    pair[1].height,
+                    pair[0].height
+                ));
+            }
+        }
+
+        // Read before the transaction starts, since in memory, reads share the writer's connection.
+        let epoch_duration = self.chain_params().await?.epoch_duration;
+
+        let mut tx = self.pool.begin().await?;
+        let lease_expires_at = claim_writer_lease(&mut tx, &self.writer_id).await?;
+        let mut note_events = Vec::new();
+
+        // The whole tree is only written every so often, as a snapshot which scanning can also
+        // later resume from after a rollback. In between, only the changes made by each block are.
+        let (last_checkpoint,): (Option<i64>,) =
+            sqlx::query_as("SELECT MAX(height) FROM note_commitment_tree_checkpoints")
+                .fetch_one(&mut tx)
+                .await?;
+        let take_snapshot = last_checkpoint.map_or(true, |checkpoint| {
+            last_height >= checkpoint as u64 + NCT_CHECKPOINT_INTERVAL
+        });
+
+        if !take_snapshot {
+            // Empty blocks passed to `record_empty_block` since the last run aren't in the
+            // database, but the tree was sealed for each of them.
+            let (recorded_height,): (i64,) = sqlx::query_as("SELECT height FROM sync_height")
+                .fetch_one(&mut tx)
+                .await?;
+            for height in (recorded_height + 1) as u64..first_height {
+                record_nct_updates(
+                    &mut tx,
+                    height,
+                    &empty_block_nct_updates(height, epoch_duration),
+                )
+                .await?;
+            }
+        }
+
+        for scan_result in &mut scan_results {
+            // Drop the notes of assets which aren't allowed, forgetting their commitments, which
+            // were witnessed while scanning.
+            if let Some(allowlist) = self.asset_allowlist() {
+                let mut forgotten = Vec::new();
+                scan_result.new_notes.retain(|record| {
+                    let allowed = allowlist.contains(&record.note.asset_id());
+                    if !allowed {
+                        nct.forget(record.note_commitment);
+                        forgotten.push(NctUpdate::Forget(record.note_commitment));
+                    }
+                    allowed
+                });
+                scan_result.nct_updates.extend(forgotten);
+                scan_result
+                    .new_quarantined_notes
+                    .retain(|record| allowlist.contains(&record.note.asset_id()));
+            }
+
+            insert_block(&mut tx, scan_result, nct, &mut note_events).await?;
+
+            if !take_snapshot {
+                record_nct_updates(&mut tx, scan_result.height, &scan_result.nct_updates).await?;
+            }
+        }
+
+        if take_snapshot {
+            let nct_bytes = bincode::serialize(nct)?;
+            sqlx::query(
+                "INSERT OR REPLACE INTO note_commitment_tree_checkpoints (height, bytes) VALUES (?, ?)",
+            )
+            .bind(last_height as i64)
+            .bind(nct_bytes.as_slice())
+            .execute(&mut tx)
+            .await?;
+            sqlx::query(
+                "DELETE FROM note_commitment_tree_checkpoints WHERE height NOT IN
+                    (SELECT height FROM note_commitment_tree_checkpoints ORDER BY height DESC LIMIT ?)",
+            )
+            .bind(NCT_CHECKPOINTS_RETAINED)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query!("UPDATE note_commitment_tree SET bytes = ?", nct_bytes)
+                .execute(&mut tx)
+                .await?;
+            sqlx::query("DELETE FROM note_commitment_tree_updates")
+                .execute(&mut tx)
+                .await?;
+        }
+
+        // Record block height as latest synced height
+
+        let latest_sync_height = last_height as i64;
+        sqlx::query!("UPDATE sync_height SET height = ?", latest_sync_height)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+        *self.writer_lease_expires_at.lock() = Some(lease_expires_at);
+        // It's critical to reset the uncommitted height here, since we've just
+        // invalidated it by committing.
+        self.uncommitted_height.lock().take();
+        self.sync_height_tx.send_replace(Some(last_height));
+
+        // Broadcast all committed note records to channel
+        // Done following tx.commit() to avoid notifying of a new NoteRecord before it is actually committed to the database
+
+        for note_record in scan_results
+            .into_iter()
+            .flat_map(|scan_result| scan_result.new_notes)
+        {
+            // This will fail to be broadcast if there is no active receiver (such as on initial sync)
+            // The error is ignored, as this isn't a problem, because if there is no active receiver there is nothing to do
+            let _ = self.scanned_notes_tx.send(note_record);
+        }
+        for (account, event) in note_events {
+            // As above, there may be no subscribers.
+            let _ = self.note_events_tx.send((Some(account), event));
+        }
+
+        Ok(())
+    }
+
+    /// Roll back everything scanned after `height`, so that the chain is rescanned from there,
+    /// e.g., after a fix to note detection, returning the height scanning resumes after.
+    ///
+    /// Scanning can only resume from a snapshot of the note commitment tree, which are taken
+    /// every [`NCT_CHECKPOINT_INTERVAL`] blocks, so this rolls back to the latest snapshot at or
+    /// before `height`, or to genesis if there's none, in which case `None` is returned. This
+    /// must not be called while a worker is scanning into this storage: a running view service
+    /// resets its storage through the `ResetToHeight` RPC instead.
+    pub async fn reset_to_height(&self, height: u64) -> anyhow::Result<Option<u64>> {
+        let mut tx = self.pool.begin().await?;
+        let lease_expires_at = claim_writer_lease(&mut tx, &self.writer_id).await?;
+
+        let checkpoint: Option<(i64, Vec<u8>)> = sqlx::query_as(
+            "SELECT height, bytes FROM note_commitment_tree_checkpoints
+            WHERE height <= ? ORDER BY height DESC LIMIT 1",
+        )
+        .bind(height_bounds(..=height).1)
+        .fetch_optional(&mut tx)
+        .await?;
+        let resume_height = match checkpoint {
+            Some((checkpoint_height, nct_bytes)) => {
+                roll_back_scanned(&mut tx, checkpoint_height, &nct_bytes).await?;
+                Some(checkpoint_height as u64)
+            }
+            None => {
+                reset_to_genesis(&mut tx).await?;
+                None
+            }
+        };
+
+        tx.commit().await?;
+        *self.writer_lease_expires_at.lock() = Some(lease_expires_at);
+        self.uncommitted_height.lock().take();
+        self.sync_height_tx.send_replace(resume_height);
+        self.send_rolled_back(resume_height);
+
+        Ok(resume_height)
+    }
+
+    /// Delete the notes spent more than `keep_spent_for_blocks` blocks before the last block
+    /// scanned, along with what's left of them in quarantine, so that the database doesn't grow
+    /// forever, returning what was deleted.
+    ///
+    /// Only notes spent no later than the oldest snapshot of the note commitment tree are deleted,
+    /// since rolling back to a snapshot would otherwise rescan their spends without the notes
+    /// to forget from the tree. Notes whose commitments are still in the tree, like notes whose
+    /// spends are quarantined and could be rolled back, are kept. Deleted notes are missing from
+    /// the notes of the transactions which created and spent them.
+    ///
+    /// If enough of the database is then free, it's vacuumed. Like [`Self::reset_to_height`],
+    /// this must not be called while a worker is scanning into this storage.
+    pub async fn prune(&self, keep_spent_for_blocks: u64) -> anyhow::Result<PruneSummary> {
+        let mut tx = self.pool.begin().await?;
+        let lease_expires_at = claim_writer_lease(&mut tx, &self.writer_id).await?;
+
+        let (sync_height,): (i64,) = sqlx::query_as("SELECT height FROM sync_height")
+            .fetch_one(&mut tx)
+            .await?;
+        let (oldest_checkpoint,): (Option<i64>,) =
+            sqlx::query_as("SELECT MIN(height) FROM note_commitment_tree_checkpoints")
+                .fetch_one(&mut tx)
+                .await?;
+        // If nothing was scanned yet, there are no snapshots either, so nothing is pruned.
+        let cutoff = (sync_height - height_bounds(..=keep_spent_for_blocks).1)
+            .min(oldest_checkpoint.unwrap_or(-1));
+
+        let nct = load_nct(&mut tx).await?;
+        let spent: Vec<(Vec<u8>,)> = sqlx::query_as(
+            "SELECT note_commitment FROM notes
+            WHERE height_spent <= ?
+            AND nullifier NOT IN (SELECT nullifier FROM quarantined_nullifiers)",
+        )
+        .bind(cutoff)
+        .fetch_all(&mut tx)
+        .await?;
+
+        let mut summary = PruneSummary::default();
+        for (commitment_bytes,) in spent {
+            if nct
+                .witness(Commitment::try_from(commitment_bytes.as_slice())?)
+                .is_some()
+            {
+                tracing::warn!(
+                    commitment = %hex::encode(&commitment_bytes),
+                    "not pruning spent note whose commitment is still in the tree"
+                );
+                continue;
+            }
+            for table in ["notes", "note_reservations", "transaction_notes"] {
+                sqlx::query(&format!("DELETE FROM {} WHERE note_commitment = ?", table))
+                    .bind(commitment_bytes.as_slice())
+                    .execute(&mut tx)
+                    .await?;
+            }
+            summary.notes += 1;
+        }
+
+        // After a rollback, the cutoff may be lower than one notes were already pruned up to.
+        if summary.notes > 0 {
+            sqlx::query("DELETE FROM pruned_height WHERE height < ?")
+                .bind(cutoff)
+                .execute(&mut tx)
+                .await?;
+            sqlx::query(
+                "INSERT INTO pruned_height (height) SELECT ?
+                WHERE NOT EXISTS (SELECT 1 FROM pruned_height)",
+            )
+            .bind(cutoff)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        summary.quarantined_nullifiers = sqlx::query(
+            "DELETE FROM quarantined_nullifiers WHERE height <= ?
+            AND nullifier NOT IN (SELECT nullifier FROM notes)",
+        )
+        .bind(cutoff)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+        *self.writer_lease_expires_at.lock() = Some(lease_expires_at);
+        tracing::info!(?summary, cutoff, "pruned view storage");
+
+        // Deleted rows only free pages for reuse, so the file only shrinks once it's vacuumed,
+        // which rewrites the whole database, and is only worth it once enough of it is free.
+        let (free_pages,): (i64,) = sqlx::query_as("PRAGMA freelist_count")
+            .fetch_one(&self.pool)
+            .await?;
+        let (pages,): (i64,) = sqlx::query_as("PRAGMA page_count")
+            .fetch_one(&self.pool)
+            .await?;
+        if free_pages > 0 && free_pages * VACUUM_FREE_FRACTION >= pages {
+            sqlx::query("VACUUM").execute(&self.pool).await?;
+            summary.vacuumed = true;
+        }
+
+        Ok(summary)
+    }
+
+    /// Write a snapshot of everything scanned into this storage, as of the last block committed
+    /// to the database, to `path`.
+    ///
+    /// [`Self::import_snapshot`] restores it into storage for the same accounts, e.g., on another
+    /// machine, without rescanning the chain.
+    pub async fn export_snapshot(&self, path: impl AsRef<Utf8Path>) -> anyhow::Result<()> {
+        let fvk_hashes = self
+            .accounts()
+            .await?
+            .iter()
+            .map(Account::fvk_hash)
+            .collect();
+
+        // Read everything else in one transaction, so that a block recorded meanwhile can't be
+        // half included.
+        let mut tx = self.read_pool.begin().await?;
+        let chain_params: (Vec<u8>,) = sqlx::query_as("SELECT bytes FROM chain_params LIMIT 1")
+            .fetch_one(&mut tx)
+            .await?;
+        let sync_height: (i64,) =
+            sqlx::query_as("SELECT height FROM sync_height ORDER BY height DESC LIMIT 1")
+                .fetch_one(&mut tx)
+                .await?;
+        let nct = load_nct(&mut tx).await?;
+        let snapshot = Snapshot {
+            fvk_hashes,
+            chain_params: chain_params.0,
+            sync_height: sync_height.0,
+            nct: bincode::serialize(&nct)?,
+            notes: sqlx::query_as("SELECT * FROM notes")
+                .fetch_all(&mut tx)
+                .await?,
+            quarantined_notes: sqlx::query_as("SELECT * FROM quarantined_notes")
+                .fetch_all(&mut tx)
+                .await?,
+            quarantined_nullifiers: sqlx::query_as("SELECT * FROM quarantined_nullifiers")
+                .fetch_all(&mut tx)
+                .await?,
+            assets: sqlx::query_as("SELECT * FROM assets")
+                .fetch_all(&mut tx)
+                .await?,
+        };
+        tx.commit().await?;
+
+        let path = path.as_ref();
+        tokio::fs::write(path, snapshot.encode()?)
+            .await
+            .with_context(|| format!("could not write view storage snapshot to {}", path))
+    }
+
+    /// Replace everything scanned into this storage with the snapshot at `path`, written by
+    /// [`Self::export_snapshot`], so that scanning resumes after the block it was taken at.
+    ///
+    /// The snapshot must be of storage with the same accounts, in the same order, for the same
+    /// chain. Like [`Self::reset_to_height`], this must not be called while a worker is scanning
+    /// into this storage.
+    pub async fn import_snapshot(&self, path: impl AsRef<Utf8Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let bytes = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("could not read view storage snapshot from {}", path))?;
+        let snapshot = Snapshot::decode(&bytes)?;
+
+        let fvk_hashes = self
+            .accounts()
+            .await?
+            .iter()
+            .map(Account::fvk_hash)
+            .collect::<Vec<_>>();
+        if let (Some(stored), Some(expected)) = (snapshot.fvk_hashes.first(), fvk_hashes.first()) {
+            if stored != expected {
+                return Err(FvkMismatchError {
+                    stored: *stored,
+                    expected: *expected,
+                }
+                .into());
+            }
+        }
+        if snapshot.fvk_hashes != fvk_hashes {
+            return Err(anyhow!(
+                "view storage snapshot has {} accounts, which don't match this storage's {}",
+                snapshot.fvk_hashes.len(),
+                fvk_hashes.len()
+            ));
+        }
+        let chain_id = ChainParams::decode(snapshot.chain_params.as_slice())?.chain_id;
+        let expected_chain_id = self.chain_params().await?.chain_id;
+        if chain_id != expected_chain_id {
+            return Err(anyhow!(
+                "view storage snapshot is of chain {:?}, not {:?}",
+                chain_id,
+                expected_chain_id
+            ));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let lease_expires_at = claim_writer_lease(&mut tx, &self.writer_id).await?;
+        reset_to_genesis(&mut tx).await?;
+        restore_snapshot(&mut tx, &snapshot).await?;
+        tx.commit().await?;
+
+        let sync_height = u64::try_from(snapshot.sync_height).ok();
+        tracing::info!(?sync_height, "imported view storage snapshot");
+        *self.writer_lease_expires_at.lock() = Some(lease_expires_at);
+        self.uncommitted_height.lock().take();
+        self.sync_height_tx.send_replace(sync_height);
+        // Everything scanned was replaced by the snapshot's notes.
+        self.send_rolled_back(None);
+
+        Ok(())
+    }
+}
+
+/// Load the latest snapshot of the note commitment tree, with the changes made by each block
+/// since replayed onto it.
+async fn load_nct(tx: &mut sqlx::Transaction<'_, Sqlite>) -> anyhow::Result<tct::Tree> {
+    let result = query!(
+        r#"
+        SELECT bytes
+        FROM note_commitment_tree
+        LIMIT 1
+        "#
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let updates: Vec<(Vec<u8>,)> =
+        sqlx::query_as("SELECT updates FROM note_commitment_tree_updates ORDER BY height")
+            .fetch_all(&mut *tx)
+            .await?;
+
+    let mut nct: tct::Tree = bincode::deserialize(result.bytes.as_slice())?;
+    for (updates,) in updates {
+        for update in bincode::deserialize::<Vec<NctUpdate>>(&updates)? {
+            update.apply(&mut nct)?;
+        }
+    }
+
+    Ok(nct)
+}
+
+/// Insert the contents of one block into the database, forgetting spent note commitments from the
+/// `nct`, and adding the changes to our notes to `note_events`.
+async fn insert_block(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    scan_result: &mut ScanResult,
+    nct: &mut tct::Tree,
+    note_events: &mut Vec<(u32, NoteEvent)>,
+) -> anyhow::Result<()> {
+    // Record the block's timestamp, so that notes created or spent in it can be dated
+    if let Some(block_time) = &scan_result.block_time {
+        sqlx::query("INSERT OR REPLACE INTO block_times (height, block_time) VALUES (?, ?)")
+            .bind(scan_result.height as i64)
+            .bind(block_time)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // Insert all quarantined note commitments into storage
+    for quarantined_note_record in &scan_result.new_quarantined_notes {
+        let note_commitment = quarantined_note_record
+            .note_commitment
+            .0
+            .to_bytes()
+            .to_vec();
+        let height_created = scan_result.height as i64;
+        let diversifier = quarantined_note_record.note.diversifier().0.to_vec();
+        let amount = quarantined_note_record.note.amount().value() as i64;
+        let asset_id = quarantined_note_record.note.asset_id().to_bytes().to_vec();
+        let transmission_key = quarantined_note_record.note.transmission_key().0.to_vec();
+        let blinding_factor = quarantined_note_record
+            .note
+            .note_blinding()
+            .to_bytes()
+            .to_vec();
+        let diversifier_index = quarantined_note_record.diversifier_index.0.to_vec();
+        let unbonding_epoch = quarantined_note_record.unbonding_epoch as i64;
+        let identity_key = quarantined_note_record.identity_key.encode_to_vec();
+        sqlx::query!(
+            "INSERT INTO quarantined_notes
+                    (
+                        note_commitment,
+                        height_created,
+                        diversifier,
+                        amount,
+                        asset_id,
+                        transmission_key,
+                        blinding_factor,
+                        diversifier_index,
+                        unbonding_epoch,
+                        identity_key
+                    )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            note_commitment,
+            height_created,
+            diversifier,
+            amount,
+            asset_id,
+            transmission_key,
+            blinding_factor,
+            diversifier_index,
+            unbonding_epoch,
+            identity_key,
+        )
+        .execute(&mut *tx)
+        .await?;
+        set_note_account(
+            tx,
+            "quarantined_notes",
+            &quarantined_note_record.note_commitment,
+            scan_result,
+        )
+        .await?;
+        note_events.push((
+            note_account(&quarantined_note_record.note_commitment, scan_result),
+            NoteEvent::Quarantined(quarantined_note_record.clone()),
+        ));
+    }
+
+    // Insert all new note records into storage
+    for note_record in &scan_result.new_notes {
+        // https://github.com/launchbadge/sqlx/issues/1430
+        // https://github.com/launchbadge/sqlx/issues/1151
+        // For some reason we can't use any temporaries with the query! macro
+        // any more, even though we did so just fine in the past, e.g.,
+        // https://github.com/penumbra-zone/penumbra/blob/e857a7ae2b11b36514a5ac83f8e0b174fa10a65f/pd/src/state/writer.rs#L201-L207
+        let note_commitment = note_record.note_commitment.0.to_bytes().to_vec();
+        let height_created = scan_result.height as i64;
+        let diversifier = note_record.note.diversifier().0.to_vec();
+        let amount = note_record.note.amount().value() as i64;
+        let asset_id = note_record.note.asset_id().to_bytes().to_vec();
+        let transmission_key = note_record.note.transmission_key().0.to_vec();
+        let blinding_factor = note_record.note.note_blinding().to_bytes().to_vec();
+        let diversifier_index = note_record.diversifier_index.0.to_vec();
+        let nullifier = note_record.nullifier.to_bytes().to_vec();
+        let position = (u64::from(note_record.position)) as i64;
+        sqlx::query!(
+            "INSERT INTO notes
+                    (
+                        note_commitment,
+                        height_spent,
+                        height_created,
+                        diversifier,
+                        amount,
+                        asset_id,
+                        transmission_key,
+                        blinding_factor,
+                        diversifier_index,
+                        nullifier,
+                        position
+                    )
+                    VALUES
+                    (
+                        ?,
+                        NULL,
+                        ?,
+                        ?,
+                        ?,
+                        ?,
+                        ?,
+                        ?,
+                        ?,
+                        ?,
+                        ?
+                    )",
+            note_commitment,
+            // height_spent is NULL
+            height_created,
+            diversifier,
+            amount,
+            asset_id,
+            transmission_key,
+            blinding_factor,
+            diversifier_index,
+            nullifier,
+            position,
+        )
+        .execute(&mut *tx)
+        .await?;
+        set_note_account(tx, "notes", &note_record.note_commitment, scan_result).await?;
+        if let Some(source) = note_record.source {
+            sqlx::query("UPDATE notes SET source = ? WHERE note_commitment = ?")
+                .bind(source.code())
+                .bind(&note_commitment)
+                .execute(&mut *tx)
+                .await?;
+        }
+        // A note which was quarantined is the output of our own undelegation, now unbonded, and
+        // created by no transaction in this block.
+        sqlx::query(
+            "UPDATE notes SET source = ?2 WHERE note_commitment = ?1
+            AND EXISTS (SELECT 1 FROM quarantined_notes WHERE note_commitment = ?1)",
+        )
+        .bind(&note_commitment)
+        .bind(NoteOrigin::Change.code())
+        .execute(&mut *tx)
+        .await?;
+        note_events.push((
+            note_account(&note_record.note_commitment, scan_result),
+            NoteEvent::Detected(note_record.clone()),
+        ));
+
+        // If this note corresponded to a previously quarantined note, delete it from quarantine
+        // also, because it is now applied
+        sqlx::query!(
+            "DELETE FROM quarantined_notes WHERE note_commitment = ?",
+            note_commitment,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Add all quarantined nullifiers to storage and mark notes as spent, *without* forgetting
+    // them from the NCT (because they could be rolled back)
+    for (identity_key, quarantined_nullifiers) in &scan_result.spent_quarantined_nullifiers {
+        let identity_key = identity_key.encode_to_vec();
+        for quarantined_nullifier in quarantined_nullifiers {
+            let height_spent = scan_result.height as i64;
+            let nullifier = quarantined_nullifier.to_bytes().to_vec();
+
+            // Track the quarantined nullifier
+            sqlx::query(
+                "INSERT INTO quarantined_nullifiers
+                        (
+                            identity_key,
+                            nullifier,
+                            height
+                        )
+                    VALUES (?, ?, ?)",
+            )
+            .bind(identity_key.as_slice())
+            .bind(nullifier.as_slice())
+            .bind(height_spent)
+            .execute(&mut *tx)
+            .await?;
+
+            // Mark the note as spent
+            sqlx::query!(
+                "UPDATE notes SET height_spent = ? WHERE nullifier = ?",
+                height_spent,
+                nullifier,
+            )
+            .execute(&mut *tx)
+            .await?;
+            note_events.extend(spent_note_event(tx, &nullifier).await?);
+        }
+    }
+
+    // Update any rows of the table with matching nullifiers to have height_spent
+    for nullifier in &scan_result.spent_nullifiers {
+        // https://github.com/launchbadge/sqlx/issues/1430
+        // https://github.com/launchbadge/sqlx/issues/1151
+        // For some reason we can't use any temporaries with the query! macro
+        // any more, even though we did so just fine in the past, e.g.,
+        // https://github.com/penumbra-zone/penumbra/blob/e857a7ae2b11b36514a5ac83f8e0b174fa10a65f/pd/src/state/writer.rs#L201-L207
+        let height_spent = scan_result.height as i64;
+        let nullifier = nullifier.to_bytes().to_vec();
+        let spent_commitment_bytes = sqlx::query!(
+            "UPDATE notes SET height_spent = ? WHERE nullifier = ? RETURNING note_commitment",
+            height_spent,
+            nullifier,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(bytes) = spent_commitment_bytes {
+            // Forget spent note commitments from the NCT
+            let spent_commitment = Commitment::try_from(bytes.note_commitment.as_slice())?;
+            nct.forget(spent_commitment);
+            scan_result
+                .nct_updates
+                .push(NctUpdate::Forget(spent_commitment));
+            note_events.extend(spent_note_event(tx, &nullifier).await?);
+        }
+
+        // If the nullifier was previously quarantined, remove it from the list of quarantined
+        // nullifiers, because it has now been spent
+        sqlx::query!(
+            "DELETE FROM quarantined_nullifiers WHERE nullifier = ?",
+            nullifier,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // For any slashed validator, remove all quarantined notes and nullifiers for that
+    // validator, and un-spend all spent notes that were referred to by all rolled back
+    // nullifiers
+    for identity_key in &scan_result.slashed_validators {
+        let identity_key = identity_key.encode_to_vec();
+
+        // Delete all quarantined notes for this validator
+        sqlx::query!(
+            "DELETE FROM quarantined_notes WHERE identity_key = ?",
+            identity_key,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Collect all the currently quarantined nullifiers for this validator, deleting them in
+        // the process
+        let rolled_back_nullifiers = sqlx::query!(
+            "DELETE FROM quarantined_nullifiers WHERE identity_key = ? RETURNING nullifier",
+            identity_key,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // For each such nullifier, roll back the spend of the note associated with it, marking
+        // that note as spendable again
+        for rolled_back_nullifier in rolled_back_nullifiers {
+            let rolled_back_nullifier = rolled_back_nullifier.nullifier.to_vec();
+            sqlx::query!(
+                "UPDATE notes SET height_spent = NULL WHERE nullifier = ?",
+                rolled_back_nullifier,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Record which account the note with `commitment`, just inserted into `table`, belongs to.
+///
+/// The column defaults to the first account, so this only writes for the others.
+async fn set_note_account(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    table: &str,
+    commitment: &tct::Commitment,
+    scan_result: &ScanResult,
+) -> anyhow::Result<()> {
+    let account = note_account(commitment, scan_result);
+    if account != 0 {
+        sqlx::query(&format!(
+            "UPDATE {} SET account = ? WHERE note_commitment = ?",
+            table
+        ))
+        .bind(account as i64)
+        .bind(commitment.0.to_bytes().to_vec())
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The index of the account a note found while scanning belongs to.
+fn note_account(commitment: &tct::Commitment, scan_result: &ScanResult) -> u32 {
+    scan_result.accounts.get(commitment).copied().unwrap_or(0)
+}
+
+/// The event for the spend of the note with `nullifier`, which must already be marked spent, along
+/// with the account it belongs to, if we have the note.
+async fn spent_note_event(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    nullifier: &[u8],
+) -> anyhow::Result<Option<(u32, NoteEvent)>> {
+    let row = sqlx::query(
+        "SELECT notes.*, block_times.block_time AS time_created
+        FROM notes
+        LEFT JOIN block_times ON notes.height_created = block_times.height
+        WHERE nullifier = ?",
+    )
+    .bind(nullifier)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let account = row.try_get::<i64, _>("account")? as u32;
+
+    Ok(Some((
+        account,
+        NoteEvent::Spent(NoteRecord::from_row(&row)?),
+    )))
+}
+
+/// Record the changes `updates` made to the note commitment tree by the block at `height`.
+async fn record_nct_updates(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    height: u64,
+    updates: &[NctUpdate],
+) -> anyhow::Result<()> {
+    sqlx::query("INSERT INTO note_commitment_tree_updates (height, updates) VALUES (?, ?)")
+        .bind(height as i64)
+        .bind(bincode::serialize(updates)?)
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(())
+}
+
+/// Take or renew the writer lease for `holder`, returning when it expires, unless another holder's
+/// lease hasn't expired yet.
+///
+/// This is a single statement, so two workers racing for the lease can't both take it.
+async fn claim_writer_lease(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    holder: &str,
+) -> anyhow::Result<i64> {
+    let now = unix_now();
+    let expires_at = now + WRITER_LEASE_TTL.as_secs() as i64;
+    let claimed = sqlx::query(
+        "INSERT INTO writer_lease (id, holder, expires_at) VALUES (0, ?1, ?2)
+        ON CONFLICT (id) DO UPDATE SET holder = ?1, expires_at = ?2
+        WHERE writer_lease.holder = ?1 OR writer_lease.expires_at <= ?3",
+    )
+    .bind(holder)
+    .bind(expires_at)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if claimed == 0 {
+        let (holder, expires_at): (String, i64) =
+            sqlx::query_as("SELECT holder, expires_at FROM writer_lease WHERE id = 0")
+                .fetch_one(&mut *tx)
+                .await?;
+        return Err(WriterLeaseHeldError { holder, expires_at }.into());
+    }
+
+    Ok(expires_at)
+}
+
+/// Forget everything scanned, so that the chain is rescanned from genesis, e.g., after an account
+/// is added, or more assets are allowed.
+async fn reset_to_genesis(tx: &mut sqlx::Transaction<'_, Sqlite>) -> anyhow::Result<()> {
+    for table in [
+        "notes",
+        "quarantined_notes",
+        "quarantined_nullifiers",
+        "note_reservations",
+        "transactions",
+        "transaction_notes",
+        "transaction_heights",
+        "transaction_sent_outputs",
+        "note_commitment_tree_checkpoints",
+        "note_commitment_tree_updates",
+        "pruned_height",
+    ] {
+        sqlx::query(&format!("DELETE FROM {}", table))
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("UPDATE note_commitment_tree SET bytes = ?")
+        .bind(bincode::serialize(&tct::Tree::new())?)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE sync_height SET height = ?")
+        .bind(-1i64)
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(())
+}
+
+/// Insert the contents of `snapshot` into a database from which everything scanned was forgotten.
+async fn restore_snapshot(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    snapshot: &Snapshot,
+) -> anyhow::Result<()> {
+    for note in &snapshot.notes {
+        sqlx::query(
+            "INSERT INTO notes
+                (
+                    note_commitment,
+                    height_spent,
+                    height_created,
+                    diversifier,
+                    amount,
+                    asset_id,
+                    transmission_key,
+                    blinding_factor,
+                    diversifier_index,
+                    nullifier,
+                    position,
+                    account,
+                    source
+                )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&note.note_commitment)
+        .bind(note.height_spent)
+        .bind(note.height_created)
+        .bind(&note.diversifier)
+        .bind(note.amount)
+        .bind(&note.asset_id)
+        .bind(&note.transmission_key)
+        .bind(&note.blinding_factor)
+        .bind(&note.diversifier_index)
+        .bind(&note.nullifier)
+        .bind(note.position)
+        .bind(note.account)
+        .bind(note.source)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for note in &snapshot.quarantined_notes {
+        sqlx::query(
+            "INSERT INTO quarantined_notes
+                (
+                    note_commitment,
+                    height_created,
+                    diversifier,
+                    amount,
+                    asset_id,
+                    transmission_key,
+                    blinding_factor,
+                    diversifier_index,
+                    unbonding_epoch,
+                    identity_key,
+                    account
+                )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&note.note_commitment)
+        .bind(note.height_created)
+        .bind(&note.diversifier)
+        .bind(note.amount)
+        .bind(&note.asset_id)
+        .bind(&note.transmission_key)
+        .bind(&note.blinding_factor)
+        .bind(&note.diversifier_index)
+        .bind(note.unbonding_epoch)
+        .bind(&note.identity_key)
+        .bind(note.account)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for nullifier in &snapshot.quarantined_nullifiers {
+        sqlx::query(
+            "INSERT INTO quarantined_nullifiers (nullifier, identity_key, height) VALUES (?, ?, ?)",
+        )
+        .bind(&nullifier.nullifier)
+        .bind(&nullifier.identity_key)
+        .bind(nullifier.height)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for asset in &snapshot.assets {
+        sqlx::query("INSERT OR IGNORE INTO assets (asset_id, denom) VALUES (?, ?)")
+            .bind(&asset.asset_id)
+            .bind(&asset.denom)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query("UPDATE chain_params SET bytes = ?")
+        .bind(&snapshot.chain_params)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE note_commitment_tree SET bytes = ?")
+        .bind(&snapshot.nct)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE sync_height SET height = ?")
+        .bind(snapshot.sync_height)
+        .execute(&mut *tx)
+        .await?;
+    // The snapshot's tree is also a checkpoint to roll back to, rather than to genesis.
+    if snapshot.sync_height >= 0 {
+        sqlx::query("INSERT INTO note_commitment_tree_checkpoints (height, bytes) VALUES (?, ?)")
+            .bind(snapshot.sync_height)
+            .bind(&snapshot.nct)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Forget everything scanned after `height`, restoring the note commitment tree from `nct_bytes`,
+/// its snapshot as of that height.
+async fn roll_back_scanned(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    height: i64,
+    nct_bytes: &[u8],
+) -> anyhow::Result<()> {
+    for (table, column) in [
+        ("notes", "height_created"),
+        ("quarantined_notes", "height_created"),
+        ("quarantined_nullifiers", "height"),
+        ("transactions", "height"),
+        ("transaction_heights", "height"),
+        ("note_commitment_tree_checkpoints", "height"),
+    ] {
+        sqlx::query(&format!("DELETE FROM {} WHERE {} > ?", table, column))
+            .bind(height)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for table in ["transaction_notes", "transaction_sent_outputs"] {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE tx_hash NOT IN (SELECT tx_hash FROM transactions)",
+            table
+        ))
+        .execute(&mut *tx)
+        .await?;
+    }
+    sqlx::query(
+        "DELETE FROM note_reservations WHERE note_commitment NOT IN
+            (SELECT note_commitment FROM notes)",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Notes spent since are unspent until their spends are rescanned.
+    sqlx::query("UPDATE notes SET height_spent = NULL WHERE height_spent > ?")
+        .bind(height)
+        .execute(&mut *tx)
+        .await?;
+
+    // The changes recorded since the last snapshot don't apply to this one.
+    sqlx::query("UPDATE note_commitment_tree SET bytes = ?")
+        .bind(nct_bytes)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM note_commitment_tree_updates")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE sync_height SET height = ?")
+        .bind(height)
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(())
+}
+
+/// Add a note's `amount`, as stored in the database, to the running `total`.
+///
+/// Amounts are stored as signed integers, like in `NoteRecord`'s `FromRow` impl, so they're
+/// summed here rather than with SQL's `SUM`, which would overflow, and would treat amounts of
+/// 2^63 or more as negative.
+fn add_amount(total: &mut u64, amount: i64) -> anyhow::Result<()> {
+    *total = total
+        .checked_add(amount as u64)
+        .ok_or_else(|| anyhow!("balance overflows a u64"))?;
+    Ok(())
+}
+
+/// The inclusive bounds of a `range` of heights, as stored in the database.
+///
+/// Heights are stored as signed integers, so bounds past `i64::MAX` are clamped to it.
+fn height_bounds(range: impl RangeBounds<u64>) -> (i64, i64) {
+    let clamp = |height: u64| height.min(i64::MAX as u64) as i64;
+    let start_height = match range.start_bound() {
+        Bound::Included(&height) => clamp(height),
+        Bound::Excluded(&height) => clamp(height.saturating_add(1)),
+        Bound::Unbounded => 0,
+    };
+    let end_height = match range.end_bound() {
+        Bound::Included(&height) => clamp(height),
+        Bound::Excluded(&height) => clamp(height) - 1,
+        Bound::Unbounded => i64::MAX,
+    };
+    (start_height, end_height)
+}
+
+/// The options for connecting to the database at `path` as `config` says, decrypting it with `key`,
+/// if set.
+fn connect_options(
+    path: &Utf8Path,
+    key: Option<&StorageKey>,
+    config: &StorageConfig,
+) -> anyhow::Result<SqliteConnectOptions> {
+    let mut options = SqliteConnectOptions::from_str(path.as_str())?
+        .journal_mode(config.journal_mode)
+        .synchronous(config.synchronous)
+        .busy_timeout(config.busy_timeout);
+    if let Some(key) = key {
+        // SQLCipher requires the key before anything else is read, so sqlx sends it first.
+        options = options.pragma("key", key.pragma_value());
+    }
+    Ok(options)
+}
+
+/// Create the tables of a new database through its writer connection `pool`, and initialize them
+/// with an empty NCT, the chain `params`, and the `fvk` of the first account.
+async fn populate(
+    pool: &Pool<Sqlite>,
+    fvk: FullViewingKey,
+    params: ChainParams,
+) -> anyhow::Result<()> {
+    // Run migrations
+    sqlx::migrate!().run(pool).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let nct_bytes = bincode::serialize(&tct::Tree::new())?;
+    let chain_params_bytes = &ChainParams::encode_to_vec(&params)[..];
+    let fvk_bytes = &FullViewingKey::encode_to_vec(&fvk)[..];
+
+    sqlx::query!(
+        "INSERT INTO note_commitment_tree (bytes) VALUES (?)",
+        nct_bytes
+    )
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO chain_params (bytes) VALUES (?)",
+        chain_params_bytes
+    )
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query!("INSERT INTO full_viewing_key (bytes) VALUES (?)", fvk_bytes)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("INSERT INTO full_viewing_keys (account, label, bytes) VALUES (0, ?, ?)")
+        .bind(DEFAULT_ACCOUNT_LABEL)
+        .bind(fvk_bytes)
+        .execute(&mut tx)
+        .await?;
+
+    // Insert -1 as a signaling value for pre-genesis.
+    // We just have to be careful to treat negative values as None
+    // in last_sync_height.
+    sqlx::query!("INSERT INTO sync_height (height) VALUES (?)", -1i64)
         .execute(&mut tx)
         .await?;
 
-        tx.commit().await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Connect the single writer connection to the database at `path`, decrypting it with `key`, if
+/// set.
+async fn connect_writer(
+    path: &Utf8Path,
+    key: Option<&StorageKey>,
+    config: &StorageConfig,
+) -> anyhow::Result<Pool<Sqlite>> {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(connect_options(path, key, config)?)
+        .await
+        .with_context(|| match key {
+            Some(_) => format!(
+                "could not decrypt the view database at {} with the given key",
+                path
+            ),
+            None => format!(
+                "could not open the view database at {}, which needs a key if it's encrypted",
+                path
+            ),
+        })?;
+
+    if key.is_some() {
+        // Without SQLCipher, the `key` pragma is silently ignored, and the database would be
+        // written in cleartext.
+        let cipher_version: Option<(String,)> = sqlx::query_as("PRAGMA cipher_version")
+            .fetch_optional(&pool)
+            .await?;
+        if cipher_version.is_none() {
+            return Err(anyhow!(
+                "can't encrypt the view database at {}: this build lacks the `sqlcipher` feature",
+                path
+            ));
+        }
+    }
+
+    Ok(pool)
+}
+
+/// Connect the read-only connections to the database at `path`, decrypting it with `key`, if set.
+async fn connect_readers(
+    path: &Utf8Path,
+    key: Option<&StorageKey>,
+    config: &StorageConfig,
+) -> anyhow::Result<Pool<Sqlite>> {
+    Ok(SqlitePoolOptions::new()
+        .max_connections(config.max_connections)
+        .connect_with(connect_options(path, key, config)?.read_only(true))
+        .await?)
+}
+
+/// Load the asset allowlist, which is `None` if no assets are listed.
+async fn load_asset_allowlist(pool: &Pool<Sqlite>) -> anyhow::Result<Option<BTreeSet<asset::Id>>> {
+    let rows: Vec<(Vec<u8>,)> = sqlx::query_as("SELECT asset_id FROM asset_allowlist")
+        .fetch_all(pool)
+        .await?;
+
+    let allowlist = rows
+        .into_iter()
+        .map(|(bytes,)| asset::Id::try_from(bytes.as_slice()))
+        .collect::<Result<BTreeSet<_>, _>>()?;
+
+    Ok(if allowlist.is_empty() {
+        None
+    } else {
+        Some(allowlist)
+    })
+}
+
+/// The current time, in seconds since the Unix epoch.
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is after the Unix epoch")
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+    use penumbra_crypto::{
+        keys::{SeedPhrase, SpendKey},
+        Value,
+    };
+    use rand_core::OsRng;
+
+    use super::*;
+
+    /// Generate a note to `fvk` whose commitment contains a `'` byte, which would end a string
+    /// literal if the commitment were ever spliced into SQL.
+    fn note_with_quote(fvk: &FullViewingKey) -> Note {
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let value = Value {
+            amount: 1,
+            asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+        };
+        loop {
+            let note = Note::generate(&mut OsRng, &address, value);
+            if note.commit().0.to_bytes().contains(&b'\'') {
+                return note;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn commitment_with_quote_round_trips() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("view.sqlite")).unwrap();
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize(&path, fvk.clone(), ChainParams::default()).await?;
+
+        let note = note_with_quote(&fvk);
+        let note_commitment = note.commit();
+        let mut nct = tct::Tree::new();
+        let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+        let record = NoteRecord {
+            note_commitment,
+            diversifier_index: fvk.incoming().index_for_diversifier(&note.diversifier()),
+            nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+            note: note.clone(),
+            height_created: 0,
+            height_spent: None,
+            position,
+            time_created: None,
+            source: None,
+        };
+        storage
+            .record_block(
+                ScanResult {
+                    new_notes: vec![record.clone()],
+                    new_quarantined_notes: Vec::new(),
+                    accounts: [(note_commitment, 0)].into_iter().collect(),
+                    spent_nullifiers: Vec::new(),
+                    spent_quarantined_nullifiers: BTreeMap::new(),
+                    slashed_validators: Vec::new(),
+                    height: 0,
+                    block_time: None,
+                    nct_updates: Vec::new(),
+                },
+                &mut nct,
+            )
+            .await?;
+
+        let found = storage.note_by_commitment(note_commitment, false).await?;
+        assert_eq!(found.note_commitment, note_commitment);
+        let found = storage.note_by_nullifier(record.nullifier, false).await?;
+        assert_eq!(found.note_commitment, note_commitment);
+
+        let notes = storage
+            .notes(
+                false,
+                Some(note.asset_id()),
+                Some(record.diversifier_index),
+                Amount::zero(),
+                SelectionStrategy::InOrder,
+                None,
+                None,
+                true,
+                Some(0),
+                &[],
+            )
+            .await?;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].note_commitment, note_commitment);
+
+        let balances = storage.balances_by_index(Some(0)).await?;
+        assert_eq!(balances[&record.diversifier_index][&note.asset_id()], 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn released_notes_can_be_reserved_again() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
+        let commitments = [
+            note_with_quote(&fvk).commit(),
+            note_with_quote(&fvk).commit(),
+        ];
+
+        storage.reserve_notes(b"first", &commitments).await?;
+        assert!(storage
+            .reserve_notes(b"second", &commitments[1..])
+            .await
+            .is_err());
+
+        // Once the first plan gives up on its notes, the second can take them.
+        storage.release_notes(&commitments).await?;
+        storage.reserve_notes(b"second", &commitments[1..]).await?;
+
+        // Releasing notes which aren't reserved does nothing.
+        storage.release_notes(&commitments[..1]).await?;
+        assert!(storage.reserve_notes(b"first", &commitments).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn in_memory_storage_records_blocks() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
+        assert_eq!(storage.last_sync_height().await?, None);
+        assert_eq!(storage.accounts().await?[0].fvk.hash(), fvk.hash());
+
+        let mut nct = tct::Tree::new();
+        nct.end_block()?;
+        storage
+            .record_block(
+                ScanResult {
+                    height: 0,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await?;
+        assert_eq!(storage.last_sync_height().await?, Some(0));
+        assert_eq!(storage.note_commitment_tree().await?.root(), nct.root());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn nct_changes_are_replayed_onto_snapshot() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let params = ChainParams::default();
+        let storage = Storage::initialize_in_memory(fvk.clone(), params.clone()).await?;
+        let mut nct = tct::Tree::new();
+
+        // The first block is recorded with a snapshot of the whole tree...
+        let nct_updates = empty_block_nct_updates(0, params.epoch_duration);
+        for update in &nct_updates {
+            update.apply(&mut nct)?;
+        }
+        storage
+            .record_block(
+                ScanResult {
+                    height: 0,
+                    nct_updates,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await?;
+
+        // ...an empty block is only recorded in memory...
+        for update in empty_block_nct_updates(1, params.epoch_duration) {
+            update.apply(&mut nct)?;
+        }
+        storage.record_empty_block(1).await?;
+
+        // ...and the next block only records its changes, along with the empty block's.
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let value = Value {
+            amount: 1,
+            asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+        };
+        let kept = Note::generate(&mut OsRng, &address, value).commit();
+        let forgotten = Note::generate(&mut OsRng, &address, value).commit();
+        let nct_updates = vec![
+            NctUpdate::InsertKept(kept),
+            NctUpdate::InsertForgotten(forgotten),
+            NctUpdate::EndBlock,
+        ];
+        for update in &nct_updates {
+            update.apply(&mut nct)?;
+        }
+        storage
+            .record_block(
+                ScanResult {
+                    height: 2,
+                    nct_updates,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await?;
+
+        let (updates,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM note_commitment_tree_updates")
+                .fetch_one(&storage.pool)
+                .await?;
+        assert_eq!(updates, 2);
+
+        let loaded = storage.note_commitment_tree().await?;
+        assert_eq!(loaded.root(), nct.root());
+        assert!(loaded.witness(kept).is_some());
+        assert!(loaded.witness(forgotten).is_none());
 
         Ok(())
     }
 
-    pub async fn record_empty_block(&self, height: u64) -> anyhow::Result<()> {
-        //Check that the incoming block height follows the latest recorded height
-        let last_sync_height = self.last_sync_height().await?.ok_or_else(|| {
-            anyhow::anyhow!("invalid: tried to record empty block as genesis block")
-        })?;
+    #[tokio::test]
+    async fn quarantined_notes_are_filtered_and_summed() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
 
-        if height != last_sync_height + 1 {
-            return Err(anyhow::anyhow!(
-                "Wrong block height {} for latest sync height {}",
-                height,
-                last_sync_height
-            ));
-        }
+        let validator = |i| {
+            let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), i);
+            IdentityKey(*sk.full_viewing_key().spend_verification_key())
+        };
+        let (alice, bob) = (validator(0), validator(1));
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let quarantined = |amount, unbonding_epoch, identity_key| {
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: upenumbra,
+                },
+            );
+            QuarantinedNoteRecord {
+                note_commitment: note.commit(),
+                diversifier_index: 0u64.into(),
+                note,
+                height_created: 0,
+                unbonding_epoch,
+                identity_key,
+            }
+        };
+        let notes = vec![
+            quarantined(1, 10, alice),
+            quarantined(2, 10, alice),
+            quarantined(4, 20, alice),
+            quarantined(8, 10, bob),
+        ];
+
+        let mut nct = tct::Tree::new();
+        nct.end_block()?;
+        storage
+            .record_block(
+                ScanResult {
+                    accounts: notes.iter().map(|n| (n.note_commitment, 0)).collect(),
+                    new_quarantined_notes: notes,
+                    height: 0,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await?;
+
+        let total = |notes: Vec<QuarantinedNoteRecord>| -> u64 {
+            notes.iter().map(|n| u64::from(n.note.amount())).sum()
+        };
+        assert_eq!(
+            total(storage.quarantined_notes(None, None, None, None).await?),
+            15
+        );
+        assert_eq!(
+            total(
+                storage
+                    .quarantined_notes(Some(0), Some(&alice), None, None)
+                    .await?
+            ),
+            7
+        );
+        assert_eq!(
+            total(
+                storage
+                    .quarantined_notes(None, Some(&alice), Some(10), Some(upenumbra))
+                    .await?
+            ),
+            3
+        );
+        assert_eq!(
+            total(storage.quarantined_notes(None, None, Some(9), None).await?),
+            0
+        );
+
+        let balances = storage.quarantined_balance_by_validator(Some(0)).await?;
+        assert_eq!(balances[&alice][&10][&upenumbra], 3);
+        assert_eq!(balances[&alice][&20][&upenumbra], 4);
+        assert_eq!(balances[&bob].len(), 1);
+        assert_eq!(balances[&bob][&10][&upenumbra], 8);
 
-        *self.uncommitted_height.lock() = Some(height.try_into().unwrap());
         Ok(())
     }
 
-    pub async fn record_block(
-        &self,
-        scan_result: ScanResult,
-        nct: &mut tct::Tree,
-    ) -> anyhow::Result<()> {
-        //Check that the incoming block height follows the latest recorded height
-        let last_sync_height = self.last_sync_height().await?;
+    #[tokio::test]
+    async fn notes_are_selected_by_strategy() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
 
-        let correct_height = match last_sync_height {
-            // Require that the new block follows the last one we scanned.
-            Some(cur_height) => scan_result.height == cur_height + 1,
-            // Require that the new block represents the initial chain state.
-            None => scan_result.height == 0,
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let mut nct = tct::Tree::new();
+        let mut record = |amount, index: u64| -> anyhow::Result<NoteRecord> {
+            let (address, _) = fvk.incoming().payment_address(index.into());
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: upenumbra,
+                },
+            );
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+            Ok(NoteRecord {
+                note_commitment,
+                diversifier_index: index.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: 0,
+                height_spent: None,
+                position,
+                time_created: None,
+                source: None,
+            })
         };
+        let notes = vec![record(1, 0)?, record(8, 1)?, record(2, 0)?, record(4, 0)?];
+        storage
+            .record_block(
+                ScanResult {
+                    accounts: notes.iter().map(|n| (n.note_commitment, 0)).collect(),
+                    new_notes: notes,
+                    height: 0,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await?;
 
-        if !correct_height {
-            return Err(anyhow::anyhow!(
-                "Wrong block height {} for latest sync height {:?}",
-                scan_result.height,
-                last_sync_height
-            ));
+        let select = |amount: u64, strategy| {
+            let storage = storage.clone();
+            async move {
+                let notes = storage
+                    .notes(
+                        false,
+                        Some(upenumbra),
+                        None,
+                        amount.into(),
+                        strategy,
+                        None,
+                        None,
+                        false,
+                        Some(0),
+                        &[],
+                    )
+                    .await?;
+                anyhow::Ok(
+                    notes
+                        .iter()
+                        .map(|n| (u64::from(n.note.amount()), n.diversifier_index))
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        let (zero, one) = (DiversifierIndex::from(0u64), DiversifierIndex::from(1u64));
+
+        assert_eq!(
+            select(5, SelectionStrategy::InOrder).await?,
+            vec![(1, zero), (8, one)]
+        );
+        assert_eq!(
+            select(5, SelectionStrategy::LargestFirst).await?,
+            vec![(8, one)]
+        );
+        assert_eq!(
+            select(5, SelectionStrategy::SmallestFirst).await?,
+            vec![(1, zero), (2, zero), (4, zero)]
+        );
+        // Notes are cut off as soon as the ones taken cover the amount exactly...
+        assert_eq!(
+            select(3, SelectionStrategy::SmallestFirst).await?,
+            vec![(1, zero), (2, zero)]
+        );
+        // ...and every note is taken when it takes all of them.
+        assert_eq!(
+            select(15, SelectionStrategy::InOrder).await?,
+            vec![(1, zero), (8, one), (2, zero), (4, zero)]
+        );
+        // A 4 and a 1 cover 5 exactly, where the 8 alone would leave change.
+        assert_eq!(
+            select(5, SelectionStrategy::MinimizeChange).await?,
+            vec![(4, zero), (1, zero)]
+        );
+        assert_eq!(
+            select(6, SelectionStrategy::MinimizeChange).await?,
+            vec![(4, zero), (2, zero)]
+        );
+        // Index 0 alone can cover 5, so index 1's larger note isn't touched...
+        assert_eq!(
+            select(5, SelectionStrategy::PrivacyPreferring).await?,
+            vec![(4, zero), (2, zero)]
+        );
+        // ...but no index alone can cover 12, so the fewest indices are drawn on.
+        assert_eq!(
+            select(12, SelectionStrategy::PrivacyPreferring).await?,
+            vec![(8, one), (4, zero)]
+        );
+        for strategy in [
+            SelectionStrategy::InOrder,
+            SelectionStrategy::LargestFirst,
+            SelectionStrategy::SmallestFirst,
+            SelectionStrategy::MinimizeChange,
+            SelectionStrategy::PrivacyPreferring,
+        ] {
+            assert!(select(16, strategy).await.is_err());
         }
-        let mut tx = self.pool.begin().await?;
 
-        // Insert all quarantined note commitments into storage
-        for quarantined_note_record in &scan_result.new_quarantined_notes {
-            let note_commitment = quarantined_note_record
-                .note_commitment
-                .0
-                .to_bytes()
-                .to_vec();
-            let height_created = scan_result.height as i64;
-            let diversifier = quarantined_note_record.note.diversifier().0.to_vec();
-            let amount = quarantined_note_record.note.amount() as i64;
-            let asset_id = quarantined_note_record.note.asset_id().to_bytes().to_vec();
-            let transmission_key = quarantined_note_record.note.transmission_key().0.to_vec();
-            let blinding_factor = quarantined_note_record
-                .note
-                .note_blinding()
-                .to_bytes()
-                .to_vec();
-            let diversifier_index = quarantined_note_record.diversifier_index.0.to_vec();
-            let unbonding_epoch = quarantined_note_record.unbonding_epoch as i64;
-            let identity_key = quarantined_note_record.identity_key.encode_to_vec();
-            sqlx::query!(
-                "INSERT INTO quarantined_notes
-                    (
-                        note_commitment,
-                        height_created,
-                        diversifier,
-                        amount,
-                        asset_id,
-                        transmission_key,
-                        blinding_factor,
-                        diversifier_index,
-                        unbonding_epoch,
-                        identity_key
-                    )
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn notes_are_classified_by_source() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
+
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let mut nct = tct::Tree::new();
+        let mut record = |amount| -> anyhow::Result<NoteRecord> {
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: upenumbra,
+                },
+            );
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+            Ok(NoteRecord {
                 note_commitment,
-                height_created,
-                diversifier,
-                amount,
-                asset_id,
-                transmission_key,
-                blinding_factor,
-                diversifier_index,
-                unbonding_epoch,
-                identity_key,
+                diversifier_index: 0u64.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: 1,
+                height_spent: None,
+                position,
+                time_created: None,
+                source: None,
+            })
+        };
+        let (received, change, reward) = (record(1)?, record(2)?, record(3)?);
+        let notes = vec![received.clone(), change.clone(), reward.clone()];
+        storage
+            .record_block(
+                ScanResult {
+                    accounts: notes.iter().map(|n| (n.note_commitment, 0)).collect(),
+                    new_notes: notes,
+                    height: 1,
+                    ..Default::default()
+                },
+                &mut nct,
             )
-            .execute(&mut tx)
             .await?;
-        }
 
-        // Insert all new note records into storage
-        for note_record in &scan_result.new_notes {
-            // https://github.com/launchbadge/sqlx/issues/1430
-            // https://github.com/launchbadge/sqlx/issues/1151
-            // For some reason we can't use any temporaries with the query! macro
-            // any more, even though we did so just fine in the past, e.g.,
-            // https://github.com/penumbra-zone/penumbra/blob/e857a7ae2b11b36514a5ac83f8e0b174fa10a65f/pd/src/state/writer.rs#L201-L207
-            let note_commitment = note_record.note_commitment.0.to_bytes().to_vec();
-            let height_created = scan_result.height as i64;
-            let diversifier = note_record.note.diversifier().0.to_vec();
-            let amount = note_record.note.amount() as i64;
-            let asset_id = note_record.note.asset_id().to_bytes().to_vec();
-            let transmission_key = note_record.note.transmission_key().0.to_vec();
-            let blinding_factor = note_record.note.note_blinding().to_bytes().to_vec();
-            let diversifier_index = note_record.diversifier_index.0.to_vec();
-            let nullifier = note_record.nullifier.to_bytes().to_vec();
-            let position = (u64::from(note_record.position)) as i64;
-            sqlx::query!(
-                "INSERT INTO notes
-                    (
-                        note_commitment,
-                        height_spent,
-                        height_created,
-                        diversifier,
-                        amount,
-                        asset_id,
-                        transmission_key,
-                        blinding_factor,
-                        diversifier_index,
-                        nullifier,
-                        position
+        let amounts = |sources: &'static [Option<NoteOrigin>]| {
+            let storage = storage.clone();
+            async move {
+                let notes = storage
+                    .notes(
+                        false,
+                        None,
+                        None,
+                        Amount::zero(),
+                        SelectionStrategy::default(),
+                        None,
+                        None,
+                        false,
+                        None,
+                        sources,
                     )
-                    VALUES
-                    (
-                        ?,
-                        NULL,
-                        ?,
-                        ?,
-                        ?,
-                        ?,
-                        ?,
-                        ?,
-                        ?,
-                        ?,
-                        ?
-                    )",
-                note_commitment,
-                // height_spent is NULL
-                height_created,
-                diversifier,
-                amount,
-                asset_id,
-                transmission_key,
-                blinding_factor,
-                diversifier_index,
-                nullifier,
-                position,
+                    .await?;
+                anyhow::Ok(
+                    notes
+                        .iter()
+                        .map(|n| (u64::from(n.note.amount()), n.source))
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        // Nothing is known about the notes until their transactions are fetched.
+        assert_eq!(
+            amounts(&[None]).await?,
+            vec![(1, None), (2, None), (3, None)]
+        );
+
+        let transaction = |tx_hash, spends, outputs| TransactionInfo {
+            tx_hash,
+            height: 1,
+            spends,
+            outputs,
+            fee: 0,
+            memo: None,
+            sent: Vec::new(),
+        };
+        storage
+            .record_transactions(
+                1,
+                &[
+                    transaction([1; 32], Vec::new(), vec![received]),
+                    // The spent note needn't be real, only ours.
+                    transaction([2; 32], vec![reward], vec![change]),
+                ],
             )
-            .execute(&mut tx)
             .await?;
 
-            // If this note corresponded to a previously quarantined note, delete it from quarantine
-            // also, because it is now applied
-            sqlx::query!(
-                "DELETE FROM quarantined_notes WHERE note_commitment = ?",
-                note_commitment,
+        assert_eq!(
+            amounts(&[]).await?,
+            vec![
+                (1, Some(NoteOrigin::Received)),
+                (2, Some(NoteOrigin::Change)),
+                (3, Some(NoteOrigin::StakingReward)),
+            ]
+        );
+        assert_eq!(
+            amounts(&[Some(NoteOrigin::Received), Some(NoteOrigin::StakingReward)]).await?,
+            vec![
+                (1, Some(NoteOrigin::Received)),
+                (3, Some(NoteOrigin::StakingReward)),
+            ]
+        );
+        assert_eq!(amounts(&[None]).await?, vec![]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_one_storage_records_blocks_into_a_database() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("view.sqlite")).unwrap();
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let first = Storage::initialize(&path, fvk, ChainParams::default()).await?;
+        let second = Storage::load(&path).await?;
+
+        first.acquire_writer_lease().await?;
+        // Renewing the lease is fine...
+        first.acquire_writer_lease().await?;
+        // ...but another storage can't take it while it's held.
+        let error = second.acquire_writer_lease().await.unwrap_err();
+        assert!(error.is::<WriterLeaseHeldError>());
+
+        let mut nct = tct::Tree::new();
+        nct.end_block()?;
+        let error = second
+            .record_block(
+                ScanResult {
+                    height: 0,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await
+            .unwrap_err();
+        assert!(error.is::<WriterLeaseHeldError>());
+        assert_eq!(second.last_sync_height().await?, None);
+
+        first
+            .record_block(
+                ScanResult {
+                    height: 0,
+                    ..Default::default()
+                },
+                &mut nct,
             )
-            .execute(&mut tx)
             .await?;
-        }
+        assert_eq!(second.last_sync_height().await?, Some(0));
 
-        // Add all quarantined nullifiers to storage and mark notes as spent, *without* forgetting
-        // them from the NCT (because they could be rolled back)
-        for (identity_key, quarantined_nullifiers) in scan_result.spent_quarantined_nullifiers {
-            let identity_key = identity_key.encode_to_vec();
-            for quarantined_nullifier in quarantined_nullifiers {
-                let height_spent = scan_result.height as i64;
-                let nullifier = quarantined_nullifier.to_bytes().to_vec();
+        // Once the lease expires, the other storage can take it over.
+        sqlx::query("UPDATE writer_lease SET expires_at = 0")
+            .execute(&first.pool)
+            .await?;
+        second.acquire_writer_lease().await?;
+        assert!(first
+            .acquire_writer_lease()
+            .await
+            .unwrap_err()
+            .is::<WriterLeaseHeldError>());
 
-                // Track the quarantined nullifier
-                sqlx::query!(
-                    "INSERT INTO quarantined_nullifiers
-                        (
-                            identity_key,
-                            nullifier
-                        )
-                    VALUES (?, ?)",
-                    identity_key,
-                    nullifier,
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn snapshot_restores_storage_for_the_same_accounts() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("view.snapshot")).unwrap();
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
+
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let mut nct = tct::Tree::new();
+        let mut notes = Vec::new();
+        for amount in [1, 2] {
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: upenumbra,
+                },
+            );
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+            notes.push(NoteRecord {
+                note_commitment,
+                diversifier_index: 0u64.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: 0,
+                height_spent: None,
+                position,
+                time_created: None,
+                source: None,
+            });
+        }
+        storage
+            .record_block(
+                ScanResult {
+                    accounts: notes.iter().map(|n| (n.note_commitment, 0)).collect(),
+                    new_notes: notes,
+                    height: 0,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await?;
+        storage.export_snapshot(&path).await?;
+
+        let restored = Storage::initialize_in_memory(fvk, ChainParams::default()).await?;
+        restored.import_snapshot(&path).await?;
+        assert_eq!(restored.last_sync_height().await?, Some(0));
+        assert_eq!(restored.note_commitment_tree().await?.root(), nct.root());
+        let commitments = |notes: Vec<NoteRecord>| {
+            notes
+                .into_iter()
+                .map(|n| (n.note_commitment, n.position))
+                .collect::<Vec<_>>()
+        };
+        let all_notes = |storage: Storage| async move {
+            storage
+                .notes(
+                    false,
+                    None,
+                    None,
+                    Amount::zero(),
+                    SelectionStrategy::default(),
+                    None,
+                    None,
+                    false,
+                    None,
+                    &[],
                 )
-                .execute(&mut tx)
+                .await
+        };
+        assert_eq!(
+            commitments(all_notes(restored).await?),
+            commitments(all_notes(storage).await?)
+        );
+
+        // Another wallet's storage can't be restored from the snapshot.
+        let other_sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let other = Storage::initialize_in_memory(
+            other_sk.full_viewing_key().clone(),
+            ChainParams::default(),
+        )
+        .await?;
+        let error = other.import_snapshot(&path).await.unwrap_err();
+        assert!(error.is::<FvkMismatchError>());
+        assert_eq!(other.last_sync_height().await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn contacts_are_recorded_and_removed() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let storage =
+            Storage::initialize_in_memory(sk.full_viewing_key().clone(), ChainParams::default())
                 .await?;
+        let other_sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let (address, _) = other_sk
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
 
-                // Mark the note as spent
-                sqlx::query!(
-                    "UPDATE notes SET height_spent = ? WHERE nullifier = ?",
-                    height_spent,
-                    nullifier,
-                )
-                .execute(&mut tx)
+        storage.record_contact("alice", &address).await?;
+        assert_eq!(
+            storage
+                .contacts()
+                .await?
+                .get("alice")
+                .map(ToString::to_string),
+            Some(address.to_string())
+        );
+
+        // A label can't be reused for another address.
+        let (other_address, _) = other_sk
+            .full_viewing_key()
+            .incoming()
+            .payment_address(1u64.into());
+        assert!(storage
+            .record_contact("alice", &other_address)
+            .await
+            .is_err());
+
+        assert!(storage.remove_contact("alice").await?);
+        assert!(!storage.remove_contact("alice").await?);
+        assert!(storage.contacts().await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn diversifier_indices_are_labeled() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let storage =
+            Storage::initialize_in_memory(sk.full_viewing_key().clone(), ChainParams::default())
                 .await?;
-            }
-        }
 
-        // Update any rows of the table with matching nullifiers to have height_spent
-        for nullifier in scan_result.spent_nullifiers {
-            // https://github.com/launchbadge/sqlx/issues/1430
-            // https://github.com/launchbadge/sqlx/issues/1151
-            // For some reason we can't use any temporaries with the query! macro
-            // any more, even though we did so just fine in the past, e.g.,
-            // https://github.com/penumbra-zone/penumbra/blob/e857a7ae2b11b36514a5ac83f8e0b174fa10a65f/pd/src/state/writer.rs#L201-L207
-            let height_spent = scan_result.height as i64;
-            let nullifier = nullifier.to_bytes().to_vec();
-            let spent_commitment_bytes = sqlx::query!(
-                "UPDATE notes SET height_spent = ? WHERE nullifier = ? RETURNING note_commitment",
-                height_spent,
-                nullifier,
+        storage.set_label(0, 1u64.into(), Some("savings")).await?;
+        storage.set_label(0, 2u64.into(), Some("savings")).await?;
+        assert_eq!(
+            storage.label(0, 1u64.into()).await?.as_deref(),
+            Some("savings")
+        );
+        assert_eq!(storage.labels(0).await?.len(), 2);
+        // Labels belong to the account they were set for.
+        assert!(storage.labels(1).await?.is_empty());
+
+        storage.set_label(0, 1u64.into(), Some("rent")).await?;
+        storage.set_label(0, 2u64.into(), None).await?;
+        assert_eq!(
+            storage.labels(0).await?.into_iter().collect::<Vec<_>>(),
+            vec![(1u64.into(), "rent".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn balances_are_grouped_by_label() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
+
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let mut nct = tct::Tree::new();
+        let mut record = |amount, index: u64| -> anyhow::Result<NoteRecord> {
+            let (address, _) = fvk.incoming().payment_address(index.into());
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: upenumbra,
+                },
+            );
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+            Ok(NoteRecord {
+                note_commitment,
+                diversifier_index: index.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: 0,
+                height_spent: None,
+                position,
+                time_created: None,
+                source: None,
+            })
+        };
+        let notes = vec![record(1, 256)?, record(2, 1)?, record(4, 3)?];
+        storage
+            .record_block(
+                ScanResult {
+                    accounts: notes.iter().map(|n| (n.note_commitment, 0)).collect(),
+                    new_notes: notes,
+                    height: 0,
+                    ..Default::default()
+                },
+                &mut nct,
             )
-            .fetch_optional(&mut tx)
             .await?;
 
-            if let Some(bytes) = spent_commitment_bytes {
-                // Forget spent note commitments from the NCT
-                let spent_commitment = Commitment::try_from(bytes.note_commitment.as_slice())?;
-                nct.forget(spent_commitment);
-            }
+        // Index 256 sorts before index 1 by its little-endian bytes, but the group is still
+        // shown under the lowest index.
+        let (one, three, many) = (
+            DiversifierIndex::from(1u64),
+            DiversifierIndex::from(3u64),
+            DiversifierIndex::from(256u64),
+        );
+        storage.set_label(0, many, Some("savings")).await?;
+        storage.set_label(0, one, Some("savings")).await?;
+        assert_eq!(
+            storage.label_groups(0).await?,
+            [(one, one), (many, one)].into_iter().collect()
+        );
 
-            // If the nullifier was previously quarantined, remove it from the list of quarantined
-            // nullifiers, because it has now been spent
-            sqlx::query!(
-                "DELETE FROM quarantined_nullifiers WHERE nullifier = ?",
-                nullifier,
+        let balances = storage.balances_by_label(0).await?;
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances[&one][&upenumbra], 3);
+        assert_eq!(balances[&three][&upenumbra], 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spent_notes_are_pruned_once_forgotten_by_every_snapshot() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
+
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let value = Value {
+            amount: 1,
+            asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+        };
+        let mut nct = tct::Tree::new();
+        let mut records = Vec::new();
+        for _ in 0..2 {
+            let note = Note::generate(&mut OsRng, &address, value);
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+            records.push(NoteRecord {
+                note_commitment,
+                diversifier_index: 0u64.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: 0,
+                height_spent: None,
+                position,
+                time_created: None,
+                source: None,
+            });
+        }
+        storage
+            .record_block(
+                ScanResult {
+                    new_notes: records.clone(),
+                    accounts: records
+                        .iter()
+                        .map(|record| (record.note_commitment, 0))
+                        .collect(),
+                    height: 0,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await?;
+
+        // The first note is spent, the second one's spend is quarantined, and so is the spend of
+        // someone else's note.
+        let validator = IdentityKey(*sk.full_viewing_key().spend_verification_key());
+        let someone_elses = Nullifier::derive(
+            fvk.nullifier_key(),
+            0u64.into(),
+            &Note::generate(&mut OsRng, &address, value).commit(),
+        );
+        storage
+            .record_block(
+                ScanResult {
+                    spent_nullifiers: vec![records[0].nullifier],
+                    spent_quarantined_nullifiers: [(
+                        validator,
+                        vec![records[1].nullifier, someone_elses],
+                    )]
+                    .into_iter()
+                    .collect(),
+                    height: 1,
+                    ..Default::default()
+                },
+                &mut nct,
             )
-            .execute(&mut tx)
             .await?;
+        for height in 2..1000 {
+            storage.record_empty_block(height).await?;
         }
+        storage
+            .record_block(
+                ScanResult {
+                    height: 1000,
+                    ..Default::default()
+                },
+                &mut nct,
+            )
+            .await?;
 
-        // For any slashed validator, remove all quarantined notes and nullifiers for that
-        // validator, and un-spend all spent notes that were referred to by all rolled back
-        // nullifiers
-        for identity_key in scan_result.slashed_validators {
-            let identity_key = identity_key.encode_to_vec();
+        // Rolling back to the snapshot at genesis would rescan the spend.
+        assert_eq!(storage.prune(0).await?, PruneSummary::default());
 
-            // Delete all quarantined notes for this validator
-            sqlx::query!(
-                "DELETE FROM quarantined_notes WHERE identity_key = ?",
-                identity_key,
-            )
-            .execute(&mut tx)
+        // Once it's no longer retained, only the quarantine is kept, along with anything spent
+        // recently enough.
+        sqlx::query("DELETE FROM note_commitment_tree_checkpoints WHERE height = 0")
+            .execute(&storage.pool)
             .await?;
+        assert_eq!(storage.prune(1000).await?.notes, 0);
+        let summary = storage.prune(0).await?;
+        assert_eq!(summary.notes, 1);
+        assert_eq!(summary.quarantined_nullifiers, 1);
 
-            // Collect all the currently quarantined nullifiers for this validator, deleting them in
-            // the process
-            let rolled_back_nullifiers = sqlx::query!(
-                "DELETE FROM quarantined_nullifiers WHERE identity_key = ? RETURNING nullifier",
-                identity_key,
-            )
-            .fetch_all(&mut tx)
+        let (notes,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM notes")
+            .fetch_one(&storage.pool)
             .await?;
+        assert_eq!(notes, 1);
+        let (quarantined,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM quarantined_nullifiers")
+            .fetch_one(&storage.pool)
+            .await?;
+        assert_eq!(quarantined, 1);
+        assert_eq!(storage.note_commitment_tree().await?.root(), nct.root());
+        assert!(storage.balance_at_height(None, 999, None).await.is_err());
+        assert!(storage.balance_at_height(None, 1000, None).await.is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn balances_are_reconstructed_at_past_heights() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
+
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let mut nct = tct::Tree::new();
+        let mut record = |amount, height| -> anyhow::Result<NoteRecord> {
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: upenumbra,
+                },
+            );
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+            Ok(NoteRecord {
+                note_commitment,
+                diversifier_index: 0u64.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: height,
+                height_spent: None,
+                position,
+                time_created: None,
+                source: None,
+            })
+        };
+        let first = record(10, 0)?;
+        let second = record(5, 1)?;
 
-            // For each such nullifier, roll back the spend of the note associated with it, marking
-            // that note as spendable again
-            for rolled_back_nullifier in rolled_back_nullifiers {
-                let rolled_back_nullifier = rolled_back_nullifier.nullifier.to_vec();
-                sqlx::query!(
-                    "UPDATE notes SET height_spent = NULL WHERE nullifier = ?",
-                    rolled_back_nullifier,
+        // The first note is received at height 0, the second at height 1, and the first is spent
+        // at height 2.
+        for (height, new_notes, spent_nullifiers) in [
+            (0, vec![first.clone()], vec![]),
+            (1, vec![second.clone()], vec![]),
+            (2, vec![], vec![first.nullifier]),
+        ] {
+            storage
+                .record_block(
+                    ScanResult {
+                        accounts: new_notes
+                            .iter()
+                            .map(|record: &NoteRecord| (record.note_commitment, 0))
+                            .collect(),
+                        new_notes,
+                        spent_nullifiers,
+                        height,
+                        ..Default::default()
+                    },
+                    &mut nct,
                 )
-                .execute(&mut tx)
                 .await?;
-            }
         }
 
-        // Update NCT table with current NCT state
+        let storage = &storage;
+        let balance = move |height| storage.balance_at_height(Some(upenumbra), height, None);
+        assert_eq!(balance(0).await?[&upenumbra], 10);
+        assert_eq!(balance(1).await?[&upenumbra], 15);
+        assert_eq!(balance(2).await?[&upenumbra], 5);
+        // Other accounts held nothing.
+        assert!(storage
+            .balance_at_height(None, 1, Some(1))
+            .await?
+            .is_empty());
+        // The view data can't say what's held past the sync height.
+        assert!(balance(3).await.is_err());
 
-        let nct_bytes = bincode::serialize(nct)?;
-        sqlx::query!("UPDATE note_commitment_tree SET bytes = ?", nct_bytes)
-            .execute(&mut tx)
-            .await?;
+        Ok(())
+    }
 
-        // Record block height as latest synced height
+    #[tokio::test]
+    async fn balances_of_large_amounts_are_summed_exactly() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
 
-        let latest_sync_height = scan_result.height as i64;
-        sqlx::query!("UPDATE sync_height SET height = ?", latest_sync_height)
-            .execute(&mut tx)
-            .await?;
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let validator = IdentityKey(*sk.full_viewing_key().spend_verification_key());
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let mut nct = tct::Tree::new();
+        let generate = |amount| {
+            Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: upenumbra,
+                },
+            )
+        };
+        // Record a block with a note of `amount`, and another quarantined note of `amount`.
+        let record = |nct: &mut tct::Tree, height, amount| {
+            let note = generate(amount);
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+            nct.end_block()?;
+            let note_record = NoteRecord {
+                note_commitment,
+                diversifier_index: 0u64.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: height,
+                height_spent: None,
+                position,
+                time_created: None,
+                source: None,
+            };
+            let quarantined_note = generate(amount);
+            let quarantined = QuarantinedNoteRecord {
+                note_commitment: quarantined_note.commit(),
+                diversifier_index: 0u64.into(),
+                note: quarantined_note,
+                height_created: height,
+                unbonding_epoch: 10,
+                identity_key: validator.clone(),
+            };
+            anyhow::Ok(ScanResult {
+                accounts: [(note_commitment, 0), (quarantined.note_commitment, 0)]
+                    .into_iter()
+                    .collect(),
+                new_notes: vec![note_record],
+                new_quarantined_notes: vec![quarantined],
+                height,
+                ..Default::default()
+            })
+        };
 
-        tx.commit().await?;
-        // It's critical to reset the uncommitted height here, since we've just
-        // invalidated it by committing.
-        self.uncommitted_height.lock().take();
+        // Amounts of 2^63 or more don't fit in the signed integers the database stores.
+        for (height, amount) in [(0, 1 << 63), (1, (1 << 63) - 1)] {
+            let scan_result = record(&mut nct, height, amount)?;
+            storage.record_block(scan_result, &mut nct).await?;
+        }
+        assert_eq!(storage.balances(None, None).await?[&upenumbra], u64::MAX);
+        assert_eq!(
+            storage.balances_by_index(Some(0)).await?[&DiversifierIndex::from(0u64)][&upenumbra],
+            u64::MAX
+        );
+        assert_eq!(
+            storage.quarantined_balance_by_validator(None).await?[&validator][&10][&upenumbra],
+            u64::MAX
+        );
+        assert_eq!(
+            storage.balance_at_height(None, 1, None).await?[&upenumbra],
+            u64::MAX
+        );
 
-        // Broadcast all committed note records to channel
-        // Done following tx.commit() to avoid notifying of a new NoteRecord before it is actually committed to the database
+        // A balance too large for a u64 is an error, rather than wrapping around.
+        let scan_result = record(&mut nct, 2, 1)?;
+        storage.record_block(scan_result, &mut nct).await?;
+        assert!(storage.balances(None, None).await.is_err());
+        assert!(storage.balances_by_index(None).await.is_err());
+        assert!(storage.balance_at_height(None, 2, None).await.is_err());
+        assert!(storage
+            .quarantined_balance_by_validator(None)
+            .await
+            .is_err());
 
-        for note_record in scan_result.new_notes {
-            // This will fail to be broadcast if there is no active receiver (such as on initial sync)
-            // The error is ignored, as this isn't a problem, because if there is no active receiver there is nothing to do
-            let _ = self.scanned_notes_tx.send(note_record);
+        // Listing the notes doesn't sum them, and they're selected by their unsigned amounts.
+        let select = |amount: u64, strategy| {
+            let storage = storage.clone();
+            async move {
+                let notes = storage
+                    .notes(
+                        false,
+                        Some(upenumbra),
+                        None,
+                        amount.into(),
+                        strategy,
+                        None,
+                        None,
+                        false,
+                        None,
+                        &[],
+                    )
+                    .await?;
+                anyhow::Ok(
+                    notes
+                        .iter()
+                        .map(|n| u64::from(n.note.amount()))
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        assert_eq!(select(0, SelectionStrategy::default()).await?.len(), 3);
+        assert_eq!(
+            select(2, SelectionStrategy::LargestFirst).await?,
+            vec![1 << 63]
+        );
+        assert_eq!(
+            select(1 << 63, SelectionStrategy::SmallestFirst).await?,
+            vec![1, (1 << 63) - 1]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rollbacks_are_streamed_to_every_account() -> anyhow::Result<()> {
+        let sk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        let fvk = sk.full_viewing_key().clone();
+        let storage = Storage::initialize_in_memory(fvk.clone(), ChainParams::default()).await?;
+        let mut events = storage.subscribe_note_events();
+
+        let upenumbra = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        let (address, _) = fvk.incoming().payment_address(0u64.into());
+        let mut nct = tct::Tree::new();
+        for height in 0..2 {
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount: 1,
+                    asset_id: upenumbra,
+                },
+            );
+            let note_commitment = note.commit();
+            let position = nct.insert(tct::Witness::Keep, note_commitment)?;
+            nct.end_block()?;
+            let record = NoteRecord {
+                note_commitment,
+                diversifier_index: 0u64.into(),
+                nullifier: Nullifier::derive(fvk.nullifier_key(), position, &note_commitment),
+                note,
+                height_created: height,
+                height_spent: None,
+                position,
+                time_created: None,
+                source: None,
+            };
+            storage
+                .record_block(
+                    ScanResult {
+                        accounts: [(note_commitment, 0)].into_iter().collect(),
+                        new_notes: vec![record],
+                        height,
+                        ..Default::default()
+                    },
+                    &mut nct,
+                )
+                .await?;
+            assert!(matches!(
+                events.try_recv()?,
+                (Some(0), NoteEvent::Detected(record)) if record.note_commitment == note_commitment
+            ));
         }
 
+        // The tree was only snapshotted at the first block, so that's where scanning resumes.
+        assert_eq!(storage.reset_to_height(1).await?, Some(0));
+        assert!(matches!(
+            events.try_recv()?,
+            (None, NoteEvent::RolledBack { height: Some(0) })
+        ));
+
+        // Adding an account rescans everything, for every account.
+        let other = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+        storage
+            .add_account("other", other.full_viewing_key())
+            .await?;
+        assert!(matches!(
+            events.try_recv()?,
+            (None, NoteEvent::RolledBack { height: None })
+        ));
+        assert!(events.try_recv().is_err());
+
         Ok(())
     }
 }
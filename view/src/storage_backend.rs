@@ -0,0 +1,204 @@
+use std::num::NonZeroU64;
+
+use async_trait::async_trait;
+use penumbra_chain::{params::ChainParams, sync::CompactBlock};
+use penumbra_crypto::Asset;
+use penumbra_tct as tct;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::Storage;
+use crate::{sync::ScanResult, Account, NoteRecord, TransactionInfo};
+
+/// The storage the view worker scans blocks into.
+///
+/// [`Storage`](crate::Storage), backed by SQLite, is the default. Implementing this for another
+/// store lets the same scanning and note tracking logic run where SQLite isn't available, like
+/// [`IndexedDbStorage`](crate::IndexedDbStorage) in a browser. See `Storage` for the contract of
+/// each method.
+///
+/// On wasm, where handles to browser storage can't be sent between threads, neither the backend
+/// nor the futures of its methods need to be `Send`.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait StorageBackend: Clone + MaybeSendSync + 'static {
+    /// The accounts to scan for.
+    async fn accounts(&self) -> anyhow::Result<Vec<Account>>;
+
+    /// The last block height scanned to, if any.
+    async fn last_sync_height(&self) -> anyhow::Result<Option<u64>>;
+
+    /// The note commitment tree as of the last block scanned.
+    async fn note_commitment_tree(&self) -> anyhow::Result<tct::Tree>;
+
+    async fn chain_params(&self) -> anyhow::Result<ChainParams>;
+
+    async fn update_chain_params(&self, params: &ChainParams) -> anyhow::Result<()>;
+
+    async fn assets(&self) -> anyhow::Result<Vec<Asset>>;
+
+    async fn record_asset(&self, asset: Asset) -> anyhow::Result<()>;
+
+    /// Record a block with nothing in it for us.
+    async fn record_empty_block(&self, height: u64) -> anyhow::Result<()>;
+
+    /// Record a run of consecutive scanned blocks, persisting `nct` as of the last one.
+    ///
+    /// Each block's [`ScanResult::nct_updates`] describe how it changed `nct`, so that a backend
+    /// need not rewrite the whole tree.
+    async fn record_blocks(
+        &self,
+        scan_results: Vec<ScanResult>,
+        nct: &mut tct::Tree,
+    ) -> anyhow::Result<()>;
+
+    /// Roll back everything scanned after `height`, returning the height scanning resumes
+    /// after, or `None` if it restarts from genesis.
+    async fn reset_to_height(&self, height: u64) -> anyhow::Result<Option<u64>>;
+
+    /// Our notes which were created or spent at `height`.
+    async fn notes_at_height(&self, height: u64) -> anyhow::Result<Vec<NoteRecord>>;
+
+    async fn heights_missing_block_times(&self) -> anyhow::Result<Vec<u64>>;
+
+    async fn record_block_time(&self, height: u64, block_time: String) -> anyhow::Result<()>;
+
+    async fn heights_missing_transactions(&self) -> anyhow::Result<Vec<u64>>;
+
+    async fn record_transactions(
+        &self,
+        height: u64,
+        transactions: &[TransactionInfo],
+    ) -> anyhow::Result<()>;
+
+    /// How many of the most recently scanned compact blocks are kept, or `None` if they aren't.
+    fn compact_block_retention(&self) -> Option<NonZeroU64>;
+
+    /// Whether the transactions which created or spent our notes are fetched and recorded, which
+    /// reveals the heights of those transactions to the node.
+    fn transaction_history(&self) -> bool;
+
+    async fn cache_compact_blocks(&self, blocks: &[CompactBlock]) -> anyhow::Result<()>;
+
+    async fn record_sync_source_health(
+        &self,
+        url: &str,
+        error: Option<String>,
+    ) -> anyhow::Result<()>;
+
+    /// Take the lease on recording blocks, failing with a
+    /// [`WriterLeaseHeldError`](crate::WriterLeaseHeldError) if another worker scanning into the
+    /// same store holds it.
+    ///
+    /// A store which can't be shared between workers needn't implement this.
+    async fn acquire_writer_lease(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// `Send + Sync`, except on wasm, where nothing needs to be.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + Sync> MaybeSendSync for T {}
+
+/// `Send + Sync`, except on wasm, where nothing needs to be.
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSendSync {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSendSync for T {}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl StorageBackend for Storage {
+    async fn accounts(&self) -> anyhow::Result<Vec<Account>> {
+        Storage::accounts(self).await
+    }
+
+    async fn last_sync_height(&self) -> anyhow::Result<Option<u64>> {
+        Storage::last_sync_height(self).await
+    }
+
+    async fn note_commitment_tree(&self) -> anyhow::Result<tct::Tree> {
+        Storage::note_commitment_tree(self).await
+    }
+
+    async fn chain_params(&self) -> anyhow::Result<ChainParams> {
+        Storage::chain_params(self).await
+    }
+
+    async fn update_chain_params(&self, params: &ChainParams) -> anyhow::Result<()> {
+        Storage::update_chain_params(self, params).await
+    }
+
+    async fn assets(&self) -> anyhow::Result<Vec<Asset>> {
+        Storage::assets(self).await
+    }
+
+    async fn record_asset(&self, asset: Asset) -> anyhow::Result<()> {
+        Storage::record_asset(self, asset).await
+    }
+
+    async fn record_empty_block(&self, height: u64) -> anyhow::Result<()> {
+        Storage::record_empty_block(self, height).await
+    }
+
+    async fn record_blocks(
+        &self,
+        scan_results: Vec<ScanResult>,
+        nct: &mut tct::Tree,
+    ) -> anyhow::Result<()> {
+        Storage::record_blocks(self, scan_results, nct).await
+    }
+
+    async fn reset_to_height(&self, height: u64) -> anyhow::Result<Option<u64>> {
+        Storage::reset_to_height(self, height).await
+    }
+
+    async fn notes_at_height(&self, height: u64) -> anyhow::Result<Vec<NoteRecord>> {
+        Storage::notes_at_height(self, height).await
+    }
+
+    async fn heights_missing_block_times(&self) -> anyhow::Result<Vec<u64>> {
+        Storage::heights_missing_block_times(self).await
+    }
+
+    async fn record_block_time(&self, height: u64, block_time: String) -> anyhow::Result<()> {
+        Storage::record_block_time(self, height, block_time).await
+    }
+
+    async fn heights_missing_transactions(&self) -> anyhow::Result<Vec<u64>> {
+        Storage::heights_missing_transactions(self).await
+    }
+
+    async fn record_transactions(
+        &self,
+        height: u64,
+        transactions: &[TransactionInfo],
+    ) -> anyhow::Result<()> {
+        Storage::record_transactions(self, height, transactions).await
+    }
+
+    fn compact_block_retention(&self) -> Option<NonZeroU64> {
+        Storage::compact_block_retention(self)
+    }
+
+    fn transaction_history(&self) -> bool {
+        Storage::transaction_history(self)
+    }
+
+    async fn cache_compact_blocks(&self, blocks: &[CompactBlock]) -> anyhow::Result<()> {
+        Storage::cache_compact_blocks(self, blocks).await
+    }
+
+    async fn record_sync_source_health(
+        &self,
+        url: &str,
+        error: Option<String>,
+    ) -> anyhow::Result<()> {
+        Storage::record_sync_source_health(self, url, error).await
+    }
+
+    async fn acquire_writer_lease(&self) -> anyhow::Result<()> {
+        Storage::acquire_writer_lease(self).await
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+pub use sqlx::sqlite::{SqliteJournalMode as JournalMode, SqliteSynchronous as Synchronous};
+
+/// How the connections to a [`Storage`](crate::Storage) database are set up.
+///
+/// The defaults suit a wallet whose notes are read, e.g., by a GUI, while the worker records
+/// blocks: with the write-ahead log, readers don't block the writer, and with `NORMAL`
+/// synchronization, the writer only waits for the disk at checkpoints rather than at every
+/// commit, which can only lose the last blocks recorded, which are scanned again, if the machine
+/// loses power.
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    pub(crate) journal_mode: JournalMode,
+    pub(crate) synchronous: Synchronous,
+    pub(crate) busy_timeout: Duration,
+    pub(crate) max_connections: u32,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            busy_timeout: Duration::from_secs(5),
+            max_connections: 4,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// Use `journal_mode` for the database. Other modes than the write-ahead log make the worker
+    /// wait for readers to finish before recording a block, and readers wait for it in turn.
+    pub fn with_journal_mode(mut self, journal_mode: JournalMode) -> Self {
+        self.journal_mode = journal_mode;
+        self
+    }
+
+    /// Wait for the disk as `synchronous` says before committing.
+    pub fn with_synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.synchronous = synchronous;
+        self
+    }
+
+    /// Retry statements on a locked database for up to `busy_timeout` before failing.
+    pub fn with_busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = busy_timeout;
+        self
+    }
+
+    /// Use up to `max_connections` connections for reads, which run concurrently with each other.
+    ///
+    /// Writes always go through a single connection of their own.
+    pub fn with_max_connections(mut self, max_connections: u32) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+}
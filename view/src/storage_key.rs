@@ -0,0 +1,44 @@
+use penumbra_crypto::FullViewingKey;
+use penumbra_proto::Protobuf;
+
+/// The key an encrypted view database is encrypted with, using SQLCipher.
+///
+/// Encryption covers the whole database file, including the notes' diversifiers, amounts, and
+/// blinding factors, and is only available when the view service is built with the `sqlcipher`
+/// feature.
+#[derive(Clone)]
+pub enum StorageKey {
+    /// A passphrase, which SQLCipher stretches into a key.
+    Passphrase(String),
+    /// A key derived from a full viewing key, so that the database can only be read by someone
+    /// who could scan the chain for its notes anyway.
+    FullViewingKey(FullViewingKey),
+}
+
+impl StorageKey {
+    /// The value of SQLCipher's `key` pragma for this key.
+    pub(crate) fn pragma_value(&self) -> String {
+        match self {
+            // A string literal, with any quotes escaped.
+            StorageKey::Passphrase(passphrase) => format!("'{}'", passphrase.replace('\'', "''")),
+            // A raw key, which SQLCipher uses as is.
+            StorageKey::FullViewingKey(fvk) => {
+                let key = blake2b_simd::Params::new()
+                    .hash_length(32)
+                    .personal(b"Penumbra_ViewDB")
+                    .hash(&fvk.encode_to_vec());
+                format!("\"x'{}'\"", hex::encode(key.as_bytes()))
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for StorageKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the key into logs.
+        match self {
+            StorageKey::Passphrase(_) => f.write_str("StorageKey::Passphrase(..)"),
+            StorageKey::FullViewingKey(_) => f.write_str("StorageKey::FullViewingKey(..)"),
+        }
+    }
+}
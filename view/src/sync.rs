@@ -1,23 +1,35 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
 
 use penumbra_chain::{CompactBlock, Epoch};
 use penumbra_crypto::{note, IdentityKey, Nullifier};
-use penumbra_crypto::{FullViewingKey, Note, NotePayload};
+use penumbra_crypto::{Note, NotePayload};
 use penumbra_tct as tct;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{NoteRecord, QuarantinedNoteRecord};
+use crate::{Account, NoteOrigin, NoteRecord, QuarantinedNoteRecord};
 
 /// Contains the results of scanning a single block.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ScanResult {
     // write as new rows
     pub new_notes: Vec<NoteRecord>,
     pub new_quarantined_notes: Vec<QuarantinedNoteRecord>,
+    // the index of the account each new note (quarantined or not) belongs to
+    pub accounts: BTreeMap<note::Commitment, u32>,
     // use to update existing rows
     pub spent_nullifiers: Vec<Nullifier>,
     pub spent_quarantined_nullifiers: BTreeMap<IdentityKey, Vec<Nullifier>>,
     pub slashed_validators: Vec<IdentityKey>,
     pub height: u64,
+    // record in the block times table, if known
+    pub block_time: Option<String>,
+    // replay onto the persisted note commitment tree
+    pub nct_updates: Vec<NctUpdate>,
 }
 
 impl ScanResult {
@@ -30,9 +42,152 @@ impl ScanResult {
     }
 }
 
-#[tracing::instrument(skip(fvk, note_commitment_tree, note_payloads, nullifiers))]
+/// A change made to the note commitment tree, so that storage can persist only what changed in
+/// each block, rather than the whole tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NctUpdate {
+    /// Insert a commitment, keeping it so that it can be witnessed.
+    InsertKept(note::Commitment),
+    /// Insert a commitment, forgetting it immediately.
+    InsertForgotten(note::Commitment),
+    /// Insert a whole block by its root, when none of its commitments are kept.
+    InsertBlock(tct::builder::block::Root),
+    EndBlock,
+    EndEpoch,
+    /// Forget a kept commitment, which will never need to be witnessed again.
+    Forget(note::Commitment),
+}
+
+impl NctUpdate {
+    /// Make this change to `nct`.
+    pub fn apply(&self, nct: &mut tct::Tree) -> anyhow::Result<()> {
+        match *self {
+            NctUpdate::InsertKept(commitment) => {
+                nct.insert(tct::Witness::Keep, commitment)
+                    .map_err(|e| anyhow::anyhow!("could not insert commitment: {}", e))?;
+            }
+            NctUpdate::InsertForgotten(commitment) => {
+                nct.insert(tct::Witness::Forget, commitment)
+                    .map_err(|e| anyhow::anyhow!("could not insert commitment: {}", e))?;
+            }
+            NctUpdate::InsertBlock(block_root) => {
+                nct.insert_block(block_root)
+                    .map_err(|e| anyhow::anyhow!("could not insert block: {}", e))?;
+            }
+            NctUpdate::EndBlock => {
+                nct.end_block()
+                    .map_err(|e| anyhow::anyhow!("could not end block: {}", e))?;
+            }
+            NctUpdate::EndEpoch => {
+                nct.end_epoch()
+                    .map_err(|e| anyhow::anyhow!("could not end epoch: {}", e))?;
+            }
+            NctUpdate::Forget(commitment) => {
+                nct.forget(commitment);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The changes made to the note commitment tree by a block at `height` which didn't need
+/// scanning: the block is sealed, and the epoch too, if it ends there.
+///
+/// A block whose epoch root marks an early rollover of the tree's epoch always needs scanning,
+/// so the epoch only ends here on the chain's epoch boundaries.
+pub fn empty_block_nct_updates(height: u64, epoch_duration: u64) -> Vec<NctUpdate> {
+    let mut updates = vec![NctUpdate::EndBlock];
+    if Epoch::from_height(height, epoch_duration).is_epoch_end(height) {
+        updates.push(NctUpdate::EndEpoch);
+    }
+    updates
+}
+
+/// The thread pool used to trial-decrypt the notes in each block, which is the bulk of the work of
+/// scanning, shared with the worker so that its size can be changed while it runs.
+#[derive(Debug)]
+pub(crate) struct ScanPool {
+    pool: Mutex<Arc<rayon::ThreadPool>>,
+}
+
+impl Default for ScanPool {
+    /// A pool with one thread per core.
+    fn default() -> Self {
+        Self {
+            pool: Mutex::new(Arc::new(build_pool(None))),
+        }
+    }
+}
+
+impl ScanPool {
+    /// Trial-decrypt using at most `threads` threads, or one per core if `None`.
+    ///
+    /// Blocks which are already being scanned finish on the previous pool.
+    pub fn set_threads(&self, threads: Option<NonZeroUsize>) {
+        *self.pool.lock().unwrap() = Arc::new(build_pool(threads));
+    }
+
+    /// The pool to scan the next block with.
+    pub fn get(&self) -> Arc<rayon::ThreadPool> {
+        self.pool.lock().unwrap().clone()
+    }
+}
+
+fn build_pool(threads: Option<NonZeroUsize>) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.map_or(0, NonZeroUsize::get))
+        .thread_name(|i| format!("view-scan-{}", i))
+        .build()
+        .expect("building the scan thread pool must succeed")
+}
+
+/// Trial-decrypt `note_payloads` on `pool`, returning the notes meant for any of the `accounts`,
+/// along with the account each is meant for, in their original order.
+fn trial_decrypt<'a>(
+    pool: &rayon::ThreadPool,
+    accounts: &'a [Account],
+    note_payloads: &[NotePayload],
+) -> Vec<(&'a Account, Note)> {
+    pool.install(|| {
+        note_payloads
+            .par_iter()
+            .filter_map(
+                |NotePayload {
+                     note_commitment,
+                     ephemeral_key,
+                     encrypted_note,
+                 }| {
+                    // Try to decrypt the encrypted note using the ephemeral key and each account's
+                    // incoming viewing key -- if it doesn't decrypt, it wasn't meant for us.
+                    let found = accounts.iter().find_map(|account| {
+                        let note = Note::decrypt(
+                            encrypted_note.as_ref(),
+                            account.fvk.incoming(),
+                            ephemeral_key,
+                        )
+                        .ok()?;
+                        tracing::debug!(
+                            ?note_commitment,
+                            ?note,
+                            account = %account.label,
+                            "found note while scanning"
+                        );
+                        Some((account, note))
+                    });
+                    if found.is_none() {
+                        // Useful when re-scanning cached blocks to find out why a note was missed.
+                        tracing::trace!(?note_commitment, "note did not decrypt for any account");
+                    }
+                    found
+                },
+            )
+            .collect()
+    })
+}
+
+#[tracing::instrument(skip(accounts, note_commitment_tree, note_payloads, nullifiers, pool))]
 pub fn scan_block(
-    fvk: &FullViewingKey,
+    accounts: &[Account],
     note_commitment_tree: &mut tct::Tree,
     CompactBlock {
         height,
@@ -42,29 +197,18 @@ pub fn scan_block(
         epoch_root,
         quarantined,
         slashed,
+        block_time,
+        // The NCT root is checked by the caller, before the block is recorded
+        nct_root: _,
     }: CompactBlock,
     epoch_duration: u64,
+    pool: &rayon::ThreadPool,
 ) -> ScanResult {
-    // Trial-decrypt a note with our own specific viewing key
-    let trial_decrypt = |NotePayload {
-                             note_commitment,
-                             ephemeral_key,
-                             encrypted_note,
-                         }: &NotePayload|
-     -> Option<Note> {
-        // Try to decrypt the encrypted note using the ephemeral key and persistent incoming
-        // viewing key -- if it doesn't decrypt, it wasn't meant for us.
-        if let Ok(note) = Note::decrypt(encrypted_note.as_ref(), fvk.incoming(), ephemeral_key) {
-            tracing::debug!(?note_commitment, ?note, "found note while scanning");
-            Some(note)
-        } else {
-            None
-        }
-    };
-
     // Notes we've found in this block that are meant for us
     let new_notes: Vec<NoteRecord>;
+    let mut nct_updates: Vec<NctUpdate> = Vec::new();
     let mut new_quarantined_notes: Vec<QuarantinedNoteRecord> = Vec::new();
+    let mut note_accounts: BTreeMap<note::Commitment, u32> = BTreeMap::new();
 
     // Nullifiers we've found in this block
     let spent_nullifiers: Vec<Nullifier> = nullifiers;
@@ -87,31 +231,29 @@ pub fn scan_block(
                 .or_default()
                 .extend(unbonding.nullifiers);
             // Trial-decrypt the quarantined notes, keeping track of the ones that were meant for us
-            new_quarantined_notes.extend(
-                unbonding
-                    .note_payloads
-                    .into_iter()
-                    .filter_map(|note_payload| trial_decrypt(&note_payload))
-                    .map(|note| QuarantinedNoteRecord {
-                        note_commitment: note.commit(),
-                        height_created: height,
-                        diversifier_index: fvk
-                            .incoming()
-                            .index_for_diversifier(&note.diversifier()),
-                        note,
-                        unbonding_epoch,
-                        identity_key,
-                    }),
-            );
+            for (account, note) in trial_decrypt(pool, accounts, &unbonding.note_payloads) {
+                note_accounts.insert(note.commit(), account.index);
+                new_quarantined_notes.push(QuarantinedNoteRecord {
+                    note_commitment: note.commit(),
+                    height_created: height,
+                    diversifier_index: account
+                        .fvk
+                        .incoming()
+                        .index_for_diversifier(&note.diversifier()),
+                    note,
+                    unbonding_epoch,
+                    identity_key,
+                });
+            }
         }
     }
 
     // Trial-decrypt the notes in this block, keeping track of the ones that were meant for us
-    let mut decrypted_applied_notes: BTreeMap<note::Commitment, Note> = note_payloads
-        .iter()
-        .filter_map(trial_decrypt)
-        .map(|note| (note.commit(), note))
-        .collect();
+    let mut decrypted_applied_notes: BTreeMap<note::Commitment, (&Account, Note)> =
+        trial_decrypt(pool, accounts, &note_payloads)
+            .into_iter()
+            .map(|(account, note)| (note.commit(), (account, note)))
+            .collect();
 
     if decrypted_applied_notes.is_empty() {
         // We didn't find any notes for us in this block
@@ -122,6 +264,7 @@ pub fn scan_block(
         note_commitment_tree
             .insert_block(block_root)
             .expect("inserting a block root must succeed");
+        nct_updates.push(NctUpdate::InsertBlock(block_root));
     } else {
         // If we found at least one note for us in this block, we have to explicitly construct the
         // whole block in the NCT by inserting each commitment one at a time
@@ -130,13 +273,15 @@ pub fn scan_block(
             .filter_map(|note_payload| {
                 let note_commitment = note_payload.note_commitment;
 
-                if let Some(note) = decrypted_applied_notes.remove(&note_commitment) {
+                if let Some((account, note)) = decrypted_applied_notes.remove(&note_commitment) {
                     // Keep track of this commitment for later witnessing
                     let position = note_commitment_tree
                         .insert(tct::Witness::Keep, note_commitment)
                         .expect("inserting a commitment must succeed");
+                    nct_updates.push(NctUpdate::InsertKept(note_commitment));
 
-                    let nullifier = fvk.derive_nullifier(position, &note_commitment);
+                    let nullifier =
+                        Nullifier::derive(account.fvk.nullifier_key(), position, &note_commitment);
 
                     let diversifier = &note.diversifier();
 
@@ -145,17 +290,25 @@ pub fn scan_block(
                         height_spent: None,
                         height_created: height,
                         note,
-                        diversifier_index: fvk.incoming().index_for_diversifier(diversifier),
+                        diversifier_index: account
+                            .fvk
+                            .incoming()
+                            .index_for_diversifier(diversifier),
                         nullifier,
                         position,
+                        time_created: block_time.clone(),
+                        // Other notes are classified once their transactions are fetched.
+                        source: (height == 0).then_some(NoteOrigin::Genesis),
                     };
 
+                    note_accounts.insert(note_commitment, account.index);
                     Some(record)
                 } else {
                     // Don't remember this commitment; it wasn't ours
                     note_commitment_tree
                         .insert(tct::Witness::Forget, note_commitment)
                         .expect("inserting a commitment must succeed");
+                    nct_updates.push(NctUpdate::InsertForgotten(note_commitment));
 
                     None
                 }
@@ -166,14 +319,17 @@ pub fn scan_block(
         note_commitment_tree
             .end_block()
             .expect("ending the block must succed");
+        nct_updates.push(NctUpdate::EndBlock);
     }
 
-    // If we've also reached the end of the epoch, end the epoch in the commitment tree
-    if Epoch::from_height(height, epoch_duration).is_epoch_end(height) {
+    // If we've also reached the end of the epoch, end the epoch in the commitment tree; the chain
+    // also ends the commitment tree's epoch early if it fills up, which it marks with an epoch root
+    if epoch_root.is_some() || Epoch::from_height(height, epoch_duration).is_epoch_end(height) {
         tracing::debug!(?height, "end of epoch");
         note_commitment_tree
             .end_epoch()
             .expect("ending the epoch must succeed");
+        nct_updates.push(NctUpdate::EndEpoch);
     }
 
     // Print the TCT root for debugging
@@ -182,10 +338,13 @@ pub fn scan_block(
     let result = ScanResult {
         new_notes,
         new_quarantined_notes,
+        accounts: note_accounts,
         spent_nullifiers,
         spent_quarantined_nullifiers,
         slashed_validators: slashed,
         height,
+        block_time,
+        nct_updates,
     };
 
     if !result.spent_quarantined_nullifiers.is_empty() || !result.new_quarantined_notes.is_empty() {
@@ -194,3 +353,57 @@ pub fn scan_block(
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn early_epoch_rollovers_are_followed() {
+        // Longer than an epoch of the note commitment tree, so the chain rolls it over early.
+        let epoch_duration = 1 << 20;
+        let pool = build_pool(NonZeroUsize::new(1));
+        let mut chain_nct = tct::Tree::new();
+        let mut client_nct = tct::Tree::new();
+
+        let mut rolled_over = false;
+        for height in 0..=(1 << 16) {
+            // The chain ends the epoch as soon as it's full, as the shielded pool does...
+            let block_root = chain_nct.end_block().unwrap();
+            let epoch_root = if chain_nct.current_epoch_is_full() {
+                Some(chain_nct.end_epoch().unwrap())
+            } else {
+                None
+            };
+            rolled_over = epoch_root.is_some();
+            let block = CompactBlock {
+                height,
+                block_root,
+                epoch_root,
+                nct_root: Some(chain_nct.root()),
+                ..Default::default()
+            };
+
+            // ...and the client follows along, syncing the block as the worker does.
+            if block.requires_scanning() {
+                scan_block(&[], &mut client_nct, block, epoch_duration, &pool);
+            } else {
+                for update in empty_block_nct_updates(height, epoch_duration) {
+                    update.apply(&mut client_nct).unwrap();
+                }
+            }
+            assert_eq!(
+                client_nct.root(),
+                chain_nct.root(),
+                "diverged at {}",
+                height
+            );
+
+            if rolled_over {
+                break;
+            }
+        }
+        assert!(rolled_over);
+        assert!(!client_nct.current_epoch_is_full());
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    num::NonZeroU32,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+/// The longest a single boost lasts, since the duration is given by clients.
+const MAX_BOOST: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Limits how quickly the worker scans blocks, so that a view service running in the background
+/// (e.g., a wallet daemon on a laptop) doesn't peg a core during a long initial sync.
+///
+/// The limit can be lifted temporarily with [`SyncThrottle::boost`], for when a user is waiting
+/// on the sync to finish.
+#[derive(Debug, Default)]
+pub(crate) struct SyncThrottle {
+    state: Mutex<State>,
+    // Wakes the worker if it's waiting out the limit when a boost begins.
+    boosted: Notify,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    max_blocks_per_second: Option<NonZeroU32>,
+    boosted_until: Option<Instant>,
+}
+
+impl SyncThrottle {
+    /// Set the maximum number of blocks to scan per second, or `None` to scan as fast as possible.
+    pub fn set_max_blocks_per_second(&self, max_blocks_per_second: Option<NonZeroU32>) {
+        self.state.lock().unwrap().max_blocks_per_second = max_blocks_per_second;
+    }
+
+    /// Lift the limit for the next `duration` (at most [`MAX_BOOST`]), or longer if a previous
+    /// boost lasts longer.
+    pub fn boost(&self, duration: Duration) {
+        let now = Instant::now();
+        let until = now.checked_add(duration.min(MAX_BOOST)).unwrap_or(now);
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.boosted_until.map_or(true, |boosted| boosted < until) {
+                state.boosted_until = Some(until);
+            }
+        }
+        self.boosted.notify_waiters();
+    }
+
+    /// Wait until the next block may be scanned, given that scanning the previous one started at
+    /// `started`.
+    pub async fn pace(&self, started: Instant) {
+        // Register for boost notifications before checking the state, so that a boost that begins
+        // in between isn't missed.
+        let boosted = self.boosted.notified();
+
+        let min_block_time = {
+            let state = self.state.lock().unwrap();
+            if state
+                .boosted_until
+                .map_or(false, |boosted| Instant::now() < boosted)
+            {
+                return;
+            }
+            match state.max_blocks_per_second {
+                Some(max) => Duration::from_secs(1) / max.get(),
+                None => return,
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep_until((started + min_block_time).into()) => {}
+            _ = boosted => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boost_is_clamped() {
+        let throttle = SyncThrottle::default();
+        // This would overflow `Instant` if it weren't clamped.
+        throttle.boost(Duration::MAX);
+
+        let boosted_until = throttle.state.lock().unwrap().boosted_until.unwrap();
+        assert!(boosted_until > Instant::now());
+        assert!(boosted_until <= Instant::now() + MAX_BOOST);
+    }
+}
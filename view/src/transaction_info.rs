@@ -0,0 +1,101 @@
+use penumbra_crypto::Note;
+use penumbra_proto::{view as pb, Protobuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::NoteRecord;
+
+/// Corresponds to the TransactionInfo proto
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "pb::TransactionInfo", into = "pb::TransactionInfo")]
+pub struct TransactionInfo {
+    pub tx_hash: [u8; 32],
+    pub height: u64,
+    /// Our notes spent by the transaction.
+    pub spends: Vec<NoteRecord>,
+    /// Our notes created by the transaction.
+    pub outputs: Vec<NoteRecord>,
+    pub fee: u64,
+    /// The text of the memo of the first of our outputs, if any.
+    pub memo: Option<String>,
+    /// The outputs we sent to others, recovered with our outgoing viewing key.
+    pub sent: Vec<SentOutput>,
+}
+
+/// Corresponds to the SentOutput proto
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "pb::SentOutput", into = "pb::SentOutput")]
+pub struct SentOutput {
+    /// The note sent, which names the recipient's diversifier and transmission key.
+    pub note: Note,
+    /// The text of the output's memo, if any.
+    pub memo: Option<String>,
+}
+
+impl Protobuf<pb::TransactionInfo> for TransactionInfo {}
+impl From<TransactionInfo> for pb::TransactionInfo {
+    fn from(v: TransactionInfo) -> Self {
+        pb::TransactionInfo {
+            tx_hash: v.tx_hash.to_vec(),
+            height: v.height,
+            spends: v.spends.into_iter().map(Into::into).collect(),
+            outputs: v.outputs.into_iter().map(Into::into).collect(),
+            fee: v.fee,
+            memo: v.memo,
+            sent: v.sent.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFrom<pb::TransactionInfo> for TransactionInfo {
+    type Error = anyhow::Error;
+    fn try_from(v: pb::TransactionInfo) -> Result<Self, Self::Error> {
+        Ok(TransactionInfo {
+            tx_hash: v
+                .tx_hash
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("transaction hash must be 32 bytes"))?,
+            height: v.height,
+            spends: v
+                .spends
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            outputs: v
+                .outputs
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            fee: v.fee,
+            memo: v.memo,
+            sent: v
+                .sent
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl Protobuf<pb::SentOutput> for SentOutput {}
+impl From<SentOutput> for pb::SentOutput {
+    fn from(v: SentOutput) -> Self {
+        pb::SentOutput {
+            note: Some(v.note.into()),
+            memo: v.memo,
+        }
+    }
+}
+
+impl TryFrom<pb::SentOutput> for SentOutput {
+    type Error = anyhow::Error;
+    fn try_from(v: pb::SentOutput) -> Result<Self, Self::Error> {
+        Ok(SentOutput {
+            note: v
+                .note
+                .ok_or_else(|| anyhow::anyhow!("missing note"))?
+                .try_into()?,
+            memo: v.memo,
+        })
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use penumbra_crypto::Amount;
+use penumbra_tct as tct;
+use tokio::sync::{broadcast::error::TryRecvError, watch, RwLock};
+
+use crate::{SelectionStrategy, Storage};
+
+/// How many of the largest unspent notes of each asset to keep auth paths for, which covers the
+/// notes most spends are planned from.
+const NOTES_PER_ASSET: usize = 8;
+
+/// Auth paths for the largest unspent notes of each asset, refreshed after each block is scanned,
+/// so that witnessing a transaction's spends on a wallet with a big note commitment tree doesn't
+/// wait on walking the tree.
+///
+/// The auth paths are only valid against the root they were computed at, so once another block
+/// is scanned, they're only used again after they're refreshed.
+#[derive(Debug, Default)]
+pub(crate) struct WitnessCache {
+    cached: Mutex<Option<Cached>>,
+}
+
+#[derive(Debug)]
+struct Cached {
+    /// The root of the note commitment tree the auth paths lead to.
+    root: tct::Root,
+    proofs: HashMap<tct::Commitment, tct::Proof>,
+}
+
+impl WitnessCache {
+    /// The cached auth path of `commitment` to `root`, if any.
+    pub fn get(&self, root: tct::Root, commitment: tct::Commitment) -> Option<tct::Proof> {
+        let cached = self.cached.lock().unwrap();
+        let cached = cached.as_ref().filter(|cached| cached.root == root)?;
+        cached.proofs.get(&commitment).cloned()
+    }
+
+    /// The auth path of `commitment` to the current root of `nct`, from the cache if it was
+    /// refreshed at that root, or else by walking the tree.
+    pub fn witness(&self, nct: &tct::Tree, commitment: tct::Commitment) -> Option<tct::Proof> {
+        self.get(nct.root(), commitment)
+            .or_else(|| nct.witness(commitment))
+    }
+
+    /// Compute the auth paths of `commitments` to the current root of `nct`.
+    pub async fn refresh(&self, nct: &RwLock<tct::Tree>, commitments: &[tct::Commitment]) {
+        // This holds the read lock while witnessing, which keeps the worker from recording the
+        // next block until it's done, but there are only a few notes of each asset to witness.
+        let nct = nct.read().await;
+        let root = nct.root();
+        let proofs = commitments
+            .iter()
+            // Notes recorded since the tree was last reloaded are always in it, but a reset may
+            // have raced with choosing the notes.
+            .filter_map(|commitment| Some((*commitment, nct.witness(*commitment)?)))
+            .collect::<HashMap<_, _>>();
+        drop(nct);
+
+        tracing::debug!(?root, count = proofs.len(), "refreshed cached witnesses");
+        *self.cached.lock().unwrap() = Some(Cached { root, proofs });
+    }
+
+    /// Refresh the cache each time the worker records a new block, until it shuts down.
+    pub async fn run(
+        self: Arc<Self>,
+        storage: Storage,
+        nct: Arc<RwLock<tct::Tree>>,
+        mut sync_height_rx: watch::Receiver<u64>,
+    ) {
+        // The notes to witness only change along with our notes, so they're only chosen again
+        // after a note event, rather than after every block.
+        let mut note_events = storage.subscribe_note_events();
+        let mut commitments = None;
+        loop {
+            loop {
+                match note_events.try_recv() {
+                    Ok(_) | Err(TryRecvError::Lagged(_)) => commitments = None,
+                    Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => break,
+                }
+            }
+            if commitments.is_none() {
+                // Witnesses are a cache, so failing to choose the notes just leaves requests to
+                // walk the tree until the next block.
+                match largest_notes(&storage).await {
+                    Ok(largest) => commitments = Some(largest),
+                    Err(e) => tracing::warn!(?e, "could not refresh cached witnesses"),
+                }
+            }
+            if let Some(commitments) = &commitments {
+                self.refresh(&nct, commitments).await;
+            }
+
+            if sync_height_rx.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The commitments of the largest unspent notes of each asset in `storage`.
+async fn largest_notes(storage: &Storage) -> anyhow::Result<Vec<tct::Commitment>> {
+    let notes = storage
+        .notes(
+            false,
+            None,
+            None,
+            Amount::zero(),
+            SelectionStrategy::default(),
+            None,
+            None,
+            false,
+            None,
+            &[],
+        )
+        .await?;
+
+    let mut by_asset = BTreeMap::<_, Vec<_>>::new();
+    for record in notes {
+        by_asset
+            .entry(record.note.asset_id())
+            .or_default()
+            .push(record);
+    }
+
+    let mut commitments = Vec::new();
+    for mut records in by_asset.into_values() {
+        records.sort_by_key(|record| std::cmp::Reverse(record.note.amount()));
+        commitments.extend(
+            records
+                .into_iter()
+                .take(NOTES_PER_ASSET)
+                .map(|record| record.note_commitment),
+        );
+    }
+
+    Ok(commitments)
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::Fq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stale_roots_fall_back_to_the_tree() {
+        let commitment = tct::Commitment(Fq::from(1u64));
+        let mut tree = tct::Tree::new();
+        tree.insert(tct::Witness::Keep, commitment).unwrap();
+        let nct = RwLock::new(tree);
+
+        let cache = WitnessCache::default();
+        cache.refresh(&nct, &[commitment]).await;
+        let cached_root = nct.read().await.root();
+        assert!(cache.get(cached_root, commitment).is_some());
+
+        // Another block moves the root, so the cached auth path is stale...
+        let mut tree = nct.write().await;
+        tree.end_block().unwrap();
+        tree.insert(tct::Witness::Keep, tct::Commitment(Fq::from(2u64)))
+            .unwrap();
+        assert_ne!(tree.root(), cached_root);
+        assert!(cache.get(tree.root(), commitment).is_none());
+
+        // ...and the auth path is found by walking the tree instead.
+        let proof = cache.witness(&tree, commitment).unwrap();
+        proof.verify(tree.root()).unwrap();
+        assert!(proof.verify(cached_root).is_err());
+    }
+}
@@ -1,47 +1,106 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use crate::{sync::scan_block, Storage};
-use penumbra_chain::{sync::CompactBlock, Epoch};
-use penumbra_crypto::{Asset, FullViewingKey};
-use penumbra_proto::client::oblivious::{
-    oblivious_query_client::ObliviousQueryClient, AssetListRequest, CompactBlockRangeRequest,
+use crate::{
+    scan_rate::ScanRate,
+    sync::{empty_block_nct_updates, scan_block, ScanPool, ScanResult},
+    throttle::SyncThrottle,
+    Account, NoteRecord, SentOutput, Storage, StorageBackend, SyncConnection, TransactionInfo,
+    WriterLeaseHeldError,
 };
+use futures::FutureExt;
+use penumbra_chain::{params::ChainParams, sync::CompactBlock, Epoch};
+use penumbra_crypto::{keys::OutgoingViewingKey, memo::MemoPlaintext, Asset, Note};
 #[cfg(feature = "nct-divergence-check")]
 use penumbra_proto::client::specific::specific_query_client::SpecificQueryClient;
-use tokio::sync::{watch, RwLock};
-use tonic::transport::Channel;
-pub struct Worker {
-    storage: Storage,
+use penumbra_proto::{
+    chain as pb,
+    client::oblivious::{
+        oblivious_query_client::ObliviousQueryClient, AssetListRequest, ChainParamsRequest,
+        CompactBlockRangeRequest,
+    },
+    Protobuf,
+};
+use penumbra_transaction::{action::output::Output, Action, Transaction};
+use tokio::sync::{mpsc, oneshot, watch, RwLock};
+use tonic::{codec::Streaming, transport::Channel};
+
+/// The most scanned blocks to hold before recording them in storage, in one transaction.
+const MAX_PENDING_BLOCKS: usize = 1000;
+/// The longest to hold scanned blocks before recording them in storage.
+const MAX_PENDING_DURATION: Duration = Duration::from_secs(5);
+/// How long to wait before the first attempt to reconnect to the node, which doubles with each
+/// failed attempt.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// The longest to wait between attempts to reconnect to the node.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// A request for the worker to roll its storage back to `height`, with
+/// [`StorageBackend::reset_to_height`], and rescan the chain from there.
+pub struct ResetRequest {
+    pub height: u64,
+    /// Receives the height scanning resumes after, or `None` if it restarts from genesis.
+    pub reply: oneshot::Sender<anyhow::Result<Option<u64>>>,
+}
+
+/// Scans the chain into storage, which is SQLite-backed [`Storage`] unless another
+/// [`StorageBackend`] is used.
+pub struct Worker<S: StorageBackend = Storage> {
+    storage: S,
     client: ObliviousQueryClient<Channel>,
+    // The URL of the node we sync from, recorded with the results of its health checks.
+    sync_url: String,
+    // The URL of the node's Tendermint RPC, from which the transactions in blocks are fetched.
+    tendermint_url: String,
     nct: Arc<RwLock<penumbra_tct::Tree>>,
-    fvk: FullViewingKey, // TODO: notifications (see TODOs on ViewService)
+    // The accounts to scan for, which only change when storage is reset to genesis.
+    accounts: Vec<Account>, // TODO: notifications (see TODOs on ViewService)
     error_slot: Arc<Mutex<Option<anyhow::Error>>>,
     sync_height_tx: watch::Sender<u64>,
+    connection_tx: watch::Sender<SyncConnection>,
+    throttle: Arc<SyncThrottle>,
+    scan_pool: Arc<ScanPool>,
+    scan_rate: Arc<ScanRate>,
+    // Blocks received but not yet added to the compact block cache, if it's enabled.
+    uncached_blocks: Vec<CompactBlock>,
+    reset_rx: mpsc::Receiver<ResetRequest>,
+    // A reset received while syncing, which stops the sync so that it can be carried out.
+    reset: Option<ResetRequest>,
     #[cfg(feature = "nct-divergence-check")]
     specific_client: SpecificQueryClient<Channel>,
 }
 
-impl Worker {
+impl<S: StorageBackend> Worker<S> {
     /// Creates a new worker, returning:
     ///
     /// - the worker itself;
     /// - a shared, in-memory NCT instance;
     /// - a shared error slot;
-    /// - a channel for notifying the client of sync progress.
+    /// - a channel for notifying the client of sync progress;
+    /// - a channel for notifying the client of the state of the connection to the node;
+    /// - a channel for asking the worker to reset its storage to a height.
     pub async fn new(
-        storage: Storage,
+        storage: S,
         node: String,
         pd_port: u16,
+        tendermint_port: u16,
+        throttle: Arc<SyncThrottle>,
+        scan_pool: Arc<ScanPool>,
+        scan_rate: Arc<ScanRate>,
     ) -> Result<
         (
             Self,
             Arc<RwLock<penumbra_tct::Tree>>,
             Arc<Mutex<Option<anyhow::Error>>>,
             watch::Receiver<u64>,
+            watch::Receiver<SyncConnection>,
+            mpsc::Sender<ResetRequest>,
         ),
         anyhow::Error,
     > {
-        let fvk = storage.full_viewing_key().await?;
+        let accounts = storage.accounts().await?;
 
         // Create a shared, in-memory NCT.
         let nct = Arc::new(RwLock::new(storage.note_commitment_tree().await?));
@@ -52,26 +111,42 @@ impl Worker {
             watch::channel(storage.last_sync_height().await?.unwrap_or(0));
         // Mark the current height as seen, since it's not new.
         sync_height_rx.borrow_and_update();
+        // Create a channel for the worker to notify of connection changes.
+        let (connection_tx, connection_rx) = watch::channel(SyncConnection::Connected);
+        // Create a channel for resets, which are carried out one at a time.
+        let (reset_tx, reset_rx) = mpsc::channel(1);
 
-        let client = ObliviousQueryClient::connect(format!("http://{}:{}", node, pd_port)).await?;
+        let sync_url = format!("http://{}:{}", node, pd_port);
+        let client = ObliviousQueryClient::connect(sync_url.clone()).await?;
+        let tendermint_url = format!("http://{}:{}", node, tendermint_port);
         #[cfg(feature = "nct-divergence-check")]
-        let specific_client =
-            SpecificQueryClient::connect(format!("http://{}:{}", node, pd_port)).await?;
+        let specific_client = SpecificQueryClient::connect(sync_url.clone()).await?;
 
         Ok((
             Self {
                 storage,
                 client,
+                sync_url,
+                tendermint_url,
                 nct: nct.clone(),
-                fvk,
+                accounts,
                 error_slot: error_slot.clone(),
                 sync_height_tx,
+                connection_tx,
+                throttle,
+                scan_pool,
+                scan_rate,
+                uncached_blocks: Vec::new(),
+                reset_rx,
+                reset: None,
                 #[cfg(feature = "nct-divergence-check")]
                 specific_client,
             },
             nct,
             error_slot,
             sync_height_rx,
+            connection_rx,
+            reset_tx,
         ))
     }
 
@@ -112,6 +187,174 @@ impl Worker {
         Ok(())
     }
 
+    /// Fetch the current chain parameters, replacing our cached copy if they've changed.
+    ///
+    /// Responses from a node which is behind our sync height are ignored, since its parameters
+    /// may be older than the ones we have.
+    pub async fn refresh_chain_params(&mut self) -> Result<(), anyhow::Error> {
+        let cached = self.storage.chain_params().await?;
+
+        let response = self
+            .client
+            .chain_params_with_height(tonic::Request::new(ChainParamsRequest {
+                chain_id: cached.chain_id.clone(),
+            }))
+            .await?;
+        crate::check_chain_id(response.metadata(), &cached.chain_id)?;
+        let response = response.into_inner();
+        let params: ChainParams = response
+            .chain_params
+            .ok_or_else(|| anyhow::anyhow!("missing chain params in response"))?
+            .into();
+
+        let sync_height = self.storage.last_sync_height().await?.unwrap_or(0);
+        if response.height < sync_height {
+            tracing::debug!(
+                params_height = response.height,
+                sync_height,
+                "ignoring chain params from a node behind our sync height"
+            );
+            return Ok(());
+        }
+
+        if params != cached {
+            tracing::info!(height = response.height, ?params, "chain params changed");
+            self.storage.update_chain_params(&params).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the timestamps of blocks containing our notes that were scanned before we recorded
+    /// block times, so that existing history can be dated.
+    pub async fn backfill_block_times(&mut self) -> Result<(), anyhow::Error> {
+        let heights = self.storage.heights_missing_block_times().await?;
+        if heights.is_empty() {
+            return Ok(());
+        }
+        tracing::info!(count = heights.len(), "backfilling block times");
+
+        let chain_id = self.storage.chain_params().await?.chain_id;
+
+        for height in heights {
+            let mut stream = self
+                .client
+                .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
+                    chain_id: chain_id.clone(),
+                    start_height: height,
+                    end_height: height,
+                    keep_alive: false,
+                    ..Default::default()
+                }))
+                .await?
+                .into_inner();
+
+            while let Some(block) = stream.message().await? {
+                let block = CompactBlock::try_from(block)?;
+                if let Some(block_time) = block.block_time {
+                    self.storage
+                        .record_block_time(block.height, block_time)
+                        .await?;
+                }
+            }
+        }
+
+        tracing::info!("backfilled block times");
+
+        Ok(())
+    }
+
+    /// Fetch and record the transactions which created or spent our notes, in blocks whose
+    /// transactions we haven't recorded yet.
+    ///
+    /// Compact blocks don't delimit transactions, so the full blocks are fetched from the node's
+    /// Tendermint RPC. Since that reveals which heights we're interested in, this does nothing
+    /// unless transaction history is turned on in storage.
+    pub async fn record_transactions(&mut self) -> Result<(), anyhow::Error> {
+        if !self.storage.transaction_history() {
+            return Ok(());
+        }
+
+        for height in self.storage.heights_missing_transactions().await? {
+            let notes = self.storage.notes_at_height(height).await?;
+            let transactions = fetch_transactions(&self.tendermint_url, height).await?;
+
+            let mut infos = Vec::new();
+            for transaction in transactions {
+                let nullifiers = transaction.spent_nullifiers();
+                let spends: Vec<NoteRecord> = notes
+                    .iter()
+                    .filter(|record| {
+                        record.height_spent == Some(height)
+                            && nullifiers.contains(&record.nullifier)
+                    })
+                    .cloned()
+                    .collect();
+
+                let mut outputs = Vec::new();
+                let mut memo = None;
+                let mut sent = Vec::new();
+                for action in transaction.actions() {
+                    let output = match action {
+                        Action::Output(output) => output,
+                        _ => continue,
+                    };
+                    let payload = &output.body.note_payload;
+                    let record = match notes.iter().find(|record| {
+                        record.height_created == height
+                            && record.note_commitment == payload.note_commitment
+                    }) {
+                        Some(record) => record,
+                        None => {
+                            // If we spent notes in the transaction, we sent its other outputs,
+                            // so we can recover them with our outgoing viewing key.
+                            if !spends.is_empty() {
+                                sent.extend(self.accounts.iter().find_map(|account| {
+                                    decrypt_sent_output(output, account.fvk.outgoing())
+                                }));
+                            }
+                            continue;
+                        }
+                    };
+                    if memo.is_none() {
+                        // Only the account the output is addressed to can decrypt its memo.
+                        memo = self
+                            .accounts
+                            .iter()
+                            .find_map(|account| {
+                                MemoPlaintext::decrypt(
+                                    output.body.encrypted_memo.clone(),
+                                    account.fvk.incoming(),
+                                    &payload.ephemeral_key,
+                                )
+                                .ok()
+                            })
+                            .map(|memo| memo.text())
+                            .filter(|text| !text.is_empty());
+                    }
+                    outputs.push(record.clone());
+                }
+
+                if spends.is_empty() && outputs.is_empty() {
+                    continue;
+                }
+                infos.push(TransactionInfo {
+                    tx_hash: transaction.id(),
+                    height,
+                    spends,
+                    outputs,
+                    fee: transaction.transaction_body().fee.0,
+                    memo,
+                    sent,
+                });
+            }
+
+            self.storage.record_transactions(height, &infos).await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn sync(&mut self) -> Result<(), anyhow::Error> {
         // Do a single sync run, up to whatever the latest block height is
         tracing::info!("starting client sync");
@@ -123,57 +366,169 @@ impl Worker {
             .map(|h| h + 1)
             .unwrap_or(0);
 
-        let epoch_duration = self.storage.chain_params().await?.epoch_duration;
+        let ChainParams {
+            chain_id,
+            epoch_duration,
+            ..
+        } = self.storage.chain_params().await?;
 
-        let mut stream = self
+        let response = self
             .client
             .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
-                chain_id: self.storage.chain_params().await?.chain_id,
+                chain_id: chain_id.clone(),
                 start_height,
                 end_height: 0,
                 // Instruct the server to keep feeding us blocks as they're created.
                 keep_alive: true,
+                ..Default::default()
             }))
-            .await?
-            .into_inner();
+            .await?;
+        crate::check_chain_id(response.metadata(), &chain_id)?;
+        let mut stream = response.into_inner();
+
+        // The node is reachable and serving blocks.
+        self.storage
+            .record_sync_source_health(&self.sync_url, None)
+            .await?;
+        self.connection_tx.send_replace(SyncConnection::Connected);
+
+        // Blocks which have been scanned into the in-memory NCT, but not yet recorded in storage.
+        let mut pending = Vec::new();
+        if let Err(e) = self
+            .sync_blocks(&mut stream, epoch_duration, &mut pending)
+            .await
+        {
+            // The sync resumes from the last recorded height, so rather than record blocks
+            // whose changes may have only partly reached the in-memory NCT, drop them along with
+            // any blocks not yet cached, and reload the NCT from storage to match.
+            pending.clear();
+            self.uncached_blocks.clear();
+            *self.nct.write().await = self.storage.note_commitment_tree().await?;
+            return Err(e);
+        }
+        self.flush(&mut pending).await
+    }
+
+    /// Scan the blocks in `stream`, recording them in storage in batches.
+    async fn sync_blocks(
+        &mut self,
+        stream: &mut Streaming<pb::CompactBlock>,
+        epoch_duration: u64,
+        pending: &mut Vec<ScanResult>,
+    ) -> Result<(), anyhow::Error> {
+        let mut pending_since = Instant::now();
+
+        // Blocks are scanned into this copy of the NCT, and their changes are only made to the
+        // shared NCT once the copy's root matches the one committed to in the block, so that a
+        // block which diverges from the chain never reaches the NCT that spends are witnessed
+        // from.
+        let mut working_nct = self.nct.read().await.clone();
 
-        while let Some(block) = stream.message().await? {
-            let block = CompactBlock::try_from(block)?;
+        loop {
+            // Stop syncing if asked to reset, so that the reset doesn't race with scanning.
+            if let Ok(reset) = self.reset_rx.try_recv() {
+                self.reset = Some(reset);
+                return Ok(());
+            }
+
+            // Before waiting for the next block, record the pending ones, so that once we're
+            // caught up, each block is recorded as soon as it's scanned.
+            let response = match stream.message().now_or_never() {
+                Some(response) => response?,
+                None => {
+                    self.flush(pending).await?;
+                    tokio::select! {
+                        response = stream.message() => response?,
+                        Some(reset) = self.reset_rx.recv() => {
+                            self.reset = Some(reset);
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+            // We asked the node to keep the stream open, so it only closes it when it shuts down.
+            let response = response.ok_or_else(|| {
+                tonic::Status::unavailable("node closed the compact block stream")
+            })?;
+
+            let started = Instant::now();
+            let block = CompactBlock::try_from(response)?;
+            if self.storage.compact_block_retention().is_some() {
+                self.uncached_blocks.push(block.clone());
+            }
             let height = block.height;
+            let expected_nct_root = block.nct_root;
+            let requires_scanning = block.requires_scanning();
+            let notes = block.note_payloads.len();
+
+            let scan_result = if !requires_scanning {
+                // Optimization: if the block is empty, seal the NCT, and skip touching the
+                // database. A block with an epoch root is always scanned, including one which
+                // rolls the NCT's epoch over early, but the epoch still ends on a boundary:
+                let nct_updates = empty_block_nct_updates(height, epoch_duration);
+                for update in &nct_updates {
+                    update.apply(&mut working_nct)?;
+                }
+                ScanResult {
+                    height,
+                    nct_updates,
+                    ..Default::default()
+                }
+            } else {
+                // Otherwise, scan the block, holding its changes until the batch is recorded:
+                scan_block(
+                    &self.accounts,
+                    &mut working_nct,
+                    block,
+                    epoch_duration,
+                    &self.scan_pool.get(),
+                )
+            };
+            check_nct_root(height, expected_nct_root, working_nct.root())?;
+            #[cfg(feature = "nct-divergence-check")]
+            nct_divergence_check(&mut self.specific_client, height, working_nct.root()).await?;
 
-            // Lock the NCT only while processing this block.
+            // Lock the shared NCT only while processing this block.
             let mut nct_guard = self.nct.write().await;
+            for update in &scan_result.nct_updates {
+                update.apply(&mut nct_guard)?;
+            }
 
-            if !block.requires_scanning() {
-                // Optimization: if the block is empty, seal the in-memory NCT,
-                // and skip touching the database:
-                nct_guard.end_block().unwrap();
-                // We also need to end the epoch, since if there are no funding streams, then an
-                // epoch boundary won't necessarily require scanning:
-                if Epoch::from_height(height, epoch_duration).is_epoch_end(height) {
-                    nct_guard
-                        .end_epoch()
-                        .expect("ending the epoch must succeed");
-                }
+            if !requires_scanning && pending.is_empty() {
                 self.storage.record_empty_block(height).await?;
                 // Notify all watchers of the new height we just recorded.
                 self.sync_height_tx.send(height)?;
             } else {
-                // Otherwise, scan the block and commit its changes:
-                let scan_result = scan_block(&self.fvk, &mut nct_guard, block, epoch_duration);
-                let height = scan_result.height;
-
-                self.storage
-                    .record_block(scan_result, &mut nct_guard)
-                    .await?;
-                // Notify all watchers of the new height we just recorded.
-                self.sync_height_tx.send(height)?;
+                // Storage must record blocks in order, so an empty block waits with the others.
+                if pending.is_empty() {
+                    pending_since = Instant::now();
+                }
+                pending.push(scan_result);
             }
-            #[cfg(feature = "nct-divergence-check")]
-            nct_divergence_check(&mut self.specific_client, height, nct_guard.root()).await?;
 
             // Release the NCT RwLock
             drop(nct_guard);
+            self.scan_rate.record(notes);
+
+            if pending.len() >= MAX_PENDING_BLOCKS
+                || pending_since.elapsed() >= MAX_PENDING_DURATION
+            {
+                self.flush(pending).await?;
+            }
+            if self.uncached_blocks.len() >= MAX_PENDING_BLOCKS {
+                self.cache_blocks().await?;
+            }
+
+            // Parameters can only change between epochs, so check for new ones at each boundary.
+            if Epoch::from_height(height, epoch_duration).is_epoch_end(height) {
+                self.refresh_chain_params().await?;
+            }
+
+            // Scanning is where the CPU time goes, so only scanned blocks count against the
+            // throttle; empty blocks are recorded as fast as they arrive.
+            if requires_scanning {
+                self.throttle.pace(started).await;
+            }
 
             // Check if we should stop waiting for blocks to arrive, because the view
             // services are dropped and we're supposed to shut down.
@@ -181,6 +536,56 @@ impl Worker {
                 return Ok(());
             }
         }
+    }
+
+    /// Record the `pending` blocks in storage, in one transaction.
+    async fn flush(&mut self, pending: &mut Vec<ScanResult>) -> Result<(), anyhow::Error> {
+        self.cache_blocks().await?;
+
+        let height = match pending.last() {
+            Some(last) => last.height,
+            None => return Ok(()),
+        };
+
+        let mut nct_guard = self.nct.write().await;
+        self.storage
+            .record_blocks(std::mem::take(pending), &mut nct_guard)
+            .await?;
+        drop(nct_guard);
+        // Notify all watchers of the new height we just recorded.
+        self.sync_height_tx.send(height)?;
+
+        // Transaction history is a convenience, so failing to fetch it shouldn't stop the sync:
+        // the heights stay missing, and are retried after the next flush.
+        if let Err(e) = self.record_transactions().await {
+            tracing::warn!(?e, "could not record transactions");
+        }
+
+        Ok(())
+    }
+
+    /// Add the blocks received since the last call to the compact block cache.
+    async fn cache_blocks(&mut self) -> Result<(), anyhow::Error> {
+        let blocks = std::mem::take(&mut self.uncached_blocks);
+        self.storage.cache_compact_blocks(&blocks).await
+    }
+
+    /// Carry out the reset which stopped the last sync, reloading the in-memory NCT from the
+    /// rolled-back storage.
+    async fn reset(&mut self) -> Result<(), anyhow::Error> {
+        let ResetRequest { height, reply } = self.reset.take().expect("a reset was requested");
+        tracing::info!(height, "resetting storage");
+
+        // Blocks received before the reset may be past the height rolled back to, so they're
+        // fetched again rather than cached.
+        self.uncached_blocks.clear();
+        let result = self.storage.reset_to_height(height).await;
+        if let Ok(resume_height) = result {
+            *self.nct.write().await = self.storage.note_commitment_tree().await?;
+            self.sync_height_tx.send(resume_height.unwrap_or(0))?;
+        }
+        // The client may have given up waiting.
+        let _ = reply.send(result);
 
         Ok(())
     }
@@ -189,7 +594,11 @@ impl Worker {
     #[allow(clippy::never_loop)]
     pub async fn run(mut self) -> Result<(), anyhow::Error> {
         self.run_inner().await.map_err(|e| {
-            tracing::info!(?e, "view worker error");
+            if e.is::<WriterLeaseHeldError>() {
+                tracing::error!(%e, "another view worker is scanning into this storage");
+            } else {
+                tracing::info!(?e, "view worker error");
+            }
             self.error_slot.lock().unwrap().replace(e);
             // Exit the worker to avoid looping endlessly.
             anyhow::anyhow!("view worker error")
@@ -197,24 +606,72 @@ impl Worker {
     }
 
     async fn run_inner(&mut self) -> Result<(), anyhow::Error> {
+        // Before writing anything, make sure no other worker is scanning into the same storage.
+        self.storage.acquire_writer_lease().await?;
+
         // For now, this can be outside of the loop, because assets are only
         // created at genesis. In the future, we'll want to have a way for
         // clients to learn about assets as they're created.
         self.fetch_assets().await?;
+        self.refresh_chain_params().await?;
+        self.backfill_block_times().await?;
+        if let Err(e) = self.record_transactions().await {
+            tracing::warn!(?e, "could not record transactions");
+        }
 
         let mut error_count = 0;
+        let mut reconnect_delay = MIN_RECONNECT_DELAY;
         loop {
-            match self.sync().await {
-                // If the sync returns `Ok` then it means we're shutting down.
+            let e = match self.sync().await {
+                // The sync stopped to reset storage, so start over from the reset height.
+                Ok(()) if self.reset.is_some() => {
+                    self.reset().await?;
+                    continue;
+                }
+                // Otherwise, if the sync returns `Ok` then it means we're shutting down.
                 Ok(()) => return Ok(()),
-                Err(e) => {
-                    tracing::warn!(?e);
-                    error_count += 1;
-                    // Retry a few times and then give up.
-                    if error_count > 3 {
-                        return Err(e);
+                Err(e) => e,
+            };
+            tracing::warn!(?e);
+            self.storage
+                .record_sync_source_health(&self.sync_url, Some(format!("{:#}", e)))
+                .await?;
+
+            if e.is::<NctRootMismatchError>() {
+                // The node disagrees with storage, so syncing from it again would fail the same
+                // way: wait for storage to be reset to a height before they diverged.
+                self.connection_tx.send_replace(SyncConnection::Desynced);
+                match self.reset_rx.recv().await {
+                    Some(reset) => {
+                        self.reset = Some(reset);
+                        self.reset().await?;
+                        continue;
                     }
+                    // The view services are dropped, so we're shutting down.
+                    None => return Ok(()),
+                }
+            }
+
+            if is_connection_error(&e) {
+                // The connection dropped, e.g., because the node restarted, so reconnect with
+                // exponential backoff, resuming from the last height recorded in storage. The
+                // backoff starts over if the last reconnection succeeded.
+                let previous = self
+                    .connection_tx
+                    .send_replace(SyncConnection::Reconnecting);
+                if previous == SyncConnection::Connected {
+                    reconnect_delay = MIN_RECONNECT_DELAY;
                 }
+                tracing::info!(?reconnect_delay, "reconnecting to node");
+                tokio::time::sleep(reconnect_delay).await;
+                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                continue;
+            }
+
+            error_count += 1;
+            // Retry a few times and then give up.
+            if error_count > 3 {
+                return Err(e);
             }
             // Wait a bit before restarting
             tokio::time::sleep(std::time::Duration::from_millis(1729)).await;
@@ -222,6 +679,143 @@ impl Worker {
     }
 }
 
+/// Recover the note and memo of an `output` we sent, using the outgoing viewing key `ovk`.
+fn decrypt_sent_output(output: &Output, ovk: &OutgoingViewingKey) -> Option<SentOutput> {
+    let body = &output.body;
+    let payload = &body.note_payload;
+    let note = Note::decrypt_outgoing(
+        payload.encrypted_note.as_ref(),
+        &body.ovk_wrapped_key,
+        ovk,
+        body.value_commitment,
+        payload.note_commitment,
+        &payload.ephemeral_key,
+    )
+    .ok()?;
+
+    let (transmission_key, esk) = Note::decrypt_key(
+        &body.ovk_wrapped_key,
+        ovk,
+        body.value_commitment,
+        payload.note_commitment,
+        &payload.ephemeral_key,
+    )
+    .ok()?;
+    let memo = MemoPlaintext::decrypt_outgoing(
+        body.encrypted_memo.clone(),
+        &esk,
+        &transmission_key,
+        &payload.ephemeral_key,
+    )
+    .ok()
+    .map(|memo| memo.text())
+    .filter(|text| !text.is_empty());
+
+    Some(SentOutput { note, memo })
+}
+
+/// Fetch the transactions in the block at `height` from the Tendermint RPC at `tendermint_url`.
+async fn fetch_transactions(
+    tendermint_url: &str,
+    height: u64,
+) -> Result<Vec<Transaction>, anyhow::Error> {
+    /// A transaction, as encoded in Tendermint RPC responses.
+    #[derive(serde::Deserialize)]
+    struct Tx(#[serde(with = "penumbra_proto::serializers::base64str")] Vec<u8>);
+
+    let rsp: serde_json::Value =
+        reqwest::get(format!("{}/block?height={}", tendermint_url, height))
+            .await?
+            .json()
+            .await?;
+
+    if let Some(error) = rsp.get("error") {
+        return Err(anyhow::anyhow!(
+            "could not fetch block {}: {}",
+            height,
+            error
+        ));
+    }
+    // Tendermint encodes an empty list of transactions as `null`.
+    let txs: Option<Vec<Tx>> =
+        serde_json::from_value(rsp.get("result").unwrap_or(&rsp)["block"]["data"]["txs"].clone())?;
+
+    txs.unwrap_or_default()
+        .into_iter()
+        .map(|Tx(bytes)| Transaction::decode(bytes.as_slice()))
+        .collect()
+}
+
+/// Check that the root of our NCT, after scanning the block at this height into it, matches the
+/// root committed to in the compact block, so that a sync bug is caught at the block where it
+/// happens, before the block's changes reach the shared NCT or storage, rather than when we next
+/// try to spend.
+///
+/// Blocks produced before the root was included in compact blocks aren't checked.
+fn check_nct_root(
+    height: u64,
+    expected_root: Option<penumbra_tct::Root>,
+    actual_root: penumbra_tct::Root,
+) -> anyhow::Result<()> {
+    let expected_root = match expected_root {
+        Some(expected_root) => expected_root,
+        None => return Ok(()),
+    };
+
+    if actual_root == expected_root {
+        Ok(())
+    } else {
+        let e = NctRootMismatchError {
+            height,
+            expected: expected_root,
+            actual: actual_root,
+        };
+        // Print the error immediately, so that it's visible in the logs.
+        tracing::error!(%e);
+        Err(e.into())
+    }
+}
+
+/// An error returned when the NCT root committed to in a compact block differs from the root of
+/// our NCT after scanning it, which means storage has diverged from the node's chain.
+#[derive(Debug)]
+struct NctRootMismatchError {
+    height: u64,
+    expected: penumbra_tct::Root,
+    actual: penumbra_tct::Root,
+}
+
+impl std::fmt::Display for NctRootMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "NCT root mismatch at height {}: compact block has {}, but our tree has {}",
+            self.height, self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for NctRootMismatchError {}
+
+/// Whether `e` means the connection to the node was lost, rather than that the node or storage
+/// misbehaved, so that the sync can resume once the node is reachable again.
+fn is_connection_error(e: &anyhow::Error) -> bool {
+    if e.is::<tonic::transport::Error>() {
+        return true;
+    }
+    match e.downcast_ref::<tonic::Status>() {
+        // A dropped connection surfaces as `Unknown` when a stream's body can't be read.
+        Some(status) => matches!(
+            status.code(),
+            tonic::Code::Unavailable
+                | tonic::Code::Unknown
+                | tonic::Code::Cancelled
+                | tonic::Code::DeadlineExceeded
+        ),
+        None => false,
+    }
+}
+
 #[cfg(feature = "nct-divergence-check")]
 async fn nct_divergence_check(
     client: &mut SpecificQueryClient<Channel>,
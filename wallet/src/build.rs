@@ -2,16 +2,37 @@ use anyhow::Result;
 use penumbra_crypto::FullViewingKey;
 use penumbra_custody::{AuthorizeRequest, CustodyClient};
 use penumbra_proto::view::WitnessRequest;
-use penumbra_transaction::{plan::TransactionPlan, Transaction};
+use penumbra_transaction::{
+    plan::{BuildProgress, TransactionPlan},
+    Transaction, WitnessData,
+};
 use penumbra_view::ViewClient;
 use rand_core::{CryptoRng, RngCore};
 
 pub async fn build_transaction<V, C, R>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    custody: &mut C,
+    rng: R,
+    plan: TransactionPlan,
+) -> Result<Transaction>
+where
+    V: ViewClient,
+    C: CustodyClient,
+    R: RngCore + CryptoRng,
+{
+    build_transaction_with_progress(fvk, view, custody, rng, plan, |_| {}).await
+}
+
+/// Like [`build_transaction`], but calls `progress` after each proof is generated, so that the
+/// caller can show progress while building a transaction with many spends or outputs.
+pub async fn build_transaction_with_progress<V, C, R>(
     fvk: &FullViewingKey,
     view: &mut V,
     custody: &mut C,
     mut rng: R,
     plan: TransactionPlan,
+    progress: impl FnMut(BuildProgress),
 ) -> Result<Transaction>
 where
     V: ViewClient,
@@ -27,16 +48,24 @@ where
         .await?;
 
     // Get the witness data from the view service...
-    let witness_data = view
-        .witness(WitnessRequest {
-            fvk_hash: Some(fvk.hash().into()),
-            note_commitments: plan
-                .spend_plans()
-                .map(|spend| spend.note.commit().into())
-                .collect(),
-        })
-        .await?;
+    let witness_data = witness(fvk, view, &plan).await?;
 
     // ... and then build the transaction:
-    plan.build(&mut rng, fvk, auth_data, witness_data)
+    plan.build_with_progress(&mut rng, fvk, auth_data, witness_data, progress)
+}
+
+/// Gets the witness data for the notes spent by `plan` from the view service.
+pub async fn witness<V: ViewClient>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    plan: &TransactionPlan,
+) -> Result<WitnessData> {
+    view.witness(WitnessRequest {
+        fvk_hash: Some(fvk.hash().into()),
+        note_commitments: plan
+            .spend_plans()
+            .map(|spend| spend.note.commit().into())
+            .collect(),
+    })
+    .await
 }
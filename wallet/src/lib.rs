@@ -2,6 +2,7 @@
 #![recursion_limit = "256"]
 
 mod build;
-pub use build::build_transaction;
+pub use build::{build_transaction, build_transaction_with_progress, witness};
 
 pub mod plan;
+pub mod template;
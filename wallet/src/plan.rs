@@ -4,12 +4,19 @@ use anyhow::Result;
 use penumbra_component::stake::rate::RateData;
 use penumbra_component::stake::validator;
 use penumbra_crypto::{
-    asset::Denom, keys::DiversifierIndex, memo::MemoPlaintext, transaction::Fee, Address,
-    DelegationToken, FullViewingKey, Value, STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM,
+    asset::{self, Denom},
+    keys::DiversifierIndex,
+    memo::MemoPlaintext,
+    transaction::Fee,
+    Address, Amount, DelegationToken, FullViewingKey, Note, Value, STAKING_TOKEN_ASSET_ID,
+    STAKING_TOKEN_DENOM,
 };
-use penumbra_proto::view::NotesRequest;
-use penumbra_transaction::plan::{ActionPlan, OutputPlan, SpendPlan, TransactionPlan};
-use penumbra_view::{NoteRecord, ViewClient};
+use penumbra_proto::view::{NoteSelectionStrategy, NotesRequest};
+use penumbra_transaction::{
+    plan::{ActionPlan, OutputPlan, SpendPlan, TransactionPlan},
+    Action, Transaction,
+};
+use penumbra_view::{NoteRecord, SelectionStrategy, ViewClient};
 use rand_core::{CryptoRng, RngCore};
 use tracing::instrument;
 
@@ -19,7 +26,7 @@ pub async fn validator_definition<V, R>(
     mut rng: R,
     new_validator: validator::Definition,
     fee: u64,
-    source_address: Option<u64>,
+    source_address: Option<DiversifierIndex>,
 ) -> Result<TransactionPlan>
 where
     V: ViewClient,
@@ -29,7 +36,7 @@ where
     // address; otherwise, send it to the default address.
     let (self_address, _dtk) = fvk
         .incoming()
-        .payment_address(source_address.unwrap_or(0).into());
+        .payment_address(source_address.unwrap_or_default());
 
     let chain_params = view.chain_params().await?;
 
@@ -43,33 +50,48 @@ where
         .push(ActionPlan::ValidatorDefinition(new_validator.into()));
 
     // Add the required spends, and track change:
-    let spend_amount = fee;
-    let mut spent_amount = 0;
-    let source_index: Option<DiversifierIndex> = source_address.map(Into::into);
+    let spend_amount = Amount::from(fee);
+    check_funds(
+        fvk,
+        view,
+        &[(STAKING_TOKEN_DENOM.clone(), spend_amount)]
+            .into_iter()
+            .collect(),
+        source_address,
+    )
+    .await?;
     let notes_to_spend = view
         .notes(NotesRequest {
             fvk_hash: Some(fvk.hash().into()),
             asset_id: Some((*STAKING_TOKEN_ASSET_ID).into()),
-            diversifier_index: source_index.map(Into::into),
-            amount_to_spend: spend_amount,
+            diversifier_index: source_address.map(Into::into),
+            amount_to_spend: spend_amount.into(),
             include_spent: false,
+            exclude_reserved: true,
+            ..Default::default()
         })
         .await?;
+    let spent_amount = total_amount(&notes_to_spend)?;
     for note_record in notes_to_spend {
-        spent_amount += note_record.note.amount();
         plan.actions
             .push(SpendPlan::new(&mut rng, note_record.note, note_record.position).into());
     }
     // Add a change note if we have change left over:
-    let change_amount = spent_amount - spend_amount;
+    let change_amount = spent_amount.checked_sub(spend_amount).ok_or_else(|| {
+        anyhow::anyhow!(
+            "not enough notes to pay fee: wanted {}, have {}",
+            spend_amount,
+            spent_amount
+        )
+    })?;
     // TODO: support dummy notes, and produce a change output unconditionally.
     // let change_note = if change_amount > 0 { ... } else { /* dummy note */}
-    if change_amount > 0 {
+    if change_amount > Amount::zero() {
         plan.actions.push(
             OutputPlan::new(
                 &mut rng,
                 Value {
-                    amount: change_amount,
+                    amount: change_amount.into(),
                     asset_id: *STAKING_TOKEN_ASSET_ID,
                 },
                 self_address,
@@ -79,6 +101,8 @@ where
         );
     }
 
+    reserve_spends(fvk, view, &mut rng, &plan).await?;
+
     Ok(plan)
 }
 
@@ -91,7 +115,7 @@ pub async fn delegate<V, R>(
     rate_data: RateData,
     unbonded_amount: u64,
     fee: u64,
-    source_address: Option<u64>,
+    source_address: Option<DiversifierIndex>,
 ) -> Result<TransactionPlan>
 where
     V: ViewClient,
@@ -101,7 +125,7 @@ where
     // address; otherwise, send them to the default address.
     let (self_address, _dtk) = fvk
         .incoming()
-        .payment_address(source_address.unwrap_or(0).into());
+        .payment_address(source_address.unwrap_or_default());
 
     let chain_params = view.chain_params().await?;
 
@@ -130,45 +154,54 @@ where
     );
 
     // Get a list of notes to spend from the view service:
-    let spend_amount = unbonded_amount + fee;
-    let source_index: Option<DiversifierIndex> = source_address.map(Into::into);
+    let spend_amount = Amount::from(unbonded_amount)
+        .checked_add(fee.into())
+        .ok_or_else(|| anyhow::anyhow!("delegation amount and fee overflowed"))?;
+    check_funds(
+        fvk,
+        view,
+        &[(STAKING_TOKEN_DENOM.clone(), spend_amount)]
+            .into_iter()
+            .collect(),
+        source_address,
+    )
+    .await?;
     let notes_to_spend = view
         .notes(NotesRequest {
             fvk_hash: Some(fvk.hash().into()),
             asset_id: Some((*STAKING_TOKEN_ASSET_ID).into()),
-            diversifier_index: source_index.map(Into::into),
-            amount_to_spend: spend_amount,
+            diversifier_index: source_address.map(Into::into),
+            amount_to_spend: spend_amount.into(),
             include_spent: false,
+            exclude_reserved: true,
+            ..Default::default()
         })
         .await?;
 
     // Add the required spends, and track change:
-    let mut spent_amount = 0;
+    let spent_amount = total_amount(&notes_to_spend)?;
     for note_record in notes_to_spend {
-        spent_amount += note_record.note.amount();
         plan.actions
             .push(SpendPlan::new(&mut rng, note_record.note, note_record.position).into());
     }
 
-    if spent_amount < spend_amount {
-        return Err(anyhow::anyhow!(
+    // Add a change note if we have change left over:
+    let change_amount = spent_amount.checked_sub(spend_amount).ok_or_else(|| {
+        anyhow::anyhow!(
             "not enough notes to delegate: wanted to delegate {}, have {}",
             spend_amount,
             spent_amount
-        ));
-    }
-
-    // Add a change note if we have change left over:
-    let change_amount = spent_amount - spend_amount;
+        )
+    })?;
 
     // TODO: support dummy notes, and produce a change output unconditionally.
     // let change_note = if change_amount > 0 { ... } else { /* dummy note */}
-    if change_amount > 0 {
+    if change_amount > Amount::zero() {
         plan.actions.push(
             OutputPlan::new(
                 &mut rng,
                 Value {
-                    amount: change_amount,
+                    amount: change_amount.into(),
                     asset_id: *STAKING_TOKEN_ASSET_ID,
                 },
                 self_address,
@@ -178,6 +211,8 @@ where
         );
     }
 
+    reserve_spends(fvk, view, &mut rng, &plan).await?;
+
     Ok(plan)
 }
 
@@ -189,7 +224,7 @@ pub async fn undelegate<V, R>(
     rate_data: RateData,
     delegation_notes: Vec<NoteRecord>,
     fee: u64,
-    source_address: Option<u64>,
+    source_address: Option<DiversifierIndex>,
 ) -> Result<TransactionPlan>
 where
     V: ViewClient,
@@ -197,16 +232,11 @@ where
 {
     let (self_address, _dtk) = fvk
         .incoming()
-        .payment_address(source_address.unwrap_or(0).into());
+        .payment_address(source_address.unwrap_or_default());
 
     let chain_params = view.chain_params().await?;
 
-    let delegation_amount = delegation_notes
-        .iter()
-        .map(|record| record.note.amount())
-        .sum();
-
-    let spend_amount = delegation_amount;
+    let delegation_amount: u64 = total_amount(&delegation_notes)?.into();
 
     // Because the outputs of an undelegation are quarantined, we want to
     // avoid any unnecessary change outputs, so we pay fees out of the
@@ -245,25 +275,108 @@ where
         .into(),
     );
 
-    let mut spent_amount = 0;
     for note_record in delegation_notes {
-        tracing::debug!(?note_record, ?spend_amount);
-        spent_amount += note_record.note.amount();
+        tracing::debug!(?note_record);
         plan.actions
             .push(SpendPlan::new(&mut rng, note_record.note, note_record.position).into());
     }
 
-    if spent_amount < spend_amount {
-        Err(anyhow::anyhow!(
-            "not enough delegated tokens to undelegate: wanted to undelegate {}, have {}",
-            spend_amount,
-            spent_amount,
-        ))?;
+    reserve_spends(fvk, view, &mut rng, &plan).await?;
+
+    Ok(plan)
+}
+
+/// Generate a new transaction plan moving stake from one validator to another
+///
+/// The delegation notes are undelegated from the validator described by `from_rate_data`, and the
+/// unbonded stake is delegated to the validator described by `to_rate_data` in the same
+/// transaction, so the stake is delegated to the new validator straight away. Like any output of
+/// an undelegation, the new delegation tokens are quarantined until the old validator's unbonding
+/// period ends.
+#[allow(clippy::too_many_arguments)]
+pub async fn redelegate<V, R>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    mut rng: R,
+    from_rate_data: RateData,
+    to_rate_data: RateData,
+    delegation_notes: Vec<NoteRecord>,
+    fee: u64,
+    source_address: Option<DiversifierIndex>,
+) -> Result<TransactionPlan>
+where
+    V: ViewClient,
+    R: RngCore + CryptoRng,
+{
+    let (self_address, _dtk) = fvk
+        .incoming()
+        .payment_address(source_address.unwrap_or_default());
+
+    let chain_params = view.chain_params().await?;
+
+    let delegation_amount: u64 = total_amount(&delegation_notes)?.into();
+
+    // As for undelegations, we pay fees out of the unbonded amount, to avoid
+    // any unnecessary (quarantined) change outputs.
+    let unbonded_amount = from_rate_data.unbonded_amount(delegation_amount);
+    let redelegated_amount = unbonded_amount.checked_sub(fee).ok_or_else(|| {
+        anyhow::anyhow!(
+            "unbonded amount {} from delegation amount {} is insufficient to pay fees {}",
+            unbonded_amount,
+            delegation_amount,
+            fee
+        )
+    })?;
+
+    let mut plan = TransactionPlan {
+        chain_id: chain_params.chain_id,
+        fee: Fee(fee),
+        ..Default::default()
+    };
+
+    // Add the undelegation from the old validator, and the delegation to the new one:
+    plan.actions
+        .push(from_rate_data.build_undelegate(delegation_amount).into());
+    plan.actions
+        .push(to_rate_data.build_delegate(redelegated_amount).into());
+
+    // Add an output to ourselves to record the new delegation:
+    plan.actions.push(
+        OutputPlan::new(
+            &mut rng,
+            Value {
+                amount: to_rate_data.delegation_amount(redelegated_amount),
+                asset_id: DelegationToken::new(to_rate_data.identity_key).id(),
+            },
+            self_address,
+            MemoPlaintext::default(),
+        )
+        .into(),
+    );
+
+    for note_record in delegation_notes {
+        plan.actions
+            .push(SpendPlan::new(&mut rng, note_record.note, note_record.position).into());
     }
 
+    reserve_spends(fvk, view, &mut rng, &plan).await?;
+
     Ok(plan)
 }
 
+/// Generate a new transaction plan sending funds to `dest_address`.
+///
+/// If `source_address` is unset, notes are selected from any address index, unless
+/// `single_account` is set, in which case the funds are spent from whichever single address index
+/// can cover the entire transaction, so that funds received by different addresses are never
+/// merged.
+///
+/// If `include_return_address` is set, the memo of each output to `dest_address` begins with the
+/// address of the source index (or index 0, if funds may come from any index), so that the
+/// recipient can refund the payment.
+///
+/// The notes to spend are chosen according to `note_selection`.
+#[allow(clippy::too_many_arguments)]
 #[instrument(skip(fvk, view, rng, values, fee, dest_address, source_address, tx_memo))]
 pub async fn send<V, R>(
     fvk: &FullViewingKey,
@@ -272,19 +385,26 @@ pub async fn send<V, R>(
     values: &[Value],
     fee: u64,
     dest_address: Address,
-    source_address: Option<u64>,
+    source_address: Option<DiversifierIndex>,
     tx_memo: Option<String>,
+    single_account: bool,
+    include_return_address: bool,
+    note_selection: SelectionStrategy,
 ) -> Result<TransactionPlan, anyhow::Error>
 where
     V: ViewClient,
     R: RngCore + CryptoRng,
 {
-    tracing::debug!(?values, ?fee, ?dest_address, ?source_address, ?tx_memo);
-    let memo = if let Some(input_memo) = tx_memo {
-        input_memo.as_bytes().try_into()?
-    } else {
-        MemoPlaintext::default()
-    };
+    tracing::debug!(
+        ?values,
+        ?fee,
+        ?dest_address,
+        ?source_address,
+        ?tx_memo,
+        ?single_account,
+        ?include_return_address,
+        ?note_selection
+    );
 
     let chain_params = view.chain_params().await?;
 
@@ -297,21 +417,55 @@ where
     let assets = view.assets().await?;
     // Track totals of the output values rather than just processing
     // them individually, so we can plan the required spends.
-    let mut output_value = HashMap::<Denom, u64>::new();
+    let mut output_value = HashMap::<Denom, Amount>::new();
     for Value { amount, asset_id } in values {
         let denom = assets
             .get(asset_id)
             .ok_or_else(|| anyhow::anyhow!("unknown denomination for asset id {}", asset_id))?;
-        output_value.insert(denom.clone(), *amount);
+        let total = output_value.entry(denom.clone()).or_default();
+        *total = total
+            .checked_add((*amount).into())
+            .ok_or_else(|| anyhow::anyhow!("total output amount of {} overflowed", denom))?;
+    }
+
+    // The value we need to spend is the output value, plus fees.
+    let mut value_to_spend = output_value.clone();
+    if fee > 0 {
+        let staking_amount = value_to_spend
+            .entry(STAKING_TOKEN_DENOM.clone())
+            .or_default();
+        *staking_amount = staking_amount
+            .checked_add(fee.into())
+            .ok_or_else(|| anyhow::anyhow!("output amount and fee overflowed"))?;
     }
 
+    check_funds(fvk, view, &value_to_spend, source_address).await?;
+
+    // If we were asked to keep funds received by different address indices
+    // apart, pick a single address index to spend everything from.
+    let source_address = match source_address {
+        Some(index) => Some(index),
+        None if single_account => Some(single_source_address(fvk, view, &value_to_spend).await?),
+        None => None,
+    };
+
+    let tx_memo = tx_memo.unwrap_or_default();
+    let memo = if include_return_address {
+        let (return_address, _dtk) = fvk
+            .incoming()
+            .payment_address(source_address.unwrap_or_default());
+        MemoPlaintext::with_return_address(&mut rng, fvk.incoming(), &return_address, &tx_memo)?
+    } else {
+        MemoPlaintext::from_text(&tx_memo)?
+    };
+
     // Add outputs for the funds we want to send:
     for (denom, amount) in &output_value {
         plan.actions.push(
             OutputPlan::new(
                 &mut rng,
                 Value {
-                    amount: *amount,
+                    amount: (*amount).into(),
                     asset_id: denom.id(),
                 },
                 dest_address,
@@ -321,30 +475,24 @@ where
         );
     }
 
-    // The value we need to spend is the output value, plus fees.
-    let mut value_to_spend = output_value;
-    if fee > 0 {
-        *value_to_spend
-            .entry(STAKING_TOKEN_DENOM.clone())
-            .or_default() += fee;
-    }
-
     // Add the required spends:
     for (denom, spend_amount) in value_to_spend {
         // Only produce an output if the amount is greater than zero
-        if spend_amount == 0 {
+        if spend_amount == Amount::zero() {
             continue;
         }
 
-        let source_index: Option<DiversifierIndex> = source_address.map(Into::into);
         // Select a list of notes that provides at least the required amount.
         let notes_to_spend = view
             .notes(NotesRequest {
                 fvk_hash: Some(fvk.hash().into()),
                 asset_id: Some(denom.id().into()),
-                diversifier_index: source_index.map(Into::into),
-                amount_to_spend: spend_amount,
+                diversifier_index: source_address.map(Into::into),
+                amount_to_spend: spend_amount.into(),
                 include_spent: false,
+                exclude_reserved: true,
+                selection_strategy: NoteSelectionStrategy::from(note_selection) as i32,
+                ..Default::default()
             })
             .await?;
         if notes_to_spend.is_empty() {
@@ -352,22 +500,16 @@ where
             return Err(anyhow::anyhow!("not enough notes to spend",));
         }
 
-        let change_address_index: u64 = fvk
-            .incoming()
-            .index_for_diversifier(
-                &notes_to_spend
-                    .last()
-                    .expect("notes_to_spend should never be empty")
-                    .note
-                    .diversifier(),
-            )
-            .try_into()?;
+        let change_address_index = fvk.incoming().index_for_diversifier(
+            &notes_to_spend
+                .last()
+                .expect("notes_to_spend should never be empty")
+                .note
+                .diversifier(),
+        );
 
-        let (change_address, _dtk) = fvk.incoming().payment_address(change_address_index.into());
-        let spent: u64 = notes_to_spend
-            .iter()
-            .map(|note_record| note_record.note.amount())
-            .sum();
+        let (change_address, _dtk) = fvk.incoming().payment_address(change_address_index);
+        let spent = total_amount(&notes_to_spend)?;
 
         // Spend each of the notes we selected.
         for note_record in notes_to_spend {
@@ -376,13 +518,15 @@ where
         }
 
         // Find out how much change we have and whether to add a change output.
-        let change = spent - spend_amount;
-        if change > 0 {
+        let change = spent
+            .checked_sub(spend_amount)
+            .ok_or_else(|| anyhow::anyhow!("not enough notes to spend"))?;
+        if change > Amount::zero() {
             plan.actions.push(
                 OutputPlan::new(
                     &mut rng,
                     Value {
-                        amount: change,
+                        amount: change.into(),
                         asset_id: denom.id(),
                     },
                     change_address,
@@ -393,15 +537,363 @@ where
         }
     }
 
+    reserve_spends(fvk, view, &mut rng, &plan).await?;
+
     Ok(plan)
 }
 
+/// Generate a new transaction plan splitting off a note holding exactly `value`, sent back to
+/// `source_address` (or index 0, if it's unset).
+///
+/// This is how exact amounts are prepared for actions which consume whole notes, like
+/// undelegations. If `source_address` is unset, `single_account` is as for [`send`].
+#[instrument(skip(fvk, view, rng, value, fee))]
+pub async fn split<V, R>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    rng: R,
+    value: Value,
+    fee: u64,
+    source_address: Option<DiversifierIndex>,
+    single_account: bool,
+) -> Result<TransactionPlan, anyhow::Error>
+where
+    V: ViewClient,
+    R: RngCore + CryptoRng,
+{
+    let (self_address, _dtk) = fvk
+        .incoming()
+        .payment_address(source_address.unwrap_or_default());
+
+    send(
+        fvk,
+        view,
+        rng,
+        &[value],
+        fee,
+        self_address,
+        source_address,
+        None,
+        single_account,
+        false,
+        SelectionStrategy::default(),
+    )
+    .await
+}
+
+/// Generate a new transaction plan moving funds between two of our own address indices.
+///
+/// The funds are spent only from notes received by `source_address`, with any change returned
+/// there, and the output is marked as an internal transfer in its memo, so that it can be told
+/// apart from a payment when reviewing the transaction later.
+#[instrument(skip(fvk, view, rng, values, fee))]
+pub async fn transfer<V, R>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    rng: R,
+    values: &[Value],
+    fee: u64,
+    source_address: DiversifierIndex,
+    dest_address: DiversifierIndex,
+) -> Result<TransactionPlan, anyhow::Error>
+where
+    V: ViewClient,
+    R: RngCore + CryptoRng,
+{
+    if source_address == dest_address {
+        return Err(anyhow::anyhow!(
+            "cannot transfer from address {} to itself",
+            source_address
+        ));
+    }
+
+    let (dest, _dtk) = fvk.incoming().payment_address(dest_address);
+    let memo = format!(
+        "internal transfer from address {} to address {}",
+        source_address, dest_address
+    );
+
+    send(
+        fvk,
+        view,
+        rng,
+        values,
+        fee,
+        dest,
+        Some(source_address),
+        Some(memo),
+        false,
+        false,
+        SelectionStrategy::default(),
+    )
+    .await
+}
+
+/// Generate a new transaction plan refunding the payments we received in `transaction` to the
+/// return address in their memos.
+///
+/// Only return addresses which the sender proved they own are used, so that a sender can't direct
+/// the refund to someone else. The refund is spent from the address index which received the
+/// payment, and carries no return address of its own, so that it can't be bounced back.
+#[instrument(skip(fvk, view, rng, transaction, fee))]
+pub async fn refund<V, R>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    rng: R,
+    transaction: &Transaction,
+    fee: u64,
+) -> Result<TransactionPlan, anyhow::Error>
+where
+    V: ViewClient,
+    R: RngCore + CryptoRng,
+{
+    let mut return_address = None;
+    let mut source_address = None;
+    let mut values = BTreeMap::<asset::Id, Amount>::new();
+
+    for action in transaction.actions() {
+        let output = match action {
+            Action::Output(output) => output,
+            _ => continue,
+        };
+        let payload = &output.body.note_payload;
+
+        // Only outputs addressed to us can be decrypted with our viewing key.
+        let note = match Note::decrypt(
+            payload.encrypted_note.as_ref(),
+            fvk.incoming(),
+            &payload.ephemeral_key,
+        ) {
+            Ok(note) => note,
+            Err(_) => continue,
+        };
+        let memo = MemoPlaintext::decrypt(
+            output.body.encrypted_memo.clone(),
+            fvk.incoming(),
+            &payload.ephemeral_key,
+        )?;
+
+        // Skip our own change outputs, and payments which can't be refunded.
+        let address = match memo.return_address() {
+            Some(address) if !fvk.incoming().views_address(&address) => address,
+            _ => continue,
+        };
+        if return_address.map_or(false, |return_address| return_address != address) {
+            return Err(anyhow::anyhow!(
+                "transaction contains payments with different return addresses"
+            ));
+        }
+        return_address = Some(address);
+
+        // Spend from the index the payment was actually sent to, which must be the same for
+        // every payment refunded, since the refund is spent from a single index.
+        let index = refund_source_index(fvk, &note)?;
+        if source_address.map_or(false, |source_address| source_address != index) {
+            return Err(anyhow::anyhow!(
+                "transaction contains payments to different address indices"
+            ));
+        }
+        source_address = Some(index);
+        let total = values.entry(note.asset_id()).or_default();
+        *total = total
+            .checked_add(note.amount())
+            .ok_or_else(|| anyhow::anyhow!("refunded amount overflowed"))?;
+    }
+
+    let return_address = return_address.ok_or_else(|| {
+        anyhow::anyhow!("transaction contains no refundable payments to this wallet")
+    })?;
+    let values = values
+        .into_iter()
+        .map(|(asset_id, amount)| Value {
+            amount: amount.into(),
+            asset_id,
+        })
+        .collect::<Vec<_>>();
+    let memo = format!("refund of transaction {}", hex::encode(transaction.id()));
+
+    send(
+        fvk,
+        view,
+        rng,
+        &values,
+        fee,
+        return_address,
+        source_address,
+        Some(memo),
+        false,
+        false,
+        SelectionStrategy::default(),
+    )
+    .await
+}
+
+/// The address index which received `note`, checking that the address at that index is the one
+/// the note was actually sent to.
+fn refund_source_index(
+    fvk: &FullViewingKey,
+    note: &Note,
+) -> Result<DiversifierIndex, anyhow::Error> {
+    let index = fvk.incoming().index_for_diversifier(&note.diversifier());
+    let (address, _dtk) = fvk.incoming().payment_address(index);
+    if *address.diversifier() != note.diversifier()
+        || *address.transmission_key() != note.transmission_key()
+    {
+        return Err(anyhow::anyhow!(
+            "could not resolve the address index of a refunded payment"
+        ));
+    }
+    Ok(index)
+}
+
+/// The error returned when the wallet doesn't hold enough of some assets to fund a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsufficientFunds {
+    /// The assets the wallet doesn't hold enough of.
+    pub shortfalls: Vec<Shortfall>,
+}
+
+/// How much of an asset a transaction requires, and how much the wallet can spend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortfall {
+    pub denom: Denom,
+    pub required: Amount,
+    pub available: Amount,
+}
+
+impl std::fmt::Display for InsufficientFunds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "insufficient funds:")?;
+        for (i, shortfall) in self.shortfalls.iter().enumerate() {
+            let missing = shortfall
+                .required
+                .checked_sub(shortfall.available)
+                .unwrap_or_default();
+            write!(
+                f,
+                "{} need {}{}, but only {}{} is spendable (short by {}{})",
+                if i == 0 { "" } else { ";" },
+                shortfall.required,
+                shortfall.denom,
+                shortfall.available,
+                shortfall.denom,
+                missing,
+                shortfall.denom,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InsufficientFunds {}
+
+/// Check that the unspent, unreserved notes at `source_address` (or at any address, if it's
+/// `None`) cover `value_to_spend`, so that a shortfall is reported for every asset at once,
+/// rather than as a failure to select notes for the first one.
+async fn check_funds<V: ViewClient>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    value_to_spend: &HashMap<Denom, Amount>,
+    source_address: Option<DiversifierIndex>,
+) -> Result<()> {
+    let notes = view
+        .notes(NotesRequest {
+            fvk_hash: Some(fvk.hash().into()),
+            diversifier_index: source_address.map(Into::into),
+            include_spent: false,
+            exclude_reserved: true,
+            ..Default::default()
+        })
+        .await?;
+
+    let mut shortfalls = Vec::new();
+    for (denom, &required) in value_to_spend {
+        let available = total_amount(
+            notes
+                .iter()
+                .filter(|record| record.note.asset_id() == denom.id()),
+        )?;
+        if available < required {
+            shortfalls.push(Shortfall {
+                denom: denom.clone(),
+                required,
+                available,
+            });
+        }
+    }
+
+    if shortfalls.is_empty() {
+        Ok(())
+    } else {
+        Err(InsufficientFunds { shortfalls }.into())
+    }
+}
+
+/// Sum the amounts of the given notes, failing rather than overflowing.
+fn total_amount<'a>(records: impl IntoIterator<Item = &'a NoteRecord>) -> Result<Amount> {
+    Amount::checked_sum(records.into_iter().map(|record| record.note.amount()))
+        .ok_or_else(|| anyhow::anyhow!("total amount of notes overflowed"))
+}
+
+/// Find the lowest address index whose unspent notes alone can cover `value_to_spend`.
+async fn single_source_address<V: ViewClient>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    value_to_spend: &HashMap<Denom, Amount>,
+) -> Result<DiversifierIndex> {
+    let notes = view.unspent_notes_by_address_and_asset(fvk.hash()).await?;
+
+    for (index, notes_by_asset) in notes {
+        let covers = value_to_spend.iter().all(|(denom, &amount)| {
+            match notes_by_asset.get(&denom.id()) {
+                // If the total overflows, it certainly covers the amount
+                Some(records) => {
+                    total_amount(records).map_or(true, |available| available >= amount)
+                }
+                None => amount == Amount::zero(),
+            }
+        });
+        if covers {
+            return Ok(index);
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "no single address holds enough funds for this transaction: specify a source address, or allow spending across addresses"
+    ))
+}
+
 #[instrument(skip(fvk, view, rng))]
 pub async fn sweep<V, R>(
     fvk: &FullViewingKey,
     view: &mut V,
-    mut rng: R,
+    rng: R,
 ) -> Result<Vec<TransactionPlan>, anyhow::Error>
+where
+    V: ViewClient,
+    R: RngCore + CryptoRng,
+{
+    let mut plans = Vec::new();
+    match plan_sweeps(fvk, view, rng, &mut plans).await {
+        Ok(()) => Ok(plans),
+        Err(e) => {
+            // None of the sweeps will be submitted, so don't leave the notes of those we did plan
+            // reserved.
+            for plan in &plans {
+                release(fvk, view, plan).await?;
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Plan the sweeps for [`sweep`], adding each to `plans` once its notes are reserved.
+async fn plan_sweeps<V, R>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    mut rng: R,
+    plans: &mut Vec<TransactionPlan>,
+) -> Result<()>
 where
     V: ViewClient,
     R: RngCore + CryptoRng,
@@ -413,6 +905,7 @@ where
     let all_notes = view
         .notes(NotesRequest {
             fvk_hash: Some(fvk.hash().into()),
+            exclude_reserved: true,
             ..Default::default()
         })
         .await?;
@@ -429,15 +922,13 @@ where
             .push(record);
     }
 
-    let mut plans = Vec::new();
-
     for (index, notes_by_denom) in notes_by_addr_and_denom {
         tracing::info!(?index, "processing address");
         let (addr, _dtk) = fvk.incoming().payment_address(index);
 
         for (asset_id, mut records) in notes_by_denom {
             // Sort notes by amount, ascending, so the biggest notes are at the end...
-            records.sort_by(|a, b| a.note.value().amount.cmp(&b.note.value().amount));
+            records.sort_by_key(|record| record.note.amount());
             // ... so that when we use chunks_exact, we get SWEEP_COUNT sized
             // chunks, ignoring the biggest notes in the remainder.
             for group in records.chunks_exact(SWEEP_COUNT) {
@@ -456,7 +947,7 @@ where
                     OutputPlan::new(
                         &mut rng,
                         Value {
-                            amount: group.iter().map(|record| record.note.amount()).sum(),
+                            amount: total_amount(group)?.into(),
                             asset_id,
                         },
                         addr,
@@ -466,10 +957,98 @@ where
                 );
 
                 tracing::debug!(?plan);
+                reserve_spends(fvk, view, &mut rng, &plan).await?;
                 plans.push(plan);
             }
         }
     }
 
-    Ok(plans)
+    Ok(())
+}
+
+/// Soft-reserve the notes spent by `plan` in the view service, so that concurrent clients
+/// planning against the same view service don't select them too.
+async fn reserve_spends<V, R>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    rng: &mut R,
+    plan: &TransactionPlan,
+) -> Result<()>
+where
+    V: ViewClient,
+    R: RngCore + CryptoRng,
+{
+    let note_commitments: Vec<_> = plan
+        .spend_plans()
+        .map(|spend_plan| spend_plan.note.commit())
+        .collect();
+    if note_commitments.is_empty() {
+        return Ok(());
+    }
+
+    let mut plan_id = vec![0; 32];
+    rng.fill_bytes(&mut plan_id);
+    view.reserve_notes(fvk.hash(), plan_id, note_commitments)
+        .await
+}
+
+/// Release the reservations of the notes spent by `plan`, which was planned by one of the
+/// functions in this module, e.g., because building or submitting it failed, so that they can be
+/// selected into other plans right away, rather than once the reservations lapse.
+pub async fn release<V: ViewClient>(
+    fvk: &FullViewingKey,
+    view: &mut V,
+    plan: &TransactionPlan,
+) -> Result<()> {
+    let note_commitments: Vec<_> = plan
+        .spend_plans()
+        .map(|spend_plan| spend_plan.note.commit())
+        .collect();
+    if note_commitments.is_empty() {
+        return Ok(());
+    }
+
+    view.release_notes(fvk.hash(), note_commitments).await
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::keys::{SeedPhrase, SpendKey};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn note_to(address: &Address) -> Note {
+        Note::generate(
+            &mut OsRng,
+            address,
+            Value {
+                amount: 10u64.into(),
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            },
+        )
+    }
+
+    #[test]
+    fn refunds_are_spent_from_the_receiving_index() {
+        let fvk = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0)
+            .full_viewing_key()
+            .clone();
+
+        for index in [0u64, 1, 17, u32::MAX as u64 + 1] {
+            let index = DiversifierIndex::from(index);
+            let (address, _dtk) = fvk.incoming().payment_address(index);
+            assert_eq!(
+                refund_source_index(&fvk, &note_to(&address)).unwrap(),
+                index
+            );
+        }
+
+        // A note to someone else's address doesn't resolve to any of our indices.
+        let (other_address, _dtk) = SpendKey::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0)
+            .full_viewing_key()
+            .incoming()
+            .payment_address(1u64.into());
+        assert!(refund_source_index(&fvk, &note_to(&other_address)).is_err());
+    }
 }
@@ -0,0 +1,271 @@
+//! Templates for the transactions wallets make most often.
+//!
+//! A [`Template`] describes a transaction by its high-level parameters, checks them, and plans it
+//! with the functions in [`crate::plan`], so that every frontend, whether `pcli`, a GUI, or a bot,
+//! plans the same operation in the same way.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use penumbra_component::stake::rate::RateData;
+use penumbra_crypto::{
+    asset, keys::DiversifierIndex, Address, DelegationToken, FullViewingKey, Value,
+};
+use penumbra_transaction::plan::TransactionPlan;
+use penumbra_view::{NoteRecord, SelectionStrategy, ViewClient};
+use rand_core::{CryptoRng, RngCore};
+
+use crate::plan;
+
+/// A common operation to plan a transaction for.
+#[derive(Debug, Clone)]
+pub enum Template {
+    /// Send `values` to `dest_address`. See [`plan::send`].
+    Send {
+        values: Vec<Value>,
+        dest_address: Address,
+        fee: u64,
+        source_address: Option<DiversifierIndex>,
+        memo: Option<String>,
+        single_account: bool,
+        include_return_address: bool,
+        note_selection: SelectionStrategy,
+    },
+    /// Delegate `unbonded_amount` of the staking token to the validator described by
+    /// `rate_data`. See [`plan::delegate`].
+    Delegate {
+        rate_data: RateData,
+        unbonded_amount: u64,
+        fee: u64,
+        source_address: Option<DiversifierIndex>,
+    },
+    /// Undelegate all of `delegation_notes` from the validator described by `rate_data`. See
+    /// [`plan::undelegate`].
+    Undelegate {
+        rate_data: RateData,
+        delegation_notes: Vec<NoteRecord>,
+        fee: u64,
+        source_address: Option<DiversifierIndex>,
+    },
+    /// Sweep small notes into larger ones, which may take several transactions. See
+    /// [`plan::sweep`].
+    Sweep,
+    /// Split off a note holding exactly `value`. See [`plan::split`].
+    Split {
+        value: Value,
+        fee: u64,
+        source_address: Option<DiversifierIndex>,
+        single_account: bool,
+    },
+}
+
+/// The error returned when a [`Template`]'s parameters describe a transaction which can't be
+/// planned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidTemplate {
+    /// Nothing would be sent.
+    NoValues,
+    /// A value to send, delegate, or split has a zero amount.
+    ZeroAmount,
+    /// The same asset is listed more than once among the values to send.
+    DuplicateAsset(asset::Id),
+    /// There are no notes to undelegate.
+    NoDelegationNotes,
+    /// A note to undelegate isn't a delegation token for the validator being undelegated from.
+    WrongDelegationToken(asset::Id),
+    /// The staking token amount and fee don't fit in an amount.
+    Overflow,
+}
+
+impl std::fmt::Display for InvalidTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidTemplate::NoValues => write!(f, "no values to send"),
+            InvalidTemplate::ZeroAmount => write!(f, "amounts must be greater than zero"),
+            InvalidTemplate::DuplicateAsset(asset_id) => {
+                write!(f, "asset {} is listed more than once", asset_id)
+            }
+            InvalidTemplate::NoDelegationNotes => write!(f, "no delegation notes to undelegate"),
+            InvalidTemplate::WrongDelegationToken(asset_id) => write!(
+                f,
+                "note of asset {} is not a delegation token for the validator",
+                asset_id
+            ),
+            InvalidTemplate::Overflow => write!(f, "amount and fee overflowed"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidTemplate {}
+
+impl Template {
+    /// Check the template's parameters, without consulting the view service.
+    ///
+    /// Whether the wallet holds enough funds is only checked when planning.
+    pub fn validate(&self) -> Result<(), InvalidTemplate> {
+        match self {
+            Template::Send { values, .. } => {
+                if values.is_empty() {
+                    return Err(InvalidTemplate::NoValues);
+                }
+                let mut assets = BTreeSet::new();
+                for value in values {
+                    if value.amount == 0 {
+                        return Err(InvalidTemplate::ZeroAmount);
+                    }
+                    if !assets.insert(value.asset_id) {
+                        return Err(InvalidTemplate::DuplicateAsset(value.asset_id));
+                    }
+                }
+            }
+            Template::Delegate {
+                unbonded_amount,
+                fee,
+                ..
+            } => {
+                if *unbonded_amount == 0 {
+                    return Err(InvalidTemplate::ZeroAmount);
+                }
+                unbonded_amount
+                    .checked_add(*fee)
+                    .ok_or(InvalidTemplate::Overflow)?;
+            }
+            Template::Undelegate {
+                rate_data,
+                delegation_notes,
+                ..
+            } => {
+                if delegation_notes.is_empty() {
+                    return Err(InvalidTemplate::NoDelegationNotes);
+                }
+                let delegation_token = DelegationToken::new(rate_data.identity_key).id();
+                for record in delegation_notes {
+                    if record.note.asset_id() != delegation_token {
+                        return Err(InvalidTemplate::WrongDelegationToken(
+                            record.note.asset_id(),
+                        ));
+                    }
+                }
+            }
+            Template::Sweep => {}
+            Template::Split { value, .. } => {
+                if value.amount == 0 {
+                    return Err(InvalidTemplate::ZeroAmount);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the template, then plan the transactions it describes, in the order they should
+    /// be submitted.
+    ///
+    /// Every template but [`Template::Sweep`] plans exactly one transaction.
+    pub async fn plan<V, R>(
+        self,
+        fvk: &FullViewingKey,
+        view: &mut V,
+        rng: R,
+    ) -> Result<Vec<TransactionPlan>>
+    where
+        V: ViewClient,
+        R: RngCore + CryptoRng,
+    {
+        self.validate()?;
+
+        let plan = match self {
+            Template::Send {
+                values,
+                dest_address,
+                fee,
+                source_address,
+                memo,
+                single_account,
+                include_return_address,
+                note_selection,
+            } => {
+                plan::send(
+                    fvk,
+                    view,
+                    rng,
+                    &values,
+                    fee,
+                    dest_address,
+                    source_address,
+                    memo,
+                    single_account,
+                    include_return_address,
+                    note_selection,
+                )
+                .await?
+            }
+            Template::Delegate {
+                rate_data,
+                unbonded_amount,
+                fee,
+                source_address,
+            } => {
+                plan::delegate(
+                    fvk,
+                    view,
+                    rng,
+                    rate_data,
+                    unbonded_amount,
+                    fee,
+                    source_address,
+                )
+                .await?
+            }
+            Template::Undelegate {
+                rate_data,
+                delegation_notes,
+                fee,
+                source_address,
+            } => {
+                plan::undelegate(
+                    fvk,
+                    view,
+                    rng,
+                    rate_data,
+                    delegation_notes,
+                    fee,
+                    source_address,
+                )
+                .await?
+            }
+            Template::Sweep => return plan::sweep(fvk, view, rng).await,
+            Template::Split {
+                value,
+                fee,
+                source_address,
+                single_account,
+            } => plan::split(fvk, view, rng, value, fee, source_address, single_account).await?,
+        };
+
+        Ok(vec![plan])
+    }
+
+    /// Plan a template which describes a single transaction.
+    ///
+    /// Fails for [`Template::Sweep`], which may need several.
+    pub async fn plan_one<V, R>(
+        self,
+        fvk: &FullViewingKey,
+        view: &mut V,
+        rng: R,
+    ) -> Result<TransactionPlan>
+    where
+        V: ViewClient,
+        R: RngCore + CryptoRng,
+    {
+        if let Template::Sweep = self {
+            return Err(anyhow::anyhow!("sweeps may need more than one transaction"));
+        }
+
+        self.plan(fvk, view, rng)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("template planned no transaction"))
+    }
+}